@@ -0,0 +1,392 @@
+//! The run-time library of compiled Tiger programs: the standard library
+//! functions, allocation, and the `main` that calls the program's
+//! `tigermain`. `tigerc build` links the static library built from this
+//! crate with the emitted assembly.
+//!
+//! Functions follow the C calling convention under the `tig_` names the
+//! compiler calls them by. Values have the layouts `translate` gives them:
+//!
+//! - a string points to its length in bytes, a word, followed by the bytes;
+//! - an array points to its first element, with its length in the word
+//!   before it;
+//! - a record points to its fields, a word each;
+//! - `nil` is the null pointer.
+//!
+//! Memory is never freed. A program that allocates more than
+//! `TIGER_HEAP_SIZE` bytes, when that is set, fails with "out of memory",
+//! as it does under `tigerc run`. Errors print a message to standard error
+//! and end the program with exit code 1, like `tigerc run`.
+//!
+//! Output is buffered, and written out by `flush`, before `getchar` reads,
+//! and when the program ends, by returning, `exit` or an error.
+
+// The compiler calls some functions by camel-case names, as in the book.
+#![allow(non_snake_case)]
+
+#[cfg(test)]
+mod tests;
+
+use std::alloc::{self, Layout};
+use std::cell::{Cell, RefCell};
+use std::ffi::{c_char, CStr};
+use std::io::{BufWriter, Read, Stdout, Write};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+const WORD: usize = std::mem::size_of::<i64>();
+
+thread_local! {
+    /// What `print` has written but not yet flushed.
+    static OUTPUT: RefCell<BufWriter<Stdout>> =
+        RefCell::new(BufWriter::with_capacity(1 << 16, std::io::stdout()));
+    /// The state of the generator `random` draws from.
+    static RANDOM: Cell<u64> = const { Cell::new(0) };
+    /// The bytes allocated so far, as `heap_used` returns them.
+    static HEAP_USED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Writes out everything printed so far.
+fn flush() -> std::io::Result<()> {
+    OUTPUT.with(|out| out.borrow_mut().flush())
+}
+
+/// The program's arguments after its name, as `tig_setArgs` was given them.
+static ARGS: OnceLock<Vec<Vec<u8>>> = OnceLock::new();
+
+/// When the program started, which `clock` counts from.
+static START: OnceLock<Instant> = OnceLock::new();
+
+/// The most bytes the program may allocate, from `TIGER_HEAP_SIZE`.
+static HEAP_LIMIT: OnceLock<Option<usize>> = OnceLock::new();
+
+/// A string as the compiled code sees it; only its first field is declared.
+#[repr(C)]
+struct TigString {
+    len: i64,
+}
+
+impl TigString {
+    fn bytes(&self) -> &[u8] {
+        let data = (self as *const TigString).wrapping_add(1) as *const u8;
+        // SAFETY: the bytes follow the length, as every string is laid out.
+        unsafe { std::slice::from_raw_parts(data, self.len as usize) }
+    }
+}
+
+/// `bytes` as a new string.
+fn string(bytes: &[u8]) -> *const TigString {
+    let s = allocate(WORD + bytes.len()) as *mut TigString;
+    // SAFETY: `allocate` returned room for the length and the bytes.
+    unsafe {
+        (*s).len = bytes.len() as i64;
+        let data = s.add(1) as *mut u8;
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), data, bytes.len());
+    }
+    s
+}
+
+/// `size` zeroed bytes, aligned to a word.
+fn allocate(size: usize) -> *mut u8 {
+    let layout = Layout::from_size_align(size.max(1), WORD).unwrap_or_else(|_| {
+        fail(&format!("cannot allocate {size} bytes"));
+    });
+    // Counted in whole words, as `tigerc run` counts them.
+    let used = HEAP_USED.get().saturating_add(layout.pad_to_align().size());
+    if heap_limit().is_some_and(|limit| used > limit) {
+        fail("out of memory");
+    }
+    HEAP_USED.set(used);
+    // SAFETY: the layout has a nonzero size.
+    let p = unsafe { alloc::alloc_zeroed(layout) };
+    if p.is_null() {
+        fail("out of memory");
+    }
+    p
+}
+
+/// The limit `TIGER_HEAP_SIZE` sets, if it is set.
+fn heap_limit() -> Option<usize> {
+    *HEAP_LIMIT.get_or_init(|| {
+        let size = std::env::var_os("TIGER_HEAP_SIZE")?;
+        let limit = size.to_str().and_then(parse_heap_size);
+        Some(limit.unwrap_or_else(|| {
+            fail(&format!(
+                "invalid TIGER_HEAP_SIZE `{}`",
+                size.to_string_lossy()
+            ))
+        }))
+    })
+}
+
+/// A number of bytes, which may end in `k`, `m` or `g` for KiB, MiB or
+/// GiB. `tigerc run` reads `TIGER_HEAP_SIZE` the same way.
+fn parse_heap_size(s: &str) -> Option<usize> {
+    let (digits, unit) = match s.char_indices().last()? {
+        (i, 'k' | 'K') => (&s[..i], 1 << 10),
+        (i, 'm' | 'M') => (&s[..i], 1 << 20),
+        (i, 'g' | 'G') => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse::<usize>().ok()?.checked_mul(unit)
+}
+
+/// Ends the program after a run-time error.
+fn fail(msg: &str) -> ! {
+    // Output may be mid-write if writing it failed.
+    let _ = OUTPUT.with(|out| out.try_borrow_mut().map(|mut out| out.flush()));
+    eprintln!("error: {msg}");
+    std::process::exit(1)
+}
+
+#[cfg(not(test))]
+#[no_mangle]
+unsafe extern "C" fn main(argc: i32, argv: *const *const c_char) -> i32 {
+    extern "C" {
+        fn tigermain(static_link: i64) -> i64;
+    }
+    START.get_or_init(Instant::now);
+    // A bad limit is reported even by a program that never allocates.
+    heap_limit();
+    // SAFETY: `argc` and `argv` are as the C library passes them, and
+    // `tigermain` is the compiled program, which follows the C calling
+    // convention; its static link is unused.
+    tig_setArgs(argc, argv);
+    tigermain(0);
+    tig_flush();
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn tig_print(s: *const TigString) {
+    let written = OUTPUT.with(|out| out.borrow_mut().write_all((*s).bytes()));
+    if written.is_err() {
+        fail("cannot write output");
+    }
+}
+
+#[no_mangle]
+extern "C" fn tig_flush() {
+    if flush().is_err() {
+        fail("cannot write output");
+    }
+}
+
+/// The next byte of input as a string, or `""` at the end of the input.
+/// Output is flushed first, so a prompt shows before the program waits.
+#[no_mangle]
+extern "C" fn tig_getchar() -> *const TigString {
+    tig_flush();
+    let mut byte = [0];
+    match std::io::stdin().read(&mut byte) {
+        Ok(0) => string(b""),
+        Ok(_) => string(&byte),
+        Err(_) => fail("cannot read input"),
+    }
+}
+
+/// The first byte of `s`, or -1 if it is empty.
+#[no_mangle]
+unsafe extern "C" fn tig_ord(s: *const TigString) -> i64 {
+    (*s).bytes().first().map_or(-1, |&b| b as i64)
+}
+
+#[no_mangle]
+extern "C" fn tig_chr(n: i64) -> *const TigString {
+    match u8::try_from(n) {
+        Ok(b) => string(&[b]),
+        Err(_) => fail(&format!("`chr` of {n} is out of range")),
+    }
+}
+
+#[no_mangle]
+extern "C" fn tig_itoa(n: i64) -> *const TigString {
+    string(n.to_string().as_bytes())
+}
+
+/// The integer `s` spells, as an optional `-` and at least one decimal
+/// digit with nothing around them, or `default` if it spells none in range.
+#[no_mangle]
+unsafe extern "C" fn tig_atoi(s: *const TigString, default: i64) -> i64 {
+    let bytes = (*s).bytes();
+    let digits = bytes.strip_prefix(b"-").unwrap_or(bytes);
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+        return default;
+    }
+    std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(default)
+}
+
+#[no_mangle]
+unsafe extern "C" fn tig_size(s: *const TigString) -> i64 {
+    (*s).len
+}
+
+#[no_mangle]
+unsafe extern "C" fn tig_substring(s: *const TigString, first: i64, n: i64) -> *const TigString {
+    let bytes = (*s).bytes();
+    match first.checked_add(n) {
+        Some(end) if first >= 0 && n >= 0 && end <= bytes.len() as i64 => {
+            string(&bytes[first as usize..end as usize])
+        }
+        _ => fail(&format!(
+            "substring {first}..{} is out of bounds for size {}",
+            first.wrapping_add(n),
+            bytes.len()
+        )),
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn tig_concat(a: *const TigString, b: *const TigString) -> *const TigString {
+    string(&[(*a).bytes(), (*b).bytes()].concat())
+}
+
+#[no_mangle]
+extern "C" fn tig_not(n: i64) -> i64 {
+    (n == 0) as i64
+}
+
+#[no_mangle]
+extern "C" fn tig_exit(code: i64) -> ! {
+    // Only the low 8 bits of an exit status reach the parent process.
+    if !(0..=255).contains(&code) {
+        fail(&format!("`exit` code {code} is out of range"));
+    }
+    tig_flush();
+    std::process::exit(code as i32)
+}
+
+/// Keeps the arguments `argv` will return: the `argc` strings of `argv`,
+/// as `main` is given them, less the first, the program's name. Only the
+/// first call counts.
+#[no_mangle]
+unsafe extern "C" fn tig_setArgs(argc: i32, argv: *const *const c_char) {
+    let args = (1..argc.max(1) as usize)
+        .map(|i| CStr::from_ptr(*argv.add(i)).to_bytes().to_vec())
+        .collect();
+    let _ = ARGS.set(args);
+}
+
+#[no_mangle]
+extern "C" fn tig_argc() -> i64 {
+    ARGS.get().map_or(0, Vec::len) as i64
+}
+
+#[no_mangle]
+extern "C" fn tig_argv(i: i64) -> *const TigString {
+    let arg = usize::try_from(i).ok().and_then(|i| ARGS.get()?.get(i));
+    match arg {
+        Some(arg) => string(arg),
+        None => fail(&format!("`argv` of {i} is out of range")),
+    }
+}
+
+/// The value of the environment variable `name`, or `""` if it is unset,
+/// or if `name` is not UTF-8 or could not name one.
+#[no_mangle]
+unsafe extern "C" fn tig_getenv(name: *const TigString) -> *const TigString {
+    let value = std::str::from_utf8((*name).bytes())
+        .ok()
+        .and_then(std::env::var_os)
+        .map_or_else(Vec::new, |value| value.into_encoded_bytes());
+    string(&value)
+}
+
+/// The next number below `n` from SplitMix64, the generator `tigerc run`
+/// draws from too. It starts from state 0 unless `seed` sets one.
+#[no_mangle]
+extern "C" fn tig_random(n: i64) -> i64 {
+    if n <= 0 {
+        fail(&format!("`random` of {n} is out of range"));
+    }
+    let state = RANDOM.get().wrapping_add(0x9e37_79b9_7f4a_7c15);
+    RANDOM.set(state);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z % n as u64) as i64
+}
+
+#[no_mangle]
+extern "C" fn tig_seed(x: i64) {
+    RANDOM.set(x as u64);
+}
+
+/// The bytes the program has allocated, each allocation rounded up to a
+/// whole word.
+#[no_mangle]
+extern "C" fn tig_heap_used() -> i64 {
+    HEAP_USED.get() as i64
+}
+
+/// Collects garbage, which is nothing: memory is never freed, so nothing
+/// becomes garbage that could be reused.
+#[no_mangle]
+extern "C" fn tig_gc_collect() {}
+
+/// The microseconds since the program started, from a clock that never
+/// goes back.
+#[no_mangle]
+extern "C" fn tig_clock() -> i64 {
+    START.get_or_init(Instant::now).elapsed().as_micros() as i64
+}
+
+/// Whether `a` and `b` have the same contents: 1 if so, 0 if not.
+#[no_mangle]
+unsafe extern "C" fn tig_stringEqual(a: *const TigString, b: *const TigString) -> i64 {
+    ((*a).bytes() == (*b).bytes()) as i64
+}
+
+/// -1, 0 or 1 as `a` sorts before, with or after `b`, byte by byte.
+#[no_mangle]
+unsafe extern "C" fn tig_stringCompare(a: *const TigString, b: *const TigString) -> i64 {
+    (*a).bytes().cmp((*b).bytes()) as i64
+}
+
+/// A new array of `size` elements, all `init`.
+#[no_mangle]
+extern "C" fn tig_initArray(size: i64, init: i64) -> *mut i64 {
+    let Ok(len) = usize::try_from(size) else {
+        fail(&format!("negative array size {size}"));
+    };
+    let Some(bytes) = len.checked_add(1).and_then(|n| n.checked_mul(WORD)) else {
+        fail(&format!("cannot allocate an array of {size} elements"));
+    };
+    let words = allocate(bytes) as *mut i64;
+    // SAFETY: `allocate` returned room for the length and the elements.
+    unsafe {
+        *words = size;
+        let elems = words.add(1);
+        std::slice::from_raw_parts_mut(elems, len).fill(init);
+        elems
+    }
+}
+
+/// A new record of `size` bytes, with every field 0.
+#[no_mangle]
+extern "C" fn tig_allocRecord(size: i64) -> *mut u8 {
+    allocate(size as usize)
+}
+
+#[no_mangle]
+extern "C" fn tig_indexError(index: i64) -> ! {
+    fail(&format!("index {index} is out of bounds"))
+}
+
+/// A field, named by `field`, of a `nil` record.
+#[no_mangle]
+unsafe extern "C" fn tig_nilError(field: *const TigString) -> ! {
+    let field = String::from_utf8_lossy((*field).bytes());
+    fail(&format!("field `{field}` of nil record"))
+}
+
+#[no_mangle]
+extern "C" fn tig_divError() -> ! {
+    fail("division by zero")
+}
@@ -0,0 +1,131 @@
+use super::*;
+
+fn contents(s: *const TigString) -> Vec<u8> {
+    // SAFETY: the runtime only returns valid strings.
+    unsafe { (*s).bytes().to_vec() }
+}
+
+#[test]
+fn strings_are_a_length_then_bytes() {
+    let s = string(b"tiger");
+    // SAFETY: `string` returns a valid string.
+    unsafe {
+        assert_eq!(tig_size(s), 5);
+        assert_eq!(*(s as *const u8).add(WORD), b't');
+    }
+    assert_eq!(contents(string(b"")), b"");
+}
+
+#[test]
+fn string_functions_work_on_bytes() {
+    let (a, b) = (string(b"ab"), string(b"abc"));
+    // SAFETY: the arguments are valid strings.
+    unsafe {
+        assert_eq!(contents(tig_concat(a, b)), b"ababc");
+        assert_eq!(contents(tig_substring(b, 1, 2)), b"bc");
+        assert_eq!(contents(tig_substring(b, 3, 0)), b"");
+        assert_eq!(tig_ord(b), 'a' as i64);
+        assert_eq!(tig_ord(string(b"")), -1);
+        assert_eq!(tig_stringEqual(a, string(b"ab")), 1);
+        assert_eq!(tig_stringEqual(a, b), 0);
+        assert_eq!(tig_stringCompare(a, b), -1);
+        assert_eq!(tig_stringCompare(b, a), 1);
+        assert_eq!(tig_stringCompare(a, a), 0);
+    }
+    assert_eq!(contents(tig_chr(255)), [255]);
+    assert_eq!(contents(tig_itoa(-120)), b"-120");
+    assert_eq!(contents(tig_itoa(i64::MIN)), b"-9223372036854775808");
+    // SAFETY: the arguments are valid strings.
+    unsafe {
+        assert_eq!(tig_atoi(string(b"-120"), 0), -120);
+        assert_eq!(tig_atoi(string(b"007"), 0), 7);
+        for bad in [&b""[..], b"-", b"+1", b" 1", b"1 ", b"9223372036854775808"] {
+            assert_eq!(tig_atoi(string(bad), -1), -1);
+        }
+    }
+    assert_eq!((tig_not(0), tig_not(7)), (1, 0));
+}
+
+#[test]
+fn arguments_skip_the_program_name() {
+    let args = [c"prog", c"a", c"bc"].map(|s| s.as_ptr());
+    // SAFETY: `args` holds three C strings, as `main` is passed them.
+    unsafe { tig_setArgs(3, args.as_ptr()) };
+    assert_eq!(tig_argc(), 2);
+    assert_eq!(contents(tig_argv(1)), b"bc");
+}
+
+#[test]
+fn unset_variables_read_as_empty() {
+    // SAFETY: the names are valid strings.
+    unsafe {
+        let name = string(b"CARGO_PKG_NAME");
+        assert_eq!(
+            contents(tig_getenv(name)),
+            env!("CARGO_PKG_NAME").as_bytes()
+        );
+        assert_eq!(contents(tig_getenv(string(b"TIGER_SURELY_UNSET"))), b"");
+        assert_eq!(contents(tig_getenv(string(b"\xff"))), b"");
+    }
+}
+
+#[test]
+fn random_numbers_follow_the_seed() {
+    // The numbers `tigerc run` draws too, as `interp::tests` checks.
+    let draws = || (0..5).map(|_| tig_random(100)).collect::<Vec<_>>();
+    tig_seed(42);
+    let first = draws();
+    assert_eq!(first, [13, 91, 58, 64, 50]);
+    tig_seed(42);
+    assert_eq!(draws(), first);
+    tig_seed(-1);
+    assert_eq!(tig_random(i64::MAX), 7_266_964_230_113_668_129);
+}
+
+#[test]
+fn the_clock_never_goes_back() {
+    let (a, b) = (tig_clock(), tig_clock());
+    assert!(0 <= a && a <= b, "{a} then {b}");
+}
+
+#[test]
+fn arrays_keep_their_length_before_the_elements() {
+    let a = tig_initArray(3, 7);
+    // SAFETY: the array has a length word and three elements.
+    unsafe {
+        assert_eq!(*a.sub(1), 3);
+        assert_eq!(std::slice::from_raw_parts(a, 3), [7, 7, 7]);
+    }
+    let empty = tig_initArray(0, 1);
+    // SAFETY: even an empty array has its length word.
+    unsafe { assert_eq!(*empty.sub(1), 0) };
+}
+
+#[test]
+fn records_start_zeroed() {
+    let r = tig_allocRecord(3 * WORD as i64) as *const i64;
+    // SAFETY: the record has three fields.
+    unsafe { assert_eq!(std::slice::from_raw_parts(r, 3), [0, 0, 0]) };
+    assert!(!tig_allocRecord(0).is_null());
+}
+
+#[test]
+fn heap_used_counts_whole_words() {
+    let before = tig_heap_used();
+    tig_allocRecord(0);
+    tig_initArray(2, 0);
+    string(b"tiger");
+    tig_gc_collect();
+    assert_eq!(tig_heap_used() - before, (1 + 3 + 2) * WORD as i64);
+}
+
+#[test]
+fn heap_sizes_may_have_a_unit() {
+    assert_eq!(parse_heap_size("4096"), Some(4096));
+    assert_eq!(parse_heap_size("64k"), Some(64 << 10));
+    assert_eq!(parse_heap_size("2M"), Some(2 << 20));
+    assert_eq!(parse_heap_size("1g"), Some(1 << 30));
+    for bad in ["", "k", "-1", "1.5m", "1kb", "99999999999999999999g"] {
+        assert_eq!(parse_heap_size(bad), None, "{bad:?}");
+    }
+}
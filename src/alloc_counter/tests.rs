@@ -0,0 +1,160 @@
+use crate::alloc_counter::measure;
+use crate::lexer::{StringReader, TokenKind};
+use crate::parser::parse;
+use crate::semant::Semant;
+
+use std::time::Instant;
+
+const SNIPPET: &str = r#"
+    type list = {hd: int, tl: list}
+    var xs := list{hd = 1, tl = nil}
+    function length(l: list): int =
+        /* recursive /* nested */ comment */
+        if l = nil then 0 else 1 + length(l.tl)
+    var msg := "hello\"world"
+"#;
+
+/// A large program made of `copies` independent `let` blocks.
+fn large_program(copies: usize) -> String {
+    let mut src = String::from("(");
+    for i in 0..copies {
+        if i > 0 {
+            src.push(';');
+        }
+        src.push_str("let");
+        src.push_str(SNIPPET);
+        src.push_str("in length(xs) + size(msg) end");
+    }
+    src.push(')');
+    src
+}
+
+fn count_tokens(src: &str) -> usize {
+    let mut sr = StringReader::new(src);
+    let mut count = 0;
+    while *sr.next_token().kind() != TokenKind::EOF {
+        count += 1;
+    }
+    count
+}
+
+#[test]
+fn lexing_does_not_allocate_per_token() {
+    let small = large_program(10);
+    let large = large_program(1000);
+    // Intern the program's names first; after that, lexing should only
+    // allocate the contents of each string literal, one per copy of the
+    // snippet, and a constant amount of scratch space regardless of size.
+    count_tokens(&small);
+    let (_, small_stats) = measure(|| count_tokens(&small));
+    let (tokens, large_stats) = measure(|| count_tokens(&large));
+    assert!(tokens > 50_000);
+    assert_eq!(large_stats.allocations - small_stats.allocations, 1000 - 10);
+}
+
+#[test]
+fn parsing_stays_within_allocation_budget() {
+    let src = large_program(1000);
+    let tokens = count_tokens(&src);
+    let (ast, stats) = measure(|| parse(&src));
+    assert!(ast.is_ok());
+    // Each copy of the snippet is 68 tokens and 23 allocations, 9 of them
+    // lists of declarations, fields, parameters and arguments and most of
+    // the rest boxed children. A vector that kept two elements inline would
+    // save 8 of the lists, some 7% of the parser's time on the 16 MB of
+    // `front_end_memory`, at the price of an unsafe container of our own
+    // and a bigger node for every list. Arena slices would save the boxes
+    // too, and put a lifetime on every tree in the compiler. Checking the
+    // same program makes ten times as many allocations, so that is where to
+    // look first.
+    assert!(
+        2 * stats.allocations <= tokens,
+        "{} allocations for {tokens} tokens",
+        stats.allocations
+    );
+}
+
+#[test]
+fn checking_stays_within_allocation_budget() {
+    let ast = parse(&large_program(1000)).unwrap();
+    let tokens = count_tokens(&large_program(1000));
+    let (_, stats) = measure(|| {
+        let mut semant = Semant::new();
+        semant.check(&ast);
+        semant
+    });
+    // About 229 allocations for each 68-token copy of the snippet: types,
+    // scopes, the IR trees and the facts the language server asks for.
+    assert!(
+        stats.allocations <= 4 * tokens,
+        "{} allocations for {tokens} tokens",
+        stats.allocations
+    );
+}
+
+#[test]
+fn string_literals_are_shared_with_fragments() {
+    let check = |literal: &str| {
+        let ast = parse(&format!("(print(\"{literal}\"); print(\"{literal}\"))")).unwrap();
+        measure(|| Semant::new().check(&ast)).1
+    };
+    let short = check("x");
+    let long = check(&"x".repeat(100_000));
+    // The fragments share the literal's bytes rather than copying them.
+    assert!(long.bytes < short.bytes + 1_000, "{long:?} vs {short:?}");
+}
+
+#[test]
+#[ignore = "a benchmark; run with --ignored --nocapture, sized by TIGER_BENCH_MB"]
+fn lexer_throughput() {
+    let megabytes: usize = std::env::var("TIGER_BENCH_MB")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(16);
+    let copies = megabytes * 1_000_000 / large_program(1).len();
+    let src = large_program(copies);
+    count_tokens(&src);
+    let small = large_program(1);
+    let (_, fixed) = measure(|| count_tokens(&small));
+    let start = Instant::now();
+    let (tokens, stats) = measure(|| count_tokens(&src));
+    let secs = start.elapsed().as_secs_f64();
+    println!(
+        "lexed {:.1} MB, {tokens} tokens, in {secs:.3}s: {:.1} MB/s, {:.1} Mtokens/s, {} allocations",
+        src.len() as f64 / 1e6,
+        src.len() as f64 / 1e6 / secs,
+        tokens as f64 / 1e6 / secs,
+        stats.allocations,
+    );
+    // One string literal's contents for each copy past the first.
+    assert_eq!(stats.allocations - fixed.allocations, copies - 1);
+}
+
+#[test]
+#[ignore = "a benchmark; run with --ignored --nocapture, sized by TIGER_BENCH_MB"]
+fn front_end_memory() {
+    let megabytes: usize = std::env::var("TIGER_BENCH_MB")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(16);
+    // The program is one long sequence. The compiler's stack keeps the
+    // benchmark from overflowing, in a debug build, wherever the front end
+    // recurses over it.
+    crate::limits::with_stack(|| {
+        let src = large_program(megabytes * 1_000_000 / large_program(1).len());
+        let (ast, parsed) = measure(|| parse(&src).unwrap());
+        let (_, checked) = measure(|| {
+            let mut semant = Semant::new();
+            semant.check(&ast);
+            semant
+        });
+        println!(
+            "{:.1} MB: parsing made {} allocations of {:.1} MB, checking and translating {} of {:.1} MB",
+            src.len() as f64 / 1e6,
+            parsed.allocations,
+            parsed.bytes as f64 / 1e6,
+            checked.allocations,
+            checked.bytes as f64 / 1e6,
+        );
+    });
+}
@@ -0,0 +1,315 @@
+#![allow(dead_code)]
+
+//! Control flow graphs of IR basic blocks, and a dataflow framework for the
+//! analyses that run over them.
+//!
+//! `Cfg` connects the blocks of `canon::basic_blocks` through their final
+//! jumps, and gives their reverse postorder and dominator tree. `solve`
+//! iterates a gen/kill problem over any graph given as successor lists,
+//! forwards or backwards, so a new analysis only describes what each node
+//! generates and kills. Liveness uses it over the instruction flow graph,
+//! and `undefined_reads` over the blocks of a function.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+use crate::graph;
+use crate::ir::{Exp, Stm};
+use crate::temp::{Label, Temp};
+
+/// A function's basic blocks and the edges between them. Block 0 is the
+/// entry; jumps to labels outside the function, such as the `done` label of
+/// `basic_blocks`, leave it.
+pub(crate) struct Cfg {
+    pub(crate) blocks: Vec<Vec<Stm>>,
+    pub(crate) succ: Vec<Vec<usize>>,
+    pub(crate) pred: Vec<Vec<usize>>,
+}
+
+impl Cfg {
+    /// The graph of `blocks` as `canon::basic_blocks` returns them: each
+    /// starts with a `Label` and ends with a `Jump` or `CJump`.
+    pub(crate) fn new(blocks: Vec<Vec<Stm>>) -> Cfg {
+        let index: HashMap<Label, usize> = blocks
+            .iter()
+            .enumerate()
+            .filter_map(|(i, block)| match block.first() {
+                Some(Stm::Label(label)) => Some((*label, i)),
+                _ => None,
+            })
+            .collect();
+        let succ: Vec<Vec<usize>> = blocks
+            .iter()
+            .map(|block| {
+                let targets = match block.last() {
+                    Some(Stm::Jump(_, labels)) => labels.clone(),
+                    Some(Stm::CJump(_, _, _, t, f)) => vec![*t, *f],
+                    _ => Vec::new(),
+                };
+                let mut succ: Vec<usize> = Vec::new();
+                for target in targets.iter().filter_map(|label| index.get(label)) {
+                    if !succ.contains(target) {
+                        succ.push(*target);
+                    }
+                }
+                succ
+            })
+            .collect();
+        let mut pred = vec![Vec::new(); blocks.len()];
+        for (b, targets) in succ.iter().enumerate() {
+            for &s in targets {
+                pred[s].push(b);
+            }
+        }
+        Cfg { blocks, succ, pred }
+    }
+
+    /// The blocks reachable from the entry, each before its successors
+    /// except along back edges.
+    pub(crate) fn reverse_postorder(&self) -> Vec<usize> {
+        if self.blocks.is_empty() {
+            return Vec::new();
+        }
+        let mut order = graph::postorder(&self.succ, 0);
+        order.reverse();
+        order
+    }
+
+    pub(crate) fn dominator_tree(&self) -> DomTree {
+        if self.blocks.is_empty() {
+            return DomTree::new(Vec::new());
+        }
+        DomTree::new(graph::dominators(&self.succ, 0))
+    }
+}
+
+/// Which blocks dominate which, from the immediate dominators of
+/// `graph::dominators`.
+pub(crate) struct DomTree {
+    /// The immediate dominator of each block; the entry's is itself, and
+    /// unreachable blocks have none.
+    pub(crate) idom: Vec<Option<usize>>,
+    /// The blocks each block immediately dominates, in index order.
+    pub(crate) children: Vec<Vec<usize>>,
+}
+
+impl DomTree {
+    fn new(idom: Vec<Option<usize>>) -> DomTree {
+        let mut children = vec![Vec::new(); idom.len()];
+        for (b, d) in idom.iter().enumerate() {
+            match *d {
+                Some(d) if d != b => children[d].push(b),
+                _ => {}
+            }
+        }
+        DomTree { idom, children }
+    }
+
+    /// Whether every path from the entry to `b` goes through `a`.
+    pub(crate) fn dominates(&self, a: usize, b: usize) -> bool {
+        graph::dominates(&self.idom, a, b)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Direction {
+    /// Facts flow from predecessors, as in reaching definitions.
+    Forward,
+    /// Facts flow from successors, as in liveness.
+    Backward,
+}
+
+/// How the facts of a node's neighbours combine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Meet {
+    /// A fact holds if it holds along some path ("may" problems).
+    Union,
+    /// A fact holds if it holds along every path ("must" problems).
+    Intersection,
+}
+
+/// A gen/kill dataflow problem over a graph of `gen.len()` nodes.
+pub(crate) struct Problem<T> {
+    pub(crate) direction: Direction,
+    pub(crate) meet: Meet,
+    /// The facts each node makes hold, whatever held before it.
+    pub(crate) gen: Vec<BTreeSet<T>>,
+    /// The facts each node stops from holding, unless it generates them.
+    pub(crate) kill: Vec<BTreeSet<T>>,
+}
+
+/// The facts holding on entry to and on exit from each node, in program
+/// order whatever the direction of the problem.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Solution<T> {
+    pub(crate) entry: Vec<BTreeSet<T>>,
+    pub(crate) exit: Vec<BTreeSet<T>>,
+}
+
+/// Solves `problem` over the graph `succ` by iterating the node equations
+/// from a worklist to their fixed point. Nodes without neighbours on the
+/// incoming side start from the empty set.
+pub(crate) fn solve<T: Ord + Clone>(succ: &[Vec<usize>], problem: &Problem<T>) -> Solution<T> {
+    let n = succ.len();
+    let mut pred = vec![Vec::new(); n];
+    for (v, targets) in succ.iter().enumerate() {
+        for &w in targets {
+            pred[w].push(v);
+        }
+    }
+    // `from` are the nodes facts come from, `to` those they flow on to.
+    let (from, to) = match problem.direction {
+        Direction::Forward => (&pred[..], succ),
+        Direction::Backward => (succ, &pred[..]),
+    };
+    // Intersections start from every fact and shrink; unions start empty
+    // and grow.
+    let top: BTreeSet<T> = match problem.meet {
+        Meet::Union => BTreeSet::new(),
+        Meet::Intersection => problem.gen.iter().flatten().cloned().collect(),
+    };
+    let mut before: Vec<BTreeSet<T>> = vec![BTreeSet::new(); n];
+    let mut after: Vec<BTreeSet<T>> = vec![top; n];
+    let mut work: VecDeque<usize> = match problem.direction {
+        Direction::Forward => (0..n).collect(),
+        Direction::Backward => (0..n).rev().collect(),
+    };
+    let mut queued = vec![true; n];
+    while let Some(v) = work.pop_front() {
+        queued[v] = false;
+        let mut incoming = from[v].iter().map(|&u| &after[u]);
+        let mut facts = incoming.next().cloned().unwrap_or_default();
+        for other in incoming {
+            match problem.meet {
+                Meet::Union => facts.extend(other.iter().cloned()),
+                Meet::Intersection => facts.retain(|fact| other.contains(fact)),
+            }
+        }
+        let mut out = problem.gen[v].clone();
+        out.extend(
+            facts
+                .iter()
+                .filter(|fact| !problem.kill[v].contains(fact))
+                .cloned(),
+        );
+        before[v] = facts;
+        if out != after[v] {
+            after[v] = out;
+            for &w in &to[v] {
+                if !std::mem::replace(&mut queued[w], true) {
+                    work.push_back(w);
+                }
+            }
+        }
+    }
+    match problem.direction {
+        Direction::Forward => Solution {
+            entry: before,
+            exit: after,
+        },
+        Direction::Backward => Solution {
+            entry: after,
+            exit: before,
+        },
+    }
+}
+
+/// The temporaries some path from the entry of `cfg` reads before moving
+/// anything into them. Machine registers are defined on entry.
+///
+/// This is reaching definitions with one pseudo-definition "undefined" per
+/// temporary at a node before the entry: a read is flagged when its
+/// temporary's pseudo-definition reaches it. Blocks not reachable from the
+/// entry are never flagged.
+pub(crate) fn undefined_reads(cfg: &Cfg) -> BTreeSet<Temp> {
+    let n = cfg.blocks.len();
+    if n == 0 {
+        return BTreeSet::new();
+    }
+    let mut read = BTreeSet::new();
+    let mut kill = vec![BTreeSet::new(); n + 1];
+    for (b, block) in cfg.blocks.iter().enumerate() {
+        for stm in block {
+            visit_stm(stm, &mut |t, def| {
+                if def {
+                    kill[b].insert(t);
+                } else if !t.is_register() {
+                    read.insert(t);
+                }
+            });
+        }
+    }
+    let mut gen = vec![BTreeSet::new(); n + 1];
+    gen[n] = read;
+    let mut succ = cfg.succ.clone();
+    succ.push(vec![0]);
+    let problem = Problem {
+        direction: Direction::Forward,
+        meet: Meet::Union,
+        gen,
+        kill,
+    };
+    let solution = solve(&succ, &problem);
+    let mut found = BTreeSet::new();
+    for b in cfg.reverse_postorder() {
+        let mut undefined = solution.entry[b].clone();
+        for stm in &cfg.blocks[b] {
+            visit_stm(stm, &mut |t, def| {
+                if def {
+                    undefined.remove(&t);
+                } else if undefined.contains(&t) {
+                    found.insert(t);
+                }
+            });
+        }
+    }
+    found
+}
+
+/// Calls `f` on each temporary `stm` reads, with `false`, or moves into,
+/// with `true`, in the order it does so.
+fn visit_stm(stm: &Stm, f: &mut impl FnMut(Temp, bool)) {
+    match stm {
+        Stm::Move(dst, src) => {
+            visit_exp(src, f);
+            match &**dst {
+                Exp::Temp(t) => f(*t, true),
+                dst => visit_exp(dst, f),
+            }
+        }
+        Stm::Exp(e) | Stm::Jump(e, _) => visit_exp(e, f),
+        Stm::CJump(_, a, b, _, _) => {
+            visit_exp(a, f);
+            visit_exp(b, f);
+        }
+        Stm::Seq(a, b) => {
+            visit_stm(a, f);
+            visit_stm(b, f);
+        }
+        Stm::Label(_) => {}
+    }
+}
+
+fn visit_exp(exp: &Exp, f: &mut impl FnMut(Temp, bool)) {
+    match exp {
+        Exp::Const(_) | Exp::Name(_) => {}
+        Exp::Temp(t) => f(*t, false),
+        Exp::BinOp(_, a, b) => {
+            visit_exp(a, f);
+            visit_exp(b, f);
+        }
+        Exp::Mem(addr) => visit_exp(addr, f),
+        Exp::Call(func, args) => {
+            visit_exp(func, f);
+            for arg in args {
+                visit_exp(arg, f);
+            }
+        }
+        Exp::ESeq(stm, e) => {
+            visit_stm(stm, f);
+            visit_exp(e, f);
+        }
+    }
+}
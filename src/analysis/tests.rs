@@ -0,0 +1,133 @@
+use std::collections::BTreeSet;
+
+use crate::analysis::{solve, undefined_reads, Cfg, Direction, Meet, Problem};
+use crate::frame;
+use crate::ir::{BinOp, Exp, RelOp, Stm};
+use crate::temp::{Label, Temp};
+
+/// The blocks of `t := 0; while t < 10 do t := t + 1`:
+///
+/// ```text
+/// 0: L0: t := 0                      jump L1
+/// 1: L1: if t < 10 goto L2 else L3
+/// 2: L2: t := t + 1                  jump L1
+/// 3: L3:                             jump done
+/// ```
+fn counting_loop() -> Cfg {
+    let t = Temp::new();
+    let [l0, l1, l2, l3, done] = [(); 5].map(|()| Label::new());
+    Cfg::new(vec![
+        vec![
+            Stm::Label(l0),
+            Stm::mov(Exp::Temp(t), Exp::Const(0)),
+            Stm::jump(l1),
+        ],
+        vec![
+            Stm::Label(l1),
+            Stm::cjump(RelOp::Lt, Exp::Temp(t), Exp::Const(10), l2, l3),
+        ],
+        vec![
+            Stm::Label(l2),
+            Stm::mov(
+                Exp::Temp(t),
+                Exp::binop(BinOp::Plus, Exp::Temp(t), Exp::Const(1)),
+            ),
+            Stm::jump(l1),
+        ],
+        vec![Stm::Label(l3), Stm::jump(done)],
+    ])
+}
+
+fn sets(sets: &[&[u32]]) -> Vec<BTreeSet<u32>> {
+    sets.iter().map(|s| s.iter().copied().collect()).collect()
+}
+
+#[test]
+fn blocks_are_connected_by_their_jumps() {
+    let cfg = counting_loop();
+    assert_eq!(cfg.succ, [vec![1], vec![2, 3], vec![1], vec![]]);
+    assert_eq!(cfg.pred, [vec![], vec![0, 2], vec![1], vec![1]]);
+    assert_eq!(cfg.reverse_postorder(), [0, 1, 3, 2]);
+    let dom = cfg.dominator_tree();
+    assert_eq!(dom.idom, [Some(0), Some(0), Some(1), Some(1)]);
+    assert_eq!(dom.children, [vec![1], vec![2, 3], vec![], vec![]]);
+    assert!(dom.dominates(1, 2) && !dom.dominates(2, 3));
+}
+
+#[test]
+fn forward_problems_meet_over_predecessors() {
+    let cfg = counting_loop();
+    // Reaching definitions: 0 is `t := 0` in block 0, 1 is `t := t + 1` in
+    // block 2, and each kills the other.
+    let reaching = Problem {
+        direction: Direction::Forward,
+        meet: Meet::Union,
+        gen: sets(&[&[0], &[], &[1], &[]]),
+        kill: sets(&[&[1], &[], &[0], &[]]),
+    };
+    let solution = solve(&cfg.succ, &reaching);
+    assert_eq!(solution.entry, sets(&[&[], &[0, 1], &[0, 1], &[0, 1]]));
+    assert_eq!(solution.exit, sets(&[&[0], &[0, 1], &[1], &[0, 1]]));
+
+    // The same facts, but only those holding along every path.
+    let must = Problem {
+        meet: Meet::Intersection,
+        kill: sets(&[&[], &[], &[], &[]]),
+        ..reaching
+    };
+    let solution = solve(&cfg.succ, &must);
+    assert_eq!(solution.entry, sets(&[&[], &[0], &[0], &[0]]));
+    assert_eq!(solution.exit, sets(&[&[0], &[0], &[0, 1], &[0]]));
+}
+
+#[test]
+fn backward_problems_meet_over_successors() {
+    let cfg = counting_loop();
+    // Liveness of `t`, used in blocks 1 and 2 and defined in 0 and 2.
+    let live = Problem {
+        direction: Direction::Backward,
+        meet: Meet::Union,
+        gen: sets(&[&[], &[0], &[0], &[]]),
+        kill: sets(&[&[0], &[], &[0], &[]]),
+    };
+    let solution = solve(&cfg.succ, &live);
+    assert_eq!(solution.entry, sets(&[&[], &[0], &[0], &[]]));
+    assert_eq!(solution.exit, sets(&[&[0], &[0], &[0], &[]]));
+}
+
+#[test]
+fn reads_before_every_definition_are_found() {
+    assert_eq!(undefined_reads(&counting_loop()), BTreeSet::new());
+
+    // `if c then u := 1; return u + t` with `t` never defined, and `c` the
+    // first argument register.
+    let (t, u) = (Temp::new(), Temp::new());
+    let c = frame::ARG_REGS[0];
+    let [l0, l1, l2, l3, done] = [(); 5].map(|()| Label::new());
+    let cfg = Cfg::new(vec![
+        vec![
+            Stm::Label(l0),
+            Stm::cjump(RelOp::Ne, Exp::Temp(c), Exp::Const(0), l1, l2),
+        ],
+        vec![
+            Stm::Label(l1),
+            Stm::mov(Exp::Temp(u), Exp::Const(1)),
+            Stm::jump(l2),
+        ],
+        vec![
+            Stm::Label(l2),
+            Stm::mov(
+                Exp::Temp(frame::RV),
+                Exp::binop(BinOp::Plus, Exp::Temp(u), Exp::Temp(t)),
+            ),
+            Stm::jump(done),
+        ],
+        // Unreachable, so its read is not reported.
+        vec![
+            Stm::Label(l3),
+            Stm::Exp(Box::new(Exp::Temp(Temp::new()))),
+            Stm::jump(l2),
+        ],
+    ]);
+    assert_eq!(undefined_reads(&cfg), BTreeSet::from([t, u]));
+}
@@ -0,0 +1,264 @@
+//! Terse constructors for syntax trees in tests, e.g.
+//! `call("f", [int(1), var("x")])` for `f(1, x)`.
+//!
+//! Built nodes have no source text. Each gets a distinct empty span, so
+//! analyses keyed by position, such as escape analysis, still tell the
+//! declarations apart. Compare built trees with parsed ones through
+//! `pretty::sexp`, which leaves spans out.
+
+use std::cell::Cell;
+
+use super::{
+    ByteStr, Dec, Exp, ExpKind, Field, FunDec, Oper, RecordField, Symbol, Ty, TypeDec, Var, VarDec,
+    VarKind,
+};
+use crate::span::Span;
+
+thread_local! {
+    static NEXT_POS: Cell<u32> = const { Cell::new(0) };
+}
+
+fn pos() -> Span {
+    NEXT_POS.with(|next| {
+        let n = next.get();
+        next.set(n + 1);
+        Span::new(n, n)
+    })
+}
+
+fn sym(name: &str) -> Symbol {
+    Symbol::intern(name)
+}
+
+fn exp(kind: ExpKind) -> Exp {
+    Exp { kind, pos: pos() }
+}
+
+fn boxed(e: Exp) -> Box<Exp> {
+    Box::new(e)
+}
+
+pub(crate) fn nil() -> Exp {
+    exp(ExpKind::Nil)
+}
+
+pub(crate) fn unit() -> Exp {
+    exp(ExpKind::Unit)
+}
+
+pub(crate) fn int(n: i64) -> Exp {
+    exp(ExpKind::Int(n))
+}
+
+pub(crate) fn string(s: &str) -> Exp {
+    exp(ExpKind::String(ByteStr::from(s)))
+}
+
+/// The value of the variable `name`.
+pub(crate) fn var(name: &str) -> Exp {
+    lvalue(simple(name))
+}
+
+/// The value of `var`.
+pub(crate) fn lvalue(var: Var) -> Exp {
+    exp(ExpKind::Var(var))
+}
+
+pub(crate) fn simple(name: &str) -> Var {
+    Var {
+        kind: VarKind::Simple(sym(name)),
+        pos: pos(),
+    }
+}
+
+/// `record.name`
+pub(crate) fn field(record: Var, name: &str) -> Var {
+    Var {
+        kind: VarKind::Field(Box::new(record), sym(name)),
+        pos: pos(),
+    }
+}
+
+/// `array[index]`
+pub(crate) fn subscript(array: Var, index: Exp) -> Var {
+    Var {
+        kind: VarKind::Subscript(Box::new(array), boxed(index)),
+        pos: pos(),
+    }
+}
+
+pub(crate) fn call(func: &str, args: impl IntoIterator<Item = Exp>) -> Exp {
+    exp(ExpKind::Call {
+        func: sym(func),
+        args: args.into_iter().collect(),
+    })
+}
+
+pub(crate) fn op(left: Exp, op: Oper, right: Exp) -> Exp {
+    exp(ExpKind::Op {
+        left: boxed(left),
+        op,
+        right: boxed(right),
+    })
+}
+
+/// `typ { name = exp, ... }`
+pub(crate) fn record<'a>(typ: &str, fields: impl IntoIterator<Item = (&'a str, Exp)>) -> Exp {
+    let fields = fields
+        .into_iter()
+        .map(|(name, exp)| RecordField {
+            name: sym(name),
+            exp,
+            pos: pos(),
+        })
+        .collect();
+    exp(ExpKind::Record {
+        typ: sym(typ),
+        fields,
+    })
+}
+
+/// `typ [size] of init`
+pub(crate) fn array(typ: &str, size: Exp, init: Exp) -> Exp {
+    exp(ExpKind::Array {
+        typ: sym(typ),
+        size: boxed(size),
+        init: boxed(init),
+    })
+}
+
+/// `(e1; ...; en)`, represented as the parser does: `()` when empty and the
+/// expression itself when there is only one.
+pub(crate) fn seq(exps: impl IntoIterator<Item = Exp>) -> Exp {
+    let mut exps: Vec<Exp> = exps.into_iter().collect();
+    match exps.len() {
+        0 => unit(),
+        1 => exps.pop().expect("length checked"),
+        _ => exp(ExpKind::Seq(exps)),
+    }
+}
+
+pub(crate) fn assign(var: Var, value: Exp) -> Exp {
+    exp(ExpKind::Assign {
+        var,
+        exp: boxed(value),
+    })
+}
+
+pub(crate) fn if_then(test: Exp, then_: Exp) -> Exp {
+    exp(ExpKind::If {
+        test: boxed(test),
+        then_: boxed(then_),
+        else_: None,
+    })
+}
+
+pub(crate) fn if_else(test: Exp, then_: Exp, else_: Exp) -> Exp {
+    exp(ExpKind::If {
+        test: boxed(test),
+        then_: boxed(then_),
+        else_: Some(boxed(else_)),
+    })
+}
+
+pub(crate) fn while_(test: Exp, body: Exp) -> Exp {
+    exp(ExpKind::While {
+        test: boxed(test),
+        body: boxed(body),
+    })
+}
+
+pub(crate) fn for_(var: &str, lo: Exp, hi: Exp, body: Exp) -> Exp {
+    exp(ExpKind::For {
+        var: sym(var),
+        lo: boxed(lo),
+        hi: boxed(hi),
+        body: boxed(body),
+    })
+}
+
+pub(crate) fn break_() -> Exp {
+    exp(ExpKind::Break)
+}
+
+/// `let decs in body end`, with `body` a sequence as in `seq`.
+pub(crate) fn let_(
+    decs: impl IntoIterator<Item = Dec>,
+    body: impl IntoIterator<Item = Exp>,
+) -> Exp {
+    exp(ExpKind::Let {
+        decs: decs.into_iter().collect(),
+        body: boxed(seq(body)),
+    })
+}
+
+/// `var name: typ := init`, without `: typ` when `typ` is `None`.
+pub(crate) fn var_dec(name: &str, typ: Option<&str>, init: Exp) -> Dec {
+    Dec::Var(VarDec {
+        name: sym(name),
+        typ: typ.map(|t| (sym(t), pos())),
+        init,
+        pos: pos(),
+    })
+}
+
+/// A group of mutually recursive functions.
+pub(crate) fn functions(group: impl IntoIterator<Item = FunDec>) -> Dec {
+    Dec::Function(group.into_iter().collect())
+}
+
+/// `function name(params): result = body`; a procedure when `result` is
+/// `None`.
+pub(crate) fn fun_dec<'a>(
+    name: &str,
+    params: impl IntoIterator<Item = (&'a str, &'a str)>,
+    result: Option<&str>,
+    body: Exp,
+) -> FunDec {
+    FunDec {
+        name: sym(name),
+        params: field_list(params),
+        result: result.map(|t| (sym(t), pos())),
+        body,
+        pos: pos(),
+    }
+}
+
+/// A group of mutually recursive types.
+pub(crate) fn types(group: impl IntoIterator<Item = TypeDec>) -> Dec {
+    Dec::Type(group.into_iter().collect())
+}
+
+pub(crate) fn type_dec(name: &str, ty: Ty) -> TypeDec {
+    TypeDec {
+        name: sym(name),
+        ty,
+        pos: pos(),
+    }
+}
+
+/// The type named `name`.
+pub(crate) fn name_ty(name: &str) -> Ty {
+    Ty::Name(sym(name), pos())
+}
+
+/// `array of elem`
+pub(crate) fn array_ty(elem: &str) -> Ty {
+    Ty::Array(sym(elem), pos())
+}
+
+/// `{ name: type, ... }`
+pub(crate) fn record_ty<'a>(fields: impl IntoIterator<Item = (&'a str, &'a str)>) -> Ty {
+    Ty::Record(field_list(fields))
+}
+
+fn field_list<'a>(fields: impl IntoIterator<Item = (&'a str, &'a str)>) -> Vec<Field> {
+    fields
+        .into_iter()
+        .map(|(name, typ)| Field {
+            name: sym(name),
+            typ: sym(typ),
+            pos: pos(),
+        })
+        .collect()
+}
@@ -0,0 +1,215 @@
+//! The tree as JSON, for `--emit ast-json`, so that tools can read the
+//! parser's output without linking against the compiler.
+//!
+//! Every node is an object with its `kind`, the variant name, and its
+//! `span` as `{"lo": .., "hi": ..}` byte offsets, followed by the fields of
+//! the variant under the names they have here. `then_` and `else_` are
+//! `then` and `else`, an absent optional field is `null`, and an operator
+//! is its source text. A record type, which has no span of its own, is
+//! only its `kind` and `fields`. Integer literals are written out in full;
+//! a reader that parses JSON numbers as doubles will round those past 2^53.
+
+use super::{
+    Dec, Exp, ExpKind, Field, FunDec, RecordField, Symbol, Ty, TypeDec, Var, VarDec, VarKind,
+};
+use crate::json::Json;
+use crate::span::Span;
+
+pub(crate) fn exp(exp: &Exp) -> Json {
+    let boxed = |e: &Exp| self::exp(e);
+    let fields = match &exp.kind {
+        ExpKind::Var(v) => vec![("var", var(v))],
+        ExpKind::Nil | ExpKind::Unit | ExpKind::Break | ExpKind::Error => Vec::new(),
+        ExpKind::Int(n) => vec![("value", Json::Int(*n))],
+        ExpKind::String(s) => vec![("value", Json::str(s.to_string()))],
+        ExpKind::Call { func, args } => vec![
+            ("func", Json::str(func.to_string())),
+            ("args", Json::Array(args.iter().map(boxed).collect())),
+        ],
+        ExpKind::Op { left, op, right } => vec![
+            ("op", Json::str(op.text())),
+            ("left", boxed(left)),
+            ("right", boxed(right)),
+        ],
+        ExpKind::Record { typ, fields } => vec![
+            ("typ", Json::str(typ.to_string())),
+            (
+                "fields",
+                Json::Array(fields.iter().map(record_field).collect()),
+            ),
+        ],
+        ExpKind::Seq(exps) => vec![("exps", Json::Array(exps.iter().map(boxed).collect()))],
+        ExpKind::Assign { var: v, exp } => vec![("var", var(v)), ("exp", boxed(exp))],
+        ExpKind::If { test, then_, else_ } => vec![
+            ("test", boxed(test)),
+            ("then", boxed(then_)),
+            ("else", else_.as_deref().map_or(Json::Null, boxed)),
+        ],
+        ExpKind::While { test, body } => vec![("test", boxed(test)), ("body", boxed(body))],
+        ExpKind::For { var, lo, hi, body } => vec![
+            ("var", Json::str(var.to_string())),
+            ("lo", boxed(lo)),
+            ("hi", boxed(hi)),
+            ("body", boxed(body)),
+        ],
+        ExpKind::Let { decs, body } => vec![
+            ("decs", Json::Array(decs.iter().map(dec).collect())),
+            ("body", boxed(body)),
+        ],
+        ExpKind::Array { typ, size, init } => vec![
+            ("typ", Json::str(typ.to_string())),
+            ("size", boxed(size)),
+            ("init", boxed(init)),
+        ],
+    };
+    let kind = match exp.kind {
+        ExpKind::Var(_) => "Var",
+        ExpKind::Nil => "Nil",
+        ExpKind::Unit => "Unit",
+        ExpKind::Int(_) => "Int",
+        ExpKind::String(_) => "String",
+        ExpKind::Call { .. } => "Call",
+        ExpKind::Op { .. } => "Op",
+        ExpKind::Record { .. } => "Record",
+        ExpKind::Seq(_) => "Seq",
+        ExpKind::Assign { .. } => "Assign",
+        ExpKind::If { .. } => "If",
+        ExpKind::While { .. } => "While",
+        ExpKind::For { .. } => "For",
+        ExpKind::Break => "Break",
+        ExpKind::Let { .. } => "Let",
+        ExpKind::Array { .. } => "Array",
+        ExpKind::Error => "Error",
+    };
+    node(kind, exp.pos, fields)
+}
+
+/// An object with `kind`, `span`, and then `fields`.
+fn node(kind: &str, pos: Span, fields: Vec<(&str, Json)>) -> Json {
+    let header = [("kind", Json::str(kind)), ("span", span(pos))];
+    Json::object(header.into_iter().chain(fields))
+}
+
+fn span(pos: Span) -> Json {
+    Json::object([("lo", Json::from(pos.lo())), ("hi", Json::from(pos.hi()))])
+}
+
+fn var(v: &Var) -> Json {
+    match &v.kind {
+        VarKind::Simple(name) => node("Simple", v.pos, vec![("name", Json::str(name.to_string()))]),
+        VarKind::Field(base, field) => node(
+            "Field",
+            v.pos,
+            vec![("var", var(base)), ("field", Json::str(field.to_string()))],
+        ),
+        VarKind::Subscript(base, index) => node(
+            "Subscript",
+            v.pos,
+            vec![("var", var(base)), ("index", exp(index))],
+        ),
+    }
+}
+
+fn record_field(field: &RecordField) -> Json {
+    node(
+        "RecordField",
+        field.pos,
+        vec![
+            ("name", Json::str(field.name.to_string())),
+            ("exp", exp(&field.exp)),
+        ],
+    )
+}
+
+/// A name with the span of its use, as in a type annotation.
+fn name_at(name: &Symbol, pos: Span) -> Json {
+    Json::object([("name", Json::str(name.to_string())), ("span", span(pos))])
+}
+
+fn dec(dec: &Dec) -> Json {
+    match dec {
+        Dec::Function(group) => {
+            let members = group.iter().map(fun_dec).collect();
+            group_node("Function", group.iter().map(|f| f.pos), members)
+        }
+        Dec::Var(v) => var_dec(v),
+        Dec::Type(group) => {
+            let members = group.iter().map(type_dec).collect();
+            group_node("Type", group.iter().map(|t| t.pos), members)
+        }
+        Dec::Import(import) => node(
+            "Import",
+            import.pos,
+            vec![("path", Json::str(import.path.to_string()))],
+        ),
+    }
+}
+
+/// A group of declarations, whose span covers its members.
+fn group_node(kind: &str, mut spans: impl Iterator<Item = Span>, members: Vec<Json>) -> Json {
+    let first = spans.next().expect("a group is never empty");
+    let pos = spans.last().map_or(first, |last| first.to(last));
+    node(kind, pos, vec![("group", Json::Array(members))])
+}
+
+fn fun_dec(f: &FunDec) -> Json {
+    node(
+        "FunDec",
+        f.pos,
+        vec![
+            ("name", Json::str(f.name.to_string())),
+            ("params", Json::Array(f.params.iter().map(field).collect())),
+            (
+                "result",
+                f.result
+                    .as_ref()
+                    .map_or(Json::Null, |(name, pos)| name_at(name, *pos)),
+            ),
+            ("body", exp(&f.body)),
+        ],
+    )
+}
+
+fn var_dec(v: &VarDec) -> Json {
+    node(
+        "Var",
+        v.pos,
+        vec![
+            ("name", Json::str(v.name.to_string())),
+            (
+                "typ",
+                v.typ
+                    .as_ref()
+                    .map_or(Json::Null, |(name, pos)| name_at(name, *pos)),
+            ),
+            ("init", exp(&v.init)),
+        ],
+    )
+}
+
+fn type_dec(t: &TypeDec) -> Json {
+    let ty = match &t.ty {
+        Ty::Name(name, pos) => node("Name", *pos, vec![("name", Json::str(name.to_string()))]),
+        Ty::Array(name, pos) => node("Array", *pos, vec![("elem", Json::str(name.to_string()))]),
+        Ty::Record(fields) => Json::object([
+            ("kind", Json::str("Record")),
+            ("fields", Json::Array(fields.iter().map(field).collect())),
+        ]),
+    };
+    node(
+        "TypeDec",
+        t.pos,
+        vec![("name", Json::str(t.name.to_string())), ("ty", ty)],
+    )
+}
+
+fn field(f: &Field) -> Json {
+    node(
+        "Field",
+        f.pos,
+        vec![
+            ("name", Json::str(f.name.to_string())),
+            ("typ", Json::str(f.typ.to_string())),
+        ],
+    )
+}
@@ -0,0 +1,192 @@
+#![allow(dead_code)]
+
+//! Abstract syntax of Tiger programs, following the `Absyn` module of
+//! Appel's book. Every node records the source span it was parsed from.
+
+#[cfg(test)]
+pub(crate) mod build;
+pub(crate) mod json;
+pub(crate) mod visit;
+
+use crate::span::Span;
+pub(crate) use crate::symbol::{ByteStr, Symbol};
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Exp {
+    pub(crate) kind: ExpKind,
+    pub(crate) pos: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum ExpKind {
+    Var(Var),
+    Nil,
+    /// `()`, and the value of an empty `let ... in end` body.
+    Unit,
+    Int(i64),
+    /// A string literal's bytes, escapes decoded.
+    String(ByteStr),
+    Call {
+        func: Symbol,
+        args: Vec<Exp>,
+    },
+    Op {
+        left: Box<Exp>,
+        op: Oper,
+        right: Box<Exp>,
+    },
+    Record {
+        typ: Symbol,
+        fields: Vec<RecordField>,
+    },
+    /// `(e1; e2; ...)` with at least two expressions. A single parenthesized
+    /// expression is represented by the expression itself.
+    Seq(Vec<Exp>),
+    Assign {
+        var: Var,
+        exp: Box<Exp>,
+    },
+    /// `if test then then_` has no `else_` and produces no value.
+    If {
+        test: Box<Exp>,
+        then_: Box<Exp>,
+        else_: Option<Box<Exp>>,
+    },
+    While {
+        test: Box<Exp>,
+        body: Box<Exp>,
+    },
+    For {
+        var: Symbol,
+        lo: Box<Exp>,
+        hi: Box<Exp>,
+        body: Box<Exp>,
+    },
+    Break,
+    Let {
+        decs: Vec<Dec>,
+        body: Box<Exp>,
+    },
+    Array {
+        typ: Symbol,
+        size: Box<Exp>,
+        init: Box<Exp>,
+    },
+    /// Stands in for an expression that failed to parse. Only trees parsed
+    /// with syntax errors contain it, and those are never type checked.
+    Error,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Var {
+    pub(crate) kind: VarKind,
+    pub(crate) pos: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum VarKind {
+    Simple(Symbol),
+    Field(Box<Var>, Symbol),
+    Subscript(Box<Var>, Box<Exp>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Oper {
+    Plus,
+    Minus,
+    Times,
+    Divide,
+    Eq,
+    Neq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+impl Oper {
+    /// The operator as written in source.
+    pub(crate) fn text(self) -> &'static str {
+        match self {
+            Oper::Plus => "+",
+            Oper::Minus => "-",
+            Oper::Times => "*",
+            Oper::Divide => "/",
+            Oper::Eq => "=",
+            Oper::Neq => "<>",
+            Oper::Lt => "<",
+            Oper::Le => "<=",
+            Oper::Gt => ">",
+            Oper::Ge => ">=",
+            Oper::And => "&",
+            Oper::Or => "|",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct RecordField {
+    pub(crate) name: Symbol,
+    pub(crate) exp: Exp,
+    pub(crate) pos: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Dec {
+    /// A group of adjacent, possibly mutually recursive, function declarations.
+    Function(Vec<FunDec>),
+    Var(VarDec),
+    /// A group of adjacent, possibly mutually recursive, type declarations.
+    Type(Vec<TypeDec>),
+    /// `import "lib.tig"`: the declarations of another file. The driver
+    /// replaces it with them before type checking.
+    Import(Import),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct FunDec {
+    pub(crate) name: Symbol,
+    pub(crate) params: Vec<Field>,
+    pub(crate) result: Option<(Symbol, Span)>,
+    pub(crate) body: Exp,
+    pub(crate) pos: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct VarDec {
+    pub(crate) name: Symbol,
+    pub(crate) typ: Option<(Symbol, Span)>,
+    pub(crate) init: Exp,
+    pub(crate) pos: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Import {
+    /// The file, relative to the importing one.
+    pub(crate) path: Symbol,
+    pub(crate) pos: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct TypeDec {
+    pub(crate) name: Symbol,
+    pub(crate) ty: Ty,
+    pub(crate) pos: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Ty {
+    Name(Symbol, Span),
+    Record(Vec<Field>),
+    Array(Symbol, Span),
+}
+
+/// A `name: type-id` pair in a record type or a parameter list.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Field {
+    pub(crate) name: Symbol,
+    pub(crate) typ: Symbol,
+    pub(crate) pos: Span,
+}
@@ -0,0 +1,89 @@
+//! Read-only traversal of the AST.
+//!
+//! Implementors override the `visit_*` methods they care about and call the
+//! matching `walk_*` function to continue into the children, as in rustc's
+//! `Visitor`. The walk order is source order.
+
+use super::{Dec, Exp, ExpKind, Var, VarKind};
+
+pub(crate) trait Visitor: Sized {
+    fn visit_exp(&mut self, exp: &Exp) {
+        walk_exp(self, exp);
+    }
+
+    fn visit_var(&mut self, var: &Var) {
+        walk_var(self, var);
+    }
+
+    fn visit_dec(&mut self, dec: &Dec) {
+        walk_dec(self, dec);
+    }
+}
+
+pub(crate) fn walk_exp<V: Visitor>(v: &mut V, exp: &Exp) {
+    match &exp.kind {
+        ExpKind::Var(var) => v.visit_var(var),
+        ExpKind::Nil
+        | ExpKind::Unit
+        | ExpKind::Int(_)
+        | ExpKind::String(_)
+        | ExpKind::Break
+        | ExpKind::Error => {}
+        ExpKind::Call { args, .. } => args.iter().for_each(|arg| v.visit_exp(arg)),
+        ExpKind::Op { left, right, .. } => {
+            v.visit_exp(left);
+            v.visit_exp(right);
+        }
+        ExpKind::Record { fields, .. } => fields.iter().for_each(|f| v.visit_exp(&f.exp)),
+        ExpKind::Seq(exps) => exps.iter().for_each(|e| v.visit_exp(e)),
+        ExpKind::Assign { var, exp } => {
+            v.visit_var(var);
+            v.visit_exp(exp);
+        }
+        ExpKind::If { test, then_, else_ } => {
+            v.visit_exp(test);
+            v.visit_exp(then_);
+            if let Some(else_) = else_ {
+                v.visit_exp(else_);
+            }
+        }
+        ExpKind::While { test, body } => {
+            v.visit_exp(test);
+            v.visit_exp(body);
+        }
+        ExpKind::For { lo, hi, body, .. } => {
+            v.visit_exp(lo);
+            v.visit_exp(hi);
+            v.visit_exp(body);
+        }
+        ExpKind::Let { decs, body } => {
+            decs.iter().for_each(|dec| v.visit_dec(dec));
+            v.visit_exp(body);
+        }
+        ExpKind::Array { size, init, .. } => {
+            v.visit_exp(size);
+            v.visit_exp(init);
+        }
+    }
+}
+
+pub(crate) fn walk_var<V: Visitor>(v: &mut V, var: &Var) {
+    match &var.kind {
+        VarKind::Simple(_) => {}
+        VarKind::Field(base, _) => v.visit_var(base),
+        VarKind::Subscript(base, index) => {
+            v.visit_var(base);
+            v.visit_exp(index);
+        }
+    }
+}
+
+/// Visits initializers and function bodies; type declarations and imports
+/// have no expressions to visit.
+pub(crate) fn walk_dec<V: Visitor>(v: &mut V, dec: &Dec) {
+    match dec {
+        Dec::Var(var) => v.visit_exp(&var.init),
+        Dec::Function(group) => group.iter().for_each(|f| v.visit_exp(&f.body)),
+        Dec::Type(_) | Dec::Import(_) => {}
+    }
+}
@@ -0,0 +1,376 @@
+//! Lowers canonical IR to LLVM IR, as text that `llc` compiles and `lli`
+//! runs, so programs run wherever LLVM does without instruction selection
+//! of our own.
+//!
+//! Every function keeps its frame as `frame` lays it out, in an `alloca`
+//! whose address is the frame pointer: escaping locals below it, and the
+//! arguments past the sixth above the two words a native call would push.
+//! Static links are then frame addresses as in the native code, and an
+//! escaping variable is reached through memory the same way. Each
+//! temporary, machine registers included, is an `alloca` of its own, which
+//! LLVM turns back into registers when it optimizes the module (`mem2reg`).
+//! Every value is an `i64`; pointers only appear where memory is read or
+//! written.
+//!
+//! The text uses opaque `ptr` types, which LLVM 15 and later assume and
+//! LLVM 14 reads with `-opaque-pointers`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::canon;
+use crate::frame::{ARG_REGS, FP, RV, SP, WORD_SIZE};
+use crate::ir::{BinOp, Exp, RelOp, Stm};
+use crate::opt::{self, Fuel, Passes};
+use crate::temp::{Label, Temp};
+use crate::translate::Fragment;
+
+/// The name of the function `jit_entry` adds, which `lli` starts from.
+pub(crate) const JIT_ENTRY: &str = "tigerjit";
+
+/// The LLVM module of the translated program `fragments`, its functions
+/// optimized with `passes`. Functions the program calls but does not
+/// define are declared, to be found in the runtime library.
+pub(crate) fn module(fragments: &[Fragment], passes: Passes) -> Result<String, String> {
+    let mut out = String::from("declare void @llvm.trap() noreturn nounwind\n");
+    let mut defined = BTreeSet::new();
+    let mut called = BTreeMap::new();
+    let mut fuel = Fuel::new(passes);
+    for fragment in fragments {
+        match fragment {
+            Fragment::Proc { body, frame } => {
+                let (blocks, done) = canon::basic_blocks(canon::linearize(body.clone()));
+                let stms = opt::optimize(canon::trace_schedule(blocks, done), passes, &mut fuel);
+                for stm in &stms {
+                    calls(stm, &mut called);
+                }
+                let params = frame.formals().len();
+                let slots = frame.size() / WORD_SIZE;
+                out.push_str(&function(frame.name(), params, slots, &stms)?);
+                defined.insert(frame.name().name());
+            }
+            Fragment::String(label, s) => out.push_str(&string(*label, s.as_bytes())),
+        }
+    }
+    for (name, arity) in called {
+        if !defined.contains(name) {
+            let params = vec!["i64"; arity].join(", ");
+            let _ = writeln!(out, "declare i64 @\"{name}\"({params})");
+        }
+    }
+    Ok(out)
+}
+
+/// `module` with a function `JIT_ENTRY` that runs the program as the
+/// runtime library's `main` does, for `lli`, which must start from a
+/// function of the module it runs.
+pub(crate) fn jit_entry(module: &str) -> String {
+    let mut out = module.to_string();
+    if !module.contains("@\"tig_flush\"(") {
+        out.push_str("declare i64 @\"tig_flush\"()\n");
+    }
+    out.push_str("declare void @\"tig_setArgs\"(i32, ptr)\n");
+    let _ = write!(
+        out,
+        "define i32 @{JIT_ENTRY}(i32 %argc, ptr %argv) {{\n  \
+         call void @\"tig_setArgs\"(i32 %argc, ptr %argv)\n  \
+         call i64 @\"tigermain\"(i64 0)\n  \
+         call i64 @\"tig_flush\"()\n  ret i32 0\n}}\n"
+    );
+    out
+}
+
+/// Notes the callees of `stm` with the number of arguments passed.
+fn calls(stm: &Stm, called: &mut BTreeMap<&'static str, usize>) {
+    let exp = match stm {
+        Stm::Move(_, exp) | Stm::Exp(exp) => exp,
+        _ => return,
+    };
+    if let Exp::Call(func, args) = &**exp {
+        if let Exp::Name(label) = **func {
+            let arity = called.entry(label.name()).or_default();
+            *arity = (*arity).max(args.len());
+        }
+    }
+}
+
+/// A string literal: its length word, then its bytes, as the runtime
+/// library expects.
+fn string(label: Label, s: &[u8]) -> String {
+    let mut bytes = String::new();
+    for &b in s {
+        match b {
+            b' '..=b'~' if b != b'"' && b != b'\\' => bytes.push(b as char),
+            b => {
+                let _ = write!(bytes, "\\{b:02X}");
+            }
+        }
+    }
+    let n = s.len();
+    format!(
+        "@\"{label}\" = private constant {{ i64, [{n} x i8] }} \
+         {{ i64 {n}, [{n} x i8] c\"{bytes}\" }}, align {WORD_SIZE}\n"
+    )
+}
+
+/// The definition of one function of `params` parameters, static link
+/// included, with `slots` words of escaping locals.
+fn function(name: Label, params: usize, slots: i64, stms: &[Stm]) -> Result<String, String> {
+    let mut temps = BTreeSet::from([FP, SP, RV]);
+    temps.extend(&ARG_REGS);
+    for stm in stms {
+        stm_temps(stm, &mut temps);
+    }
+    let stacked = params.saturating_sub(ARG_REGS.len());
+    let words = slots + 2 + stacked as i64;
+
+    let mut f = Lowering {
+        out: String::new(),
+        next: 0,
+        open: true,
+        proc: name,
+    };
+    let formals: Vec<String> = (0..params).map(|i| format!("i64 %a{i}")).collect();
+    let _ = writeln!(f.out, "define i64 @\"{name}\"({}) {{", formals.join(", "));
+    f.out.push_str("entry:\n");
+    let _ = writeln!(f.out, "  %frame = alloca [{words} x i64], align 16");
+    for t in &temps {
+        let _ = writeln!(f.out, "  %{t} = alloca i64");
+        let _ = writeln!(f.out, "  store i64 0, ptr %{t}");
+    }
+    let base = f.value("ptrtoint ptr %frame to i64");
+    let fp = f.value(&format!("add i64 {base}, {}", slots * WORD_SIZE));
+    let _ = writeln!(f.out, "  store i64 {fp}, ptr %{FP}");
+    let _ = writeln!(f.out, "  store i64 {base}, ptr %{SP}");
+    for i in 0..params {
+        match ARG_REGS.get(i) {
+            Some(reg) => {
+                let _ = writeln!(f.out, "  store i64 %a{i}, ptr %{reg}");
+            }
+            None => {
+                let offset = (2 + (i - ARG_REGS.len()) as i64) * WORD_SIZE;
+                let addr = f.value(&format!("add i64 {fp}, {offset}"));
+                f.store(&addr, &format!("%a{i}"));
+            }
+        }
+    }
+    for stm in stms {
+        f.stm(stm)?;
+    }
+    if f.open {
+        let rv = f.value(&format!("load i64, ptr %{RV}"));
+        f.close(&format!("ret i64 {rv}"));
+    }
+    f.out.push_str("}\n");
+    Ok(f.out)
+}
+
+fn stm_temps(stm: &Stm, temps: &mut BTreeSet<Temp>) {
+    match stm {
+        Stm::Move(a, b) | Stm::CJump(_, a, b, ..) => {
+            exp_temps(a, temps);
+            exp_temps(b, temps);
+        }
+        Stm::Exp(e) | Stm::Jump(e, _) => exp_temps(e, temps),
+        Stm::Seq(a, b) => {
+            stm_temps(a, temps);
+            stm_temps(b, temps);
+        }
+        Stm::Label(_) => {}
+    }
+}
+
+fn exp_temps(exp: &Exp, temps: &mut BTreeSet<Temp>) {
+    match exp {
+        Exp::Temp(t) => {
+            temps.insert(*t);
+        }
+        Exp::BinOp(_, a, b) => {
+            exp_temps(a, temps);
+            exp_temps(b, temps);
+        }
+        Exp::Mem(e) => exp_temps(e, temps),
+        Exp::Call(func, args) => {
+            exp_temps(func, temps);
+            args.iter().for_each(|arg| exp_temps(arg, temps));
+        }
+        Exp::ESeq(s, e) => {
+            stm_temps(s, temps);
+            exp_temps(e, temps);
+        }
+        Exp::Const(_) | Exp::Name(_) => {}
+    }
+}
+
+/// The body of one function being lowered.
+struct Lowering {
+    out: String,
+    /// The number of the next value.
+    next: u32,
+    /// Whether the current block still lacks its terminator.
+    open: bool,
+    proc: Label,
+}
+
+impl Lowering {
+    /// A new value computed by `instr`.
+    fn value(&mut self, instr: &str) -> String {
+        let v = format!("%v{}", self.next);
+        self.next += 1;
+        let _ = writeln!(self.out, "  {v} = {instr}");
+        v
+    }
+
+    fn store(&mut self, addr: &str, value: &str) {
+        let ptr = self.value(&format!("inttoptr i64 {addr} to ptr"));
+        let _ = writeln!(self.out, "  store i64 {value}, ptr {ptr}");
+    }
+
+    /// Ends the current block with `terminator`.
+    fn close(&mut self, terminator: &str) {
+        let _ = writeln!(self.out, "  {terminator}");
+        self.open = false;
+    }
+
+    fn branch(&mut self, target: Label) {
+        self.close(&format!("br label %\"{target}\""));
+    }
+
+    /// Starts a block for statements that follow a jump without a label,
+    /// which no jump reaches, as LLVM wants every block to begin with one.
+    fn reopen(&mut self) {
+        if !self.open {
+            let _ = writeln!(self.out, "dead{}:", self.next);
+            self.next += 1;
+            self.open = true;
+        }
+    }
+
+    fn stm(&mut self, stm: &Stm) -> Result<(), String> {
+        if let Stm::Label(label) = stm {
+            if self.open {
+                self.branch(*label);
+            }
+            let _ = writeln!(self.out, "\"{label}\":");
+            self.open = true;
+            return Ok(());
+        }
+        self.reopen();
+        match stm {
+            Stm::Move(dst, src) => match &**dst {
+                Exp::Temp(t) => {
+                    let value = self.exp(src)?;
+                    let _ = writeln!(self.out, "  store i64 {value}, ptr %{t}");
+                }
+                Exp::Mem(addr) => {
+                    let addr = self.exp(addr)?;
+                    let value = self.exp(src)?;
+                    self.store(&addr, &value);
+                }
+                _ => unreachable!("`MOVE` into a temporary or memory"),
+            },
+            Stm::Exp(exp) => {
+                self.exp(exp)?;
+            }
+            Stm::Jump(target, _) => match **target {
+                Exp::Name(target) => self.branch(target),
+                _ => return Err(format!("computed jump in `{}`", self.proc)),
+            },
+            Stm::CJump(op, a, b, t, f) => {
+                let (a, b) = (self.exp(a)?, self.exp(b)?);
+                let test = self.value(&format!("icmp {} i64 {a}, {b}", relation(*op)));
+                self.close(&format!("br i1 {test}, label %\"{t}\", label %\"{f}\""));
+            }
+            Stm::Seq(..) | Stm::Label(_) => unreachable!("canonical trees have no `SEQ`"),
+        }
+        Ok(())
+    }
+
+    /// The operand holding the value of `exp`.
+    fn exp(&mut self, exp: &Exp) -> Result<String, String> {
+        Ok(match exp {
+            Exp::Const(n) => n.to_string(),
+            Exp::Name(label) => format!("ptrtoint (ptr @\"{label}\" to i64)"),
+            Exp::Temp(t) => self.value(&format!("load i64, ptr %{t}")),
+            Exp::BinOp(op, a, b) => {
+                let (a, b) = (self.exp(a)?, self.exp(b)?);
+                let instr = match op {
+                    BinOp::Plus => "add",
+                    BinOp::Minus => "sub",
+                    BinOp::Mul => "mul",
+                    // Traps on zero as the native code does, and negates for
+                    // -1, wrapping as the interpreters do, rather than leaving
+                    // either result undefined.
+                    BinOp::Div => {
+                        let n = self.next;
+                        let zero = self.value(&format!("icmp eq i64 {b}, 0"));
+                        let _ = writeln!(
+                            self.out,
+                            "  br i1 {zero}, label %trap{n}, label %div{n}\n\
+                             trap{n}:\n  call void @llvm.trap()\n  unreachable\n\
+                             div{n}:"
+                        );
+                        let minus_one = self.value(&format!("icmp eq i64 {b}, -1"));
+                        let _ = writeln!(
+                            self.out,
+                            "  br i1 {minus_one}, label %neg{n}, label %quot{n}\nneg{n}:"
+                        );
+                        let neg = self.value(&format!("sub i64 0, {a}"));
+                        let _ = writeln!(self.out, "  br label %join{n}\nquot{n}:");
+                        let quot = self.value(&format!("sdiv i64 {a}, {b}"));
+                        let _ = writeln!(self.out, "  br label %join{n}\njoin{n}:");
+                        return Ok(
+                            self.value(&format!("phi i64 [{neg}, %neg{n}], [{quot}, %quot{n}]"))
+                        );
+                    }
+                    BinOp::And => "and",
+                    BinOp::Or => "or",
+                    BinOp::Xor => "xor",
+                    BinOp::LShift | BinOp::RShift | BinOp::ARShift => {
+                        // Only the low six bits of a shift count matter, as
+                        // on x86-64; LLVM leaves larger counts undefined.
+                        let count = self.value(&format!("and i64 {b}, 63"));
+                        let instr = match op {
+                            BinOp::LShift => "shl",
+                            BinOp::RShift => "lshr",
+                            _ => "ashr",
+                        };
+                        return Ok(self.value(&format!("{instr} i64 {a}, {count}")));
+                    }
+                };
+                self.value(&format!("{instr} i64 {a}, {b}"))
+            }
+            Exp::Mem(addr) => {
+                let addr = self.exp(addr)?;
+                let ptr = self.value(&format!("inttoptr i64 {addr} to ptr"));
+                self.value(&format!("load i64, ptr {ptr}"))
+            }
+            Exp::Call(func, args) => {
+                let Exp::Name(callee) = **func else {
+                    return Err(format!("computed call in `{}`", self.proc));
+                };
+                let args = args
+                    .iter()
+                    .map(|arg| Ok(format!("i64 {}", self.exp(arg)?)))
+                    .collect::<Result<Vec<_>, String>>()?;
+                self.value(&format!("call i64 @\"{callee}\"({})", args.join(", ")))
+            }
+            Exp::ESeq(..) => unreachable!("canonical trees have no `ESEQ`"),
+        })
+    }
+}
+
+fn relation(op: RelOp) -> &'static str {
+    match op {
+        RelOp::Eq => "eq",
+        RelOp::Ne => "ne",
+        RelOp::Lt => "slt",
+        RelOp::Gt => "sgt",
+        RelOp::Le => "sle",
+        RelOp::Ge => "sge",
+        RelOp::ULt => "ult",
+        RelOp::ULe => "ule",
+        RelOp::UGt => "ugt",
+        RelOp::UGe => "uge",
+    }
+}
@@ -0,0 +1,10 @@
+#![allow(dead_code)]
+
+//! Backends that hand the program to an existing compiler instead of
+//! selecting instructions themselves, built with the cargo feature of the
+//! same name.
+
+#[cfg(feature = "llvm")]
+pub(crate) mod llvm;
+#[cfg(test)]
+mod tests;
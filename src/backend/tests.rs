@@ -0,0 +1,68 @@
+use crate::backend::llvm;
+use crate::opt::Passes;
+use crate::parser::parse;
+use crate::semant::Semant;
+
+fn module(src: &str, passes: Passes) -> String {
+    let exp = parse(src).unwrap();
+    let mut semant = Semant::new();
+    semant.check(&exp);
+    assert!(semant.errors().is_empty(), "{:?}", semant.errors());
+    llvm::module(semant.fragments(), passes).unwrap()
+}
+
+#[test]
+fn programs_become_modules() {
+    let src = "let
+        function f(x: int): int = if x < 2 then x else f(x - 1) * 2
+    in print(\"a\\\"b\\n\"); f(3) / 2 end";
+    for passes in [Passes::NONE, Passes::ALL] {
+        let out = module(src, passes);
+        assert!(out.contains("define i64 @\"tigermain\"(i64 %a0)"), "{out}");
+        assert!(out.contains("declare i64 @\"tig_print\"(i64)\n"), "{out}");
+        assert!(!out.contains("define i64 @\"tig_print\""), "{out}");
+        // A string is its length followed by its bytes, escaped.
+        assert!(out.contains("{ i64 4, [4 x i8] c\"a\\22b\\0A\" }"), "{out}");
+        // Division by zero traps rather than being undefined.
+        assert!(out.contains("call void @llvm.trap()"), "{out}");
+        // Nor is the smallest integer divided by -1, which wraps.
+        assert!(out.contains("icmp eq i64 2, -1"), "{out}");
+    }
+}
+
+#[test]
+fn the_jit_entry_calls_the_program_and_flushes() {
+    let out = llvm::jit_entry(&module("42", Passes::NONE));
+    let entry = format!("define i32 @{}(i32 %argc, ptr %argv)", llvm::JIT_ENTRY);
+    assert!(out.contains(&entry), "{out}");
+    assert!(out.contains("call void @\"tig_setArgs\"(i32 %argc, ptr %argv)"));
+    assert!(out.contains("call i64 @\"tigermain\"(i64 0)"), "{out}");
+    assert_eq!(out.matches("declare i64 @\"tig_flush\"()").count(), 1);
+}
+
+/// Nothing is gated on `features::detect` before lowering to LLVM: every
+/// construct it reports translates to canonical IR, all of which lowers.
+/// This program uses them all, so a construct the backend stopped lowering
+/// would fail here rather than in a user's build.
+#[test]
+fn every_detected_construct_lowers() {
+    let src = "let
+        type list = {head: int, tail: list}
+        type ints = array of int
+        var a := ints [3] of 0
+        function sum(l: list): int =
+            let function add(x: int): int = x + l.head
+            in if l = nil then 0 else add(sum(l.tail)) end
+    in
+        for i := 0 to 2 do (a[i] := sum(list {head = i, tail = nil}); if i = 1 then break);
+        while a[0] < 0 do break;
+        print(\"done\")
+    end";
+    let f = crate::features::detect(&parse(src).unwrap());
+    assert!(f.recursion && f.nested_functions && f.records && f.arrays && f.strings);
+    assert!(f.for_loops && f.while_loops && f.breaks);
+    for passes in [Passes::NONE, Passes::ALL] {
+        let out = module(src, passes);
+        assert!(out.contains("define i64 @\"sum"), "{out}");
+    }
+}
@@ -0,0 +1,30 @@
+//! Cancelling analyses nobody waits for any more.
+//!
+//! The language server analyzes a document on every change, and an edit
+//! made while the previous one is still being analyzed makes that analysis
+//! stale. The thread that reads the edits cancels the token of the stale
+//! analysis; the analysis checks it between phases, and the type checker
+//! at every expression, and gives up. What a cancelled phase produced is
+//! incomplete and must be thrown away.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A flag shared by the work it cancels and whoever cancels it. Clones
+/// share the flag. The default token is never cancelled.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub(crate) fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub(crate) fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
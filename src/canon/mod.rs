@@ -0,0 +1,271 @@
+#![allow(dead_code)]
+
+//! Canonical trees, the `Canon` module of chapter 8.
+//!
+//! `linearize` removes `ESeq`s and `Seq`s and moves every `Call` to the top
+//! of an `Exp` or `Move(Temp, ..)` statement. `basic_blocks` then cuts the
+//! list into blocks that start with a label and end with a jump, and
+//! `trace_schedule` orders the blocks so that every `CJump` is followed by
+//! its false label.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+
+use crate::ir::{Exp, Stm};
+use crate::temp::{Label, Temp};
+
+/// The statements of `stm` as a flat list with no `Seq` or `ESeq`, where
+/// each `Call` is the whole right-hand side of a `Move` to a temporary or
+/// of an `Exp`.
+pub(crate) fn linearize(stm: Stm) -> Vec<Stm> {
+    let mut out = Vec::new();
+    linear(do_stm(stm), &mut out);
+    out
+}
+
+fn linear(mut stm: Stm, out: &mut Vec<Stm>) {
+    // Loops down the right spine, as `join` builds it, and recurses only
+    // into left operands.
+    while let Stm::Seq(a, b) = stm {
+        linear(*a, out);
+        stm = *b;
+    }
+    if !is_nop(&stm) {
+        out.push(stm);
+    }
+}
+
+fn nop() -> Stm {
+    Stm::exp(Exp::Const(0))
+}
+
+fn is_nop(stm: &Stm) -> bool {
+    matches!(stm, Stm::Exp(e) if matches!(**e, Exp::Const(_)))
+}
+
+/// `a; b`, leaving out no-ops.
+fn join(a: Stm, b: Stm) -> Stm {
+    if is_nop(&a) {
+        b
+    } else if is_nop(&b) {
+        a
+    } else {
+        Stm::Seq(Box::new(a), Box::new(b))
+    }
+}
+
+/// Whether `stm` can run before `exp` is evaluated without changing
+/// either. Conservative: only trivially true cases are recognized.
+fn commute(stm: &Stm, exp: &Exp) -> bool {
+    is_nop(stm) || matches!(exp, Exp::Name(_) | Exp::Const(_))
+}
+
+/// Pulls the side effects out of `exps`, keeping their evaluation order.
+/// Returns the statement to run first and the remaining pure expressions.
+fn reorder(exps: Vec<Exp>) -> (Stm, Vec<Exp>) {
+    let mut exps = exps.into_iter();
+    let Some(first) = exps.next() else {
+        return (nop(), Vec::new());
+    };
+    // A call clobbers the return-value register, so its result must be
+    // saved before the next call.
+    let first = match first {
+        Exp::Call(..) => {
+            let t = Temp::new();
+            Exp::eseq(Stm::mov(Exp::Temp(t), first), Exp::Temp(t))
+        }
+        first => first,
+    };
+    let (s, e) = do_exp(first);
+    let (rest_stm, mut rest) = reorder(exps.collect());
+    if commute(&rest_stm, &e) {
+        rest.insert(0, e);
+        (join(s, rest_stm), rest)
+    } else {
+        let t = Temp::new();
+        rest.insert(0, Exp::Temp(t));
+        (join(join(s, Stm::mov(Exp::Temp(t), e)), rest_stm), rest)
+    }
+}
+
+fn do_exp(exp: Exp) -> (Stm, Exp) {
+    match exp {
+        Exp::BinOp(op, a, b) => {
+            let (s, mut es) = reorder(vec![*a, *b]);
+            let b = es.pop().expect("two operands");
+            let a = es.pop().expect("two operands");
+            (s, Exp::binop(op, a, b))
+        }
+        Exp::Mem(a) => {
+            let (s, mut es) = reorder(vec![*a]);
+            (s, Exp::mem(es.pop().expect("one operand")))
+        }
+        Exp::ESeq(s, e) => {
+            let s = do_stm(*s);
+            let (s2, e) = do_exp(*e);
+            (join(s, s2), e)
+        }
+        Exp::Call(func, args) => reorder_call(*func, args),
+        exp => (nop(), exp),
+    }
+}
+
+/// Reorders the function and arguments of a call, leaving the call itself
+/// in place.
+fn reorder_call(func: Exp, args: Vec<Exp>) -> (Stm, Exp) {
+    let (s, mut es) = reorder(std::iter::once(func).chain(args).collect());
+    let func = es.remove(0);
+    (s, Exp::call(func, es))
+}
+
+fn do_stm(stm: Stm) -> Stm {
+    match stm {
+        Stm::Seq(a, b) => join(do_stm(*a), do_stm(*b)),
+        Stm::Jump(e, labels) => {
+            let (s, mut es) = reorder(vec![*e]);
+            join(s, Stm::jump_to(es.pop().expect("one operand"), labels))
+        }
+        Stm::CJump(op, a, b, t, f) => {
+            let (s, mut es) = reorder(vec![*a, *b]);
+            let b = es.pop().expect("two operands");
+            let a = es.pop().expect("two operands");
+            join(s, Stm::cjump(op, a, b, t, f))
+        }
+        Stm::Move(dst, src) => match (*dst, *src) {
+            (Exp::Temp(t), Exp::Call(func, args)) => {
+                let (s, call) = reorder_call(*func, args);
+                join(s, Stm::mov(Exp::Temp(t), call))
+            }
+            (Exp::Temp(t), src) => {
+                let (s, mut es) = reorder(vec![src]);
+                join(s, Stm::mov(Exp::Temp(t), es.pop().expect("one operand")))
+            }
+            (Exp::Mem(addr), src) => {
+                let (s, mut es) = reorder(vec![*addr, src]);
+                let src = es.pop().expect("two operands");
+                let addr = es.pop().expect("two operands");
+                join(s, Stm::mov(Exp::mem(addr), src))
+            }
+            (Exp::ESeq(s, e), src) => do_stm(Stm::Seq(s, Box::new(Stm::mov(*e, src)))),
+            (dst, _) => unreachable!("cannot move into {dst:?}"),
+        },
+        Stm::Exp(e) => match *e {
+            Exp::Call(func, args) => {
+                let (s, call) = reorder_call(*func, args);
+                join(s, Stm::exp(call))
+            }
+            e => {
+                let (s, mut es) = reorder(vec![e]);
+                join(s, Stm::exp(es.pop().expect("one operand")))
+            }
+        },
+        Stm::Label(_) => stm,
+    }
+}
+
+/// Splits linearized statements into basic blocks. Every block starts with
+/// a `Label` and ends with a `Jump` or `CJump`; the last one jumps to the
+/// returned label, which the caller places at the end of the function.
+pub(crate) fn basic_blocks(stms: Vec<Stm>) -> (Vec<Vec<Stm>>, Label) {
+    let done = Label::new();
+    let mut blocks: Vec<Vec<Stm>> = Vec::new();
+    let mut current: Vec<Stm> = Vec::new();
+    for stm in stms {
+        match stm {
+            Stm::Label(label) => {
+                if !current.is_empty() {
+                    current.push(Stm::jump(label));
+                    blocks.push(std::mem::take(&mut current));
+                }
+                current.push(Stm::Label(label));
+            }
+            stm => {
+                if current.is_empty() {
+                    current.push(Stm::Label(Label::new()));
+                }
+                let ends_block = matches!(stm, Stm::Jump(..) | Stm::CJump(..));
+                current.push(stm);
+                if ends_block {
+                    blocks.push(std::mem::take(&mut current));
+                }
+            }
+        }
+    }
+    if !current.is_empty() {
+        current.push(Stm::jump(done));
+        blocks.push(current);
+    }
+    (blocks, done)
+}
+
+fn block_label(block: &[Stm]) -> Label {
+    match block.first() {
+        Some(Stm::Label(label)) => *label,
+        _ => unreachable!("basic blocks start with a label"),
+    }
+}
+
+/// Orders the blocks into traces so that every `CJump` is immediately
+/// followed by its false label, and drops jumps to the next statement.
+pub(crate) fn trace_schedule(blocks: Vec<Vec<Stm>>, done: Label) -> Vec<Stm> {
+    let index: HashMap<Label, usize> = blocks
+        .iter()
+        .enumerate()
+        .map(|(i, block)| (block_label(block), i))
+        .collect();
+    let mut blocks: Vec<Option<Vec<Stm>>> = blocks.into_iter().map(Some).collect();
+    let mut out: Vec<Stm> = Vec::new();
+    for start in 0..blocks.len() {
+        let mut next = Some(start);
+        while let Some(i) = next.take() {
+            let Some(mut block) = blocks[i].take() else {
+                break;
+            };
+            let unmarked = |label: &Label, blocks: &[Option<Vec<Stm>>]| {
+                index.get(label).copied().filter(|&j| blocks[j].is_some())
+            };
+            match block.pop().expect("blocks end with a jump") {
+                Stm::Jump(target, labels) => {
+                    block.push(Stm::jump_to(*target, labels.clone()));
+                    if let [label] = labels[..] {
+                        next = unmarked(&label, &blocks);
+                    }
+                }
+                Stm::CJump(op, a, b, t, f) => {
+                    if let Some(j) = unmarked(&f, &blocks) {
+                        block.push(Stm::cjump(op, *a, *b, t, f));
+                        next = Some(j);
+                    } else if let Some(j) = unmarked(&t, &blocks) {
+                        block.push(Stm::cjump(op.negate(), *a, *b, f, t));
+                        next = Some(j);
+                    } else {
+                        let f2 = Label::new();
+                        block.push(Stm::cjump(op, *a, *b, t, f2));
+                        block.push(Stm::Label(f2));
+                        block.push(Stm::jump(f));
+                    }
+                }
+                _ => unreachable!("blocks end with a jump"),
+            }
+            out.extend(block);
+        }
+    }
+    out.push(Stm::Label(done));
+    remove_fallthrough_jumps(out)
+}
+
+/// Drops `Jump(l)` when the next statement is `Label(l)`.
+pub(crate) fn remove_fallthrough_jumps(stms: Vec<Stm>) -> Vec<Stm> {
+    let mut out: Vec<Stm> = Vec::with_capacity(stms.len());
+    for stm in stms {
+        if let (Stm::Label(label), Some(Stm::Jump(target, _))) = (&stm, out.last()) {
+            if **target == Exp::Name(*label) {
+                out.pop();
+            }
+        }
+        out.push(stm);
+    }
+    out
+}
@@ -0,0 +1,191 @@
+use crate::canon::{basic_blocks, linearize, trace_schedule};
+use crate::ir::{self, Exp, RelOp, Stm};
+use crate::parser::parse;
+use crate::semant::Semant;
+use crate::temp::{Label, Temp};
+use crate::translate::Fragment;
+
+/// Translates `src`, which must type check, and canonicalizes every
+/// procedure body.
+fn canonical_bodies(src: &str) -> Vec<Vec<Stm>> {
+    let exp = parse(src).unwrap_or_else(|e| panic!("failed to parse {src:?}: {e:?}"));
+    let mut semant = Semant::new();
+    semant.check(&exp);
+    assert!(semant.errors().is_empty(), "{:?}", semant.errors());
+    semant
+        .fragments()
+        .iter()
+        .filter_map(|fragment| match fragment {
+            Fragment::Proc { body, .. } => {
+                let (blocks, done) = basic_blocks(linearize(body.clone()));
+                let stms = trace_schedule(blocks, done);
+                assert_eq!(ir::check(&ir::seq(stms.clone())), Ok(()));
+                Some(stms)
+            }
+            Fragment::String(..) => None,
+        })
+        .collect()
+}
+
+fn has_eseq_or_call(exp: &Exp) -> bool {
+    match exp {
+        Exp::ESeq(..) | Exp::Call(..) => true,
+        Exp::BinOp(_, a, b) => has_eseq_or_call(a) || has_eseq_or_call(b),
+        Exp::Mem(a) => has_eseq_or_call(a),
+        Exp::Const(_) | Exp::Name(_) | Exp::Temp(_) => false,
+    }
+}
+
+/// Checks the properties `trace_schedule` promises: no `Seq` or `ESeq`,
+/// calls only at the top of `Exp` or `Move(Temp, ..)`, and every `CJump`
+/// followed by its false label.
+fn assert_canonical(stms: &[Stm]) {
+    let pure_call = |exp: &Exp| match exp {
+        Exp::Call(func, args) => !has_eseq_or_call(func) && !args.iter().any(has_eseq_or_call),
+        exp => !has_eseq_or_call(exp),
+    };
+    for (i, stm) in stms.iter().enumerate() {
+        let ok = match stm {
+            Stm::Seq(..) => false,
+            Stm::Label(_) => true,
+            Stm::Exp(e) => pure_call(e),
+            Stm::Move(dst, src) => match **dst {
+                Exp::Temp(_) => pure_call(src),
+                _ => !has_eseq_or_call(dst) && !has_eseq_or_call(src),
+            },
+            Stm::Jump(e, _) => !has_eseq_or_call(e),
+            Stm::CJump(_, a, b, _, f) => {
+                !has_eseq_or_call(a)
+                    && !has_eseq_or_call(b)
+                    && stms.get(i + 1) == Some(&Stm::Label(*f))
+            }
+        };
+        assert!(ok, "not canonical at {i}: {stm}");
+    }
+}
+
+#[test]
+fn eseqs_are_lifted_in_evaluation_order() {
+    let (a, b) = (Temp::new(), Temp::new());
+    let effect = |t: Temp, n: i64| Stm::mov(Exp::Temp(t), Exp::Const(n));
+    // MOVE(a, BINOP(+, ESEQ(b := 1, TEMP b), ESEQ(a := 2, TEMP a)))
+    let stm = Stm::mov(
+        Exp::Temp(a),
+        Exp::binop(
+            ir::BinOp::Plus,
+            Exp::eseq(effect(b, 1), Exp::Temp(b)),
+            Exp::eseq(effect(a, 2), Exp::Temp(a)),
+        ),
+    );
+    let stms = linearize(stm);
+    assert_eq!(stms.len(), 4, "{stms:?}");
+    assert_eq!(stms[0], effect(b, 1));
+    // `b` must be read before the second ESEQ runs, so it is saved.
+    let Stm::Move(saved, value) = &stms[1] else {
+        panic!("expected a move, got {}", stms[1]);
+    };
+    assert_eq!(**value, Exp::Temp(b));
+    assert_eq!(stms[2], effect(a, 2));
+    assert_eq!(
+        stms[3],
+        Stm::mov(
+            Exp::Temp(a),
+            Exp::binop(ir::BinOp::Plus, (**saved).clone(), Exp::Temp(a))
+        )
+    );
+}
+
+#[test]
+fn nested_calls_are_saved_in_temporaries() {
+    let f = Exp::Name(Label::named("f"));
+    let inner = Exp::call(f.clone(), vec![Exp::Const(1)]);
+    let stms = linearize(Stm::exp(Exp::call(f.clone(), vec![inner.clone(), inner])));
+    let saved_calls = stms
+        .iter()
+        .filter(|stm| matches!(stm, Stm::Move(t, c) if matches!(**t, Exp::Temp(_)) && matches!(**c, Exp::Call(..))))
+        .count();
+    assert_eq!(saved_calls, 2, "{stms:?}");
+    assert!(
+        matches!(stms.last(), Some(Stm::Exp(e)) if matches!(**e, Exp::Call(..))),
+        "{stms:?}"
+    );
+    assert_canonical(&stms);
+}
+
+#[test]
+fn blocks_start_with_labels_and_end_with_jumps() {
+    let (l1, l2) = (Label::new(), Label::new());
+    let t = Temp::new();
+    let stms = vec![
+        Stm::mov(Exp::Temp(t), Exp::Const(0)),
+        Stm::cjump(RelOp::Lt, Exp::Temp(t), Exp::Const(1), l1, l2),
+        Stm::Label(l1),
+        Stm::mov(Exp::Temp(t), Exp::Const(1)),
+        Stm::Label(l2),
+    ];
+    let (blocks, done) = basic_blocks(stms);
+    assert_eq!(blocks.len(), 3);
+    for block in &blocks {
+        assert!(matches!(block.first(), Some(Stm::Label(_))), "{block:?}");
+        assert!(
+            matches!(block.last(), Some(Stm::Jump(..) | Stm::CJump(..))),
+            "{block:?}"
+        );
+    }
+    assert_eq!(blocks[1].last(), Some(&Stm::jump(l2)));
+    assert_eq!(blocks[2].last(), Some(&Stm::jump(done)));
+}
+
+#[test]
+fn traces_put_false_labels_after_cjumps() {
+    let (t, f, join) = (Label::new(), Label::new(), Label::new());
+    let x = Temp::new();
+    // The false block comes first, so the condition must be negated.
+    let blocks = vec![
+        vec![
+            Stm::Label(Label::new()),
+            Stm::cjump(RelOp::Lt, Exp::Temp(x), Exp::Const(0), t, f),
+        ],
+        vec![Stm::Label(f), Stm::jump(join)],
+        vec![Stm::Label(join), Stm::jump(t)],
+        vec![Stm::Label(t), Stm::jump(f)],
+    ];
+    let done = Label::new();
+    let stms = trace_schedule(blocks, done);
+    assert_canonical(&stms);
+    assert_eq!(stms.last(), Some(&Stm::Label(done)));
+    // Jumps to the next statement are dropped.
+    for pair in stms.windows(2) {
+        if let [Stm::Jump(target, _), Stm::Label(label)] = pair {
+            assert_ne!(**target, Exp::Name(*label));
+        }
+    }
+}
+
+#[test]
+fn translated_programs_become_canonical() {
+    let programs = [
+        "let
+            type intArray = array of int
+            var a := intArray [10] of 0
+            function f(x: int): int = if x < 2 then x else f(x - 1) + f(x - 2)
+        in
+            for i := 0 to 9 do a[i] := f(i);
+            while a[0] < 10 & a[1] <> 3 do (a[0] := a[0] + 1; if a[0] = 5 then break);
+            print(chr(ord(\"a\") + a[9]))
+        end",
+        "let
+            type r = {a: int, b: string}
+            var x := r {a = 1, b = \"s\"}
+            function g(n: int): string = if n > 0 | x.b = \"t\" then \"y\" else \"n\"
+        in
+            x.a := size(concat(g(x.a), g(2)));
+            if x = nil then print(\"nil\")
+        end",
+    ];
+    for src in programs {
+        for stms in canonical_bodies(src) {
+            assert_canonical(&stms);
+        }
+    }
+}
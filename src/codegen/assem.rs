@@ -0,0 +1,116 @@
+//! Machine instructions with abstract operands, the `Assem` module of
+//! chapter 9.
+//!
+//! The assembly text refers to operands by position: `` `s0 `` is the first
+//! source temporary, `` `d0 `` the first destination and `` `j0 `` the first
+//! jump target. Register allocation fills them in when formatting.
+
+use std::fmt::Write;
+
+use crate::frame;
+use crate::temp::{Label, Temp};
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Instr {
+    Oper {
+        assem: String,
+        dst: Vec<Temp>,
+        src: Vec<Temp>,
+        /// Where control may go next; `None` means the next instruction.
+        jump: Option<Vec<Label>>,
+    },
+    Label {
+        assem: String,
+        label: Label,
+    },
+    /// A register-to-register copy, which the allocator may coalesce.
+    Move {
+        assem: String,
+        dst: Temp,
+        src: Temp,
+    },
+}
+
+impl Instr {
+    pub(crate) fn oper(assem: impl Into<String>, dst: Vec<Temp>, src: Vec<Temp>) -> Instr {
+        Instr::Oper {
+            assem: assem.into(),
+            dst,
+            src,
+            jump: None,
+        }
+    }
+
+    pub(crate) fn jump(assem: impl Into<String>, src: Vec<Temp>, targets: Vec<Label>) -> Instr {
+        Instr::Oper {
+            assem: assem.into(),
+            dst: Vec::new(),
+            src,
+            jump: Some(targets),
+        }
+    }
+
+    pub(crate) fn mov(dst: Temp, src: Temp) -> Instr {
+        Instr::Move {
+            assem: "movq `s0, `d0".to_string(),
+            dst,
+            src,
+        }
+    }
+
+    pub(crate) fn dst(&self) -> &[Temp] {
+        match self {
+            Instr::Oper { dst, .. } => dst,
+            Instr::Move { dst, .. } => std::slice::from_ref(dst),
+            Instr::Label { .. } => &[],
+        }
+    }
+
+    pub(crate) fn src(&self) -> &[Temp] {
+        match self {
+            Instr::Oper { src, .. } => src,
+            Instr::Move { src, .. } => std::slice::from_ref(src),
+            Instr::Label { .. } => &[],
+        }
+    }
+
+    /// The assembly text with operands named by `name`.
+    pub(crate) fn format(&self, name: &dyn Fn(Temp) -> String) -> String {
+        let (assem, jumps) = match self {
+            Instr::Oper { assem, jump, .. } => (assem, jump.as_deref().unwrap_or(&[])),
+            Instr::Label { assem, .. } => (assem, &[][..]),
+            Instr::Move { assem, .. } => (assem, &[][..]),
+        };
+        let mut out = String::new();
+        let mut chars = assem.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '`' {
+                out.push(c);
+                continue;
+            }
+            let kind = chars.next().expect("operand kind after backtick");
+            let mut index = 0;
+            while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+                index = index * 10 + digit as usize;
+                chars.next();
+            }
+            match kind {
+                's' => out.push_str(&name(self.src()[index])),
+                'd' => out.push_str(&name(self.dst()[index])),
+                'j' => write!(out, "{}", jumps[index]).unwrap(),
+                '`' => out.push('`'),
+                _ => panic!("unknown operand kind `{kind}` in {assem:?}"),
+            }
+        }
+        out
+    }
+}
+
+/// Names machine registers and leaves other temporaries as `t<n>`, for
+/// printing instructions before register allocation.
+pub(crate) fn temp_name(temp: Temp) -> String {
+    match frame::register_name(temp) {
+        Some(reg) => reg.to_string(),
+        None => temp.to_string(),
+    }
+}
@@ -0,0 +1,79 @@
+//! Writes a translated program as an AT&T-syntax assembly file.
+
+use std::fmt::Write;
+
+use crate::analysis::{self, Cfg};
+use crate::canon;
+use crate::codegen::{assem, codegen, Instr};
+use crate::frame::{self, Frame, Proc};
+use crate::ir::Stm;
+use crate::opt::{self, Fuel, Passes};
+use crate::regalloc;
+use crate::temp::Temp;
+use crate::translate::Fragment;
+
+/// The assembly for all `fragments`: functions in `.text`, string literals
+/// in `.rodata`. The functions are optimized with `passes`.
+pub(crate) fn program(fragments: &[Fragment], passes: Passes) -> String {
+    let mut text = String::from("\t.text\n");
+    let mut data = String::from("\t.section .rodata\n");
+    let mut fuel = Fuel::new(passes);
+    for fragment in fragments {
+        match fragment {
+            Fragment::Proc { body, frame } => {
+                text.push_str(&function(body, frame, passes, &mut fuel))
+            }
+            Fragment::String(label, s) => data.push_str(&frame::string(*label, s.as_bytes())),
+        }
+    }
+    text.push_str(&data);
+    text.push_str("\t.section .note.GNU-stack,\"\",@progbits\n");
+    text
+}
+
+/// The assembly of one translated function, optimized with the rewrites
+/// `fuel` still allows.
+pub(crate) fn function(body: &Stm, frame: &Frame, passes: Passes, fuel: &mut Fuel) -> String {
+    compile(body, frame, passes, fuel).0
+}
+
+/// The assembly `function` writes, and how many temporaries register
+/// allocation spilled to the frame.
+pub(crate) fn compile(
+    body: &Stm,
+    frame: &Frame,
+    passes: Passes,
+    fuel: &mut Fuel,
+) -> (String, usize) {
+    let stms = canon::linearize(body.clone());
+    let (blocks, done) = canon::basic_blocks(stms);
+    debug_assert_eq!(
+        analysis::undefined_reads(&Cfg::new(blocks.clone())),
+        Default::default(),
+        "{} reads temporaries it never defined",
+        frame.name()
+    );
+    let stms = opt::optimize(canon::trace_schedule(blocks, done), passes, fuel);
+    let instrs = codegen(stms);
+    let mut frame = frame.clone();
+    let mut alloc = regalloc::alloc(frame::proc_entry_exit2(instrs), &mut frame);
+    alloc.remove_redundant_moves();
+    let proc = frame::proc_entry_exit3(&frame, alloc.instrs);
+    let name = |t: Temp| assem::temp_name(alloc.registers.get(&t).copied().unwrap_or(t));
+    (proc_text(&proc, &name), alloc.spills)
+}
+
+/// One function, naming temporaries with `name`.
+pub(crate) fn proc_text(proc: &Proc, name: &dyn Fn(Temp) -> String) -> String {
+    let mut out = proc.prolog.clone();
+    for instr in &proc.body {
+        let line = instr.format(name);
+        match instr {
+            _ if line.is_empty() => {}
+            Instr::Label { .. } => writeln!(out, "{line}").unwrap(),
+            _ => writeln!(out, "\t{line}").unwrap(),
+        }
+    }
+    out.push_str(&proc.epilog);
+    out
+}
@@ -0,0 +1,291 @@
+#![allow(dead_code)]
+
+//! Instruction selection for x86-64, the `Codegen` module of chapter 9.
+//!
+//! `codegen` tiles canonical trees by maximal munch, producing two-address
+//! AT&T instructions over temporaries. Each tile covers as much of the tree
+//! as one instruction can: memory operands absorb a constant offset, and
+//! arithmetic absorbs an immediate right operand.
+
+pub(crate) mod assem;
+pub(crate) mod emit;
+pub(crate) mod size;
+#[cfg(test)]
+mod tests;
+
+pub(crate) use assem::Instr;
+
+use crate::frame::{ARG_REGS, CALLER_SAVES, RAX, RCX, RDX, RV, SP, WORD_SIZE};
+use crate::ir::{BinOp, Exp, RelOp, Stm};
+use crate::temp::Temp;
+
+/// Selects instructions for canonical statements, as produced by
+/// `canon::trace_schedule`.
+pub(crate) fn codegen(stms: Vec<Stm>) -> Vec<Instr> {
+    let mut munch = Munch { instrs: Vec::new() };
+    for stm in stms {
+        munch.stm(stm);
+    }
+    munch.instrs
+}
+
+struct Munch {
+    instrs: Vec<Instr>,
+}
+
+/// Whether `n` fits in the sign-extended 32-bit immediate of most
+/// instructions.
+fn is_imm32(n: i64) -> bool {
+    i32::try_from(n).is_ok()
+}
+
+/// A memory operand `offset(`sK)`.
+fn mem_operand(offset: i64, src_index: usize) -> String {
+    match offset {
+        0 => format!("(`s{src_index})"),
+        _ => format!("{offset}(`s{src_index})"),
+    }
+}
+
+fn condition_code(op: RelOp) -> &'static str {
+    match op {
+        RelOp::Eq => "e",
+        RelOp::Ne => "ne",
+        RelOp::Lt => "l",
+        RelOp::Gt => "g",
+        RelOp::Le => "le",
+        RelOp::Ge => "ge",
+        RelOp::ULt => "b",
+        RelOp::ULe => "be",
+        RelOp::UGt => "a",
+        RelOp::UGe => "ae",
+    }
+}
+
+impl Munch {
+    fn emit(&mut self, instr: Instr) {
+        self.instrs.push(instr);
+    }
+
+    fn stm(&mut self, stm: Stm) {
+        match stm {
+            Stm::Seq(a, b) => {
+                self.stm(*a);
+                self.stm(*b);
+            }
+            Stm::Label(label) => self.emit(Instr::Label {
+                assem: format!("{label}:"),
+                label,
+            }),
+            Stm::Jump(target, labels) => match *target {
+                Exp::Name(label) => self.emit(Instr::jump(format!("jmp {label}"), vec![], labels)),
+                target => {
+                    let t = self.exp(target);
+                    self.emit(Instr::jump("jmp *`s0", vec![t], labels));
+                }
+            },
+            Stm::CJump(op, a, b, t, f) => {
+                let (op, a, b) = match (*a, *b) {
+                    (Exp::Const(n), b) if is_imm32(n) => (op.commute(), b, Exp::Const(n)),
+                    (a, b) => (op, a, b),
+                };
+                let a = self.exp(a);
+                match b {
+                    Exp::Const(n) if is_imm32(n) => {
+                        self.emit(Instr::oper(format!("cmpq ${n}, `s0"), vec![], vec![a]))
+                    }
+                    b => {
+                        let b = self.exp(b);
+                        self.emit(Instr::oper("cmpq `s1, `s0", vec![], vec![a, b]));
+                    }
+                }
+                let cc = condition_code(op);
+                self.emit(Instr::jump(format!("j{cc} `j0"), vec![], vec![t, f]));
+            }
+            Stm::Move(dst, src) => match (*dst, *src) {
+                (Exp::Mem(addr), Exp::Const(n)) if is_imm32(n) => {
+                    let (offset, base) = self.addr(*addr);
+                    let assem = format!("movq ${n}, {}", mem_operand(offset, 0));
+                    self.emit(Instr::oper(assem, vec![], vec![base]));
+                }
+                (Exp::Mem(addr), src) => {
+                    let value = self.exp(src);
+                    let (offset, base) = self.addr(*addr);
+                    let assem = format!("movq `s0, {}", mem_operand(offset, 1));
+                    self.emit(Instr::oper(assem, vec![], vec![value, base]));
+                }
+                (Exp::Temp(dst), Exp::Call(func, args)) => {
+                    self.call(*func, args);
+                    self.emit(Instr::mov(dst, RV));
+                }
+                (Exp::Temp(dst), Exp::Mem(addr)) => {
+                    let (offset, base) = self.addr(*addr);
+                    let assem = format!("movq {}, `d0", mem_operand(offset, 0));
+                    self.emit(Instr::oper(assem, vec![dst], vec![base]));
+                }
+                (Exp::Temp(dst), Exp::Const(n)) => self.constant(n, dst),
+                (Exp::Temp(dst), src) => {
+                    let src = self.exp(src);
+                    self.emit(Instr::mov(dst, src));
+                }
+                (dst, _) => unreachable!("cannot move into {dst:?}"),
+            },
+            Stm::Exp(e) => match *e {
+                Exp::Call(func, args) => self.call(*func, args),
+                e => {
+                    self.exp(e);
+                }
+            },
+        }
+    }
+
+    /// Splits an address into a base temporary and a constant offset.
+    fn addr(&mut self, addr: Exp) -> (i64, Temp) {
+        let (base, offset) = match addr {
+            Exp::BinOp(BinOp::Plus, a, b) => match (*a, *b) {
+                (base, Exp::Const(n)) | (Exp::Const(n), base) if is_imm32(n) => (base, n),
+                (a, b) => (Exp::binop(BinOp::Plus, a, b), 0),
+            },
+            Exp::BinOp(BinOp::Minus, a, b) => match (*a, *b) {
+                (base, Exp::Const(n)) if n.checked_neg().is_some_and(is_imm32) => (base, -n),
+                (a, b) => (Exp::binop(BinOp::Minus, a, b), 0),
+            },
+            addr => (addr, 0),
+        };
+        (offset, self.exp(base))
+    }
+
+    fn constant(&mut self, n: i64, dst: Temp) {
+        let mnemonic = if is_imm32(n) { "movq" } else { "movabsq" };
+        self.emit(Instr::oper(
+            format!("{mnemonic} ${n}, `d0"),
+            vec![dst],
+            vec![],
+        ));
+    }
+
+    fn exp(&mut self, exp: Exp) -> Temp {
+        match exp {
+            Exp::Temp(t) => t,
+            Exp::Const(n) => {
+                let t = Temp::new();
+                self.constant(n, t);
+                t
+            }
+            Exp::Name(label) => {
+                let t = Temp::new();
+                self.emit(Instr::oper(
+                    format!("leaq {label}(%rip), `d0"),
+                    vec![t],
+                    vec![],
+                ));
+                t
+            }
+            Exp::Mem(addr) => {
+                let t = Temp::new();
+                let (offset, base) = self.addr(*addr);
+                let assem = format!("movq {}, `d0", mem_operand(offset, 0));
+                self.emit(Instr::oper(assem, vec![t], vec![base]));
+                t
+            }
+            Exp::BinOp(op, a, b) => self.binop(op, *a, *b),
+            Exp::Call(func, args) => {
+                self.call(*func, args);
+                let t = Temp::new();
+                self.emit(Instr::mov(t, RV));
+                t
+            }
+            Exp::ESeq(s, e) => {
+                self.stm(*s);
+                self.exp(*e)
+            }
+        }
+    }
+
+    fn binop(&mut self, op: BinOp, a: Exp, b: Exp) -> Temp {
+        let t = Temp::new();
+        let mnemonic = match op {
+            BinOp::Plus => "addq",
+            BinOp::Minus => "subq",
+            BinOp::Mul => "imulq",
+            BinOp::And => "andq",
+            BinOp::Or => "orq",
+            BinOp::Xor => "xorq",
+            BinOp::LShift => "salq",
+            BinOp::RShift => "shrq",
+            BinOp::ARShift => "sarq",
+            BinOp::Div => {
+                let (a, b) = (self.exp(a), self.exp(b));
+                self.emit(Instr::mov(RAX, a));
+                self.emit(Instr::oper("cqto", vec![RDX], vec![RAX]));
+                self.emit(Instr::oper("idivq `s0", vec![RAX, RDX], vec![b, RAX, RDX]));
+                self.emit(Instr::mov(t, RAX));
+                return t;
+            }
+        };
+        let a = self.exp(a);
+        self.emit(Instr::mov(t, a));
+        let shift = matches!(op, BinOp::LShift | BinOp::RShift | BinOp::ARShift);
+        match b {
+            Exp::Const(n) if is_imm32(n) => self.emit(Instr::oper(
+                format!("{mnemonic} ${n}, `d0"),
+                vec![t],
+                vec![t],
+            )),
+            b if shift => {
+                let b = self.exp(b);
+                self.emit(Instr::mov(RCX, b));
+                let assem = format!("{mnemonic} %cl, `d0");
+                self.emit(Instr::oper(assem, vec![t], vec![RCX, t]));
+            }
+            b => {
+                let b = self.exp(b);
+                let assem = format!("{mnemonic} `s0, `d0");
+                self.emit(Instr::oper(assem, vec![t], vec![b, t]));
+            }
+        }
+        t
+    }
+
+    /// Calls `func`, leaving the result in the return-value register. The
+    /// first six arguments go in registers and the rest are pushed, keeping
+    /// the stack 16-byte aligned at the call.
+    fn call(&mut self, func: Exp, args: Vec<Exp>) {
+        let args: Vec<Temp> = args.into_iter().map(|arg| self.exp(arg)).collect();
+        let on_stack = args.len().saturating_sub(ARG_REGS.len());
+        let padding = on_stack % 2;
+        if padding == 1 {
+            self.emit(Instr::oper(
+                format!("subq ${WORD_SIZE}, %rsp"),
+                vec![SP],
+                vec![SP],
+            ));
+        }
+        for &arg in args.iter().skip(ARG_REGS.len()).rev() {
+            self.emit(Instr::oper("pushq `s0", vec![SP], vec![arg, SP]));
+        }
+        let in_regs = args.len().min(ARG_REGS.len());
+        for (&reg, &arg) in ARG_REGS.iter().zip(&args) {
+            self.emit(Instr::mov(reg, arg));
+        }
+        let uses = ARG_REGS[..in_regs].to_vec();
+        let clobbers = CALLER_SAVES.to_vec();
+        match func {
+            Exp::Name(label) => self.emit(Instr::oper(format!("call {label}"), clobbers, uses)),
+            func => {
+                let f = self.exp(func);
+                let mut uses = uses;
+                uses.insert(0, f);
+                self.emit(Instr::oper("call *`s0", clobbers, uses));
+            }
+        }
+        let pop = (on_stack + padding) as i64 * WORD_SIZE;
+        if pop > 0 {
+            self.emit(Instr::oper(
+                format!("addq ${pop}, %rsp"),
+                vec![SP],
+                vec![SP],
+            ));
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! How big each compiled function is, for `--size-report`: its
+//! instructions after register allocation, the temporaries spilled to its
+//! frame, and an estimate of its machine code in bytes.
+//!
+//! The estimate assembles nothing. It takes each instruction as `emit`
+//! prints it to the encoding an assembler picks for it: a REX prefix, an
+//! opcode and a ModRM byte, then the displacement and immediate, each one
+//! byte when it fits in `i8` and four otherwise. Jumps are counted with
+//! 32-bit offsets, as they are before an assembler relaxes them.
+
+use crate::opt::{Fuel, Passes};
+use crate::temp::Label;
+use crate::translate::Fragment;
+
+use super::emit;
+
+/// The size of one function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct FunctionSize {
+    pub(crate) name: Label,
+    /// Instructions, counting the prologue and epilogue.
+    pub(crate) instructions: usize,
+    /// Temporaries spilled to the frame.
+    pub(crate) spills: usize,
+    /// The estimated size of the machine code.
+    pub(crate) bytes: usize,
+}
+
+/// The size of every function of `fragments`, compiled as `emit::program`
+/// compiles them, in the order of the fragments.
+pub(crate) fn program(fragments: &[Fragment], passes: Passes) -> Vec<FunctionSize> {
+    let mut fuel = Fuel::new(passes);
+    fragments
+        .iter()
+        .filter_map(|fragment| match fragment {
+            Fragment::Proc { body, frame } => {
+                let (text, spills) = emit::compile(body, frame, passes, &mut fuel);
+                let instrs: Vec<&str> = text.lines().filter_map(instruction).collect();
+                Some(FunctionSize {
+                    name: frame.name(),
+                    instructions: instrs.len(),
+                    spills,
+                    bytes: instrs.iter().map(|instr| bytes(instr)).sum(),
+                })
+            }
+            Fragment::String(..) => None,
+        })
+        .collect()
+}
+
+/// The instruction on a line of assembly, if it is not a label or a
+/// directive.
+fn instruction(line: &str) -> Option<&str> {
+    let instr = line.strip_prefix('\t')?;
+    (!instr.starts_with('.')).then_some(instr)
+}
+
+/// The estimated encoded size of `instr`.
+pub(crate) fn bytes(instr: &str) -> usize {
+    let (mnemonic, operands) = instr.split_once(' ').unwrap_or((instr, ""));
+    let operands: Vec<&str> = operands.split(", ").filter(|op| !op.is_empty()).collect();
+    match (mnemonic, &operands[..]) {
+        ("ret" | "leave", _) => 1,
+        ("cqto", _) => 2,
+        ("pushq" | "popq", [reg]) => 1 + extended(reg) as usize,
+        ("call" | "jmp", [target]) => match target.strip_prefix('*') {
+            Some(reg) => 2 + extended(reg) as usize,
+            None => 5,
+        },
+        (jcc, _) if jcc.starts_with('j') => 6,
+        // `movq` has no 8-bit immediate, but a 64-bit one into a register.
+        ("movq", [imm, dst]) if imm.starts_with('$') => match imm[1..].parse::<i64>() {
+            Ok(n) if i32::try_from(n).is_err() => 10,
+            _ => 7 + operand(dst),
+        },
+        // Multiplying registers takes a two-byte opcode.
+        ("imulq", [src, _]) if !src.starts_with('$') => 4 + operand(src),
+        _ => 3 + operands.iter().map(|op| operand(op)).sum::<usize>(),
+    }
+}
+
+/// The displacement or immediate bytes `op` adds to an instruction.
+fn operand(op: &str) -> usize {
+    if let Some(imm) = op.strip_prefix('$') {
+        return match imm.parse::<i64>() {
+            Ok(n) if i8::try_from(n).is_ok() => 1,
+            _ => 4,
+        };
+    }
+    let Some((disp, base)) = op.split_once('(') else {
+        return 0;
+    };
+    let base = base.trim_end_matches(')');
+    if base == "%rip" {
+        return 4;
+    }
+    // `%rsp` and `%r12` need a SIB byte, and `%rbp` and `%r13` a
+    // displacement even when it is zero.
+    let sib = matches!(base, "%rsp" | "%r12") as usize;
+    let disp = match disp.parse::<i64>().unwrap_or(0) {
+        0 if !matches!(base, "%rbp" | "%r13") => 0,
+        n if i8::try_from(n).is_ok() => 1,
+        _ => 4,
+    };
+    sib + disp
+}
+
+/// Whether `reg` is one of `%r8` to `%r15`, which need a REX prefix.
+fn extended(reg: &str) -> bool {
+    reg.strip_prefix("%r")
+        .is_some_and(|n| n.starts_with(|c: char| c.is_ascii_digit()))
+}
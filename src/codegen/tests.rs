@@ -0,0 +1,166 @@
+use crate::codegen::assem::temp_name;
+use crate::codegen::{codegen, emit, size, Instr};
+use crate::frame::{FP, RAX, RDI};
+use crate::ir::{BinOp, Exp, RelOp, Stm};
+use crate::opt::Passes;
+use crate::parser::parse;
+use crate::semant::Semant;
+use crate::temp::{Label, Temp};
+
+fn lines(stms: Vec<Stm>) -> Vec<String> {
+    codegen(stms)
+        .iter()
+        .map(|instr| instr.format(&temp_name))
+        .collect()
+}
+
+#[test]
+fn operands_are_substituted() {
+    let (a, b) = (Temp::new(), Temp::new());
+    let l = Label::named("done");
+    let instr = Instr::Oper {
+        assem: "addq `s0, `d0 # `j0".to_string(),
+        dst: vec![a],
+        src: vec![b, a],
+        jump: Some(vec![l]),
+    };
+    assert_eq!(instr.format(&temp_name), format!("addq {b}, {a} # done"));
+    assert_eq!(
+        Instr::mov(RAX, a).format(&temp_name),
+        format!("movq {a}, %rax")
+    );
+}
+
+#[test]
+fn memory_operands_absorb_offsets() {
+    let fp_minus_8 = Exp::binop(BinOp::Plus, Exp::Temp(FP), Exp::Const(-8));
+    let t = Temp::new();
+    let out = lines(vec![
+        Stm::mov(Exp::mem(fp_minus_8.clone()), Exp::Const(5)),
+        Stm::mov(Exp::Temp(t), Exp::mem(fp_minus_8)),
+        Stm::mov(Exp::Temp(t), Exp::Const(1 << 40)),
+    ]);
+    assert_eq!(
+        out,
+        [
+            "movq $5, -8(%rbp)".to_string(),
+            format!("movq -8(%rbp), {t}"),
+            format!("movabsq $1099511627776, {t}"),
+        ]
+    );
+}
+
+#[test]
+fn comparisons_with_constants_on_the_left_are_commuted() {
+    let t = Temp::new();
+    let (yes, no) = (Label::named("yes"), Label::named("no"));
+    let out = lines(vec![Stm::cjump(
+        RelOp::Lt,
+        Exp::Const(3),
+        Exp::Temp(t),
+        yes,
+        no,
+    )]);
+    assert_eq!(out, [format!("cmpq $3, {t}"), "jg yes".to_string()]);
+}
+
+#[test]
+fn calls_pass_extra_arguments_on_an_aligned_stack() {
+    let args: Vec<Temp> = (0..7).map(|_| Temp::new()).collect();
+    let call = Exp::call(
+        Exp::Name(Label::named("g")),
+        args.iter().map(|&t| Exp::Temp(t)).collect(),
+    );
+    let instrs = codegen(vec![Stm::exp(call)]);
+    let out: Vec<String> = instrs.iter().map(|i| i.format(&temp_name)).collect();
+    assert_eq!(out[0], "subq $8, %rsp");
+    assert_eq!(out[1], format!("pushq {}", args[6]));
+    assert_eq!(out[2], format!("movq {}, %rdi", args[0]));
+    assert_eq!(out[8], "call g");
+    assert_eq!(out[9], "addq $16, %rsp");
+    let call = &instrs[8];
+    assert_eq!(call.src().len(), 6);
+    assert!(call.src().contains(&RDI));
+    assert!(call.dst().contains(&RAX));
+}
+
+#[test]
+fn programs_are_emitted_as_assembly() {
+    let src = "let
+        function f(x: int): int = if x < 2 then x else f(x - 1) * 2
+    in print(\"a\\\"b\\n\"); f(3) / 2 end";
+    let exp = parse(src).unwrap();
+    let mut semant = Semant::new();
+    semant.check(&exp);
+    assert!(semant.errors().is_empty(), "{:?}", semant.errors());
+    let asm = emit::program(semant.fragments(), Passes::NONE);
+    assert!(!asm.contains('`'), "{asm}");
+    assert!(asm.contains("tigermain:\n\tpushq %rbp\n\tmovq %rsp, %rbp\n"));
+    assert!(asm.contains("\tcall tig_print\n"));
+    assert!(asm.contains("\tidivq "));
+    assert!(
+        asm.contains("\t.quad 4\n\t.ascii \"a\\\"b\\012\"\n"),
+        "{asm}"
+    );
+    assert_eq!(asm.matches("\tleave\n\tret\n").count(), 2);
+}
+
+#[test]
+fn sizes_are_estimated_as_the_assembler_encodes() {
+    // The lengths `as` gives each, but for the jumps, which it relaxes to
+    // two bytes when the target is near.
+    let encodings = [
+        ("pushq %rbp", 1),
+        ("pushq %r12", 2),
+        ("movq %rsp, %rbp", 3),
+        ("subq $16, %rsp", 4),
+        ("subq $4096, %rsp", 7),
+        ("movq %rdi, -8(%rbp)", 4),
+        ("movq (%rax), %rcx", 3),
+        ("movq (%rbp), %rcx", 4),
+        ("movq 8(%rsp), %rcx", 5),
+        ("movq -1000(%rbp), %rcx", 7),
+        ("movq $-5, %r9", 7),
+        ("movq $4294967296, %rax", 10),
+        ("movq $3, -8(%rbp)", 8),
+        ("imulq $1000, %rax", 7),
+        ("imulq %rcx, %rax", 4),
+        ("cmpq %rcx, %rdx", 3),
+        ("leaq L1(%rip), %rax", 7),
+        ("sarq %cl, %rax", 3),
+        ("cqto", 2),
+        ("idivq %rcx", 3),
+        ("call tig_print", 5),
+        ("call *%r11", 3),
+        ("jmp L1", 5),
+        ("jge L1", 6),
+        ("leave", 1),
+        ("ret", 1),
+    ];
+    for (instr, bytes) in encodings {
+        assert_eq!(size::bytes(instr), bytes, "{instr}");
+    }
+}
+
+#[test]
+fn sizes_count_every_function() {
+    let src = "let function f(x: int): int = x + 1 in print(itoa(f(2))) end";
+    let exp = parse(src).unwrap();
+    let mut semant = Semant::new();
+    semant.check(&exp);
+    let sizes = size::program(semant.fragments(), Passes::NONE);
+    let names: Vec<String> = sizes.iter().map(|f| f.name.to_string()).collect();
+    assert_eq!(names, ["f.0", "tigermain"]);
+    let asm = emit::program(semant.fragments(), Passes::NONE);
+    let instructions = asm
+        .lines()
+        .filter(|line| line.starts_with('\t') && !line.starts_with("\t."))
+        .count();
+    assert_eq!(
+        sizes.iter().map(|f| f.instructions).sum::<usize>(),
+        instructions
+    );
+    assert!(sizes
+        .iter()
+        .all(|f| f.spills == 0 && f.bytes > f.instructions));
+}
@@ -0,0 +1,112 @@
+//! Assembles a green tree as `parser` parses: the parser opens and closes
+//! nodes around what it parses, and adds every token it consumes, after
+//! the trivia before it.
+
+use std::rc::Rc;
+
+use super::{GreenElement, GreenNode, GreenToken, NodeKind, SyntaxNode};
+use crate::lexer::Token;
+use crate::shared_str::SharedStr;
+use crate::span::FileId;
+
+pub(crate) struct Builder {
+    /// The nodes being built, outermost first, with their children so far.
+    stack: Vec<(NodeKind, Vec<GreenElement>)>,
+    /// The end of the text in the tree so far.
+    hi: u32,
+}
+
+impl Builder {
+    /// A builder with the `Program` node open.
+    pub(crate) fn new() -> Builder {
+        Builder {
+            stack: vec![(NodeKind::Program, Vec::new())],
+            hi: 0,
+        }
+    }
+
+    fn children(&mut self) -> &mut Vec<GreenElement> {
+        &mut self.stack.last_mut().expect("the program node is open").1
+    }
+
+    /// Adds the trivia before `token`, a token of `src`, to the open node,
+    /// unless it is in the tree already.
+    pub(crate) fn trivia(&mut self, token: &Token, src: &str) {
+        let leading = token.leading_trivia();
+        if leading.hi() <= self.hi {
+            return;
+        }
+        for trivia in token.trivia(src) {
+            self.push(&trivia, src);
+        }
+        self.hi = leading.hi();
+    }
+
+    /// Adds `token` to the open node, after the trivia before it. `EOF` and
+    /// keywords the parser assumed have no text and are left out.
+    pub(crate) fn token(&mut self, token: &Token, src: &str) {
+        self.trivia(token, src);
+        if token.pos().lo() < token.pos().hi() {
+            self.push(token, src);
+            self.hi = token.pos().hi();
+        }
+    }
+
+    fn push(&mut self, token: &Token, src: &str) {
+        let text = match (token.kind().text(), token.symbol()) {
+            (Some(text), _) => SharedStr::from_static(text),
+            (None, Some(name)) => SharedStr::from(name),
+            (None, None) => {
+                SharedStr::from(&src[token.pos().lo() as usize..token.pos().hi() as usize])
+            }
+        };
+        let token = GreenToken::new(token.kind().clone(), text);
+        self.children().push(GreenElement::Token(token));
+    }
+
+    pub(crate) fn start(&mut self, kind: NodeKind) {
+        self.stack.push((kind, Vec::new()));
+    }
+
+    /// Closes the open node. One that got no children, because what it
+    /// was to hold failed to parse before its first token, is dropped.
+    pub(crate) fn finish(&mut self) {
+        let (kind, children) = self.stack.pop().expect("a node is open");
+        if !children.is_empty() {
+            let node = GreenNode::new(kind, children);
+            self.children().push(GreenElement::Node(Rc::new(node)));
+        }
+    }
+
+    /// Where a node that wraps what comes next could start, for nodes whose
+    /// kind is only known after their first child, such as `Binary`.
+    pub(crate) fn checkpoint(&mut self) -> usize {
+        self.children().len()
+    }
+
+    /// Starts a node of `kind` around the children added since `checkpoint`.
+    pub(crate) fn start_at(&mut self, checkpoint: usize, kind: NodeKind) {
+        let children = self.children().split_off(checkpoint);
+        self.stack.push((kind, children));
+    }
+
+    /// Puts the child at `checkpoint` in a node of `kind` of its own,
+    /// leaving those after it where they are.
+    pub(crate) fn wrap_child(&mut self, checkpoint: usize, kind: NodeKind) {
+        let children = self.children();
+        if let Some(child) = children.get_mut(checkpoint) {
+            let node = GreenNode::new(kind, vec![child.clone()]);
+            *child = GreenElement::Node(Rc::new(node));
+        }
+    }
+
+    /// The tree, closing any node still open; `file` is the file it is the
+    /// text of.
+    pub(crate) fn finish_root(mut self, file: FileId) -> SyntaxNode {
+        while self.stack.len() > 1 {
+            self.finish();
+        }
+        let (kind, children) = self.stack.pop().expect("the program node is open");
+        SyntaxNode::root(Rc::new(GreenNode::new(kind, children)), file)
+    }
+}
@@ -0,0 +1,416 @@
+#![allow(dead_code)]
+
+//! Concrete syntax trees: a program as it was written, with every byte of
+//! the input in the tree, whitespace and comments included, so that tools
+//! which rewrite source, such as a formatter or a refactoring, can keep
+//! what the `ast` throws away.
+//!
+//! The tree comes in two layers. A `GreenNode` is immutable and knows only
+//! its kind, its children and its length, so equal subtrees can be shared
+//! and edits can rebuild just the path to the root. A `SyntaxNode` is a view
+//! of a green node at an offset in a file, with a parent, through which the
+//! tree is navigated and spans are computed on demand.
+//!
+//! `parse` builds the tree with `parser`, which assembles it through a
+//! `builder::Builder` while it parses when asked to, so there is one
+//! grammar. It never fails: tokens the parser skipped after a syntax error
+//! are kept in `Error` nodes, and the syntax errors themselves are
+//! reported by `parser::parse_reporting`, as for any other program.
+
+pub(crate) mod builder;
+#[cfg(test)]
+mod tests;
+
+use std::fmt;
+use std::rc::Rc;
+
+use crate::lexer::{LexError, TokenKind};
+use crate::limits::Limits;
+use crate::parser;
+use crate::shared_str::SharedStr;
+use crate::span::{FileId, Span};
+
+/// A parsed tree, with the lexical errors found on the way.
+pub(crate) struct Parse {
+    pub(crate) root: SyntaxNode,
+    pub(crate) errors: Vec<LexError>,
+}
+
+/// The concrete syntax tree of `src`.
+pub(crate) fn parse(src: &str) -> Parse {
+    parse_in(src, FileId::MAIN, Limits::default())
+}
+
+/// `parse` for `src`, the text of `file`.
+pub(crate) fn parse_in(src: &str, file: FileId, limits: Limits) -> Parse {
+    parser::parse_concrete(src, file, limits)
+}
+
+/// The kind of an interior node. Leaves are tokens, with their `TokenKind`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum NodeKind {
+    /// The whole input: an expression, then anything that could not be
+    /// parsed, then the trailing trivia.
+    Program,
+    /// `nil`, an integer or a string.
+    Literal,
+    Break,
+    /// `(e1; ...; en)`, with any number of expressions.
+    Paren,
+    /// A variable that is a plain name.
+    Name,
+    /// `var.field`.
+    FieldAccess,
+    /// `var[index]`.
+    Index,
+    /// `-e`.
+    Neg,
+    /// `e1 op e2`.
+    Binary,
+    Assign,
+    Call,
+    Record,
+    /// `field = e` in a record expression.
+    RecordField,
+    /// `type-id [size] of init`.
+    Array,
+    If,
+    While,
+    For,
+    /// `let` with its declarations and body as direct children.
+    Let,
+    TypeDec,
+    /// A type declared as another one's name.
+    NameTy,
+    RecordTy,
+    ArrayTy,
+    /// `name: type-id` in a record type or a parameter list.
+    TyField,
+    FunDec,
+    VarDec,
+    Import,
+    /// Tokens the grammar has no place for, or nothing where it needed
+    /// something.
+    Error,
+}
+
+impl NodeKind {
+    /// Whether nodes of this kind are expressions.
+    pub(crate) fn is_exp(self) -> bool {
+        !matches!(
+            self,
+            NodeKind::Program
+                | NodeKind::RecordField
+                | NodeKind::TypeDec
+                | NodeKind::NameTy
+                | NodeKind::RecordTy
+                | NodeKind::ArrayTy
+                | NodeKind::TyField
+                | NodeKind::FunDec
+                | NodeKind::VarDec
+                | NodeKind::Import
+        )
+    }
+
+    /// Whether nodes of this kind are declarations.
+    pub(crate) fn is_dec(self) -> bool {
+        matches!(
+            self,
+            NodeKind::TypeDec | NodeKind::FunDec | NodeKind::VarDec | NodeKind::Import
+        )
+    }
+}
+
+/// An immutable subtree, without positions.
+#[derive(Debug, PartialEq)]
+pub(crate) struct GreenNode {
+    kind: NodeKind,
+    len: u32,
+    children: Vec<GreenElement>,
+}
+
+impl GreenNode {
+    pub(crate) fn new(kind: NodeKind, children: Vec<GreenElement>) -> GreenNode {
+        let len = children.iter().map(GreenElement::len).sum();
+        GreenNode {
+            kind,
+            len,
+            children,
+        }
+    }
+
+    pub(crate) fn kind(&self) -> NodeKind {
+        self.kind
+    }
+
+    pub(crate) fn len(&self) -> u32 {
+        self.len
+    }
+
+    pub(crate) fn children(&self) -> &[GreenElement] {
+        &self.children
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct GreenToken {
+    kind: TokenKind,
+    text: SharedStr,
+}
+
+impl GreenToken {
+    pub(crate) fn new(kind: TokenKind, text: SharedStr) -> GreenToken {
+        GreenToken { kind, text }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum GreenElement {
+    Node(Rc<GreenNode>),
+    Token(GreenToken),
+}
+
+impl GreenElement {
+    fn len(&self) -> u32 {
+        match self {
+            GreenElement::Node(node) => node.len,
+            GreenElement::Token(token) => token.text.len() as u32,
+        }
+    }
+}
+
+/// A green node at its place in a file.
+#[derive(Clone)]
+pub(crate) struct SyntaxNode(Rc<NodeData>);
+
+struct NodeData {
+    green: Rc<GreenNode>,
+    offset: u32,
+    file: FileId,
+    parent: Option<SyntaxNode>,
+}
+
+/// A token at its place in a file.
+#[derive(Clone)]
+pub(crate) struct SyntaxToken {
+    kind: TokenKind,
+    text: SharedStr,
+    pos: Span,
+    parent: SyntaxNode,
+}
+
+#[derive(Clone)]
+pub(crate) enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl SyntaxNode {
+    /// The root of the tree `green`, which is the text of `file`.
+    pub(crate) fn root(green: Rc<GreenNode>, file: FileId) -> SyntaxNode {
+        SyntaxNode(Rc::new(NodeData {
+            green,
+            offset: 0,
+            file,
+            parent: None,
+        }))
+    }
+
+    pub(crate) fn kind(&self) -> NodeKind {
+        self.0.green.kind
+    }
+
+    pub(crate) fn green(&self) -> &Rc<GreenNode> {
+        &self.0.green
+    }
+
+    pub(crate) fn parent(&self) -> Option<&SyntaxNode> {
+        self.0.parent.as_ref()
+    }
+
+    /// Everything the node covers, trivia included.
+    pub(crate) fn span(&self) -> Span {
+        let lo = self.0.offset;
+        Span::in_file(self.0.file, lo, lo + self.0.green.len)
+    }
+
+    /// The span from the node's first token to its last one, leaving out
+    /// the trivia around them; empty at the node's start if it has no
+    /// tokens.
+    pub(crate) fn trimmed(&self) -> Span {
+        match (self.first_token(), self.last_token()) {
+            (Some(first), Some(last)) => first.pos.to(last.pos),
+            _ => self.span().shrink_to_lo(),
+        }
+    }
+
+    /// The first token in the subtree that is not trivia.
+    pub(crate) fn first_token(&self) -> Option<SyntaxToken> {
+        self.children_with_tokens().find_map(|child| match child {
+            SyntaxElement::Node(node) => node.first_token(),
+            SyntaxElement::Token(token) => (!token.is_trivia()).then_some(token),
+        })
+    }
+
+    /// The last token in the subtree that is not trivia.
+    pub(crate) fn last_token(&self) -> Option<SyntaxToken> {
+        let children: Vec<SyntaxElement> = self.children_with_tokens().collect();
+        children.into_iter().rev().find_map(|child| match child {
+            SyntaxElement::Node(node) => node.last_token(),
+            SyntaxElement::Token(token) => (!token.is_trivia()).then_some(token),
+        })
+    }
+
+    pub(crate) fn children_with_tokens(&self) -> impl Iterator<Item = SyntaxElement> + '_ {
+        let mut offset = self.0.offset;
+        self.0.green.children.iter().map(move |child| {
+            let lo = offset;
+            offset += child.len();
+            match child {
+                GreenElement::Node(green) => SyntaxElement::Node(SyntaxNode(Rc::new(NodeData {
+                    green: green.clone(),
+                    offset: lo,
+                    file: self.0.file,
+                    parent: Some(self.clone()),
+                }))),
+                GreenElement::Token(token) => SyntaxElement::Token(SyntaxToken {
+                    kind: token.kind.clone(),
+                    text: token.text.clone(),
+                    pos: Span::in_file(self.0.file, lo, offset),
+                    parent: self.clone(),
+                }),
+            }
+        })
+    }
+
+    pub(crate) fn children(&self) -> impl Iterator<Item = SyntaxNode> + '_ {
+        self.children_with_tokens().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    /// The tokens that are children of this node, not of its descendants.
+    pub(crate) fn child_tokens(&self) -> impl Iterator<Item = SyntaxToken> + '_ {
+        self.children_with_tokens().filter_map(|child| match child {
+            SyntaxElement::Token(token) => Some(token),
+            SyntaxElement::Node(_) => None,
+        })
+    }
+
+    /// The first child token of kind `kind`.
+    pub(crate) fn token(&self, kind: TokenKind) -> Option<SyntaxToken> {
+        self.child_tokens().find(|t| t.kind == kind)
+    }
+
+    /// Every token in the subtree, in source order.
+    pub(crate) fn tokens(&self) -> impl Iterator<Item = SyntaxToken> {
+        let mut tokens = Vec::new();
+        self.collect_tokens(&mut tokens);
+        tokens.into_iter()
+    }
+
+    /// The node and every node below it, in source order.
+    pub(crate) fn descendants(&self) -> impl Iterator<Item = SyntaxNode> {
+        let mut nodes = vec![self.clone()];
+        self.collect_nodes(&mut nodes);
+        nodes.into_iter()
+    }
+
+    fn collect_nodes(&self, out: &mut Vec<SyntaxNode>) {
+        for node in self.children() {
+            out.push(node.clone());
+            node.collect_nodes(out);
+        }
+    }
+
+    fn collect_tokens(&self, out: &mut Vec<SyntaxToken>) {
+        for child in self.children_with_tokens() {
+            match child {
+                SyntaxElement::Node(node) => node.collect_tokens(out),
+                SyntaxElement::Token(token) => out.push(token),
+            }
+        }
+    }
+
+    /// The token that covers byte `offset`, or that starts there if it is
+    /// between two; `None` past the end.
+    pub(crate) fn token_at(&self, offset: u32) -> Option<SyntaxToken> {
+        for child in self.children_with_tokens() {
+            match child {
+                SyntaxElement::Node(node) if node.span().hi() > offset => {
+                    return node.token_at(offset)
+                }
+                SyntaxElement::Token(token) if token.pos.hi() > offset => return Some(token),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// The source text the node covers, exactly.
+    pub(crate) fn text(&self) -> String {
+        let mut out = String::with_capacity(self.0.green.len as usize);
+        self.tokens().for_each(|t| out.push_str(&t.text));
+        out
+    }
+
+    fn dump(&self, depth: usize, out: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let span = self.span();
+        writeln!(
+            out,
+            "{:indent$}{:?}@{}..{}",
+            "",
+            self.kind(),
+            span.lo(),
+            span.hi(),
+            indent = depth * 2
+        )?;
+        for child in self.children_with_tokens() {
+            match child {
+                SyntaxElement::Node(node) => node.dump(depth + 1, out)?,
+                SyntaxElement::Token(token) => writeln!(
+                    out,
+                    "{:indent$}{:?}@{}..{} {:?}",
+                    "",
+                    token.kind,
+                    token.pos.lo(),
+                    token.pos.hi(),
+                    token.text.as_str(),
+                    indent = (depth + 1) * 2
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SyntaxNode {
+    /// The tree, one node or token a line, indented by depth, with spans.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.dump(0, f)
+    }
+}
+
+impl SyntaxToken {
+    pub(crate) fn kind(&self) -> &TokenKind {
+        &self.kind
+    }
+
+    pub(crate) fn text(&self) -> &SharedStr {
+        &self.text
+    }
+
+    pub(crate) fn pos(&self) -> Span {
+        self.pos
+    }
+
+    pub(crate) fn parent(&self) -> &SyntaxNode {
+        &self.parent
+    }
+
+    /// Whitespace and comments.
+    pub(crate) fn is_trivia(&self) -> bool {
+        matches!(self.kind, TokenKind::WHITESPACE | TokenKind::COMMENT)
+    }
+}
@@ -0,0 +1,154 @@
+use crate::cst::{self, NodeKind};
+use crate::lexer::TokenKind;
+use crate::parser;
+
+/// Programs that use every construct, with comments and odd spacing.
+const PROGRAMS: &[&str] = &[
+    "nil",
+    "  42 /* answer */ ",
+    "-(x) + y * 3 - -z",
+    "a < b & c <> d | (e; f)",
+    "()",
+    "(1; \"two\\n\"; ())",
+    "let in end",
+    "let in a; b end",
+    "let in (a); b end",
+    "let var x := 1 in x := x + 1; x end",
+    "let
+        /* a list */
+        type list = {hd: int, tl: list}
+        type ints = array of int
+        type id = int
+        var xs : list := list { hd = 1, tl = nil }
+        var a := ints [10] of 0
+        function f(n: int, l: list): int =
+            if n = 0 then l.hd else f(n - 1, l.tl)
+        function g() = ()
+        import \"lib.tig\"
+    in
+        a[2] := f(3, xs);
+        xs.tl.hd := a[1];
+        for i := 0 to 9 do (if a[i] > 0 then break);
+        while 1 do break;
+        g()
+    end",
+    "m[1][2].f[3] := (m[0].g)",
+];
+
+#[test]
+fn trees_hold_their_text_and_no_errors() {
+    for src in PROGRAMS {
+        parser::parse(src).unwrap();
+        let root = cst::parse(src).root;
+        assert_eq!(root.span().hi() as usize, src.len());
+        assert_eq!(crate::testing::check_cst(src), Ok(()), "input: {src:?}");
+    }
+}
+
+#[test]
+fn nodes_follow_the_grammar() {
+    let root = cst::parse("let var a := t [2] of 0 in a[1].f := -g(1, x) + 2 end").root;
+    let kinds: Vec<NodeKind> = root.descendants().map(|node| node.kind()).collect();
+    use NodeKind::*;
+    assert_eq!(
+        kinds,
+        [
+            Program,
+            Let,
+            VarDec,
+            Array,
+            Literal,
+            Literal,
+            Assign,
+            FieldAccess,
+            Index,
+            Name,
+            Literal,
+            Binary,
+            Neg,
+            Call,
+            Literal,
+            Name,
+            Literal,
+        ]
+    );
+}
+
+#[test]
+fn the_ui_fixtures_hold_their_text() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/ui");
+    let mut checked = 0;
+    for entry in std::fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|ext| ext == "tig") {
+            let src = std::fs::read_to_string(&path).unwrap();
+            let result = crate::testing::check_cst(&src);
+            assert_eq!(result, Ok(()), "{}", path.display());
+            checked += 1;
+        }
+    }
+    assert!(checked > 0, "no fixtures in {}", dir.display());
+}
+
+#[test]
+fn trivia_stays_outside_the_nodes_it_surrounds() {
+    let src = "/* c */ f( 1 ,x ) ";
+    let root = cst::parse(src).root;
+    let call = root.children().next().unwrap();
+    assert_eq!(call.kind(), NodeKind::Call);
+    assert_eq!(call.text(), "f( 1 ,x )");
+    assert_eq!(call.trimmed(), call.span());
+    let args: Vec<String> = call.children().map(|arg| arg.text()).collect();
+    assert_eq!(args, ["1", "x"]);
+    let token = root.token_at(3).unwrap();
+    assert_eq!(token.kind(), &TokenKind::COMMENT);
+    assert_eq!(token.parent().kind(), NodeKind::Program);
+    assert_eq!(root.token_at(11).unwrap().text(), "1");
+    assert!(root.token_at(src.len() as u32).is_none());
+}
+
+#[test]
+fn errors_keep_every_token() {
+    for src in [
+        "let var := in 1 end",
+        "(1 +) ] x",
+        "if then else",
+        "\"open",
+        "1.5 % $",
+    ] {
+        let parse = cst::parse(src);
+        assert_eq!(parse.root.text(), src);
+        let errors = parse
+            .root
+            .descendants()
+            .filter(|n| n.kind() == NodeKind::Error);
+        assert!(
+            errors.count() > 0 || parser::parse_reporting(src).complete,
+            "{src:?}"
+        );
+    }
+    assert!(!cst::parse("\"open").errors.is_empty());
+}
+
+#[test]
+fn trees_print_with_spans() {
+    let root = cst::parse("-a /* x */").root;
+    assert_eq!(
+        root.to_string(),
+        "Program@0..10
+  Neg@0..2
+    MINUS@0..1 \"-\"
+    Name@1..2
+      ID@1..2 \"a\"
+  WHITESPACE@2..3 \" \"
+  COMMENT@3..10 \"/* x */\"
+"
+    );
+}
+
+#[test]
+fn nesting_is_limited_like_the_parser() {
+    let src = format!("{}1{}", "(".repeat(10_000), ")".repeat(10_000));
+    let root = cst::parse(&src).root;
+    assert_eq!(root.text(), src);
+}
@@ -0,0 +1,509 @@
+//! Message catalogs: the text of every diagnostic in each language.
+//!
+//! A `Message` is a catalog key and the values of its placeholders, and is
+//! only turned into text, in the reader's language, when it is printed.
+//! Keys of diagnostics are error codes such as `E0317`; labels, notes and
+//! the phrases substituted into other messages have descriptive keys. A
+//! translation may leave keys out; they are shown in English.
+//!
+//! Argument values are program text, numbers and type names, which no
+//! language changes.
+
+use std::fmt;
+
+use crate::json::Json;
+use crate::shared_str::SharedStr;
+
+/// A language messages can be shown in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum Lang {
+    #[default]
+    English,
+    Nepali,
+}
+
+impl Lang {
+    /// A language from a locale name such as `ne` or `ne_NP.UTF-8`.
+    pub(crate) fn parse(locale: &str) -> Option<Lang> {
+        let code = locale.split(['_', '-', '.']).next().unwrap_or_default();
+        match code.to_ascii_lowercase().as_str() {
+            "en" => Some(Lang::English),
+            "ne" => Some(Lang::Nepali),
+            _ => None,
+        }
+    }
+
+    /// The language `TIGER_LANG` asks for, or English.
+    pub(crate) fn from_env() -> Lang {
+        std::env::var("TIGER_LANG")
+            .ok()
+            .and_then(|locale| Lang::parse(&locale))
+            .unwrap_or_default()
+    }
+
+    fn catalog(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Lang::English => ENGLISH,
+            Lang::Nepali => NEPALI,
+        }
+    }
+
+    /// The template of `key`, falling back to English.
+    fn template(self, key: &str) -> Option<&'static str> {
+        let find = |catalog: &[(&str, &'static str)]| {
+            catalog.iter().find(|(k, _)| *k == key).map(|&(_, t)| t)
+        };
+        find(self.catalog()).or_else(|| find(ENGLISH))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Message {
+    /// Text with no catalog entry, shown as is in every language.
+    Text(SharedStr),
+    Keyed {
+        key: &'static str,
+        /// Values of the template's `{name}` placeholders.
+        args: Vec<(&'static str, Message)>,
+    },
+}
+
+impl Message {
+    pub(crate) fn new(key: &'static str) -> Message {
+        debug_assert!(Lang::English.template(key).is_some(), "no message `{key}`");
+        Message::Keyed {
+            key,
+            args: Vec::new(),
+        }
+    }
+
+    /// Fills the placeholder `name` with `value`, the same in every language.
+    pub(crate) fn arg(self, name: &'static str, value: impl Into<SharedStr>) -> Message {
+        self.nested(name, Message::Text(value.into()))
+    }
+
+    /// Fills the placeholder `name` with another message, such as a phrase.
+    pub(crate) fn nested(mut self, name: &'static str, value: Message) -> Message {
+        if let Message::Keyed { args, .. } = &mut self {
+            args.push((name, value));
+        }
+        self
+    }
+
+    /// The catalog key, if the message has one.
+    pub(crate) fn key(&self) -> Option<&'static str> {
+        match self {
+            Message::Text(_) => None,
+            Message::Keyed { key, .. } => Some(key),
+        }
+    }
+
+    pub(crate) fn render(&self, lang: Lang) -> String {
+        let (key, args) = match self {
+            Message::Text(text) => return text.to_string(),
+            Message::Keyed { key, args } => (key, args),
+        };
+        let Some(template) = lang.template(key) else {
+            return key.to_string();
+        };
+        let mut out = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(open) = rest.find('{') {
+            out.push_str(&rest[..open]);
+            let Some(close) = rest[open..].find('}') else {
+                break;
+            };
+            let name = &rest[open + 1..open + close];
+            match args.iter().find(|(n, _)| *n == name) {
+                Some((_, value)) => out.push_str(&value.render(lang)),
+                None => out.push_str(&rest[open..=open + close]),
+            }
+            rest = &rest[open + close + 1..];
+        }
+        out.push_str(rest);
+        out
+    }
+
+    /// The message without its text: `{"code": key, "args": {...}}`, with
+    /// nested messages in the same form, or the text of a `Text`.
+    pub(crate) fn to_json(&self) -> Json {
+        match self {
+            Message::Text(text) => Json::str(text.as_str()),
+            Message::Keyed { key, args } => Json::object([
+                ("code", Json::str(*key)),
+                (
+                    "args",
+                    Json::object(args.iter().map(|(name, value)| (*name, value.to_json()))),
+                ),
+            ]),
+        }
+    }
+}
+
+impl From<String> for Message {
+    fn from(text: String) -> Message {
+        Message::Text(text.into())
+    }
+}
+
+impl From<&str> for Message {
+    fn from(text: &str) -> Message {
+        Message::Text(text.into())
+    }
+}
+
+/// Compares the English text.
+impl PartialEq<&str> for Message {
+    fn eq(&self, text: &&str) -> bool {
+        self.render(Lang::English) == *text
+    }
+}
+
+/// In English.
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render(Lang::English))
+    }
+}
+
+pub(super) const ENGLISH: &[(&str, &str)] = &[
+    ("E0001", "invalid UTF-8 at byte {byte}"),
+    ("E0002", "internal compiler error: {msg}"),
+    // Lexical errors.
+    ("E0101", "unterminated string literal"),
+    ("E0102", "unterminated comment"),
+    ("E0103", "invalid escape sequence"),
+    ("E0104", "unexpected character `{char}`"),
+    ("E0105", "integer literal is too large"),
+    ("E0106", "malformed number: {reason}"),
+    (
+        "E0107",
+        "comment nested too deeply; the limit is {max} levels",
+    ),
+    ("E0108", "string literal too long; the limit is {max} bytes"),
+    // Syntax errors.
+    ("E0201", "missing `{keyword}`"),
+    (
+        "E0202",
+        "expression nested too deeply; the limit is {max} levels",
+    ),
+    ("E0203", "too many fields; the limit is {max}"),
+    ("E0204", "expected {expected}, found {found}"),
+    ("E0205", "invalid left-hand side of assignment"),
+    ("E0206", "non-associative operator `{op}` cannot be chained"),
+    ("E0207", "Tiger has no floating point literals"),
+    (
+        "E0208",
+        "chain of operators or accessors too long; the limit is {max}",
+    ),
+    // Type errors.
+    ("E0301", "{what}: expected `{expected}`, found `{found}`"),
+    ("E0302", "`{name}` is not a record type"),
+    (
+        "E0303",
+        "record `{name}` has {fields}, but {given}",
+    ),
+    ("E0304", "expected field `{expected}`, found `{found}`"),
+    ("E0305", "cannot assign to loop variable `{name}`"),
+    (
+        "E0306",
+        "`if` branches have different types: `{then}` and `{else}`",
+    ),
+    ("E0307", "`break` outside of a loop"),
+    ("E0308", "`{name}` is not an array type"),
+    ("E0309", "`{name}` is a variable, not a function"),
+    ("E0310", "undefined function `{name}`"),
+    ("E0311", "`{name}` takes 1 argument, but {given}"),
+    (
+        "E0312",
+        "`{name}` takes {expected} arguments, but {given}",
+    ),
+    ("E0313", "cannot order values of type `{ty}`"),
+    ("E0314", "cannot compare values of type `()`"),
+    ("E0315", "cannot compare `nil` with `nil`"),
+    ("E0316", "`{name}` is a function, not a variable"),
+    ("E0317", "undefined variable `{name}`"),
+    ("E0318", "type `{ty}` has no field `{field}`"),
+    ("E0319", "type `{ty}` is not a record"),
+    ("E0320", "type `{ty}` is not an array"),
+    ("E0321", "undefined type `{name}`"),
+    (
+        "E0322",
+        "cannot infer the type of `{name}` from `nil`; add a record type annotation",
+    ),
+    ("E0323", "type `{name}` is defined in terms of itself"),
+    (
+        "E0324",
+        "type `{name}` is declared twice in one group of type declarations",
+    ),
+    (
+        "E0325",
+        "function `{name}` is declared twice in one group of function declarations",
+    ),
+    (
+        "E0326",
+        "`import \"{path}\"` was not resolved; imports are only allowed among the declarations of the outermost `let`",
+    ),
+    ("E0327", "field `{name}` is declared twice in one record type"),
+    (
+        "E0328",
+        "parameter `{name}` is declared twice in one parameter list",
+    ),
+    ("E0329", "field `{name}` is given twice in one record expression"),
+    ("E0330", "variable `{name}` is used before it is declared"),
+    // Run-time errors.
+    ("E0401", "negative array size {size}"),
+    ("E0402", "division by zero"),
+    ("E0403", "cannot write output: {error}"),
+    ("E0404", "cannot read input: {error}"),
+    ("E0405", "`chr` of {n} is out of range"),
+    (
+        "E0406",
+        "substring {first}..{end} is out of bounds for size {size}",
+    ),
+    ("E0407", "field `{field}` of nil record"),
+    (
+        "E0408",
+        "index {index} is out of bounds for array of length {len}",
+    ),
+    ("E0409", "out of memory"),
+    ("E0410", "`argv` of {i} is out of range"),
+    ("E0411", "`exit` code {code} is out of range"),
+    ("E0412", "`random` of {n} is out of range"),
+    // Import errors.
+    ("E0501", "cannot import `{path}`: {error}"),
+    ("E0502", "import cycle: `{path}` ends up importing itself"),
+    ("E0503", "`{name}` is also declared in `{file}`"),
+    // Warnings.
+    (
+        "W0101",
+        "`{name}` is reserved as a keyword for future versions of Tiger",
+    ),
+    ("W0201", "unused variable `{name}`"),
+    ("W0202", "unused parameter `{name}`"),
+    ("W0203", "function `{name}` is never called"),
+    ("W0204", "unreachable expression"),
+    ("W0301", "array size is always negative: {size}"),
+    ("W0302", "array index is always negative: {index}"),
+    ("W0303", "condition is always false"),
+    ("W0304", "`for` loop never runs: {lo} is greater than {hi}"),
+    ("W0305", "array index is always out of bounds: {index} is not less than the size {size}"),
+    // Labels and notes.
+    (
+        "rename-reserved",
+        "rename it so the program still compiles once it is a keyword",
+    ),
+    ("in-this-let", "in this `let`"),
+    (
+        "assumed-keyword",
+        "assumed an `{keyword}` here to parse the rest of the program",
+    ),
+    ("because-annotation", "expected because of this annotation"),
+    ("first-declared", "first declared here"),
+    ("first-given", "first given here"),
+    ("declared-after-use", "declared here, after the use"),
+    (
+        "unreachable-after",
+        "any code following this expression is unreachable",
+    ),
+    ("lint-off", "turn this warning off with `-W {lint}=off`"),
+    ("alias-in-cycle", "`{name}` is an alias of `{next}`"),
+    ("because-result", "expected because of this result type"),
+    ("imported-here", "`{path}` is imported here"),
+    ("folded-from", "folded from this constant"),
+    // Phrases.
+    ("end-of-input", "end of input"),
+    ("token", "`{token}`"),
+    ("either-token", "`{a}` or `{b}`"),
+    ("expected-expression", "an expression"),
+    ("expected-identifier", "an identifier"),
+    ("expected-field-name", "a field name"),
+    ("expected-function-name", "a function name"),
+    ("expected-variable-name", "a variable name"),
+    ("expected-type-name", "a type name"),
+    ("expected-loop-variable", "a loop variable"),
+    ("expected-type", "a type"),
+    ("expected-element-type", "an element type"),
+    ("expected-result-type", "a result type"),
+    ("expected-file-name", "a file name"),
+    ("expected-declaration-or-in", "a declaration or `in`"),
+    ("expected-declaration-or-end", "a declaration or end of input"),
+    ("if-condition", "`if` condition"),
+    ("if-without-else", "`if` without `else`"),
+    ("while-condition", "`while` condition"),
+    ("while-body", "`while` body"),
+    ("for-lower-bound", "`for` lower bound"),
+    ("for-upper-bound", "`for` upper bound"),
+    ("for-body", "`for` body"),
+    ("arithmetic-operand", "arithmetic operand"),
+    ("array-index", "array index"),
+    ("array-initializer", "array initializer"),
+    ("array-size", "array size"),
+    ("function-body", "function body"),
+    ("procedure-body", "procedure body"),
+    ("mismatched-argument", "mismatched argument type"),
+    ("mismatched-comparison", "mismatched comparison"),
+    ("mismatched-initializer", "mismatched initializer"),
+    ("mismatched-assignment", "mismatched types in assignment"),
+    ("field", "field `{name}`"),
+    ("one-field", "1 field"),
+    ("fields", "{n} fields"),
+    ("given-one", "1 was given"),
+    ("given", "{n} were given"),
+];
+
+pub(super) const NEPALI: &[(&str, &str)] = &[
+    ("E0001", "बाइट {byte} मा अमान्य UTF-8"),
+    ("E0002", "आन्तरिक कम्पाइलर त्रुटि: {msg}"),
+    ("E0101", "स्ट्रिङ लिटरल बन्द गरिएको छैन"),
+    ("E0102", "टिप्पणी बन्द गरिएको छैन"),
+    ("E0103", "अमान्य एस्केप अनुक्रम"),
+    ("E0104", "अनपेक्षित अक्षर `{char}`"),
+    ("E0105", "पूर्णाङ्क लिटरल धेरै ठूलो छ"),
+    ("E0106", "गलत संख्या: {reason}"),
+    ("E0107", "टिप्पणी धेरै तहसम्म नेस्ट गरिएको छ; सीमा {max} तह हो"),
+    ("E0108", "स्ट्रिङ लिटरल धेरै लामो छ; सीमा {max} बाइट हो"),
+    ("E0201", "`{keyword}` छुटेको छ"),
+    ("E0202", "अभिव्यक्ति धेरै तहसम्म नेस्ट गरिएको छ; सीमा {max} तह हो"),
+    ("E0203", "धेरै फिल्डहरू; सीमा {max} हो"),
+    ("E0204", "{expected} अपेक्षित थियो, {found} भेटियो"),
+    ("E0205", "असाइनमेन्टको बायाँ पक्ष अमान्य छ"),
+    ("E0206", "असहचारी अपरेटर `{op}` लगातार प्रयोग गर्न मिल्दैन"),
+    ("E0207", "Tiger मा दशमलव संख्याका लिटरल हुँदैनन्"),
+    ("E0208", "अपरेटर वा एक्सेसरको शृङ्खला धेरै लामो छ; सीमा {max} हो"),
+    ("E0301", "{what}: `{expected}` अपेक्षित थियो, `{found}` भेटियो"),
+    ("E0302", "`{name}` रेकर्ड प्रकार होइन"),
+    ("E0303", "रेकर्ड `{name}` मा {fields}, तर {given}"),
+    ("E0304", "फिल्ड `{expected}` अपेक्षित थियो, `{found}` भेटियो"),
+    ("E0305", "लूप चर `{name}` मा मान राख्न मिल्दैन"),
+    (
+        "E0306",
+        "`if` का शाखाहरूका प्रकार फरक छन्: `{then}` र `{else}`",
+    ),
+    ("E0307", "लूपबाहिर `break`"),
+    ("E0308", "`{name}` एरे प्रकार होइन"),
+    ("E0309", "`{name}` चर हो, फङ्सन होइन"),
+    ("E0310", "अपरिभाषित फङ्सन `{name}`"),
+    ("E0311", "`{name}` ले १ आर्गुमेन्ट लिन्छ, तर {given}"),
+    ("E0312", "`{name}` ले {expected} आर्गुमेन्ट लिन्छ, तर {given}"),
+    ("E0313", "`{ty}` प्रकारका मानहरूको क्रम मिलाउन मिल्दैन"),
+    ("E0314", "`()` प्रकारका मानहरू तुलना गर्न मिल्दैन"),
+    ("E0315", "`nil` लाई `nil` सँग तुलना गर्न मिल्दैन"),
+    ("E0316", "`{name}` फङ्सन हो, चर होइन"),
+    ("E0317", "अपरिभाषित चर `{name}`"),
+    ("E0318", "प्रकार `{ty}` मा फिल्ड `{field}` छैन"),
+    ("E0319", "प्रकार `{ty}` रेकर्ड होइन"),
+    ("E0320", "प्रकार `{ty}` एरे होइन"),
+    ("E0321", "अपरिभाषित प्रकार `{name}`"),
+    (
+        "E0322",
+        "`nil` बाट `{name}` को प्रकार थाहा पाउन सकिँदैन; रेकर्ड प्रकारको एनोटेसन थप्नुहोस्",
+    ),
+    ("E0323", "प्रकार `{name}` आफैँको आधारमा परिभाषित छ"),
+    (
+        "E0324",
+        "प्रकार `{name}` एउटै प्रकार घोषणा समूहमा दुई पटक घोषित छ",
+    ),
+    ("E0325", "फङ्सन `{name}` एउटै फङ्सन घोषणा समूहमा दुई पटक घोषित छ"),
+    (
+        "E0326",
+        "`import \"{path}\"` समाधान भएन; आयात सबैभन्दा बाहिरको `let` का घोषणाहरूमा मात्र राख्न मिल्छ",
+    ),
+    ("E0327", "फिल्ड `{name}` एउटै रेकर्ड प्रकारमा दुई पटक घोषित छ"),
+    (
+        "E0328",
+        "प्यारामिटर `{name}` एउटै प्यारामिटर सूचीमा दुई पटक घोषित छ",
+    ),
+    ("E0329", "फिल्ड `{name}` एउटै रेकर्ड अभिव्यक्तिमा दुई पटक दिइएको छ"),
+    ("E0330", "चर `{name}` घोषणा हुनुअघि नै प्रयोग गरिएको छ"),
+    ("E0401", "एरेको आकार {size} ऋणात्मक छ"),
+    ("E0402", "शून्यले भाग"),
+    ("E0403", "आउटपुट लेख्न सकिएन: {error}"),
+    ("E0404", "इनपुट पढ्न सकिएन: {error}"),
+    ("E0405", "`chr` को {n} दायराबाहिर छ"),
+    ("E0406", "सबस्ट्रिङ {first}..{end} आकार {size} को सीमाबाहिर छ"),
+    ("E0407", "nil रेकर्डको फिल्ड `{field}`"),
+    ("E0408", "इन्डेक्स {index} लम्बाइ {len} भएको एरेको सीमाबाहिर छ"),
+    ("E0409", "मेमोरी सकियो"),
+    ("E0410", "`argv` को {i} दायराबाहिर छ"),
+    ("E0411", "`exit` को कोड {code} दायराबाहिर छ"),
+    ("E0412", "`random` को {n} दायराबाहिर छ"),
+    ("E0501", "`{path}` आयात गर्न सकिएन: {error}"),
+    ("E0502", "आयात चक्र: `{path}` ले अन्ततः आफैँलाई आयात गर्छ"),
+    ("E0503", "`{name}` `{file}` मा पनि घोषित छ"),
+    (
+        "W0101",
+        "`{name}` Tiger का आगामी संस्करणहरूका लागि किवर्डका रूपमा आरक्षित छ",
+    ),
+    ("W0201", "प्रयोग नगरिएको चर `{name}`"),
+    ("W0202", "प्रयोग नगरिएको प्यारामिटर `{name}`"),
+    ("W0203", "फङ्सन `{name}` कहिल्यै बोलाइएको छैन"),
+    ("W0204", "कहिल्यै नपुगिने अभिव्यक्ति"),
+    ("W0301", "एरेको आकार सधैँ ऋणात्मक हुन्छ: {size}"),
+    ("W0302", "एरेको इन्डेक्स सधैँ ऋणात्मक हुन्छ: {index}"),
+    ("W0303", "सर्त सधैँ गलत हुन्छ"),
+    ("W0304", "`for` लूप कहिल्यै चल्दैन: {lo} {hi} भन्दा ठूलो छ"),
+    (
+        "W0305",
+        "एरेको इन्डेक्स सधैँ सीमाबाहिर हुन्छ: {index} आकार {size} भन्दा सानो छैन",
+    ),
+    (
+        "rename-reserved",
+        "यो किवर्ड भएपछि पनि प्रोग्राम कम्पाइल होस् भनेर यसको नाम बदल्नुहोस्",
+    ),
+    ("in-this-let", "यो `let` भित्र"),
+    (
+        "assumed-keyword",
+        "बाँकी प्रोग्राम पार्स गर्न यहाँ `{keyword}` छ भनी मानियो",
+    ),
+    ("because-annotation", "यो एनोटेसनले गर्दा अपेक्षित"),
+    ("first-declared", "पहिलो पटक यहाँ घोषित"),
+    ("first-given", "पहिलो पटक यहाँ दिइएको"),
+    ("declared-after-use", "प्रयोगपछि यहाँ घोषित"),
+    (
+        "unreachable-after",
+        "यो अभिव्यक्तिपछिको कुनै पनि कोड कहिल्यै चल्दैन",
+    ),
+    ("lint-off", "यो चेतावनी `-W {lint}=off` ले बन्द गर्नुहोस्"),
+    ("alias-in-cycle", "`{name}` `{next}` को उपनाम हो"),
+    ("because-result", "यो परिणाम प्रकारले गर्दा अपेक्षित"),
+    ("imported-here", "`{path}` यहाँ आयात गरिएको छ"),
+    ("folded-from", "यो स्थिर मानबाट गणना गरिएको"),
+    ("end-of-input", "इनपुटको अन्त्य"),
+    ("either-token", "`{a}` वा `{b}`"),
+    ("expected-expression", "अभिव्यक्ति"),
+    ("expected-identifier", "नाम"),
+    ("expected-field-name", "फिल्डको नाम"),
+    ("expected-function-name", "फङ्सनको नाम"),
+    ("expected-variable-name", "चरको नाम"),
+    ("expected-type-name", "प्रकारको नाम"),
+    ("expected-loop-variable", "लूप चर"),
+    ("expected-type", "प्रकार"),
+    ("expected-element-type", "एलिमेन्टको प्रकार"),
+    ("expected-result-type", "परिणामको प्रकार"),
+    ("expected-file-name", "फाइलको नाम"),
+    ("expected-declaration-or-in", "घोषणा वा `in`"),
+    ("expected-declaration-or-end", "घोषणा वा इनपुटको अन्त्य"),
+    ("if-condition", "`if` को सर्त"),
+    ("if-without-else", "`else` बिनाको `if`"),
+    ("while-condition", "`while` को सर्त"),
+    ("while-body", "`while` को मुख्य भाग"),
+    ("for-lower-bound", "`for` को तल्लो सीमा"),
+    ("for-upper-bound", "`for` को माथिल्लो सीमा"),
+    ("for-body", "`for` को मुख्य भाग"),
+    ("arithmetic-operand", "अङ्कगणितीय अपरेन्ड"),
+    ("array-index", "एरे इन्डेक्स"),
+    ("array-initializer", "एरेको प्रारम्भिक मान"),
+    ("array-size", "एरेको आकार"),
+    ("function-body", "फङ्सनको मुख्य भाग"),
+    ("procedure-body", "प्रोसिजरको मुख्य भाग"),
+    ("mismatched-argument", "आर्गुमेन्टको प्रकार मिलेन"),
+    ("mismatched-comparison", "तुलना मिलेन"),
+    ("mismatched-initializer", "प्रारम्भिक मान मिलेन"),
+    ("mismatched-assignment", "असाइनमेन्टमा प्रकार मिलेन"),
+    ("field", "फिल्ड `{name}`"),
+    ("one-field", "१ फिल्ड छ"),
+    ("fields", "{n} फिल्ड छन्"),
+    ("given-one", "१ दिइयो"),
+    ("given", "{n} दिइयो"),
+];
@@ -0,0 +1,298 @@
+#![allow(dead_code)]
+
+//! Diagnostics shared by every phase, and their rendering.
+//!
+//! Each phase's errors convert to a `Diagnostic`, which the driver collects
+//! so that one run reports everything it found. A diagnostic is rendered
+//! as `file:line:col: error: msg`, followed by the lines it points at, with
+//! `^` under the primary span and `-` under secondary labels:
+//!
+//! ```text
+//! t.tig:2:17: error: mismatched initializer: expected `int`, found `string`
+//!   |
+//! 2 |   var x: int := "one"
+//!   |                  ^^^^^
+//!   |          --- expected because of this annotation
+//! ```
+//!
+//! Messages are kept as catalog keys and arguments, in `catalog`, and put
+//! into words in the reader's language when rendered.
+
+pub(crate) mod catalog;
+#[cfg(test)]
+mod tests;
+
+pub(crate) use catalog::{Lang, Message};
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+
+use crate::interp::RuntimeError;
+use crate::lexer::{LexError, ReservedWord};
+use crate::parser::{Insertion, ParseError};
+use crate::semant::TypeError;
+use crate::source_map::{DecodeError, SourceFile, SourceMap};
+use crate::span::{FileId, Span};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    /// ANSI SGR code the severity is printed in.
+    fn color(self) -> &'static str {
+        match self {
+            Severity::Error => "1;31",
+            Severity::Warning => "1;33",
+            Severity::Note => "1;36",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        })
+    }
+}
+
+/// A secondary span of a diagnostic and what it has to do with the error.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Label {
+    pub(crate) pos: Span,
+    pub(crate) msg: Message,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Diagnostic {
+    pub(crate) severity: Severity,
+    pub(crate) msg: Message,
+    /// The span the diagnostic is about.
+    pub(crate) pos: Span,
+    pub(crate) labels: Vec<Label>,
+    /// Printed after the source lines as `= note: ...`.
+    pub(crate) notes: Vec<Message>,
+}
+
+impl Diagnostic {
+    pub(crate) fn new(severity: Severity, pos: Span, msg: impl Into<Message>) -> Diagnostic {
+        Diagnostic {
+            severity,
+            msg: msg.into(),
+            pos,
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub(crate) fn error(pos: Span, msg: impl Into<Message>) -> Diagnostic {
+        Diagnostic::new(Severity::Error, pos, msg)
+    }
+
+    pub(crate) fn warning(pos: Span, msg: impl Into<Message>) -> Diagnostic {
+        Diagnostic::new(Severity::Warning, pos, msg)
+    }
+
+    pub(crate) fn with_label(mut self, pos: Span, msg: impl Into<Message>) -> Diagnostic {
+        self.labels.push(Label {
+            pos,
+            msg: msg.into(),
+        });
+        self
+    }
+
+    pub(crate) fn with_note(mut self, note: impl Into<Message>) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub(crate) fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl From<&DecodeError> for Diagnostic {
+    fn from(err: &DecodeError) -> Diagnostic {
+        Diagnostic::error(err.pos, err.message())
+    }
+}
+
+impl From<&LexError> for Diagnostic {
+    fn from(err: &LexError) -> Diagnostic {
+        Diagnostic::error(err.pos(), err.message())
+    }
+}
+
+impl From<&ReservedWord> for Diagnostic {
+    fn from(word: &ReservedWord) -> Diagnostic {
+        Diagnostic::warning(word.pos, Message::new("W0101").arg("name", word.name))
+            .with_note(Message::new("rename-reserved"))
+    }
+}
+
+impl From<&Insertion> for Diagnostic {
+    fn from(insertion: &Insertion) -> Diagnostic {
+        let keyword = insertion.keyword();
+        Diagnostic::error(insertion.pos, insertion.message())
+            .with_label(insertion.opener, Message::new("in-this-let"))
+            .with_note(Message::new("assumed-keyword").arg("keyword", keyword))
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(err: &ParseError) -> Diagnostic {
+        Diagnostic::error(err.pos, err.msg.clone())
+    }
+}
+
+impl From<&TypeError> for Diagnostic {
+    fn from(err: &TypeError) -> Diagnostic {
+        Diagnostic {
+            labels: err.labels.clone(),
+            ..Diagnostic::error(err.pos, err.msg.clone())
+        }
+    }
+}
+
+impl From<&RuntimeError> for Diagnostic {
+    fn from(err: &RuntimeError) -> Diagnostic {
+        Diagnostic::error(err.pos, err.msg.clone())
+    }
+}
+
+/// An underline below one source line.
+struct Mark<'a> {
+    /// Byte range within the line.
+    lo: usize,
+    hi: usize,
+    primary: bool,
+    msg: &'a str,
+}
+
+pub(crate) struct Renderer {
+    /// Whether to use ANSI colors.
+    color: bool,
+    lang: Lang,
+}
+
+impl Renderer {
+    pub(crate) fn new(color: bool) -> Renderer {
+        Renderer {
+            color,
+            lang: Lang::English,
+        }
+    }
+
+    pub(crate) fn with_lang(mut self, lang: Lang) -> Renderer {
+        self.lang = lang;
+        self
+    }
+
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.color && !text.is_empty() {
+            format!("\x1b[{code}m{text}\x1b[0m")
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Renders `diag`, all of whose spans are in `file`.
+    pub(crate) fn render(&self, file: &SourceFile, diag: &Diagnostic) -> String {
+        self.render_with(&|_| file, diag)
+    }
+
+    /// Renders `diag`, whose spans may be in any file of `map`. Lines of
+    /// other files than the primary span's come after its own, under the
+    /// name of their file.
+    pub(crate) fn render_in(&self, map: &SourceMap, diag: &Diagnostic) -> String {
+        self.render_with(&|id| map.get(id), diag)
+    }
+
+    fn render_with<'f>(
+        &self,
+        files: &dyn Fn(FileId) -> &'f SourceFile,
+        diag: &Diagnostic,
+    ) -> String {
+        let file = files(diag.pos.file);
+        let (line, col) = file.lookup_line_col(diag.pos.lo());
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{}:{line}:{col}: {}: {}",
+            file.name(),
+            self.paint(diag.severity.color(), &diag.severity.to_string()),
+            self.paint("1", &diag.msg.render(self.lang))
+        );
+
+        // Lines by whether they are in another file than the primary span,
+        // that file, and line number.
+        let mut lines: BTreeMap<(bool, FileId, usize), Vec<Mark>> = BTreeMap::new();
+        let label_msgs: Vec<String> = diag
+            .labels
+            .iter()
+            .map(|l| l.msg.render(self.lang))
+            .collect();
+        let primary = std::iter::once((diag.pos, true, ""));
+        let labels =
+            (diag.labels.iter().zip(&label_msgs)).map(|(l, msg)| (l.pos, false, msg.as_str()));
+        for (pos, primary, msg) in primary.chain(labels) {
+            let file = files(pos.file);
+            let (line, _) = file.lookup_line_col(pos.lo());
+            let start = file.line_start(line);
+            let len = file.line_text(line).len();
+            // Spans running past the line are underlined to its end.
+            let lo = ((pos.lo() - start) as usize).min(len);
+            let hi = ((pos.hi().max(pos.lo()) - start) as usize).clamp(lo, len);
+            let key = (pos.file != diag.pos.file, pos.file, line);
+            lines.entry(key).or_default().push(Mark {
+                lo,
+                hi,
+                primary,
+                msg,
+            });
+        }
+
+        let last_line = lines.keys().map(|&(_, _, n)| n).max();
+        let width = last_line.map_or(1, |n| n.to_string().len());
+        let gutter = |n: &str| self.paint("1;34", &format!("{n:>width$} |"));
+        let _ = writeln!(out, "{}", gutter(""));
+        let mut current = diag.pos.file;
+        for (&(_, id, n), marks) in &lines {
+            let file = files(id);
+            if id != current {
+                current = id;
+                let (_, col) = file.lookup_line_col(file.line_start(n) + marks[0].lo as u32);
+                let arrow = self.paint("1;34", ":::");
+                let _ = writeln!(out, "{:>width$} {arrow} {}:{n}:{col}", "", file.name());
+            }
+            let text = file.line_text(n);
+            let _ = writeln!(out, "{} {text}", gutter(&n.to_string()));
+            for mark in marks {
+                // Copy tabs so the underline lines up however they are shown.
+                let indent: String = text[..mark.lo]
+                    .chars()
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                let len = text[mark.lo..mark.hi].chars().count().max(1);
+                let (c, code) = match mark.primary {
+                    true => ('^', diag.severity.color()),
+                    false => ('-', "1;34"),
+                };
+                let underline = self.paint(code, &c.to_string().repeat(len));
+                let row = format!("{} {indent}{underline} {}", gutter(""), mark.msg);
+                let _ = writeln!(out, "{}", row.trim_end());
+            }
+        }
+        for note in &diag.notes {
+            let note = note.render(self.lang);
+            let _ = writeln!(out, "{:>width$} = {}: {note}", "", self.paint("1", "note"));
+        }
+        out
+    }
+}
@@ -0,0 +1,219 @@
+use crate::diagnostics::catalog::{ENGLISH, NEPALI};
+use crate::diagnostics::{Diagnostic, Lang, Message, Renderer};
+use crate::parser::parse_reporting;
+use crate::semant::type_check;
+use crate::source_map::{SourceFile, SourceMap};
+use crate::span::{FileId, Span};
+
+fn render(src: &str, diag: &Diagnostic) -> String {
+    Renderer::new(false).render(&SourceFile::new("t.tig", src), diag)
+}
+
+#[test]
+fn primary_span_is_underlined() {
+    let src = "let\n  var x := y\nin x end";
+    let diag = Diagnostic::error(Span::new(15, 16), "undefined variable `y`");
+    let expected = "\
+t.tig:2:12: error: undefined variable `y`
+  |
+2 |   var x := y
+  |            ^
+";
+    assert_eq!(render(src, &diag), expected);
+}
+
+#[test]
+fn labels_and_notes_follow_the_primary_span() {
+    let src = "let\n  var x: int := \"one\"\nin\n  x\nend";
+    let diag = Diagnostic::error(Span::new(20, 25), "mismatched initializer")
+        .with_label(Span::new(13, 16), "expected because of this")
+        .with_label(Span::new(31, 32), "used here")
+        .with_note("strings are not ints");
+    let expected = "\
+t.tig:2:17: error: mismatched initializer
+  |
+2 |   var x: int := \"one\"
+  |                 ^^^^^
+  |          --- expected because of this
+4 |   x
+  |   - used here
+  = note: strings are not ints
+";
+    assert_eq!(render(src, &diag), expected);
+}
+
+#[test]
+fn underlines_keep_tabs_and_stop_at_the_line_end() {
+    let src = "\tx := \"abc\ndef\"";
+    let diag = Diagnostic::warning(Span::new(6, 15), "long string");
+    let expected = "\
+t.tig:1:7: warning: long string
+  |
+1 | \tx := \"abc
+  | \t     ^^^^
+";
+    assert_eq!(render(src, &diag), expected);
+    let eof = Diagnostic::error(Span::new(15, 15), "expected `end`");
+    assert!(render(src, &eof).ends_with("2 | def\"\n  |     ^\n"));
+}
+
+#[test]
+fn labels_in_other_files_name_their_file() {
+    let mut map = SourceMap::new();
+    let main = map.add(SourceFile::new("main.tig", "f(1)"));
+    let lib = map.add(SourceFile::new("lib.tig", "/* lib */\nfunction f() = ()"));
+    assert_eq!(main, FileId::MAIN);
+    let diag = Diagnostic::error(Span::in_file(main, 0, 4), "too many arguments")
+        .with_label(Span::in_file(lib, 19, 20), "declared here");
+    let expected = "\
+main.tig:1:1: error: too many arguments
+  |
+1 | f(1)
+  | ^^^^
+  ::: lib.tig:2:10
+2 | function f() = ()
+  |          - declared here
+";
+    assert_eq!(Renderer::new(false).render_in(&map, &diag), expected);
+}
+
+#[test]
+fn color_wraps_severity_and_underline() {
+    let file = SourceFile::new("t.tig", "nil");
+    let diag = Diagnostic::error(Span::new(0, 3), "bad");
+    let colored = Renderer::new(true).render(&file, &diag);
+    assert!(colored.contains("\x1b[1;31merror\x1b[0m"), "{colored:?}");
+    assert!(colored.contains("\x1b[1;31m^^^\x1b[0m"), "{colored:?}");
+    assert!(!Renderer::new(false).render(&file, &diag).contains('\x1b'));
+}
+
+#[test]
+fn lexical_errors_are_all_reported() {
+    let parsed = parse_reporting("let var a := \"\\q\" var b := \"\\w\" in a end");
+    assert!(parsed.ast.is_some() && parsed.complete);
+    let msgs: Vec<String> = parsed
+        .diagnostics
+        .iter()
+        .map(|d| d.msg.to_string())
+        .collect();
+    assert_eq!(msgs, ["invalid escape sequence", "invalid escape sequence"]);
+}
+
+#[test]
+fn reserved_words_are_warned_about() {
+    let parsed = parse_reporting("let var import := 1 in import end");
+    assert!(parsed.complete);
+    let warnings: Vec<(bool, u32)> = parsed
+        .diagnostics
+        .iter()
+        .map(|d| (d.is_error(), d.pos.lo()))
+        .collect();
+    assert_eq!(warnings, [(false, 8), (false, 23)]);
+    let expected = "\
+t.tig:1:9: warning: `import` is reserved as a keyword for future versions of Tiger
+  |
+1 | let var import := 1 in import end
+  |         ^^^^^^
+  = note: rename it so the program still compiles once it is a keyword
+";
+    assert_eq!(
+        render("let var import := 1 in import end", &parsed.diagnostics[0]),
+        expected
+    );
+}
+
+#[test]
+fn type_errors_carry_their_labels() {
+    let src = "let function f(): int = \"s\" in f() end";
+    let exp = crate::parser::parse(src).unwrap();
+    let errors = type_check(&exp).unwrap_err();
+    let diag = Diagnostic::from(&errors[0]);
+    assert_eq!(diag.labels.len(), 1);
+    assert_eq!(diag.labels[0].pos, Span::new(18, 21));
+}
+
+#[test]
+fn integer_overflow_still_leaves_a_complete_tree() {
+    let parsed = parse_reporting("let var a := 99999999999999999999 in a + \"s\" end");
+    assert!(parsed.complete);
+    let msgs: Vec<String> = parsed
+        .diagnostics
+        .iter()
+        .map(|d| d.msg.to_string())
+        .collect();
+    assert_eq!(msgs, ["integer literal is too large"]);
+    let errors = type_check(&parsed.ast.unwrap()).unwrap_err();
+    assert_eq!(errors.len(), 1);
+}
+
+#[test]
+fn messages_render_in_the_chosen_language() {
+    let src = "let var x: int := \"one\" in x end";
+    let errors = type_check(&crate::parser::parse(src).unwrap()).unwrap_err();
+    let diag = Diagnostic::from(&errors[0]);
+    let file = SourceFile::new("t.tig", src);
+    let nepali = Renderer::new(false)
+        .with_lang(Lang::Nepali)
+        .render(&file, &diag);
+    let expected = "\
+t.tig:1:19: error: प्रारम्भिक मान मिलेन: `int` अपेक्षित थियो, `string` भेटियो
+  |
+1 | let var x: int := \"one\" in x end
+  |                   ^^^^^
+  |            --- यो एनोटेसनले गर्दा अपेक्षित
+";
+    assert_eq!(nepali, expected);
+    assert_eq!(
+        diag.msg.to_string(),
+        "mismatched initializer: expected `int`, found `string`"
+    );
+    // Text outside the catalog reads the same in every language.
+    assert_eq!(Message::from("as is").render(Lang::Nepali), "as is");
+}
+
+#[test]
+fn syntax_errors_render_in_the_chosen_language() {
+    let render = |src: &str, lang| {
+        let parsed = crate::parser::parse_reporting(src);
+        parsed.diagnostics[0].msg.render(lang)
+    };
+    let src = "let var := 1 in x end";
+    assert_eq!(
+        render(src, Lang::English),
+        "expected a variable name, found `:=`"
+    );
+    assert_eq!(
+        render(src, Lang::Nepali),
+        "चरको नाम अपेक्षित थियो, `:=` भेटियो"
+    );
+    assert_eq!(
+        render("(1 2)", Lang::Nepali),
+        "`;` वा `)` अपेक्षित थियो, `2` भेटियो"
+    );
+}
+
+#[test]
+fn locales_name_languages() {
+    assert_eq!(Lang::parse("ne"), Some(Lang::Nepali));
+    assert_eq!(Lang::parse("ne_NP.UTF-8"), Some(Lang::Nepali));
+    assert_eq!(Lang::parse("en-US"), Some(Lang::English));
+    assert_eq!(Lang::parse("fr"), None);
+}
+
+#[test]
+fn translations_have_the_placeholders_of_the_english() {
+    let placeholders = |template: &str| {
+        let mut names: Vec<String> = template
+            .split('{')
+            .skip(1)
+            .map(|s| s[..s.find('}').unwrap()].to_string())
+            .collect();
+        names.sort();
+        names
+    };
+    for (key, template) in NEPALI {
+        let english = ENGLISH.iter().find(|(k, _)| k == key);
+        let (_, english) = english.unwrap_or_else(|| panic!("`{key}` is not in English"));
+        assert_eq!(placeholders(template), placeholders(english), "{key}");
+    }
+}
@@ -0,0 +1,222 @@
+//! `tigerc check`: checks many programs in one run, such as a corpus of
+//! student submissions. Each file is checked on its own, so a file that
+//! crashes the compiler is reported as an internal compiler error and the
+//! rest are still checked. A summary at the end sorts the files into those
+//! that passed, failed with diagnostics, or crashed.
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+use crate::codegen::emit;
+use crate::diagnostics::Diagnostic;
+use crate::lints::{self, Lints};
+use crate::opt::Passes;
+use crate::semant::Semant;
+
+#[derive(Debug, PartialEq)]
+pub(super) struct CheckOptions {
+    /// Files, and directories to check every `.tig` file of.
+    pub(super) paths: Vec<String>,
+    /// Also generate assembly, without assembling or linking it.
+    pub(super) backend: bool,
+    pub(super) passes: Passes,
+}
+
+impl CheckOptions {
+    pub(super) fn parse(args: &[String]) -> Result<CheckOptions, String> {
+        let mut paths = Vec::new();
+        let mut backend = false;
+        let mut passes = Passes::NONE;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--backend" => backend = true,
+                "--opt-level" => {
+                    let level = args.next().ok_or("`--opt-level` expects an argument")?;
+                    passes = Passes::for_level(level)?;
+                }
+                flag if flag.starts_with("--opt-level=") => {
+                    passes = Passes::for_level(&flag["--opt-level=".len()..])?;
+                }
+                flag if flag.starts_with('-') => return Err(format!("unknown option `{flag}`")),
+                path => paths.push(path.to_string()),
+            }
+        }
+        if paths.is_empty() {
+            return Err("`check` expects files or directories".to_string());
+        }
+        Ok(CheckOptions {
+            paths,
+            backend,
+            passes,
+        })
+    }
+}
+
+/// How checking one file ended.
+#[derive(Clone, Debug, PartialEq)]
+pub(super) enum Outcome {
+    Passed,
+    /// The program has errors, or could not be read.
+    Failed(String),
+    /// The compiler panicked; the message says where and why.
+    Crashed(String),
+}
+
+pub(super) fn run(args: &[String]) -> Result<(), String> {
+    let opts = CheckOptions::parse(args)?;
+    let files = expand(&opts.paths)?;
+    // The panic message is kept for the summary instead of printed as it
+    // happens.
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|info| {
+        let mut msg = info.payload_as_str().unwrap_or("panicked").to_string();
+        if let Some(location) = info.location() {
+            let _ = write!(msg, " at {location}");
+        }
+        LAST_PANIC.with(|last| *last.borrow_mut() = Some(msg));
+    }));
+    let outcomes = check_all(&files, |path| check_file(path, &opts));
+    panic::set_hook(default_hook);
+    for (path, outcome) in files.iter().zip(&outcomes) {
+        if let Outcome::Crashed(msg) = outcome {
+            eprintln!("error: internal compiler error while checking `{path}`: {msg}");
+            eprintln!(
+                "  = note: this is a bug in tigerc; please attach the bundle of \
+                 `tigerc {path} --emit bundle` to a bug report\n"
+            );
+        }
+    }
+    let text = summary(&files, &outcomes);
+    print!("{text}");
+    match outcomes.iter().filter(|o| **o != Outcome::Passed).count() {
+        0 => Ok(()),
+        n => Err(format!("{n} of {} files did not pass", files.len())),
+    }
+}
+
+thread_local! {
+    /// The message of the last panic on this thread, set by the panic hook
+    /// of `run` with the location it happened at.
+    static LAST_PANIC: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Checks every file with `check`, isolating each one: a panic while
+/// checking a file makes it crash without stopping the others.
+pub(super) fn check_all(
+    files: &[String],
+    check: impl Fn(&str) -> Result<(), String>,
+) -> Vec<Outcome> {
+    files
+        .iter()
+        .map(
+            |path| match panic::catch_unwind(AssertUnwindSafe(|| check(path))) {
+                Ok(Ok(())) => Outcome::Passed,
+                Ok(Err(msg)) => Outcome::Failed(msg),
+                Err(payload) => {
+                    let hooked = LAST_PANIC.with(|last| last.borrow_mut().take());
+                    Outcome::Crashed(hooked.unwrap_or_else(|| panic_message(&*payload)))
+                }
+            },
+        )
+        .collect()
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match (
+        payload.downcast_ref::<&str>(),
+        payload.downcast_ref::<String>(),
+    ) {
+        (Some(msg), _) => msg.to_string(),
+        (_, Some(msg)) => msg.clone(),
+        _ => "panicked".to_string(),
+    }
+}
+
+/// Type checks a program and lints it, and generates its assembly if
+/// `opts.backend` is set.
+fn check_file(path: &str, opts: &CheckOptions) -> Result<(), String> {
+    let (sources, ast, mut diagnostics) = super::parse_file(path)?;
+    let Some(ast) = ast else {
+        return super::compile::report(&sources, &diagnostics);
+    };
+    let mut semant = Semant::new();
+    semant.check(&ast);
+    diagnostics.extend(semant.errors().iter().map(Diagnostic::from));
+    if !diagnostics.iter().any(Diagnostic::is_error) {
+        diagnostics.extend(lints::check(&ast, &semant, Lints::default()));
+    }
+    super::compile::report(&sources, &diagnostics)?;
+    if opts.backend {
+        emit::program(semant.fragments(), opts.passes);
+    }
+    Ok(())
+}
+
+/// `paths` with every directory replaced by the `.tig` files under it, in
+/// name order.
+pub(super) fn expand(paths: &[String]) -> Result<Vec<String>, String> {
+    let mut files = Vec::new();
+    for path in paths {
+        if Path::new(path).is_dir() {
+            collect_tig_files(Path::new(path), &mut files)?;
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
+fn collect_tig_files(dir: &Path, files: &mut Vec<String>) -> Result<(), String> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("could not read `{}`: {e}", dir.display()))?;
+    let mut entries: Vec<_> = entries
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("could not read `{}`: {e}", dir.display()))?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            collect_tig_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "tig") {
+            files.push(path.display().to_string());
+        }
+    }
+    Ok(())
+}
+
+/// A count of each outcome, then the files that crashed and those that
+/// failed.
+pub(super) fn summary(files: &[String], outcomes: &[Outcome]) -> String {
+    let count = |f: fn(&Outcome) -> bool| outcomes.iter().filter(|o| f(o)).count();
+    let passed = count(|o| *o == Outcome::Passed);
+    let failed = count(|o| matches!(o, Outcome::Failed(_)));
+    let crashed = count(|o| matches!(o, Outcome::Crashed(_)));
+    let mut out = format!(
+        "checked {} file{}: {passed} passed, {failed} failed, {crashed} crashed\n",
+        files.len(),
+        if files.len() == 1 { "" } else { "s" }
+    );
+    for (title, crashes) in [("crashed", true), ("failed", false)] {
+        let listed: Vec<(&String, &String)> = files
+            .iter()
+            .zip(outcomes)
+            .filter_map(|(path, outcome)| match outcome {
+                Outcome::Crashed(msg) if crashes => Some((path, msg)),
+                Outcome::Failed(msg) if !crashes => Some((path, msg)),
+                _ => None,
+            })
+            .collect();
+        if !listed.is_empty() {
+            let _ = writeln!(out, "{title}:");
+            for (path, msg) in listed {
+                let first_line = msg.lines().next().unwrap_or_default();
+                let _ = writeln!(out, "    {path}: {first_line}");
+            }
+        }
+    }
+    out
+}
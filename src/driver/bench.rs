@@ -0,0 +1,183 @@
+//! `tigerc bench`: builds a program with the native backend, runs the
+//! executable `--runs` times, and reports how long the runs took. Each run
+//! reads no input and its output is discarded; every run must end the same
+//! way, so a program that fails part of the time is not timed as if it
+//! had worked.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::opt::Passes;
+
+use super::build::{self, Backend, BuildOptions};
+
+#[derive(Debug, PartialEq)]
+pub(super) struct BenchOptions {
+    pub(super) path: String,
+    pub(super) runs: u32,
+    pub(super) passes: Passes,
+    pub(super) runtime: Option<PathBuf>,
+    /// The arguments each run is given, after `--`.
+    pub(super) args: Vec<String>,
+}
+
+impl BenchOptions {
+    pub(super) fn parse(args: &[String]) -> Result<BenchOptions, String> {
+        let mut path = None;
+        let mut runs = 10;
+        let mut passes = Passes::NONE;
+        let mut runtime = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--runs" => {
+                    let n = args.next().ok_or("`--runs` expects a number")?;
+                    runs = parse_runs(n)?;
+                }
+                flag if flag.starts_with("--runs=") => {
+                    runs = parse_runs(&flag["--runs=".len()..])?;
+                }
+                "--opt-level" => {
+                    let level = args.next().ok_or("`--opt-level` expects an argument")?;
+                    passes = Passes::for_level(level)?;
+                }
+                flag if flag.starts_with("--opt-level=") => {
+                    passes = Passes::for_level(&flag["--opt-level=".len()..])?;
+                }
+                "--runtime" => {
+                    let lib = args.next().ok_or("`--runtime` expects a file name")?;
+                    runtime = Some(PathBuf::from(lib));
+                }
+                "--" => break,
+                flag if flag.starts_with('-') && flag != "-" => {
+                    return Err(format!("unknown option `{flag}`"))
+                }
+                file if path.is_none() => path = Some(file.to_string()),
+                extra => return Err(format!("unexpected argument `{extra}`")),
+            }
+        }
+        Ok(BenchOptions {
+            path: path.ok_or("`bench` expects a file name")?,
+            runs,
+            passes,
+            runtime,
+            args: args.cloned().collect(),
+        })
+    }
+}
+
+fn parse_runs(n: &str) -> Result<u32, String> {
+    match n.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("invalid number of runs `{n}`")),
+    }
+}
+
+pub(super) fn run(args: &[String]) -> Result<(), String> {
+    let opts = BenchOptions::parse(args)?;
+    let exe = std::env::temp_dir().join(format!("tigerc-bench-{}", std::process::id()));
+    build::build(&BuildOptions {
+        path: opts.path.clone(),
+        output: exe.to_string_lossy().into_owned(),
+        runtime: opts.runtime.clone(),
+        passes: opts.passes,
+        backend: Backend::Native,
+    })?;
+    let times = time(&exe, &opts);
+    let _ = std::fs::remove_file(&exe);
+    let (times, code) = times?;
+    print!("{}", report(&Stats::of(&times), code));
+    Ok(())
+}
+
+/// The time of each run of `exe`, and the exit code they all ended with.
+fn time(exe: &Path, opts: &BenchOptions) -> Result<(Vec<Duration>, i32), String> {
+    let mut times = Vec::with_capacity(opts.runs as usize);
+    let mut first = None;
+    for run in 1..=opts.runs {
+        let start = Instant::now();
+        let status = Command::new(exe)
+            .args(&opts.args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map_err(|e| format!("cannot run `{}`: {e}", exe.display()))?;
+        times.push(start.elapsed());
+        let code = status
+            .code()
+            .ok_or_else(|| format!("run {run} was killed ({status})"))?;
+        match first {
+            None => first = Some(code),
+            Some(first) if first != code => {
+                return Err(format!(
+                    "run {run} exited with code {code}, but the first with {first}"
+                ))
+            }
+            Some(_) => {}
+        }
+    }
+    Ok((times, first.unwrap_or(0)))
+}
+
+/// How long a set of runs took.
+#[derive(Debug, PartialEq)]
+pub(super) struct Stats {
+    pub(super) runs: usize,
+    pub(super) min: Duration,
+    pub(super) median: Duration,
+    pub(super) mean: Duration,
+    pub(super) max: Duration,
+    /// The standard deviation of the runs from the mean.
+    pub(super) deviation: Duration,
+}
+
+impl Stats {
+    /// The statistics of `times`, which must not be empty.
+    pub(super) fn of(times: &[Duration]) -> Stats {
+        let mut sorted = times.to_vec();
+        sorted.sort();
+        let n = sorted.len();
+        let median = match n % 2 {
+            1 => sorted[n / 2],
+            _ => (sorted[n / 2 - 1] + sorted[n / 2]) / 2,
+        };
+        let mean = sorted.iter().sum::<Duration>() / n as u32;
+        let variance = sorted
+            .iter()
+            .map(|t| (t.as_secs_f64() - mean.as_secs_f64()).powi(2))
+            .sum::<f64>()
+            / n as f64;
+        Stats {
+            runs: n,
+            min: sorted[0],
+            median,
+            mean,
+            max: sorted[n - 1],
+            deviation: Duration::from_secs_f64(variance.sqrt()),
+        }
+    }
+}
+
+/// `stats` as `tigerc bench` prints them, in milliseconds.
+pub(super) fn report(stats: &Stats, code: i32) -> String {
+    let ms = |t: Duration| format!("{:.3} ms", t.as_secs_f64() * 1000.0);
+    let mut out = format!(
+        "{} run{}, exit code {code}\n",
+        stats.runs,
+        if stats.runs == 1 { "" } else { "s" }
+    );
+    let rows = [
+        ("min", stats.min),
+        ("median", stats.median),
+        ("mean", stats.mean),
+        ("max", stats.max),
+        ("std dev", stats.deviation),
+    ];
+    for (name, t) in rows {
+        let _ = writeln!(out, "{name:<8}{:>12}", ms(t));
+    }
+    out
+}
@@ -0,0 +1,172 @@
+//! `tigerc build`: compiles a program to an executable. The assembly is
+//! assembled and linked with the runtime library, built from the `runtime`
+//! crate, by the system C compiler: `$CC`, or `cc`. With `--backend llvm`,
+//! LLVM compiles the program instead, to an object file linked the same way.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::codegen::emit;
+use crate::diagnostics::Diagnostic;
+use crate::opt::Passes;
+use crate::semant::Semant;
+
+/// The file name of the runtime library.
+const RUNTIME: &str = "libtiger_runtime.a";
+
+#[derive(Debug, PartialEq)]
+pub(super) struct BuildOptions {
+    pub(super) path: String,
+    /// The executable; by default the program's file name without `.tig`,
+    /// or `a.out`.
+    pub(super) output: String,
+    /// The runtime library; by default found next to `tigerc`.
+    pub(super) runtime: Option<PathBuf>,
+    pub(super) passes: Passes,
+    pub(super) backend: Backend,
+}
+
+/// What turns the IR into machine code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Backend {
+    /// The instruction selection and register allocation of `codegen`.
+    Native,
+    /// LLVM, through `llc`; needs the `llvm` feature.
+    Llvm,
+}
+
+impl Backend {
+    fn parse(s: &str) -> Result<Backend, String> {
+        match s {
+            "native" => Ok(Backend::Native),
+            "llvm" => Ok(Backend::Llvm),
+            _ => Err(format!("unknown backend `{s}`")),
+        }
+    }
+}
+
+impl BuildOptions {
+    pub(super) fn parse(args: &[String]) -> Result<BuildOptions, String> {
+        let (mut path, mut output, mut runtime) = (None, None, None);
+        let mut passes = Passes::NONE;
+        let mut fuel = None;
+        let mut backend = Backend::Native;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--backend" => {
+                    backend = Backend::parse(args.next().ok_or("`--backend` expects an argument")?)?
+                }
+                flag if flag.starts_with("--backend=") => {
+                    backend = Backend::parse(&flag["--backend=".len()..])?;
+                }
+                "-o" => output = Some(args.next().ok_or("`-o` expects a file name")?.clone()),
+                "--runtime" => {
+                    let lib = args.next().ok_or("`--runtime` expects a file name")?;
+                    runtime = Some(PathBuf::from(lib));
+                }
+                "--opt-level" => {
+                    let level = args.next().ok_or("`--opt-level` expects an argument")?;
+                    passes = Passes::for_level(level)?;
+                }
+                flag if flag.starts_with("--opt-level=") => {
+                    passes = Passes::for_level(&flag["--opt-level=".len()..])?;
+                }
+                "--opt-fuel" => {
+                    let n = args.next().ok_or("`--opt-fuel` expects a number")?;
+                    fuel = Some(super::compile::parse_fuel(n)?);
+                }
+                flag if flag.starts_with("--opt-fuel=") => {
+                    fuel = Some(super::compile::parse_fuel(&flag["--opt-fuel=".len()..])?);
+                }
+                flag if flag.starts_with('-') && flag != "-" => {
+                    return Err(format!("unknown option `{flag}`"))
+                }
+                file if path.is_none() => path = Some(file.to_string()),
+                extra => return Err(format!("unexpected argument `{extra}`")),
+            }
+        }
+        let path = path.ok_or("`build` expects a file name")?;
+        let output = match output {
+            Some(output) => output,
+            None if path == "-" => return Err("`build` needs `-o` to read stdin".to_string()),
+            None => path.strip_suffix(".tig").unwrap_or("a.out").to_string(),
+        };
+        Ok(BuildOptions {
+            path,
+            output,
+            runtime,
+            passes: Passes { fuel, ..passes },
+            backend,
+        })
+    }
+}
+
+pub(super) fn run(args: &[String]) -> Result<(), String> {
+    build(&BuildOptions::parse(args)?)
+}
+
+/// Compiles and links the program `opts` names.
+pub(super) fn build(opts: &BuildOptions) -> Result<(), String> {
+    let (sources, ast, mut diagnostics) = super::parse_file(&opts.path)?;
+    let Some(ast) = ast else {
+        return super::compile::report(&sources, &diagnostics);
+    };
+    let mut semant = Semant::new();
+    semant.check(&ast);
+    diagnostics.extend(semant.errors().iter().map(Diagnostic::from));
+    super::compile::report(&sources, &diagnostics)?;
+
+    let runtime = match &opts.runtime {
+        Some(lib) => lib.clone(),
+        None => find_runtime()?,
+    };
+    if opts.backend == Backend::Llvm {
+        let module = super::llvm::module(semant.fragments(), opts.passes)?;
+        let object = super::llvm::compile(&module, &opts.output)?;
+        let linked = link(&object, &runtime, &opts.output);
+        let _ = std::fs::remove_file(&object);
+        return linked;
+    }
+    let asm = emit::program(semant.fragments(), opts.passes);
+    // The assembly goes next to the executable while it is linked.
+    let asm_path = format!("{}.s", opts.output);
+    std::fs::write(&asm_path, asm).map_err(|e| format!("could not write `{asm_path}`: {e}"))?;
+    let linked = link(Path::new(&asm_path), &runtime, &opts.output);
+    let _ = std::fs::remove_file(&asm_path);
+    linked
+}
+
+/// Looks for the runtime library next to the `tigerc` executable, where
+/// `cargo build` puts it, or in the directory above, for the copies of
+/// `tigerc` Cargo keeps in `deps`.
+pub(super) fn find_runtime() -> Result<PathBuf, String> {
+    let exe = std::env::current_exe().map_err(|e| format!("cannot find `tigerc`: {e}"))?;
+    exe.ancestors()
+        .skip(1)
+        .take(2)
+        .map(|dir| dir.join(RUNTIME))
+        .find(|lib| lib.is_file())
+        .ok_or_else(|| {
+            format!(
+                "cannot find the runtime library `{RUNTIME}`; build it with \
+                 `cargo build -p tiger-runtime`, or pass `--runtime`"
+            )
+        })
+}
+
+/// Links `code`, assembly or an object file, with `runtime` into `output`.
+fn link(code: &Path, runtime: &Path, output: &str) -> Result<(), String> {
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(&cc)
+        .arg("-o")
+        .arg(output)
+        .arg(code)
+        .arg(runtime)
+        .status()
+        .map_err(|e| format!("cannot run `{cc}`: {e}"))?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(format!("`{cc}` failed to link `{output}` ({status})")),
+    }
+}
@@ -0,0 +1,295 @@
+//! `tigerc --emit bundle`: every artifact of one compilation, written to a
+//! directory so that a bug report can attach a single reproducible bundle.
+//!
+//! `manifest.json` lists the files written, with what each one holds, the
+//! input, and the optimization passes. A program that does not compile
+//! still gets a bundle, with the artifacts of the phases it got through and
+//! its diagnostics in `diagnostics.txt`.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::ast::{self, Exp};
+use crate::canon;
+use crate::codegen::{self, assem, emit, size};
+use crate::diagnostics::{Diagnostic, Renderer};
+use crate::flowgraph;
+use crate::ir::Stm;
+use crate::json::Json;
+use crate::opt::{self, Fuel, Passes};
+use crate::pretty;
+use crate::semant::Semant;
+use crate::source_map::{SourceFile, SourceMap};
+use crate::translate::Fragment;
+
+/// Where the bundle goes without `--out-dir`.
+pub(super) const DEFAULT_DIR: &str = "target/tiger";
+
+/// The optimization passes in the order `opt::optimize` runs them.
+const PASSES: [(&str, Passes); 4] = [
+    (
+        "fold-constants",
+        Passes {
+            fold_constants: true,
+            ..Passes::NONE
+        },
+    ),
+    (
+        "simplify",
+        Passes {
+            simplify: true,
+            ..Passes::NONE
+        },
+    ),
+    (
+        "fold-branches",
+        Passes {
+            fold_branches: true,
+            ..Passes::NONE
+        },
+    ),
+    (
+        "remove-unreachable",
+        Passes {
+            remove_unreachable: true,
+            ..Passes::NONE
+        },
+    ),
+];
+
+/// The directory being written and the files written to it so far.
+struct Bundle {
+    dir: PathBuf,
+    files: Vec<(String, String)>,
+}
+
+impl Bundle {
+    fn write(&mut self, name: &str, description: &str, contents: &str) -> Result<(), String> {
+        let path = self.dir.join(name);
+        std::fs::write(&path, contents)
+            .map_err(|e| format!("could not write `{}`: {e}", path.display()))?;
+        self.files.push((name.to_string(), description.to_string()));
+        Ok(())
+    }
+}
+
+/// Writes the bundle of the program in `sources.main()` to `dir`, then
+/// reports its diagnostics as compiling it would.
+pub(super) fn write(
+    sources: &SourceMap,
+    ast: Option<&Exp>,
+    mut diagnostics: Vec<Diagnostic>,
+    dir: &Path,
+    passes: Passes,
+) -> Result<(), String> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("could not create `{}`: {e}", dir.display()))?;
+    let mut bundle = Bundle {
+        dir: dir.to_path_buf(),
+        files: Vec::new(),
+    };
+    let mut stats = Vec::new();
+    let file = sources.main();
+
+    bundle.write("source.tig", "the program, as read", file.src())?;
+    let (tokens, _) = super::tokens::lex_all(file.src());
+    stats.push(("tokens", Json::from(tokens.len() as u32)));
+    let tokens = super::tokens::render_json(file, &tokens);
+    bundle.write("tokens.json", "the tokens, as `tokens --json`", &tokens)?;
+
+    let semant = ast.map(|ast| {
+        bundle.write("ast.txt", "the syntax tree", &pretty::tree(ast))?;
+        let json = format!("{}\n", ast::json::exp(ast));
+        bundle.write("ast.json", "the syntax tree, as `--emit ast-json`", &json)?;
+        let mut semant = Semant::new();
+        let ty = semant.check(ast);
+        diagnostics.extend(semant.errors().iter().map(Diagnostic::from));
+        let types = types_json(&semant, semant.types.name(ty));
+        bundle.write("types.json", "the type of every declared name", &types)?;
+        Ok::<_, String>(semant)
+    });
+    let semant = semant.transpose()?;
+    let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+    stats.push(("errors", Json::from(errors as u32)));
+
+    if let (Some(ast), Some(semant)) = (ast.filter(|_| errors == 0), &semant) {
+        backend(&mut bundle, &mut stats, semant.fragments(), passes)?;
+        let html = super::report::html(file, ast, semant);
+        bundle.write("report.html", "every phase, linked to the source", &html)?;
+    }
+    if !diagnostics.is_empty() {
+        let renderer = Renderer::new(false);
+        let text: String = diagnostics
+            .iter()
+            .map(|d| renderer.render_in(sources, d) + "\n")
+            .collect();
+        bundle.write("diagnostics.txt", "errors and warnings", &text)?;
+    }
+
+    let stats = Json::object(stats);
+    bundle.write(
+        "stats.json",
+        "sizes of each phase's output",
+        &format!("{stats}\n"),
+    )?;
+    let manifest = manifest(&bundle, file, passes, errors == 0);
+    std::fs::write(dir.join("manifest.json"), manifest)
+        .map_err(|e| format!("could not write `{}`: {e}", dir.display()))?;
+    super::compile::report(sources, &diagnostics)
+}
+
+/// The IR after each phase, the control flow graphs, and the assembly.
+fn backend(
+    bundle: &mut Bundle,
+    stats: &mut Vec<(&'static str, Json)>,
+    fragments: &[Fragment],
+    passes: Passes,
+) -> Result<(), String> {
+    let ir: String = fragments.iter().map(|f| f.to_string()).collect();
+    bundle.write("ir-0-translate.txt", "the IR trees, as translated", &ir)?;
+
+    let procs: Vec<(String, Vec<Stm>)> = fragments
+        .iter()
+        .filter_map(|fragment| match fragment {
+            Fragment::Proc { body, frame } => {
+                let (blocks, done) = canon::basic_blocks(canon::linearize(body.clone()));
+                Some((
+                    frame.name().to_string(),
+                    canon::trace_schedule(blocks, done),
+                ))
+            }
+            Fragment::String(..) => None,
+        })
+        .collect();
+    stats.push(("functions", Json::from(procs.len() as u32)));
+    let mut stages = vec![("canon", Passes::NONE)];
+    let mut so_far = Passes {
+        fuel: passes.fuel,
+        ..Passes::NONE
+    };
+    for (name, pass) in PASSES {
+        if enabled(passes, pass) {
+            so_far = union(so_far, pass);
+            stages.push((name, so_far));
+        }
+    }
+    let mut sizes = Vec::new();
+    let mut optimized = Vec::new();
+    for (i, &(name, stage)) in stages.iter().enumerate() {
+        let mut text = String::new();
+        let mut count = 0;
+        optimized.clear();
+        let mut fuel = Fuel::new(stage);
+        for (proc, stms) in &procs {
+            let stms = opt::optimize(stms.clone(), stage, &mut fuel);
+            let _ = writeln!(text, "PROC {proc}");
+            for stm in &stms {
+                let _ = writeln!(text, "{stm}");
+            }
+            count += stms.len();
+            optimized.push((proc, stms));
+        }
+        let file = format!("ir-{}-{name}.txt", i + 1);
+        let description = match name {
+            "canon" => "the canonical trees, in trace order".to_string(),
+            pass => format!("the canonical trees after `{pass}`"),
+        };
+        bundle.write(&file, &description, &text)?;
+        sizes.push((name, Json::from(count as u32)));
+    }
+    stats.push(("ir_statements", Json::object(sizes)));
+
+    let mut dot = String::from("digraph cfg {\n");
+    let mut instructions = 0;
+    for (i, (proc, stms)) in optimized.iter().enumerate() {
+        let instrs = codegen::codegen(stms.clone());
+        instructions += instrs.len();
+        let graph = flowgraph::instrs_to_graph(&instrs);
+        let _ = writeln!(dot, "  subgraph cluster_{i} {{\n    label=\"{proc}\";");
+        for (n, instr) in instrs.iter().enumerate() {
+            let label = Json::str(instr.format(&assem::temp_name));
+            let _ = writeln!(dot, "    f{i}_{n} [shape=box, label={label}];");
+        }
+        for (n, node) in graph.nodes.iter().enumerate() {
+            for succ in &node.succ {
+                let _ = writeln!(dot, "    f{i}_{n} -> f{i}_{succ};");
+            }
+        }
+        dot.push_str("  }\n");
+    }
+    dot.push_str("}\n");
+    bundle.write(
+        "cfg.dot",
+        "the control flow graph of each function's instructions, before register allocation",
+        &dot,
+    )?;
+    stats.push(("instructions", Json::from(instructions as u32)));
+
+    let sizes = super::sizes::json(&size::program(fragments, passes));
+    let sizes = format!("{sizes}\n");
+    bundle.write(
+        "sizes.json",
+        "each function's size, as `--size-report json`",
+        &sizes,
+    )?;
+    let asm = emit::program(fragments, passes);
+    stats.push(("asm_lines", Json::from(asm.lines().count() as u32)));
+    bundle.write("asm.s", "x86-64 assembly", &asm)
+}
+
+fn enabled(passes: Passes, pass: Passes) -> bool {
+    union(passes, pass) == passes
+}
+
+fn union(a: Passes, b: Passes) -> Passes {
+    Passes {
+        fold_constants: a.fold_constants || b.fold_constants,
+        simplify: a.simplify || b.simplify,
+        fold_branches: a.fold_branches || b.fold_branches,
+        remove_unreachable: a.remove_unreachable || b.remove_unreachable,
+        fuel: a.fuel,
+    }
+}
+
+fn types_json(semant: &Semant, program: String) -> String {
+    let derivations = semant.derivations().iter().map(|d| {
+        Json::object([
+            ("name", Json::str(d.name.to_string())),
+            (
+                "span",
+                Json::object([
+                    ("lo", Json::from(d.pos.lo())),
+                    ("hi", Json::from(d.pos.hi())),
+                ]),
+            ),
+            ("type", Json::str(d.ty.as_str())),
+            ("rule", Json::str(d.rule)),
+        ])
+    });
+    let types = Json::object([
+        ("program", Json::String(program)),
+        ("derivations", Json::Array(derivations.collect())),
+    ]);
+    format!("{types}\n")
+}
+
+fn manifest(bundle: &Bundle, file: &SourceFile, passes: Passes, compiled: bool) -> String {
+    let passes = PASSES
+        .iter()
+        .filter(|(_, pass)| enabled(passes, *pass))
+        .map(|(name, _)| Json::str(*name));
+    let files = bundle.files.iter().map(|(name, description)| {
+        Json::object([
+            ("name", Json::str(name.as_str())),
+            ("contents", Json::str(description.as_str())),
+        ])
+    });
+    let manifest = Json::object([
+        ("tigerc", Json::str(env!("CARGO_PKG_VERSION"))),
+        ("input", Json::str(file.name().as_str())),
+        ("passes", Json::Array(passes.collect())),
+        ("compiled", Json::from(compiled)),
+        ("files", Json::Array(files.collect())),
+    ]);
+    format!("{manifest}\n")
+}
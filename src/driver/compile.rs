@@ -0,0 +1,462 @@
+use crate::ast;
+use crate::codegen::{self, size};
+use crate::cst;
+use crate::diagnostics::Diagnostic;
+use crate::features;
+use crate::fmt;
+use crate::interp;
+use crate::lints::{self, Lints};
+use crate::opt::Passes;
+use crate::parser::grammar;
+use crate::pretty;
+use crate::semant::{type_graph, Semant};
+use crate::source_map::{NewlinePolicy, SourceMap};
+
+use super::sizes::SizeReport;
+
+/// Output selected with `--emit`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Emit {
+    Tokens,
+    /// The tokens as `tigerc tokens --json` prints them.
+    TokensJson,
+    /// The concrete syntax tree, whitespace and comments included.
+    Cst,
+    Ast,
+    /// The tree as `ast::json` writes it, on one line.
+    AstJson,
+    /// The tree as an s-expression without spans, which `--from ast` reads.
+    Sexp,
+    TypedAst,
+    TypeGraph,
+    Ir,
+    Asm,
+    /// The program as an LLVM module, with the `llvm` feature.
+    Llvm,
+    Report,
+    Grammar,
+    /// Every artifact, written to `--out-dir` by `bundle::write`.
+    Bundle,
+}
+
+impl Emit {
+    fn parse(s: &str) -> Result<Emit, String> {
+        Ok(match s {
+            "tokens" => Emit::Tokens,
+            "tokens-json" => Emit::TokensJson,
+            "cst" => Emit::Cst,
+            "ast" => Emit::Ast,
+            "ast-json" => Emit::AstJson,
+            "sexp" => Emit::Sexp,
+            "typed-ast" => Emit::TypedAst,
+            "type-graph" => Emit::TypeGraph,
+            "ir" => Emit::Ir,
+            "asm" => Emit::Asm,
+            "llvm" => Emit::Llvm,
+            "report" => Emit::Report,
+            "grammar" => Emit::Grammar,
+            "bundle" => Emit::Bundle,
+            _ => return Err(format!("cannot emit `{s}`")),
+        })
+    }
+}
+
+/// What the input file holds, selected with `--from`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Input {
+    Tiger,
+    /// A syntax tree as `pretty::sexp` prints it, which bypasses the lexer
+    /// and parser.
+    Ast,
+}
+
+impl Input {
+    fn parse(s: &str) -> Result<Input, String> {
+        match s {
+            "tiger" => Ok(Input::Tiger),
+            "ast" => Ok(Input::Ast),
+            _ => Err(format!("cannot read input from `{s}`")),
+        }
+    }
+}
+
+pub(super) struct CompileOptions {
+    pub(super) path: Option<String>,
+    pub(super) from: Input,
+    /// `None` only type checks the program.
+    pub(super) emit: Option<Emit>,
+    /// Line endings of the emitted output.
+    pub(super) newline: NewlinePolicy,
+    /// Walk through every phase instead of emitting one.
+    pub(super) explain: bool,
+    /// Optimizations applied to the emitted assembly, and their fuel.
+    pub(super) passes: Passes,
+    /// Where `--emit bundle` writes, if not `bundle::DEFAULT_DIR`.
+    pub(super) out_dir: Option<String>,
+    /// The lint groups `-W` left on.
+    pub(super) lints: Lints,
+    /// Print the size of each compiled function instead of emitting.
+    pub(super) size_report: Option<SizeReport>,
+}
+
+impl CompileOptions {
+    pub(super) fn parse(args: &[String]) -> Result<CompileOptions, String> {
+        let mut path = None;
+        let mut from = Input::Tiger;
+        let mut emit = None;
+        let mut newline = NewlinePolicy::default();
+        let mut explain = false;
+        let mut passes = Passes::NONE;
+        let mut fuel = None;
+        let mut out_dir = None;
+        let mut lints = Lints::default();
+        let mut size_report = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--emit" => {
+                    let what = args.next().ok_or("`--emit` expects an argument")?;
+                    emit = Some(Emit::parse(what)?);
+                }
+                flag if flag.starts_with("--emit=") => {
+                    emit = Some(Emit::parse(&flag["--emit=".len()..])?);
+                }
+                "--from" => {
+                    let what = args.next().ok_or("`--from` expects an argument")?;
+                    from = Input::parse(what)?;
+                }
+                flag if flag.starts_with("--from=") => {
+                    from = Input::parse(&flag["--from=".len()..])?;
+                }
+                "--newline" => {
+                    let what = args.next().ok_or("`--newline` expects an argument")?;
+                    newline = parse_newline(what)?;
+                }
+                flag if flag.starts_with("--newline=") => {
+                    newline = parse_newline(&flag["--newline=".len()..])?;
+                }
+                "--opt-level" => {
+                    let level = args.next().ok_or("`--opt-level` expects an argument")?;
+                    passes = Passes::for_level(level)?;
+                }
+                flag if flag.starts_with("--opt-level=") => {
+                    passes = Passes::for_level(&flag["--opt-level=".len()..])?;
+                }
+                "--opt-fuel" => {
+                    let n = args.next().ok_or("`--opt-fuel` expects a number")?;
+                    fuel = Some(parse_fuel(n)?);
+                }
+                flag if flag.starts_with("--opt-fuel=") => {
+                    fuel = Some(parse_fuel(&flag["--opt-fuel=".len()..])?);
+                }
+                "--out-dir" => {
+                    let dir = args.next().ok_or("`--out-dir` expects an argument")?;
+                    out_dir = Some(dir.to_string());
+                }
+                flag if flag.starts_with("--out-dir=") => {
+                    out_dir = Some(flag["--out-dir=".len()..].to_string());
+                }
+                "-W" => {
+                    let setting = args.next().ok_or("`-W` expects an argument")?;
+                    lints.set(setting)?;
+                }
+                flag if flag.starts_with("-W") => lints.set(&flag["-W".len()..])?,
+                "--size-report" => {
+                    let format = args.next().ok_or("`--size-report` expects a format")?;
+                    size_report = Some(SizeReport::parse(format)?);
+                }
+                flag if flag.starts_with("--size-report=") => {
+                    size_report = Some(SizeReport::parse(&flag["--size-report=".len()..])?);
+                }
+                "--explain" => explain = true,
+                flag if flag.starts_with("--") => return Err(format!("unknown option `{flag}`")),
+                file if path.is_none() => path = Some(file.to_string()),
+                extra => return Err(format!("unexpected argument `{extra}`")),
+            }
+        }
+        if explain && emit.is_some() {
+            return Err("`--explain` cannot be combined with `--emit`".to_string());
+        }
+        if size_report.is_some() && (explain || emit.is_some()) {
+            return Err(
+                "`--size-report` cannot be combined with `--emit` or `--explain`".to_string(),
+            );
+        }
+        let needs_source = match emit {
+            Some(Emit::Tokens) => Some("tokens"),
+            Some(Emit::TokensJson) => Some("tokens-json"),
+            Some(Emit::Cst) => Some("cst"),
+            Some(Emit::Bundle) => Some("bundle"),
+            _ => None,
+        };
+        if let (Input::Ast, Some(what)) = (from, needs_source) {
+            return Err(format!(
+                "`--emit {what}` needs Tiger source, not `--from ast`"
+            ));
+        }
+        if out_dir.is_some() && emit != Some(Emit::Bundle) {
+            return Err("`--out-dir` is only used with `--emit bundle`".to_string());
+        }
+        Ok(CompileOptions {
+            path,
+            from,
+            emit,
+            newline,
+            explain,
+            passes: Passes { fuel, ..passes },
+            out_dir,
+            lints,
+            size_report,
+        })
+    }
+}
+
+fn parse_newline(s: &str) -> Result<NewlinePolicy, String> {
+    NewlinePolicy::parse(s).ok_or_else(|| format!("unknown newline policy `{s}`"))
+}
+
+/// The number of rewrites `--opt-fuel` allows.
+pub(super) fn parse_fuel(s: &str) -> Result<u64, String> {
+    s.parse()
+        .map_err(|_| format!("invalid optimization fuel `{s}`"))
+}
+
+pub(super) fn run(opts: &CompileOptions) -> Result<(), String> {
+    if opts.emit == Some(Emit::Grammar) {
+        print!("{}", opts.newline.apply(&grammar::ebnf(), None));
+        return Ok(());
+    }
+    let path = opts.path.as_deref().ok_or("no input file")?;
+    if let Some(emit @ (Emit::Tokens | Emit::TokensJson)) = opts.emit {
+        let (file, decode_errors) = super::read_source(path)?;
+        let (tokens, errors) = super::tokens::lex_all(file.src());
+        let text = match emit {
+            Emit::Tokens => pretty::tokens(&tokens),
+            _ => super::tokens::render_json(&file, &tokens),
+        };
+        print!("{}", opts.newline.apply(&text, Some(&file)));
+        let errors = errors.iter().map(Diagnostic::from);
+        let diagnostics = super::decode_diagnostics(&decode_errors, errors);
+        return report(&SourceMap::from(file), &diagnostics);
+    }
+    if opts.emit == Some(Emit::Cst) {
+        let (file, decode_errors) = super::read_source(path)?;
+        let parse = cst::parse(file.src());
+        print!(
+            "{}",
+            opts.newline.apply(&parse.root.to_string(), Some(&file))
+        );
+        let errors = parse.errors.iter().map(Diagnostic::from);
+        let diagnostics = super::decode_diagnostics(&decode_errors, errors);
+        return report(&SourceMap::from(file), &diagnostics);
+    }
+
+    let (sources, ast, mut diagnostics) = match opts.from {
+        Input::Tiger => super::parse_file(path)?,
+        Input::Ast => super::read_ast(path)?,
+    };
+    if opts.emit == Some(Emit::Bundle) {
+        let dir = opts
+            .out_dir
+            .as_deref()
+            .unwrap_or(super::bundle::DEFAULT_DIR);
+        return super::bundle::write(
+            &sources,
+            ast.as_ref(),
+            diagnostics,
+            dir.as_ref(),
+            opts.passes,
+        );
+    }
+    let file = sources.main();
+    let output = |text: &str| print!("{}", opts.newline.apply(text, Some(file)));
+    let Some(ast) = ast else {
+        return report(&sources, &diagnostics);
+    };
+    match opts.emit {
+        Some(Emit::Ast) => {
+            report(&sources, &diagnostics)?;
+            output(&pretty::tree(&ast));
+            return Ok(());
+        }
+        Some(Emit::AstJson) => {
+            report(&sources, &diagnostics)?;
+            output(&format!("{}\n", ast::json::exp(&ast)));
+            return Ok(());
+        }
+        Some(Emit::Sexp) => {
+            report(&sources, &diagnostics)?;
+            output(&format!("{}\n", pretty::sexp(&ast)));
+            return Ok(());
+        }
+        Some(Emit::TypeGraph) => {
+            report(&sources, &diagnostics)?;
+            output(&type_graph::dot(&ast));
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    // Lexical errors leave a usable tree, so type errors are reported
+    // alongside them.
+    let mut semant = Semant::new();
+    let ty = semant.check(&ast);
+    diagnostics.extend(semant.errors().iter().map(Diagnostic::from));
+    if !diagnostics.iter().any(Diagnostic::is_error) {
+        diagnostics.extend(lints::check(&ast, &semant, opts.lints));
+    }
+    report(&sources, &diagnostics)?;
+    if opts.explain {
+        output(&super::explain::explain(&sources, &ast, &semant, ty));
+        return Ok(());
+    }
+    if let Some(report) = opts.size_report {
+        output(&report.render(&size::program(semant.fragments(), opts.passes)));
+        return Ok(());
+    }
+    match opts.emit {
+        Some(Emit::TypedAst) => {
+            output(&format!(
+                "{}: {}\n",
+                pretty::tree(&ast),
+                semant.types.name(ty)
+            ));
+            Ok(())
+        }
+        Some(Emit::Ir) => {
+            let ir: String = semant.fragments().iter().map(|f| f.to_string()).collect();
+            output(&ir);
+            Ok(())
+        }
+        Some(Emit::Asm) => {
+            output(&codegen::emit::program(semant.fragments(), opts.passes));
+            Ok(())
+        }
+        Some(Emit::Llvm) => {
+            output(&super::llvm::module(semant.fragments(), opts.passes)?);
+            Ok(())
+        }
+        Some(Emit::Report) => {
+            output(&super::report::html(file, &ast, &semant));
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// `tigerc features`: reports the language constructs a program uses.
+pub(super) fn features(args: &[String]) -> Result<(), String> {
+    let path = match args {
+        [path] => path,
+        [] => return Err("`features` expects a file name".to_string()),
+        [_, extra, ..] => return Err(format!("unexpected argument `{extra}`")),
+    };
+    let (sources, ast, diagnostics) = super::parse_file(path)?;
+    report(&sources, &diagnostics)?;
+    let ast = ast.expect("only syntax errors leave no tree");
+    print!("{}", features::detect(&ast).to_json());
+    Ok(())
+}
+
+/// `tigerc fmt`: prints a program formatted, or with `--check`, fails if
+/// formatting would change it. Lines end as `--newline` says, by default
+/// as they do in the file.
+pub(super) fn format(args: &[String]) -> Result<(), String> {
+    let mut path = None;
+    let mut check = false;
+    let mut newline = NewlinePolicy::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--check" => check = true,
+            "--newline" => {
+                let what = args.next().ok_or("`--newline` expects an argument")?;
+                newline = parse_newline(what)?;
+            }
+            flag if flag.starts_with("--newline=") => {
+                newline = parse_newline(&flag["--newline=".len()..])?;
+            }
+            _ if path.is_none() => path = Some(arg),
+            extra => return Err(format!("unexpected argument `{extra}`")),
+        }
+    }
+    let path = path.ok_or("`fmt` expects a file name")?;
+    let (file, decode_errors) = super::read_source(path)?;
+    if !decode_errors.is_empty() {
+        let diagnostics = super::decode_diagnostics(&decode_errors, []);
+        return report(&SourceMap::from(file), &diagnostics);
+    }
+    let formatted = match fmt::format_source(file.src()) {
+        Ok(formatted) => newline.apply(&formatted, Some(&file)).into_owned(),
+        Err(errors) => return report(&SourceMap::from(file), &errors),
+    };
+    if !check {
+        print!("{formatted}");
+        Ok(())
+    } else if formatted == file.src() {
+        Ok(())
+    } else {
+        Err(format!("`{path}` is not formatted"))
+    }
+}
+
+/// `tigerc run`: type checks a program and interprets it, with the
+/// arguments after `--` as its own. Returns the program's exit code.
+pub(super) fn interpret(args: &[String]) -> Result<i32, String> {
+    let (args, program_args) = match args.iter().position(|arg| arg == "--") {
+        Some(i) => (&args[..i], &args[i + 1..]),
+        None => (args, &[][..]),
+    };
+    let (path, jit) = match args {
+        [flag, path] | [path, flag] if flag == "--jit" => (path, true),
+        [path] => (path, false),
+        [] => return Err("`run` expects a file name".to_string()),
+        [_, extra, ..] => return Err(format!("unexpected argument `{extra}`")),
+    };
+    let (sources, ast, mut diagnostics) = super::parse_file(path)?;
+    let Some(ast) = ast else {
+        return report(&sources, &diagnostics).map(|()| 0);
+    };
+    let mut semant = Semant::new();
+    semant.check(&ast);
+    diagnostics.extend(semant.errors().iter().map(Diagnostic::from));
+    report(&sources, &diagnostics)?;
+    if jit {
+        let module = super::llvm::module(semant.fragments(), Passes::NONE)?;
+        return super::llvm::jit(&module, program_args);
+    }
+    let result = interp::run_with_heap(
+        &ast,
+        program_args,
+        &mut std::io::stdin().lock(),
+        &mut std::io::BufWriter::new(std::io::stdout().lock()),
+        heap_limit()?,
+    );
+    match result {
+        Ok(code) => Ok(code as i32),
+        Err(err) => report(&sources, &[Diagnostic::from(&err)]).map(|()| 0),
+    }
+}
+
+/// The heap limit `TIGER_HEAP_SIZE` sets, as compiled programs read it.
+fn heap_limit() -> Result<Option<u64>, String> {
+    let Some(size) = std::env::var_os("TIGER_HEAP_SIZE") else {
+        return Ok(None);
+    };
+    match size.to_str().and_then(interp::heap_size) {
+        Some(limit) => Ok(Some(limit)),
+        None => Err(format!(
+            "invalid TIGER_HEAP_SIZE `{}`",
+            size.to_string_lossy()
+        )),
+    }
+}
+
+/// Prints `diagnostics`, failing if any of them is an error.
+pub(super) fn report(sources: &SourceMap, diagnostics: &[Diagnostic]) -> Result<(), String> {
+    match super::print_diagnostics(sources, diagnostics) {
+        0 => Ok(()),
+        1 => Err("aborting due to 1 error".to_string()),
+        n => Err(format!("aborting due to {n} errors")),
+    }
+}
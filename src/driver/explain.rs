@@ -0,0 +1,126 @@
+//! `tigerc --explain`: takes a small program through every phase, with a
+//! short note on what each phase did to it.
+
+use std::fmt::Write;
+
+use crate::ast::Exp;
+use crate::canon;
+use crate::codegen::{self, assem};
+use crate::opt::Passes;
+use crate::pretty;
+use crate::semant::Semant;
+use crate::source_map::SourceMap;
+use crate::span::FileId;
+use crate::translate::Fragment;
+use crate::types::Ty;
+
+/// The walkthrough of a program that type checked as `ty`, whose imports
+/// are the other files of `sources`.
+pub(super) fn explain(sources: &SourceMap, ast: &Exp, semant: &Semant, ty: Ty) -> String {
+    let mut out = String::new();
+    let (tokens, _) = super::tokens::lex_all(sources.main().src());
+    section(
+        &mut out,
+        "1. tokens",
+        "The lexer splits the source into tokens, each with its byte span.",
+    );
+    out.push_str(&pretty::tokens(&tokens));
+
+    section(
+        &mut out,
+        "2. abstract syntax",
+        "The parser builds a tree of expressions and declarations, one node per \
+         line below, indented by depth.",
+    );
+    out.push_str(&pretty::tree(ast));
+
+    section(
+        &mut out,
+        "3. types",
+        "Semantic analysis gives every declared name a type, by the rule after \
+         `--`.",
+    );
+    let typings: Vec<(String, String)> = semant
+        .derivations()
+        .iter()
+        .map(|d| {
+            let file = sources.get(d.pos.file);
+            let (line, col) = file.lookup_line_col(d.pos.lo());
+            let pos = match d.pos.file {
+                FileId::MAIN => format!("{line}:{col}"),
+                _ => format!("{}:{line}:{col}", file.name()),
+            };
+            (pos, format!("{}: {}", d.name, d.ty))
+        })
+        .collect();
+    let pos_w = typings.iter().map(|(pos, _)| pos.len()).max().unwrap_or(0);
+    let typing_w = typings.iter().map(|(_, t)| t.len()).max().unwrap_or(0);
+    for ((pos, typing), d) in typings.iter().zip(semant.derivations()) {
+        let _ = writeln!(out, "{pos:<pos_w$}  {typing:<typing_w$}  -- {}", d.rule);
+    }
+    let _ = writeln!(
+        out,
+        "The whole program has type `{}`.",
+        semant.types.name(ty)
+    );
+
+    section(
+        &mut out,
+        "4. intermediate representation",
+        "Each function becomes one IR tree over an unlimited supply of \
+         temporaries; string literals become fragments of their own.",
+    );
+    for fragment in semant.fragments() {
+        let _ = write!(out, "{fragment}");
+    }
+
+    let procs: Vec<_> = semant
+        .fragments()
+        .iter()
+        .filter_map(|fragment| match fragment {
+            Fragment::Proc { body, frame } => {
+                let (blocks, done) = canon::basic_blocks(canon::linearize(body.clone()));
+                Some((frame.name(), canon::trace_schedule(blocks, done)))
+            }
+            Fragment::String(..) => None,
+        })
+        .collect();
+    section(
+        &mut out,
+        "5. canonical trees and instruction selection",
+        "Canon lifts every ESEQ and nested CALL into a statement of its own, \
+         then orders the basic blocks into traces so that each CJUMP falls \
+         through to its false label. Maximal munch then covers each statement \
+         with the x86-64 instructions shown after `=>`; temporaries are not \
+         registers yet.",
+    );
+    for (name, stms) in &procs {
+        let _ = writeln!(out, "PROC {name}");
+        for stm in stms {
+            let _ = writeln!(out, "{stm}");
+            for instr in codegen::codegen(vec![stm.clone()]) {
+                let line = instr.format(&assem::temp_name);
+                if !line.is_empty() {
+                    let _ = writeln!(out, "    => {line}");
+                }
+            }
+        }
+    }
+
+    section(
+        &mut out,
+        "6. assembly",
+        "Register allocation colors the temporaries with machine registers, \
+         spilling to the frame when they run out, and each function gets its \
+         prologue and epilogue.",
+    );
+    out.push_str(&codegen::emit::program(semant.fragments(), Passes::NONE));
+    out
+}
+
+fn section(out: &mut String, title: &str, note: &str) {
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    let _ = writeln!(out, "== {title} ==\n{note}\n");
+}
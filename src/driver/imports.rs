@@ -0,0 +1,164 @@
+//! Resolves `import "lib.tig"` declarations, so that a program can be split
+//! across files. An imported file is a sequence of declarations, as between
+//! `let` and `in`, and its path is relative to the file importing it.
+//! Imports are allowed only among the declarations of the program's
+//! outermost `let`, and those of imported files. Each one is replaced with
+//! the declarations of its file, which may import others in turn.
+//!
+//! Every file is read and parsed once. A file imported again, by the same or
+//! another file, adds nothing more, as its declarations are already in
+//! scope; an import that would lead back to a file still being imported is
+//! a cycle and reported. A name declared at the top level of two different
+//! files is reported too, as one would silently hide the other, though a
+//! file may still redeclare its own names as any `let` can.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::ast::{Dec, Exp, ExpKind, Import, Symbol};
+use crate::diagnostics::{Diagnostic, Message};
+use crate::limits::Limits;
+use crate::parser;
+use crate::source_map::{DecodeError, SourceMap};
+use crate::span::Span;
+
+/// Replaces the imports of `ast`, the program of `sources.main()`, with the
+/// declarations they name, adding the files read to `sources`. Returns the
+/// errors found on the way, spanned in the files they are about.
+pub(super) fn resolve(sources: &mut SourceMap, ast: &mut Exp) -> Vec<Diagnostic> {
+    let ExpKind::Let { decs, .. } = &mut ast.kind else {
+        return Vec::new();
+    };
+    // Stdin, `-`, has no parent and imports from the current directory.
+    let main = Path::new(sources.main().name());
+    let dir = main.parent().unwrap_or(Path::new("")).to_path_buf();
+    let stack = std::fs::canonicalize(main)
+        .map(|path| (path, None))
+        .into_iter()
+        .collect();
+    let mut resolver = Resolver {
+        sources,
+        included: HashSet::new(),
+        stack,
+        declared: HashMap::new(),
+        diagnostics: Vec::new(),
+    };
+    *decs = resolver.splice(std::mem::take(decs), &dir);
+    resolver.diagnostics
+}
+
+struct Resolver<'s> {
+    sources: &'s mut SourceMap,
+    /// The canonical paths of the files imported so far.
+    included: HashSet<PathBuf>,
+    /// The files being resolved, outermost first, each with the import
+    /// that led to it; the main file has none.
+    stack: Vec<(PathBuf, Option<Import>)>,
+    /// Where each top-level type, and each variable or function, was first
+    /// declared; the flag is whether the name is a type's.
+    declared: HashMap<(Symbol, bool), Span>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Resolver<'_> {
+    /// `decs` with each import replaced by the declarations it names.
+    /// Imports are relative to `dir`.
+    fn splice(&mut self, decs: Vec<Dec>, dir: &Path) -> Vec<Dec> {
+        let mut out = Vec::new();
+        for dec in decs {
+            match dec {
+                Dec::Import(import) => out.extend(self.import(import, dir)),
+                dec => {
+                    self.declare(&dec);
+                    out.push(dec);
+                }
+            }
+        }
+        out
+    }
+
+    fn import(&mut self, import: Import, dir: &Path) -> Vec<Dec> {
+        let path = dir.join(import.path.as_str());
+        let cannot = |error: &dyn ToString| {
+            Diagnostic::error(
+                import.pos,
+                Message::new("E0501")
+                    .arg("path", import.path)
+                    .arg("error", error.to_string()),
+            )
+        };
+        let canonical = match std::fs::canonicalize(&path) {
+            Ok(canonical) => canonical,
+            Err(err) => {
+                self.diagnostics.push(cannot(&err));
+                return Vec::new();
+            }
+        };
+        if let Some(at) = self.stack.iter().position(|(file, _)| *file == canonical) {
+            let cycle = self.stack[at + 1..]
+                .iter()
+                .filter_map(|(_, via)| via.as_ref());
+            let diag = cycle.fold(
+                Diagnostic::error(import.pos, Message::new("E0502").arg("path", import.path)),
+                |diag, via| {
+                    diag.with_label(via.pos, Message::new("imported-here").arg("path", via.path))
+                },
+            );
+            self.diagnostics.push(diag);
+            return Vec::new();
+        }
+        if !self.included.insert(canonical.clone()) {
+            return Vec::new();
+        }
+        let (file, decode_errors) = match super::read_source(&path.to_string_lossy()) {
+            Ok(read) => read,
+            Err(err) => {
+                self.diagnostics.push(cannot(&err));
+                return Vec::new();
+            }
+        };
+        let id = self.sources.add(file);
+        let library = parser::parse_library_in(self.sources.get(id).src(), id, Limits::default());
+        // Decoding knows nothing of files, so its spans are moved into this one.
+        let decode_errors: Vec<DecodeError> = decode_errors
+            .into_iter()
+            .map(|mut err| {
+                err.pos = Span::in_file(id, err.pos.lo(), err.pos.hi());
+                err
+            })
+            .collect();
+        let mut diagnostics = super::decode_diagnostics(&decode_errors, library.diagnostics);
+        diagnostics.sort_by_key(|d| d.pos.lo());
+        self.diagnostics.extend(diagnostics);
+
+        let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        self.stack.push((canonical, Some(import)));
+        let decs = self.splice(library.decs, &dir);
+        self.stack.pop();
+        decs
+    }
+
+    /// Notes the names `dec` declares, reporting any that another file
+    /// declared first.
+    fn declare(&mut self, dec: &Dec) {
+        let names: Vec<(Symbol, bool, Span)> = match dec {
+            Dec::Var(v) => vec![(v.name, false, v.pos)],
+            Dec::Function(group) => group.iter().map(|f| (f.name, false, f.pos)).collect(),
+            Dec::Type(group) => group.iter().map(|t| (t.name, true, t.pos)).collect(),
+            Dec::Import(_) => Vec::new(),
+        };
+        for (name, is_type, pos) in names {
+            let first = *self.declared.entry((name, is_type)).or_insert(pos);
+            if first.file != pos.file {
+                let file = self.sources.get(first.file).name().clone();
+                self.diagnostics.push(
+                    Diagnostic::error(
+                        pos,
+                        Message::new("E0503").arg("name", name).arg("file", file),
+                    )
+                    .with_label(first, Message::new("first-declared")),
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! `tigerc lexdiff`: compares the tokens of a file with a snapshot taken
+//! with `tigerc tokens --json`, to check a change to the lexer against a
+//! corpus. Only the first difference is shown, as the tokens after it
+//! usually differ too.
+
+use crate::diagnostics::Diagnostic;
+use crate::json::{self, Json};
+use crate::lexer::Token;
+use crate::source_map::{SourceFile, SourceMap};
+use crate::span::Span;
+
+/// A token as recorded in a snapshot.
+#[derive(Clone, Debug, PartialEq)]
+pub(super) struct SnapshotToken {
+    pub(super) kind: String,
+    pub(super) pos: Span,
+    pub(super) lexeme: String,
+}
+
+impl SnapshotToken {
+    fn of(file: &SourceFile, token: &Token) -> SnapshotToken {
+        SnapshotToken {
+            kind: format!("{:?}", token.kind()),
+            pos: *token.pos(),
+            lexeme: file.span_to_snippet(*token.pos()).to_string(),
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "{} at {}..{}: {:?}",
+            self.kind,
+            self.pos.lo(),
+            self.pos.hi(),
+            self.lexeme
+        )
+    }
+}
+
+pub(super) fn run(args: &[String]) -> Result<(), String> {
+    let (snapshot_path, path) = match args {
+        [snapshot, path] => (snapshot, path),
+        [_, _, extra, ..] => return Err(format!("unexpected argument `{extra}`")),
+        _ => return Err("`lexdiff` expects a snapshot and a file name".to_string()),
+    };
+    let json = std::fs::read_to_string(snapshot_path)
+        .map_err(|e| format!("cannot read `{snapshot_path}`: {e}"))?;
+    let snapshot = parse_snapshot(&json).map_err(|e| format!("`{snapshot_path}`: {e}"))?;
+    let (file, _) = super::read_source(path)?;
+    let (tokens, _) = super::tokens::lex_all(file.src());
+    match diff(&file, &snapshot, &tokens) {
+        None => {
+            println!("{} tokens match `{snapshot_path}`", tokens.len());
+            Ok(())
+        }
+        Some(diag) => {
+            super::print_diagnostics(&SourceMap::from(file), &[diag]);
+            Err("the tokens differ from the snapshot".to_string())
+        }
+    }
+}
+
+/// The first token of `tokens` that differs from `snapshot`, with both
+/// versions, or `None` if they are the same.
+pub(super) fn diff(
+    file: &SourceFile,
+    snapshot: &[SnapshotToken],
+    tokens: &[Token],
+) -> Option<Diagnostic> {
+    let current: Vec<SnapshotToken> = tokens.iter().map(|t| SnapshotToken::of(file, t)).collect();
+    let i = (0..snapshot.len().max(current.len())).find(|&i| snapshot.get(i) != current.get(i))?;
+    let (msg, pos) = match (snapshot.get(i), current.get(i)) {
+        (_, Some(token)) => (format!("token #{i} differs from the snapshot"), token.pos),
+        (Some(token), None) => (format!("token #{i} is missing"), token.pos),
+        (None, None) => unreachable!("`i` is in range of one of them"),
+    };
+    let describe =
+        |token: Option<&SnapshotToken>| token.map_or("none".to_string(), |t| t.describe());
+    let mut diag = Diagnostic::error(pos, msg)
+        .with_note(format!("snapshot: {}", describe(snapshot.get(i))))
+        .with_note(format!("current:  {}", describe(current.get(i))));
+    if let Some(prev) = i.checked_sub(1).and_then(|j| current.get(j)) {
+        diag = diag.with_label(prev.pos, "the tokens agree up to here");
+    }
+    Some(diag)
+}
+
+/// Reads the output of `tigerc tokens --json`: an array of objects. Fields
+/// other than `kind`, `lo`, `hi` and `lexeme` are ignored.
+pub(super) fn parse_snapshot(text: &str) -> Result<Vec<SnapshotToken>, String> {
+    let Json::Array(tokens) = json::parse(text)? else {
+        return Err("expected an array of tokens".to_string());
+    };
+    tokens
+        .iter()
+        .enumerate()
+        .map(|(i, token)| {
+            let missing = |field: &str| format!("token #{i} has no `{field}`");
+            let string = |field| match token.get(field).as_str() {
+                Some(s) => Ok(s.to_string()),
+                None => Err(missing(field)),
+            };
+            let number = |field| token.get(field).as_u32().ok_or_else(|| missing(field));
+            Ok(SnapshotToken {
+                kind: string("kind")?,
+                pos: Span::new(number("lo")?, number("hi")?),
+                lexeme: string("lexeme")?,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,148 @@
+//! The driver's side of the LLVM backend: `--emit llvm`, `build --backend
+//! llvm`, which compiles the module with `llc`, and `run --jit`, which runs
+//! it with `lli`. Without the `llvm` feature each of these is an error.
+//! The LLVM tools are found through `$LLC` and `$LLI`, or on the path.
+
+use std::path::{Path, PathBuf};
+#[cfg(feature = "llvm")]
+use std::process::Command;
+
+use crate::opt::Passes;
+use crate::translate::Fragment;
+
+/// The translated program `fragments` as an LLVM module.
+pub(super) fn module(fragments: &[Fragment], passes: Passes) -> Result<String, String> {
+    #[cfg(feature = "llvm")]
+    return crate::backend::llvm::module(fragments, passes);
+    #[cfg(not(feature = "llvm"))]
+    {
+        let _ = (fragments, passes);
+        Err(NOT_BUILT.to_string())
+    }
+}
+
+/// Compiles `module` to an object file next to `output`, returning its path.
+pub(super) fn compile(module: &str, output: &str) -> Result<PathBuf, String> {
+    let ll = format!("{output}.ll");
+    let object = PathBuf::from(format!("{output}.o"));
+    std::fs::write(&ll, module).map_err(|e| format!("could not write `{ll}`: {e}"))?;
+    let compiled = llc(Path::new(&ll), &object);
+    let _ = std::fs::remove_file(&ll);
+    compiled.map(|()| object)
+}
+
+#[cfg(feature = "llvm")]
+fn llc(ll: &Path, object: &Path) -> Result<(), String> {
+    let llc = tool("LLC", "llc");
+    // Position-independent, as `cc` links executables as PIE by default.
+    let mut cmd = Command::new(&llc);
+    cmd.args(["-relocation-model=pic", "-filetype=obj", "-o"])
+        .arg(object)
+        .arg(ll);
+    if needs_opaque_pointers(&llc) {
+        cmd.arg("-opaque-pointers");
+    }
+    let status = cmd
+        .status()
+        .map_err(|e| format!("cannot run `{llc}`: {e}"))?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(format!(
+            "`{llc}` failed to compile `{}` ({status})",
+            ll.display()
+        )),
+    }
+}
+
+#[cfg(not(feature = "llvm"))]
+fn llc(_: &Path, _: &Path) -> Result<(), String> {
+    Err(NOT_BUILT.to_string())
+}
+
+/// Runs `module` with `lli`, passing it the program arguments `args`, its
+/// output going to ours, and returns its exit code.
+#[cfg(feature = "llvm")]
+pub(super) fn jit(module: &str, args: &[String]) -> Result<i32, String> {
+    let runtime = super::build::find_runtime()?;
+    let dir = std::env::temp_dir().join(format!("tigerc-jit-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("could not create `{}`: {e}", dir.display()))?;
+    let ran = jit_in(&dir, module, &runtime, args);
+    let _ = std::fs::remove_dir_all(&dir);
+    ran
+}
+
+#[cfg(not(feature = "llvm"))]
+pub(super) fn jit(_: &str, _: &[String]) -> Result<i32, String> {
+    Err(NOT_BUILT.to_string())
+}
+
+#[cfg(feature = "llvm")]
+fn jit_in(dir: &Path, module: &str, runtime: &Path, args: &[String]) -> Result<i32, String> {
+    // `lli` loads only shared libraries, so the runtime is relinked as one.
+    // Its `main` calls `tigermain`, which the module defines; it is given an
+    // address for now, as `lli` never calls that `main`.
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let shared = dir.join("libtiger_runtime.so");
+    let status = Command::new(&cc)
+        .arg("-shared")
+        .arg("-o")
+        .arg(&shared)
+        .arg("-Wl,--whole-archive")
+        .arg(runtime)
+        .args(["-Wl,--no-whole-archive", "-Wl,--defsym=tigermain=0"])
+        .status()
+        .map_err(|e| format!("cannot run `{cc}`: {e}"))?;
+    if !status.success() {
+        return Err(format!(
+            "`{cc}` failed to link the runtime for `lli` ({status})"
+        ));
+    }
+
+    let ll = dir.join("program.ll");
+    let module = crate::backend::llvm::jit_entry(module);
+    std::fs::write(&ll, module).map_err(|e| format!("could not write `{}`: {e}", ll.display()))?;
+    let lli = tool("LLI", "lli");
+    let mut cmd = Command::new(&lli);
+    cmd.arg(format!("-load={}", shared.display())).arg(format!(
+        "-entry-function={}",
+        crate::backend::llvm::JIT_ENTRY
+    ));
+    if needs_opaque_pointers(&lli) {
+        cmd.arg("-opaque-pointers");
+    }
+    let status = cmd
+        .arg(&ll)
+        .args(args)
+        .status()
+        .map_err(|e| format!("cannot run `{lli}`: {e}"))?;
+    status
+        .code()
+        .ok_or_else(|| format!("`{lli}` was killed ({status})"))
+}
+
+/// The LLVM tool named by the environment variable `var`, or `name`.
+#[cfg(feature = "llvm")]
+fn tool(var: &str, name: &str) -> String {
+    std::env::var(var).unwrap_or_else(|_| name.to_string())
+}
+
+/// Whether `tool` predates LLVM 15, which made pointers opaque by default;
+/// the module is written with opaque pointers only.
+#[cfg(feature = "llvm")]
+fn needs_opaque_pointers(tool: &str) -> bool {
+    let Ok(out) = Command::new(tool).arg("--version").output() else {
+        return false;
+    };
+    let version = String::from_utf8_lossy(&out.stdout);
+    let major = version
+        .split("LLVM version ")
+        .nth(1)
+        .and_then(|v| v.split('.').next())
+        .and_then(|v| v.trim().parse::<u32>().ok());
+    matches!(major, Some(major) if major < 15)
+}
+
+#[cfg(not(feature = "llvm"))]
+const NOT_BUILT: &str = "tigerc was built without the LLVM backend; rebuild it with \
+                         `cargo build --features llvm`";
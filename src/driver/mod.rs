@@ -0,0 +1,245 @@
+mod batch;
+mod bench;
+mod build;
+mod bundle;
+mod compile;
+mod explain;
+mod imports;
+mod lexdiff;
+mod llvm;
+mod optdiff;
+mod report;
+mod sizes;
+#[cfg(test)]
+mod tests;
+mod timings;
+mod tokens;
+
+use std::io::{IsTerminal, Read};
+
+use crate::ast::Exp;
+use crate::diagnostics::{Diagnostic, Lang, Renderer};
+use crate::limits::Limits;
+use crate::lsp;
+use crate::parser;
+use crate::pretty;
+use crate::source_map::{DecodeError, SourceFile, SourceMap};
+use crate::straight_line_prog;
+
+const USAGE: &str = "\
+usage: tigerc <file.tig> [--emit <phase> | --explain] [--newline preserve|lf|crlf]
+                         [--from tiger|ast]
+                         [--opt-level 0|1|2] [--opt-fuel <n>] [--out-dir <dir>]
+                         [-W <lint>=on|off] [--size-report text|json]
+       tigerc <command> [options]
+
+Without --emit, the program is only type checked. --explain prints every
+phase in turn, with a note on what each one does. --newline sets the line
+endings of the emitted output; by default they follow the input file.
+--opt-level optimizes the emitted assembly: 1 folds constants and simplifies
+arithmetic, 2 also folds constant branches and drops unreachable code. The
+default is 0, no optimization. --opt-fuel stops the optimizer after its first
+n rewrites of the program. --from ast reads a syntax tree as
+`--emit sexp` prints it instead of Tiger source, skipping the lexer and
+parser. -W turns a group of warnings on or off:
+`unused` variables, parameters and functions, `unreachable` code after a
+`break`, `constant` array sizes, subscripts, conditions and `for` bounds
+that are wrong on every run, or `all` of them; every group is on by default.
+--size-report prints, for every function compiled to assembly, its
+instructions, the temporaries spilled to its frame and an estimate of its
+machine code in bytes, as a table or as JSON.
+
+phases:
+    tokens       the token stream
+    tokens-json  the token stream as JSON, as `tokens --json` prints it
+    cst          the concrete syntax tree, with every token, whitespace and comments
+    ast          the syntax tree
+    ast-json     the syntax tree as JSON, with the span of every node
+    sexp         the syntax tree as one s-expression, which --from ast reads
+    typed-ast    the syntax tree and its type, after type checking
+    type-graph   the declared types as a Graphviz digraph
+    ir           the intermediate representation
+    asm          x86-64 assembly
+    llvm         an LLVM module (needs tigerc built with `--features llvm`)
+    report       an HTML page with every phase, linked to the source
+    grammar      the accepted grammar as EBNF (needs no input file)
+    bundle       every artifact above, and the IR after each pass, written to
+                 --out-dir (target/tiger by default) with a manifest.json
+
+commands:
+    tokens <file.tig> [--json] [--color] [--lossless]
+                                            print the token stream of a file
+    lexdiff <tokens.json> <file.tig>        compare the tokens of a file with a
+                                            snapshot from `tokens --json`
+    lsp                                     serve the Language Server Protocol on stdio
+    features <file.tig>                     print the constructs a program uses as JSON
+    fmt <file.tig> [--check] [--newline preserve|lf|crlf]
+                                            print the program formatted; with --check,
+                                            fail if the file is not formatted already
+    run <file.tig> [--jit] [-- <arg>...]    interpret a program, which `argv` gives the
+                                            <arg>s; with --jit, compile it with LLVM and
+                                            run it with `$LLI` or `lli`
+    build <file.tig> [-o <exe>] [--opt-level 0|1|2] [--opt-fuel <n>]
+          [--runtime <lib>] [--backend native|llvm]
+                                            compile a program to an executable, linked
+                                            with `libtiger_runtime.a` by `$CC` or `cc`;
+                                            the llvm backend compiles with `$LLC` or `llc`
+    check <path>... [--backend] [--opt-level 0|1|2]
+                                            type check many files, and every `.tig`
+                                            file in directories; with --backend, also
+                                            generate assembly. A file that crashes the
+                                            compiler is reported and the rest still
+                                            checked
+    opt-diff <file.tig> [--step-limit <n>] [--opt-fuel <n>] [--bisect-opt]
+                                            run the IR of a program at -O0 and -O2,
+                                            both reading stdin unless it is a terminal,
+                                            check that both runs print the same, and
+                                            report the steps each function took; a
+                                            run stops after 10000000 steps by default.
+                                            With --bisect-opt, name the first rewrite
+                                            of -O2 after which the runs differ
+    time <file.tig>... [--repeat <n>] [--opt-level 0|1|2]
+                                            compile programs to assembly and print
+                                            the time of each phase in microseconds as
+                                            JSON, the fastest of n runs (10 by default)
+    bench <file.tig> [--runs <n>] [--opt-level 0|1|2] [--runtime <lib>]
+          [-- <arg>...]                     build a program with the native backend,
+                                            run it n times (10 by default) with no
+                                            input, and print the fastest, median,
+                                            mean and slowest run and the standard
+                                            deviation; every run must end with the
+                                            same exit code
+    slp                                     run the chapter 1 straight-line program
+
+The outermost `let` of a program may `import \"lib.tig\"`, a file of
+declarations, relative to the importing file.
+
+Programs that `run` interprets, and executables that `build` makes, fail
+with \"out of memory\" once they allocate more than TIGER_HEAP_SIZE bytes,
+if it is set; the size may end in k, m or g. `heap_used()` returns the bytes
+a program has allocated so far.
+
+Use `-` as the file name to read the program from stdin. Diagnostics are
+colored when printed to a terminal, unless NO_COLOR is set, and in the
+language TIGER_LANG names: `en`, the default, or `ne` for Nepali.";
+
+/// Entry point of the `tigerc` binary. Returns the process exit code.
+pub(crate) fn run(args: &[String]) -> i32 {
+    let result = match args.first().map(String::as_str) {
+        Some("tokens") => tokens::run(&args[1..]).map(|()| 0),
+        Some("lexdiff") => lexdiff::run(&args[1..]).map(|()| 0),
+        Some("lsp") => lsp::serve(
+            std::io::BufReader::new(std::io::stdin()),
+            std::io::stdout().lock(),
+        )
+        .map(|()| 0),
+        Some("features") => compile::features(&args[1..]).map(|()| 0),
+        Some("fmt") => compile::format(&args[1..]).map(|()| 0),
+        Some("run") => compile::interpret(&args[1..]),
+        Some("build") => build::run(&args[1..]).map(|()| 0),
+        Some("check") => batch::run(&args[1..]).map(|()| 0),
+        Some("opt-diff") => optdiff::run(&args[1..]).map(|()| 0),
+        Some("time") => timings::run(&args[1..]).map(|()| 0),
+        Some("bench") => bench::run(&args[1..]).map(|()| 0),
+        Some("slp") => {
+            straight_line_prog::demo();
+            Ok(0)
+        }
+        Some("-h" | "--help") => {
+            println!("{USAGE}");
+            Ok(0)
+        }
+        Some(_) => compile::CompileOptions::parse(args)
+            .and_then(|opts| compile::run(&opts))
+            .map(|()| 0),
+        None => Err(USAGE.to_string()),
+    };
+    match result {
+        Ok(code) => code,
+        Err(msg) => {
+            eprintln!("tigerc: error: {msg}");
+            1
+        }
+    }
+}
+
+/// Reads the program at `path`, or stdin when `path` is `-`, decoding it
+/// as lossy UTF-8.
+fn read_source(path: &str) -> Result<(SourceFile, Vec<DecodeError>), String> {
+    let mut bytes = Vec::new();
+    if path == "-" {
+        std::io::stdin()
+            .read_to_end(&mut bytes)
+            .map_err(|e| format!("could not read stdin: {e}"))?;
+    } else {
+        bytes = std::fs::read(path).map_err(|e| format!("could not read `{path}`: {e}"))?;
+    }
+    let max = Limits::default().max_file_size;
+    if bytes.len() > max {
+        return Err(format!(
+            "`{path}` is {} bytes, more than the limit of {max}",
+            bytes.len()
+        ));
+    }
+    Ok(SourceFile::from_bytes(path, &bytes))
+}
+
+/// Reads and parses the program at `path`, and the files it imports,
+/// collecting decoding, lexical and syntax errors and those of imports.
+/// The tree is `None` only after a syntax error in the program itself.
+fn parse_file(path: &str) -> Result<(SourceMap, Option<Exp>, Vec<Diagnostic>), String> {
+    let (file, decode_errors) = read_source(path)?;
+    let parsed = parser::parse_reporting(file.src());
+    let mut diagnostics = decode_diagnostics(&decode_errors, parsed.diagnostics);
+    diagnostics.sort_by_key(|d| d.pos.lo());
+    // A tree missing parts would only produce spurious type errors.
+    let mut ast = parsed.ast.filter(|_| parsed.complete);
+    let mut sources = SourceMap::from(file);
+    if let Some(ast) = &mut ast {
+        diagnostics.extend(imports::resolve(&mut sources, ast));
+    }
+    Ok((sources, ast, diagnostics))
+}
+
+/// Reads the syntax tree at `path`, written as `pretty::sexp` prints it,
+/// and the files it imports. Spans in the tree point into that file.
+fn read_ast(path: &str) -> Result<(SourceMap, Option<Exp>, Vec<Diagnostic>), String> {
+    let (file, decode_errors) = read_source(path)?;
+    let mut sources = SourceMap::from(file);
+    let diagnostics = decode_diagnostics(&decode_errors, []);
+    match pretty::read::sexp(sources.main().src()) {
+        Ok(mut ast) if diagnostics.is_empty() => {
+            let diagnostics = imports::resolve(&mut sources, &mut ast);
+            Ok((sources, Some(ast), diagnostics))
+        }
+        Ok(_) => Ok((sources, None, diagnostics)),
+        Err(err) => Ok((sources, None, decode_diagnostics(&decode_errors, [err]))),
+    }
+}
+
+/// `decode_errors` followed by `errors`. Invalid UTF-8 is decoded as U+FFFD,
+/// which the lexer also rejects; the decoding error is reported in its place.
+fn decode_diagnostics(
+    decode_errors: &[DecodeError],
+    errors: impl IntoIterator<Item = Diagnostic>,
+) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = decode_errors.iter().map(Diagnostic::from).collect();
+    diagnostics.extend(
+        errors
+            .into_iter()
+            .filter(|d| !decode_errors.iter().any(|e| e.pos == d.pos)),
+    );
+    diagnostics
+}
+
+/// Prints `diagnostics`, which may be about any file of `sources`, to
+/// stderr and returns how many are errors. Colors are used when stderr is a
+/// terminal, unless `NO_COLOR` is set.
+fn print_diagnostics(sources: &SourceMap, diagnostics: &[Diagnostic]) -> usize {
+    let color = std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    let renderer = Renderer::new(color).with_lang(Lang::from_env());
+    for diag in diagnostics {
+        eprintln!("{}", renderer.render_in(sources, diag));
+    }
+    diagnostics.iter().filter(|d| d.is_error()).count()
+}
@@ -0,0 +1,214 @@
+//! `tigerc opt-diff`: runs a program's IR before and after optimization,
+//! checks that both runs behave the same, and reports how many steps each
+//! function took at `-O0` and `-O2`. This makes what the optimizer saves
+//! something to measure, and catches optimizations that change what a
+//! program does. With `--bisect-opt`, when the runs differ, it also finds
+//! the first rewrite of the optimizer after which they do, by running the
+//! program with less and less `--opt-fuel`.
+
+use std::fmt::Write;
+use std::io::{IsTerminal, Read};
+
+use crate::diagnostics::Diagnostic;
+use crate::interp::ir::{self, Ending, Execution};
+use crate::opt::Passes;
+use crate::semant::Semant;
+
+/// Steps each run may take without `--step-limit`.
+const DEFAULT_STEP_LIMIT: u64 = 10_000_000;
+
+#[derive(Debug, PartialEq)]
+pub(super) struct OptDiffOptions {
+    pub(super) path: String,
+    pub(super) step_limit: u64,
+    /// How many rewrites the `-O2` run may make.
+    pub(super) fuel: Option<u64>,
+    pub(super) bisect: bool,
+}
+
+impl OptDiffOptions {
+    pub(super) fn parse(args: &[String]) -> Result<OptDiffOptions, String> {
+        let mut path = None;
+        let mut step_limit = DEFAULT_STEP_LIMIT;
+        let (mut fuel, mut bisect) = (None, false);
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let limit = match arg.as_str() {
+                "--step-limit" => Some(
+                    args.next()
+                        .ok_or("`--step-limit` expects a number")?
+                        .as_str(),
+                ),
+                flag if flag.starts_with("--step-limit=") => Some(&flag["--step-limit=".len()..]),
+                _ => None,
+            };
+            match (limit, arg.as_str()) {
+                (Some(limit), _) => {
+                    step_limit = limit
+                        .parse()
+                        .map_err(|_| format!("invalid step limit `{limit}`"))?
+                }
+                (None, "--bisect-opt") => bisect = true,
+                (None, "--opt-fuel") => {
+                    let n = args.next().ok_or("`--opt-fuel` expects a number")?;
+                    fuel = Some(super::compile::parse_fuel(n)?);
+                }
+                (None, flag) if flag.starts_with("--opt-fuel=") => {
+                    fuel = Some(super::compile::parse_fuel(&flag["--opt-fuel=".len()..])?);
+                }
+                (None, flag) if flag.starts_with('-') && flag != "-" => {
+                    return Err(format!("unknown option `{flag}`"))
+                }
+                (None, file) if path.is_none() => path = Some(file.to_string()),
+                (None, extra) => return Err(format!("unexpected argument `{extra}`")),
+            }
+        }
+        Ok(OptDiffOptions {
+            path: path.ok_or("`opt-diff` expects a file name")?,
+            step_limit,
+            fuel,
+            bisect,
+        })
+    }
+}
+
+pub(super) fn run(args: &[String]) -> Result<(), String> {
+    let opts = OptDiffOptions::parse(args)?;
+    let (sources, ast, mut diagnostics) = super::parse_file(&opts.path)?;
+    let Some(ast) = ast else {
+        return super::compile::report(&sources, &diagnostics);
+    };
+    let mut semant = Semant::new();
+    semant.check(&ast);
+    diagnostics.extend(semant.errors().iter().map(Diagnostic::from));
+    super::compile::report(&sources, &diagnostics)?;
+
+    // Both runs read the same input. A terminal is not read, as nothing
+    // would end the input before the user typed an end of file.
+    let mut input = Vec::new();
+    if opts.path != "-" && !std::io::stdin().is_terminal() {
+        std::io::stdin()
+            .read_to_end(&mut input)
+            .map_err(|e| format!("could not read stdin: {e}"))?;
+    }
+    let o0 = ir::run(
+        semant.fragments(),
+        Passes::NONE,
+        &[],
+        &input,
+        opts.step_limit,
+    );
+    let o2_passes = Passes {
+        fuel: opts.fuel,
+        ..Passes::ALL
+    };
+    let o2 = ir::run(semant.fragments(), o2_passes, &[], &input, opts.step_limit);
+    print!("{}", table(&o0, &o2));
+    let summary = compare(&o0, &o2, opts.step_limit);
+    if summary.is_err() && opts.bisect && o0.ending != Ending::StepLimit {
+        let run = |fuel| {
+            let passes = Passes { fuel, ..o2_passes };
+            ir::run(semant.fragments(), passes, &[], &input, opts.step_limit)
+        };
+        let differs = |run: &Execution| run.output != o0.output || run.ending != o0.ending;
+        let first = bisect(o2.rewrites, |fuel| differs(&run(Some(fuel))));
+        println!("{}", culprit(first, o2.rewrites, &run(Some(first))));
+    }
+    println!("{}", summary?);
+    Ok(())
+}
+
+/// The smallest fuel up to `rewrites` at which `differs`, given that it
+/// does at `rewrites` and not at 0.
+pub(super) fn bisect(rewrites: u64, mut differs: impl FnMut(u64) -> bool) -> u64 {
+    let (mut same, mut different) = (0, rewrites);
+    while different - same > 1 {
+        let fuel = same + (different - same) / 2;
+        match differs(fuel) {
+            true => different = fuel,
+            false => same = fuel,
+        }
+    }
+    different
+}
+
+/// What `bisect` found: the rewrite `first` of `rewrites`, which `run` made
+/// last.
+fn culprit(first: u64, rewrites: u64, run: &Execution) -> String {
+    match run.last_rewrite {
+        Some((function, pass)) if first > 0 => format!(
+            "rewrite {first} of {rewrites}, by `{pass}` in `{function}`, \
+             is the first to change what the program does"
+        ),
+        _ => "the program changes with no rewrite made".to_string(),
+    }
+}
+
+/// The steps of every function at `-O0` and `-O2`, and what `-O2` saves.
+pub(super) fn table(o0: &Execution, o2: &Execution) -> String {
+    let rows: Vec<(String, u64, u64)> = o0
+        .steps
+        .iter()
+        .zip(&o2.steps)
+        .map(|(&(label, before), &(_, after))| (label.to_string(), before, after))
+        .chain([("total".to_string(), o0.total_steps(), o2.total_steps())])
+        .collect();
+    let width = rows.iter().map(|(name, ..)| name.len()).max().unwrap_or(0);
+    let mut out = format!(
+        "{:<width$}  {:>10}  {:>10}  saved\n",
+        "function", "-O0", "-O2"
+    );
+    for (name, before, after) in rows {
+        let saved = match before {
+            0 => "-".to_string(),
+            _ => format!(
+                "{:.1}%",
+                (before as f64 - after as f64) * 100.0 / before as f64
+            ),
+        };
+        let _ = writeln!(out, "{name:<width$}  {before:>10}  {after:>10}  {saved}");
+    }
+    out
+}
+
+/// What both runs did, or an error unless they printed the same and ended
+/// the same way.
+pub(super) fn compare(o0: &Execution, o2: &Execution, step_limit: u64) -> Result<String, String> {
+    if let Some(level) = [(o0, "-O0"), (o2, "-O2")]
+        .iter()
+        .find(|(run, _)| run.ending == Ending::StepLimit)
+        .map(|(_, level)| level)
+    {
+        return Err(format!(
+            "the run at `{level}` took more than {step_limit} steps"
+        ));
+    }
+    if o0.output != o2.output {
+        let common = o0.output.iter().zip(&o2.output).take_while(|(a, b)| a == b);
+        return Err(format!(
+            "`-O0` and `-O2` print different output, from byte {} on",
+            common.count()
+        ));
+    }
+    if o0.ending != o2.ending {
+        return Err(format!(
+            "`-O0` and `-O2` end differently: {} and {}",
+            ending(&o0.ending),
+            ending(&o2.ending)
+        ));
+    }
+    let bytes = o0.output.len();
+    Ok(format!(
+        "both runs printed the same {bytes} byte{} and {}",
+        if bytes == 1 { "" } else { "s" },
+        ending(&o0.ending)
+    ))
+}
+
+fn ending(ending: &Ending) -> String {
+    match ending {
+        Ending::Exit(code) => format!("exited with code {code}"),
+        Ending::Error(msg) => format!("failed with `{msg}`"),
+        Ending::StepLimit => "ran out of steps".to_string(),
+    }
+}
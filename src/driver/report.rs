@@ -0,0 +1,180 @@
+//! `--emit report`: a self-contained HTML page showing the source next to
+//! a tab for each phase.
+//!
+//! The tabs reuse the text dumps of the other `--emit` phases. Every line
+//! that ends in a span `@lo..hi` is linked to the source: hovering it
+//! highlights the tokens it covers, and hovering a token highlights the
+//! lines of the open tab that cover it. IR and assembly carry no spans, so
+//! each function is linked to its declaration as a whole.
+
+use std::fmt::Write;
+
+use crate::ast::Exp;
+use crate::codegen::emit;
+use crate::frame;
+use crate::lexer::TokenKind;
+use crate::opt::{Fuel, Passes};
+use crate::pretty;
+use crate::semant::Semant;
+use crate::source_map::SourceFile;
+use crate::span::Span;
+use crate::translate::Fragment;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 1em; }
+.panes { display: flex; gap: 1em; align-items: flex-start; }
+.panes > div { flex: 1; min-width: 0; }
+pre { background: #f6f6f6; padding: 0.5em; overflow: auto; }
+.tabs button { border: 1px solid #ccc; background: #eee; padding: 0.3em 0.8em; cursor: pointer; }
+.tabs button.active { background: #fff; border-bottom-color: #fff; font-weight: bold; }
+.tab { display: none; margin-top: 0; }
+.tab.active { display: block; }
+[data-lo] { cursor: default; }
+.hl { background: #ffe58a; }
+";
+
+const SCRIPT: &str = "
+const tokens = [...document.querySelectorAll('#source [data-lo]')];
+const span = e => [+e.dataset.lo, +e.dataset.hi];
+const clear = () => document.querySelectorAll('.hl').forEach(e => e.classList.remove('hl'));
+document.querySelectorAll('.tabs button').forEach(b => b.addEventListener('click', () => {
+  document.querySelectorAll('.tabs button, .tab').forEach(e => e.classList.remove('active'));
+  b.classList.add('active');
+  document.getElementById(b.dataset.tab).classList.add('active');
+}));
+document.querySelectorAll('.tab [data-lo]').forEach(line => {
+  line.addEventListener('mouseenter', () => {
+    const [lo, hi] = span(line);
+    line.classList.add('hl');
+    tokens.forEach(t => { const [l, h] = span(t); if (lo <= l && h <= hi) t.classList.add('hl'); });
+  });
+  line.addEventListener('mouseleave', clear);
+});
+tokens.forEach(t => {
+  t.addEventListener('mouseenter', () => {
+    const [l, h] = span(t);
+    t.classList.add('hl');
+    document.querySelectorAll('.tab.active [data-lo]').forEach(line => {
+      const [lo, hi] = span(line);
+      if (lo <= l && h <= hi) line.classList.add('hl');
+    });
+  });
+  t.addEventListener('mouseleave', clear);
+});
+";
+
+/// The report for a program that type checked.
+pub(super) fn html(file: &SourceFile, ast: &Exp, semant: &Semant) -> String {
+    let (tokens, _) = super::tokens::lex_all(file.src());
+    let name = escape(file.name());
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{name}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n<h1>{name}</h1>\n\
+         <div class=\"panes\">\n<div>\n<h2>source</h2>\n<pre id=\"source\">"
+    );
+    // The source is rebuilt from its tokens, so each can be highlighted.
+    let mut prev = 0;
+    for token in tokens.iter().filter(|t| t.kind() != &TokenKind::EOF) {
+        let pos = *token.pos();
+        out.push_str(&escape(&file.src()[prev..pos.lo() as usize]));
+        let _ = write!(
+            out,
+            "<span {}>{}</span>",
+            data(pos),
+            escape(file.span_to_snippet(pos))
+        );
+        prev = pos.hi() as usize;
+    }
+    out.push_str(&escape(&file.src()[prev..]));
+    out.push_str("</pre>\n</div>\n<div>\n<div class=\"tabs\">");
+
+    let mut types = String::new();
+    for d in semant.derivations() {
+        let _ = writeln!(
+            types,
+            "{}: {}  -- {} @{}..{}",
+            d.name,
+            d.ty,
+            d.rule,
+            d.pos.lo(),
+            d.pos.hi()
+        );
+    }
+    let (mut ir, mut asm) = (String::new(), String::new());
+    for fragment in semant.fragments() {
+        match fragment {
+            Fragment::Proc { body, frame } => {
+                let attrs = semant.proc_span(frame.name()).map(data).unwrap_or_default();
+                let _ = write!(ir, "<span {attrs}>{}</span>", escape(&fragment.to_string()));
+                let text = emit::function(body, frame, Passes::NONE, &mut Fuel::default());
+                let _ = write!(asm, "<span {attrs}>{}</span>", escape(&text));
+            }
+            Fragment::String(label, s) => {
+                ir.push_str(&escape(&fragment.to_string()));
+                asm.push_str(&escape(&frame::string(*label, s.as_bytes())));
+            }
+        }
+    }
+    let tabs = [
+        ("tokens", spanned_lines(&pretty::tokens(&tokens))),
+        ("ast", spanned_lines(&pretty::tree(ast))),
+        ("types", spanned_lines(&types)),
+        ("ir", ir),
+        ("asm", asm),
+    ];
+    for (i, (id, _)) in tabs.iter().enumerate() {
+        let active = if i == 0 { " class=\"active\"" } else { "" };
+        let _ = write!(out, "<button data-tab=\"{id}\"{active}>{id}</button>");
+    }
+    out.push_str("</div>\n");
+    for (i, (id, body)) in tabs.iter().enumerate() {
+        let active = if i == 0 { " active" } else { "" };
+        let _ = writeln!(out, "<pre class=\"tab{active}\" id=\"{id}\">{body}</pre>");
+    }
+    let _ = write!(
+        out,
+        "</div>\n</div>\n<script>{SCRIPT}</script>\n</body>\n</html>\n"
+    );
+    out
+}
+
+fn data(pos: Span) -> String {
+    format!("data-lo=\"{}\" data-hi=\"{}\"", pos.lo(), pos.hi())
+}
+
+/// Escapes `text` and links each line ending in a span `@lo..hi` to the
+/// source.
+fn spanned_lines(text: &str) -> String {
+    let mut out = String::new();
+    for line in text.lines() {
+        let span = line.rsplit_once(" @").and_then(|(_, span)| {
+            let (lo, hi) = span.split_once("..")?;
+            Some(Span::new(lo.parse().ok()?, hi.parse().ok()?))
+        });
+        match span {
+            Some(pos) => {
+                let _ = writeln!(out, "<span {}>{}</span>", data(pos), escape(line));
+            }
+            None => {
+                let _ = writeln!(out, "{}", escape(line));
+            }
+        }
+    }
+    out
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,80 @@
+//! `--size-report`: the instructions, spills and estimated machine code
+//! bytes of every function, as `codegen::size` counts them, in a table or
+//! as JSON.
+
+use std::fmt::Write;
+
+use crate::codegen::size::FunctionSize;
+use crate::json::Json;
+
+/// How `--size-report` prints.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum SizeReport {
+    Text,
+    Json,
+}
+
+impl SizeReport {
+    pub(super) fn parse(s: &str) -> Result<SizeReport, String> {
+        match s {
+            "text" => Ok(SizeReport::Text),
+            "json" => Ok(SizeReport::Json),
+            _ => Err(format!("unknown size report format `{s}`")),
+        }
+    }
+
+    pub(super) fn render(self, sizes: &[FunctionSize]) -> String {
+        match self {
+            SizeReport::Text => table(sizes),
+            SizeReport::Json => format!("{}\n", json(sizes)),
+        }
+    }
+}
+
+/// The sizes of every function and their total.
+fn total(sizes: &[FunctionSize]) -> [usize; 3] {
+    sizes.iter().fold([0; 3], |[i, s, b], f| {
+        [i + f.instructions, s + f.spills, b + f.bytes]
+    })
+}
+
+pub(super) fn table(sizes: &[FunctionSize]) -> String {
+    let rows: Vec<(String, [usize; 3])> = sizes
+        .iter()
+        .map(|f| (f.name.to_string(), [f.instructions, f.spills, f.bytes]))
+        .chain([("total".to_string(), total(sizes))])
+        .collect();
+    let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    let mut out = format!(
+        "{:<width$}  {:>12}  {:>6}  {:>6}\n",
+        "function", "instructions", "spills", "bytes"
+    );
+    for (name, [instructions, spills, bytes]) in rows {
+        let _ = writeln!(
+            out,
+            "{name:<width$}  {instructions:>12}  {spills:>6}  {bytes:>6}"
+        );
+    }
+    out
+}
+
+pub(super) fn json(sizes: &[FunctionSize]) -> Json {
+    let counts = |[instructions, spills, bytes]: [usize; 3]| {
+        [
+            ("instructions", Json::from(instructions as u32)),
+            ("spills", Json::from(spills as u32)),
+            ("bytes", Json::from(bytes as u32)),
+        ]
+    };
+    let functions = sizes.iter().map(|f| {
+        let name = [("function", Json::str(f.name.to_string()))];
+        Json::object(
+            name.into_iter()
+                .chain(counts([f.instructions, f.spills, f.bytes])),
+        )
+    });
+    Json::object([
+        ("functions", Json::Array(functions.collect())),
+        ("total", Json::object(counts(total(sizes)))),
+    ])
+}
@@ -0,0 +1,763 @@
+use std::time::Duration;
+
+use super::batch::{self, CheckOptions, Outcome};
+use super::bench::{self, BenchOptions, Stats};
+use super::build::{self, BuildOptions};
+use super::bundle;
+use super::compile::{self, CompileOptions, Emit, Input};
+use super::explain::explain;
+use super::lexdiff;
+use super::optdiff::{self, OptDiffOptions};
+use super::report;
+use super::sizes::SizeReport;
+use super::timings::{self, TimeOptions};
+use super::tokens::{render_json, render_table};
+use crate::codegen::size::FunctionSize;
+use crate::interp::ir::{Ending, Execution};
+use crate::json;
+use crate::opt::Passes;
+use crate::semant::Semant;
+use crate::source_map::{NewlinePolicy, SourceFile, SourceMap};
+use crate::span::Span;
+use crate::temp::Label;
+
+use crate::lexer::stream::tokenize as lex;
+
+#[test]
+fn token_table_is_aligned() {
+    let src = "let\n  var x := \"a\\tb\"\nin x end";
+    let table = render_table(&SourceFile::new("t.tig", src), &lex(src), false);
+    let expected = "\
+#  kind    span    line:col  lexeme
+0  LET     0..3    1:1       let
+1  VAR     6..9    2:3       var
+2  ID      10..11  2:7       x
+3  ASSIGN  12..14  2:9       :=
+4  STRING  15..21  2:12      \\\"a\\\\tb\\\"
+5  IN      22..24  3:1       in
+6  ID      25..26  3:4       x
+7  END     27..30  3:6       end
+8  EOF     30..30  3:9
+";
+    assert_eq!(table, expected);
+}
+
+#[test]
+fn token_json_has_one_object_per_line() {
+    let src = "a\n\"x\"";
+    let json = render_json(&SourceFile::new("t.tig", src), &lex(src));
+    let expected = r#"[
+  {"index":0,"kind":"ID","lo":0,"hi":1,"line":1,"col":1,"lexeme":"a"},
+  {"index":1,"kind":"STRING","lo":2,"hi":5,"line":2,"col":1,"lexeme":"\"x\""},
+  {"index":2,"kind":"EOF","lo":5,"hi":5,"line":2,"col":4,"lexeme":""}
+]
+"#;
+    assert_eq!(json, expected);
+}
+
+#[test]
+fn ast_json_nests_nodes_with_their_spans() {
+    let ast = crate::parser::parse("let var s: string := \"a\" in f(-1) end").unwrap();
+    let expected = concat!(
+        r#"{"kind":"Let","span":{"lo":0,"hi":37},"decs":["#,
+        r#"{"kind":"Var","span":{"lo":4,"hi":24},"name":"s","typ":{"name":"string","span":{"lo":11,"hi":17}},"#,
+        r#""init":{"kind":"String","span":{"lo":21,"hi":24},"value":"a"}}],"#,
+        r#""body":{"kind":"Call","span":{"lo":28,"hi":33},"func":"f","args":["#,
+        r#"{"kind":"Op","span":{"lo":30,"hi":32},"op":"-","#,
+        r#""left":{"kind":"Int","span":{"lo":30,"hi":31},"value":0},"#,
+        r#""right":{"kind":"Int","span":{"lo":31,"hi":32},"value":1}}]}}"#,
+    );
+    assert_eq!(crate::ast::json::exp(&ast).to_string(), expected);
+}
+
+#[test]
+fn ast_json_keeps_large_integers_exact() {
+    let ast = crate::parser::parse("9223372036854775807").unwrap();
+    let json = crate::ast::json::exp(&ast).to_string();
+    assert!(json.ends_with(r#""value":9223372036854775807}"#), "{json}");
+}
+
+#[test]
+fn compile_options() {
+    let args = |s: &str| s.split(' ').map(String::from).collect::<Vec<_>>();
+    let opts = CompileOptions::parse(&args("a.tig --emit typed-ast")).unwrap();
+    assert_eq!(opts.path.as_deref(), Some("a.tig"));
+    assert_eq!(opts.emit, Some(Emit::TypedAst));
+    let opts = CompileOptions::parse(&args("--emit=asm -")).unwrap();
+    assert_eq!(opts.path.as_deref(), Some("-"));
+    assert_eq!(opts.emit, Some(Emit::Asm));
+    let opts = CompileOptions::parse(&args("a.tig --emit ast-json")).unwrap();
+    assert_eq!(opts.emit, Some(Emit::AstJson));
+    let opts = CompileOptions::parse(&args("a.tig --emit=tokens-json")).unwrap();
+    assert_eq!(opts.emit, Some(Emit::TokensJson));
+    assert_eq!(CompileOptions::parse(&args("a.tig")).unwrap().emit, None);
+    let err = CompileOptions::parse(&args("a.tig --emit c")).err();
+    assert_eq!(err.as_deref(), Some("cannot emit `c`"));
+    let err = CompileOptions::parse(&args("a.tig b.tig")).err();
+    assert_eq!(err.as_deref(), Some("unexpected argument `b.tig`"));
+    let opts = CompileOptions::parse(&args("a.tig --newline crlf")).unwrap();
+    assert_eq!(opts.newline, NewlinePolicy::Crlf);
+    let err = CompileOptions::parse(&args("a.tig --newline=cr")).err();
+    assert_eq!(err.as_deref(), Some("unknown newline policy `cr`"));
+    let opts = CompileOptions::parse(&args("a.ast --from ast --emit sexp")).unwrap();
+    assert_eq!((opts.from, opts.emit), (Input::Ast, Some(Emit::Sexp)));
+    let err = CompileOptions::parse(&args("a.ast --from=ast --emit tokens")).err();
+    assert_eq!(
+        err.as_deref(),
+        Some("`--emit tokens` needs Tiger source, not `--from ast`")
+    );
+    let opts = CompileOptions::parse(&args("a.tig --emit bundle --out-dir=out")).unwrap();
+    assert_eq!(opts.emit, Some(Emit::Bundle));
+    assert_eq!(opts.out_dir.as_deref(), Some("out"));
+    let opts = CompileOptions::parse(&args("a.tig -W unused=off -Wunreachable=off")).unwrap();
+    assert!(!opts.lints.unused && !opts.lints.unreachable);
+    let err = CompileOptions::parse(&args("a.tig -W dead=off")).err();
+    assert_eq!(err.as_deref(), Some("unknown lint `dead`"));
+    let err = CompileOptions::parse(&args("a.tig --out-dir out")).err();
+    assert_eq!(
+        err.as_deref(),
+        Some("`--out-dir` is only used with `--emit bundle`")
+    );
+    let opts = CompileOptions::parse(&args("a.tig --size-report=json --opt-level 2")).unwrap();
+    assert_eq!(opts.size_report, Some(SizeReport::Json));
+    let err = CompileOptions::parse(&args("a.tig --size-report xml")).err();
+    assert_eq!(err.as_deref(), Some("unknown size report format `xml`"));
+    let err = CompileOptions::parse(&args("a.tig --size-report text --emit asm")).err();
+    assert_eq!(
+        err.as_deref(),
+        Some("`--size-report` cannot be combined with `--emit` or `--explain`")
+    );
+}
+
+#[test]
+fn size_reports_list_every_function_and_the_total() {
+    let size = |name: &str, instructions, spills, bytes| FunctionSize {
+        name: Label::named(name),
+        instructions,
+        spills,
+        bytes,
+    };
+    let sizes = [size("f.0", 12, 0, 40), size("tigermain", 30, 2, 101)];
+    let expected = "\
+function   instructions  spills   bytes
+f.0                  12       0      40
+tigermain            30       2     101
+total                42       2     141
+";
+    assert_eq!(SizeReport::Text.render(&sizes), expected);
+    let json = json::parse(SizeReport::Json.render(&sizes).trim()).unwrap();
+    let functions = json.get("functions").as_array();
+    assert_eq!(functions[1].get("function").as_str(), Some("tigermain"));
+    assert_eq!(functions[1].get("spills").as_u32(), Some(2));
+    assert_eq!(json.get("total").get("bytes").as_u32(), Some(141));
+}
+
+#[test]
+fn bundles_hold_every_artifact_and_a_manifest() {
+    let dir = std::env::temp_dir().join(format!("tigerc-bundle-{}", std::process::id()));
+    let src = "let function f(a: int): int = a + 1 * 2 in f(1) end";
+    let file = SourceFile::new("t.tig", src);
+    let ast = crate::parser::parse(src).unwrap();
+    bundle::write(
+        &SourceMap::from(file),
+        Some(&ast),
+        Vec::new(),
+        &dir,
+        Passes::ALL,
+    )
+    .unwrap();
+    let files: Vec<String> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    let manifest = std::fs::read_to_string(dir.join("manifest.json")).unwrap();
+    for name in [
+        "source.tig",
+        "tokens.json",
+        "ast.txt",
+        "ast.json",
+        "types.json",
+        "ir-0-translate.txt",
+        "ir-1-canon.txt",
+        "ir-2-fold-constants.txt",
+        "ir-5-remove-unreachable.txt",
+        "cfg.dot",
+        "sizes.json",
+        "asm.s",
+        "report.html",
+        "stats.json",
+    ] {
+        assert!(files.iter().any(|f| f == name), "{name} in {files:?}");
+        assert!(
+            manifest.contains(&format!("{{\"name\":\"{name}\"")),
+            "{name}"
+        );
+    }
+    assert!(manifest.contains("\"compiled\":true"), "{manifest}");
+    let folded = std::fs::read_to_string(dir.join("ir-2-fold-constants.txt")).unwrap();
+    assert!(folded.contains("CONST 2"), "{folded}");
+    let cfg = std::fs::read_to_string(dir.join("cfg.dot")).unwrap();
+    assert!(
+        cfg.starts_with("digraph cfg {\n  subgraph cluster_0 {"),
+        "{cfg}"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // A program that does not type check still gets its front end.
+    let src = "1 + \"s\"";
+    let file = SourceFile::new("t.tig", src);
+    let ast = crate::parser::parse(src).unwrap();
+    let err = bundle::write(
+        &SourceMap::from(file),
+        Some(&ast),
+        Vec::new(),
+        &dir,
+        Passes::NONE,
+    )
+    .err();
+    assert_eq!(err.as_deref(), Some("aborting due to 1 error"));
+    let manifest = std::fs::read_to_string(dir.join("manifest.json")).unwrap();
+    assert!(manifest.contains("\"compiled\":false"), "{manifest}");
+    assert!(dir.join("diagnostics.txt").exists() && !dir.join("asm.s").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn explain_walks_through_every_phase() {
+    let src = "let var n := 2 in n * 3 end";
+    let file = SourceFile::new("t.tig", src);
+    let ast = crate::parser::parse(src).unwrap();
+    let mut semant = Semant::new();
+    let ty = semant.check(&ast);
+    let text = explain(&SourceMap::from(file), &ast, &semant, ty);
+    let titles: Vec<&str> = text.lines().filter(|l| l.starts_with("== ")).collect();
+    assert_eq!(
+        titles,
+        [
+            "== 1. tokens ==",
+            "== 2. abstract syntax ==",
+            "== 3. types ==",
+            "== 4. intermediate representation ==",
+            "== 5. canonical trees and instruction selection ==",
+            "== 6. assembly ==",
+        ]
+    );
+    assert!(text.contains("1:5  n: int  -- inferred from the initializer\n"));
+    assert!(text.contains("The whole program has type `int`."));
+    assert!(text.contains("    => imulq $3, "), "{text}");
+    assert!(text.contains("tigermain:\n"));
+
+    let args = |s: &str| s.split(' ').map(String::from).collect::<Vec<_>>();
+    assert!(
+        CompileOptions::parse(&args("a.tig --explain"))
+            .unwrap()
+            .explain
+    );
+    let err = CompileOptions::parse(&args("a.tig --explain --emit ir")).err();
+    assert_eq!(
+        err.as_deref(),
+        Some("`--explain` cannot be combined with `--emit`")
+    );
+}
+
+#[test]
+fn report_links_every_phase_to_the_source() {
+    let src = "let function f(a: int): int = a in f(1) < 2 end";
+    let file = SourceFile::new("<t>.tig", src);
+    let ast = crate::parser::parse(src).unwrap();
+    let mut semant = Semant::new();
+    semant.check(&ast);
+    let page = report::html(&file, &ast, &semant);
+    assert!(page.starts_with("<!DOCTYPE html>"));
+    assert!(page.contains("<title>&lt;t&gt;.tig</title>"));
+    // Source tokens and the lines that cover them.
+    assert!(page.contains("<span data-lo=\"40\" data-hi=\"41\">&lt;</span>"));
+    assert!(page.contains("<span data-lo=\"35\" data-hi=\"43\">  Op &lt; @35..43</span>"));
+    assert!(page.contains("<span data-lo=\"4\" data-hi=\"31\">f: (int) -&gt; int"));
+    // Functions in the IR and assembly tabs are linked to their declaration.
+    let ir = &page[page.find("id=\"ir\"").unwrap()..];
+    assert!(ir.starts_with("id=\"ir\"><span data-lo=\"4\" data-hi=\"31\">PROC f."));
+    let asm = &page[page.find("id=\"asm\"").unwrap()..];
+    assert!(asm.contains("data-hi=\"47\">\t.globl tigermain"), "{asm}");
+    for tab in ["tokens", "ast", "types", "ir", "asm"] {
+        assert!(page.contains(&format!("<button data-tab=\"{tab}\"")));
+    }
+}
+
+#[test]
+fn lexdiff_shows_the_first_differing_token() {
+    let src = "let var x := 1 in x end";
+    let file = SourceFile::new("t.tig", src);
+    let snapshot = lexdiff::parse_snapshot(&render_json(&file, &lex(src))).unwrap();
+    assert_eq!(snapshot.len(), 9);
+    assert_eq!(lexdiff::diff(&file, &snapshot, &lex(src)), None);
+
+    let changed = "let var x := 12 in x end";
+    let file = SourceFile::new("t.tig", changed);
+    let diag = lexdiff::diff(&file, &snapshot, &lex(changed)).unwrap();
+    assert_eq!(diag.msg, "token #4 differs from the snapshot");
+    assert_eq!(
+        diag.notes,
+        [
+            "snapshot: INT at 13..14: \"1\"",
+            "current:  INT at 13..15: \"12\""
+        ]
+    );
+    assert_eq!(diag.labels[0].pos, Span::new(10, 12));
+
+    let shorter = lexdiff::diff(&file, &snapshot[..3], &lex(changed)).unwrap();
+    assert_eq!(shorter.notes[0], "snapshot: none");
+    let err = lexdiff::parse_snapshot("[{\"kind\": \"ID\"}]").unwrap_err();
+    assert_eq!(err, "token #0 has no `lo`");
+}
+
+#[test]
+fn formatting_keeps_the_line_endings_of_the_file() {
+    let formatted = crate::fmt::format_source("let var x := 1 in x end").unwrap();
+    let path = std::env::temp_dir().join(format!("tigerc-fmt-{}.tig", std::process::id()));
+    std::fs::write(&path, formatted.replace('\n', "\r\n")).unwrap();
+    let args = |flags: &str| {
+        let path = path.display().to_string();
+        std::iter::once(path)
+            .chain(flags.split_whitespace().map(String::from))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(compile::format(&args("--check")), Ok(()));
+    assert_eq!(compile::format(&args("--check --newline crlf")), Ok(()));
+    let err = compile::format(&args("--newline=lf --check")).err();
+    assert!(err.is_some_and(|e| e.ends_with("is not formatted")));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn check_options() {
+    let args = |s: &str| s.split(' ').map(String::from).collect::<Vec<_>>();
+    let opts = CheckOptions::parse(&args("a.tig dir --backend --opt-level=2")).unwrap();
+    assert_eq!(opts.paths, ["a.tig", "dir"]);
+    assert!(opts.backend);
+    assert_eq!(opts.passes, Passes::ALL);
+    let err = CheckOptions::parse(&args("--backend")).err();
+    assert_eq!(err.as_deref(), Some("`check` expects files or directories"));
+}
+
+#[test]
+fn batches_survive_files_that_crash_the_compiler() {
+    let dir = std::env::temp_dir().join(format!("tigerc-check-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("nested")).unwrap();
+    for name in ["b.tig", "a.tig", "nested/c.tig", "notes.txt"] {
+        std::fs::write(dir.join(name), "").unwrap();
+    }
+    let files = batch::expand(&[dir.display().to_string(), "x.tig".to_string()]).unwrap();
+    let names: Vec<&str> = files
+        .iter()
+        .map(|f| f.strip_prefix(&*dir.display().to_string()).unwrap_or(f))
+        .collect();
+    assert_eq!(names, ["/a.tig", "/b.tig", "/nested/c.tig", "x.tig"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let files: Vec<String> = ["ok.tig", "ice.tig", "bad.tig", "ok2.tig"]
+        .map(String::from)
+        .to_vec();
+    let outcomes = batch::check_all(&files, |path| match path {
+        "ice.tig" => panic!("no register for t7"),
+        "bad.tig" => Err("aborting due to 2 errors".to_string()),
+        _ => Ok(()),
+    });
+    assert_eq!(
+        outcomes,
+        [
+            Outcome::Passed,
+            Outcome::Crashed("no register for t7".to_string()),
+            Outcome::Failed("aborting due to 2 errors".to_string()),
+            Outcome::Passed,
+        ]
+    );
+    let expected = "\
+checked 4 files: 2 passed, 1 failed, 1 crashed
+crashed:
+    ice.tig: no register for t7
+failed:
+    bad.tig: aborting due to 2 errors
+";
+    assert_eq!(batch::summary(&files, &outcomes), expected);
+}
+
+#[test]
+fn imports_are_resolved_relative_to_the_importing_file() {
+    let dir = std::env::temp_dir().join(format!("tigerc-imports-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    let files = [
+        (
+            "main.tig",
+            "let import \"lib/math.tig\" import \"lib/twice.tig\" in twice(square(3)) end",
+        ),
+        ("lib/math.tig", "function square(n: int): int = n * n"),
+        // Already imported by main.tig, so it adds nothing again.
+        (
+            "lib/twice.tig",
+            "import \"math.tig\" function twice(n: int): int = n + n",
+        ),
+        ("cycle.tig", "let import \"a.tig\" in 0 end"),
+        ("a.tig", "import \"b.tig\" var a := 1"),
+        ("b.tig", "import \"a.tig\" var b := 2"),
+        ("clash.tig", "let import \"a2.tig\" var a := 3 in a end"),
+        ("a2.tig", "var a := 1"),
+        ("missing.tig", "let import \"nope.tig\" in 0 end"),
+    ];
+    for (name, src) in files {
+        std::fs::write(dir.join(name), src).unwrap();
+    }
+    let path = |name: &str| dir.join(name).display().to_string();
+    let messages = |diagnostics: &[crate::diagnostics::Diagnostic]| -> Vec<String> {
+        let lang = crate::diagnostics::Lang::English;
+        diagnostics.iter().map(|d| d.msg.render(lang)).collect()
+    };
+
+    let (sources, ast, diagnostics) = super::parse_file(&path("main.tig")).unwrap();
+    assert_eq!(messages(&diagnostics), Vec::<String>::new());
+    let ast = ast.unwrap();
+    let mut semant = Semant::new();
+    semant.check(&ast);
+    assert!(semant.errors().is_empty(), "{:?}", semant.errors());
+    let mut out = Vec::new();
+    crate::interp::run(&ast, &[], &mut std::io::empty(), &mut out).unwrap();
+    let names: Vec<&str> = (0..3)
+        .map(|i| sources.get(crate::span::FileId::new(i)).name().as_str())
+        .collect();
+    assert!(names[1].ends_with("lib/math.tig") && names[2].ends_with("lib/twice.tig"));
+
+    let (_, _, diagnostics) = super::parse_file(&path("cycle.tig")).unwrap();
+    assert_eq!(
+        messages(&diagnostics),
+        ["import cycle: `a.tig` ends up importing itself"]
+    );
+    assert_eq!(diagnostics[0].labels.len(), 1);
+
+    let (sources, _, diagnostics) = super::parse_file(&path("clash.tig")).unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    let rendered = crate::diagnostics::Renderer::new(false).render_in(&sources, &diagnostics[0]);
+    assert!(rendered.contains("`a` is also declared in `"), "{rendered}");
+    assert!(rendered.contains("a2.tig:1:1"), "{rendered}");
+
+    let (_, _, diagnostics) = super::parse_file(&path("missing.tig")).unwrap();
+    assert!(messages(&diagnostics)[0].starts_with("cannot import `nope.tig`: "));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn build_options() {
+    let args = |s: &str| s.split(' ').map(String::from).collect::<Vec<_>>();
+    let opts = BuildOptions::parse(&args("dir/a.tig --opt-level=2")).unwrap();
+    assert_eq!(opts.output, "dir/a");
+    assert_eq!(opts.passes, Passes::ALL);
+    assert_eq!(opts.backend, build::Backend::Native);
+    let opts = BuildOptions::parse(&args("a.tig --backend llvm")).unwrap();
+    assert_eq!(opts.backend, build::Backend::Llvm);
+    let err = BuildOptions::parse(&args("a.tig --backend=gcc")).err();
+    assert_eq!(err.as_deref(), Some("unknown backend `gcc`"));
+    let opts = BuildOptions::parse(&args("- -o a --runtime rt.a")).unwrap();
+    assert_eq!(opts.runtime.as_deref(), Some(std::path::Path::new("rt.a")));
+    assert_eq!(BuildOptions::parse(&args("a")).unwrap().output, "a.out");
+    let err = BuildOptions::parse(&args("-")).err();
+    assert_eq!(err.as_deref(), Some("`build` needs `-o` to read stdin"));
+    // Fuel outlasts a later `--opt-level`.
+    let opts = BuildOptions::parse(&args("a.tig --opt-fuel 7 --opt-level=2")).unwrap();
+    assert_eq!(opts.passes.fuel, Some(7));
+    let err = BuildOptions::parse(&args("a.tig --opt-fuel=-1")).err();
+    assert_eq!(err.as_deref(), Some("invalid optimization fuel `-1`"));
+}
+
+#[test]
+fn opt_diff_options() {
+    let args = |s: &str| s.split(' ').map(String::from).collect::<Vec<_>>();
+    let opts = OptDiffOptions::parse(&args("a.tig --step-limit=500")).unwrap();
+    assert_eq!((opts.path.as_str(), opts.step_limit), ("a.tig", 500));
+    let err = OptDiffOptions::parse(&args("a.tig --step-limit lots")).err();
+    assert_eq!(err.as_deref(), Some("invalid step limit `lots`"));
+    let err = OptDiffOptions::parse(&args("--step-limit 5")).err();
+    assert_eq!(err.as_deref(), Some("`opt-diff` expects a file name"));
+    let opts = OptDiffOptions::parse(&args("a.tig --bisect-opt --opt-fuel=3")).unwrap();
+    assert_eq!((opts.bisect, opts.fuel), (true, Some(3)));
+}
+
+#[test]
+fn bisecting_finds_the_first_rewrite_that_changes_a_run() {
+    for first in 1..=20 {
+        let mut runs = 0;
+        let found = optdiff::bisect(20, |fuel| {
+            runs += 1;
+            fuel >= first
+        });
+        assert_eq!(found, first);
+        assert!(runs <= 5, "{runs} runs to find {first}");
+    }
+    assert_eq!(optdiff::bisect(1, |_| unreachable!()), 1);
+}
+
+#[test]
+fn opt_diff_compares_runs() {
+    let run = |output: &str, ending: Ending, steps: &[u64]| Execution {
+        output: output.as_bytes().to_vec(),
+        ending,
+        steps: ["f", "tigermain"]
+            .iter()
+            .map(|name| Label::named(name))
+            .zip(steps.iter().copied())
+            .collect(),
+        rewrites: 0,
+        last_rewrite: None,
+    };
+    let o0 = run("hi", Ending::Exit(0), &[200, 10]);
+    let o2 = run("hi", Ending::Exit(0), &[150, 10]);
+    let expected = "\
+function          -O0         -O2  saved
+f                 200         150  25.0%
+tigermain          10          10  0.0%
+total             210         160  23.8%
+";
+    assert_eq!(optdiff::table(&o0, &o2), expected);
+    assert_eq!(
+        optdiff::compare(&o0, &o2, 1000).as_deref(),
+        Ok("both runs printed the same 2 bytes and exited with code 0")
+    );
+    let wrong = run("ho", Ending::Exit(0), &[150, 10]);
+    assert_eq!(
+        optdiff::compare(&o0, &wrong, 1000).err().as_deref(),
+        Some("`-O0` and `-O2` print different output, from byte 1 on")
+    );
+    let failed = run(
+        "hi",
+        Ending::Error("division by zero".to_string()),
+        &[150, 10],
+    );
+    assert_eq!(
+        optdiff::compare(&o0, &failed, 1000).err().as_deref(),
+        Some("`-O0` and `-O2` end differently: exited with code 0 and failed with `division by zero`")
+    );
+    let endless = run("hi", Ending::StepLimit, &[990, 10]);
+    assert_eq!(
+        optdiff::compare(&endless, &o2, 1000).err().as_deref(),
+        Some("the run at `-O0` took more than 1000 steps")
+    );
+}
+
+#[test]
+fn time_options() {
+    let args = |s: &str| s.split(' ').map(String::from).collect::<Vec<_>>();
+    let opts = TimeOptions::parse(&args("a.tig b.tig --repeat=3 --opt-level 2")).unwrap();
+    assert_eq!(opts.paths, ["a.tig", "b.tig"]);
+    assert_eq!((opts.repeat, opts.passes), (3, Passes::ALL));
+    assert_eq!(TimeOptions::parse(&args("a.tig")).unwrap().repeat, 10);
+    let err = TimeOptions::parse(&args("a.tig --repeat 0")).err();
+    assert_eq!(err.as_deref(), Some("invalid repeat count `0`"));
+    let err = TimeOptions::parse(&args("--repeat 2")).err();
+    assert_eq!(err.as_deref(), Some("`time` expects file names"));
+}
+
+#[test]
+fn timings_cover_every_phase() {
+    let src = "let function f(n: int): int = if n = 0 then 1 else n * f(n - 1) in f(5) end";
+    let files = [SourceFile::new("f.tig", src)];
+    let times = timings::measure(&files, 2, Passes::ALL).unwrap();
+    assert_eq!(times.len(), timings::PHASES.len());
+    let opts = TimeOptions::parse(&["f.tig".to_string()]).unwrap();
+    let json = timings::json(&opts, &times);
+    assert_eq!(json.get("files").as_u32(), Some(1));
+    for phase in timings::PHASES {
+        assert!(json.get("phases").get(phase).as_u32().is_some(), "{phase}");
+    }
+    let broken = [SourceFile::new("b.tig", "1 + \"a\"")];
+    assert_eq!(
+        timings::measure(&broken, 1, Passes::NONE).err().as_deref(),
+        Some("`b.tig` has errors; `time` needs programs that compile")
+    );
+}
+
+/// The newest runtime library Cargo built for the tests.
+fn runtime_library() -> std::path::PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    let deps = exe.parent().unwrap();
+    let libs = std::fs::read_dir(deps).unwrap().filter_map(|entry| {
+        let path = entry.ok()?.path();
+        let name = path.file_name()?.to_str()?;
+        if !name.starts_with("libtiger_runtime") || !name.ends_with(".a") {
+            return None;
+        }
+        Some((path.metadata().ok()?.modified().ok()?, path))
+    });
+    libs.max()
+        .map(|(_, path)| path)
+        .expect("the runtime library is built with the workspace's tests")
+}
+
+#[test]
+fn built_programs_run_with_the_runtime() {
+    let dir = std::env::temp_dir().join(format!("tigerc-build-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let src = dir.join("p.tig");
+    std::fs::write(
+        &src,
+        "let
+            type list = {head: int, tail: list}
+            type intArray = array of int
+            var a := intArray [3] of 7
+            function sum(l: list): int = if l = nil then 0 else l.head + sum(l.tail)
+            var l := list {head = 1, tail = list {head = 2, tail = nil}}
+            var min := -9223372036854775807 - 1
+            var d := -1
+        in
+            print(concat(\"sum \", chr(ord(\"0\") + sum(l))));
+            if \"abc\" < \"abd\" & not(0) then print(substring(\" hey \", 1, 3));
+            print(chr(ord(\"0\") + size(\"four\") + a[2]));
+            print(itoa(atoi(\"-12\", 0) * 2));
+            print(argv(argc() - 1));
+            seed(42);
+            print(itoa(random(100)));
+            if min / d = min then print(\"!\");
+            a[3] := 0
+        end",
+    )
+    .unwrap();
+    let exe = dir.join("p");
+    let runtime = runtime_library();
+    let backends: &[&str] = match cfg!(feature = "llvm") {
+        true => &["native", "llvm"],
+        false => &["native"],
+    };
+    let builds = backends
+        .iter()
+        .flat_map(|backend| ["0", "2"].map(|level| (backend, level)));
+    for (backend, level) in builds {
+        let args = [
+            src.to_str().unwrap(),
+            "-o",
+            exe.to_str().unwrap(),
+            "--runtime",
+            runtime.to_str().unwrap(),
+            "--opt-level",
+            level,
+            "--backend",
+            backend,
+        ];
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        build::run(&args).unwrap();
+        let out = std::process::Command::new(&exe)
+            .args(["a", "z"])
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&out.stdout), "sum 3hey;-24z13!");
+        assert_eq!(
+            String::from_utf8_lossy(&out.stderr),
+            "error: index 3 is out of bounds\n"
+        );
+        assert_eq!(out.status.code(), Some(1));
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn built_programs_count_the_heap_as_the_interpreter_does() {
+    let dir = std::env::temp_dir().join(format!("tigerc-heap-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let src = dir.join("p.tig");
+    // The program `interp::tests` runs with the same counts.
+    std::fs::write(
+        &src,
+        "let
+            type r = {a: int, b: int}
+            var x := r {a = 1, b = 2}
+            var s := concat(\"ab\", itoa(12345))
+        in
+            gc_collect();
+            print(itoa(heap_used()))
+        end",
+    )
+    .unwrap();
+    let exe = dir.join("p");
+    let runtime = runtime_library();
+    let args = [
+        src.to_str().unwrap(),
+        "-o",
+        exe.to_str().unwrap(),
+        "--runtime",
+        runtime.to_str().unwrap(),
+    ];
+    let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+    build::run(&args).unwrap();
+    let run = |limit: &str| {
+        let out = std::process::Command::new(&exe)
+            .env("TIGER_HEAP_SIZE", limit)
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&out.stdout).into_owned();
+        (stdout, String::from_utf8_lossy(&out.stderr).into_owned())
+    };
+    assert_eq!(run("1k"), ("48".to_string(), String::new()));
+    assert_eq!(
+        run("63"),
+        (String::new(), "error: out of memory\n".to_string())
+    );
+    let bad = "error: invalid TIGER_HEAP_SIZE `1kb`\n";
+    assert_eq!(run("1kb"), (String::new(), bad.to_string()));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn bench_options() {
+    let args = |s: &str| s.split(' ').map(String::from).collect::<Vec<_>>();
+    let opts = BenchOptions::parse(&args("a.tig --runs=3 --opt-level 2 -- x --y")).unwrap();
+    assert_eq!((opts.path.as_str(), opts.runs), ("a.tig", 3));
+    assert_eq!((opts.passes, opts.args), (Passes::ALL, args("x --y")));
+    assert_eq!(BenchOptions::parse(&args("a.tig")).unwrap().runs, 10);
+    let err = BenchOptions::parse(&args("a.tig --runs 0")).err();
+    assert_eq!(err.as_deref(), Some("invalid number of runs `0`"));
+    let err = BenchOptions::parse(&args("--runs 2")).err();
+    assert_eq!(err.as_deref(), Some("`bench` expects a file name"));
+}
+
+#[test]
+fn bench_reports_the_spread_of_the_runs() {
+    let ms = Duration::from_millis;
+    let stats = Stats::of(&[ms(4), ms(1), ms(3), ms(2)]);
+    assert_eq!(
+        (stats.min, stats.median, stats.mean, stats.max),
+        (
+            ms(1),
+            Duration::from_micros(2500),
+            Duration::from_micros(2500),
+            ms(4)
+        )
+    );
+    assert_eq!(stats.deviation.as_micros(), 1118);
+    let expected = "\
+4 runs, exit code 0
+min         1.000 ms
+median      2.500 ms
+mean        2.500 ms
+max         4.000 ms
+std dev     1.118 ms
+";
+    assert_eq!(bench::report(&stats, 0), expected);
+    assert_eq!(Stats::of(&[ms(5)]).median, ms(5));
+}
+
+#[test]
+fn bench_builds_and_runs_the_program() {
+    let src = std::env::temp_dir().join(format!("tigerc-bench-{}.tig", std::process::id()));
+    std::fs::write(
+        &src,
+        "let var t := clock() in exit(if clock() >= t then 3 else 4) end",
+    )
+    .unwrap();
+    let runtime = runtime_library();
+    let args = [
+        src.to_str().unwrap(),
+        "--runs",
+        "2",
+        "--runtime",
+        runtime.to_str().unwrap(),
+    ];
+    let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+    assert_eq!(bench::run(&args), Ok(()));
+    std::fs::remove_file(&src).unwrap();
+}
@@ -0,0 +1,195 @@
+//! `tigerc time`: compiles programs to assembly and reports how long each
+//! phase took, as JSON, for `cargo xtask perfcheck` to compare with a
+//! baseline. Each phase is the fastest of `--repeat` runs over all the
+//! files, which is steadier than the mean on a busy machine.
+
+use std::time::{Duration, Instant};
+
+use crate::canon;
+use crate::codegen::{assem, codegen, emit};
+use crate::diagnostics::Diagnostic;
+use crate::frame;
+use crate::json::Json;
+use crate::lexer::{LexerConfig, StringReader, TokenKind};
+use crate::opt::{self, Fuel, Passes};
+use crate::parser;
+use crate::regalloc;
+use crate::semant::Semant;
+use crate::source_map::SourceFile;
+use crate::temp::Temp;
+use crate::translate::Fragment;
+
+/// The phases timed, in pipeline order. Parsing lexes too, so `parse`
+/// includes the time of `lex`.
+pub(super) const PHASES: &[&str] = &[
+    "lex", "parse", "check", "canon", "opt", "select", "regalloc", "emit",
+];
+
+#[derive(Debug, PartialEq)]
+pub(super) struct TimeOptions {
+    pub(super) paths: Vec<String>,
+    pub(super) repeat: u32,
+    pub(super) passes: Passes,
+}
+
+impl TimeOptions {
+    pub(super) fn parse(args: &[String]) -> Result<TimeOptions, String> {
+        let mut paths = Vec::new();
+        let mut repeat = 10;
+        let mut passes = Passes::NONE;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--repeat" => {
+                    let n = args.next().ok_or("`--repeat` expects a number")?;
+                    repeat = parse_repeat(n)?;
+                }
+                flag if flag.starts_with("--repeat=") => {
+                    repeat = parse_repeat(&flag["--repeat=".len()..])?;
+                }
+                "--opt-level" => {
+                    let level = args.next().ok_or("`--opt-level` expects an argument")?;
+                    passes = Passes::for_level(level)?;
+                }
+                flag if flag.starts_with("--opt-level=") => {
+                    passes = Passes::for_level(&flag["--opt-level=".len()..])?;
+                }
+                flag if flag.starts_with('-') => return Err(format!("unknown option `{flag}`")),
+                file => paths.push(file.to_string()),
+            }
+        }
+        if paths.is_empty() {
+            return Err("`time` expects file names".to_string());
+        }
+        Ok(TimeOptions {
+            paths,
+            repeat,
+            passes,
+        })
+    }
+}
+
+fn parse_repeat(n: &str) -> Result<u32, String> {
+    match n.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("invalid repeat count `{n}`")),
+    }
+}
+
+pub(super) fn run(args: &[String]) -> Result<(), String> {
+    let opts = TimeOptions::parse(args)?;
+    let mut files = Vec::new();
+    for path in &opts.paths {
+        let (file, _) = super::read_source(path)?;
+        files.push(file);
+    }
+    let times = measure(&files, opts.repeat, opts.passes)?;
+    println!("{}", json(&opts, &times));
+    Ok(())
+}
+
+/// The time of each phase of `PHASES` over all of `files`, the fastest of
+/// `repeat` runs.
+pub(super) fn measure(
+    files: &[SourceFile],
+    repeat: u32,
+    passes: Passes,
+) -> Result<Vec<Duration>, String> {
+    let mut best = vec![Duration::MAX; PHASES.len()];
+    for _ in 0..repeat {
+        let mut total = vec![Duration::ZERO; PHASES.len()];
+        for file in files {
+            time(file, passes, &mut total)?;
+        }
+        for (best, total) in best.iter_mut().zip(total) {
+            *best = (*best).min(total);
+        }
+    }
+    Ok(best)
+}
+
+/// `{"files": n, "repeat": n, "phases": {"lex": µs, ...}}`.
+pub(super) fn json(opts: &TimeOptions, times: &[Duration]) -> Json {
+    let phases = PHASES
+        .iter()
+        .zip(times)
+        .map(|(&phase, time)| (phase, Json::Int(time.as_micros() as i64)));
+    Json::object([
+        ("files", Json::Int(opts.paths.len() as i64)),
+        ("repeat", Json::Int(opts.repeat as i64)),
+        ("phases", Json::object(phases)),
+    ])
+}
+
+/// Compiles `file`, adding the time of each phase to `total`.
+fn time(file: &SourceFile, passes: Passes, total: &mut [Duration]) -> Result<(), String> {
+    let mut phase = 0;
+    let mut timed = |phase: usize, start: Instant| total[phase] += start.elapsed();
+
+    let start = Instant::now();
+    let mut reader = StringReader::new(file.src()).with_config(LexerConfig::PARSER);
+    while reader.next_token().kind() != &TokenKind::EOF {}
+    timed(phase, start);
+    phase += 1;
+
+    let start = Instant::now();
+    let parsed = parser::parse_reporting(file.src());
+    timed(phase, start);
+    phase += 1;
+    let failed = || {
+        format!(
+            "`{}` has errors; `time` needs programs that compile",
+            file.name()
+        )
+    };
+    let ast = match parsed.ast {
+        Some(ast) if !parsed.diagnostics.iter().any(Diagnostic::is_error) => ast,
+        _ => return Err(failed()),
+    };
+
+    let start = Instant::now();
+    let mut semant = Semant::new();
+    semant.check(&ast);
+    timed(phase, start);
+    phase += 1;
+    if !semant.errors().is_empty() {
+        return Err(failed());
+    }
+
+    // The steps of `emit::function`, timed one by one.
+    for fragment in semant.fragments() {
+        let Fragment::Proc { body, frame } = fragment else {
+            continue;
+        };
+        let mut phase = phase;
+        let start = Instant::now();
+        let (blocks, done) = canon::basic_blocks(canon::linearize(body.clone()));
+        let stms = canon::trace_schedule(blocks, done);
+        timed(phase, start);
+        phase += 1;
+
+        let start = Instant::now();
+        let stms = opt::optimize(stms, passes, &mut Fuel::new(passes));
+        timed(phase, start);
+        phase += 1;
+
+        let start = Instant::now();
+        let instrs = codegen(stms);
+        timed(phase, start);
+        phase += 1;
+
+        let start = Instant::now();
+        let mut frame = frame.clone();
+        let mut alloc = regalloc::alloc(frame::proc_entry_exit2(instrs), &mut frame);
+        alloc.remove_redundant_moves();
+        timed(phase, start);
+        phase += 1;
+
+        let start = Instant::now();
+        let proc = frame::proc_entry_exit3(&frame, alloc.instrs);
+        let name = |t: Temp| assem::temp_name(alloc.registers.get(&t).copied().unwrap_or(t));
+        std::hint::black_box(emit::proc_text(&proc, &name));
+        timed(phase, start);
+    }
+    Ok(())
+}
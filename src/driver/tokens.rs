@@ -0,0 +1,186 @@
+use std::fmt::Write;
+
+use crate::diagnostics::Diagnostic;
+use crate::json::Json;
+use crate::lexer::{LexError, LexerConfig, StringReader, Token, TokenKind};
+use crate::source_map::{SourceFile, SourceMap};
+
+struct TokensOptions<'a> {
+    path: &'a str,
+    json: bool,
+    color: bool,
+    /// Also list whitespace, so that the tokens cover the whole file.
+    lossless: bool,
+}
+
+impl<'a> TokensOptions<'a> {
+    fn parse(args: &'a [String]) -> Result<TokensOptions<'a>, String> {
+        let mut path = None;
+        let mut json = false;
+        let mut color = false;
+        let mut lossless = false;
+        for arg in args {
+            match arg.as_str() {
+                "--json" => json = true,
+                "--color" => color = true,
+                "--lossless" => lossless = true,
+                flag if flag.starts_with("--") => {
+                    return Err(format!("unknown option `{flag}` for `tokens`"))
+                }
+                file if path.is_none() => path = Some(file),
+                extra => return Err(format!("unexpected argument `{extra}`")),
+            }
+        }
+        let path = path.ok_or("`tokens` expects a file name")?;
+        Ok(TokensOptions {
+            path,
+            json,
+            color,
+            lossless,
+        })
+    }
+}
+
+pub(super) fn run(args: &[String]) -> Result<(), String> {
+    let opts = TokensOptions::parse(args)?;
+    let (file, decode_errors) = super::read_source(opts.path)?;
+    let config = match opts.lossless {
+        true => LexerConfig::LOSSLESS,
+        false => LexerConfig::default(),
+    };
+    let (tokens, lex_errors) = lex_with(file.src(), config);
+    let mut errors =
+        super::decode_diagnostics(&decode_errors, lex_errors.iter().map(Diagnostic::from));
+    errors.sort_by_key(|d| d.pos.lo());
+    if opts.json {
+        print!("{}", render_json(&file, &tokens));
+    } else {
+        print!("{}", render_table(&file, &tokens, opts.color));
+    }
+    match super::print_diagnostics(&SourceMap::from(file), &errors) {
+        0 => Ok(()),
+        1 => Err("1 lexical error".to_string()),
+        n => Err(format!("{n} lexical errors")),
+    }
+}
+
+/// Lexes the whole source, keeping the trailing `EOF` token.
+pub(super) fn lex_all(src: &str) -> (Vec<Token>, Vec<LexError>) {
+    lex_with(src, LexerConfig::default())
+}
+
+fn lex_with(src: &str, config: LexerConfig) -> (Vec<Token>, Vec<LexError>) {
+    let mut sr = StringReader::new(src).with_config(config);
+    let tokens = sr.by_ref().collect();
+    (tokens, sr.errors().to_vec())
+}
+
+/// One printable row of the token listing.
+struct Row {
+    index: usize,
+    kind: String,
+    span: String,
+    line_col: String,
+    lexeme: String,
+}
+
+fn rows(file: &SourceFile, tokens: &[Token]) -> Vec<Row> {
+    tokens
+        .iter()
+        .enumerate()
+        .map(|(index, token)| {
+            let (lo, hi) = (token.pos().lo(), token.pos().hi());
+            let (line, col) = file.lookup_line_col(lo);
+            Row {
+                index,
+                kind: format!("{:?}", token.kind()),
+                span: format!("{lo}..{hi}"),
+                line_col: format!("{line}:{col}"),
+                lexeme: file
+                    .span_to_snippet(*token.pos())
+                    .escape_debug()
+                    .to_string(),
+            }
+        })
+        .collect()
+}
+
+pub(super) fn render_table(file: &SourceFile, tokens: &[Token], color: bool) -> String {
+    let rows = rows(file, tokens);
+    let index_w = rows
+        .iter()
+        .map(|r| r.index.to_string().len())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let kind_w = rows.iter().map(|r| r.kind.len()).max().unwrap_or(0).max(4);
+    let span_w = rows.iter().map(|r| r.span.len()).max().unwrap_or(0).max(4);
+    let line_col_w = rows
+        .iter()
+        .map(|r| r.line_col.len())
+        .max()
+        .unwrap_or(0)
+        .max(8);
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{:>index_w$}  {:<kind_w$}  {:<span_w$}  {:<line_col_w$}  lexeme",
+        "#", "kind", "span", "line:col"
+    );
+    for (row, token) in rows.iter().zip(tokens) {
+        // Pad before colouring so escape codes don't count towards the width.
+        let kind = format!("{:<kind_w$}", row.kind);
+        let kind = match color.then(|| kind_color(token.kind())) {
+            Some(code) => format!("\x1b[{code}m{kind}\x1b[0m"),
+            None => kind,
+        };
+        let line = format!(
+            "{:>index_w$}  {kind}  {:<span_w$}  {:<line_col_w$}  {}",
+            row.index, row.span, row.line_col, row.lexeme
+        );
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+pub(super) fn render_json(file: &SourceFile, tokens: &[Token]) -> String {
+    let mut out = String::from("[\n");
+    for (index, token) in tokens.iter().enumerate() {
+        let (lo, hi) = (token.pos().lo(), token.pos().hi());
+        let (line, col) = file.lookup_line_col(lo);
+        let object = Json::object([
+            ("index", Json::Int(index as i64)),
+            ("kind", Json::str(format!("{:?}", token.kind()))),
+            ("lo", Json::from(lo)),
+            ("hi", Json::from(hi)),
+            ("line", Json::Int(line as i64)),
+            ("col", Json::Int(col as i64)),
+            ("lexeme", Json::str(file.span_to_snippet(*token.pos()))),
+        ]);
+        let _ = write!(out, "  {object}");
+        out.push_str(if index + 1 < tokens.len() {
+            ",\n"
+        } else {
+            "\n"
+        });
+    }
+    out.push_str("]\n");
+    out
+}
+
+/// ANSI SGR color code used for `kind` in the colorized table.
+fn kind_color(kind: &TokenKind) -> &'static str {
+    use TokenKind::*;
+    match kind {
+        ARRAY | IF | THEN | ELSE | WHILE | FOR | TO | DO | LET | IN | END | OF | BREAK
+        | FUNCTION | VAR | TYPE | NIL => "35",
+        ID => "36",
+        STRING | INT | FLOAT => "32",
+        COMMENT => "90",
+        UNKNOWN => "31",
+        EOF | WHITESPACE => "2",
+        _ => "33",
+    }
+}
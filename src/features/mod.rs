@@ -0,0 +1,169 @@
+#![allow(dead_code)]
+
+//! Detection of the language constructs a program uses, for `tigerc
+//! features`. Backends that don't support everything yet can use it to
+//! reject programs up front, and it helps triage a corpus of test programs.
+
+#[cfg(test)]
+mod tests;
+
+use std::fmt::Write;
+
+use crate::ast::visit::{walk_exp, walk_var, Visitor};
+use crate::ast::{Dec, Exp, ExpKind, Ty, Var, VarKind};
+use crate::graph;
+use crate::semant::ScopedTable;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Features {
+    /// Some function can call itself, directly or through others.
+    pub(crate) recursion: bool,
+    /// Some function is declared inside another, so it needs a static link.
+    pub(crate) nested_functions: bool,
+    pub(crate) records: bool,
+    pub(crate) arrays: bool,
+    pub(crate) strings: bool,
+    pub(crate) for_loops: bool,
+    pub(crate) while_loops: bool,
+    pub(crate) breaks: bool,
+    /// Number of declared functions.
+    pub(crate) functions: usize,
+    /// Deepest nesting of function declarations; 0 for none.
+    pub(crate) max_function_depth: usize,
+}
+
+impl Features {
+    /// The features as a flat JSON object, one key per line.
+    pub(crate) fn to_json(&self) -> String {
+        let fields = [
+            ("recursion", self.recursion.to_string()),
+            ("nested_functions", self.nested_functions.to_string()),
+            ("records", self.records.to_string()),
+            ("arrays", self.arrays.to_string()),
+            ("strings", self.strings.to_string()),
+            ("for_loops", self.for_loops.to_string()),
+            ("while_loops", self.while_loops.to_string()),
+            ("breaks", self.breaks.to_string()),
+            ("functions", self.functions.to_string()),
+            ("max_function_depth", self.max_function_depth.to_string()),
+        ];
+        let mut out = String::from("{\n");
+        for (i, (key, value)) in fields.iter().enumerate() {
+            let comma = if i + 1 < fields.len() { "," } else { "" };
+            let _ = writeln!(out, "  \"{key}\": {value}{comma}");
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// Scans `exp` for the constructs it uses. Names are resolved with Tiger's
+/// scoping rules so that calls can be attributed to the right function, but
+/// the program need not type check.
+pub(crate) fn detect(exp: &Exp) -> Features {
+    let mut detector = Detector {
+        features: Features::default(),
+        venv: ScopedTable::new(),
+        calls: Vec::new(),
+        current: Vec::new(),
+    };
+    detector.visit_exp(exp);
+    detector.features.recursion = graph::on_cycle(&detector.calls).contains(&true);
+    detector.features
+}
+
+struct Detector {
+    features: Features,
+    /// Function ids by name; variables shadow functions with `None`.
+    venv: ScopedTable<Option<usize>>,
+    /// Callees of each declared function.
+    calls: Vec<Vec<usize>>,
+    /// The functions whose bodies are being visited, innermost last.
+    current: Vec<usize>,
+}
+
+impl Visitor for Detector {
+    fn visit_exp(&mut self, exp: &Exp) {
+        match &exp.kind {
+            ExpKind::String(_) => self.features.strings = true,
+            ExpKind::Record { .. } => self.features.records = true,
+            ExpKind::Array { .. } => self.features.arrays = true,
+            ExpKind::While { .. } => self.features.while_loops = true,
+            ExpKind::Break => self.features.breaks = true,
+            ExpKind::Call { func, .. } => {
+                if let (Some(&caller), Some(&Some(callee))) =
+                    (self.current.last(), self.venv.look(*func))
+                {
+                    self.calls[caller].push(callee);
+                }
+            }
+            ExpKind::For { var, lo, hi, body } => {
+                self.features.for_loops = true;
+                self.visit_exp(lo);
+                self.visit_exp(hi);
+                self.venv.begin_scope();
+                self.venv.enter(*var, None);
+                self.visit_exp(body);
+                self.venv.end_scope();
+                return;
+            }
+            ExpKind::Let { .. } => {
+                self.venv.begin_scope();
+                walk_exp(self, exp);
+                self.venv.end_scope();
+                return;
+            }
+            _ => {}
+        }
+        walk_exp(self, exp);
+    }
+
+    fn visit_var(&mut self, var: &Var) {
+        match var.kind {
+            VarKind::Field(..) => self.features.records = true,
+            VarKind::Subscript(..) => self.features.arrays = true,
+            VarKind::Simple(_) => {}
+        }
+        walk_var(self, var);
+    }
+
+    fn visit_dec(&mut self, dec: &Dec) {
+        match dec {
+            Dec::Var(v) => {
+                self.visit_exp(&v.init);
+                self.venv.enter(v.name, None);
+            }
+            Dec::Type(group) => {
+                for t in group {
+                    match t.ty {
+                        Ty::Record(_) => self.features.records = true,
+                        Ty::Array(..) => self.features.arrays = true,
+                        Ty::Name(..) => {}
+                    }
+                }
+            }
+            Dec::Import(_) => {}
+            Dec::Function(group) => {
+                let depth = self.current.len() + 1;
+                self.features.functions += group.len();
+                self.features.nested_functions |= depth > 1;
+                self.features.max_function_depth = self.features.max_function_depth.max(depth);
+                let first = self.calls.len();
+                for f in group {
+                    self.venv.enter(f.name, Some(self.calls.len()));
+                    self.calls.push(Vec::new());
+                }
+                for (id, f) in (first..).zip(group) {
+                    self.current.push(id);
+                    self.venv.begin_scope();
+                    for param in &f.params {
+                        self.venv.enter(param.name, None);
+                    }
+                    self.visit_exp(&f.body);
+                    self.venv.end_scope();
+                    self.current.pop();
+                }
+            }
+        }
+    }
+}
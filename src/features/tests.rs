@@ -0,0 +1,66 @@
+use crate::features::{detect, Features};
+use crate::parser::parse;
+
+fn features(src: &str) -> Features {
+    detect(&parse(src).unwrap_or_else(|e| panic!("failed to parse {src:?}: {e:?}")))
+}
+
+#[test]
+fn straight_line_code_uses_nothing() {
+    assert_eq!(features("1 + 2 * 3"), Features::default());
+}
+
+#[test]
+fn data_and_control_constructs() {
+    let f = features(
+        "let type r = {a: int} var x := r {a = 1} in
+             for i := 0 to 10 do (if i > x.a then break);
+             while 0 do print(\"\")
+         end",
+    );
+    assert!(f.records && f.strings && f.for_loops && f.while_loops && f.breaks);
+    assert!(!f.arrays && !f.recursion && f.functions == 0);
+    assert!(features("let var a := 0 in a[0] end").arrays);
+}
+
+#[test]
+fn recursion_follows_scoping() {
+    assert!(features("let function f(n: int): int = f(n) in f(1) end").recursion);
+    assert!(
+        features(
+            "let function f() = g()
+                 function g() = f()
+             in end"
+        )
+        .recursion
+    );
+    // The inner `f` is a variable, and the outer `g` is not the one called.
+    assert!(
+        !features(
+            "let function g() = ()
+                 function f(f: int) = (f; ())
+             in let function g() = f(1) in g() end end"
+        )
+        .recursion
+    );
+}
+
+#[test]
+fn nested_functions() {
+    let f = features(
+        "let function outer(): int =
+                 let function inner(): int = 1 in inner() end
+             function other() = ()
+         in outer() end",
+    );
+    assert!(f.nested_functions);
+    assert_eq!((f.functions, f.max_function_depth), (3, 2));
+    assert!(!features("let function f() = () in end").nested_functions);
+}
+
+#[test]
+fn json_output() {
+    let json = features("let function f() = f() in end").to_json();
+    assert!(json.starts_with("{\n  \"recursion\": true,\n"));
+    assert!(json.ends_with("  \"max_function_depth\": 1\n}\n"));
+}
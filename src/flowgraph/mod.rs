@@ -0,0 +1,69 @@
+#![allow(dead_code)]
+
+//! Control-flow graphs of instruction lists, the `Flow` and `MakeGraph`
+//! modules of chapter 10.
+//!
+//! Node `i` is instruction `i`. Control falls through to the next
+//! instruction unless the instruction lists its jump targets; an empty
+//! target list, as in the sink added by `frame::proc_entry_exit2`, ends the
+//! function.
+
+use std::collections::HashMap;
+
+use crate::codegen::Instr;
+use crate::temp::{Label, Temp};
+
+pub(crate) struct FlowGraph {
+    pub(crate) nodes: Vec<FlowNode>,
+}
+
+pub(crate) struct FlowNode {
+    pub(crate) def: Vec<Temp>,
+    pub(crate) uses: Vec<Temp>,
+    /// A register-to-register copy, whose source and destination need not
+    /// interfere.
+    pub(crate) is_move: bool,
+    pub(crate) succ: Vec<usize>,
+    pub(crate) pred: Vec<usize>,
+}
+
+pub(crate) fn instrs_to_graph(instrs: &[Instr]) -> FlowGraph {
+    let labels: HashMap<Label, usize> = instrs
+        .iter()
+        .enumerate()
+        .filter_map(|(i, instr)| match instr {
+            Instr::Label { label, .. } => Some((*label, i)),
+            _ => None,
+        })
+        .collect();
+    let mut nodes: Vec<FlowNode> = instrs
+        .iter()
+        .enumerate()
+        .map(|(i, instr)| {
+            let succ = match instr {
+                Instr::Oper {
+                    jump: Some(targets),
+                    ..
+                } => targets
+                    .iter()
+                    .filter_map(|label| labels.get(label).copied())
+                    .collect(),
+                _ if i + 1 < instrs.len() => vec![i + 1],
+                _ => Vec::new(),
+            };
+            FlowNode {
+                def: instr.dst().to_vec(),
+                uses: instr.src().to_vec(),
+                is_move: matches!(instr, Instr::Move { .. }),
+                succ,
+                pred: Vec::new(),
+            }
+        })
+        .collect();
+    for i in 0..nodes.len() {
+        for j in nodes[i].succ.clone() {
+            nodes[j].pred.push(i);
+        }
+    }
+    FlowGraph { nodes }
+}
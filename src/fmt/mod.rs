@@ -0,0 +1,729 @@
+#![allow(dead_code)]
+
+//! A source formatter: reprints a program with canonical indentation and
+//! spacing, keeping its comments.
+//!
+//! The tree leaves out comments and parentheses, so the formatter works from
+//! both the tree and the tokens. Parentheses are put back where precedence
+//! needs them, and each comment is written before the first node after it,
+//! or at the end of the line it ended in the source. Blank lines between
+//! declarations and between the expressions of a sequence are kept, at most
+//! one at a time.
+//!
+//! An expression is written on one line when it fits in `WIDTH` columns and
+//! has no comment inside; otherwise it is broken over several lines, each
+//! nested block indented by `INDENT`. `let` is always broken.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashSet;
+
+use crate::ast::{Dec, Exp, ExpKind, Field, FunDec, Oper, Ty, TypeDec, Var, VarDec, VarKind};
+use crate::diagnostics::Diagnostic;
+use crate::lexer::{StringReader, TokenKind};
+use crate::parser::{self, Assoc, BINARY_OPS};
+use crate::span::Span;
+
+const WIDTH: usize = 80;
+const INDENT: &str = "  ";
+
+/// `src` formatted, or its errors if it does not parse. Warnings do not
+/// keep a program from being formatted.
+pub(crate) fn format_source(src: &str) -> Result<String, Vec<Diagnostic>> {
+    let parsed = parser::parse_reporting(src);
+    let errors: Vec<Diagnostic> = parsed
+        .diagnostics
+        .into_iter()
+        .filter(Diagnostic::is_error)
+        .collect();
+    match parsed.ast {
+        Some(ast) if errors.is_empty() => Ok(Formatter::new(src).program(&ast)),
+        _ => Err(errors),
+    }
+}
+
+/// A comment with the line breaks around it in the source.
+#[derive(Clone, Copy)]
+struct Comment {
+    pos: Span,
+    /// Whether only whitespace comes before it on its line.
+    own_line: bool,
+    /// Whether only whitespace comes after it on its line.
+    newline_after: bool,
+}
+
+struct Formatter<'a> {
+    src: &'a str,
+    /// Every comment, in source order.
+    comments: Vec<Comment>,
+    /// How many of `comments` have been written.
+    written: usize,
+    /// Starts of the tokens with a blank line before them.
+    blank_before: HashSet<u32>,
+    out: String,
+    /// The width of the last line of `out`.
+    out_width: usize,
+    indent: usize,
+    /// Whether the current line is empty, its indentation not yet written.
+    line_start: bool,
+    /// Whether a block was opened on the line before, where a blank line
+    /// would look out of place.
+    opened: bool,
+    /// Whether the next text needs a space before it, after a comment.
+    space: bool,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(src: &'a str) -> Formatter<'a> {
+        let tokens: Vec<_> = StringReader::new(src).collect();
+        let mut comments = Vec::new();
+        let mut blank_before = HashSet::new();
+        for (i, token) in tokens.iter().enumerate() {
+            let pos = *token.pos();
+            let before = match i.checked_sub(1) {
+                Some(prev) => &src[tokens[prev].pos().hi() as usize..pos.lo() as usize],
+                None => "\n",
+            };
+            if before.matches('\n').count() > 1 {
+                blank_before.insert(pos.lo());
+            }
+            if token.kind() == &TokenKind::COMMENT {
+                let next = tokens
+                    .get(i + 1)
+                    .map_or(src.len(), |t| t.pos().lo() as usize);
+                comments.push(Comment {
+                    pos,
+                    own_line: before.contains('\n'),
+                    newline_after: src[pos.hi() as usize..next].contains('\n'),
+                });
+            }
+        }
+        Formatter {
+            src,
+            comments,
+            written: 0,
+            blank_before,
+            out: String::new(),
+            out_width: 0,
+            indent: 0,
+            line_start: true,
+            opened: false,
+            space: false,
+        }
+    }
+
+    fn program(mut self, exp: &Exp) -> String {
+        self.exp(exp);
+        self.comments_before(u32::MAX);
+        self.line();
+        self.out
+    }
+
+    fn text(&self, pos: Span) -> &'a str {
+        &self.src[pos.lo() as usize..pos.hi() as usize]
+    }
+
+    fn write(&mut self, s: &str) {
+        if std::mem::take(&mut self.line_start) {
+            self.push(&INDENT.repeat(self.indent));
+        } else if std::mem::take(&mut self.space) && !s.starts_with([' ', ')', ']', ',', ';']) {
+            self.push(" ");
+        }
+        self.space = false;
+        self.opened = false;
+        self.push(s);
+    }
+
+    /// Appends `s` to `out`, keeping `out_width` up to date.
+    fn push(&mut self, s: &str) {
+        self.out.push_str(s);
+        match s.rsplit_once('\n') {
+            Some((_, last)) => self.out_width = width(last),
+            None => self.out_width += width(s),
+        }
+    }
+
+    /// Ends the current line, unless it is empty.
+    fn line(&mut self) {
+        if !self.line_start {
+            self.push("\n");
+            self.line_start = true;
+            self.space = false;
+        }
+    }
+
+    fn blank_line(&mut self) {
+        if self.line_start && !self.opened && !self.out.is_empty() && !self.out.ends_with("\n\n") {
+            self.push("\n");
+        }
+    }
+
+    fn column(&self) -> usize {
+        match self.line_start {
+            true => self.indent * INDENT.len(),
+            false => self.out_width,
+        }
+    }
+
+    /// The columns left on the current line.
+    fn room(&self) -> usize {
+        WIDTH.saturating_sub(self.column())
+    }
+
+    /// Whether `text` fits on the current line.
+    fn fits(&self, text: &str) -> bool {
+        !text.contains('\n') && self.column() + width(text) <= WIDTH
+    }
+
+    /// Starts an indented block on the next line.
+    fn open(&mut self) {
+        self.indent += 1;
+        self.line();
+        self.opened = true;
+    }
+
+    fn close(&mut self) {
+        self.indent -= 1;
+        self.line();
+    }
+
+    fn has_comment(&self, pos: Span) -> bool {
+        let i = self.comments.partition_point(|c| c.pos.lo() < pos.lo());
+        self.comments.get(i).is_some_and(|c| c.pos.lo() < pos.hi())
+    }
+
+    fn comment_before(&self, lo: u32) -> bool {
+        self.comments
+            .get(self.written)
+            .is_some_and(|c| c.pos.lo() < lo)
+    }
+
+    /// Writes the comments that start before `lo`.
+    fn comments_before(&mut self, lo: u32) {
+        while self.comment_before(lo) {
+            let comment = self.comments[self.written];
+            self.written += 1;
+            self.comment(comment);
+        }
+    }
+
+    fn comment(&mut self, comment: Comment) {
+        let text = self.text(comment.pos);
+        if comment.own_line {
+            self.line();
+            if self.blank_before.contains(&comment.pos.lo()) {
+                self.blank_line();
+            }
+        } else if comment.newline_after
+            && self.line_start
+            && self.out.ends_with('\n')
+            && !self.out.ends_with("\n\n")
+        {
+            // It followed code on the same line, so it stays at the end of
+            // that line.
+            self.out.pop();
+            self.out_width = width(self.out.rsplit('\n').next().unwrap_or(""));
+            self.line_start = false;
+            self.space = true;
+        } else if !self.line_start && !self.out.ends_with(['(', '[', ' ']) {
+            self.space = true;
+        }
+        self.write(text);
+        match comment.newline_after {
+            true => self.line(),
+            false => self.space = true,
+        }
+    }
+
+    /// Starts a declaration or an expression of a sequence, on a line of
+    /// its own.
+    fn item(&mut self, lo: u32) {
+        self.line();
+        self.comments_before(lo);
+        if self.blank_before.contains(&lo) {
+            self.blank_line();
+        }
+    }
+
+    fn exp(&mut self, exp: &Exp) {
+        self.comments_before(exp.pos.lo());
+        match self.flat(exp, self.room()) {
+            Some(text) if self.fits(&text) => self.write(&text),
+            _ => self.broken(exp),
+        }
+    }
+
+    /// `exp` on the same line if it fits there, and otherwise in a block.
+    fn tail(&mut self, exp: &Exp) {
+        match self.flat(exp, self.room().saturating_sub(1)) {
+            Some(text) if !self.comment_before(exp.pos.lo()) && self.fits(&format!(" {text}")) => {
+                self.write(" ");
+                self.write(&text);
+            }
+            _ => self.block(exp),
+        }
+    }
+
+    fn block(&mut self, exp: &Exp) {
+        self.open();
+        self.exp(exp);
+        self.indent -= 1;
+    }
+
+    /// `exp` on one line in at most `room` characters, or `None` if it must
+    /// be broken. Each node takes its own text out of `room` before its
+    /// children are tried, so a long expression is given up on after the
+    /// first line's worth of it rather than written out whole.
+    fn flat(&self, exp: &Exp, room: usize) -> Option<String> {
+        if self.has_comment(exp.pos) {
+            return None;
+        }
+        let text = match &exp.kind {
+            ExpKind::Var(var) => self.flat_var(var, room)?,
+            ExpKind::Nil => "nil".to_string(),
+            ExpKind::Unit => "()".to_string(),
+            ExpKind::Break => "break".to_string(),
+            ExpKind::Int(_) | ExpKind::String(_) => self.text(exp.pos).to_string(),
+            ExpKind::Call { func, args } => {
+                let func = format!("{func}(");
+                let args = self.flat_joined(args, ", ", room.checked_sub(width(&func) + 1)?)?;
+                format!("{func}{args})")
+            }
+            ExpKind::Op { left, op, right } => match self.negated(exp) {
+                Some(operand) => format!(
+                    "-{}",
+                    self.flat_operand(operand, UNARY, true, room.checked_sub(1)?)?
+                ),
+                None => {
+                    let room = room.checked_sub(op.text().len() + 2)?;
+                    let left = self.flat_operand(left, level(*op), false, room)?;
+                    let right = self.flat_operand(right, level(*op), true, room - width(&left))?;
+                    format!("{left} {} {right}", op.text())
+                }
+            },
+            ExpKind::Record { typ, fields } => {
+                let mut room = room.checked_sub(width(&typ.to_string()) + 3)?;
+                let mut flat = Vec::with_capacity(fields.len());
+                for (i, f) in fields.iter().enumerate() {
+                    let name = format!("{} = ", f.name);
+                    let sep = if i > 0 { 2 } else { 0 };
+                    room = room.checked_sub(width(&name) + sep)?;
+                    let exp = self.flat(&f.exp, room)?;
+                    room -= width(&exp);
+                    flat.push(format!("{name}{exp}"));
+                }
+                format!("{typ} {{{}}}", flat.join(", "))
+            }
+            ExpKind::Seq(exps) => {
+                let room = room.checked_sub(2)?;
+                format!("({})", self.flat_joined(exps, "; ", room)?)
+            }
+            ExpKind::Assign { var, exp } => {
+                let room = room.checked_sub(4)?;
+                let var = self.flat_var(var, room)?;
+                format!("{var} := {}", self.flat(exp, room - width(&var))?)
+            }
+            ExpKind::If { test, then_, else_ } => {
+                let room = room.checked_sub("if  then ".len())?;
+                let test = self.flat(test, room)?;
+                let room = room - width(&test);
+                match else_ {
+                    None => format!("if {test} then {}", self.flat(then_, room)?),
+                    Some(else_) => {
+                        let room = room.checked_sub(" else ".len())?;
+                        let then_ = self.flat_then(then_, room)?;
+                        let else_ = self.flat(else_, room - width(&then_))?;
+                        format!("if {test} then {then_} else {else_}")
+                    }
+                }
+            }
+            ExpKind::While { test, body } => {
+                let room = room.checked_sub("while  do ".len())?;
+                let test = self.flat(test, room)?;
+                format!("while {test} do {}", self.flat(body, room - width(&test))?)
+            }
+            ExpKind::For { var, lo, hi, body } => {
+                let head = format!("for {var} := ");
+                let room = room.checked_sub(width(&head) + " to  do ".len())?;
+                let lo = self.flat(lo, room)?;
+                let room = room - width(&lo);
+                let hi = self.flat(hi, room)?;
+                let body = self.flat(body, room - width(&hi))?;
+                format!("{head}{lo} to {hi} do {body}")
+            }
+            ExpKind::Array { typ, size, init } => {
+                let head = format!("{typ} [");
+                let room = room.checked_sub(width(&head) + "] of ".len())?;
+                let size = self.flat(size, room)?;
+                let init = self.flat(init, room - width(&size))?;
+                format!("{head}{size}] of {init}")
+            }
+            ExpKind::Let { .. } => return None,
+            ExpKind::Error => unreachable!("only complete trees are formatted"),
+        };
+        (width(&text) <= room).then_some(text)
+    }
+
+    /// `exps` on one line, separated by `sep`, in at most `room` characters.
+    fn flat_joined(&self, exps: &[Exp], sep: &str, mut room: usize) -> Option<String> {
+        let mut text = String::new();
+        for (i, e) in exps.iter().enumerate() {
+            if i > 0 {
+                room = room.checked_sub(sep.len())?;
+                text.push_str(sep);
+            }
+            let exp = self.flat(e, room)?;
+            room -= width(&exp);
+            text.push_str(&exp);
+        }
+        Some(text)
+    }
+
+    fn flat_var(&self, var: &Var, room: usize) -> Option<String> {
+        let text = match &var.kind {
+            VarKind::Simple(name) => name.to_string(),
+            VarKind::Field(record, name) => {
+                let name = format!(".{name}");
+                let record = self.flat_var(record, room.checked_sub(width(&name))?)?;
+                format!("{record}{name}")
+            }
+            VarKind::Subscript(array, index) => {
+                let room = room.checked_sub(2)?;
+                let array = self.flat_var(array, room)?;
+                format!("{array}[{}]", self.flat(index, room - width(&array))?)
+            }
+        };
+        (width(&text) <= room).then_some(text)
+    }
+
+    fn flat_operand(&self, exp: &Exp, level: usize, right: bool, room: usize) -> Option<String> {
+        match self.needs_parens(exp, level, right) {
+            true => Some(format!("({})", self.flat(exp, room.checked_sub(2)?)?)),
+            false => self.flat(exp, room),
+        }
+    }
+
+    fn flat_then(&self, then_: &Exp, room: usize) -> Option<String> {
+        match dangles(then_) {
+            true => Some(format!("({})", self.flat(then_, room.checked_sub(2)?)?)),
+            false => self.flat(then_, room),
+        }
+    }
+
+    /// The operand of a unary minus, which the parser turns into `0 - e`
+    /// with the `0` at the `-`.
+    fn negated<'e>(&self, exp: &'e Exp) -> Option<&'e Exp> {
+        match &exp.kind {
+            ExpKind::Op {
+                left,
+                op: Oper::Minus,
+                right,
+            } if matches!(left.kind, ExpKind::Int(0)) && self.text(left.pos) == "-" => Some(right),
+            _ => None,
+        }
+    }
+
+    /// Whether `exp` needs parentheses as an operand of an operator of
+    /// precedence `level`, on its right side if `right`.
+    fn needs_parens(&self, exp: &Exp, level: usize, right: bool) -> bool {
+        match &exp.kind {
+            ExpKind::Op { op, .. } if self.negated(exp).is_none() => {
+                let own = self::level(*op);
+                own < level || own == level && (right || BINARY_OPS[level].assoc == Assoc::Non)
+            }
+            // These end with an expression that would take in the rest of
+            // the operation.
+            ExpKind::If { .. }
+            | ExpKind::While { .. }
+            | ExpKind::For { .. }
+            | ExpKind::Assign { .. }
+            | ExpKind::Array { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Writes an expression that does not fit on the line, or has comments
+    /// inside.
+    fn broken(&mut self, exp: &Exp) {
+        match &exp.kind {
+            ExpKind::Var(var) => self.var(var),
+            ExpKind::Call { func, args } => {
+                self.write(&format!("{func}("));
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        self.write(", ");
+                    }
+                    self.exp(arg);
+                }
+                self.comments_before(exp.pos.hi());
+                self.write(")");
+            }
+            ExpKind::Op { left, op, right } => match self.negated(exp) {
+                Some(operand) => {
+                    self.write("-");
+                    self.operand(operand, UNARY, true);
+                }
+                None => {
+                    self.operand(left, level(*op), false);
+                    self.write(&format!(" {} ", op.text()));
+                    self.operand(right, level(*op), true);
+                }
+            },
+            ExpKind::Record { typ, fields } => {
+                self.write(&format!("{typ} {{"));
+                self.open();
+                for (i, field) in fields.iter().enumerate() {
+                    self.item(field.pos.lo());
+                    self.write(&format!("{} = ", field.name));
+                    self.exp(&field.exp);
+                    if i + 1 < fields.len() {
+                        self.write(",");
+                    }
+                }
+                self.comments_before(exp.pos.hi());
+                self.close();
+                self.write("}");
+            }
+            ExpKind::Seq(exps) => {
+                self.write("(");
+                self.open();
+                self.sequence(exps);
+                self.comments_before(exp.pos.hi());
+                self.close();
+                self.write(")");
+            }
+            ExpKind::Assign { var, exp } => {
+                self.var(var);
+                self.write(" := ");
+                self.exp(exp);
+            }
+            ExpKind::If { test, then_, else_ } => {
+                self.write("if ");
+                self.exp(test);
+                self.write(" then");
+                match else_ {
+                    Some(else_) => {
+                        if dangles(then_) {
+                            self.open();
+                            self.parenthesized(then_);
+                            self.indent -= 1;
+                        } else {
+                            self.block(then_);
+                        }
+                        self.line();
+                        self.comments_before(else_.pos.lo());
+                        self.write("else");
+                        if let ExpKind::If { .. } = else_.kind {
+                            self.write(" ");
+                            self.exp(else_);
+                        } else {
+                            self.block(else_);
+                        }
+                    }
+                    None => self.block(then_),
+                }
+            }
+            ExpKind::While { test, body } => {
+                self.write("while ");
+                self.exp(test);
+                self.write(" do");
+                self.block(body);
+            }
+            ExpKind::For { var, lo, hi, body } => {
+                self.write(&format!("for {var} := "));
+                self.exp(lo);
+                self.write(" to ");
+                self.exp(hi);
+                self.write(" do");
+                self.block(body);
+            }
+            ExpKind::Let { decs, body } => {
+                self.write("let");
+                self.indent += 1;
+                self.opened = true;
+                for dec in decs {
+                    self.dec(dec);
+                }
+                self.close();
+                self.write("in");
+                let body = match &body.kind {
+                    ExpKind::Seq(exps) => exps.as_slice(),
+                    ExpKind::Unit => &[],
+                    _ => std::slice::from_ref(&**body),
+                };
+                self.open();
+                self.sequence(body);
+                self.comments_before(exp.pos.hi());
+                self.close();
+                self.write("end");
+            }
+            ExpKind::Array { typ, size, init } => {
+                self.write(&format!("{typ} ["));
+                self.exp(size);
+                self.write("] of ");
+                self.exp(init);
+            }
+            ExpKind::Nil
+            | ExpKind::Unit
+            | ExpKind::Break
+            | ExpKind::Int(_)
+            | ExpKind::String(_) => {
+                let text = self
+                    .flat(exp, usize::MAX)
+                    .expect("atoms have no comments inside");
+                self.write(&text);
+            }
+            ExpKind::Error => unreachable!("only complete trees are formatted"),
+        }
+    }
+
+    /// The expressions of a sequence, one per line.
+    fn sequence(&mut self, exps: &[Exp]) {
+        for (i, exp) in exps.iter().enumerate() {
+            self.item(exp.pos.lo());
+            self.exp(exp);
+            if i + 1 < exps.len() {
+                self.write(";");
+            }
+        }
+    }
+
+    fn operand(&mut self, exp: &Exp, level: usize, right: bool) {
+        match self.needs_parens(exp, level, right) {
+            true => self.parenthesized(exp),
+            false => self.exp(exp),
+        }
+    }
+
+    fn parenthesized(&mut self, exp: &Exp) {
+        self.comments_before(exp.pos.lo());
+        self.write("(");
+        self.exp(exp);
+        self.write(")");
+    }
+
+    fn var(&mut self, var: &Var) {
+        match &var.kind {
+            VarKind::Simple(name) => self.write(&name.to_string()),
+            VarKind::Field(record, name) => {
+                self.var(record);
+                self.write(&format!(".{name}"));
+            }
+            VarKind::Subscript(array, index) => {
+                self.var(array);
+                self.write("[");
+                self.exp(index);
+                self.write("]");
+            }
+        }
+    }
+
+    fn dec(&mut self, dec: &Dec) {
+        match dec {
+            Dec::Function(group) => group.iter().for_each(|f| self.fun_dec(f)),
+            Dec::Var(v) => self.var_dec(v),
+            Dec::Type(group) => group.iter().for_each(|t| self.type_dec(t)),
+            Dec::Import(import) => {
+                // The path as written, escapes and all.
+                let text = self.text(import.pos);
+                let path = text.find('"').map_or(text, |quote| &text[quote..]);
+                let line = format!("import {path}");
+                self.item(import.pos.lo());
+                self.write(&line);
+            }
+        }
+    }
+
+    fn type_dec(&mut self, dec: &TypeDec) {
+        self.item(dec.pos.lo());
+        self.write(&format!("type {} = ", dec.name));
+        match &dec.ty {
+            Ty::Name(name, _) => self.write(&name.to_string()),
+            Ty::Array(elem, _) => self.write(&format!("array of {elem}")),
+            Ty::Record(fields) => {
+                let flat = format!("{{{}}}", flat_fields(fields));
+                if !self.has_comment(dec.pos) && self.fits(&flat) {
+                    self.write(&flat);
+                    return;
+                }
+                self.write("{");
+                self.open();
+                for (i, field) in fields.iter().enumerate() {
+                    self.item(field.pos.lo());
+                    self.write(&format!("{}: {}", field.name, field.typ));
+                    if i + 1 < fields.len() {
+                        self.write(",");
+                    }
+                }
+                self.comments_before(dec.pos.hi());
+                self.close();
+                self.write("}");
+            }
+        }
+    }
+
+    fn var_dec(&mut self, dec: &VarDec) {
+        self.item(dec.pos.lo());
+        self.write(&format!("var {}", dec.name));
+        if let Some((typ, _)) = dec.typ {
+            self.write(&format!(": {typ}"));
+        }
+        self.write(" := ");
+        self.exp(&dec.init);
+    }
+
+    fn fun_dec(&mut self, dec: &FunDec) {
+        self.item(dec.pos.lo());
+        self.write(&format!(
+            "function {}({})",
+            dec.name,
+            flat_fields(&dec.params)
+        ));
+        if let Some((result, _)) = dec.result {
+            self.write(&format!(": {result}"));
+        }
+        self.write(" =");
+        self.tail(&dec.body);
+    }
+}
+
+/// The precedence of unary minus, which binds tighter than any binary
+/// operator.
+const UNARY: usize = BINARY_OPS.len();
+
+fn level(op: Oper) -> usize {
+    BINARY_OPS
+        .iter()
+        .position(|prec| prec.ops.iter().any(|&(_, o)| o == op))
+        .expect("every operator has a precedence")
+}
+
+/// Whether `exp` ends with an `if` without `else`, which would take the
+/// `else` of an enclosing `if`.
+fn dangles(exp: &Exp) -> bool {
+    match &exp.kind {
+        ExpKind::If { else_: None, .. } => true,
+        ExpKind::If {
+            else_: Some(exp), ..
+        }
+        | ExpKind::While { body: exp, .. }
+        | ExpKind::For { body: exp, .. }
+        | ExpKind::Assign { exp, .. }
+        | ExpKind::Array { init: exp, .. } => dangles(exp),
+        _ => false,
+    }
+}
+
+/// The number of columns `text` takes.
+fn width(text: &str) -> usize {
+    text.chars().count()
+}
+
+fn flat_fields(fields: &[Field]) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|f| format!("{}: {}", f.name, f.typ))
+        .collect();
+    fields.join(", ")
+}
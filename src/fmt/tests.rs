@@ -0,0 +1,146 @@
+use super::format_source;
+use crate::parser::parse;
+use crate::pretty::sexp;
+
+/// Formats `src`, checking that the result means the same and is already
+/// formatted.
+fn format(src: &str) -> String {
+    let formatted = format_source(src).unwrap_or_else(|e| panic!("{src:?} failed: {e:?}"));
+    let reparsed = parse(&formatted).unwrap_or_else(|e| panic!("{formatted} failed: {e:?}"));
+    assert_eq!(sexp(&reparsed), sexp(&parse(src).unwrap()), "{formatted}");
+    assert_eq!(format_source(&formatted).unwrap(), formatted);
+    formatted
+}
+
+#[test]
+fn lays_out_declarations_and_blocks() {
+    let src = "let type  list={hd:int,tl:list}
+    type intArray = array of int
+  var x:int:=  1+2*3
+
+  function f(a:int,b:int):int= if a<b then a else   b
+  function g(n: int): int =
+     let var r := 0 in for i := 0 to n do (r := r + i; print(\"x\")) ; r end
+in x := -x - (1 - 2); (a := 1) ; g(10) end";
+    let expected = "\
+let
+  type list = {hd: int, tl: list}
+  type intArray = array of int
+  var x: int := 1 + 2 * 3
+
+  function f(a: int, b: int): int = if a < b then a else b
+  function g(n: int): int =
+    let
+      var r := 0
+    in
+      for i := 0 to n do (r := r + i; print(\"x\"));
+      r
+    end
+in
+  x := -x - (1 - 2);
+  a := 1;
+  g(10)
+end
+";
+    assert_eq!(format(src), expected);
+}
+
+#[test]
+fn breaks_long_expressions() {
+    let src = "let
+  function long(aaaaaaaa: int, bbbbbbbbbb: int) = if aaaaaaaa > bbbbbbbbbb then print(\"aaaaaaaaaaaaaaaaaaaa\") else print(\"bbbbbbbbbbbbbbbbbbbbbb\")
+  var r := point { xxxxxxxxxxxxxxxxx = 1000000000, yyyyyyyyyyyyyyyyyyyy = 2000000000, z = 3 }
+in end";
+    let expected = "\
+let
+  function long(aaaaaaaa: int, bbbbbbbbbb: int) =
+    if aaaaaaaa > bbbbbbbbbb then
+      print(\"aaaaaaaaaaaaaaaaaaaa\")
+    else
+      print(\"bbbbbbbbbbbbbbbbbbbbbb\")
+  var r := point {
+    xxxxxxxxxxxxxxxxx = 1000000000,
+    yyyyyyyyyyyyyyyyyyyy = 2000000000,
+    z = 3
+  }
+in
+end
+";
+    assert_eq!(format(src), expected);
+}
+
+#[test]
+fn keeps_comments() {
+    let src = "/* header */
+let type t = int   /* trailing */
+  /* before f */
+  function f() = (a /* inline */ + b)
+in
+  if a then /* why */ b else c
+end /* the end */";
+    let expected = "\
+/* header */
+let
+  type t = int /* trailing */
+  /* before f */
+  function f() =
+    a + /* inline */ b
+in
+  if a then
+    /* why */ b
+  else
+    c
+end /* the end */
+";
+    assert_eq!(format(src), expected);
+}
+
+#[test]
+fn restores_needed_parentheses() {
+    assert_eq!(format("(a + b) * (c - d)"), "(a + b) * (c - d)\n");
+    assert_eq!(format("a - (b - c) - d"), "a - (b - c) - d\n");
+    assert_eq!(format("(a = b) = (c < d)"), "(a = b) = (c < d)\n");
+    assert_eq!(format("- (a + b) * -c"), "-(a + b) * -c\n");
+    assert_eq!(format("0 - a"), "0 - a\n");
+    assert_eq!(
+        format("(if a then b else c) + 1"),
+        "(if a then b else c) + 1\n"
+    );
+    assert_eq!(format("1 + (t [2] of 3) + 4"), "1 + (t [2] of 3) + 4\n");
+    assert_eq!(
+        format("if a then (if b then c) else d"),
+        "if a then (if b then c) else d\n"
+    );
+    assert_eq!(
+        format("if a then (while b do if c then d) else e"),
+        "if a then (while b do if c then d) else e\n"
+    );
+    assert_eq!(
+        format("if a then if b then c else d"),
+        "if a then if b then c else d\n"
+    );
+}
+
+#[test]
+fn reports_syntax_errors() {
+    let errors = format_source("let var x := in x end").unwrap_err();
+    let msgs: Vec<_> = errors.iter().map(|d| d.msg.to_string()).collect();
+    assert_eq!(msgs, ["expected an expression, found `in`"]);
+    // Warnings do not stop formatting.
+    assert_eq!(format("class"), "class\n");
+}
+
+#[test]
+fn gives_up_on_a_flat_layout_early() {
+    // Each operand of a long chain is tried on the line it starts on, which
+    // took time quadratic in the length of the chain when the whole rest of
+    // it was laid out first.
+    let n = crate::limits::Limits::default().max_chain_length - 10;
+    let src: Vec<String> = (0..n).map(|i| format!("x{}", i % 7)).collect();
+    let src = src.join(" + ");
+    let formatted = crate::limits::with_stack(|| {
+        let exp = parse(&src).unwrap();
+        super::Formatter::new(&src).program(&exp)
+    });
+    assert_eq!(formatted, format!("{src}\n"));
+}
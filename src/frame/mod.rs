@@ -0,0 +1,220 @@
+#![allow(dead_code)]
+
+//! Stack frames for x86-64, the `Frame` module of chapter 6.
+//!
+//! Frames follow the System V calling convention: the first six arguments
+//! arrive in registers and the rest on the stack above the return address.
+//! `%rbp` is the frame pointer; locals live at negative offsets from it.
+//! Every Tiger function takes its static link as a hidden first argument.
+
+use crate::codegen::Instr;
+use crate::ir::{self, BinOp, Exp, Stm};
+use crate::temp::{Label, Temp};
+
+pub(crate) const WORD_SIZE: i64 = 8;
+
+pub(crate) const RAX: Temp = Temp::register(0);
+pub(crate) const RBX: Temp = Temp::register(1);
+pub(crate) const RCX: Temp = Temp::register(2);
+pub(crate) const RDX: Temp = Temp::register(3);
+pub(crate) const RSI: Temp = Temp::register(4);
+pub(crate) const RDI: Temp = Temp::register(5);
+pub(crate) const RBP: Temp = Temp::register(6);
+pub(crate) const RSP: Temp = Temp::register(7);
+pub(crate) const R8: Temp = Temp::register(8);
+pub(crate) const R9: Temp = Temp::register(9);
+pub(crate) const R10: Temp = Temp::register(10);
+pub(crate) const R11: Temp = Temp::register(11);
+pub(crate) const R12: Temp = Temp::register(12);
+pub(crate) const R13: Temp = Temp::register(13);
+pub(crate) const R14: Temp = Temp::register(14);
+pub(crate) const R15: Temp = Temp::register(15);
+
+/// The frame pointer.
+pub(crate) const FP: Temp = RBP;
+/// Where functions leave their result.
+pub(crate) const RV: Temp = RAX;
+pub(crate) const SP: Temp = RSP;
+
+pub(crate) const ARG_REGS: [Temp; 6] = [RDI, RSI, RDX, RCX, R8, R9];
+pub(crate) const CALLEE_SAVES: [Temp; 5] = [RBX, R12, R13, R14, R15];
+pub(crate) const CALLER_SAVES: [Temp; 9] = [RAX, RCX, RDX, RSI, RDI, R8, R9, R10, R11];
+/// Registers the allocator may assign: all but the stack and frame
+/// pointers, caller-saves first.
+pub(crate) const ALLOCATABLE: [Temp; 14] = [
+    RAX, RCX, RDX, RSI, RDI, R8, R9, R10, R11, RBX, R12, R13, R14, R15,
+];
+
+/// The assembly name of a machine register.
+pub(crate) fn register_name(temp: Temp) -> Option<&'static str> {
+    const NAMES: [&str; 16] = [
+        "%rax", "%rbx", "%rcx", "%rdx", "%rsi", "%rdi", "%rbp", "%rsp", "%r8", "%r9", "%r10",
+        "%r11", "%r12", "%r13", "%r14", "%r15",
+    ];
+    NAMES.get(temp.index() as usize).copied()
+}
+
+/// Where a formal parameter or local variable is kept.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Access {
+    /// At this offset from the frame pointer.
+    InFrame(i64),
+    InReg(Temp),
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Frame {
+    name: Label,
+    formals: Vec<Access>,
+    /// Number of words allocated below the frame pointer.
+    slots: i64,
+}
+
+impl Frame {
+    /// A frame for function `name` whose formals escape as given. Escaping
+    /// formals are copied into the frame on entry; the rest get temporaries.
+    pub(crate) fn new(name: Label, escapes: &[bool]) -> Frame {
+        let mut frame = Frame {
+            name,
+            formals: Vec::new(),
+            slots: 0,
+        };
+        frame.formals = escapes
+            .iter()
+            .map(|&escape| frame.alloc_local(escape))
+            .collect();
+        frame
+    }
+
+    pub(crate) fn name(&self) -> Label {
+        self.name
+    }
+
+    /// Where the function sees its formals, after the view shift.
+    pub(crate) fn formals(&self) -> &[Access] {
+        &self.formals
+    }
+
+    pub(crate) fn alloc_local(&mut self, escape: bool) -> Access {
+        if escape {
+            self.slots += 1;
+            Access::InFrame(-self.slots * WORD_SIZE)
+        } else {
+            Access::InReg(Temp::new())
+        }
+    }
+
+    /// Bytes of locals below the frame pointer.
+    pub(crate) fn size(&self) -> i64 {
+        self.slots * WORD_SIZE
+    }
+}
+
+/// The location of `access` given the address of its frame.
+pub(crate) fn exp(access: Access, frame_ptr: Exp) -> Exp {
+    match access {
+        Access::InFrame(offset) => Exp::offset(frame_ptr, offset),
+        Access::InReg(temp) => Exp::Temp(temp),
+    }
+}
+
+/// A call to a runtime function, which takes no static link.
+pub(crate) fn external_call(name: &str, args: Vec<Exp>) -> Exp {
+    Exp::call(Exp::Name(Label::named(name)), args)
+}
+
+/// The incoming location of argument `i`: a register for the first six,
+/// otherwise above the saved frame pointer and return address.
+fn incoming_arg(i: usize) -> Exp {
+    match ARG_REGS.get(i) {
+        Some(&reg) => Exp::Temp(reg),
+        None => {
+            let offset = (2 + (i - ARG_REGS.len()) as i64) * WORD_SIZE;
+            Exp::offset(Exp::Temp(FP), offset)
+        }
+    }
+}
+
+/// The view shift: moves incoming arguments to where the body expects its
+/// formals, and saves and restores the callee-save registers around `body`
+/// so the register allocator may spill them if it needs to.
+pub(crate) fn proc_entry_exit1(frame: &Frame, body: Stm) -> Stm {
+    let saved: Vec<Temp> = CALLEE_SAVES.iter().map(|_| Temp::new()).collect();
+    let save = CALLEE_SAVES
+        .iter()
+        .zip(&saved)
+        .map(|(&reg, &temp)| Stm::mov(Exp::Temp(temp), Exp::Temp(reg)));
+    let args = frame
+        .formals
+        .iter()
+        .enumerate()
+        .map(|(i, &access)| Stm::mov(exp(access, Exp::Temp(FP)), incoming_arg(i)));
+    let restore = CALLEE_SAVES
+        .iter()
+        .zip(&saved)
+        .map(|(&reg, &temp)| Stm::mov(Exp::Temp(reg), Exp::Temp(temp)));
+    let stms: Vec<Stm> = save
+        .chain(args)
+        .chain(std::iter::once(body))
+        .chain(restore)
+        .collect();
+    ir::seq(stms)
+}
+
+/// The address of word `index` of the block at `base`.
+pub(crate) fn word_index(base: Exp, index: Exp) -> Exp {
+    Exp::binop(
+        BinOp::Plus,
+        base,
+        Exp::binop(BinOp::Mul, index, Exp::Const(WORD_SIZE)),
+    )
+}
+
+/// Marks the return value, stack pointer and callee-save registers as live
+/// at the end of the body, so the register allocator keeps them intact.
+pub(crate) fn proc_entry_exit2(mut body: Vec<Instr>) -> Vec<Instr> {
+    let live_out = [RV, SP].into_iter().chain(CALLEE_SAVES).collect();
+    body.push(Instr::jump("", live_out, Vec::new()));
+    body
+}
+
+/// A function's instructions with the prologue and epilogue that set up
+/// and tear down its frame.
+pub(crate) struct Proc {
+    pub(crate) prolog: String,
+    pub(crate) body: Vec<Instr>,
+    pub(crate) epilog: String,
+}
+
+pub(crate) fn proc_entry_exit3(frame: &Frame, body: Vec<Instr>) -> Proc {
+    let name = frame.name();
+    // The call pushed the return address and the prologue pushes `%rbp`,
+    // so a multiple of 16 keeps the stack aligned for calls in the body.
+    let size = (frame.size() + 15) / 16 * 16;
+    let mut prolog = format!("\t.globl {name}\n{name}:\n\tpushq %rbp\n\tmovq %rsp, %rbp\n");
+    if size > 0 {
+        prolog.push_str(&format!("\tsubq ${size}, %rsp\n"));
+    }
+    Proc {
+        prolog,
+        body,
+        epilog: "\tleave\n\tret\n".to_string(),
+    }
+}
+
+/// The data directives for a string literal: a length word, then the bytes.
+pub(crate) fn string(label: Label, s: &[u8]) -> String {
+    let mut escaped = String::new();
+    for &b in s {
+        match b {
+            b'"' => escaped.push_str("\\\""),
+            b'\\' => escaped.push_str("\\\\"),
+            b' '..=b'~' => escaped.push(b as char),
+            b => escaped.push_str(&format!("\\{b:03o}")),
+        }
+    }
+    format!(
+        "\t.balign 8\n{label}:\n\t.quad {}\n\t.ascii \"{escaped}\"\n",
+        s.len()
+    )
+}
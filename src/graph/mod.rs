@@ -0,0 +1,204 @@
+#![allow(dead_code)]
+
+//! Algorithms on directed graphs, shared by the phases that order or
+//! analyze dependencies.
+//!
+//! A graph is a successor list per node, `succ[n]`, with nodes numbered
+//! from 0. Every result is deterministic: nodes are visited in index order
+//! and successors in list order, so the same graph always gives the same
+//! answer, whatever the hashing.
+
+#[cfg(test)]
+mod tests;
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+const UNVISITED: usize = usize::MAX;
+
+/// The strongly connected components of `succ`, by Tarjan's algorithm.
+/// Each component lists its nodes in increasing order, and comes after the
+/// components it has edges to: the list is in reverse topological order.
+pub(crate) fn sccs(succ: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let n = succ.len();
+    let mut index = vec![UNVISITED; n];
+    let mut low = vec![0; n];
+    let mut on_stack = vec![false; n];
+    let mut stack = Vec::new();
+    let mut components = Vec::new();
+    let mut next = 0;
+    // The depth-first search keeps its own stack of nodes and the position
+    // of the next successor to visit, so that deep graphs cannot overflow.
+    let mut calls: Vec<(usize, usize)> = Vec::new();
+    for root in 0..n {
+        if index[root] != UNVISITED {
+            continue;
+        }
+        calls.push((root, 0));
+        while let Some((v, i)) = calls.pop() {
+            if i == 0 {
+                (index[v], low[v]) = (next, next);
+                next += 1;
+                stack.push(v);
+                on_stack[v] = true;
+            }
+            if let Some(&w) = succ[v].get(i) {
+                calls.push((v, i + 1));
+                if index[w] == UNVISITED {
+                    calls.push((w, 0));
+                } else if on_stack[w] {
+                    low[v] = low[v].min(index[w]);
+                }
+                continue;
+            }
+            if low[v] == index[v] {
+                let mut component = Vec::new();
+                loop {
+                    let w = stack.pop().expect("`v` is on the stack");
+                    on_stack[w] = false;
+                    component.push(w);
+                    if w == v {
+                        break;
+                    }
+                }
+                component.sort_unstable();
+                components.push(component);
+            }
+            if let Some(&(parent, _)) = calls.last() {
+                low[parent] = low[parent].min(low[v]);
+            }
+        }
+    }
+    components
+}
+
+/// Marks the nodes that are on a cycle: those in a component of more than
+/// one node, or with an edge to themselves.
+pub(crate) fn on_cycle(succ: &[Vec<usize>]) -> Vec<bool> {
+    let mut marks = vec![false; succ.len()];
+    for component in sccs(succ) {
+        if component.len() > 1 || succ[component[0]].contains(&component[0]) {
+            for n in component {
+                marks[n] = true;
+            }
+        }
+    }
+    marks
+}
+
+/// The nodes of `succ` with every node before its successors. Among the
+/// orders that are, it is the one closest to index order: whenever several
+/// nodes could come next, the lowest numbered does. Fails with the nodes of
+/// a cycle, in increasing order, if there is one.
+pub(crate) fn topo_sort(succ: &[Vec<usize>]) -> Result<Vec<usize>, Vec<usize>> {
+    let mut indegree = vec![0; succ.len()];
+    for &w in succ.iter().flatten() {
+        indegree[w] += 1;
+    }
+    let mut ready: BinaryHeap<Reverse<usize>> = (0..succ.len())
+        .filter(|&v| indegree[v] == 0)
+        .map(Reverse)
+        .collect();
+    let mut order = Vec::with_capacity(succ.len());
+    while let Some(Reverse(v)) = ready.pop() {
+        order.push(v);
+        for &w in &succ[v] {
+            indegree[w] -= 1;
+            if indegree[w] == 0 {
+                ready.push(Reverse(w));
+            }
+        }
+    }
+    if order.len() == succ.len() {
+        return Ok(order);
+    }
+    let cycle = sccs(succ)
+        .into_iter()
+        .rev()
+        .find(|c| c.len() > 1 || succ[c[0]].contains(&c[0]))
+        .expect("a graph without a topological order has a cycle");
+    Err(cycle)
+}
+
+/// The nodes reachable from `entry` in depth-first postorder: every node
+/// comes after all the nodes it has a path to, except along back edges.
+/// Reversed, it is the order forward dataflow problems converge fastest in.
+pub(crate) fn postorder(succ: &[Vec<usize>], entry: usize) -> Vec<usize> {
+    let mut postorder = Vec::new();
+    let mut visited = vec![false; succ.len()];
+    let mut calls = vec![(entry, 0)];
+    visited[entry] = true;
+    while let Some((v, i)) = calls.pop() {
+        match succ[v].get(i) {
+            Some(&w) => {
+                calls.push((v, i + 1));
+                if !visited[w] {
+                    visited[w] = true;
+                    calls.push((w, 0));
+                }
+            }
+            None => postorder.push(v),
+        }
+    }
+    postorder
+}
+
+/// The immediate dominator of every node reachable from `entry`, by the
+/// iterative algorithm of Cooper, Harvey and Kennedy. The entry is its own
+/// immediate dominator; unreachable nodes have none.
+pub(crate) fn dominators(succ: &[Vec<usize>], entry: usize) -> Vec<Option<usize>> {
+    let n = succ.len();
+    let postorder = postorder(succ, entry);
+    let mut number = vec![UNVISITED; n];
+    for (i, &v) in postorder.iter().enumerate() {
+        number[v] = i;
+    }
+    let mut pred = vec![Vec::new(); n];
+    for (v, targets) in succ.iter().enumerate() {
+        for &w in targets {
+            pred[w].push(v);
+        }
+    }
+
+    let mut idom = vec![None; n];
+    idom[entry] = Some(entry);
+    let intersect = |idom: &[Option<usize>], mut a: usize, mut b: usize| {
+        while a != b {
+            while number[a] < number[b] {
+                a = idom[a].expect("processed nodes have a dominator");
+            }
+            while number[b] < number[a] {
+                b = idom[b].expect("processed nodes have a dominator");
+            }
+        }
+        a
+    };
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &v in postorder.iter().rev().filter(|&&v| v != entry) {
+            let mut processed = pred[v].iter().copied().filter(|&p| idom[p].is_some());
+            let first = processed.next().expect("a predecessor comes first");
+            let new = processed.fold(first, |d, p| intersect(&idom, p, d));
+            if idom[v] != Some(new) {
+                idom[v] = Some(new);
+                changed = true;
+            }
+        }
+    }
+    idom
+}
+
+/// Whether `a` dominates `b`, given the immediate dominators from
+/// `dominators`. Every reachable node dominates itself.
+pub(crate) fn dominates(idom: &[Option<usize>], a: usize, mut b: usize) -> bool {
+    loop {
+        if a == b && idom[b].is_some() {
+            return true;
+        }
+        match idom[b] {
+            Some(d) if d != b => b = d,
+            _ => return false,
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use super::{dominates, dominators, on_cycle, postorder, sccs, topo_sort};
+use crate::testing::Rng;
+
+/// A graph of up to `max_nodes` nodes, with about two edges per node.
+fn graph(rng: &mut Rng, max_nodes: usize) -> Vec<Vec<usize>> {
+    let n = 1 + rng.below(max_nodes);
+    (0..n)
+        .map(|_| (0..rng.below(4)).map(|_| rng.below(n)).collect())
+        .collect()
+}
+
+/// A graph whose edges all go from a node to a later one in a random
+/// order of the nodes, so that it has no cycle.
+fn dag(rng: &mut Rng, max_nodes: usize) -> Vec<Vec<usize>> {
+    let n = 1 + rng.below(max_nodes);
+    let mut rank: Vec<usize> = (0..n).collect();
+    for i in (1..n).rev() {
+        rank.swap(i, rng.below(i + 1));
+    }
+    let mut succ = vec![Vec::new(); n];
+    for _ in 0..rng.below(3 * n) {
+        let (a, b) = (rng.below(n), rng.below(n));
+        if rank[a] < rank[b] {
+            succ[a].push(b);
+        }
+    }
+    succ
+}
+
+/// The nodes reachable from `from` without going through `avoid`.
+fn reachable(succ: &[Vec<usize>], from: usize, avoid: Option<usize>) -> Vec<bool> {
+    let mut seen = vec![false; succ.len()];
+    let mut work = vec![from];
+    while let Some(v) = work.pop() {
+        if seen[v] || Some(v) == avoid {
+            continue;
+        }
+        seen[v] = true;
+        work.extend(&succ[v]);
+    }
+    seen
+}
+
+#[test]
+fn components_are_found_in_reverse_topological_order() {
+    let succ = vec![vec![1], vec![2, 3], vec![0], vec![4], vec![3], vec![]];
+    assert_eq!(sccs(&succ), [vec![3, 4], vec![0, 1, 2], vec![5]]);
+    assert_eq!(on_cycle(&succ), [true, true, true, true, true, false]);
+    assert_eq!(on_cycle(&[vec![0], vec![]]), [true, false]);
+}
+
+#[test]
+fn components_partition_the_graph_into_strongly_connected_parts() {
+    for seed in 0..300 {
+        let mut rng = Rng::new(seed);
+        let succ = graph(&mut rng, 12);
+        let components = sccs(&succ);
+        let mut component = vec![usize::MAX; succ.len()];
+        for (i, c) in components.iter().enumerate() {
+            assert!(c.windows(2).all(|w| w[0] < w[1]), "{succ:?}");
+            for &v in c {
+                assert_eq!(component[v], usize::MAX, "{succ:?}");
+                component[v] = i;
+            }
+        }
+        assert!(component.iter().all(|&c| c != usize::MAX), "{succ:?}");
+        let reach: Vec<Vec<bool>> = (0..succ.len()).map(|v| reachable(&succ, v, None)).collect();
+        for a in 0..succ.len() {
+            for b in 0..succ.len() {
+                let mutual = reach[a][b] && reach[b][a];
+                assert_eq!(component[a] == component[b], mutual, "{succ:?}");
+            }
+            // Edges between components go to earlier ones.
+            for &b in &succ[a] {
+                assert!(component[b] <= component[a], "{succ:?}");
+            }
+        }
+    }
+}
+
+#[test]
+fn topological_order_is_the_index_order_where_edges_allow() {
+    assert_eq!(topo_sort(&[vec![], vec![], vec![]]), Ok(vec![0, 1, 2]));
+    assert_eq!(topo_sort(&[vec![], vec![0], vec![]]), Ok(vec![1, 0, 2]));
+    assert_eq!(
+        topo_sort(&[vec![1], vec![2], vec![1], vec![0]]),
+        Err(vec![1, 2])
+    );
+    assert_eq!(topo_sort(&[vec![], vec![1]]), Err(vec![1]));
+}
+
+#[test]
+fn topological_orders_respect_every_edge() {
+    for seed in 0..300 {
+        let mut rng = Rng::new(seed);
+        let succ = dag(&mut rng, 15);
+        let order = topo_sort(&succ).unwrap_or_else(|c| panic!("cycle {c:?} in {succ:?}"));
+        let mut position = vec![usize::MAX; succ.len()];
+        for (i, &v) in order.iter().enumerate() {
+            position[v] = i;
+        }
+        assert!(position.iter().all(|&p| p != usize::MAX), "{succ:?}");
+        for (v, targets) in succ.iter().enumerate() {
+            for &w in targets {
+                assert!(position[v] < position[w], "{succ:?}");
+            }
+        }
+        // Each node comes as early as its predecessors allow: any node
+        // before a lower numbered one is an ancestor of a node in between.
+        for i in 0..order.len() {
+            for j in i + 1..order.len() {
+                if order[j] < order[i] {
+                    let forced = order[i..j]
+                        .iter()
+                        .any(|&u| reachable(&succ, u, None)[order[j]]);
+                    assert!(forced, "{order:?} in {succ:?}");
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn cyclic_graphs_report_a_cycle() {
+    for seed in 0..300 {
+        let mut rng = Rng::new(seed);
+        let succ = graph(&mut rng, 10);
+        let cycles = on_cycle(&succ);
+        match topo_sort(&succ) {
+            Ok(_) => assert!(!cycles.contains(&true), "{succ:?}"),
+            Err(cycle) => {
+                assert!(cycle.iter().all(|&v| cycles[v]), "{succ:?}");
+                let reach = reachable(&succ, cycle[0], None);
+                assert!(cycle.iter().all(|&v| reach[v]), "{succ:?}");
+            }
+        }
+    }
+}
+
+#[test]
+fn postorder_visits_successors_first() {
+    // 0 -> {1, 2}, 1 -> 3, 2 -> 3, 3 -> 1, and 4 is unreachable.
+    let succ = vec![vec![1, 2], vec![3], vec![3], vec![1], vec![0]];
+    assert_eq!(postorder(&succ, 0), [3, 1, 2, 0]);
+}
+
+#[test]
+fn immediate_dominators() {
+    // 0 -> 1 -> {2, 3} -> 4, and 5 is unreachable.
+    let succ = vec![vec![1], vec![2, 3], vec![4], vec![4], vec![1], vec![4]];
+    let idom = dominators(&succ, 0);
+    assert_eq!(idom, [Some(0), Some(0), Some(1), Some(1), Some(1), None]);
+    assert!(dominates(&idom, 1, 4));
+    assert!(dominates(&idom, 4, 4));
+    assert!(!dominates(&idom, 2, 4));
+    assert!(!dominates(&idom, 0, 5));
+}
+
+#[test]
+fn dominators_match_their_definition() {
+    for seed in 0..300 {
+        let mut rng = Rng::new(seed);
+        let succ = graph(&mut rng, 12);
+        let idom = dominators(&succ, 0);
+        let reach = reachable(&succ, 0, None);
+        for d in 0..succ.len() {
+            // `d` dominates the reachable nodes that cannot be reached
+            // without it.
+            let without = reachable(&succ, 0, Some(d));
+            for v in 0..succ.len() {
+                let expected = reach[v] && (d == v || !without[v]);
+                assert_eq!(dominates(&idom, d, v), expected, "{d} {v} in {succ:?}");
+            }
+        }
+        for v in 0..succ.len() {
+            assert_eq!(idom[v].is_some(), reach[v], "{succ:?}");
+        }
+    }
+}
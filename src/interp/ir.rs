@@ -0,0 +1,523 @@
+//! An interpreter for translated programs: runs the canonical IR trees of
+//! every function as `emit::function` would hand them to instruction
+//! selection, after the optimizer, so the same program can be run before
+//! and after optimization and the two runs compared.
+//!
+//! Memory is a map from byte addresses to words, with frames laid out as
+//! `frame` lays them out: arguments past the sixth above the frame pointer,
+//! escaping locals below it. Strings live beside the words, keyed by their
+//! address, since only the runtime library looks inside them. Runtime
+//! functions behave, and fail with the messages, of the `runtime` crate.
+//!
+//! Each statement run other than a label, and each operator, memory read
+//! and call evaluated, is a step, roughly one instruction of the generated
+//! code.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::Instant;
+
+use crate::analysis::{self, Cfg};
+use crate::canon;
+use crate::frame::{ARG_REGS, FP, RV, SP, WORD_SIZE};
+use crate::ir::{BinOp, Exp, RelOp, Stm};
+use crate::opt::{self, Fuel, Passes};
+use crate::temp::{Label, Temp};
+use crate::translate::Fragment;
+
+use super::Random;
+
+/// Where the stack starts, growing down, and the heap, growing up.
+const STACK_TOP: i64 = 1 << 40;
+const HEAP_BASE: i64 = 1 << 32;
+/// How deeply calls may nest before the run stops, as a native stack
+/// would overflow.
+const MAX_DEPTH: usize = 10_000;
+/// The stack of the thread running the program, which recurses with the
+/// calls it runs: room for `MAX_DEPTH` calls in an unoptimized build.
+const THREAD_STACK: usize = 256 << 20;
+
+/// The result of running a program.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Execution {
+    /// Everything the program printed.
+    pub(crate) output: Vec<u8>,
+    pub(crate) ending: Ending,
+    /// The steps taken in each function, in the order of the fragments.
+    pub(crate) steps: Vec<(Label, u64)>,
+    /// The rewrites the optimizer made.
+    pub(crate) rewrites: u64,
+    /// The function the last rewrite was made in, and the pass that made it.
+    pub(crate) last_rewrite: Option<(Label, &'static str)>,
+}
+
+impl Execution {
+    pub(crate) fn total_steps(&self) -> u64 {
+        self.steps.iter().map(|(_, n)| n).sum()
+    }
+}
+
+/// How a run ended.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Ending {
+    /// The program finished, with exit code 0, or called `exit`.
+    Exit(i64),
+    /// A run-time error, with the message the runtime library prints.
+    Error(String),
+    /// The program took more steps than the limit.
+    StepLimit,
+}
+
+/// Runs the translated program `fragments`, optimized with `passes`, with
+/// the program arguments `args`, reading `getchar` input from `input`. The
+/// run stops after `step_limit` steps.
+pub(crate) fn run(
+    fragments: &[Fragment],
+    passes: Passes,
+    args: &[String],
+    input: &[u8],
+    step_limit: u64,
+) -> Execution {
+    // Labels and temporaries are numbered per thread, so the trees are
+    // made canonical here, where they were translated.
+    let mut procs = HashMap::new();
+    let mut order = Vec::new();
+    let mut fuel = Fuel::new(passes);
+    let mut last_rewrite = None;
+    for fragment in fragments {
+        if let Fragment::Proc { body, frame } = fragment {
+            let (blocks, done) = canon::basic_blocks(canon::linearize(body.clone()));
+            debug_assert_eq!(
+                analysis::undefined_reads(&Cfg::new(blocks.clone())),
+                Default::default(),
+                "{} reads temporaries it never defined",
+                frame.name()
+            );
+            let used = fuel.used;
+            let stms = opt::optimize(canon::trace_schedule(blocks, done), passes, &mut fuel);
+            if fuel.used > used {
+                last_rewrite = fuel.last.map(|pass| (frame.name(), pass));
+            }
+            let labels = stms
+                .iter()
+                .enumerate()
+                .filter_map(|(i, stm)| match stm {
+                    Stm::Label(label) => Some((*label, i)),
+                    _ => None,
+                })
+                .collect();
+            let proc = Proc {
+                stms,
+                labels,
+                frame_size: frame.size(),
+            };
+            procs.insert(frame.name(), proc);
+            order.push(frame.name());
+        }
+    }
+    let execution = std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .stack_size(THREAD_STACK)
+            .spawn_scoped(scope, || {
+                execute(fragments, &procs, &order, args, input, step_limit)
+            })
+            .expect("could not start the interpreter thread")
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    });
+    Execution {
+        rewrites: fuel.used,
+        last_rewrite,
+        ..execution
+    }
+}
+
+fn execute(
+    fragments: &[Fragment],
+    procs: &HashMap<Label, Proc>,
+    order: &[Label],
+    args: &[String],
+    input: &[u8],
+    step_limit: u64,
+) -> Execution {
+    let mut machine = Machine {
+        literals: HashMap::new(),
+        memory: HashMap::new(),
+        strings: HashMap::new(),
+        heap: HEAP_BASE,
+        heap_start: HEAP_BASE,
+        sp: STACK_TOP,
+        args,
+        input,
+        output: Vec::new(),
+        steps: HashMap::new(),
+        taken: 0,
+        step_limit,
+        depth: 0,
+        random: Random::default(),
+        start: Instant::now(),
+    };
+    for fragment in fragments {
+        if let Fragment::String(label, s) = fragment {
+            let addr = machine.string(s.as_bytes());
+            machine.literals.insert(*label, addr);
+        }
+    }
+    machine.heap_start = machine.heap;
+    let main = Label::named("tigermain");
+    let ending = match machine.call(procs, main, &[0]) {
+        Ok(_) => Ending::Exit(0),
+        Err(Stop::Exit(code)) => Ending::Exit(code),
+        Err(Stop::Error(msg)) => Ending::Error(msg),
+        Err(Stop::StepLimit) => Ending::StepLimit,
+    };
+    let steps = order
+        .iter()
+        .map(|&label| (label, machine.steps.get(&label).copied().unwrap_or(0)))
+        .collect();
+    Execution {
+        output: machine.output,
+        ending,
+        steps,
+        rewrites: 0,
+        last_rewrite: None,
+    }
+}
+
+/// A function's canonical statements, and where each label is among them.
+struct Proc {
+    stms: Vec<Stm>,
+    labels: HashMap<Label, usize>,
+    frame_size: i64,
+}
+
+/// Why running stopped before the program returned.
+enum Stop {
+    Exit(i64),
+    Error(String),
+    StepLimit,
+}
+
+type Run<T> = Result<T, Stop>;
+
+fn error<T>(msg: String) -> Run<T> {
+    Err(Stop::Error(msg))
+}
+
+struct Machine<'a> {
+    /// The address of each string literal.
+    literals: HashMap<Label, i64>,
+    /// Words by address; words never written read as 0.
+    memory: HashMap<i64, i64>,
+    /// The contents of every string by its address.
+    strings: HashMap<i64, Rc<[u8]>>,
+    /// The next free heap address.
+    heap: i64,
+    /// Where the heap started after the string literals, which the
+    /// runtime library does not allocate, so `heap_used` does not count.
+    heap_start: i64,
+    sp: i64,
+    args: &'a [String],
+    input: &'a [u8],
+    output: Vec<u8>,
+    steps: HashMap<Label, u64>,
+    taken: u64,
+    step_limit: u64,
+    /// Calls being run.
+    depth: usize,
+    random: Random,
+    /// When the run started, which `clock` counts from.
+    start: Instant,
+}
+
+impl Machine<'_> {
+    fn step(&mut self, proc: Label) -> Run<()> {
+        self.taken += 1;
+        if self.taken > self.step_limit {
+            return Err(Stop::StepLimit);
+        }
+        *self.steps.entry(proc).or_default() += 1;
+        Ok(())
+    }
+
+    /// `size` zeroed bytes of heap, aligned to a word.
+    fn allocate(&mut self, size: i64) -> i64 {
+        let addr = self.heap;
+        self.heap += (size.max(1) + WORD_SIZE - 1) / WORD_SIZE * WORD_SIZE;
+        addr
+    }
+
+    fn string(&mut self, bytes: &[u8]) -> i64 {
+        let addr = self.allocate(WORD_SIZE + bytes.len() as i64);
+        self.memory.insert(addr, bytes.len() as i64);
+        self.strings.insert(addr, bytes.into());
+        addr
+    }
+
+    fn text(&self, addr: i64) -> Run<Rc<[u8]>> {
+        match self.strings.get(&addr) {
+            Some(bytes) => Ok(bytes.clone()),
+            None => error(format!("address {addr:#x} is not a string")),
+        }
+    }
+
+    fn load(&self, addr: i64) -> Run<i64> {
+        match addr {
+            0..HEAP_BASE => error(format!("invalid memory access at address {addr:#x}")),
+            _ => Ok(self.memory.get(&addr).copied().unwrap_or(0)),
+        }
+    }
+
+    fn store(&mut self, addr: i64, value: i64) -> Run<()> {
+        self.load(addr)?;
+        self.memory.insert(addr, value);
+        Ok(())
+    }
+
+    /// Calls the function `label` with `args`, its static link first,
+    /// returning what it leaves in the return-value register.
+    fn call(&mut self, procs: &HashMap<Label, Proc>, label: Label, args: &[i64]) -> Run<i64> {
+        let Some(proc) = procs.get(&label) else {
+            return self.runtime(label.name(), args);
+        };
+        if self.depth == MAX_DEPTH {
+            return error(format!("calls nested more than {MAX_DEPTH} deep"));
+        }
+        let saved_sp = self.sp;
+        let stacked = args.len().saturating_sub(ARG_REGS.len());
+        self.sp -= stacked as i64 * WORD_SIZE;
+        for (i, &arg) in args.iter().skip(ARG_REGS.len()).enumerate() {
+            self.store(self.sp + i as i64 * WORD_SIZE, arg)?;
+        }
+        // Below the stacked arguments are the return address and the
+        // saved frame pointer.
+        let fp = self.sp - 2 * WORD_SIZE;
+        self.sp = fp - proc.frame_size;
+        let mut temps: HashMap<Temp, i64> =
+            ARG_REGS.iter().copied().zip(args.iter().copied()).collect();
+        temps.insert(FP, fp);
+        temps.insert(SP, self.sp);
+
+        self.depth += 1;
+        let result = self.body(procs, label, proc, &mut temps);
+        self.depth -= 1;
+        self.sp = saved_sp;
+        result?;
+        Ok(temps.get(&RV).copied().unwrap_or(0))
+    }
+
+    fn body(
+        &mut self,
+        procs: &HashMap<Label, Proc>,
+        label: Label,
+        proc: &Proc,
+        temps: &mut HashMap<Temp, i64>,
+    ) -> Run<()> {
+        let jump = |target: &Label| match proc.labels.get(target) {
+            Some(&pc) => Ok(pc),
+            None => error(format!("jump to `{target}` outside `{label}`")),
+        };
+        let mut pc = 0;
+        while let Some(stm) = proc.stms.get(pc) {
+            pc += 1;
+            if let Stm::Label(_) = stm {
+                continue;
+            }
+            self.step(label)?;
+            match stm {
+                Stm::Move(dst, src) => match &**dst {
+                    Exp::Temp(t) => {
+                        let value = self.eval(procs, label, temps, src)?;
+                        temps.insert(*t, value);
+                    }
+                    Exp::Mem(addr) => {
+                        let addr = self.eval(procs, label, temps, addr)?;
+                        let value = self.eval(procs, label, temps, src)?;
+                        self.store(addr, value)?;
+                    }
+                    _ => unreachable!("`MOVE` into a temporary or memory"),
+                },
+                Stm::Exp(exp) => {
+                    self.eval(procs, label, temps, exp)?;
+                }
+                Stm::Jump(target, _) => match &**target {
+                    Exp::Name(target) => pc = jump(target)?,
+                    _ => return error(format!("computed jump in `{label}`")),
+                },
+                Stm::CJump(op, a, b, t, f) => {
+                    let a = self.eval(procs, label, temps, a)?;
+                    let b = self.eval(procs, label, temps, b)?;
+                    pc = jump(if relation(*op, a, b) { t } else { f })?;
+                }
+                Stm::Seq(..) | Stm::Label(_) => unreachable!("canonical trees have no `SEQ`"),
+            }
+        }
+        Ok(())
+    }
+
+    fn eval(
+        &mut self,
+        procs: &HashMap<Label, Proc>,
+        label: Label,
+        temps: &mut HashMap<Temp, i64>,
+        exp: &Exp,
+    ) -> Run<i64> {
+        match exp {
+            Exp::Const(n) => Ok(*n),
+            Exp::Name(name) => match self.literals.get(name) {
+                Some(&addr) => Ok(addr),
+                None => error(format!("`{name}` is not a string literal")),
+            },
+            Exp::Temp(t) => Ok(temps.get(t).copied().unwrap_or(0)),
+            Exp::BinOp(op, a, b) => {
+                let a = self.eval(procs, label, temps, a)?;
+                let b = self.eval(procs, label, temps, b)?;
+                self.step(label)?;
+                binop(*op, a, b)
+            }
+            Exp::Mem(addr) => {
+                let addr = self.eval(procs, label, temps, addr)?;
+                self.step(label)?;
+                self.load(addr)
+            }
+            Exp::Call(func, args) => {
+                let Exp::Name(callee) = **func else {
+                    return error(format!("computed call in `{label}`"));
+                };
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval(procs, label, temps, arg))
+                    .collect::<Run<Vec<i64>>>()?;
+                self.step(label)?;
+                self.call(procs, callee, &args)
+            }
+            Exp::ESeq(..) => unreachable!("canonical trees have no `ESEQ`"),
+        }
+    }
+
+    /// The runtime library function `name`.
+    fn runtime(&mut self, name: &str, args: &[i64]) -> Run<i64> {
+        let arg = |i: usize| args.get(i).copied().unwrap_or(0);
+        match name {
+            "tig_print" => {
+                let text = self.text(arg(0))?;
+                self.output.extend_from_slice(&text);
+                Ok(0)
+            }
+            "tig_flush" => Ok(0),
+            "tig_getchar" => {
+                let (byte, rest) = match self.input {
+                    [b, rest @ ..] => (vec![*b], rest),
+                    [] => (Vec::new(), self.input),
+                };
+                self.input = rest;
+                Ok(self.string(&byte))
+            }
+            "tig_ord" => Ok(self.text(arg(0))?.first().map_or(-1, |&b| b as i64)),
+            "tig_chr" => match u8::try_from(arg(0)) {
+                Ok(b) => Ok(self.string(&[b])),
+                Err(_) => error(format!("`chr` of {} is out of range", arg(0))),
+            },
+            "tig_itoa" => Ok(self.string(arg(0).to_string().as_bytes())),
+            "tig_atoi" => Ok(super::atoi(&self.text(arg(0))?).unwrap_or(arg(1))),
+            "tig_size" => Ok(self.text(arg(0))?.len() as i64),
+            "tig_substring" => {
+                let bytes = self.text(arg(0))?;
+                let (first, n) = (arg(1), arg(2));
+                match first.checked_add(n) {
+                    Some(end) if first >= 0 && n >= 0 && end <= bytes.len() as i64 => {
+                        Ok(self.string(&bytes[first as usize..end as usize]))
+                    }
+                    _ => error(format!(
+                        "substring {first}..{} is out of bounds for size {}",
+                        first.wrapping_add(n),
+                        bytes.len()
+                    )),
+                }
+            }
+            "tig_concat" => {
+                let joined = [self.text(arg(0))?, self.text(arg(1))?].concat();
+                Ok(self.string(&joined))
+            }
+            "tig_not" => Ok((arg(0) == 0) as i64),
+            "tig_exit" => match arg(0) {
+                code @ 0..=255 => Err(Stop::Exit(code)),
+                code => error(format!("`exit` code {code} is out of range")),
+            },
+            "tig_argc" => Ok(self.args.len() as i64),
+            "tig_argv" => match usize::try_from(arg(0)).ok().and_then(|i| self.args.get(i)) {
+                Some(s) => Ok(self.string(s.as_bytes())),
+                None => error(format!("`argv` of {} is out of range", arg(0))),
+            },
+            "tig_getenv" => {
+                let value = super::getenv(&self.text(arg(0))?);
+                Ok(self.string(&value))
+            }
+            "tig_random" => match arg(0) {
+                n @ 1.. => Ok(self.random.below(n)),
+                n => error(format!("`random` of {n} is out of range")),
+            },
+            "tig_seed" => {
+                self.random = Random(arg(0) as u64);
+                Ok(0)
+            }
+            "tig_clock" => Ok(self.start.elapsed().as_micros() as i64),
+            "tig_heap_used" => Ok(self.heap - self.heap_start),
+            "tig_gc_collect" => Ok(0),
+            "tig_stringEqual" => Ok((self.text(arg(0))? == self.text(arg(1))?) as i64),
+            "tig_stringCompare" => Ok(self.text(arg(0))?.cmp(&self.text(arg(1))?) as i64),
+            "tig_initArray" => {
+                let (size, init) = (arg(0), arg(1));
+                if size < 0 {
+                    return error(format!("negative array size {size}"));
+                }
+                let words = self.allocate((size + 1) * WORD_SIZE);
+                self.memory.insert(words, size);
+                for i in 1..=size {
+                    self.memory.insert(words + i * WORD_SIZE, init);
+                }
+                Ok(words + WORD_SIZE)
+            }
+            "tig_allocRecord" => Ok(self.allocate(arg(0))),
+            "tig_indexError" => error(format!("index {} is out of bounds", arg(0))),
+            "tig_nilError" => {
+                let field = String::from_utf8_lossy(&self.text(arg(0))?).into_owned();
+                error(format!("field `{field}` of nil record"))
+            }
+            "tig_divError" => error("division by zero".to_string()),
+            _ => error(format!("call to unknown function `{name}`")),
+        }
+    }
+}
+
+fn binop(op: BinOp, a: i64, b: i64) -> Run<i64> {
+    Ok(match op {
+        BinOp::Plus => a.wrapping_add(b),
+        BinOp::Minus => a.wrapping_sub(b),
+        BinOp::Mul => a.wrapping_mul(b),
+        BinOp::Div if b == 0 => return error("division by zero".to_string()),
+        BinOp::Div => a.wrapping_div(b),
+        BinOp::And => a & b,
+        BinOp::Or => a | b,
+        BinOp::Xor => a ^ b,
+        BinOp::LShift => a.wrapping_shl(b as u32 & 63),
+        BinOp::RShift => ((a as u64) >> (b & 63)) as i64,
+        BinOp::ARShift => a >> (b & 63),
+    })
+}
+
+fn relation(op: RelOp, a: i64, b: i64) -> bool {
+    let (ua, ub) = (a as u64, b as u64);
+    match op {
+        RelOp::Eq => a == b,
+        RelOp::Ne => a != b,
+        RelOp::Lt => a < b,
+        RelOp::Gt => a > b,
+        RelOp::Le => a <= b,
+        RelOp::Ge => a >= b,
+        RelOp::ULt => ua < ub,
+        RelOp::ULe => ua <= ub,
+        RelOp::UGt => ua > ub,
+        RelOp::UGe => ua >= ub,
+    }
+}
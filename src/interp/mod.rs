@@ -0,0 +1,603 @@
+#![allow(dead_code)]
+
+//! A tree-walking interpreter for type-checked programs.
+//!
+//! This gives the front end an executable semantics before there is a back
+//! end: records and arrays live on a heap and are compared by identity,
+//! strings by contents, and functions close over the scope they were
+//! declared in. The program must have passed `Semant::check`; ill-typed
+//! programs make the interpreter panic.
+
+pub(crate) mod ir;
+#[cfg(test)]
+mod tests;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{Read, Write};
+use std::rc::Rc;
+use std::time::Instant;
+
+use crate::ast::{Dec, Exp, ExpKind, FunDec, Oper, Var, VarKind};
+use crate::diagnostics::Message;
+use crate::span::Span;
+use crate::symbol::Symbol;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Value {
+    Int(i64),
+    /// A string's bytes. Strings are bytes, as in the runtime library, so
+    /// `size` and `substring` count bytes and `chr` makes one.
+    Str(Rc<[u8]>),
+    /// An index into the heap.
+    Record(usize),
+    Array(usize),
+    Nil,
+    /// The result of expressions that produce no value.
+    Unit,
+}
+
+impl Value {
+    fn int(&self) -> i64 {
+        match self {
+            Value::Int(n) => *n,
+            v => unreachable!("type checked: expected an int, found {v:?}"),
+        }
+    }
+
+    fn str(&self) -> &[u8] {
+        match self {
+            Value::Str(s) => s,
+            v => unreachable!("type checked: expected a string, found {v:?}"),
+        }
+    }
+}
+
+/// An error that stops the program, such as an out-of-bounds subscript.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct RuntimeError {
+    pub(crate) msg: Message,
+    pub(crate) pos: Span,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+/// Runs `exp` with the program arguments `args`, reading `getchar` input
+/// from `input` and printing to `output`. Returns the exit code: the
+/// argument of `exit`, or 0 when the program finishes normally.
+pub(crate) fn run(
+    exp: &Exp,
+    args: &[String],
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<i64, RuntimeError> {
+    run_with_heap(exp, args, input, output, None)
+}
+
+/// Runs `exp` as `run` does, failing with "out of memory" once the
+/// program has allocated more than `heap_limit` bytes.
+pub(crate) fn run_with_heap(
+    exp: &Exp,
+    args: &[String],
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+    heap_limit: Option<u64>,
+) -> Result<i64, RuntimeError> {
+    let mut interp = Interpreter {
+        heap: Vec::new(),
+        heap_used: 0,
+        heap_limit,
+        env: None,
+        args,
+        input,
+        output,
+        random: Random::default(),
+        start: Instant::now(),
+    };
+    let result = match interp.eval(exp) {
+        Ok(_) => Ok(0),
+        Err(Unwind::Exit(code)) => Ok(code),
+        Err(Unwind::Error(err)) => Err(err),
+        Err(Unwind::Break) => unreachable!("type checked: `break` outside a loop"),
+    };
+    interp.flush(exp.pos)?;
+    result
+}
+
+/// Why evaluation stopped early.
+enum Unwind {
+    Break,
+    Exit(i64),
+    Error(RuntimeError),
+}
+
+type Eval<T> = Result<T, Unwind>;
+
+/// The size of an integer or a pointer in the runtime library.
+const WORD: u64 = 8;
+
+fn error<T>(pos: Span, msg: Message) -> Eval<T> {
+    Err(Unwind::Error(RuntimeError { msg, pos }))
+}
+
+/// The integer `atoi` reads from `s`: an optional `-` and at least one
+/// decimal digit, nothing else, in range. `None` for anything else.
+fn atoi(s: &[u8]) -> Option<i64> {
+    let digits = s.strip_prefix(b"-").unwrap_or(s);
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    std::str::from_utf8(s).ok()?.parse().ok()
+}
+
+/// The value of the environment variable `name`, as `getenv` returns it:
+/// empty if it is unset, or if `name` is not UTF-8 or could not name one.
+fn getenv(name: &[u8]) -> Vec<u8> {
+    std::str::from_utf8(name)
+        .ok()
+        .and_then(std::env::var_os)
+        .map_or_else(Vec::new, |value| value.into_encoded_bytes())
+}
+
+/// A number of bytes, as `TIGER_HEAP_SIZE` gives the heap limit: digits,
+/// which may end in `k`, `m` or `g` for KiB, MiB or GiB. The runtime
+/// library reads it the same way.
+pub(crate) fn heap_size(s: &str) -> Option<u64> {
+    let (digits, unit) = match s.char_indices().last()? {
+        (i, 'k' | 'K') => (&s[..i], 1 << 10),
+        (i, 'm' | 'M') => (&s[..i], 1 << 20),
+        (i, 'g' | 'G') => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse::<u64>().ok()?.checked_mul(unit)
+}
+
+/// The generator `random` draws from, SplitMix64, in the state a program
+/// starts with or `seed` gives it. The runtime library draws the same
+/// numbers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Random(pub(crate) u64);
+
+impl Random {
+    /// The next number below `n`, which must be positive.
+    pub(crate) fn below(&mut self, n: i64) -> i64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        (z % n as u64) as i64
+    }
+}
+
+enum Object {
+    Record(Vec<(Symbol, Value)>),
+    Array(Vec<Value>),
+}
+
+enum Binding<'a> {
+    Var(Value),
+    Fun(&'a FunDec),
+}
+
+/// One declaration's worth of bindings. Scopes are immutable once built
+/// except for variable values, so closures can share them.
+struct Scope<'a> {
+    bindings: HashMap<Symbol, RefCell<Binding<'a>>>,
+    parent: Env<'a>,
+}
+
+type Env<'a> = Option<Rc<Scope<'a>>>;
+
+struct Interpreter<'a, 'io> {
+    heap: Vec<Object>,
+    /// The bytes the runtime library would have allocated for the heap
+    /// objects and the strings made so far, as `heap_used` returns them.
+    heap_used: u64,
+    heap_limit: Option<u64>,
+    env: Env<'a>,
+    /// The arguments `argv` returns, without the program's name.
+    args: &'io [String],
+    input: &'io mut dyn Read,
+    output: &'io mut dyn Write,
+    random: Random,
+    /// When the program started, which `clock` counts from.
+    start: Instant,
+}
+
+impl<'a> Interpreter<'a, '_> {
+    fn push_scope(&mut self, bindings: impl IntoIterator<Item = (Symbol, Binding<'a>)>) {
+        let bindings = bindings
+            .into_iter()
+            .map(|(name, binding)| (name, RefCell::new(binding)))
+            .collect();
+        self.env = Some(Rc::new(Scope {
+            bindings,
+            parent: self.env.take(),
+        }));
+    }
+
+    /// The innermost scope binding `name`.
+    fn lookup(&self, name: Symbol) -> Option<&Rc<Scope<'a>>> {
+        let mut scope = self.env.as_ref();
+        while let Some(s) = scope {
+            if s.bindings.contains_key(&name) {
+                return Some(s);
+            }
+            scope = s.parent.as_ref();
+        }
+        None
+    }
+
+    fn eval(&mut self, exp: &'a Exp) -> Eval<Value> {
+        match &exp.kind {
+            ExpKind::Var(var) => self.read_var(var),
+            ExpKind::Nil => Ok(Value::Nil),
+            ExpKind::Unit => Ok(Value::Unit),
+            ExpKind::Error => unreachable!("type checked: no syntax errors"),
+            ExpKind::Int(n) => Ok(Value::Int(*n)),
+            ExpKind::String(s) => Ok(Value::Str(Rc::from(s.as_bytes()))),
+            ExpKind::Call { func, args } => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<Eval<Vec<_>>>()?;
+                self.call(*func, args, exp.pos)
+            }
+            ExpKind::Op { left, op, right } => self.op(left, *op, right),
+            ExpKind::Record { fields, .. } => {
+                let fields = fields
+                    .iter()
+                    .map(|field| Ok((field.name, self.eval(&field.exp)?)))
+                    .collect::<Eval<Vec<_>>>()?;
+                self.allocate(fields.len() as u64 * WORD, exp.pos)?;
+                self.heap.push(Object::Record(fields));
+                Ok(Value::Record(self.heap.len() - 1))
+            }
+            ExpKind::Seq(exps) => {
+                let mut value = Value::Unit;
+                for exp in exps {
+                    value = self.eval(exp)?;
+                }
+                Ok(value)
+            }
+            ExpKind::Assign { var, exp } => {
+                let value = self.eval(exp)?;
+                self.write_var(var, value)?;
+                Ok(Value::Unit)
+            }
+            ExpKind::If { test, then_, else_ } => {
+                if self.eval(test)?.int() != 0 {
+                    self.eval(then_)
+                } else if let Some(else_) = else_ {
+                    self.eval(else_)
+                } else {
+                    Ok(Value::Unit)
+                }
+            }
+            ExpKind::While { test, body } => {
+                while self.eval(test)?.int() != 0 {
+                    match self.eval(body) {
+                        Err(Unwind::Break) => break,
+                        result => result?,
+                    };
+                }
+                Ok(Value::Unit)
+            }
+            ExpKind::For { var, lo, hi, body } => {
+                let lo = self.eval(lo)?.int();
+                let hi = self.eval(hi)?.int();
+                let saved = self.env.clone();
+                let mut i = lo;
+                // Tests before incrementing so `hi = max_int` terminates.
+                while i <= hi {
+                    self.push_scope([(*var, Binding::Var(Value::Int(i)))]);
+                    let result = self.eval(body);
+                    self.env = saved.clone();
+                    match result {
+                        Err(Unwind::Break) => break,
+                        result => result?,
+                    };
+                    if i == hi {
+                        break;
+                    }
+                    i += 1;
+                }
+                Ok(Value::Unit)
+            }
+            ExpKind::Break => Err(Unwind::Break),
+            ExpKind::Let { decs, body } => {
+                let saved = self.env.clone();
+                let result = decs
+                    .iter()
+                    .try_for_each(|dec| self.dec(dec))
+                    .and_then(|()| self.eval(body));
+                self.env = saved;
+                result
+            }
+            ExpKind::Array { size, init, .. } => {
+                let size = self.eval(size)?.int();
+                let init = self.eval(init)?;
+                let Ok(len) = usize::try_from(size) else {
+                    return error(exp.pos, Message::new("E0401").arg("size", size));
+                };
+                // The length is stored before the elements.
+                let bytes = (len as u64).saturating_add(1).saturating_mul(WORD);
+                self.allocate(bytes, exp.pos)?;
+                let mut elems = Vec::new();
+                if elems.try_reserve_exact(len).is_err() {
+                    return error(exp.pos, Message::new("E0409"));
+                }
+                elems.resize(len, init);
+                self.heap.push(Object::Array(elems));
+                Ok(Value::Array(self.heap.len() - 1))
+            }
+        }
+    }
+
+    fn dec(&mut self, dec: &'a Dec) -> Eval<()> {
+        match dec {
+            Dec::Var(v) => {
+                let value = self.eval(&v.init)?;
+                self.push_scope([(v.name, Binding::Var(value))]);
+            }
+            Dec::Function(group) => {
+                self.push_scope(group.iter().map(|f| (f.name, Binding::Fun(f))));
+            }
+            // The driver resolves imports before a program runs.
+            Dec::Type(_) | Dec::Import(_) => {}
+        }
+        Ok(())
+    }
+
+    fn op(&mut self, left: &'a Exp, op: Oper, right: &'a Exp) -> Eval<Value> {
+        let l = self.eval(left)?;
+        match op {
+            Oper::And if l.int() == 0 => return Ok(Value::Int(0)),
+            Oper::Or if l.int() != 0 => return Ok(Value::Int(1)),
+            _ => {}
+        }
+        let r = self.eval(right)?;
+        let n = match op {
+            Oper::Plus => l.int().wrapping_add(r.int()),
+            Oper::Minus => l.int().wrapping_sub(r.int()),
+            Oper::Times => l.int().wrapping_mul(r.int()),
+            Oper::Divide => match r.int() {
+                0 => return error(right.pos, Message::new("E0402")),
+                d => l.int().wrapping_div(d),
+            },
+            Oper::And | Oper::Or => (r.int() != 0) as i64,
+            Oper::Eq => (l == r) as i64,
+            Oper::Neq => (l != r) as i64,
+            Oper::Lt | Oper::Le | Oper::Gt | Oper::Ge => {
+                let ord = match (&l, &r) {
+                    (Value::Str(a), Value::Str(b)) => a.cmp(b),
+                    _ => l.int().cmp(&r.int()),
+                };
+                let holds = match op {
+                    Oper::Lt => ord.is_lt(),
+                    Oper::Le => ord.is_le(),
+                    Oper::Gt => ord.is_gt(),
+                    _ => ord.is_ge(),
+                };
+                holds as i64
+            }
+        };
+        Ok(Value::Int(n))
+    }
+
+    fn call(&mut self, name: Symbol, args: Vec<Value>, pos: Span) -> Eval<Value> {
+        let Some(scope) = self.lookup(name).cloned() else {
+            return self.builtin(name, args, pos);
+        };
+        let fun = match &*scope.bindings[&name].borrow() {
+            Binding::Fun(fun) => *fun,
+            Binding::Var(_) => unreachable!("type checked: `{name}` is a function"),
+        };
+        let caller = self.env.replace(scope);
+        self.push_scope(
+            fun.params
+                .iter()
+                .zip(args)
+                .map(|(param, arg)| (param.name, Binding::Var(arg))),
+        );
+        let result = self.eval(&fun.body);
+        self.env = caller;
+        result
+    }
+
+    fn builtin(&mut self, name: Symbol, args: Vec<Value>, pos: Span) -> Eval<Value> {
+        let value = match (name.as_str(), &args[..]) {
+            ("print", [s]) => {
+                self.output
+                    .write_all(s.str())
+                    .or_else(|e| error(pos, Message::new("E0403").arg("error", e.to_string())))?;
+                Value::Unit
+            }
+            ("flush", []) => {
+                self.flush(pos).map_err(Unwind::Error)?;
+                Value::Unit
+            }
+            ("getchar", []) => {
+                // As in the runtime library, a prompt shows before the
+                // program waits.
+                self.flush(pos).map_err(Unwind::Error)?;
+                let mut byte = [0];
+                match self.input.read(&mut byte) {
+                    Ok(0) => Value::Str(Rc::from(&[][..])),
+                    Ok(_) => Value::Str(Rc::from(&byte[..])),
+                    Err(e) => return error(pos, Message::new("E0404").arg("error", e.to_string())),
+                }
+            }
+            ("ord", [s]) => Value::Int(s.str().first().map_or(-1, |&b| b as i64)),
+            ("chr", [n]) => match u32::try_from(n.int()).ok().filter(|&n| n <= 255) {
+                Some(n) => Value::Str(Rc::from(&[n as u8][..])),
+                None => return error(pos, Message::new("E0405").arg("n", n.int())),
+            },
+            ("itoa", [n]) => Value::Str(n.int().to_string().as_bytes().into()),
+            ("atoi", [s, default]) => Value::Int(atoi(s.str()).unwrap_or(default.int())),
+            ("size", [s]) => Value::Int(s.str().len() as i64),
+            ("substring", [s, first, n]) => {
+                let bytes = s.str();
+                let (first, n) = (first.int(), n.int());
+                if first < 0 || n < 0 || first + n > bytes.len() as i64 {
+                    let msg = Message::new("E0406")
+                        .arg("first", first)
+                        .arg("end", first + n)
+                        .arg("size", bytes.len());
+                    return error(pos, msg);
+                }
+                Value::Str(Rc::from(&bytes[first as usize..(first + n) as usize]))
+            }
+            ("concat", [a, b]) => Value::Str([a.str(), b.str()].concat().into()),
+            ("not", [n]) => Value::Int((n.int() == 0) as i64),
+            ("exit", [code]) => match code.int() {
+                code @ 0..=255 => return Err(Unwind::Exit(code)),
+                code => return error(pos, Message::new("E0411").arg("code", code)),
+            },
+            ("argc", []) => Value::Int(self.args.len() as i64),
+            ("argv", [i]) => match usize::try_from(i.int()).ok().and_then(|i| self.args.get(i)) {
+                Some(arg) => Value::Str(arg.as_bytes().into()),
+                None => return error(pos, Message::new("E0410").arg("i", i.int())),
+            },
+            ("getenv", [name]) => Value::Str(getenv(name.str()).into()),
+            ("random", [n]) => match n.int() {
+                n @ 1.. => Value::Int(self.random.below(n)),
+                n => return error(pos, Message::new("E0412").arg("n", n)),
+            },
+            ("seed", [x]) => {
+                self.random = Random(x.int() as u64);
+                Value::Unit
+            }
+            ("clock", []) => Value::Int(self.start.elapsed().as_micros() as i64),
+            ("heap_used", []) => Value::Int(self.heap_used as i64),
+            // Nothing is ever freed, as in the runtime library.
+            ("gc_collect", []) => Value::Unit,
+            _ => unreachable!("type checked: unknown function `{name}`"),
+        };
+        // Every string a function returns is new: a length word and then
+        // the bytes.
+        if let Value::Str(s) = &value {
+            self.allocate(WORD + s.len() as u64, pos)?;
+        }
+        Ok(value)
+    }
+
+    /// Counts `size` bytes of heap, rounded up to a word, as the runtime
+    /// library allocates them.
+    fn allocate(&mut self, size: u64, pos: Span) -> Eval<()> {
+        let used = self
+            .heap_used
+            .saturating_add(size.max(1).div_ceil(WORD) * WORD);
+        if self.heap_limit.is_some_and(|limit| used > limit) {
+            return error(pos, Message::new("E0409"));
+        }
+        self.heap_used = used;
+        Ok(())
+    }
+
+    fn flush(&mut self, pos: Span) -> Result<(), RuntimeError> {
+        self.output.flush().map_err(|e| RuntimeError {
+            msg: Message::new("E0403").arg("error", e.to_string()),
+            pos,
+        })
+    }
+
+    fn read_var(&mut self, var: &'a Var) -> Eval<Value> {
+        match &var.kind {
+            VarKind::Simple(name) => {
+                let scope = self.lookup(*name).expect("type checked: variable is bound");
+                match &*scope.bindings[name].borrow() {
+                    Binding::Var(value) => Ok(value.clone()),
+                    Binding::Fun(_) => unreachable!("type checked: `{name}` is a variable"),
+                }
+            }
+            VarKind::Field(record, field) => {
+                let record = self.read_var(record)?;
+                let fields = self.record(record, *field, var.pos)?;
+                Ok(fields
+                    .iter()
+                    .find(|(name, _)| name == field)
+                    .map(|(_, value)| value.clone())
+                    .expect("type checked: record has the field"))
+            }
+            VarKind::Subscript(array, index) => {
+                let array = self.read_var(array)?;
+                let index = self.eval(index)?.int();
+                let elements = self.array(array)?;
+                let i = checked_index(index, elements.len(), var.pos)?;
+                Ok(elements[i].clone())
+            }
+        }
+    }
+
+    fn write_var(&mut self, var: &'a Var, value: Value) -> Eval<()> {
+        match &var.kind {
+            VarKind::Simple(name) => {
+                let scope = self.lookup(*name).expect("type checked: variable is bound");
+                *scope.bindings[name].borrow_mut() = Binding::Var(value);
+            }
+            VarKind::Field(record, field) => {
+                let record = self.read_var(record)?;
+                let slot = self
+                    .record(record, *field, var.pos)?
+                    .iter_mut()
+                    .find(|(name, _)| name == field)
+                    .expect("type checked: record has the field");
+                slot.1 = value;
+            }
+            VarKind::Subscript(array, index) => {
+                let array = self.read_var(array)?;
+                let index = self.eval(index)?.int();
+                let elements = self.array(array)?;
+                let i = checked_index(index, elements.len(), var.pos)?;
+                elements[i] = value;
+            }
+        }
+        Ok(())
+    }
+
+    fn record(
+        &mut self,
+        value: Value,
+        field: Symbol,
+        pos: Span,
+    ) -> Eval<&mut Vec<(Symbol, Value)>> {
+        match value {
+            Value::Record(i) => match &mut self.heap[i] {
+                Object::Record(fields) => Ok(fields),
+                Object::Array(_) => unreachable!("records are not arrays"),
+            },
+            Value::Nil => error(pos, Message::new("E0407").arg("field", field)),
+            v => unreachable!("type checked: expected a record, found {v:?}"),
+        }
+    }
+
+    fn array(&mut self, value: Value) -> Eval<&mut Vec<Value>> {
+        match value {
+            Value::Array(i) => match &mut self.heap[i] {
+                Object::Array(elements) => Ok(elements),
+                Object::Record(_) => unreachable!("arrays are not records"),
+            },
+            v => unreachable!("type checked: expected an array, found {v:?}"),
+        }
+    }
+}
+
+fn checked_index(index: i64, len: usize, pos: Span) -> Eval<usize> {
+    match usize::try_from(index) {
+        Ok(i) if i < len => Ok(i),
+        _ => error(
+            pos,
+            Message::new("E0408").arg("index", index).arg("len", len),
+        ),
+    }
+}
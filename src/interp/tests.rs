@@ -0,0 +1,470 @@
+use crate::interp::ir::{self, Ending, Execution};
+use crate::interp::{heap_size, run, run_with_heap, RuntimeError};
+use crate::opt::Passes;
+use crate::parser::parse;
+use crate::semant::Semant;
+
+/// Runs `src`, which must type check, with `input` on stdin. Returns the
+/// exit code or the runtime error, and everything printed.
+fn interpret(src: &str, input: &str) -> (Result<i64, RuntimeError>, String) {
+    let exp = parse(src).unwrap_or_else(|e| panic!("failed to parse {src:?}: {e:?}"));
+    let mut semant = Semant::new();
+    semant.check(&exp);
+    assert!(semant.errors().is_empty(), "{:?}", semant.errors());
+    let mut output = Vec::new();
+    let result = run(&exp, &[], &mut input.as_bytes(), &mut output);
+    (result, String::from_utf8(output).unwrap())
+}
+
+fn output(src: &str) -> String {
+    let (result, output) = interpret(src, "");
+    assert_eq!(result, Ok(0), "output so far: {output:?}");
+    output
+}
+
+fn runtime_error(src: &str) -> String {
+    let (result, _) = interpret(src, "");
+    result.expect_err("program should fail").msg.to_string()
+}
+
+#[test]
+fn arithmetic_and_comparisons() {
+    let src = r#"let
+        function b(x: int) = print(if x then "1" else "0")
+    in
+        b(7 / 2 = 3); b(-7 / 2 = -3); b(2 + 3 * 4 = 14);
+        b("abc" < "abd"); b("b" >= "abc"); b("x" = "x");
+        b(1 < 2 & 2 < 1); b(0 | 5)
+    end"#;
+    assert_eq!(output(src), "11111101");
+}
+
+#[test]
+fn nested_functions_close_over_their_scope() {
+    let src = r#"let
+        var a := "outer"
+        function show() = print(a)
+        var a := "shadowed"
+        function counter(): int =
+            let var n := 0
+                function next(): int = (n := n + 1; n)
+            in next(); next(); next() end
+    in
+        show(); print(a);
+        print(chr(ord("0") + counter()))
+    end"#;
+    assert_eq!(output(src), "outershadowed3");
+}
+
+#[test]
+fn records_and_arrays_are_shared_by_reference() {
+    let src = r#"let
+        type point = {x: int, y: int}
+        type row = array of int
+        var p := point {x = 1, y = 2}
+        var q := p
+        var r := row [3] of 0
+        var s := r
+        function digit(n: int) = print(chr(ord("0") + n))
+    in
+        q.y := 5; s[2] := 7;
+        digit(p.y); digit(r[2]); digit(r[0]);
+        digit(p = q); digit(p = point {x = 1, y = 5}); digit(p <> nil)
+    end"#;
+    assert_eq!(output(src), "570101");
+}
+
+#[test]
+fn loops_and_break() {
+    let src = r#"let
+        var i := 0
+    in
+        for j := 1 to 9 do (if j = 4 then break; print(chr(ord("0") + j)));
+        while 1 do (i := i + 1; if i > 2 then break; print("w"));
+        for j := 5 to 4 do print("never")
+    end"#;
+    assert_eq!(output(src), "123ww");
+}
+
+#[test]
+fn string_builtins() {
+    let src = r#"(
+        print(substring("hello", 1, 3));
+        print(concat("a", "b"));
+        print(chr(size("four") + 48));
+        print(chr(not(0) + 48));
+        print(getchar()); print(getchar()); print(getchar())
+    )"#;
+    let (result, output) = interpret(src, "xy");
+    assert_eq!(result, Ok(0));
+    assert_eq!(output, "ellab41xy");
+}
+
+#[test]
+fn numbers_convert_to_and_from_strings() {
+    let src = r#"(
+        print(itoa(-42)); print(" "); print(itoa(9223372036854775807)); print(" ");
+        print(itoa(atoi("-9223372036854775808", 0) + atoi("17", 0)));
+        print(itoa(atoi("", -1))); print(itoa(atoi("-", -1)));
+        print(itoa(atoi(" 1", -1))); print(itoa(atoi("+1", -1)));
+        print(itoa(atoi("9223372036854775808", -1))); print(itoa(atoi("1a", -1)))
+    )"#;
+    let expected = "-42 9223372036854775807 -9223372036854775791-1-1-1-1-1-1";
+    let (result, output) = interpret(src, "");
+    assert_eq!((result, output.as_str()), (Ok(0), expected));
+    for passes in [Passes::NONE, Passes::ALL] {
+        let run = run_ir(src, passes, "");
+        assert_eq!((run.output, run.ending), (expected.into(), Ending::Exit(0)));
+    }
+}
+
+/// Output that keeps what was written between flushes.
+#[derive(Default)]
+struct Flushes {
+    pending: Vec<u8>,
+    flushed: Vec<String>,
+}
+
+impl std::io::Write for Flushes {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        let pending = std::mem::take(&mut self.pending);
+        self.flushed.push(String::from_utf8(pending).unwrap());
+        Ok(())
+    }
+}
+
+#[test]
+fn output_is_flushed_before_input_is_read_and_at_the_end() {
+    let src = r#"(print("name? "); print(getchar()); print("!"); flush(); print("bye"))"#;
+    let exp = parse(src).unwrap();
+    let mut output = Flushes::default();
+    assert_eq!(run(&exp, &[], &mut &b"x"[..], &mut output), Ok(0));
+    assert_eq!(output.flushed, ["name? ", "x!", "bye"]);
+}
+
+#[test]
+fn exit_stops_the_program() {
+    let (result, output) = interpret("(print(\"a\"); exit(3); print(\"b\"))", "");
+    assert_eq!(result, Ok(3));
+    assert_eq!(output, "a");
+}
+
+#[test]
+fn runtime_errors() {
+    let array = "let type a = array of int var v := a [2] of 0 in";
+    assert_eq!(
+        runtime_error(&format!("{array} v[2] end")),
+        "index 2 is out of bounds for array of length 2"
+    );
+    assert_eq!(
+        runtime_error(&format!("{array} v[-1] := 1 end")),
+        "index -1 is out of bounds for array of length 2"
+    );
+    assert_eq!(
+        runtime_error("let type r = {f: int} var x: r := nil in x.f end"),
+        "field `f` of nil record"
+    );
+    assert_eq!(runtime_error("1 / 0"), "division by zero");
+    assert_eq!(
+        runtime_error("let type a = array of int in a [-1] of 0 end"),
+        "negative array size -1"
+    );
+    assert_eq!(
+        runtime_error("let type a = array of int in a [1000000000000000] of 0 end"),
+        "out of memory"
+    );
+    assert_eq!(
+        runtime_error("substring(\"abc\", 2, 2)"),
+        "substring 2..4 is out of bounds for size 3"
+    );
+    assert_eq!(runtime_error("chr(256)"), "`chr` of 256 is out of range");
+    assert_eq!(
+        runtime_error("exit(256)"),
+        "`exit` code 256 is out of range"
+    );
+    assert_eq!(runtime_error("exit(-1)"), "`exit` code -1 is out of range");
+}
+
+/// Runs the IR of `src`, which must type check, optimized with `passes`.
+fn run_ir(src: &str, passes: Passes, input: &str) -> Execution {
+    let exp = parse(src).unwrap_or_else(|e| panic!("failed to parse {src:?}: {e:?}"));
+    let mut semant = Semant::new();
+    semant.check(&exp);
+    assert!(semant.errors().is_empty(), "{:?}", semant.errors());
+    ir::run(semant.fragments(), passes, &[], input.as_bytes(), 1_000_000)
+}
+
+#[test]
+fn ir_runs_print_what_the_tree_interpreter_prints() {
+    let programs = [
+        r#"let
+            type list = {head: int, tail: list}
+            function build(n: int): list =
+                if n = 0 then nil else list {head = n, tail = build(n - 1)}
+            function sum(l: list): int = if l = nil then 0 else l.head + sum(l.tail)
+            var total := sum(build(10))
+            function many(a: int, b: int, c: int, d: int, e: int, f: int, g: int, h: int): int =
+                a + b * 2 + c * 3 + d * 4 + e * 5 + f * 6 + g * 7 + h * 8
+        in
+            print(chr(ord("0") + total / 10)); print(chr(ord("0") + total - total / 10 * 10));
+            print(if many(1, 1, 1, 1, 1, 1, 1, 1) = 36 then " yes" else " no")
+        end"#,
+        r#"let
+            type array_of_int = array of int
+            var a := array_of_int [5] of 3
+            var s := ""
+        in
+            for i := 0 to 4 do a[i] := a[i] * i;
+            for i := 0 to 4 do s := concat(s, chr(ord("0") + a[i]));
+            print(substring(s, 1, 3)); print(if "ab" < "b" then "<" else ">=")
+        end"#,
+        r#"let
+            function echo() = let var c := getchar() in
+                if c <> "" then (print(c); echo()) end
+        in echo() end"#,
+    ];
+    for src in programs {
+        let (result, expected) = interpret(src, "tiger");
+        assert_eq!(result, Ok(0));
+        for passes in [Passes::NONE, Passes::ALL] {
+            let run = run_ir(src, passes, "tiger");
+            assert_eq!(run.ending, Ending::Exit(0), "{src}");
+            assert_eq!(String::from_utf8(run.output).unwrap(), expected, "{src}");
+        }
+    }
+}
+
+#[test]
+fn ir_runs_end_like_the_runtime_library() {
+    let run = run_ir(
+        "let type ints = array of int var a := ints [2] of 0 in a[2] end",
+        Passes::NONE,
+        "",
+    );
+    assert_eq!(
+        run.ending,
+        Ending::Error("index 2 is out of bounds".to_string())
+    );
+    // Nil records and zero divisors fail as in the tree interpreter.
+    for src in [
+        "let type r = {f: int} var x: r := nil in x.f end",
+        "let var zero := 0 in 1 / zero end",
+        "1 / 0",
+        "(print(\"a\"); exit(300))",
+    ] {
+        let expected = runtime_error(src);
+        for passes in [Passes::NONE, Passes::ALL] {
+            let run = run_ir(src, passes, "");
+            assert_eq!(run.ending, Ending::Error(expected.clone()), "{src}");
+        }
+    }
+    // The smallest integer divided by -1 wraps, as in the tree interpreter.
+    let src = "let var m := -9223372036854775807 - 1 var d := -1 in
+        exit(if m / d = m & m / -1 = m then 5 else 6) end";
+    assert_eq!(interpret(src, "").0, Ok(5));
+    for passes in [Passes::NONE, Passes::ALL] {
+        assert_eq!(run_ir(src, passes, "").ending, Ending::Exit(5));
+    }
+    let run = run_ir("(print(\"a\"); exit(3); print(\"b\"))", Passes::ALL, "");
+    assert_eq!((run.output, run.ending), (b"a".to_vec(), Ending::Exit(3)));
+    let run = run_ir("(exit(255); exit(300))", Passes::ALL, "");
+    assert_eq!(run.ending, Ending::Exit(255));
+    let forever = "while 1 do ()";
+    assert_eq!(run_ir(forever, Passes::NONE, "").ending, Ending::StepLimit);
+}
+
+#[test]
+fn programs_see_their_arguments_in_both_interpreters() {
+    let args = ["one".to_string(), "".to_string(), "é".to_string()];
+    let src = "(print(itoa(argc())); for i := 0 to argc() - 1 do print(argv(i)); argv(3))";
+    let exp = parse(src).unwrap();
+    let mut semant = Semant::new();
+    semant.check(&exp);
+    let mut output = Vec::new();
+    let result = run(&exp, &args, &mut &b""[..], &mut output);
+    assert_eq!(output, "3oneé".as_bytes());
+    let msg = "`argv` of 3 is out of range";
+    assert_eq!(result.unwrap_err().to_string(), msg);
+    for passes in [Passes::NONE, Passes::ALL] {
+        let run = ir::run(semant.fragments(), passes, &args, b"", 1_000_000);
+        assert_eq!(
+            (run.output, run.ending),
+            (output.clone(), Ending::Error(msg.into()))
+        );
+    }
+}
+
+#[test]
+fn getenv_reads_the_environment_in_both_interpreters() {
+    // Cargo sets `CARGO_PKG_NAME` for the tests it runs.
+    let src = r#"(print(getenv("CARGO_PKG_NAME")); print("|");
+        print(getenv("TIGER_SURELY_UNSET")); print(getenv("")); print(getenv("A=B")))"#;
+    let expected = format!("{}|", env!("CARGO_PKG_NAME"));
+    let (result, output) = interpret(src, "");
+    assert_eq!((result, output.as_str()), (Ok(0), expected.as_str()));
+    for passes in [Passes::NONE, Passes::ALL] {
+        let run = run_ir(src, passes, "");
+        assert_eq!(
+            (run.output, run.ending),
+            (expected.clone().into(), Ending::Exit(0))
+        );
+    }
+}
+
+#[test]
+fn strings_are_bytes_in_both_interpreters() {
+    let src = r#"let var e := "é" in
+        print(chr(200)); print(chr(ord("0") + size(e)));
+        print(substring(e, 1, 1)); print(chr(ord("0") + ord(e) - 195));
+        print(concat(e, chr(255)))
+    end"#;
+    let exp = parse(src).unwrap();
+    let mut output = Vec::new();
+    assert_eq!(run(&exp, &[], &mut &b""[..], &mut output), Ok(0));
+    assert_eq!(output, b"\xc82\xa90\xc3\xa9\xff");
+    for passes in [Passes::NONE, Passes::ALL] {
+        let run = run_ir(src, passes, "");
+        assert_eq!((run.output, run.ending), (output.clone(), Ending::Exit(0)));
+    }
+}
+
+#[test]
+fn byte_escapes_are_one_byte() {
+    let src = r#"let var s := "\200\255" in
+        print(chr(ord("0") + size("\200"))); print(chr(ord("0") + size(s)));
+        print(s); print(chr(ord("0") + ord(s) - 199))
+    end"#;
+    let exp = parse(src).unwrap();
+    let mut output = Vec::new();
+    assert_eq!(run(&exp, &[], &mut &b""[..], &mut output), Ok(0));
+    assert_eq!(output, b"12\xc8\xff1");
+    for passes in [Passes::NONE, Passes::ALL] {
+        let run = run_ir(src, passes, "");
+        assert_eq!((run.output, run.ending), (output.clone(), Ending::Exit(0)));
+    }
+}
+
+#[test]
+fn loops_up_to_the_largest_integer_end() {
+    let src = "for i := 9223372036854775806 to 9223372036854775807 do print(\"x\")";
+    assert_eq!(output(src), "xx");
+    for passes in [Passes::NONE, Passes::ALL] {
+        let run = run_ir(src, passes, "");
+        assert_eq!((run.output, run.ending), (b"xx".to_vec(), Ending::Exit(0)));
+    }
+}
+
+#[test]
+fn optimized_ir_takes_fewer_steps() {
+    let src = "let var x := 0 function f(n: int): int = n * 1 + 0 + (2 * 3) in for i := 1 to 10 do x := f(i) end";
+    let o0 = run_ir(src, Passes::NONE, "");
+    let o2 = run_ir(src, Passes::ALL, "");
+    assert_eq!(o0.steps.len(), 2);
+    assert_eq!(o0.steps[1].0.to_string(), "tigermain");
+    let f = |run: &Execution| run.steps[0].1;
+    assert!(f(&o2) < f(&o0), "{:?} vs {:?}", o0.steps, o2.steps);
+    assert!(o2.total_steps() < o0.total_steps());
+}
+
+#[test]
+fn every_amount_of_fuel_runs_the_same_program() {
+    let src = "let function f(n: int): int = n * 1 + (2 * 3)
+        in print(itoa(f(4))); if 1 > 2 then print(\"never\") end";
+    let o2 = run_ir(src, Passes::ALL, "");
+    assert_eq!(o2.output, b"10");
+    assert_eq!(o2.rewrites, 6);
+    let (function, _) = o2.last_rewrite.unwrap();
+    assert_eq!(function.to_string(), "tigermain");
+    for fuel in 0..=o2.rewrites {
+        let passes = Passes {
+            fuel: Some(fuel),
+            ..Passes::ALL
+        };
+        let run = run_ir(src, passes, "");
+        assert_eq!((&run.output, &run.ending), (&o2.output, &o2.ending));
+        assert_eq!(run.rewrites, fuel);
+    }
+}
+
+#[test]
+fn random_numbers_are_the_same_in_both_interpreters() {
+    // The runtime library's tests expect the same numbers after `seed(42)`.
+    let src = "let function draw(n: int) = print(concat(itoa(random(n)), \" \")) in
+        for i := 1 to 3 do draw(10);
+        seed(42);
+        for i := 1 to 5 do draw(100);
+        random(0)
+    end";
+    let expected = "5 0 9 13 91 58 64 50 ";
+    let exp = parse(src).unwrap();
+    let mut semant = Semant::new();
+    semant.check(&exp);
+    let mut output = Vec::new();
+    let result = run(&exp, &[], &mut &b""[..], &mut output);
+    assert_eq!(String::from_utf8(output).unwrap(), expected);
+    let msg = "`random` of 0 is out of range";
+    assert_eq!(result.unwrap_err().to_string(), msg);
+    for passes in [Passes::NONE, Passes::ALL] {
+        let run = ir::run(semant.fragments(), passes, &[], b"", 1_000_000);
+        assert_eq!(
+            (run.output, run.ending),
+            (expected.into(), Ending::Error(msg.into()))
+        );
+    }
+}
+
+#[test]
+fn the_clock_never_goes_back_in_either_interpreter() {
+    let src = "let var a := clock() var b := clock() in exit(if 0 <= a & a <= b then 7 else 1) end";
+    assert_eq!(interpret(src, "").0, Ok(7));
+    assert_eq!(run_ir(src, Passes::ALL, "").ending, Ending::Exit(7));
+}
+
+/// Allocates a record of two fields, 16 bytes, an array of three elements
+/// and its length, 32, and two strings of 5 and 7 bytes and their
+/// lengths, 16 each.
+const ALLOCATES_80_BYTES: &str = "let
+    type r = {a: int, b: int}
+    type arr = array of int
+    var x := r {a = 1, b = 2}
+    var y := arr [3] of 0
+    var s := concat(\"ab\", itoa(12345))
+in
+    gc_collect();
+    print(itoa(heap_used()))
+end";
+
+#[test]
+fn heap_used_counts_the_same_words_in_either_interpreter() {
+    assert_eq!(output(ALLOCATES_80_BYTES), "80");
+    for passes in [Passes::NONE, Passes::ALL] {
+        let run = run_ir(ALLOCATES_80_BYTES, passes, "");
+        assert_eq!((run.output, run.ending), (b"80".to_vec(), Ending::Exit(0)));
+    }
+}
+
+#[test]
+fn allocating_past_the_heap_limit_runs_out_of_memory() {
+    let exp = parse(ALLOCATES_80_BYTES).unwrap();
+    Semant::new().check(&exp);
+    let run = |limit| run_with_heap(&exp, &[], &mut &b""[..], &mut Vec::new(), Some(limit));
+    // `itoa` of the count allocates 16 more bytes.
+    assert_eq!(run(96), Ok(0));
+    assert_eq!(run(95).unwrap_err().msg.to_string(), "out of memory");
+    assert_eq!(run(79).unwrap_err().msg.to_string(), "out of memory");
+}
+
+#[test]
+fn heap_sizes_may_have_a_unit() {
+    assert_eq!(heap_size("4096"), Some(4096));
+    assert_eq!(heap_size("64k"), Some(64 << 10));
+    assert_eq!(heap_size("2M"), Some(2 << 20));
+    for bad in ["", "g", "-1", "1.5m", "1kb", "99999999999999999999g"] {
+        assert_eq!(heap_size(bad), None, "{bad:?}");
+    }
+}
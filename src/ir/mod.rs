@@ -0,0 +1,377 @@
+#![allow(dead_code)]
+
+//! The Tree intermediate representation of chapter 7.
+//!
+//! Expressions compute values and may have side effects; statements only
+//! have side effects. `Display` prints trees in the indented prefix form of
+//! the book's `printtree`, naming machine registers.
+//!
+//! Build trees with the shorthands on `Exp` and `Stm` rather than the
+//! variants: they panic on nodes the backend can't handle, such as a `MOVE`
+//! into a constant, so a bad tree is caught where it is made. `check` finds
+//! the same mistakes, and jumps to missing labels, in a whole tree.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::frame;
+use crate::temp::{Label, Temp};
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Exp {
+    Const(i64),
+    /// The address of a label.
+    Name(Label),
+    Temp(Temp),
+    BinOp(BinOp, Box<Exp>, Box<Exp>),
+    /// The word of memory at the given address.
+    Mem(Box<Exp>),
+    Call(Box<Exp>, Vec<Exp>),
+    /// Runs the statement, then evaluates the expression.
+    ESeq(Box<Stm>, Box<Exp>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Stm {
+    /// Stores the value of the second expression into the first, which is a
+    /// `Temp` or a `Mem`.
+    Move(Box<Exp>, Box<Exp>),
+    /// Evaluates the expression and discards the result.
+    Exp(Box<Exp>),
+    /// Jumps to the computed address, which is one of the listed labels.
+    Jump(Box<Exp>, Vec<Label>),
+    CJump(RelOp, Box<Exp>, Box<Exp>, Label, Label),
+    Seq(Box<Stm>, Box<Stm>),
+    Label(Label),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum BinOp {
+    Plus,
+    Minus,
+    Mul,
+    Div,
+    And,
+    Or,
+    LShift,
+    RShift,
+    ARShift,
+    Xor,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RelOp {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    ULt,
+    ULe,
+    UGt,
+    UGe,
+}
+
+impl RelOp {
+    /// The relation that holds exactly when `self` doesn't.
+    pub(crate) fn negate(self) -> RelOp {
+        match self {
+            RelOp::Eq => RelOp::Ne,
+            RelOp::Ne => RelOp::Eq,
+            RelOp::Lt => RelOp::Ge,
+            RelOp::Ge => RelOp::Lt,
+            RelOp::Gt => RelOp::Le,
+            RelOp::Le => RelOp::Gt,
+            RelOp::ULt => RelOp::UGe,
+            RelOp::UGe => RelOp::ULt,
+            RelOp::UGt => RelOp::ULe,
+            RelOp::ULe => RelOp::UGt,
+        }
+    }
+
+    /// The relation with its operands swapped: `a < b` iff `b > a`.
+    pub(crate) fn commute(self) -> RelOp {
+        match self {
+            RelOp::Eq => RelOp::Eq,
+            RelOp::Ne => RelOp::Ne,
+            RelOp::Lt => RelOp::Gt,
+            RelOp::Gt => RelOp::Lt,
+            RelOp::Le => RelOp::Ge,
+            RelOp::Ge => RelOp::Le,
+            RelOp::ULt => RelOp::UGt,
+            RelOp::UGt => RelOp::ULt,
+            RelOp::ULe => RelOp::UGe,
+            RelOp::UGe => RelOp::ULe,
+        }
+    }
+}
+
+/// Shorthands for building trees without spelling out every `Box`.
+impl Exp {
+    pub(crate) fn binop(op: BinOp, left: Exp, right: Exp) -> Exp {
+        Exp::BinOp(op, Box::new(left), Box::new(right))
+    }
+
+    pub(crate) fn mem(addr: Exp) -> Exp {
+        Exp::Mem(Box::new(addr))
+    }
+
+    pub(crate) fn call(func: Exp, args: Vec<Exp>) -> Exp {
+        Exp::Call(Box::new(func), args)
+    }
+
+    pub(crate) fn eseq(stm: Stm, exp: Exp) -> Exp {
+        Exp::ESeq(Box::new(stm), Box::new(exp))
+    }
+
+    /// The word at `offset` bytes from `base`.
+    pub(crate) fn offset(base: Exp, offset: i64) -> Exp {
+        match offset {
+            0 => Exp::mem(base),
+            _ => Exp::mem(Exp::binop(BinOp::Plus, base, Exp::Const(offset))),
+        }
+    }
+}
+
+impl Stm {
+    /// Panics unless `dst` is a `TEMP` or a `MEM`, possibly behind `ESEQ`s.
+    pub(crate) fn mov(dst: Exp, src: Exp) -> Stm {
+        assert!(is_lvalue(&dst), "MOVE into a non-location:\n{dst}");
+        Stm::Move(Box::new(dst), Box::new(src))
+    }
+
+    pub(crate) fn exp(exp: Exp) -> Stm {
+        Stm::Exp(Box::new(exp))
+    }
+
+    pub(crate) fn jump(label: Label) -> Stm {
+        Stm::Jump(Box::new(Exp::Name(label)), vec![label])
+    }
+
+    /// A jump to a computed address, one of `labels`. Panics if `labels` is
+    /// empty or `target` names a label that isn't in it.
+    pub(crate) fn jump_to(target: Exp, labels: Vec<Label>) -> Stm {
+        if let Err(msg) = check_jump(&target, &labels) {
+            panic!("{msg}");
+        }
+        Stm::Jump(Box::new(target), labels)
+    }
+
+    pub(crate) fn cjump(op: RelOp, left: Exp, right: Exp, t: Label, f: Label) -> Stm {
+        Stm::CJump(op, Box::new(left), Box::new(right), t, f)
+    }
+}
+
+/// Chains statements with `Seq`; an empty list is a no-op. The chain is
+/// balanced, so that a long sequence is a shallow tree rather than one as
+/// deep as it is long, which would overflow the stack of every pass that
+/// recurses over it.
+pub(crate) fn seq(stms: impl IntoIterator<Item = Stm>) -> Stm {
+    let mut stms: Vec<Stm> = stms.into_iter().collect();
+    if stms.is_empty() {
+        return Stm::exp(Exp::Const(0));
+    }
+    // Pair up neighbours until one tree is left.
+    while stms.len() > 1 {
+        let mut paired = Vec::with_capacity(stms.len().div_ceil(2));
+        let mut rest = stms.into_iter();
+        while let Some(a) = rest.next() {
+            paired.push(match rest.next() {
+                Some(b) => Stm::Seq(Box::new(a), Box::new(b)),
+                None => a,
+            });
+        }
+        stms = paired;
+    }
+    stms.pop().expect("one tree is left")
+}
+
+fn is_lvalue(exp: &Exp) -> bool {
+    match exp {
+        Exp::Temp(_) | Exp::Mem(_) => true,
+        Exp::ESeq(_, exp) => is_lvalue(exp),
+        _ => false,
+    }
+}
+
+fn check_jump(target: &Exp, labels: &[Label]) -> Result<(), String> {
+    match target {
+        _ if labels.is_empty() => Err("JUMP with no possible targets".to_string()),
+        Exp::Name(label) if !labels.contains(label) => Err(format!(
+            "JUMP to {label}, which is not among its targets {labels:?}"
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that `body`, a whole function, is well formed: every `MOVE` is
+/// into a location, every `JUMP` lists where it may go, and the labels it
+/// jumps to are defined in it exactly once.
+pub(crate) fn check(body: &Stm) -> Result<(), String> {
+    let mut defined = HashSet::new();
+    let mut targets = Vec::new();
+    check_stm(body, &mut defined, &mut targets)?;
+    match targets.into_iter().find(|label| !defined.contains(label)) {
+        Some(label) => Err(format!("jump to {label}, which is never defined")),
+        None => Ok(()),
+    }
+}
+
+fn check_stm(
+    mut stm: &Stm,
+    defined: &mut HashSet<Label>,
+    targets: &mut Vec<Label>,
+) -> Result<(), String> {
+    // The right spine of a `Seq` chain is walked in a loop, so that a chain
+    // built by hand, as long as it may be, does not recurse once per link.
+    while let Stm::Seq(a, b) = stm {
+        check_stm(a, defined, targets)?;
+        stm = b;
+    }
+    match stm {
+        Stm::Move(dst, src) => {
+            if !is_lvalue(dst) {
+                return Err(format!("MOVE into a non-location:\n{dst}"));
+            }
+            check_exp(dst, defined, targets)?;
+            check_exp(src, defined, targets)
+        }
+        Stm::Exp(exp) => check_exp(exp, defined, targets),
+        Stm::Jump(target, labels) => {
+            check_jump(target, labels)?;
+            targets.extend(labels);
+            check_exp(target, defined, targets)
+        }
+        Stm::CJump(_, a, b, t, f) => {
+            targets.extend([*t, *f]);
+            check_exp(a, defined, targets)?;
+            check_exp(b, defined, targets)
+        }
+        Stm::Seq(..) => unreachable!("walked above"),
+        Stm::Label(label) => match defined.insert(*label) {
+            true => Ok(()),
+            false => Err(format!("label {label} is defined twice")),
+        },
+    }
+}
+
+fn check_exp(
+    exp: &Exp,
+    defined: &mut HashSet<Label>,
+    targets: &mut Vec<Label>,
+) -> Result<(), String> {
+    match exp {
+        Exp::Const(_) | Exp::Name(_) | Exp::Temp(_) => Ok(()),
+        Exp::BinOp(_, a, b) => {
+            check_exp(a, defined, targets)?;
+            check_exp(b, defined, targets)
+        }
+        Exp::Mem(addr) => check_exp(addr, defined, targets),
+        Exp::Call(func, args) => {
+            check_exp(func, defined, targets)?;
+            args.iter()
+                .try_for_each(|arg| check_exp(arg, defined, targets))
+        }
+        Exp::ESeq(stm, exp) => {
+            check_stm(stm, defined, targets)?;
+            check_exp(exp, defined, targets)
+        }
+    }
+}
+
+impl fmt::Display for Stm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_stm(f, self, 0)
+    }
+}
+
+impl fmt::Display for Exp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_exp(f, self, 0)
+    }
+}
+
+fn indent(f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+    write!(f, "{:1$}", "", depth)
+}
+
+fn write_stm(f: &mut fmt::Formatter<'_>, stm: &Stm, depth: usize) -> fmt::Result {
+    indent(f, depth)?;
+    match stm {
+        Stm::Seq(a, b) => {
+            writeln!(f, "SEQ(")?;
+            write_stm(f, a, depth + 1)?;
+            writeln!(f, ",")?;
+            write_stm(f, b, depth + 1)?;
+            write!(f, ")")
+        }
+        Stm::Label(label) => write!(f, "LABEL {label}"),
+        Stm::Jump(target, _) => {
+            writeln!(f, "JUMP(")?;
+            write_exp(f, target, depth + 1)?;
+            write!(f, ")")
+        }
+        Stm::CJump(op, a, b, t, fl) => {
+            writeln!(f, "CJUMP({op:?},")?;
+            write_exp(f, a, depth + 1)?;
+            writeln!(f, ",")?;
+            write_exp(f, b, depth + 1)?;
+            writeln!(f, ",")?;
+            indent(f, depth + 1)?;
+            write!(f, "{t},{fl})")
+        }
+        Stm::Move(dst, src) => {
+            writeln!(f, "MOVE(")?;
+            write_exp(f, dst, depth + 1)?;
+            writeln!(f, ",")?;
+            write_exp(f, src, depth + 1)?;
+            write!(f, ")")
+        }
+        Stm::Exp(e) => {
+            writeln!(f, "EXP(")?;
+            write_exp(f, e, depth + 1)?;
+            write!(f, ")")
+        }
+    }
+}
+
+fn write_exp(f: &mut fmt::Formatter<'_>, exp: &Exp, depth: usize) -> fmt::Result {
+    indent(f, depth)?;
+    match exp {
+        Exp::BinOp(op, a, b) => {
+            writeln!(f, "BINOP({op:?},")?;
+            write_exp(f, a, depth + 1)?;
+            writeln!(f, ",")?;
+            write_exp(f, b, depth + 1)?;
+            write!(f, ")")
+        }
+        Exp::Mem(e) => {
+            writeln!(f, "MEM(")?;
+            write_exp(f, e, depth + 1)?;
+            write!(f, ")")
+        }
+        Exp::Temp(t) => match frame::register_name(*t) {
+            Some(reg) => write!(f, "TEMP {reg}"),
+            None => write!(f, "TEMP {t}"),
+        },
+        Exp::ESeq(s, e) => {
+            writeln!(f, "ESEQ(")?;
+            write_stm(f, s, depth + 1)?;
+            writeln!(f, ",")?;
+            write_exp(f, e, depth + 1)?;
+            write!(f, ")")
+        }
+        Exp::Name(label) => write!(f, "NAME {label}"),
+        Exp::Const(n) => write!(f, "CONST {n}"),
+        Exp::Call(func, args) => {
+            writeln!(f, "CALL(")?;
+            write_exp(f, func, depth + 1)?;
+            for arg in args {
+                writeln!(f, ",")?;
+                write_exp(f, arg, depth + 1)?;
+            }
+            write!(f, ")")
+        }
+    }
+}
@@ -0,0 +1,275 @@
+#![allow(dead_code)]
+
+//! Just enough JSON for the Language Server Protocol, the JSON forms of
+//! `--emit` and the token snapshots of `tigerc lexdiff`: a value type, a
+//! parser and a compact printer.
+
+#[cfg(test)]
+mod tests;
+
+use std::fmt::{self, Write};
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    /// A number written without a fraction or exponent that fits, kept
+    /// exact rather than rounded to the nearest `f64`.
+    Int(i64),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// Members in the order they were written.
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// An object from `(key, value)` pairs.
+    pub(crate) fn object<'a>(members: impl IntoIterator<Item = (&'a str, Json)>) -> Json {
+        Json::Object(
+            members
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+        )
+    }
+
+    pub(crate) fn str(s: impl Into<String>) -> Json {
+        Json::String(s.into())
+    }
+
+    /// The member `key` of an object, or `Null`.
+    pub(crate) fn get(&self, key: &str) -> &Json {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find(|(k, _)| k == key)
+                .map_or(&Json::Null, |(_, v)| v),
+            _ => &Json::Null,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_u32(&self) -> Option<u32> {
+        match *self {
+            Json::Int(n) => u32::try_from(n).ok(),
+            Json::Number(n) if n >= 0.0 && n <= u32::MAX as f64 && n.fract() == 0.0 => {
+                Some(n as u32)
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_array(&self) -> &[Json] {
+        match self {
+            Json::Array(items) => items,
+            _ => &[],
+        }
+    }
+}
+
+impl From<u32> for Json {
+    fn from(n: u32) -> Json {
+        Json::Int(n.into())
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Bool(b)
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{b}"),
+            Json::Int(n) => write!(f, "{n}"),
+            Json::Number(n) => write!(f, "{n}"),
+            Json::String(s) => write_string(f, s),
+            Json::Array(items) => {
+                f.write_char('[')?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                f.write_char(']')
+            }
+            Json::Object(members) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+/// Parses a whole JSON text.
+pub(crate) fn parse(text: &str) -> Result<Json, String> {
+    let mut p = Parser { rest: text };
+    let value = p.value()?;
+    match p.skip_whitespace().chars().next() {
+        None => Ok(value),
+        Some(c) => Err(format!("unexpected `{c}` after the value")),
+    }
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) -> &'a str {
+        self.rest = self.rest.trim_start_matches([' ', '\t', '\n', '\r']);
+        self.rest
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        match self.skip_whitespace().strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        match self.eat(token) {
+            true => Ok(()),
+            false => Err(format!("expected `{token}`")),
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        match self.skip_whitespace().chars().next() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => self.string().map(Json::String),
+            Some('-' | '0'..='9') => self.number(),
+            _ if self.eat("null") => Ok(Json::Null),
+            _ if self.eat("true") => Ok(Json::Bool(true)),
+            _ if self.eat("false") => Ok(Json::Bool(false)),
+            Some(c) => Err(format!("unexpected `{c}`")),
+            None => Err("unexpected end of input".to_string()),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect("{")?;
+        let mut members = Vec::new();
+        while !self.eat("}") {
+            if !members.is_empty() {
+                self.expect(",")?;
+            }
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(":")?;
+            members.push((key, self.value()?));
+        }
+        Ok(Json::Object(members))
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect("[")?;
+        let mut items = Vec::new();
+        while !self.eat("]") {
+            if !items.is_empty() {
+                self.expect(",")?;
+            }
+            items.push(self.value()?);
+        }
+        Ok(Json::Array(items))
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let len = self
+            .rest
+            .find(|c: char| !matches!(c, '-' | '+' | '.' | 'e' | 'E' | '0'..='9'))
+            .unwrap_or(self.rest.len());
+        let text = &self.rest[..len];
+        let n = match text.parse() {
+            Ok(n) => Json::Int(n),
+            Err(_) => Json::Number(
+                text.parse()
+                    .map_err(|_| format!("invalid number `{text}`"))?,
+            ),
+        };
+        self.rest = &self.rest[len..];
+        Ok(n)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect("\"")?;
+        let mut out = String::new();
+        let mut chars = self.rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.rest = &self.rest[i + 1..];
+                    return Ok(out);
+                }
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('n') => out.push('\n'),
+                    Some('r') => out.push('\r'),
+                    Some('t') => out.push('\t'),
+                    Some('b') => out.push('\u{8}'),
+                    Some('f') => out.push('\u{c}'),
+                    Some(c @ ('"' | '\\' | '/')) => out.push(c),
+                    Some('u') => {
+                        let first = utf16_unit(&mut chars)?;
+                        // Characters outside the BMP come as a surrogate pair.
+                        let mut units = vec![first];
+                        if (0xD800..0xDC00).contains(&first) {
+                            if let (Some((_, '\\')), Some((_, 'u'))) = (chars.next(), chars.next())
+                            {
+                                units.push(utf16_unit(&mut chars)?);
+                            }
+                        }
+                        out.extend(char::decode_utf16(units).map(|c| c.unwrap_or('\u{FFFD}')));
+                    }
+                    _ => return Err("invalid escape in string".to_string()),
+                },
+                c => out.push(c),
+            }
+        }
+        Err("unterminated string".to_string())
+    }
+}
+
+/// The four hex digits of a `\\u` escape.
+fn utf16_unit(chars: &mut std::str::CharIndices<'_>) -> Result<u16, String> {
+    let hex: String = chars.take(4).map(|(_, c)| c).collect();
+    u16::from_str_radix(&hex, 16).map_err(|_| format!("invalid escape `\\u{hex}`"))
+}
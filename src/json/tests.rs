@@ -0,0 +1,31 @@
+use super::*;
+
+#[test]
+fn json_round_trips() {
+    let text = r#"{"a":[1,-2.5,true,null],"b":"x\"\né😀"}"#;
+    let value = parse(text).unwrap();
+    assert_eq!(value.get("b").as_str(), Some("x\"\né😀"));
+    assert_eq!(value.get("a").as_array()[0].as_u32(), Some(1));
+    assert_eq!(parse(&value.to_string()), Ok(value));
+    assert!(parse("{\"a\": }").is_err());
+}
+
+#[test]
+fn integers_are_exact() {
+    let text = "[9223372036854775807,-9223372036854775808,1e3,2.0]";
+    let value = parse(text).unwrap();
+    assert_eq!(value.as_array()[0], Json::Int(i64::MAX));
+    assert_eq!(value.as_array()[2], Json::Number(1000.0));
+    assert_eq!(
+        value.to_string(),
+        "[9223372036854775807,-9223372036854775808,1000,2]"
+    );
+}
+
+#[test]
+fn strings_escape_control_characters() {
+    assert_eq!(
+        Json::str("a\"b\\c\n\u{1}").to_string(),
+        r#""a\"b\\c\n\u0001""#
+    );
+}
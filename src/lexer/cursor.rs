@@ -1,76 +1,103 @@
+use std::ops::Range;
 use std::str::Chars;
 
-pub struct Cursor<'a> {
-    len: usize,
+/// A forward-only cursor over source text.
+///
+/// Peeks return `None` past the end rather than a sentinel character, so a
+/// literal `'\0'` in the input can't be mistaken for end of file. The cursor
+/// also tracks the span consumed since the last `start_span`, which is how
+/// the lexer measures tokens; other scanners (trivia, includes) can use it
+/// the same way.
+#[derive(Clone)]
+pub struct SourceCursor<'a> {
+    src: &'a str,
     chars: Chars<'a>,
-    prev: char,
+    /// Byte offset where the current span started.
+    span_start: usize,
+    prev: Option<char>,
 }
 
-pub(crate) const EOF_CHAR: char = '\0';
-
-impl<'a> Cursor<'a> {
-    pub fn new(input: &'a str) -> Cursor<'a> {
-        Cursor {
-            len: input.len(),
-            chars: input.chars(),
-            prev: EOF_CHAR,
+impl<'a> SourceCursor<'a> {
+    pub fn new(src: &'a str) -> SourceCursor<'a> {
+        SourceCursor {
+            src,
+            chars: src.chars(),
+            span_start: 0,
+            prev: None,
         }
     }
 
+    /// The text not consumed yet.
     pub fn as_str(&self) -> &'a str {
         self.chars.as_str()
     }
 
-    pub(crate) fn prev(&self) -> char {
-        self.prev
+    /// Byte offset of the next character.
+    pub fn offset(&self) -> usize {
+        self.src.len() - self.chars.as_str().len()
     }
 
-    /// Getting `EOF_CHAR` doesn't always mean actual end of file,
-    /// it should be checked with `is_eof` method.
-    pub fn peek_first(&self) -> char {
-        self.chars.clone().nth(0).unwrap_or(EOF_CHAR)
+    /// The last character consumed, if any.
+    pub fn prev(&self) -> Option<char> {
+        self.prev
     }
 
-    pub(crate) fn peek_second(&self) -> char {
-        self.chars.clone().nth(1).unwrap_or(EOF_CHAR)
+    pub fn peek(&self) -> Option<char> {
+        self.chars.clone().next()
     }
 
-    pub fn peek_third(&self) -> char {
-        self.chars.clone().nth(2).unwrap_or(EOF_CHAR)
+    /// The character `n` places ahead; `peek_n(0)` is `peek()`.
+    pub fn peek_n(&self, n: usize) -> Option<char> {
+        self.chars.clone().nth(n)
     }
 
-    /// Checks if there is nothing more to consume.
-    pub(crate) fn is_eof(&self) -> bool {
+    pub fn is_eof(&self) -> bool {
         self.chars.as_str().is_empty()
     }
 
-    /// Returns amount of already consumed symbols.
-    pub(crate) fn len_advanced(&self) -> u32 {
-        (self.len - self.chars.as_str().len()) as u32
-    }
-
-    /// Resets the number of bytes consumed to 0.
-    pub(crate) fn reset_len(&mut self) {
-        self.len = self.chars.as_str().len();
-    }
-
     /// Moves to the next character.
-    pub(crate) fn bump(&mut self) -> Option<char> {
+    pub fn bump(&mut self) -> Option<char> {
         let c = self.chars.next()?;
-        self.prev = c;
+        self.prev = Some(c);
         Some(c)
     }
 
-    pub(crate) fn bump_n(&mut self, n: u32) {
-        for _ in 0..n {
+    /// Consumes up to `n` characters, returning how many there were.
+    pub fn bump_n(&mut self, n: usize) -> usize {
+        (0..n).take_while(|_| self.bump().is_some()).count()
+    }
+
+    /// Consumes the next character if it is `c`.
+    pub fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
             self.bump();
+            true
+        } else {
+            false
         }
     }
 
-    /// Eats symbols while predicate returns true or until the end of file is reached.
-    pub(crate) fn bump_while(&mut self, mut predicate: impl FnMut(char) -> bool) {
-        while predicate(self.peek_first()) && !self.is_eof() {
+    /// Consumes characters while `predicate` holds, returning them.
+    pub fn bump_while(&mut self, mut predicate: impl FnMut(char) -> bool) -> &'a str {
+        let start = self.offset();
+        while self.peek().is_some_and(&mut predicate) {
             self.bump();
         }
+        &self.src[start..self.offset()]
+    }
+
+    /// Starts a new span at the current position.
+    pub fn start_span(&mut self) {
+        self.span_start = self.offset();
+    }
+
+    /// Byte range consumed since the last `start_span`.
+    pub fn span(&self) -> Range<usize> {
+        self.span_start..self.offset()
+    }
+
+    /// Text consumed since the last `start_span`.
+    pub fn consumed(&self) -> &'a str {
+        &self.src[self.span()]
     }
 }
@@ -0,0 +1,88 @@
+//! Relexing after an edit, for editors that keep the tokens of an open file.
+//!
+//! Only the text around the edit is lexed again. Lexing restarts a little
+//! before the edit, since a token's kind can depend on the characters after
+//! it (`:` and `:=`, `1.` and `1.5`). Once a new token starts where an old
+//! one did, past the edit, the rest of the old tokens are kept, shifted by
+//! the change in length: a token depends only on the text from where the
+//! lexer started scanning it, so everything after would come out the same.
+
+use super::{LexerConfig, Span, StringReader, Token, TokenKind};
+
+/// How far past its end the lexer may look to decide a token, as in `1e+5`.
+const LOOKAHEAD: u32 = 2;
+
+/// Replaces the bytes `pos` of a source with `text`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct TextEdit {
+    pub(crate) pos: Span,
+    pub(crate) text: String,
+}
+
+impl TextEdit {
+    pub(crate) fn new(pos: Span, text: impl Into<String>) -> TextEdit {
+        TextEdit {
+            pos,
+            text: text.into(),
+        }
+    }
+
+    /// `src` with the edit made.
+    pub(crate) fn apply(&self, src: &str) -> String {
+        let (lo, hi) = (self.pos.lo() as usize, self.pos.hi() as usize);
+        [&src[..lo], &self.text, &src[hi..]].concat()
+    }
+}
+
+/// The tokens of `src`, the source after `edit`, given `old`, every token of
+/// the source before it up to `EOF` as lexed with `config`. Lexical errors
+/// are not collected; lex the whole file to get those.
+pub(crate) fn relex(old: &[Token], edit: &TextEdit, src: &str, config: LexerConfig) -> Vec<Token> {
+    let delta = edit.text.len() as i64 - (edit.pos.hi() - edit.pos.lo()) as i64;
+    let shift = |offset: u32| (offset as i64 + delta) as u32;
+    let edit_end = edit.pos.lo() + edit.text.len() as u32;
+
+    // Keep the tokens that end well before the edit.
+    let keep = old.partition_point(|t| t.pos.hi() + LOOKAHEAD < edit.pos.lo());
+    let start = old.get(keep).map_or(0, |t| t.leading.lo());
+    let mut tokens = old[..keep].to_vec();
+
+    let mut reader = StringReader::new(&src[start as usize..]).with_config(config);
+    // Old tokens that start after the edit, where the new ones may line up.
+    let mut rest = old[keep..]
+        .iter()
+        .skip_while(|t| t.leading.lo() < edit.pos.hi());
+    let mut next_old = rest.next();
+    loop {
+        let token = at(reader.next_token(), start);
+        let scan_start = token.leading.lo();
+        while next_old.is_some_and(|old| shift(old.leading.lo()) < scan_start) {
+            next_old = rest.next();
+        }
+        if let Some(old) = next_old {
+            if scan_start >= edit_end && shift(old.leading.lo()) == scan_start {
+                tokens.extend(std::iter::once(old).chain(rest).map(|t| moved(t, shift)));
+                return tokens;
+            }
+        }
+        let eof = token.kind == TokenKind::EOF;
+        tokens.push(token);
+        if eof {
+            return tokens;
+        }
+    }
+}
+
+/// `token`, lexed from text starting at `offset`, with absolute spans.
+fn at(token: Token, offset: u32) -> Token {
+    moved(&token, |p| p + offset)
+}
+
+fn moved(token: &Token, shift: impl Fn(u32) -> u32) -> Token {
+    let span = |pos: Span| pos.map(&shift);
+    Token {
+        pos: span(token.pos),
+        leading: span(token.leading),
+        ..token.clone()
+    }
+}
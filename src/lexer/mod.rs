@@ -1,9 +1,21 @@
 #![allow(dead_code)]
+// Token kinds follow the terminal names used in Appel's Tiger grammar.
+#![allow(clippy::upper_case_acronyms)]
 
-pub(crate) mod cursor;
+mod cursor;
+pub(crate) mod incremental;
+pub(crate) mod stream;
+#[cfg(test)]
 mod tests;
 
-use cursor::Cursor;
+use std::fmt;
+
+use crate::diagnostics::Message;
+use crate::limits::Limits;
+use crate::span::{FileId, Span};
+use crate::symbol::{ByteStr, Symbol};
+pub use cursor::SourceCursor;
+pub(crate) use stream::TokenStream;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub(crate) enum TokenKind {
@@ -109,47 +121,352 @@ pub(crate) enum TokenKind {
     WHITESPACE,
 }
 
+impl TokenKind {
+    /// The fixed spelling of punctuation, operators and keywords; `None` for
+    /// tokens whose text varies.
+    pub(crate) fn text(&self) -> Option<&'static str> {
+        use TokenKind::*;
+        Some(match self {
+            COMMA => ",",
+            COLON => ":",
+            SEMICOLON => ";",
+            LPAREN => "(",
+            RPAREN => ")",
+            LBRACK => "[",
+            RBRACK => "]",
+            LCURLY => "{",
+            RCURLY => "}",
+            DOT => ".",
+            ASSIGN => ":=",
+            PLUS => "+",
+            MINUS => "-",
+            TIMES => "*",
+            DIVIDE => "/",
+            PERCENT => "%",
+            EQ => "=",
+            NEQ => "<>",
+            LT => "<",
+            LE => "<=",
+            GT => ">",
+            GE => ">=",
+            AND => "&",
+            OR => "|",
+            ARRAY => "array",
+            IF => "if",
+            THEN => "then",
+            ELSE => "else",
+            WHILE => "while",
+            FOR => "for",
+            TO => "to",
+            DO => "do",
+            LET => "let",
+            IN => "in",
+            END => "end",
+            OF => "of",
+            BREAK => "break",
+            FUNCTION => "function",
+            VAR => "var",
+            TYPE => "type",
+            NIL => "nil",
+            ID | STRING | INT | FLOAT | COMMENT | EOF | UNKNOWN | WHITESPACE => return None,
+        })
+    }
+}
+
+/// Literal payload of a token, decoded once by the lexer.
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) enum TokenValue {
+    None,
+    Ident(Symbol),
+    Int(i64),
+    Float(f64),
+    /// String contents with the quotes removed and escapes decoded, as
+    /// bytes. Interned so that lexing doesn't allocate per string literal.
+    Str(ByteStr),
+}
+
 #[derive(Clone, PartialEq, Debug)]
 pub(crate) struct Token {
     kind: TokenKind,
-    pos: TokenPos,
+    pos: Span,
+    value: TokenValue,
+    /// The whitespace and comments skipped right before this token. Empty,
+    /// at `pos.lo()`, when nothing was skipped.
+    leading: Span,
 }
 impl Token {
-    fn new(kind: TokenKind, pos: TokenPos) -> Token {
-        Token { kind, pos }
+    fn new(kind: TokenKind, pos: Span) -> Token {
+        Token {
+            kind,
+            pos,
+            value: TokenValue::None,
+            leading: pos.shrink_to_lo(),
+        }
+    }
+
+    pub(crate) fn value(&self) -> &TokenValue {
+        &self.value
+    }
+
+    /// Name of an `ID` token.
+    pub(crate) fn symbol(&self) -> Option<Symbol> {
+        match self.value {
+            TokenValue::Ident(sym) => Some(sym),
+            _ => None,
+        }
+    }
+
+    /// Value of an `INT` token, or `None` if it doesn't fit in an `i64`.
+    pub(crate) fn int(&self) -> Option<i64> {
+        match self.value {
+            TokenValue::Int(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn float(&self) -> Option<f64> {
+        match self.value {
+            TokenValue::Float(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    /// Decoded contents of a `STRING` token.
+    pub(crate) fn string(&self) -> Option<ByteStr> {
+        match &self.value {
+            TokenValue::Str(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn kind(&self) -> &TokenKind {
+        &self.kind
+    }
+
+    pub(crate) fn pos(&self) -> &Span {
+        &self.pos
+    }
+
+    /// Span of the trivia the lexer skipped before this token.
+    pub(crate) fn leading_trivia(&self) -> Span {
+        self.leading
+    }
+
+    /// The skipped trivia as `WHITESPACE` and `COMMENT` tokens, relexed from
+    /// `src`, the text this token was lexed from.
+    pub(crate) fn trivia(&self, src: &str) -> Vec<Token> {
+        let (lo, hi) = (self.leading.lo(), self.leading.hi());
+        StringReader::new(&src[lo as usize..hi as usize])
+            .with_config(LexerConfig::LOSSLESS)
+            .with_file(self.leading.file)
+            .filter(|t| t.kind != TokenKind::EOF)
+            .map(|t| Token::new(t.kind, t.pos.map(|p| lo + p)))
+            .collect()
     }
 }
 
-#[derive(Clone, PartialEq, Debug)]
-pub(crate) struct TokenPos(u32, u32);
-impl TokenPos {
-    fn new(lo: u32, hi: u32) -> TokenPos {
-        TokenPos(lo, hi)
+/// A malformed token. The lexer still produces a token for the offending
+/// text (`STRING`, `COMMENT`, `INT`, `FLOAT` or `UNKNOWN`) so that lexing
+/// can continue.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum LexError {
+    UnterminatedString(Span),
+    UnterminatedComment(Span),
+    InvalidEscape(Span),
+    UnexpectedChar(char, Span),
+    /// An `INT` literal that doesn't fit in an `i64`.
+    IntegerOverflow(Span),
+    /// A number with what is wrong with it, e.g. a `.` without digits after.
+    MalformedNumber(&'static str, Span),
+    /// A comment nested deeper than `Limits::max_comment_nesting`, at the
+    /// opening that went past it.
+    CommentTooDeep(u32, Span),
+    /// A string literal longer than `Limits::max_string_length`.
+    StringTooLong(usize, Span),
+}
+
+impl LexError {
+    pub(crate) fn pos(&self) -> Span {
+        match self {
+            LexError::UnterminatedString(pos)
+            | LexError::UnterminatedComment(pos)
+            | LexError::InvalidEscape(pos)
+            | LexError::UnexpectedChar(_, pos)
+            | LexError::IntegerOverflow(pos)
+            | LexError::MalformedNumber(_, pos)
+            | LexError::CommentTooDeep(_, pos)
+            | LexError::StringTooLong(_, pos) => *pos,
+        }
+    }
+}
+
+impl LexError {
+    pub(crate) fn message(&self) -> Message {
+        match self {
+            LexError::UnterminatedString(_) => Message::new("E0101"),
+            LexError::UnterminatedComment(_) => Message::new("E0102"),
+            LexError::InvalidEscape(_) => Message::new("E0103"),
+            LexError::UnexpectedChar(c, _) => {
+                Message::new("E0104").arg("char", c.escape_debug().to_string())
+            }
+            LexError::IntegerOverflow(_) => Message::new("E0105"),
+            LexError::MalformedNumber(reason, _) => Message::new("E0106").arg("reason", *reason),
+            LexError::CommentTooDeep(max, _) => Message::new("E0107").arg("max", *max),
+            LexError::StringTooLong(max, _) => Message::new("E0108").arg("max", *max),
+        }
     }
 }
 
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+/// Words the object and module extensions of Tiger use as keywords. They
+/// are still identifiers here, but using one as a name is warned about.
+pub(crate) const FUTURE_KEYWORDS: &[&str] =
+    &["class", "extends", "method", "new", "import", "primitive"];
+
+/// Which trivia the lexer turns into tokens. Trivia it doesn't keep is
+/// still recorded, as the `leading_trivia` span of the next token.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct LexerConfig {
+    /// Emit runs of whitespace as `WHITESPACE` tokens.
+    pub(crate) preserve_whitespace: bool,
+    /// Emit comments as `COMMENT` tokens.
+    pub(crate) preserve_comments: bool,
+    /// Identifiers set aside as keywords of a later dialect. They lex as
+    /// `ID`, and each one is also recorded as a `ReservedWord`.
+    pub(crate) reserved_words: &'static [&'static str],
+}
+
+impl LexerConfig {
+    /// Every byte of the source is covered by exactly one token, as
+    /// formatters and highlighters need.
+    pub(crate) const LOSSLESS: LexerConfig = LexerConfig {
+        preserve_whitespace: true,
+        preserve_comments: true,
+        reserved_words: FUTURE_KEYWORDS,
+    };
+
+    /// Only the tokens the grammar is made of.
+    pub(crate) const PARSER: LexerConfig = LexerConfig {
+        preserve_whitespace: false,
+        preserve_comments: false,
+        reserved_words: FUTURE_KEYWORDS,
+    };
+}
+
+impl Default for LexerConfig {
+    /// Comments are tokens but whitespace is not.
+    fn default() -> LexerConfig {
+        LexerConfig {
+            preserve_whitespace: false,
+            preserve_comments: true,
+            reserved_words: FUTURE_KEYWORDS,
+        }
+    }
+}
+
+/// An identifier that is reserved as a keyword of a later dialect.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ReservedWord {
+    pub(crate) name: Symbol,
+    pub(crate) pos: Span,
+}
+
 pub(crate) struct StringReader<'a> {
     src: &'a str,
-    cursor: Cursor<'a>,
+    /// The file `src` is the text of, for the spans of its tokens.
+    file: FileId,
+    cursor: SourceCursor<'a>,
     pos: u32,
+    /// Reused buffer for decoding string literals.
+    scratch: Vec<u8>,
+    /// Whether the last string literal had its closing quote.
+    string_closed: bool,
+    errors: Vec<LexError>,
+    /// Identifiers among `config.reserved_words`, in source order.
+    reserved: Vec<ReservedWord>,
+    /// Set once `EOF` has been yielded by the `Iterator` impl.
+    finished: bool,
+    /// Whether identifiers may contain non-ASCII letters and digits.
+    unicode_identifiers: bool,
+    config: LexerConfig,
+    limits: Limits,
 }
 impl StringReader<'_> {
-    fn new<'a>(src: &'a str) -> StringReader<'a> {
+    pub(crate) fn new<'a>(src: &'a str) -> StringReader<'a> {
         StringReader {
             src,
-            cursor: Cursor::new(&src),
+            file: FileId::MAIN,
+            cursor: SourceCursor::new(src),
             pos: 0,
+            scratch: Vec::new(),
+            string_closed: false,
+            errors: Vec::new(),
+            reserved: Vec::new(),
+            finished: false,
+            unicode_identifiers: false,
+            config: LexerConfig::default(),
+            limits: Limits::default(),
         }
     }
+
+    pub(crate) fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub(crate) fn with_config(mut self, config: LexerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Spans tokens as being in `file` rather than `FileId::MAIN`.
+    pub(crate) fn with_file(mut self, file: FileId) -> Self {
+        self.file = file;
+        self
+    }
+
+    /// Also accepts identifiers made of Unicode letters and digits, beyond
+    /// the ASCII ones of the Tiger spec. Letters and digits are the
+    /// `Alphabetic` and `Numeric` characters, close to `XID_Start` and
+    /// `XID_Continue` for the scripts programs are written in.
+    pub(crate) fn with_unicode_identifiers(mut self, enabled: bool) -> Self {
+        self.unicode_identifiers = enabled;
+        self
+    }
 }
 
 impl StringReader<'_> {
+    fn span(&self, lo: u32, hi: u32) -> Span {
+        Span::in_file(self.file, lo, hi)
+    }
+
+    /// Errors found so far, in source order.
+    pub(crate) fn errors(&self) -> &[LexError] {
+        &self.errors
+    }
+
+    /// Reserved words used as identifiers so far, in source order.
+    pub(crate) fn reserved_words(&self) -> &[ReservedWord] {
+        &self.reserved
+    }
+
     pub fn next_token(&mut self) -> Token {
+        let trivia_start = self.pos;
         loop {
             let start = self.pos;
             let ch = match self.cursor.bump() {
                 Some(c) => c,
-                None => return Token::new(TokenKind::EOF, TokenPos(self.pos, self.pos)),
+                None => {
+                    return Token {
+                        leading: self.span(trivia_start, start),
+                        ..Token::new(TokenKind::EOF, self.span(start, start))
+                    }
+                }
             };
 
             // Calculate kind. We also advance cursor to the next token in this process
@@ -161,8 +478,8 @@ impl StringReader<'_> {
                 ')' => TokenKind::RPAREN,
                 '[' => TokenKind::LBRACK,
                 ']' => TokenKind::RBRACK,
-                '{' => TokenKind::RCURLY,
-                '}' => TokenKind::LCURLY,
+                '{' => TokenKind::LCURLY,
+                '}' => TokenKind::RCURLY,
                 '.' => TokenKind::DOT,
 
                 ':' => self.colon(),
@@ -185,184 +502,342 @@ impl StringReader<'_> {
 
                 c => match c {
                     'a'..='z' | 'A'..='Z' => self.cook_identifier(start),
-                    _ => TokenKind::UNKNOWN,
+                    c if self.unicode_identifiers && c.is_alphabetic() => {
+                        self.cook_identifier(start)
+                    }
+                    _ => {
+                        self.errors
+                            .push(LexError::UnexpectedChar(c, self.span(start, self.offset())));
+                        TokenKind::UNKNOWN
+                    }
                 },
             };
-            let token_len = self.cursor.len_advanced();
-            self.cursor.reset_len();
-            self.pos += token_len;
+            self.pos = self.offset();
+            self.cursor.start_span();
 
-            if kind == TokenKind::WHITESPACE {
+            let skip = match kind {
+                TokenKind::WHITESPACE => !self.config.preserve_whitespace,
+                TokenKind::COMMENT => !self.config.preserve_comments,
+                _ => false,
+            };
+            if skip {
                 continue;
             }
 
-            let token = Token::new(kind, TokenPos(start, self.pos));
+            let value = self.cook_value(&kind, start);
+            let token = Token {
+                kind,
+                pos: self.span(start, self.pos),
+                value,
+                leading: self.span(trivia_start, start),
+            };
             return token;
         }
     }
 
-    fn cook_identifier(&mut self, start: u32) -> TokenKind {
-        loop {
-            match self.cursor.peek_first() {
-                'a'..='z' | 'A'..='Z' | '0'..='9' => {
-                    self.cursor.bump();
+    /// Decodes the payload of the token spanning `start..self.pos`.
+    fn cook_value(&mut self, kind: &TokenKind, start: u32) -> TokenValue {
+        let text = &self.src[start as usize..self.pos as usize];
+        match kind {
+            TokenKind::ID => {
+                let name = Symbol::intern(text);
+                if self.config.reserved_words.contains(&text) {
+                    let pos = self.span(start, self.pos);
+                    self.reserved.push(ReservedWord { name, pos });
                 }
-                _ => break,
-            };
+                TokenValue::Ident(name)
+            }
+            TokenKind::INT => match text.parse() {
+                Ok(n) => TokenValue::Int(n),
+                Err(_) => {
+                    let pos = self.span(start, self.pos);
+                    self.errors.push(LexError::IntegerOverflow(pos));
+                    TokenValue::None
+                }
+            },
+            // Malformed literals were reported by `cook_number` and don't parse.
+            TokenKind::FLOAT => text.parse().map_or(TokenValue::None, TokenValue::Float),
+            TokenKind::STRING => {
+                let end = if self.string_closed {
+                    text.len() - 1
+                } else {
+                    text.len()
+                };
+                TokenValue::Str(unescape(&text[1..end], &mut self.scratch))
+            }
+            _ => TokenValue::None,
         }
+    }
 
-        let end: usize = (start + self.cursor.len_advanced())
-            .try_into()
-            .expect("input program length falls within usize bounds");
-        let start: usize = start
-            .try_into()
-            .expect("input program length falls within usize bounds");
-
-        let token = &self.src[start..end];
-        // TODO: find out if this pattern matching needs to be optimized
-        // or if llvm optimizes this automatically
-        match token {
-            "array" => TokenKind::ARRAY,
-            "if" => TokenKind::IF,
-            "then" => TokenKind::THEN,
-            "else" => TokenKind::ELSE,
-            "while" => TokenKind::WHILE,
-            "for" => TokenKind::FOR,
-            "to" => TokenKind::TO,
-            "do" => TokenKind::DO,
-            "let" => TokenKind::LET,
-            "in" => TokenKind::IN,
-            "end" => TokenKind::END,
-            "of" => TokenKind::OF,
-            "break" => TokenKind::BREAK,
-            "function" => TokenKind::FUNCTION,
-            "var" => TokenKind::VAR,
-            "type" => TokenKind::TYPE,
-            "nil" => TokenKind::NIL,
-            _ => TokenKind::ID,
-        }
+    /// Identifiers start with a letter, followed by letters, digits and
+    /// underscores.
+    fn cook_identifier(&mut self, start: u32) -> TokenKind {
+        debug_assert_eq!(start, self.pos);
+        let unicode = self.unicode_identifiers;
+        self.cursor.bump_while(|c| {
+            c.is_ascii_alphanumeric() || c == '_' || (unicode && c.is_alphanumeric())
+        });
+        keyword(self.cursor.consumed()).unwrap_or(TokenKind::ID)
     }
 
     fn whitespace(&mut self) -> TokenKind {
-        debug_assert!(is_whitespace(self.cursor.prev()));
+        debug_assert!(self.cursor.prev().is_some_and(is_whitespace));
         self.cursor.bump_while(is_whitespace);
         TokenKind::WHITESPACE
     }
     fn colon(&mut self) -> TokenKind {
-        debug_assert!(self.cursor.prev() == ':');
-        if self.cursor.peek_first() == '=' {
-            self.cursor.bump();
+        debug_assert!(self.cursor.prev() == Some(':'));
+        if self.cursor.eat('=') {
             TokenKind::ASSIGN
         } else {
             TokenKind::COLON
         }
     }
     fn less_than(&mut self) -> TokenKind {
-        debug_assert!(self.cursor.prev() == '<');
-        match self.cursor.peek_first() {
-            '>' => {
-                self.cursor.bump();
-                TokenKind::NEQ
-            }
-            '=' => {
-                self.cursor.bump();
-                TokenKind::LE
-            }
-            _ => TokenKind::LT,
+        debug_assert!(self.cursor.prev() == Some('<'));
+        if self.cursor.eat('>') {
+            TokenKind::NEQ
+        } else if self.cursor.eat('=') {
+            TokenKind::LE
+        } else {
+            TokenKind::LT
         }
     }
     fn greater_than(&mut self) -> TokenKind {
-        debug_assert!(self.cursor.prev() == '>');
-        match self.cursor.peek_first() {
-            '=' => {
-                self.cursor.bump();
-                TokenKind::GE
-            }
-            _ => TokenKind::GT,
+        debug_assert!(self.cursor.prev() == Some('>'));
+        if self.cursor.eat('=') {
+            TokenKind::GE
+        } else {
+            TokenKind::GT
         }
     }
 
+    /// `digits`, or a `FLOAT` with a fraction `.digits`, an exponent
+    /// `e[+-]digits`, or both. A `.` or exponent sign without digits after
+    /// it, and further `.`s, are kept in the token and reported.
     fn cook_number(&mut self) -> TokenKind {
-        debug_assert!('0' <= self.cursor.prev() && self.cursor.prev() <= '9');
-        let mut decimal_found = false;
-        loop {
-            match self.cursor.peek_first() {
-                '0'..='9' => {
-                    self.cursor.bump();
-                }
-                '.' => {
-                    if decimal_found {
-                        break;
-                    }
-                    decimal_found = true;
-                    self.cursor.bump();
-                }
-                c if is_whitespace(c) => break,
-                _ => break,
+        debug_assert!(self.cursor.prev().is_some_and(|c| c.is_ascii_digit()));
+        let is_digit = |c: char| c.is_ascii_digit();
+        let mut malformed = None;
+        self.cursor.bump_while(is_digit);
+        let mut kind = TokenKind::INT;
+        if self.cursor.eat('.') {
+            kind = TokenKind::FLOAT;
+            if self.cursor.bump_while(is_digit).is_empty() {
+                malformed = Some("expected digits after the decimal point");
             }
         }
-        if !decimal_found {
-            TokenKind::INT
-        } else {
-            TokenKind::FLOAT
+        // An `e` followed by anything else starts the next token, as in `1else`.
+        let sign = matches!(self.cursor.peek_n(1), Some('+' | '-'));
+        let exp_digit = self.cursor.peek_n(if sign { 2 } else { 1 });
+        if matches!(self.cursor.peek(), Some('e' | 'E'))
+            && (sign || exp_digit.is_some_and(is_digit))
+        {
+            kind = TokenKind::FLOAT;
+            self.cursor.bump_n(if sign { 2 } else { 1 });
+            if self.cursor.bump_while(is_digit).is_empty() {
+                malformed = Some("expected digits in the exponent");
+            }
+        }
+        if self.cursor.peek() == Some('.') {
+            self.cursor.bump_while(|c| c == '.' || c.is_ascii_digit());
+            malformed = Some("more than one decimal point");
         }
+        if let Some(reason) = malformed {
+            let pos = self.span(self.pos, self.offset());
+            self.errors.push(LexError::MalformedNumber(reason, pos));
+            kind = TokenKind::FLOAT;
+        }
+        kind
     }
 
     fn cook_string(&mut self) -> TokenKind {
-        debug_assert!(self.cursor.prev() == '"');
+        debug_assert!(self.cursor.prev() == Some('"'));
         while let Some(c) = self.cursor.bump() {
             match c {
                 '"' => {
+                    self.string_closed = true;
+                    self.check_string_length();
                     return TokenKind::STRING;
                 }
-                '\\' if self.cursor.peek_first() == '\\' || self.cursor.peek_first() == '"' => {
-                    // Bump again to skip escaped character.
-                    self.cursor.bump();
+                '\\' => {
+                    let lo = self.offset() - 1;
+                    let rest = &self.src[lo as usize + 1..];
+                    let (len, valid) = match escape(rest) {
+                        Ok((_, len)) => (len, true),
+                        Err(len) => (len, false),
+                    };
+                    self.cursor.bump_n(rest[..len].chars().count());
+                    // A `\` at the very end is reported as an unterminated string.
+                    if !valid && !rest.is_empty() {
+                        self.errors
+                            .push(LexError::InvalidEscape(self.span(lo, self.offset())));
+                    }
                 }
                 _ => continue,
             }
         }
-        TokenKind::UNKNOWN
+        self.string_closed = false;
+        self.errors.push(LexError::UnterminatedString(
+            self.span(self.pos, self.offset()),
+        ));
+        self.check_string_length();
+        TokenKind::STRING
+    }
+
+    fn check_string_length(&mut self) {
+        let max = self.limits.max_string_length;
+        if (self.offset() - self.pos) as usize > max {
+            let pos = self.span(self.pos, self.offset());
+            self.errors.push(LexError::StringTooLong(max, pos));
+        }
     }
 
     fn slash(&mut self) -> TokenKind {
-        debug_assert!(self.cursor.prev() == '/');
+        debug_assert!(self.cursor.prev() == Some('/'));
         // it could just be devide
-        match self.cursor.peek_first() {
-            '*' => self.cook_comment(),
-            _ => TokenKind::DIVIDE,
+        if self.cursor.eat('*') {
+            self.cook_comment()
+        } else {
+            TokenKind::DIVIDE
         }
     }
 
     fn cook_comment(&mut self) -> TokenKind {
+        debug_assert!(self.cursor.prev() == Some('*'));
+        let max = self.limits.max_comment_nesting;
         let mut comment_level = 1;
-        loop {
-            match (self.cursor.peek_first(), self.cursor.peek_second()) {
-                ('*', '/') => {
+        let mut too_deep = false;
+        while comment_level > 0 {
+            match (self.cursor.peek(), self.cursor.peek_n(1)) {
+                (Some('*'), Some('/')) => {
                     comment_level -= 1;
-                    self.cursor.bump();
-                    self.cursor.bump();
-                    if comment_level == 0 {
-                        break;
-                    }
+                    self.cursor.bump_n(2);
                 }
-                ('/', '*') => {
+                (Some('/'), Some('*')) => {
                     comment_level += 1;
-                    self.cursor.bump();
-                    self.cursor.bump();
+                    let lo = self.offset();
+                    self.cursor.bump_n(2);
+                    // Only the first opening past the limit is reported.
+                    if comment_level > max && !std::mem::replace(&mut too_deep, true) {
+                        let pos = self.span(lo, self.offset());
+                        self.errors.push(LexError::CommentTooDeep(max, pos));
+                    }
                 }
-                (_, _) => match self.cursor.bump() {
-                    Some(_) => continue,
-                    None => {
-                        if self.cursor.is_eof() {
-                            break;
-                        }
+                _ => {
+                    if self.cursor.bump().is_none() {
+                        self.errors.push(LexError::UnterminatedComment(
+                            self.span(self.pos, self.offset()),
+                        ));
+                        break;
                     }
-                },
+                }
             }
         }
         TokenKind::COMMENT
     }
+
+    /// Absolute byte offset of the cursor.
+    fn offset(&self) -> u32 {
+        self.cursor.offset() as u32
+    }
+}
+
+/// The keyword spelled `word`, if it is one. Words are told apart by their
+/// length and first byte, so an identifier is compared with at most two
+/// keywords rather than all of them.
+fn keyword(word: &str) -> Option<TokenKind> {
+    use TokenKind::*;
+    let candidates: &[TokenKind] = match (word.len(), word.as_bytes().first()) {
+        (2, Some(b'd')) => &[DO],
+        (2, Some(b'i')) => &[IF, IN],
+        (2, Some(b'o')) => &[OF],
+        (2, Some(b't')) => &[TO],
+        (3, Some(b'e')) => &[END],
+        (3, Some(b'f')) => &[FOR],
+        (3, Some(b'l')) => &[LET],
+        (3, Some(b'n')) => &[NIL],
+        (3, Some(b'v')) => &[VAR],
+        (4, Some(b'e')) => &[ELSE],
+        (4, Some(b't')) => &[THEN, TYPE],
+        (5, Some(b'a')) => &[ARRAY],
+        (5, Some(b'b')) => &[BREAK],
+        (5, Some(b'w')) => &[WHILE],
+        (8, Some(b'f')) => &[FUNCTION],
+        _ => &[],
+    };
+    candidates
+        .iter()
+        .find(|kind| kind.text() == Some(word))
+        .cloned()
+}
+
+/// Decodes the escapes `cook_string` accepts into `out`, and returns the result.
+/// `out` is a scratch buffer reused across calls.
+fn unescape(raw: &str, out: &mut Vec<u8>) -> ByteStr {
+    if !raw.contains('\\') {
+        return ByteStr::from(raw);
+    }
+    out.clear();
+    let mut rest = raw;
+    while let Some(i) = rest.find('\\') {
+        out.extend_from_slice(&rest.as_bytes()[..i]);
+        rest = &rest[i + 1..];
+        // Malformed escapes were reported by `cook_string` and are dropped.
+        let len = match escape(rest) {
+            Ok((byte, len)) => {
+                out.extend(byte);
+                len
+            }
+            Err(len) => len,
+        };
+        rest = &rest[len..];
+    }
+    out.extend_from_slice(rest.as_bytes());
+    ByteStr::new(out)
+}
+
+/// Decodes the escape sequence at the start of `rest`, the text following a
+/// `\`. Returns the byte it stands for (`None` for the ignored `\f___f\`
+/// form) and its length in bytes, or, if it is malformed, the length to
+/// skip. A closing quote is never part of a malformed escape.
+fn escape(rest: &str) -> Result<(Option<u8>, usize), usize> {
+    let mut chars = rest.chars();
+    let Some(c) = chars.next() else {
+        return Err(0);
+    };
+    match c {
+        'n' => Ok((Some(b'\n'), 1)),
+        't' => Ok((Some(b'\t'), 1)),
+        '\\' | '"' => Ok((Some(c as u8), 1)),
+        // `\^c`: the control character c - 64, e.g. `\^@` is NUL and `\^?` DEL.
+        '^' => match chars.next() {
+            Some(c @ ('@'..='_' | 'a'..='z')) => Ok((Some(c.to_ascii_uppercase() as u8 - b'@'), 2)),
+            Some('?') => Ok((Some(0x7f), 2)),
+            Some(c) if c != '"' => Err(1 + c.len_utf8()),
+            _ => Err(1),
+        },
+        // `\ddd`: the single byte ddd, which may be past ASCII.
+        '0'..='9' => {
+            let digits = rest.bytes().take(3).take_while(u8::is_ascii_digit).count();
+            match rest[..digits].parse::<u8>() {
+                Ok(code) if digits == 3 => Ok((Some(code), 3)),
+                _ => Err(digits),
+            }
+        }
+        // `\f___f\`: whitespace between two backslashes is ignored, which
+        // lets a literal continue on the next line.
+        c if is_whitespace(c) => {
+            let gap = rest.len() - rest.trim_start_matches(is_whitespace).len();
+            match rest[gap..].chars().next() {
+                Some('\\') => Ok((None, gap + 1)),
+                // Running into the end is reported as an unterminated string.
+                None => Ok((None, gap)),
+                Some(_) => Err(gap),
+            }
+        }
+        c => Err(c.len_utf8()),
+    }
 }
 
 fn is_whitespace(c: char) -> bool {
@@ -0,0 +1,128 @@
+use std::collections::VecDeque;
+
+use super::{LexError, LexerConfig, ReservedWord, StringReader, Token, TokenKind};
+use crate::limits::Limits;
+use crate::span::FileId;
+
+impl Iterator for StringReader<'_> {
+    type Item = Token;
+
+    /// Yields every token up to and including `EOF`, then `None`.
+    fn next(&mut self) -> Option<Token> {
+        if self.finished {
+            return None;
+        }
+        let token = self.next_token();
+        self.finished = token.kind == TokenKind::EOF;
+        Some(token)
+    }
+}
+
+/// Lexes all of `src`, including the final `EOF` token. Lexical errors are
+/// dropped; use a `StringReader` directly to inspect them.
+pub(crate) fn tokenize(src: &str) -> Vec<Token> {
+    StringReader::new(src).collect()
+}
+
+/// The tokens of a program without comments, with arbitrary lookahead. Once
+/// the input is exhausted the stream keeps returning its `EOF` token. The
+/// comments skipped before a token are in its `leading_trivia`.
+pub(crate) struct TokenStream<'a> {
+    reader: StringReader<'a>,
+    /// Lookahead buffer; never empty, and ends with `EOF` once it's reached.
+    buffer: VecDeque<Token>,
+    /// Tokens to yield before the first token at or after each offset, in
+    /// order; see `insert`.
+    insertions: VecDeque<(u32, TokenKind)>,
+    /// A token read while yielding an insertion before it.
+    pending: Option<Token>,
+}
+
+impl<'a> TokenStream<'a> {
+    pub(crate) fn new(src: &'a str) -> TokenStream<'a> {
+        TokenStream::with_limits(src, Limits::default())
+    }
+
+    pub(crate) fn with_limits(src: &'a str, limits: Limits) -> TokenStream<'a> {
+        TokenStream::in_file(src, FileId::MAIN, limits)
+    }
+
+    /// The tokens of `src`, the text of `file`.
+    pub(crate) fn in_file(src: &'a str, file: FileId, limits: Limits) -> TokenStream<'a> {
+        let mut stream = TokenStream {
+            reader: StringReader::new(src)
+                .with_config(LexerConfig::PARSER)
+                .with_file(file)
+                .with_limits(limits),
+            buffer: VecDeque::new(),
+            insertions: VecDeque::new(),
+            pending: None,
+        };
+        stream.fill(1);
+        stream
+    }
+
+    /// Makes sure at least `n` tokens are buffered, unless `EOF` comes first.
+    fn fill(&mut self, n: usize) {
+        while self.buffer.len() < n && self.buffer.back().map(|t| &t.kind) != Some(&TokenKind::EOF)
+        {
+            let token = match self.pending.take() {
+                Some(token) => token,
+                None => self.reader.next_token(),
+            };
+            match self.insertions.front() {
+                Some(&(at, _)) if at <= token.pos.lo() => {
+                    let (at, kind) = self.insertions.pop_front().expect("just peeked");
+                    self.pending = Some(token);
+                    self.buffer
+                        .push_back(Token::new(kind, self.reader.span(at, at)));
+                }
+                _ => self.buffer.push_back(token),
+            }
+        }
+    }
+
+    /// Yields a `kind` token with an empty span right before the first token
+    /// at or after `at`, which must not have been consumed yet. The parser
+    /// uses this to assume a keyword that was left out. Insertions must be
+    /// made in source order.
+    pub(crate) fn insert(&mut self, kind: TokenKind, at: u32) {
+        match self.buffer.iter().position(|t| t.pos.lo() >= at) {
+            Some(i) => self
+                .buffer
+                .insert(i, Token::new(kind, self.reader.span(at, at))),
+            None => self.insertions.push_back((at, kind)),
+        }
+    }
+
+    pub(crate) fn peek(&self) -> &Token {
+        self.buffer.front().expect("buffer is never empty")
+    }
+
+    /// The token `n` places ahead; `peek_nth(0)` is `peek()`.
+    pub(crate) fn peek_nth(&mut self, n: usize) -> &Token {
+        self.fill(n + 1);
+        let last = self.buffer.len() - 1;
+        &self.buffer[n.min(last)]
+    }
+
+    /// Consumes the next token. At the end of input this keeps returning `EOF`.
+    pub(crate) fn bump(&mut self) -> Token {
+        if self.buffer.len() == 1 && self.peek().kind == TokenKind::EOF {
+            return self.peek().clone();
+        }
+        let token = self.buffer.pop_front().expect("buffer is never empty");
+        self.fill(1);
+        token
+    }
+
+    /// Lexical errors in the tokens read so far.
+    pub(crate) fn errors(&self) -> &[LexError] {
+        self.reader.errors()
+    }
+
+    /// Reserved words used as identifiers in the tokens read so far.
+    pub(crate) fn reserved_words(&self) -> &[ReservedWord] {
+        self.reader.reserved_words()
+    }
+}
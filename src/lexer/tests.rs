@@ -1,10 +1,11 @@
-#[cfg(test)]
-mod tests {
-    use crate::lexer::{StringReader, TokenKind};
+use crate::lexer::{LexError, LexerConfig, ReservedWord, SourceCursor, StringReader, TokenKind};
+use crate::limits::Limits;
+use crate::span::{FileId, Span};
+use crate::symbol::ByteStr;
 
-    #[test]
-    fn single_length_tokens() {
-        let src = r#"
+#[test]
+fn single_length_tokens() {
+    let src = r#"
 let 
 
  type any = {any : int}
@@ -16,16 +17,469 @@ let
   /* BODY /* OF MAIN */*/
   /* BODY /* OF MAIN */PROGRAM*/
 "#;
-        let mut sr = StringReader::new(src);
-        let mut token = sr.next_token();
-        while token.kind != TokenKind::EOF {
-            let value = &src[(token.pos.0 as usize)..(token.pos.1 as usize)];
-            println!(
-                "{:?} \t\t [{}, {}] \t\t{}",
-                token.kind, token.pos.0, token.pos.1, value,
-            );
-            // println!("{}", value);
-            token = sr.next_token();
+    let mut sr = StringReader::new(src);
+    let mut token = sr.next_token();
+    while token.kind != TokenKind::EOF {
+        let value = &src[(token.pos.lo() as usize)..(token.pos.hi() as usize)];
+        println!(
+            "{:?} \t\t [{}, {}] \t\t{}",
+            token.kind,
+            token.pos.lo(),
+            token.pos.hi(),
+            value,
+        );
+        // println!("{}", value);
+        token = sr.next_token();
+    }
+}
+
+#[test]
+fn curly_braces() {
+    let mut sr = StringReader::new("{}");
+    assert_eq!(sr.next_token().kind, TokenKind::LCURLY);
+    assert_eq!(sr.next_token().kind, TokenKind::RCURLY);
+    assert_eq!(sr.next_token().kind, TokenKind::EOF);
+}
+
+#[test]
+fn literal_values() {
+    use crate::lexer::TokenValue;
+    use crate::symbol::Symbol;
+
+    let mut sr = StringReader::new(r#"foo 42 3.5 "a\"b\\c" foo 99999999999999999999 if"#);
+    let foo = sr.next_token();
+    assert_eq!(foo.symbol(), Some(Symbol::intern("foo")));
+    assert_eq!(sr.next_token().int(), Some(42));
+    assert_eq!(sr.next_token().float(), Some(3.5));
+    let s = sr.next_token();
+    assert_eq!(
+        s.string().as_ref().map(ByteStr::as_bytes),
+        Some(&b"a\"b\\c"[..])
+    );
+    assert_eq!(sr.next_token().symbol(), foo.symbol());
+    let overflow = sr.next_token();
+    assert_eq!(overflow.kind, TokenKind::INT);
+    assert_eq!(overflow.int(), None);
+    assert_eq!(sr.next_token().value(), &TokenValue::None);
+}
+
+fn errors(src: &str) -> Vec<LexError> {
+    let mut sr = StringReader::new(src);
+    while sr.next_token().kind != TokenKind::EOF {}
+    sr.errors().to_vec()
+}
+
+#[test]
+fn collects_lexical_errors() {
+    assert_eq!(errors("a /* ok */ \"ok\\\"\" b"), vec![]);
+    assert_eq!(
+        errors("x $ \"a\\qb\" # /* /* */"),
+        vec![
+            LexError::UnexpectedChar('$', Span::new(2, 3)),
+            LexError::InvalidEscape(Span::new(6, 8)),
+            LexError::UnexpectedChar('#', Span::new(11, 12)),
+            LexError::UnterminatedComment(Span::new(13, 21)),
+        ]
+    );
+    assert_eq!(
+        errors("\"abc"),
+        vec![LexError::UnterminatedString(Span::new(0, 4))]
+    );
+}
+
+fn string_value(src: &str) -> Vec<u8> {
+    let token = StringReader::new(src).next_token();
+    token
+        .string()
+        .expect("a string literal")
+        .as_bytes()
+        .to_vec()
+}
+
+#[test]
+fn string_escapes_are_decoded() {
+    assert_eq!(string_value(r#""a\nb\tc""#), b"a\nb\tc");
+    assert_eq!(string_value(r#""\"\\""#), b"\"\\");
+    assert_eq!(string_value(r#""\^@\^A\^z\^?""#), b"\0\x01\x1a\x7f");
+    // `\ddd` is one byte, even past ASCII; other text keeps its UTF-8.
+    assert_eq!(string_value(r#""\065\0489\255""#), b"A09\xff");
+    assert_eq!(string_value(r#""\200é""#), b"\xc8\xc3\xa9");
+    assert_eq!(string_value("\"one \\\n\t   \\two\""), b"one two");
+    assert_eq!(string_value(r#""a\ \b""#), b"ab");
+}
+
+#[test]
+fn malformed_escapes_are_reported() {
+    assert_eq!(
+        errors(r#""\256" "\12" "\^1""#),
+        vec![
+            LexError::InvalidEscape(Span::new(1, 5)),
+            LexError::InvalidEscape(Span::new(8, 11)),
+            LexError::InvalidEscape(Span::new(14, 17)),
+        ]
+    );
+    // The gap form must be closed by a second backslash before the quote.
+    assert_eq!(
+        errors("\"a\\  \""),
+        vec![LexError::InvalidEscape(Span::new(2, 5))]
+    );
+    assert_eq!(string_value("\"a\\  \""), b"a");
+    assert_eq!(string_value(r#""x\qy""#), b"xy");
+}
+
+#[test]
+fn unterminated_tokens_still_produce_values() {
+    let mut sr = StringReader::new("\"abc");
+    let token = sr.next_token();
+    assert_eq!(token.kind, TokenKind::STRING);
+    assert_eq!(
+        token.string().as_ref().map(ByteStr::as_bytes),
+        Some(&b"abc"[..])
+    );
+    let mut sr = StringReader::new("\"");
+    assert_eq!(
+        sr.next_token().string().as_ref().map(ByteStr::as_bytes),
+        Some(&b""[..])
+    );
+}
+
+#[test]
+fn comment_opener_is_not_a_closer() {
+    let mut sr = StringReader::new("/*/ x */ y");
+    assert_eq!(sr.next_token().kind, TokenKind::COMMENT);
+    assert_eq!(sr.next_token().kind, TokenKind::ID);
+    assert!(sr.errors().is_empty());
+}
+
+#[test]
+fn iterator_stops_after_eof() {
+    let kinds: Vec<TokenKind> = StringReader::new("a /* c */ 1").map(|t| t.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            TokenKind::ID,
+            TokenKind::COMMENT,
+            TokenKind::INT,
+            TokenKind::EOF
+        ]
+    );
+    assert_eq!(crate::lexer::stream::tokenize("").len(), 1);
+}
+
+#[test]
+fn token_stream_lookahead() {
+    use crate::lexer::TokenStream;
+
+    let mut ts = TokenStream::new("a /* c */ := 1");
+    assert_eq!(ts.peek().kind, TokenKind::ID);
+    assert_eq!(ts.peek_nth(1).kind, TokenKind::ASSIGN);
+    assert_eq!(ts.peek_nth(2).kind, TokenKind::INT);
+    assert_eq!(ts.peek_nth(3).kind, TokenKind::EOF);
+    assert_eq!(ts.peek_nth(10).kind, TokenKind::EOF);
+    assert_eq!(ts.bump().kind, TokenKind::ID);
+    assert_eq!(ts.bump().kind, TokenKind::ASSIGN);
+    assert_eq!(ts.bump().kind, TokenKind::INT);
+    assert_eq!(ts.bump().kind, TokenKind::EOF);
+    assert_eq!(ts.bump().kind, TokenKind::EOF);
+    assert_eq!(ts.peek().kind, TokenKind::EOF);
+}
+
+#[test]
+fn source_cursor_peeks_past_eof_as_none() {
+    let mut cursor = SourceCursor::new("a\0");
+    assert_eq!(cursor.peek_n(1), Some('\0'));
+    assert_eq!(cursor.peek_n(2), None);
+    assert_eq!(cursor.bump_n(5), 2);
+    assert!(cursor.is_eof());
+    assert_eq!(cursor.peek(), None);
+    assert_eq!(cursor.prev(), Some('\0'));
+}
+
+#[test]
+fn source_cursor_tracks_consumed_span() {
+    let mut cursor = SourceCursor::new("abc123 ü:=");
+    assert_eq!(cursor.bump_while(|c| c.is_ascii_alphabetic()), "abc");
+    cursor.start_span();
+    assert_eq!(cursor.bump_while(|c| !c.is_whitespace()), "123");
+    assert_eq!(cursor.span(), 3..6);
+    assert!(cursor.eat(' '));
+    assert!(!cursor.eat(':'));
+    cursor.start_span();
+    cursor.bump();
+    assert_eq!(cursor.consumed(), "ü");
+    assert_eq!(cursor.offset(), 9);
+    assert_eq!(cursor.as_str(), ":=");
+}
+
+fn kinds_and_spans(mut sr: StringReader) -> Vec<(TokenKind, u32, u32)> {
+    std::iter::from_fn(|| Some(sr.next_token()))
+        .take_while(|t| t.kind != TokenKind::EOF)
+        .map(|t| (t.kind, t.pos.lo(), t.pos.hi()))
+        .collect()
+}
+
+#[test]
+fn identifiers_may_contain_underscores() {
+    use crate::symbol::Symbol;
+
+    let mut sr = StringReader::new("do_nothing1 a__b_ _x");
+    assert_eq!(
+        sr.next_token().symbol(),
+        Some(Symbol::intern("do_nothing1"))
+    );
+    assert_eq!(sr.next_token().symbol(), Some(Symbol::intern("a__b_")));
+    // Identifiers start with a letter.
+    assert_eq!(sr.next_token().kind, TokenKind::UNKNOWN);
+    assert_eq!(sr.next_token().symbol(), Some(Symbol::intern("x")));
+}
+
+#[test]
+fn unicode_identifiers_are_opt_in() {
+    use crate::symbol::Symbol;
+
+    // "é" and "π" take two bytes each; spans stay byte offsets.
+    let src = "café := π2";
+    assert_eq!(
+        kinds_and_spans(StringReader::new(src)),
+        [
+            (TokenKind::ID, 0, 3),
+            (TokenKind::UNKNOWN, 3, 5),
+            (TokenKind::ASSIGN, 6, 8),
+            (TokenKind::UNKNOWN, 9, 11),
+            (TokenKind::INT, 11, 12),
+        ]
+    );
+    assert_eq!(
+        errors(src),
+        [
+            LexError::UnexpectedChar('é', Span::new(3, 5)),
+            LexError::UnexpectedChar('π', Span::new(9, 11)),
+        ]
+    );
+
+    let unicode = || StringReader::new(src).with_unicode_identifiers(true);
+    assert_eq!(
+        kinds_and_spans(unicode()),
+        [
+            (TokenKind::ID, 0, 5),
+            (TokenKind::ASSIGN, 6, 8),
+            (TokenKind::ID, 9, 12),
+        ]
+    );
+    let mut sr = unicode();
+    assert_eq!(sr.next_token().symbol(), Some(Symbol::intern("café")));
+    sr.next_token();
+    assert_eq!(sr.next_token().symbol(), Some(Symbol::intern("π2")));
+    assert!(sr.errors().is_empty());
+}
+
+#[test]
+fn spans_after_multibyte_text_are_byte_offsets() {
+    let src = "/* ünïcode */ \"日本\" x";
+    assert_eq!(
+        kinds_and_spans(StringReader::new(src)),
+        [
+            (TokenKind::COMMENT, 0, 15),
+            (TokenKind::STRING, 16, 24),
+            (TokenKind::ID, 25, 26),
+        ]
+    );
+}
+
+#[test]
+fn lossless_tokens_cover_the_source() {
+    let src = "let /* a /* b */ */\n  var x := 1 in x end ";
+    let tokens: Vec<_> = StringReader::new(src)
+        .with_config(LexerConfig::LOSSLESS)
+        .collect();
+    let mut end = 0;
+    for token in &tokens {
+        assert_eq!(token.pos.lo(), end, "gap before {token:?}");
+        end = token.pos.hi();
+    }
+    assert_eq!(end as usize, src.len());
+    assert_eq!(tokens[1].kind, TokenKind::WHITESPACE);
+    assert_eq!(tokens[2].kind, TokenKind::COMMENT);
+    assert!(tokens
+        .iter()
+        .all(|t| t.leading_trivia() == Span::new(t.pos.lo(), t.pos.lo())));
+}
+
+#[test]
+fn skipped_trivia_is_attached_to_the_next_token() {
+    let src = "a /* c */\n:= 1 ";
+    let mut sr = StringReader::new(src).with_config(LexerConfig::PARSER);
+    let a = sr.next_token();
+    assert_eq!(a.leading_trivia(), Span::new(0, 0));
+    let assign = sr.next_token();
+    assert_eq!(assign.kind, TokenKind::ASSIGN);
+    assert_eq!(assign.leading_trivia(), Span::new(1, 10));
+    assert_eq!(
+        assign
+            .trivia(src)
+            .iter()
+            .map(|t| (t.kind.clone(), t.pos.lo(), t.pos.hi()))
+            .collect::<Vec<_>>(),
+        [
+            (TokenKind::WHITESPACE, 1, 2),
+            (TokenKind::COMMENT, 2, 9),
+            (TokenKind::WHITESPACE, 9, 10),
+        ]
+    );
+    sr.next_token();
+    let eof = sr.next_token();
+    assert_eq!(eof.kind, TokenKind::EOF);
+    assert_eq!(eof.leading_trivia(), Span::new(14, 15));
+
+    // By default comments are tokens, so only the whitespace is trivia.
+    let mut sr = StringReader::new(src);
+    sr.next_token();
+    let comment = sr.next_token();
+    assert_eq!(comment.kind, TokenKind::COMMENT);
+    assert_eq!(comment.leading_trivia(), Span::new(1, 2));
+
+    let file = FileId::new(1);
+    let mut sr = StringReader::new(src)
+        .with_config(LexerConfig::PARSER)
+        .with_file(file);
+    sr.next_token();
+    let assign = sr.next_token();
+    assert_eq!(assign.pos, Span::in_file(file, 10, 12));
+    assert!(assign.trivia(src).iter().all(|t| t.pos.file == file));
+}
+
+#[test]
+fn reserved_words_lex_as_identifiers() {
+    use crate::symbol::Symbol;
+
+    let mut sr = StringReader::new("class := new + classy");
+    let kinds: Vec<TokenKind> = sr.by_ref().map(|t| t.kind).collect();
+    assert_eq!(kinds[0], TokenKind::ID);
+    let reserved = |name: &str, lo, hi| ReservedWord {
+        name: Symbol::intern(name),
+        pos: Span::new(lo, hi),
+    };
+    assert_eq!(
+        sr.reserved_words(),
+        [reserved("class", 0, 5), reserved("new", 9, 12)]
+    );
+    assert!(sr.errors().is_empty());
+
+    let dialect = LexerConfig {
+        reserved_words: &["classy"],
+        ..LexerConfig::default()
+    };
+    let mut sr = StringReader::new("class := new + classy").with_config(dialect);
+    sr.by_ref().for_each(drop);
+    assert_eq!(sr.reserved_words(), [reserved("classy", 15, 21)]);
+}
+
+#[test]
+fn numeric_literals_are_validated() {
+    let mut sr = StringReader::new("1e10 3.14e-2 2E+3 7 1else");
+    assert_eq!(sr.next_token().float(), Some(1e10));
+    assert_eq!(sr.next_token().float(), Some(3.14e-2));
+    assert_eq!(sr.next_token().float(), Some(2e3));
+    assert_eq!(sr.next_token().int(), Some(7));
+    assert_eq!(sr.next_token().int(), Some(1));
+    assert_eq!(sr.next_token().kind, TokenKind::ELSE);
+    assert!(sr.errors().is_empty());
+
+    assert_eq!(
+        kinds_and_spans(StringReader::new("1. 1.2.3 4e+ x")),
+        [
+            (TokenKind::FLOAT, 0, 2),
+            (TokenKind::FLOAT, 3, 8),
+            (TokenKind::FLOAT, 9, 12),
+            (TokenKind::ID, 13, 14),
+        ]
+    );
+    assert_eq!(
+        errors("1. 1.2.3 4e+ x"),
+        [
+            LexError::MalformedNumber("expected digits after the decimal point", Span::new(0, 2)),
+            LexError::MalformedNumber("more than one decimal point", Span::new(3, 8)),
+            LexError::MalformedNumber("expected digits in the exponent", Span::new(9, 12)),
+        ]
+    );
+    assert_eq!(
+        errors("9223372036854775807 9223372036854775808"),
+        [LexError::IntegerOverflow(Span::new(20, 39))]
+    );
+}
+
+#[test]
+fn relexing_an_edit_matches_lexing_from_scratch() {
+    use crate::lexer::incremental::{relex, TextEdit};
+
+    let src = "let var ab := 1.5 /* c */ in ab <> \"s\" end";
+    let configs = [
+        LexerConfig::default(),
+        LexerConfig::LOSSLESS,
+        LexerConfig::PARSER,
+    ];
+    let inserts = ["", "x", " ", "=", ">", "e+", ".", "/*", "\"", "*/ 9"];
+    for config in configs {
+        let old: Vec<_> = StringReader::new(src).with_config(config).collect();
+        for lo in 0..=src.len() {
+            for hi in lo..=(lo + 3).min(src.len()) {
+                for text in inserts {
+                    let edit = TextEdit::new(Span::new(lo as u32, hi as u32), text);
+                    let new_src = edit.apply(src);
+                    let expected: Vec<_> =
+                        StringReader::new(&new_src).with_config(config).collect();
+                    assert_eq!(
+                        relex(&old, &edit, &new_src, config),
+                        expected,
+                        "after {edit:?} of {src:?}"
+                    );
+                }
+            }
         }
     }
 }
+
+#[test]
+fn comment_nesting_and_string_length_are_limited() {
+    let limits = Limits {
+        max_comment_nesting: 2,
+        max_string_length: 4,
+        ..Limits::default()
+    };
+    let mut sr = StringReader::new("/* /* /* /* */ */ */ */ y").with_limits(limits);
+    assert_eq!(sr.next_token().kind, TokenKind::COMMENT);
+    assert_eq!(sr.next_token().kind, TokenKind::ID);
+    assert_eq!(sr.errors(), [LexError::CommentTooDeep(2, Span::new(6, 8))]);
+    let mut sr = StringReader::new(r#""ab" "abc" "abc"#).with_limits(limits);
+    while sr.next_token().kind != TokenKind::EOF {}
+    assert_eq!(
+        sr.errors(),
+        [
+            LexError::StringTooLong(4, Span::new(5, 10)),
+            LexError::UnterminatedString(Span::new(11, 15)),
+        ]
+    );
+}
+
+#[test]
+fn keywords_are_told_apart_from_identifiers() {
+    let keywords = "array if then else while for to do let in end of break function var type nil";
+    for word in keywords.split(' ') {
+        let token = StringReader::new(word).next_token();
+        assert_eq!(token.kind.text(), Some(word));
+    }
+    for word in [
+        "i",
+        "iff",
+        "tyep",
+        "Then",
+        "functions",
+        "nul",
+        "ends",
+        "o",
+        "d0",
+    ] {
+        let token = StringReader::new(word).next_token();
+        assert_eq!(token.kind, TokenKind::ID, "{word}");
+    }
+}
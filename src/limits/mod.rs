@@ -0,0 +1,57 @@
+#![allow(dead_code)]
+
+//! Implementation limits, in one place.
+//!
+//! Inputs past these sizes would otherwise overflow a `u32` offset or the
+//! stack of one of the recursive phases. Each phase that can run into a
+//! limit reports it as an ordinary error at the place it was exceeded.
+
+/// The stack the compiler runs on, which `max_chain_length` is sized for.
+pub(crate) const STACK_SIZE: usize = 256 << 20;
+
+/// Runs `f` on a thread with a stack of `STACK_SIZE`.
+pub(crate) fn with_stack<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, f)
+            .expect("the compiler thread starts")
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Limits {
+    /// Bytes in a source file. Offsets are `u32`, so this must stay below
+    /// 4 GiB.
+    pub(crate) max_file_size: usize,
+    /// How deeply `/* ... */` comments may nest.
+    pub(crate) max_comment_nesting: u32,
+    /// Bytes in a string literal, quotes and escapes included.
+    pub(crate) max_string_length: usize,
+    /// How deeply expressions may nest, in parentheses, `let`, `if`, calls
+    /// and the like. Every phase after parsing recurses over the tree, so
+    /// this also bounds their stack use. The default leaves room for an
+    /// unoptimized build on a 2 MiB thread stack.
+    pub(crate) max_parse_depth: u32,
+    /// Operators or accessors in one chain, such as `a + b + c` or
+    /// `r.a.b[0]`. A chain is not nesting, but it makes a tree as deep as
+    /// it is long, which the later phases recurse over.
+    pub(crate) max_chain_length: u32,
+    /// Fields in a record type or record expression.
+    pub(crate) max_record_fields: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_file_size: 64 << 20,
+            max_comment_nesting: 1024,
+            max_string_length: 1 << 20,
+            max_parse_depth: 128,
+            max_chain_length: 10_000,
+            max_record_fields: 1024,
+        }
+    }
+}
@@ -0,0 +1,264 @@
+#![allow(dead_code)]
+
+//! Warnings about code that type checks but is probably a mistake: unused
+//! variables, parameters and functions, code that can never run because a
+//! `break` comes first, and the constant array sizes, subscripts,
+//! conditions and loop bounds the type checker found wrong on every run.
+//!
+//! Lints run after type checking succeeds, and take every use of a name to
+//! refer to the declaration the type checker resolved it to. Each group can be turned off with `-W
+//! <group>=off`.
+
+#[cfg(test)]
+mod tests;
+
+use crate::ast::visit::{walk_exp, walk_var, Visitor};
+use crate::ast::{Dec, Exp, ExpKind, Symbol, Var, VarKind};
+use crate::diagnostics::{Diagnostic, Message};
+use std::collections::HashMap;
+
+use crate::semant::Semant;
+use crate::span::Span;
+
+/// The lint groups that are on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Lints {
+    /// Variables, parameters and functions that are never used.
+    pub(crate) unused: bool,
+    /// Code after an expression that always `break`s.
+    pub(crate) unreachable: bool,
+    /// Negative constant array sizes and subscripts, constant subscripts
+    /// past a constant size, constant false conditions, and `for` loops
+    /// whose constant bounds are empty.
+    pub(crate) constant: bool,
+}
+
+impl Default for Lints {
+    fn default() -> Lints {
+        Lints {
+            unused: true,
+            unreachable: true,
+            constant: true,
+        }
+    }
+}
+
+impl Lints {
+    /// Applies a `-W` setting such as `unused=off`; `all` names every group.
+    pub(crate) fn set(&mut self, setting: &str) -> Result<(), String> {
+        let (group, value) = setting
+            .split_once('=')
+            .ok_or_else(|| format!("expected `<lint>=on|off`, found `{setting}`"))?;
+        let on = match value {
+            "on" => true,
+            "off" => false,
+            _ => {
+                return Err(format!(
+                    "expected `on` or `off` for `{group}`, found `{value}`"
+                ))
+            }
+        };
+        match group {
+            "unused" => self.unused = on,
+            "unreachable" => self.unreachable = on,
+            "constant" => self.constant = on,
+            "all" => {
+                self.unused = on;
+                self.unreachable = on;
+                self.constant = on;
+            }
+            _ => return Err(format!("unknown lint `{group}`")),
+        }
+        Ok(())
+    }
+}
+
+/// The warnings of the enabled `lints` for a program that type checks, in
+/// source order, including those `semant` found checking it.
+pub(crate) fn check(exp: &Exp, semant: &Semant, lints: Lints) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        decs: semant.bindings().iter().map(|b| (b.pos, b.dec)).collect(),
+        ids: HashMap::new(),
+        bindings: Vec::new(),
+        calls: Vec::new(),
+        current: Vec::new(),
+        unreachable: Vec::new(),
+    };
+    linter.visit_exp(exp);
+
+    let mut warnings = Vec::new();
+    if lints.unused {
+        let called = linter.called();
+        for (id, binding) in linter.bindings.iter().enumerate() {
+            let key = match binding.kind {
+                Kind::Var if !binding.used => "W0201",
+                Kind::Param if !binding.used => "W0202",
+                // A function inside one that is never called is not reported
+                // on its own.
+                Kind::Function { parent } if !called[id] && parent.is_none_or(|p| called[p]) => {
+                    "W0203"
+                }
+                _ => continue,
+            };
+            let msg = Message::new(key).arg("name", binding.name);
+            warnings.push(Diagnostic::warning(binding.pos, msg).with_note(off("unused")));
+        }
+    }
+    if lints.unreachable {
+        for &(pos, cause) in &linter.unreachable {
+            let diag = Diagnostic::warning(pos, Message::new("W0204"))
+                .with_label(cause, Message::new("unreachable-after"))
+                .with_note(off("unreachable"));
+            warnings.push(diag);
+        }
+    }
+    if lints.constant {
+        for warning in semant.warnings() {
+            warnings.push(warning.clone().with_note(off("constant")));
+        }
+    }
+    warnings.sort_by_key(|d| d.pos.lo());
+    warnings
+}
+
+fn off(group: &str) -> Message {
+    Message::new("lint-off").arg("lint", group)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Var,
+    Param,
+    /// `parent` is the function the declaration is nested in.
+    Function {
+        parent: Option<usize>,
+    },
+}
+
+struct Binding {
+    name: Symbol,
+    pos: Span,
+    kind: Kind,
+    used: bool,
+}
+
+struct Linter {
+    /// The declaration of every use of a name, by the span of the use.
+    decs: HashMap<Span, Span>,
+    /// Binding ids by the span of their declaration.
+    ids: HashMap<Span, usize>,
+    bindings: Vec<Binding>,
+    /// Calls as `(caller, callee)`, with no caller for calls from the main
+    /// program.
+    calls: Vec<(Option<usize>, usize)>,
+    /// The functions whose bodies are being visited, innermost last.
+    current: Vec<usize>,
+    /// Unreachable code and the expression that makes it so.
+    unreachable: Vec<(Span, Span)>,
+}
+
+impl Linter {
+    fn bind(&mut self, name: Symbol, pos: Span, kind: Kind) -> usize {
+        let id = self.bindings.len();
+        self.bindings.push(Binding {
+            name,
+            pos,
+            kind,
+            used: false,
+        });
+        self.ids.insert(pos, id);
+        id
+    }
+
+    /// The binding the name used at `pos` refers to, if it is one.
+    fn resolve(&self, pos: Span) -> Option<usize> {
+        self.ids.get(self.decs.get(&pos)?).copied()
+    }
+
+    /// Which functions the main program can reach through calls.
+    fn called(&self) -> Vec<bool> {
+        let mut called = vec![false; self.bindings.len()];
+        let mut work: Vec<usize> = self
+            .calls
+            .iter()
+            .filter(|(caller, _)| caller.is_none())
+            .map(|&(_, callee)| callee)
+            .collect();
+        while let Some(id) = work.pop() {
+            if !std::mem::replace(&mut called[id], true) {
+                let callees = self.calls.iter().filter(|(caller, _)| *caller == Some(id));
+                work.extend(callees.map(|&(_, callee)| callee));
+            }
+        }
+        called
+    }
+}
+
+/// Whether evaluating `exp` always ends in a `break`.
+fn breaks(exp: &Exp) -> bool {
+    match &exp.kind {
+        ExpKind::Break => true,
+        ExpKind::Seq(exps) => exps.iter().any(breaks),
+        ExpKind::If {
+            then_,
+            else_: Some(else_),
+            ..
+        } => breaks(then_) && breaks(else_),
+        ExpKind::Let { body, .. } => breaks(body),
+        _ => false,
+    }
+}
+
+impl Visitor for Linter {
+    fn visit_exp(&mut self, exp: &Exp) {
+        match &exp.kind {
+            ExpKind::Call { .. } => {
+                if let Some(callee) = self.resolve(exp.pos) {
+                    self.calls.push((self.current.last().copied(), callee));
+                }
+            }
+            ExpKind::Seq(exps) => {
+                if let Some(i) = exps.iter().position(breaks).filter(|&i| i + 1 < exps.len()) {
+                    let rest = exps[i + 1].pos.to(exps[exps.len() - 1].pos);
+                    self.unreachable.push((rest, exps[i].pos));
+                }
+            }
+            _ => {}
+        }
+        walk_exp(self, exp);
+    }
+
+    fn visit_var(&mut self, var: &Var) {
+        if let VarKind::Simple(_) = var.kind {
+            if let Some(id) = self.resolve(var.pos) {
+                self.bindings[id].used = true;
+            }
+        }
+        walk_var(self, var);
+    }
+
+    fn visit_dec(&mut self, dec: &Dec) {
+        match dec {
+            Dec::Var(v) => {
+                self.visit_exp(&v.init);
+                self.bind(v.name, v.pos, Kind::Var);
+            }
+            Dec::Type(_) | Dec::Import(_) => {}
+            Dec::Function(group) => {
+                let parent = self.current.last().copied();
+                let ids: Vec<usize> = group
+                    .iter()
+                    .map(|f| self.bind(f.name, f.pos, Kind::Function { parent }))
+                    .collect();
+                for (id, f) in ids.into_iter().zip(group) {
+                    self.current.push(id);
+                    for param in &f.params {
+                        self.bind(param.name, param.pos, Kind::Param);
+                    }
+                    self.visit_exp(&f.body);
+                    self.current.pop();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use crate::lints::{check, Lints};
+use crate::parser::parse;
+use crate::semant::Semant;
+
+/// The warnings for `src` under `lints`, as messages with the text they point at.
+fn warnings_with(src: &str, lints: Lints) -> Vec<(String, &str)> {
+    let exp = parse(src).unwrap_or_else(|e| panic!("failed to parse {src:?}: {e:?}"));
+    let mut semant = Semant::new();
+    semant.check(&exp);
+    check(&exp, &semant, lints)
+        .iter()
+        .map(|d| {
+            assert!(!d.is_error());
+            let text = &src[d.pos.lo() as usize..d.pos.hi() as usize];
+            (d.msg.to_string(), text)
+        })
+        .collect()
+}
+
+fn warnings(src: &str) -> Vec<(String, &str)> {
+    warnings_with(src, Lints::default())
+}
+
+#[test]
+fn used_bindings_are_not_reported() {
+    let src = "let var n := 3
+                   function fact(k: int): int = if k = 0 then 1 else k * fact(k - 1)
+               in for i := 1 to n do print(chr(fact(i))) end";
+    assert_eq!(warnings(src), []);
+}
+
+#[test]
+fn unused_variables_and_parameters() {
+    let src = "let var a := 1 var b := 2 function f(x: int, y: int): int = x in f(b, 0) end";
+    assert_eq!(
+        warnings(src),
+        [
+            ("unused variable `a`".to_string(), "var a := 1"),
+            ("unused parameter `y`".to_string(), "y: int"),
+        ]
+    );
+    // An inner declaration shadows the outer one, which is then unused.
+    assert_eq!(
+        warnings("let var a := 1 in let var a := 2 in a end end"),
+        [("unused variable `a`".to_string(), "var a := 1")]
+    );
+}
+
+#[test]
+fn functions_must_be_reachable_from_the_program() {
+    let src = "let function even(n: int): int = if n = 0 then 1 else odd(n - 1)
+                   function odd(n: int): int = if n = 0 then 0 else even(n - 1)
+                   function loop() = loop()
+               in odd(3) end";
+    assert_eq!(
+        warnings(src),
+        [(
+            "function `loop` is never called".to_string(),
+            "function loop() = loop()"
+        )]
+    );
+    // Functions nested in an uncalled one are not reported on their own.
+    let src = "let function f() = let function g() = () in g() end in end";
+    let found = warnings(src);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].0, "function `f` is never called");
+}
+
+#[test]
+fn code_after_a_break_is_unreachable() {
+    let src = "while 1 do (print(\"a\"); break; print(\"b\"); print(\"c\"))";
+    assert_eq!(
+        warnings(src),
+        [(
+            "unreachable expression".to_string(),
+            "print(\"b\"); print(\"c\")"
+        )]
+    );
+    let src = "while 1 do ((if 1 then break else break); print(\"b\"))";
+    assert_eq!(warnings(src).len(), 1);
+    // A break in one branch, or in a nested loop, may not be taken.
+    assert_eq!(warnings("while 1 do ((if 1 then break); print(\"b\"))"), []);
+    assert_eq!(
+        warnings("while 1 do ((while 1 do break); print(\"b\"))"),
+        []
+    );
+}
+
+#[test]
+fn groups_can_be_turned_off() {
+    let src = "let var a := 1 in while 1 do (break; ()) end";
+    assert_eq!(warnings(src).len(), 2);
+    let mut lints = Lints::default();
+    lints.set("unused=off").unwrap();
+    assert_eq!(
+        warnings_with(src, lints),
+        [("unreachable expression".to_string(), "()")]
+    );
+    lints.set("all=off").unwrap();
+    assert_eq!(warnings_with(src, lints), []);
+    let src = "if 2 - 2 then ()";
+    assert_eq!(
+        warnings(src),
+        [("condition is always false".to_string(), "2 - 2")]
+    );
+    let mut lints = Lints::default();
+    lints.set("constant=off").unwrap();
+    assert_eq!(warnings_with(src, lints), []);
+    assert_eq!(
+        lints.set("unused").err().as_deref(),
+        Some("expected `<lint>=on|off`, found `unused`")
+    );
+    assert_eq!(
+        lints.set("dead=off").err().as_deref(),
+        Some("unknown lint `dead`")
+    );
+}
@@ -0,0 +1,84 @@
+#![allow(dead_code)]
+
+//! Liveness analysis and interference graphs, the `Liveness` module of
+//! chapter 10.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::analysis::{self, Direction, Meet, Problem};
+use crate::flowgraph::FlowGraph;
+use crate::temp::Temp;
+
+/// The temporaries live on exit from each node of `flow`: a backward
+/// problem where a node generates its uses and kills its definitions.
+pub(crate) fn live_out(flow: &FlowGraph) -> Vec<BTreeSet<Temp>> {
+    let succ: Vec<Vec<usize>> = flow.nodes.iter().map(|node| node.succ.clone()).collect();
+    let problem = Problem {
+        direction: Direction::Backward,
+        meet: Meet::Union,
+        gen: flow
+            .nodes
+            .iter()
+            .map(|node| node.uses.iter().copied().collect())
+            .collect(),
+        kill: flow
+            .nodes
+            .iter()
+            .map(|node| node.def.iter().copied().collect())
+            .collect(),
+    };
+    analysis::solve(&succ, &problem).exit
+}
+
+/// Which temporaries may not share a register. Ordered collections keep
+/// allocation deterministic.
+#[derive(Debug, Default)]
+pub(crate) struct Interference {
+    pub(crate) adj: BTreeMap<Temp, BTreeSet<Temp>>,
+    /// `(dst, src)` of every move, candidates for sharing a register.
+    pub(crate) moves: Vec<(Temp, Temp)>,
+}
+
+impl Interference {
+    fn add_node(&mut self, t: Temp) {
+        self.adj.entry(t).or_default();
+    }
+
+    fn add_edge(&mut self, a: Temp, b: Temp) {
+        if a != b {
+            self.adj.entry(a).or_default().insert(b);
+            self.adj.entry(b).or_default().insert(a);
+        }
+    }
+
+    pub(crate) fn interferes(&self, a: Temp, b: Temp) -> bool {
+        self.adj.get(&a).is_some_and(|adj| adj.contains(&b))
+    }
+}
+
+/// Every temporary defined at a node interferes with those live after it,
+/// except that a move's destination doesn't interfere with its source.
+pub(crate) fn interference_graph(flow: &FlowGraph) -> Interference {
+    let live_out = live_out(flow);
+    let mut graph = Interference::default();
+    for (node, out) in flow.nodes.iter().zip(&live_out) {
+        for &t in node.def.iter().chain(&node.uses) {
+            graph.add_node(t);
+        }
+        for &d in &node.def {
+            for &t in out {
+                if node.is_move && node.uses.contains(&t) {
+                    continue;
+                }
+                graph.add_edge(d, t);
+            }
+        }
+        if node.is_move {
+            graph.moves.push((node.def[0], node.uses[0]));
+        }
+    }
+    graph
+}
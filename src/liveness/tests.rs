@@ -0,0 +1,75 @@
+use std::collections::BTreeSet;
+
+use crate::codegen::Instr;
+use crate::flowgraph::instrs_to_graph;
+use crate::liveness::{interference_graph, live_out};
+use crate::temp::{Label, Temp};
+
+/// The loop of Graph 10.1 in the book:
+///
+/// ```text
+///     a := 0
+/// L1: b := a + 1
+///     c := c + b
+///     a := b * 2
+///     if a < N goto L1
+///     return c
+/// ```
+fn loop_program() -> (Vec<Instr>, [Temp; 3]) {
+    let (a, b, c) = (Temp::new(), Temp::new(), Temp::new());
+    let (l1, l2) = (Label::new(), Label::new());
+    let instrs = vec![
+        Instr::oper("movq $0, `d0", vec![a], vec![]),
+        Instr::Label {
+            assem: format!("{l1}:"),
+            label: l1,
+        },
+        Instr::oper("leaq 1(`s0), `d0", vec![b], vec![a]),
+        Instr::oper("addq `s0, `d0", vec![c], vec![b, c]),
+        Instr::oper("leaq (`s0,`s0), `d0", vec![a], vec![b]),
+        Instr::oper("cmpq $10, `s0", vec![], vec![a]),
+        Instr::jump("jl `j0", vec![], vec![l1, l2]),
+        Instr::Label {
+            assem: format!("{l2}:"),
+            label: l2,
+        },
+        Instr::jump("", vec![c], vec![]),
+    ];
+    (instrs, [a, b, c])
+}
+
+#[test]
+fn live_ranges_of_the_book_example() {
+    let (instrs, [a, b, c]) = loop_program();
+    let flow = instrs_to_graph(&instrs);
+    assert_eq!(flow.nodes[6].succ, [1, 7]);
+    assert_eq!(flow.nodes[1].pred, [0, 6]);
+    assert!(flow.nodes[8].succ.is_empty());
+
+    let out = live_out(&flow);
+    let set = |temps: &[Temp]| temps.iter().copied().collect::<BTreeSet<_>>();
+    assert_eq!(out[0], set(&[a, c]));
+    assert_eq!(out[2], set(&[b, c]));
+    assert_eq!(out[3], set(&[b, c]));
+    assert_eq!(out[4], set(&[a, c]));
+    assert_eq!(out[6], set(&[a, c]));
+    assert_eq!(out[8], set(&[]));
+
+    let graph = interference_graph(&flow);
+    assert!(graph.interferes(a, c));
+    assert!(graph.interferes(b, c));
+    assert!(!graph.interferes(a, b));
+}
+
+#[test]
+fn moves_do_not_make_their_ends_interfere() {
+    let (a, b) = (Temp::new(), Temp::new());
+    let instrs = vec![
+        Instr::oper("movq $1, `d0", vec![a], vec![]),
+        Instr::mov(b, a),
+        Instr::jump("", vec![a, b], vec![]),
+    ];
+    let graph = interference_graph(&instrs_to_graph(&instrs));
+    assert!(!graph.interferes(a, b));
+    assert_eq!(graph.moves, [(b, a)]);
+}
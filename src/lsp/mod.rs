@@ -0,0 +1,609 @@
+#![allow(dead_code)]
+
+//! `tigerc lsp`: a Language Server Protocol server over stdio.
+//!
+//! Every open document is parsed and type checked on each change, with the
+//! same pipeline as the command line, and its diagnostics are published.
+//! The server also answers `textDocument/documentSymbol` with the declared
+//! functions, variables and types, `textDocument/hover` with the type of
+//! the declaration or use under the cursor, and `textDocument/definition`
+//! with the declaration a use refers to, as the type checker resolved it.
+//! `textDocument/completion` offers the declared types where a type goes,
+//! the declared functions and variables where an expression does, and the
+//! fields of a record after a `.`. Documents are synced in full, and an
+//! edit cancels the analysis of the edit before it, if it is still running.
+//!
+//! Positions are 0-based lines and UTF-16 columns, the protocol's default
+//! encoding.
+
+#[cfg(test)]
+mod tests;
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Sender};
+
+use crate::ast::visit::{self, Visitor};
+use crate::ast::{Dec, Exp};
+use crate::cancel::CancellationToken;
+use crate::diagnostics::{Diagnostic, Lang, Message, Severity};
+use crate::json::{self, Json};
+use crate::lints::{self, Lints};
+use crate::parser::{self, Completion};
+use crate::semant::{Binding, Derivation, Semant};
+use crate::source_map::{ColumnPolicy, SourceFile};
+use crate::span::Span;
+use crate::types::TyKind;
+
+/// LSP `SymbolKind`s.
+const SYMBOL_STRUCT: u32 = 23;
+const SYMBOL_FUNCTION: u32 = 12;
+const SYMBOL_VARIABLE: u32 = 13;
+
+/// LSP `CompletionItemKind`s.
+const COMPLETION_FUNCTION: u32 = 3;
+const COMPLETION_FIELD: u32 = 5;
+const COMPLETION_VARIABLE: u32 = 6;
+const COMPLETION_STRUCT: u32 = 22;
+
+/// JSON-RPC error codes for a message that is not JSON, and for an unknown
+/// method.
+const PARSE_ERROR: i32 = -32700;
+const METHOD_NOT_FOUND: i32 = -32601;
+
+/// Serves requests from `input` until the client sends `exit`, which is an
+/// error unless it asked to `shutdown` first. A message that cannot be
+/// read as JSON gets a parse error in reply, and the server goes on.
+///
+/// Messages are read on a thread of their own, so that an edit arriving
+/// while the previous one is analyzed cancels that analysis.
+pub(crate) fn serve(mut input: impl BufRead + Send, mut output: impl Write) -> Result<(), String> {
+    let mut server = Server {
+        lang: Lang::from_env(),
+        ..Server::default()
+    };
+    let (sender, messages) = mpsc::channel();
+    std::thread::scope(|scope| {
+        scope.spawn(move || read_messages(&mut input, &sender));
+        for incoming in &messages {
+            let Incoming { msg, cancel } = incoming?;
+            let replies = match msg {
+                Ok(msg) => server.respond(&msg, &cancel),
+                Err(e) => vec![error_reply(Json::Null, PARSE_ERROR, e)],
+            };
+            for reply in replies {
+                let reply = reply.to_string();
+                write!(output, "Content-Length: {}\r\n\r\n{reply}", reply.len())
+                    .and_then(|()| output.flush())
+                    .map_err(|e| format!("could not write a reply: {e}"))?;
+            }
+            if server.exited {
+                break;
+            }
+        }
+        Ok::<(), String>(())
+    })?;
+    match server.shutdown {
+        true => Ok(()),
+        false => Err("the client left without a `shutdown` request".to_string()),
+    }
+}
+
+/// A message as read, with the token that cancels the analysis it starts.
+struct Incoming {
+    msg: Result<Json, String>,
+    cancel: CancellationToken,
+}
+
+/// Sends the messages of `input` on until its end, `exit`, or the server
+/// stopping. An edit to a document cancels the analysis of the edit before
+/// it, which would only be replaced.
+fn read_messages(input: &mut impl BufRead, sender: &Sender<Result<Incoming, String>>) {
+    let mut analyses: HashMap<String, CancellationToken> = HashMap::new();
+    loop {
+        let body = match read_message(input) {
+            Ok(Some(body)) => body,
+            Ok(None) => return,
+            Err(e) => {
+                let _ = sender.send(Err(e));
+                return;
+            }
+        };
+        let msg =
+            body.and_then(|body| json::parse(&body).map_err(|e| format!("malformed message: {e}")));
+        let cancel = CancellationToken::new();
+        let method = msg
+            .as_ref()
+            .map_or("", |msg| msg.get("method").as_str().unwrap_or_default());
+        if let Ok(msg) = &msg {
+            let uri = msg.get("params").get("textDocument").get("uri").as_str();
+            let stale = match (method, uri) {
+                ("textDocument/didOpen" | "textDocument/didChange", Some(uri)) => {
+                    analyses.insert(uri.to_string(), cancel.clone())
+                }
+                ("textDocument/didClose", Some(uri)) => analyses.remove(uri),
+                _ => None,
+            };
+            if let Some(stale) = stale {
+                stale.cancel();
+            }
+        }
+        let exit = method == "exit";
+        if sender.send(Ok(Incoming { msg, cancel })).is_err() || exit {
+            return;
+        }
+    }
+}
+
+/// The body of the next message, or `None` at the end of the input. A
+/// message without a length or whose body is not UTF-8 is read past, and
+/// comes back as the error to reply with; failing to read is an error.
+fn read_message(input: &mut impl BufRead) -> Result<Option<Result<String, String>>, String> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        match input.read_line(&mut line) {
+            Ok(0) => return Ok(None),
+            Ok(_) => {}
+            Err(e) => return Err(format!("could not read a message: {e}")),
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+    let Some(length) = length else {
+        return Ok(Some(Err("message without a `Content-Length`".to_string())));
+    };
+    let mut body = vec![0; length];
+    input
+        .read_exact(&mut body)
+        .map_err(|e| format!("could not read a message: {e}"))?;
+    Ok(Some(
+        String::from_utf8(body).map_err(|_| "message is not UTF-8".to_string()),
+    ))
+}
+
+/// A JSON-RPC error reply to the request `id`.
+fn error_reply(id: Json, code: i32, message: String) -> Json {
+    Json::object([
+        ("jsonrpc", Json::str("2.0")),
+        ("id", id),
+        (
+            "error",
+            Json::object([
+                ("code", Json::Int(code.into())),
+                ("message", Json::String(message)),
+            ]),
+        ),
+    ])
+}
+
+/// Runs `f`, turning a panic into its message, so that a bug in the
+/// compiler fails one analysis rather than the server, as it fails one
+/// file of `tigerc check`.
+fn contain<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| panic_message(&*payload))
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match (
+        payload.downcast_ref::<&str>(),
+        payload.downcast_ref::<String>(),
+    ) {
+        (Some(msg), _) => msg.to_string(),
+        (_, Some(msg)) => msg.clone(),
+        _ => "panicked".to_string(),
+    }
+}
+
+/// An open document, analyzed.
+struct Document {
+    file: SourceFile,
+    diagnostics: Vec<Diagnostic>,
+    derivations: Vec<Derivation>,
+    bindings: Vec<Binding>,
+    symbols: Vec<Symbol>,
+}
+
+/// A declaration listed by `documentSymbol`.
+struct Symbol {
+    name: String,
+    kind: u32,
+    pos: Span,
+}
+
+impl Document {
+    /// The document with `text`, analyzed, or `None` if `cancel` was
+    /// cancelled first. Parsing is not interrupted: it is linear in the
+    /// size of the document and quick next to type checking.
+    fn analyze(uri: &str, text: &str, cancel: &CancellationToken) -> Option<Document> {
+        let file = SourceFile::new(uri, text).with_columns(ColumnPolicy::LSP);
+        let parsed = parser::parse_reporting(text);
+        let mut diagnostics = parsed.diagnostics;
+        let (mut derivations, mut bindings, mut symbols) = (Vec::new(), Vec::new(), Vec::new());
+        if let Some(ast) = parsed.ast.filter(|_| parsed.complete) {
+            if cancel.is_cancelled() {
+                return None;
+            }
+            let analyzed = contain(|| {
+                let mut semant = Semant::new().with_cancellation(cancel.clone());
+                semant.check(&ast);
+                if cancel.is_cancelled() {
+                    return None;
+                }
+                let mut found: Vec<Diagnostic> =
+                    semant.errors().iter().map(Diagnostic::from).collect();
+                if !found.iter().chain(&diagnostics).any(Diagnostic::is_error) {
+                    found.extend(lints::check(&ast, &semant, Lints::default()));
+                }
+                let facts = (semant.derivations().to_vec(), semant.bindings().to_vec());
+                Some((found, facts))
+            });
+            match analyzed {
+                Ok(None) => return None,
+                Ok(Some((found, facts))) => {
+                    diagnostics.extend(found);
+                    (derivations, bindings) = facts;
+                }
+                Err(msg) => {
+                    let pos = Span::new(0, 0);
+                    diagnostics.push(Diagnostic::error(
+                        pos,
+                        Message::new("E0002").arg("msg", msg),
+                    ));
+                }
+            }
+            let mut collector = SymbolCollector(Vec::new());
+            collector.visit_exp(&ast);
+            symbols = collector.0;
+        }
+        Some(Document {
+            file,
+            diagnostics,
+            derivations,
+            bindings,
+            symbols,
+        })
+    }
+
+    /// The innermost use of a declared name around `offset`.
+    fn binding_at(&self, offset: u32) -> Option<&Binding> {
+        self.bindings
+            .iter()
+            .filter(|b| b.pos.lo() <= offset && offset < b.pos.hi())
+            .min_by_key(|b| b.pos.hi() - b.pos.lo())
+    }
+
+    /// The fields of the type of `record`, the variable written before the
+    /// `.` that `prefix` follows. The document does not parse with the `.`
+    /// in it, so it is type checked without the `.` and the field name.
+    fn fields(&self, record: &Exp, prefix: Span) -> Vec<String> {
+        let src = self.file.src();
+        let rest = src[prefix.hi() as usize..].trim_start_matches(parser::is_ident_char);
+        let text = format!("{}{rest}", &src[..record.pos.hi() as usize]);
+        let Some(ast) = parser::parse_reporting(&text).ast else {
+            return Vec::new();
+        };
+        let mut semant = Semant::new();
+        semant.check(&ast);
+        match semant
+            .var_type(record.pos)
+            .map(|ty| semant.types.actual_kind(ty))
+        {
+            Some(TyKind::Record { fields, .. }) => {
+                fields.iter().map(|(name, _)| name.to_string()).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn position(&self, offset: u32) -> Json {
+        let (line, col) = self.file.lookup_line_col(offset);
+        Json::object([
+            ("line", Json::from(line as u32 - 1)),
+            ("character", Json::from(col as u32 - 1)),
+        ])
+    }
+
+    fn range(&self, pos: Span) -> Json {
+        Json::object([
+            ("start", self.position(pos.lo())),
+            ("end", self.position(pos.hi().max(pos.lo()))),
+        ])
+    }
+
+    /// The byte offset of an LSP `Position`.
+    fn offset(&self, position: &Json) -> Option<u32> {
+        let line = position.get("line").as_u32()? as usize + 1;
+        let col = position.get("character").as_u32()? as usize + 1;
+        Some(self.file.lookup_offset(line, col))
+    }
+
+    /// The diagnostic as LSP has it, in `lang`. The `code` and `data` of a
+    /// catalogued message are the same in every language.
+    fn diagnostic(&self, uri: &str, diag: &Diagnostic, lang: Lang) -> Json {
+        let severity = match diag.severity {
+            Severity::Error => 1,
+            Severity::Warning => 2,
+            Severity::Note => 3,
+        };
+        let mut message = diag.msg.render(lang);
+        for note in &diag.notes {
+            message.push_str("\nnote: ");
+            message.push_str(&note.render(lang));
+        }
+        let related = diag.labels.iter().map(|label| {
+            Json::object([
+                (
+                    "location",
+                    Json::object([("uri", Json::str(uri)), ("range", self.range(label.pos))]),
+                ),
+                ("message", Json::String(label.msg.render(lang))),
+            ])
+        });
+        let mut json = vec![
+            ("range", self.range(diag.pos)),
+            ("severity", Json::from(severity)),
+            ("source", Json::str("tigerc")),
+            ("message", Json::String(message)),
+            ("relatedInformation", Json::Array(related.collect())),
+        ];
+        if let Some(code) = diag.msg.key() {
+            json.push(("code", Json::str(code)));
+            json.push(("data", diag.msg.to_json()));
+        }
+        Json::object(json)
+    }
+}
+
+struct SymbolCollector(Vec<Symbol>);
+
+impl Visitor for SymbolCollector {
+    fn visit_dec(&mut self, dec: &Dec) {
+        let mut add = |name: &dyn ToString, kind, pos| {
+            self.0.push(Symbol {
+                name: name.to_string(),
+                kind,
+                pos,
+            })
+        };
+        match dec {
+            Dec::Function(group) => group
+                .iter()
+                .for_each(|f| add(&f.name, SYMBOL_FUNCTION, f.pos)),
+            Dec::Var(v) => add(&v.name, SYMBOL_VARIABLE, v.pos),
+            Dec::Type(group) => group
+                .iter()
+                .for_each(|t| add(&t.name, SYMBOL_STRUCT, t.pos)),
+            Dec::Import(_) => {}
+        }
+        visit::walk_dec(self, dec);
+    }
+}
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<String, Document>,
+    /// The language of diagnostic messages.
+    lang: Lang,
+    shutdown: bool,
+    exited: bool,
+}
+
+impl Server {
+    /// The replies and notifications a message calls for.
+    fn handle(&mut self, msg: &Json) -> Vec<Json> {
+        self.respond(msg, &CancellationToken::new())
+    }
+
+    /// The replies and notifications a message calls for, or none for an
+    /// edit whose analysis `cancel` cancelled: a later edit replaces it.
+    fn respond(&mut self, msg: &Json, cancel: &CancellationToken) -> Vec<Json> {
+        let method = msg.get("method").as_str().unwrap_or_default();
+        let params = msg.get("params");
+        let uri = params
+            .get("textDocument")
+            .get("uri")
+            .as_str()
+            .unwrap_or_default();
+        let result = match method {
+            "initialize" => Json::object([(
+                "capabilities",
+                Json::object([
+                    ("textDocumentSync", Json::from(1)),
+                    ("documentSymbolProvider", Json::from(true)),
+                    ("hoverProvider", Json::from(true)),
+                    ("definitionProvider", Json::from(true)),
+                    ("completionProvider", Json::object([])),
+                ]),
+            )]),
+            "shutdown" => {
+                self.shutdown = true;
+                Json::Null
+            }
+            "exit" => {
+                self.exited = true;
+                return Vec::new();
+            }
+            "textDocument/didOpen" => {
+                let text = params.get("textDocument").get("text").as_str();
+                return self.update(uri, text.unwrap_or_default(), cancel);
+            }
+            "textDocument/didChange" => {
+                let changes = params.get("contentChanges").as_array();
+                match changes.last().and_then(|c| c.get("text").as_str()) {
+                    Some(text) => return self.update(uri, text, cancel),
+                    None => return Vec::new(),
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return vec![publish(uri, Vec::new())];
+            }
+            "textDocument/documentSymbol" => match self.documents.get(uri) {
+                Some(doc) => Json::Array(
+                    doc.symbols
+                        .iter()
+                        .map(|symbol| {
+                            let range = doc.range(symbol.pos);
+                            Json::object([
+                                ("name", Json::str(&symbol.name)),
+                                ("kind", Json::from(symbol.kind)),
+                                ("range", range.clone()),
+                                ("selectionRange", range),
+                            ])
+                        })
+                        .collect(),
+                ),
+                None => Json::Null,
+            },
+            "textDocument/hover" => self.hover(uri, params.get("position")),
+            "textDocument/definition" => self.definition(uri, params.get("position")),
+            "textDocument/completion" => self.completion(uri, params.get("position")),
+            // Other notifications, such as `initialized`, need no reply.
+            _ if msg.get("id") == &Json::Null => return Vec::new(),
+            _ => {
+                let text = format!("unknown method `{method}`");
+                return vec![error_reply(msg.get("id").clone(), METHOD_NOT_FOUND, text)];
+            }
+        };
+        vec![Json::object([
+            ("jsonrpc", Json::str("2.0")),
+            ("id", msg.get("id").clone()),
+            ("result", result),
+        ])]
+    }
+
+    fn update(&mut self, uri: &str, text: &str, cancel: &CancellationToken) -> Vec<Json> {
+        let Some(doc) = Document::analyze(uri, text, cancel) else {
+            return Vec::new();
+        };
+        let diagnostics = doc
+            .diagnostics
+            .iter()
+            .map(|d| doc.diagnostic(uri, d, self.lang))
+            .collect();
+        self.documents.insert(uri.to_string(), doc);
+        vec![publish(uri, diagnostics)]
+    }
+
+    /// The type of the innermost declaration, or use of a declared variable
+    /// or function, around `position`.
+    fn hover(&self, uri: &str, position: &Json) -> Json {
+        let Some(doc) = self.documents.get(uri) else {
+            return Json::Null;
+        };
+        let Some(offset) = doc.offset(position) else {
+            return Json::Null;
+        };
+        let declared = doc
+            .derivations
+            .iter()
+            .filter(|d| d.pos.lo() <= offset && offset < d.pos.hi())
+            .map(|d| (d, d.pos));
+        // A use has the type of its declaration; types have none to show.
+        let used = doc.bindings.iter().filter_map(|b| {
+            let covers = b.pos.lo() <= offset && offset < b.pos.hi();
+            let d = doc.derivations.iter().find(|d| d.pos == b.dec)?;
+            covers.then_some((d, b.pos))
+        });
+        // Declarations first, so that they win over uses with the same span.
+        let found = declared
+            .chain(used)
+            .min_by_key(|(_, pos)| pos.hi() - pos.lo());
+        match found {
+            Some((d, pos)) => Json::object([
+                ("contents", Json::String(format!("{}: {}", d.name, d.ty))),
+                ("range", doc.range(pos)),
+            ]),
+            None => Json::Null,
+        }
+    }
+
+    /// The declaration of the name used at `position`.
+    fn definition(&self, uri: &str, position: &Json) -> Json {
+        let Some(doc) = self.documents.get(uri) else {
+            return Json::Null;
+        };
+        let found = doc
+            .offset(position)
+            .and_then(|offset| doc.binding_at(offset));
+        match found {
+            Some(b) => Json::object([("uri", Json::str(uri)), ("range", doc.range(b.dec))]),
+            None => Json::Null,
+        }
+    }
+
+    /// The names that can be written at `position` and start with the
+    /// identifier being written there.
+    fn completion(&self, uri: &str, position: &Json) -> Json {
+        let Some(doc) = self.documents.get(uri) else {
+            return Json::Null;
+        };
+        let Some(offset) = doc.offset(position) else {
+            return Json::Null;
+        };
+        let found = parser::parse_at_cursor(doc.file.src(), offset);
+        let prefix = doc.file.span_to_snippet(found.prefix);
+        let fields = match (&found.completion, &found.partial) {
+            (Completion::Field, Some(record)) => doc.fields(record, found.prefix),
+            _ => Vec::new(),
+        };
+        let mut items: Vec<(&str, u32)> = match found.completion {
+            Completion::Type => vec![("int", COMPLETION_STRUCT), ("string", COMPLETION_STRUCT)],
+            Completion::Field => fields
+                .iter()
+                .map(|f| (f.as_str(), COMPLETION_FIELD))
+                .collect(),
+            _ => Vec::new(),
+        };
+        for symbol in &doc.symbols {
+            let kind = match (&found.completion, symbol.kind) {
+                (Completion::Type, SYMBOL_STRUCT) => COMPLETION_STRUCT,
+                (
+                    Completion::Expression | Completion::Argument(_) | Completion::AssignedValue,
+                    SYMBOL_FUNCTION,
+                ) => COMPLETION_FUNCTION,
+                (
+                    Completion::Expression | Completion::Argument(_) | Completion::AssignedValue,
+                    SYMBOL_VARIABLE,
+                ) => COMPLETION_VARIABLE,
+                _ => continue,
+            };
+            if !items.iter().any(|&(name, _)| name == symbol.name) {
+                items.push((&symbol.name, kind));
+            }
+        }
+        Json::Array(
+            items
+                .into_iter()
+                .filter(|(name, _)| name.starts_with(prefix))
+                .map(|(name, kind)| {
+                    Json::object([("label", Json::str(name)), ("kind", Json::from(kind))])
+                })
+                .collect(),
+        )
+    }
+}
+
+fn publish(uri: &str, diagnostics: Vec<Json>) -> Json {
+    Json::object([
+        ("jsonrpc", Json::str("2.0")),
+        ("method", Json::str("textDocument/publishDiagnostics")),
+        (
+            "params",
+            Json::object([
+                ("uri", Json::str(uri)),
+                ("diagnostics", Json::Array(diagnostics)),
+            ]),
+        ),
+    ])
+}
@@ -0,0 +1,347 @@
+use std::sync::mpsc;
+
+use crate::cancel::CancellationToken;
+use crate::json::{self, Json};
+use crate::lsp::{contain, read_messages, serve, Server};
+
+fn message(text: &str) -> Json {
+    json::parse(text).unwrap_or_else(|e| panic!("bad test message {text:?}: {e}"))
+}
+
+fn open(server: &mut Server, text: &str) -> Vec<Json> {
+    let text = Json::str(text);
+    server.handle(&message(&format!(
+        r#"{{"jsonrpc": "2.0", "method": "textDocument/didOpen",
+            "params": {{"textDocument": {{"uri": "file:///t.tig", "text": {text}}}}}}}"#
+    )))
+}
+
+#[test]
+fn diagnostics_are_published_on_change() {
+    let mut server = Server::default();
+    let replies = open(&mut server, "let var x: int := \"s\"\nin x end");
+    let params = replies[0].get("params");
+    assert_eq!(
+        replies[0].get("method").as_str(),
+        Some("textDocument/publishDiagnostics")
+    );
+    let diag = &params.get("diagnostics").as_array()[0];
+    assert_eq!(
+        diag.get("range").to_string(),
+        r#"{"start":{"line":0,"character":18},"end":{"line":0,"character":21}}"#
+    );
+    assert_eq!(diag.get("severity").as_u32(), Some(1));
+    let related = &diag.get("relatedInformation").as_array()[0];
+    assert_eq!(
+        related.get("message").as_str(),
+        Some("expected because of this annotation")
+    );
+    // The code and arguments carry the message in any language.
+    assert_eq!(diag.get("code").as_str(), Some("E0301"));
+    assert_eq!(
+        diag.get("data").to_string(),
+        r#"{"code":"E0301","args":{"what":{"code":"mismatched-initializer","args":{}},"expected":"int","found":"string"}}"#
+    );
+
+    let fixed = message(
+        r#"{"jsonrpc": "2.0", "method": "textDocument/didChange",
+            "params": {"textDocument": {"uri": "file:///t.tig", "version": 2},
+                       "contentChanges": [{"text": "let var x: int := 1\nin x end"}]}}"#,
+    );
+    let replies = server.handle(&fixed);
+    assert_eq!(
+        replies[0].get("params").get("diagnostics"),
+        &Json::Array(vec![])
+    );
+}
+
+#[test]
+fn symbols_and_hover() {
+    let mut server = Server::default();
+    open(
+        &mut server,
+        "let type t = int\n    function f(a: t): t = a\nin f(1) end",
+    );
+    let symbols = server.handle(&message(
+        r#"{"jsonrpc": "2.0", "id": 1, "method": "textDocument/documentSymbol",
+            "params": {"textDocument": {"uri": "file:///t.tig"}}}"#,
+    ));
+    let names: Vec<_> = symbols[0]
+        .get("result")
+        .as_array()
+        .iter()
+        .map(|s| (s.get("name").to_string(), s.get("kind").as_u32()))
+        .collect();
+    assert_eq!(
+        names,
+        [
+            ("\"t\"".to_string(), Some(23)),
+            ("\"f\"".to_string(), Some(12))
+        ]
+    );
+
+    // Hovering anywhere on the function's declaration shows its type.
+    let hover = server.handle(&message(
+        r#"{"jsonrpc": "2.0", "id": 2, "method": "textDocument/hover",
+            "params": {"textDocument": {"uri": "file:///t.tig"},
+                       "position": {"line": 1, "character": 13}}}"#,
+    ));
+    let contents = hover[0].get("result").get("contents");
+    assert_eq!(contents.as_str(), Some("f: (t) -> t"));
+}
+
+#[test]
+fn uses_lead_to_their_declarations() {
+    let mut server = Server::default();
+    open(
+        &mut server,
+        "let type t = int
+    var x: t := 1
+    function f(a: t): t = a + x
+in f(x) end",
+    );
+    let mut request = |method: &str, line: u32, character: u32| {
+        let replies = server.handle(&message(&format!(
+            r#"{{"jsonrpc": "2.0", "id": 1, "method": "textDocument/{method}",
+                "params": {{"textDocument": {{"uri": "file:///t.tig"}},
+                           "position": {{"line": {line}, "character": {character}}}}}}}"#
+        )));
+        replies[0].get("result").clone()
+    };
+    let range = |result: Json| result.get("range").to_string();
+    let lines = |lo: (u32, u32), hi: (u32, u32)| {
+        format!(
+            r#"{{"start":{{"line":{},"character":{}}},"end":{{"line":{},"character":{}}}}}"#,
+            lo.0, lo.1, hi.0, hi.1
+        )
+    };
+    // `x` in the call goes to its `var`, the call to the function, and the
+    // parameter in the body to the parameter.
+    assert_eq!(range(request("definition", 3, 5)), lines((1, 4), (1, 17)));
+    assert_eq!(range(request("definition", 3, 3)), lines((2, 4), (2, 31)));
+    assert_eq!(range(request("definition", 2, 26)), lines((2, 15), (2, 19)));
+    // A type in an annotation goes to the type's declaration.
+    assert_eq!(range(request("definition", 1, 11)), lines((0, 4), (0, 16)));
+    assert_eq!(request("definition", 3, 0), Json::Null);
+    // Hovering a use shows the type of what it refers to.
+    let hover = request("hover", 3, 5);
+    assert_eq!(hover.get("contents").as_str(), Some("x: t"));
+    assert_eq!(range(hover), lines((3, 5), (3, 6)));
+    assert_eq!(
+        request("hover", 3, 3).get("contents").as_str(),
+        Some("f: (t) -> t")
+    );
+}
+
+#[test]
+fn completion_offers_the_declared_names() {
+    let mut server = Server::default();
+    open(
+        &mut server,
+        "let type point = {x: int}\n    var total := 0\n    function tick(): point = nil\nin ti end",
+    );
+    let mut complete = |line: u32, character: u32| {
+        let replies = server.handle(&message(&format!(
+            r#"{{"jsonrpc": "2.0", "id": 1, "method": "textDocument/completion",
+                "params": {{"textDocument": {{"uri": "file:///t.tig"}},
+                           "position": {{"line": {line}, "character": {character}}}}}}}"#
+        )));
+        replies[0]
+            .get("result")
+            .as_array()
+            .iter()
+            .map(|item| item.get("label").as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+    // After `ti` in the body, and before the variable's initializer.
+    assert_eq!(complete(3, 5), ["tick"]);
+    assert_eq!(complete(1, 16), ["total", "tick"]);
+    // Before the function's result type.
+    assert_eq!(complete(2, 21), ["int", "string", "point"]);
+    // Past the end of the document.
+    assert_eq!(complete(9, 0), Vec::<String>::new());
+}
+
+#[test]
+fn completion_offers_the_fields_of_a_record() {
+    let mut server = Server::default();
+    let mut complete = |text: &str| {
+        let (line, character) = text
+            .lines()
+            .enumerate()
+            .find_map(|(i, line)| Some((i, line.find('|')?)))
+            .unwrap();
+        open(&mut server, &text.replace('|', ""));
+        let replies = server.handle(&message(&format!(
+            r#"{{"jsonrpc": "2.0", "id": 1, "method": "textDocument/completion",
+                "params": {{"textDocument": {{"uri": "file:///t.tig"}},
+                           "position": {{"line": {line}, "character": {character}}}}}}}"#
+        )));
+        replies[0]
+            .get("result")
+            .as_array()
+            .iter()
+            .map(|item| {
+                assert_eq!(item.get("kind").as_u32(), Some(5));
+                item.get("label").as_str().unwrap().to_string()
+            })
+            .collect::<Vec<_>>()
+    };
+    let decs = "let type point = {foo: int, bar: int, four: int}
+    type line = {start: point, stop: point}
+    var x := point {foo = 1, bar = 2, four = 4}
+    var l := line {start = x, stop = nil}";
+    assert_eq!(
+        complete(&format!("{decs}\nin x.| end")),
+        ["foo", "bar", "four"]
+    );
+    assert_eq!(complete(&format!("{decs}\nin x.fo| end")), ["foo", "four"]);
+    // Through fields, before the rest of the name, and without an `end`.
+    assert_eq!(
+        complete(&format!("{decs}\nin l.stop.f|oo + 1")),
+        ["foo", "four"]
+    );
+    assert_eq!(complete(&format!("{decs}\nin l.|")), ["start", "stop"]);
+    // Not a record.
+    assert_eq!(
+        complete(&format!("{decs}\nin x.foo.| end")),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn documents_missing_an_end_are_still_analyzed() {
+    let mut server = Server::default();
+    let replies = open(&mut server, "let var x := 1\nin x");
+    let diagnostics = replies[0].get("params").get("diagnostics").as_array();
+    let messages: Vec<_> = diagnostics
+        .iter()
+        .map(|d| d.get("message").as_str())
+        .collect();
+    assert_eq!(
+        messages,
+        [Some(
+            "missing `end`\nnote: assumed an `end` here to parse the rest of the program"
+        )]
+    );
+    let hover = server.handle(&message(
+        r#"{"jsonrpc": "2.0", "id": 1, "method": "textDocument/hover",
+            "params": {"textDocument": {"uri": "file:///t.tig"},
+                       "position": {"line": 0, "character": 4}}}"#,
+    ));
+    assert_eq!(
+        hover[0].get("result").get("contents").as_str(),
+        Some("x: int")
+    );
+}
+
+#[test]
+fn serves_framed_messages_until_exit() {
+    let frame = |body: &str| format!("Content-Length: {}\r\n\r\n{body}", body.len());
+    let input = [
+        frame(r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#),
+        frame(r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#),
+        frame(r#"{"jsonrpc":"2.0","id":2,"method":"workspace/symbol","params":{}}"#),
+        frame(r#"{"jsonrpc":"2.0","id":3,"method":"shutdown"}"#),
+        frame(r#"{"jsonrpc":"2.0","method":"exit"}"#),
+    ]
+    .concat();
+    let mut output = Vec::new();
+    serve(input.as_bytes(), &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let bodies: Vec<&str> = output.split("\r\n\r\n").skip(1).collect();
+    assert_eq!(bodies.len(), 3, "{output}");
+    assert!(bodies[0].contains(r#""hoverProvider":true"#), "{output}");
+    assert!(
+        bodies[0].contains(r#""definitionProvider":true"#),
+        "{output}"
+    );
+    assert!(bodies[1].contains(r#""code":-32601"#), "{output}");
+    assert!(
+        bodies[2].starts_with(r#"{"jsonrpc":"2.0","id":3,"result":null}"#),
+        "{output}"
+    );
+
+    let exit = frame(r#"{"jsonrpc":"2.0","method":"exit"}"#);
+    assert!(serve(exit.as_bytes(), &mut Vec::new()).is_err());
+}
+
+#[test]
+fn malformed_messages_get_parse_errors_and_the_server_goes_on() {
+    let frame = |body: &[u8]| {
+        [
+            format!("Content-Length: {}\r\n\r\n", body.len()).as_bytes(),
+            body,
+        ]
+        .concat()
+    };
+    let input = [
+        frame(b"{not json"),
+        frame(b"\"\xff\""),
+        b"Content-Type: x\r\n\r\n".to_vec(),
+        frame(br#"{"jsonrpc":"2.0","id":3,"method":"shutdown"}"#),
+        frame(br#"{"jsonrpc":"2.0","method":"exit"}"#),
+    ]
+    .concat();
+    let mut output = Vec::new();
+    serve(&input[..], &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let bodies: Vec<&str> = output.split("\r\n\r\n").skip(1).collect();
+    assert_eq!(bodies.len(), 4, "{output}");
+    for body in &bodies[..3] {
+        assert!(
+            body.starts_with(r#"{"jsonrpc":"2.0","id":null,"error":{"code":-32700,"#),
+            "{output}"
+        );
+    }
+    assert!(bodies[1].contains("not UTF-8"), "{output}");
+    assert!(bodies[2].contains("Content-Length"), "{output}");
+}
+
+#[test]
+fn a_crash_in_analysis_is_contained() {
+    assert_eq!(contain(|| 1), Ok(1));
+    assert_eq!(contain::<i32>(|| panic!("bug")), Err("bug".to_string()));
+}
+
+#[test]
+fn an_edit_cancels_the_analysis_of_the_one_before() {
+    let frame = |body: &str| format!("Content-Length: {}\r\n\r\n{body}", body.len());
+    let edit = |method: &str, uri: &str| {
+        frame(&format!(
+            r#"{{"jsonrpc":"2.0","method":"textDocument/{method}",
+                "params":{{"textDocument":{{"uri":"{uri}","text":"1"}},
+                           "contentChanges":[{{"text":"2"}}]}}}}"#
+        ))
+    };
+    let input = [
+        edit("didOpen", "file:///a.tig"),
+        edit("didOpen", "file:///b.tig"),
+        edit("didChange", "file:///a.tig"),
+        frame(r#"{"jsonrpc":"2.0","method":"exit"}"#),
+        // Not read: the server has exited.
+        edit("didChange", "file:///b.tig"),
+    ]
+    .concat();
+    let (sender, messages) = mpsc::channel();
+    read_messages(&mut input.as_bytes(), &sender);
+    drop(sender);
+    let cancelled: Vec<bool> = messages
+        .iter()
+        .map(|incoming| incoming.unwrap().cancel.is_cancelled())
+        .collect();
+    assert_eq!(cancelled, [true, false, false, false]);
+}
+
+#[test]
+fn a_cancelled_analysis_publishes_nothing() {
+    let mut server = Server::default();
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+    let msg = message(
+        r#"{"jsonrpc": "2.0", "method": "textDocument/didOpen",
+            "params": {"textDocument": {"uri": "file:///t.tig", "text": "let var x := 1 in x end"}}}"#,
+    );
+    assert_eq!(server.respond(&msg, &cancel), Vec::new());
+    assert_eq!(open(&mut server, "let var x := 1 in x end").len(), 1);
+}
@@ -1,38 +1,45 @@
+#[cfg(test)]
+mod alloc_counter;
+mod analysis;
+mod ast;
+#[cfg(feature = "llvm")]
+mod backend;
+mod cancel;
+mod canon;
+mod codegen;
+mod cst;
+mod diagnostics;
+mod driver;
+mod features;
+mod flowgraph;
+mod fmt;
+mod frame;
+mod graph;
+mod interp;
+mod ir;
+mod json;
 mod lexer;
+mod limits;
+mod lints;
+mod liveness;
+mod lsp;
+mod opt;
+mod parser;
+mod pretty;
+mod regalloc;
+mod semant;
+mod shared_str;
+mod source_map;
+mod span;
 mod straight_line_prog;
-
-use straight_line_prog::*;
-
+mod symbol;
+mod temp;
+#[cfg(test)]
+mod testing;
+mod translate;
+mod types;
 
 fn main() {
-    let prog = AStm::Compound(
-        &AStm::Compound(
-            &AStm::Assign("a", &AExp::Op(&AExp::Num(5), ABinop::Plus, &AExp::Num(3))),
-            &AStm::Compound(
-                &AStm::Assign(
-                    "b",
-                    &AExp::Eseq(
-                        &AStm::Print(&AExpList::Pair(
-                            &AExp::Id("a"),
-                            &AExpList::Last(&AExp::Op(
-                                &AExp::Id("a"),
-                                ABinop::Minus,
-                                &AExp::Num(1),
-                            )),
-                        )),
-                        &AExp::Op(&AExp::Num(10), ABinop::Times, &AExp::Id("a")),
-                    ),
-                ),
-                &AStm::Print(&AExpList::Last(&AExp::Id("b"))),
-            ),
-        ),
-        &AStm::Assign("a", &AExp::Num(50)),
-    );
-    let (_, context) = prog.interp(None);
-    println!("response: {context:?}",);
-    if let Some(context) = context {
-        println!("a: {:?}", context.find("a"));
-        println!("b: {:?}", context.find("b"));
-        println!("c: {:?}", context.find("c"));
-    }
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    std::process::exit(limits::with_stack(|| driver::run(&args)));
 }
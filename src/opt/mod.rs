@@ -0,0 +1,294 @@
+#![allow(dead_code)]
+
+//! Optimizations over canonical trees, run between `canon::trace_schedule`
+//! and instruction selection.
+//!
+//! - Constant folding evaluates `BinOp`s of two constants, as the machine
+//!   would: arithmetic wraps, and shifts use the low six bits of the count.
+//!   A division that would trap is left for run time.
+//! - Algebraic simplification drops `+ 0`, `- 0`, `* 1` and `/ 1`, and turns
+//!   `* 0` into `0` when the other operand has no side effects.
+//! - Branch folding turns a `CJump` on two constants into a `Jump`.
+//! - Unreachable code removal drops the statements no jump or fall-through
+//!   leads to, then the jumps to the label right after them.
+//!
+//! Each pass can be turned on by itself through `Passes`, which is how the
+//! tests look at one at a time.
+//!
+//! Every rewrite a pass makes, one folded operator, simplified identity,
+//! folded branch or dropped statement, uses up one unit of `Fuel`. With
+//! `--opt-fuel=N` only the first N rewrites of the program are made, so a
+//! rewrite that changes what a program does can be found by searching for
+//! the smallest N that does, as `tigerc opt-diff --bisect-opt` does.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+
+use crate::canon;
+use crate::ir::{BinOp, Exp, RelOp, Stm};
+use crate::temp::Label;
+
+/// The passes to run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Passes {
+    pub(crate) fold_constants: bool,
+    pub(crate) simplify: bool,
+    pub(crate) fold_branches: bool,
+    pub(crate) remove_unreachable: bool,
+    /// How many rewrites the passes may make in the whole program, or
+    /// `None` for no limit.
+    pub(crate) fuel: Option<u64>,
+}
+
+impl Passes {
+    /// No optimization.
+    pub(crate) const NONE: Passes = Passes {
+        fold_constants: false,
+        simplify: false,
+        fold_branches: false,
+        remove_unreachable: false,
+        fuel: None,
+    };
+
+    /// Every pass.
+    pub(crate) const ALL: Passes = Passes {
+        fold_constants: true,
+        simplify: true,
+        fold_branches: true,
+        remove_unreachable: true,
+        fuel: None,
+    };
+
+    /// The passes of `--opt-level`: none at 0, the expression rewrites at
+    /// 1, and the control flow ones too at 2.
+    pub(crate) fn for_level(level: &str) -> Result<Passes, String> {
+        match level {
+            "0" => Ok(Passes::NONE),
+            "1" => Ok(Passes {
+                fold_constants: true,
+                simplify: true,
+                ..Passes::NONE
+            }),
+            "2" => Ok(Passes::ALL),
+            _ => Err(format!("unknown optimization level `{level}`")),
+        }
+    }
+}
+
+/// The rewrites the passes have made so far, and how many more they may
+/// make. One `Fuel` is shared by every function of a program, in the order
+/// of its fragments.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Fuel {
+    limit: Option<u64>,
+    /// The rewrites made.
+    pub(crate) used: u64,
+    /// The pass that made the last rewrite.
+    pub(crate) last: Option<&'static str>,
+}
+
+impl Fuel {
+    /// The fuel of `passes`, of which none is used yet.
+    pub(crate) fn new(passes: Passes) -> Fuel {
+        Fuel {
+            limit: passes.fuel,
+            ..Fuel::default()
+        }
+    }
+
+    /// Whether `pass` may make one more rewrite, counting it if so.
+    fn burn(&mut self, pass: &'static str) -> bool {
+        if self.limit.is_some_and(|limit| self.used >= limit) {
+            return false;
+        }
+        self.used += 1;
+        self.last = Some(pass);
+        true
+    }
+}
+
+/// Runs `passes` over the statements of a function, as `trace_schedule`
+/// returns them, making the rewrites `fuel` still allows. The result is
+/// still canonical.
+pub(crate) fn optimize(stms: Vec<Stm>, passes: Passes, fuel: &mut Fuel) -> Vec<Stm> {
+    let mut rewriter = Rewriter { passes, fuel };
+    let mut stms: Vec<Stm> = stms.into_iter().map(|stm| rewriter.stm(stm)).collect();
+    if passes.remove_unreachable {
+        stms = canon::remove_fallthrough_jumps(remove_unreachable(stms, fuel));
+    }
+    stms
+}
+
+struct Rewriter<'a> {
+    passes: Passes,
+    fuel: &'a mut Fuel,
+}
+
+impl Rewriter<'_> {
+    fn stm(&mut self, stm: Stm) -> Stm {
+        match stm {
+            Stm::Move(dst, src) => {
+                // The address of a `Mem` destination is rewritten, not the
+                // `Mem` itself.
+                let dst = match *dst {
+                    Exp::Mem(addr) => Exp::mem(self.exp(*addr)),
+                    dst => dst,
+                };
+                Stm::mov(dst, self.exp(*src))
+            }
+            Stm::Exp(e) => Stm::exp(self.exp(*e)),
+            Stm::Jump(target, labels) => Stm::Jump(Box::new(self.exp(*target)), labels),
+            Stm::CJump(op, a, b, t, f) => {
+                let (a, b) = (self.exp(*a), self.exp(*b));
+                match (&a, &b) {
+                    (Exp::Const(x), Exp::Const(y))
+                        if self.passes.fold_branches && self.fuel.burn("fold-branches") =>
+                    {
+                        Stm::jump(if compare(op, *x, *y) { t } else { f })
+                    }
+                    _ => Stm::cjump(op, a, b, t, f),
+                }
+            }
+            Stm::Seq(a, b) => Stm::Seq(Box::new(self.stm(*a)), Box::new(self.stm(*b))),
+            Stm::Label(_) => stm,
+        }
+    }
+
+    fn exp(&mut self, exp: Exp) -> Exp {
+        match exp {
+            Exp::BinOp(op, a, b) => {
+                let (a, b) = (self.exp(*a), self.exp(*b));
+                if self.passes.fold_constants {
+                    if let (Exp::Const(x), Exp::Const(y)) = (&a, &b) {
+                        if let Some(n) = fold(op, *x, *y) {
+                            if self.fuel.burn("fold-constants") {
+                                return Exp::Const(n);
+                            }
+                        }
+                    }
+                }
+                if self.passes.simplify {
+                    if let Some(operand) = simplify(op, &a, &b) {
+                        if self.fuel.burn("simplify") {
+                            return match operand {
+                                Operand::Left => a,
+                                Operand::Right => b,
+                            };
+                        }
+                    }
+                }
+                Exp::binop(op, a, b)
+            }
+            Exp::Mem(addr) => Exp::mem(self.exp(*addr)),
+            Exp::Call(func, args) => Exp::call(
+                self.exp(*func),
+                args.into_iter().map(|a| self.exp(a)).collect(),
+            ),
+            Exp::ESeq(stm, e) => Exp::eseq(self.stm(*stm), self.exp(*e)),
+            Exp::Const(_) | Exp::Name(_) | Exp::Temp(_) => exp,
+        }
+    }
+}
+
+/// `x op y`, or `None` if it would trap.
+fn fold(op: BinOp, x: i64, y: i64) -> Option<i64> {
+    Some(match op {
+        BinOp::Plus => x.wrapping_add(y),
+        BinOp::Minus => x.wrapping_sub(y),
+        BinOp::Mul => x.wrapping_mul(y),
+        BinOp::Div => x.checked_div(y)?,
+        BinOp::And => x & y,
+        BinOp::Or => x | y,
+        BinOp::Xor => x ^ y,
+        BinOp::LShift => x.wrapping_shl(y as u32),
+        BinOp::RShift => (x as u64).wrapping_shr(y as u32) as i64,
+        BinOp::ARShift => x.wrapping_shr(y as u32),
+    })
+}
+
+fn compare(op: RelOp, x: i64, y: i64) -> bool {
+    let (ux, uy) = (x as u64, y as u64);
+    match op {
+        RelOp::Eq => x == y,
+        RelOp::Ne => x != y,
+        RelOp::Lt => x < y,
+        RelOp::Gt => x > y,
+        RelOp::Le => x <= y,
+        RelOp::Ge => x >= y,
+        RelOp::ULt => ux < uy,
+        RelOp::ULe => ux <= uy,
+        RelOp::UGt => ux > uy,
+        RelOp::UGe => ux >= uy,
+    }
+}
+
+/// An operand of a `BinOp`.
+enum Operand {
+    Left,
+    Right,
+}
+
+/// The operand `a op b` equals, if it is an identity.
+fn simplify(op: BinOp, a: &Exp, b: &Exp) -> Option<Operand> {
+    match (op, a, b) {
+        (BinOp::Plus | BinOp::Minus, _, Exp::Const(0))
+        | (BinOp::Mul | BinOp::Div, _, Exp::Const(1)) => Some(Operand::Left),
+        (BinOp::Plus, Exp::Const(0), _) | (BinOp::Mul, Exp::Const(1), _) => Some(Operand::Right),
+        (BinOp::Mul, _, Exp::Const(0)) if is_pure(a) => Some(Operand::Right),
+        (BinOp::Mul, Exp::Const(0), _) if is_pure(b) => Some(Operand::Left),
+        _ => None,
+    }
+}
+
+/// Whether evaluating `exp` can be skipped: it neither calls nor reads
+/// memory, which could fault.
+fn is_pure(exp: &Exp) -> bool {
+    match exp {
+        Exp::Const(_) | Exp::Name(_) | Exp::Temp(_) => true,
+        Exp::BinOp(_, a, b) => is_pure(a) && is_pure(b),
+        Exp::Mem(_) | Exp::Call(..) | Exp::ESeq(..) => false,
+    }
+}
+
+/// `stms` without the statements that cannot run: those after a jump up to
+/// the next label that a reachable jump targets. Each statement dropped
+/// uses up a unit of `fuel`.
+fn remove_unreachable(stms: Vec<Stm>, fuel: &mut Fuel) -> Vec<Stm> {
+    let index: HashMap<Label, usize> = stms
+        .iter()
+        .enumerate()
+        .filter_map(|(i, stm)| match stm {
+            Stm::Label(label) => Some((*label, i)),
+            _ => None,
+        })
+        .collect();
+    let mut live = vec![false; stms.len()];
+    // The function's exit label stays, for the epilogue that follows it.
+    if let Some(last) = live.last_mut() {
+        *last = matches!(stms.last(), Some(Stm::Label(_)));
+    }
+    let mut work = vec![0];
+    while let Some(start) = work.pop() {
+        for (i, stm) in stms.iter().enumerate().skip(start) {
+            if live[i] {
+                break;
+            }
+            live[i] = true;
+            let targets = match stm {
+                Stm::Jump(_, labels) => labels.clone(),
+                Stm::CJump(_, _, _, t, f) => vec![*t, *f],
+                _ => Vec::new(),
+            };
+            work.extend(targets.iter().filter_map(|label| index.get(label)));
+            if matches!(stm, Stm::Jump(..)) {
+                break;
+            }
+        }
+    }
+    stms.into_iter()
+        .zip(live)
+        .filter_map(|(stm, live)| (live || !fuel.burn("remove-unreachable")).then_some(stm))
+        .collect()
+}
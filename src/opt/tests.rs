@@ -0,0 +1,240 @@
+use crate::canon::{basic_blocks, linearize, trace_schedule};
+use crate::codegen::emit;
+use crate::ir::{self, BinOp, Exp, RelOp, Stm};
+use crate::opt::{optimize, Fuel, Passes};
+use crate::parser::parse;
+use crate::semant::Semant;
+use crate::temp::{Label, Temp};
+use crate::translate::Fragment;
+
+fn only(pass: fn(&mut Passes)) -> Passes {
+    let mut passes = Passes::NONE;
+    pass(&mut passes);
+    passes
+}
+
+/// `stms` optimized with `passes`, as the only function of a program.
+fn optimized(stms: Vec<Stm>, passes: Passes) -> Vec<Stm> {
+    optimize(stms, passes, &mut Fuel::new(passes))
+}
+
+/// `exp` moved into a fresh temporary, optimized with `passes`.
+fn rewritten(exp: Exp, passes: Passes) -> Exp {
+    let t = Temp::new();
+    match &optimized(vec![Stm::mov(Exp::Temp(t), exp)], passes)[..] {
+        [Stm::Move(_, src)] => (**src).clone(),
+        stms => panic!("expected one move, got {stms:?}"),
+    }
+}
+
+fn binop(op: BinOp, a: Exp, b: Exp) -> Exp {
+    Exp::binop(op, a, b)
+}
+
+#[test]
+fn constants_fold_as_the_machine_computes() {
+    let fold = only(|p| p.fold_constants = true);
+    let c = Exp::Const;
+    let cases = [
+        (binop(BinOp::Plus, c(2), binop(BinOp::Mul, c(3), c(4))), 14),
+        (binop(BinOp::Plus, c(i64::MAX), c(1)), i64::MIN),
+        (binop(BinOp::Div, c(-7), c(2)), -3),
+        (binop(BinOp::LShift, c(1), c(65)), 2),
+        (binop(BinOp::RShift, c(-1), c(60)), 15),
+        (binop(BinOp::ARShift, c(-16), c(2)), -4),
+        (binop(BinOp::Xor, c(6), c(3)), 5),
+    ];
+    for (exp, value) in cases {
+        assert_eq!(rewritten(exp, fold), c(value));
+    }
+    // Divisions that trap are kept for run time.
+    for (a, b) in [(1, 0), (i64::MIN, -1)] {
+        let exp = binop(BinOp::Div, c(a), c(b));
+        assert_eq!(rewritten(exp.clone(), fold), exp);
+    }
+}
+
+#[test]
+fn identities_are_simplified_away() {
+    let simplify = only(|p| p.simplify = true);
+    let x = Exp::Temp(Temp::new());
+    for exp in [
+        binop(BinOp::Plus, x.clone(), Exp::Const(0)),
+        binop(BinOp::Plus, Exp::Const(0), x.clone()),
+        binop(BinOp::Minus, x.clone(), Exp::Const(0)),
+        binop(BinOp::Mul, Exp::Const(1), x.clone()),
+        binop(BinOp::Div, x.clone(), Exp::Const(1)),
+    ] {
+        assert_eq!(rewritten(exp, simplify), x);
+    }
+    let zero = binop(BinOp::Mul, x.clone(), Exp::Const(0));
+    assert_eq!(rewritten(zero, simplify), Exp::Const(0));
+    // A load may fault, so it is still done.
+    let load = binop(BinOp::Mul, Exp::mem(x.clone()), Exp::Const(0));
+    assert_eq!(rewritten(load.clone(), simplify), load);
+    // `0 - x` is not `x`.
+    let negation = binop(BinOp::Minus, Exp::Const(0), x);
+    assert_eq!(rewritten(negation.clone(), simplify), negation);
+}
+
+#[test]
+fn passes_only_run_when_enabled() {
+    let exp = binop(BinOp::Plus, Exp::Const(1), Exp::Const(0));
+    assert_eq!(rewritten(exp.clone(), Passes::NONE), exp);
+    let simplify = only(|p| p.simplify = true);
+    assert_eq!(rewritten(exp.clone(), simplify), Exp::Const(1));
+
+    let (t, f) = (Label::new(), Label::new());
+    let cjump = Stm::cjump(RelOp::Lt, Exp::Const(1), Exp::Const(2), t, f);
+    let stms = vec![cjump, Stm::Label(f), Stm::Label(t)];
+    let fold = only(|p| p.fold_constants = true);
+    assert_eq!(optimized(stms.clone(), fold), stms);
+}
+
+#[test]
+fn constant_branches_become_jumps() {
+    let fold = only(|p| p.fold_branches = true);
+    let (t, f) = (Label::new(), Label::new());
+    let cases = [
+        (RelOp::Lt, 1, 2, t),
+        (RelOp::Ge, 1, 2, f),
+        (RelOp::Eq, 3, 3, t),
+        (RelOp::ULt, -1, 2, f),
+        (RelOp::UGt, -1, 2, t),
+    ];
+    for (op, a, b, target) in cases {
+        let cjump = Stm::cjump(op, Exp::Const(a), Exp::Const(b), t, f);
+        assert_eq!(optimized(vec![cjump], fold), [Stm::jump(target)]);
+    }
+    // Folded operands make a branch constant.
+    let both = Passes {
+        fold_constants: true,
+        ..fold
+    };
+    let sum = binop(BinOp::Plus, Exp::Const(1), Exp::Const(1));
+    let cjump = Stm::cjump(RelOp::Eq, sum, Exp::Const(2), t, f);
+    assert_eq!(optimized(vec![cjump], both), [Stm::jump(t)]);
+}
+
+#[test]
+fn unreachable_statements_are_removed() {
+    let remove = only(|p| p.remove_unreachable = true);
+    let (a, b, c, done) = (Label::new(), Label::new(), Label::new(), Label::new());
+    let t = Exp::Temp(Temp::new());
+    let set = |n| Stm::mov(t.clone(), Exp::Const(n));
+    let stms = vec![
+        set(1),
+        Stm::jump(b),
+        // Nothing jumps to `a`.
+        Stm::Label(a),
+        set(2),
+        Stm::jump(c),
+        Stm::Label(b),
+        set(3),
+        Stm::jump(done),
+        Stm::Label(c),
+        set(4),
+        Stm::Label(done),
+    ];
+    // The jumps to `b` and `done` now fall through.
+    let expected = vec![set(1), Stm::Label(b), set(3), Stm::Label(done)];
+    assert_eq!(optimized(stms, remove), expected);
+}
+
+#[test]
+fn the_exit_label_is_kept_after_an_endless_loop() {
+    let remove = only(|p| p.remove_unreachable = true);
+    let (top, done) = (Label::new(), Label::new());
+    let stms = vec![Stm::Label(top), Stm::jump(top), Stm::Label(done)];
+    assert_eq!(optimized(stms.clone(), remove), stms);
+}
+
+#[test]
+fn optimized_programs_stay_canonical() {
+    let src = "let
+        var a := 3 * 4 + 0
+        function f(x: int): int =
+            if 1 < 2 then x * 1 else (print(\"never\"); 0)
+    in
+        while 0 > 1 do a := a - 1;
+        for i := 0 to 2 - 1 do a := f(a + i * 0);
+        if a = a then print(\"done\")
+    end";
+    let exp = parse(src).unwrap();
+    let mut semant = Semant::new();
+    semant.check(&exp);
+    assert!(semant.errors().is_empty(), "{:?}", semant.errors());
+    for fragment in semant.fragments() {
+        let Fragment::Proc { body, .. } = fragment else {
+            continue;
+        };
+        let (blocks, done) = basic_blocks(linearize(body.clone()));
+        let stms = trace_schedule(blocks, done);
+        let optimized = optimized(stms.clone(), Passes::ALL);
+        assert!(optimized.len() < stms.len(), "nothing was optimized");
+        assert_eq!(ir::check(&ir::seq(optimized.clone())), Ok(()));
+        for (i, stm) in optimized.iter().enumerate() {
+            if let Stm::CJump(_, _, _, _, f) = stm {
+                assert_eq!(optimized.get(i + 1), Some(&Stm::Label(*f)));
+            }
+            if let (Stm::Jump(target, _), Some(Stm::Label(next))) = (stm, optimized.get(i + 1)) {
+                assert_ne!(**target, Exp::Name(*next));
+            }
+        }
+    }
+    // The `print` in the dead branch is gone.
+    let calls = |passes| {
+        let asm = emit::program(semant.fragments(), passes);
+        asm.matches("\tcall tig_print\n").count()
+    };
+    assert_eq!((calls(Passes::NONE), calls(Passes::ALL)), (2, 1));
+    // Without fuel nothing is rewritten.
+    let lines = |passes| emit::program(semant.fragments(), passes).lines().count();
+    let empty = Passes {
+        fuel: Some(0),
+        ..Passes::ALL
+    };
+    assert_eq!(lines(empty), lines(Passes::NONE));
+    assert!(lines(Passes::ALL) < lines(Passes::NONE));
+}
+
+#[test]
+fn fuel_limits_the_rewrites() {
+    let c = Exp::Const;
+    let exp = binop(
+        BinOp::Mul,
+        binop(BinOp::Plus, c(1), c(2)),
+        binop(BinOp::Plus, c(3), c(4)),
+    );
+    let fold = |fuel| Passes {
+        fold_constants: true,
+        fuel,
+        ..Passes::NONE
+    };
+    assert_eq!(rewritten(exp.clone(), fold(Some(0))), exp);
+    let partly = binop(BinOp::Mul, c(3), binop(BinOp::Plus, c(3), c(4)));
+    assert_eq!(rewritten(exp.clone(), fold(Some(1))), partly);
+    assert_eq!(
+        rewritten(exp.clone(), fold(Some(2))),
+        binop(BinOp::Mul, c(3), c(7))
+    );
+    assert_eq!(rewritten(exp.clone(), fold(None)), c(21));
+
+    let mut fuel = Fuel::new(fold(Some(5)));
+    let t = Temp::new();
+    optimize(vec![Stm::mov(Exp::Temp(t), exp)], fold(Some(5)), &mut fuel);
+    assert_eq!((fuel.used, fuel.last), (3, Some("fold-constants")));
+}
+
+#[test]
+fn levels_select_passes() {
+    assert_eq!(Passes::for_level("0"), Ok(Passes::NONE));
+    let one = Passes::for_level("1").unwrap();
+    assert!(one.fold_constants && one.simplify);
+    assert!(!one.fold_branches && !one.remove_unreachable);
+    assert_eq!(Passes::for_level("2"), Ok(Passes::ALL));
+    assert_eq!(
+        Passes::for_level("3"),
+        Err("unknown optimization level `3`".to_string())
+    );
+}
@@ -0,0 +1,64 @@
+//! EBNF rendering of the grammar accepted by the parser.
+//!
+//! Binary operator productions are generated from `BINARY_OPS`, the same
+//! table that drives `Parser::parse_binary`. The remaining productions mirror
+//! the `parse_*` methods one to one and must be kept in sync by hand.
+
+use std::fmt::Write;
+
+use super::{Assoc, BINARY_OPS};
+
+const HEAD: &str = r#"program     = exp ;
+exp         = lvalue ":=" exp
+            | "#;
+
+const TAIL: &str = r#"unary-exp   = "-" unary-exp
+            | primary ;
+primary     = "nil" | INT | STRING | "break"
+            | "(" [ exp { ";" exp } ] ")"
+            | "if" exp "then" exp [ "else" exp ]
+            | "while" exp "do" exp
+            | "for" ID ":=" exp "to" exp "do" exp
+            | "let" { dec } "in" [ exp { ";" exp } ] "end"
+            | ID "(" [ exp { "," exp } ] ")"
+            | ID "{" [ ID "=" exp { "," ID "=" exp } ] "}"
+            | ID "[" exp "]" "of" exp
+            | lvalue ;
+lvalue      = ID { "." ID | "[" exp "]" } ;
+dec         = "type" ID "=" ty
+            | "var" ID [ ":" ID ] ":=" exp
+            | "function" ID "(" tyfields ")" [ ":" ID ] "=" exp
+            | "import" STRING ;
+ty          = ID
+            | "{" tyfields "}"
+            | "array" "of" ID ;
+tyfields    = [ ID ":" ID { "," ID ":" ID } ] ;
+"#;
+
+/// The implemented grammar in ISO-style EBNF.
+pub(crate) fn ebnf() -> String {
+    let mut out = String::from(HEAD);
+    out.push_str(BINARY_OPS[0].name);
+    out.push_str(" ;\n");
+    for (level, prec) in BINARY_OPS.iter().enumerate() {
+        let operand = BINARY_OPS
+            .get(level + 1)
+            .map_or("unary-exp", |next| next.name);
+        let ops: Vec<String> = prec
+            .ops
+            .iter()
+            .map(|(kind, _)| format!("\"{}\"", kind.text().expect("operators have fixed text")))
+            .collect();
+        let ops = if ops.len() == 1 {
+            ops[0].clone()
+        } else {
+            format!("( {} )", ops.join(" | "))
+        };
+        let _ = match prec.assoc {
+            Assoc::Left => writeln!(out, "{:<11} = {operand} {{ {ops} {operand} }} ;", prec.name),
+            Assoc::Non => writeln!(out, "{:<11} = {operand} [ {ops} {operand} ] ;", prec.name),
+        };
+    }
+    out.push_str(TAIL);
+    out
+}
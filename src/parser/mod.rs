@@ -0,0 +1,1421 @@
+#![allow(dead_code)]
+
+//! Recursive-descent parser producing the `ast` of a Tiger program.
+//!
+//! Binary operator precedence is driven by `BINARY_OPS`; unary `-` binds
+//! tighter than all of them. `if`, `while`, `for` and assignment bodies extend
+//! as far right as possible.
+//!
+//! Syntax errors are recovered from in panic mode: an expression of a
+//! sequence or a declaration that fails to parse is recorded, and tokens
+//! are skipped up to the next `;`, `in`, `end`, `function`, `var` or `type`
+//! outside any brackets. A failed expression is left in the tree as
+//! `ExpKind::Error`, and a failed declaration is dropped.
+//!
+//! `parse_concrete` parses a program into its concrete syntax tree
+//! instead, with the same grammar: the parser then also puts every token
+//! it consumes into the tree, in nodes opened and closed around what it
+//! parses, and the tokens it skips in recovering into `Error` nodes.
+//!
+//! For completion in an editor, `parse_at_cursor` parses the program up to
+//! the cursor and reports what could be written there.
+
+pub(crate) mod grammar;
+#[cfg(test)]
+mod tests;
+
+use crate::ast::{
+    Dec, Exp, ExpKind, Field, FunDec, Import, Oper, RecordField, Symbol, Ty, TypeDec, Var, VarDec,
+    VarKind,
+};
+use crate::cst::{self, builder::Builder, NodeKind};
+use crate::diagnostics::{Diagnostic, Message};
+use crate::lexer::{LexError, Token, TokenKind, TokenStream};
+use crate::limits::Limits;
+use crate::shared_str::SharedStr;
+use crate::span::{FileId, Span};
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ParseError {
+    pub(crate) msg: Message,
+    pub(crate) pos: Span,
+}
+
+type PResult<T> = Result<T, ParseError>;
+
+/// What the parser expected where it found something else, for E0204.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Expected {
+    /// A token, by its text.
+    Token(&'static str),
+    /// Either of two tokens, such as `,` or `)` in a list.
+    Either(&'static str, &'static str),
+    /// Anything a catalog phrase describes, such as `expected-type`.
+    Phrase(&'static str),
+}
+
+impl Expected {
+    fn message(self) -> Message {
+        match self {
+            Expected::Token(token) => {
+                Message::new("token").arg("token", SharedStr::from_static(token))
+            }
+            Expected::Either(a, b) => Message::new("either-token")
+                .arg("a", SharedStr::from_static(a))
+                .arg("b", SharedStr::from_static(b)),
+            Expected::Phrase(key) => Message::new(key),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Assoc {
+    Left,
+    Non,
+}
+
+/// One precedence level of binary operators.
+pub(crate) struct Precedence {
+    /// Name of the nonterminal for this level in the emitted grammar.
+    pub(crate) name: &'static str,
+    pub(crate) assoc: Assoc,
+    pub(crate) ops: &'static [(TokenKind, Oper)],
+}
+
+impl Precedence {
+    fn op(&self, kind: &TokenKind) -> Option<Oper> {
+        self.ops.iter().find(|(k, _)| k == kind).map(|&(_, op)| op)
+    }
+}
+
+/// Binary operators, from loosest to tightest binding.
+pub(crate) const BINARY_OPS: &[Precedence] = &[
+    Precedence {
+        name: "or-exp",
+        assoc: Assoc::Left,
+        ops: &[(TokenKind::OR, Oper::Or)],
+    },
+    Precedence {
+        name: "and-exp",
+        assoc: Assoc::Left,
+        ops: &[(TokenKind::AND, Oper::And)],
+    },
+    Precedence {
+        name: "cmp-exp",
+        assoc: Assoc::Non,
+        ops: &[
+            (TokenKind::EQ, Oper::Eq),
+            (TokenKind::NEQ, Oper::Neq),
+            (TokenKind::LT, Oper::Lt),
+            (TokenKind::LE, Oper::Le),
+            (TokenKind::GT, Oper::Gt),
+            (TokenKind::GE, Oper::Ge),
+        ],
+    },
+    Precedence {
+        name: "add-exp",
+        assoc: Assoc::Left,
+        ops: &[
+            (TokenKind::PLUS, Oper::Plus),
+            (TokenKind::MINUS, Oper::Minus),
+        ],
+    },
+    Precedence {
+        name: "mul-exp",
+        assoc: Assoc::Left,
+        ops: &[
+            (TokenKind::TIMES, Oper::Times),
+            (TokenKind::DIVIDE, Oper::Divide),
+        ],
+    },
+];
+
+/// Parses a whole program, which is a single expression, failing with its
+/// first error.
+pub(crate) fn parse(src: &str) -> PResult<Exp> {
+    let (parser, ast) = parse_recovering(src, FileId::MAIN, Limits::default());
+    let syntax = parser.errors.iter().cloned();
+    let syntax = syntax
+        .chain(parser.insertions.iter().map(ParseError::from))
+        .min_by_key(|err| err.pos.lo());
+    // A lexical error usually explains any syntax error after it, so it is
+    // reported in preference to those.
+    match (parser.tokens.errors().first(), syntax, ast) {
+        (Some(lex), Some(err), _) if lex.pos().lo() <= err.pos.lo() => Err(lex.into()),
+        (Some(lex), None, _) => Err(lex.into()),
+        (_, Some(err), _) => Err(err),
+        (None, None, ast) => Ok(ast.expect("only syntax errors leave no tree")),
+    }
+}
+
+/// Parses a program, assuming any `in` or `end` that seems to be missing.
+/// An `end` is first assumed where the parser runs into something that
+/// cannot follow a `let` body, such as the end of the input or a closing
+/// bracket. That is often far from where it was left out, since the `let`
+/// takes in everything up to the next `end`, so when the indentation points
+/// elsewhere the program is parsed again with the `end` assumed there.
+fn parse_recovering(src: &str, file: FileId, limits: Limits) -> (Parser<'_>, Option<Exp>) {
+    let mut parser = Parser::new(src, file, limits);
+    let ast = parser.parse_program();
+    let Some((at, opener)) = parser.misplaced_end() else {
+        return (parser, ast);
+    };
+    let mut retry = Parser::new(src, file, limits);
+    retry.tokens.insert(TokenKind::END, at);
+    let retried = retry.parse_program();
+    retry.insertions.push(Insertion {
+        keyword: TokenKind::END,
+        pos: Span::in_file(file, at, at),
+        opener,
+    });
+    retry.insertions.sort_by_key(|i| i.pos.lo());
+    match retry.problems() <= parser.problems() {
+        true => (retry, retried),
+        false => (parser, ast),
+    }
+}
+
+/// A program parsed with every error it contains.
+pub(crate) struct Parsed {
+    /// The tree, with `ExpKind::Error` where a syntax error was skipped;
+    /// `None` when not even the outermost expression could be parsed.
+    pub(crate) ast: Option<Exp>,
+    /// Lexical and syntax errors, and warnings about reserved words, in
+    /// source order.
+    pub(crate) diagnostics: Vec<Diagnostic>,
+    /// Whether the tree is the whole program: there were no syntax errors,
+    /// or only keywords that were left out and assumed.
+    pub(crate) complete: bool,
+}
+
+/// A keyword the parser assumed was left out, so that it could parse the
+/// rest of the program.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Insertion {
+    /// `in` or `end`.
+    pub(crate) keyword: TokenKind,
+    /// Where it was assumed; an empty span.
+    pub(crate) pos: Span,
+    /// The `let` it belongs to.
+    pub(crate) opener: Span,
+}
+
+impl Insertion {
+    pub(crate) fn keyword(&self) -> &'static str {
+        match self.keyword {
+            TokenKind::IN => "in",
+            _ => "end",
+        }
+    }
+
+    pub(crate) fn message(&self) -> Message {
+        Message::new("E0201").arg("keyword", self.keyword())
+    }
+}
+
+impl From<&Insertion> for ParseError {
+    fn from(insertion: &Insertion) -> ParseError {
+        ParseError {
+            msg: insertion.message(),
+            pos: insertion.pos,
+        }
+    }
+}
+
+/// Parses a program like `parse`, but reports every lexical and syntax
+/// error instead of just the first, and warns about identifiers reserved
+/// for later dialects. A syntax error right after a lexical
+/// error is left out, as the lexical error usually explains it.
+pub(crate) fn parse_reporting(src: &str) -> Parsed {
+    parse_reporting_with(src, Limits::default())
+}
+
+/// `parse_reporting` with limits other than the defaults.
+pub(crate) fn parse_reporting_with(src: &str, limits: Limits) -> Parsed {
+    parse_reporting_in(src, FileId::MAIN, limits)
+}
+
+/// `parse_reporting_with` for `src`, the text of `file`, so that the tree
+/// and diagnostics are spanned in that file.
+pub(crate) fn parse_reporting_in(src: &str, file: FileId, limits: Limits) -> Parsed {
+    let (mut parser, ast) = parse_recovering(src, file, limits);
+    Parsed {
+        ast,
+        diagnostics: parser.diagnostics(),
+        complete: parser.errors.is_empty(),
+    }
+}
+
+/// The declarations of a file that `import` names, as parsed by
+/// `parse_library_in`.
+pub(crate) struct Library {
+    /// The declarations that parsed; one that failed to is dropped.
+    pub(crate) decs: Vec<Dec>,
+    /// As in `Parsed`.
+    pub(crate) diagnostics: Vec<Diagnostic>,
+}
+
+/// Parses `src`, the text of `file`, as a library: a sequence of
+/// declarations, as between `let` and `in`, with nothing after them.
+pub(crate) fn parse_library_in(src: &str, file: FileId, limits: Limits) -> Library {
+    let mut parser = Parser::new(src, file, limits);
+    let decs = parser.parse_decs();
+    if parser.kind() != &TokenKind::EOF {
+        let err = parser.unexpected(Expected::Phrase("expected-declaration-or-end"));
+        parser.record(err);
+    }
+    Library {
+        decs,
+        diagnostics: parser.diagnostics(),
+    }
+}
+
+/// The concrete syntax tree of `src`, the text of `file`; see `cst`.
+pub(crate) fn parse_concrete(src: &str, file: FileId, limits: Limits) -> cst::Parse {
+    let mut parser = Parser::new(src, file, limits);
+    parser.tree = Some(Builder::new());
+    parser.parse_program();
+    let checkpoint = parser.checkpoint();
+    while parser.kind() != &TokenKind::EOF {
+        parser.bump();
+    }
+    parser.skipped_since(checkpoint);
+    parser.trivia();
+    let tree = parser.tree.take().expect("the tree is being built");
+    cst::Parse {
+        root: tree.finish_root(file),
+        errors: parser.tokens.errors().to_vec(),
+    }
+}
+
+/// What `parse_at_cursor` found at the cursor.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct AtCursor {
+    /// The node the completion belongs to, as far as it was written: the
+    /// record of a field, the call of an argument or the variable of an
+    /// assignment.
+    pub(crate) partial: Option<Exp>,
+    pub(crate) completion: Completion,
+    /// The identifier being written, up to the cursor, which a completion
+    /// replaces; an empty span at the cursor if there is none.
+    pub(crate) prefix: Span,
+}
+
+/// What can be written at the cursor.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Completion {
+    /// A field of the record in `partial`, after a `.`.
+    Field,
+    /// The argument at this index of the call in `partial`, which has the
+    /// arguments before it.
+    Argument(usize),
+    /// The value assigned to the variable in `partial`, after a `:=`.
+    AssignedValue,
+    /// Any other expression.
+    Expression,
+    /// A type name.
+    Type,
+    /// Nothing to complete: a new name, a keyword, or the inside of a
+    /// comment or string.
+    Nothing,
+}
+
+/// Parses `src` up to byte `offset`, as an editor would while its user
+/// types there. The parse stops at the cursor, so what follows it does not
+/// matter, and an identifier being written at the cursor is left out. A
+/// cursor inside a character is moved back to its start; one past the end
+/// of `src` has nothing to complete.
+pub(crate) fn parse_at_cursor(src: &str, offset: u32) -> AtCursor {
+    if offset as usize > src.len() {
+        return AtCursor {
+            partial: None,
+            completion: Completion::Nothing,
+            prefix: Span::new(offset, offset),
+        };
+    }
+    let offset = src.floor_char_boundary(offset as usize) as u32;
+    let before = &src[..offset as usize];
+    let word = before.len() - before.trim_end_matches(is_ident_char).len();
+    let start = match before[before.len() - word..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+        true => offset - word as u32,
+        false => offset,
+    };
+    let mut parser = Parser::new(&src[..start as usize], FileId::MAIN, Limits::default());
+    parser.cursor = true;
+    parser.parse_program();
+    while parser.kind() != &TokenKind::EOF {
+        parser.bump();
+    }
+    let in_literal = parser.tokens.errors().iter().any(|e| {
+        matches!(
+            e,
+            LexError::UnterminatedString(_) | LexError::UnterminatedComment(_)
+        )
+    });
+    let (completion, partial) = match parser.completion {
+        Some(found) if !in_literal => found,
+        _ => (Completion::Nothing, None),
+    };
+    AtCursor {
+        partial,
+        completion,
+        prefix: Span::new(start, offset),
+    }
+}
+
+pub(crate) fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+impl From<&LexError> for ParseError {
+    fn from(err: &LexError) -> ParseError {
+        ParseError {
+            msg: err.message(),
+            pos: err.pos(),
+        }
+    }
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    /// The file `src` is the text of.
+    file: FileId,
+    tokens: TokenStream<'a>,
+    /// End of the last consumed token, used to close node spans.
+    prev_hi: u32,
+    /// Brackets and `let`s consumed and not yet closed; see `recover`.
+    open: usize,
+    /// Syntax errors recovered from so far.
+    errors: Vec<ParseError>,
+    limits: Limits,
+    /// How deeply the expression being parsed is nested; see `descend`.
+    depth: u32,
+    /// Keywords assumed to be missing, in source order.
+    insertions: Vec<Insertion>,
+    /// The `let` keyword and body expressions of every `let` parsed, for
+    /// `misplaced_end`.
+    lets: Vec<(Span, Vec<Span>)>,
+    /// Whether the input ends at the cursor of `parse_at_cursor`.
+    cursor: bool,
+    /// What can be written at the cursor, once the parser has reached it.
+    completion: Option<(Completion, Option<Exp>)>,
+    /// The `import` keywords of import declarations, which are not warned
+    /// about as reserved words.
+    imports: Vec<Span>,
+    /// The concrete syntax tree, for `parse_concrete`.
+    tree: Option<Builder>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(src: &'a str, file: FileId, limits: Limits) -> Parser<'a> {
+        Parser {
+            src,
+            file,
+            tokens: TokenStream::in_file(src, file, limits),
+            prev_hi: 0,
+            open: 0,
+            errors: Vec::new(),
+            limits,
+            depth: 0,
+            insertions: Vec::new(),
+            lets: Vec::new(),
+            cursor: false,
+            completion: None,
+            imports: Vec::new(),
+            tree: None,
+        }
+    }
+
+    /// Adds the trivia before the next token to the tree.
+    fn trivia(&mut self) {
+        if let Some(tree) = &mut self.tree {
+            tree.trivia(self.tokens.peek(), self.src);
+        }
+    }
+
+    /// Parses with `f` into a new node of `kind` in the tree.
+    fn node<T>(&mut self, kind: NodeKind, f: impl FnOnce(&mut Self) -> T) -> T {
+        self.trivia();
+        if let Some(tree) = &mut self.tree {
+            tree.start(kind);
+        }
+        let result = f(self);
+        if let Some(tree) = &mut self.tree {
+            tree.finish();
+        }
+        result
+    }
+
+    /// See `Builder::checkpoint`; 0 when no tree is being built.
+    fn checkpoint(&mut self) -> usize {
+        self.trivia();
+        self.tree.as_mut().map_or(0, Builder::checkpoint)
+    }
+
+    /// Parses with `f` into a node of `kind` that starts at `checkpoint`.
+    fn wrap<T>(&mut self, checkpoint: usize, kind: NodeKind, f: impl FnOnce(&mut Self) -> T) -> T {
+        if let Some(tree) = &mut self.tree {
+            tree.start_at(checkpoint, kind);
+        }
+        let result = f(self);
+        if let Some(tree) = &mut self.tree {
+            tree.finish();
+        }
+        result
+    }
+
+    /// Puts the tokens consumed since `checkpoint`, if any, in an `Error`
+    /// node.
+    fn skipped_since(&mut self, checkpoint: usize) {
+        if let Some(tree) = &mut self.tree {
+            if tree.checkpoint() > checkpoint {
+                tree.start_at(checkpoint, NodeKind::Error);
+                tree.finish();
+            }
+        }
+    }
+
+    /// Notes that `completion` of `partial` can be written at the cursor, if
+    /// the parser has just reached it. The first note is kept, as callers
+    /// make theirs before the callee they pass the cursor to makes a more
+    /// general one.
+    fn expecting(&mut self, completion: Completion, partial: impl FnOnce() -> Option<Exp>) {
+        if self.cursor && self.kind() == &TokenKind::EOF && self.completion.is_none() {
+            self.completion = Some((completion, partial()));
+        }
+    }
+
+    fn problems(&self) -> usize {
+        self.errors.len() + self.insertions.len()
+    }
+
+    /// Assumes the `keyword` of the `let` at `opener` right after the last
+    /// token.
+    fn assume(&mut self, keyword: TokenKind, opener: Span) {
+        self.insertions.push(Insertion {
+            keyword,
+            pos: Span::in_file(self.file, self.prev_hi, self.prev_hi),
+            opener,
+        });
+    }
+
+    /// Whether the next token can start an expression.
+    fn at_exp(&self) -> bool {
+        matches!(
+            self.kind(),
+            TokenKind::ID
+                | TokenKind::INT
+                | TokenKind::STRING
+                | TokenKind::NIL
+                | TokenKind::BREAK
+                | TokenKind::MINUS
+                | TokenKind::LPAREN
+                | TokenKind::IF
+                | TokenKind::WHILE
+                | TokenKind::FOR
+                | TokenKind::LET
+        )
+    }
+
+    /// Whether the next token can only come after a `let` has ended.
+    fn after_let(&self) -> bool {
+        matches!(
+            self.kind(),
+            TokenKind::EOF
+                | TokenKind::RPAREN
+                | TokenKind::RBRACK
+                | TokenKind::RCURLY
+                | TokenKind::COMMA
+                | TokenKind::IN
+                | TokenKind::VAR
+                | TokenKind::FUNCTION
+                | TokenKind::TYPE
+                | TokenKind::THEN
+                | TokenKind::ELSE
+                | TokenKind::DO
+                | TokenKind::TO
+                | TokenKind::OF
+        )
+    }
+
+    /// Where indentation suggests the `end` of a `let` was left out, when
+    /// one had to be assumed: right after the expression before the first
+    /// one of its body that starts a line indented no deeper than the line
+    /// of the `let`. Returns that offset and the `let`, the last such one
+    /// before the assumed `end`.
+    fn misplaced_end(&self) -> Option<(u32, Span)> {
+        let assumed = self
+            .insertions
+            .iter()
+            .find(|i| i.keyword == TokenKind::END)?;
+        self.lets
+            .iter()
+            .filter(|(opener, _)| opener.lo() < assumed.pos.lo())
+            .filter_map(|(opener, body)| {
+                let indent = self.indentation(opener.lo(), false)?;
+                let outdented = body.windows(2).find(|w| {
+                    self.indentation(w[1].lo(), true)
+                        .is_some_and(|i| i <= indent)
+                })?;
+                Some((outdented[0].hi(), *opener))
+            })
+            .max_by_key(|(_, opener)| opener.lo())
+    }
+
+    /// The indentation of the line `offset` is on. With `first`, `None`
+    /// unless `offset` is the first thing on its line.
+    fn indentation(&self, offset: u32, first: bool) -> Option<usize> {
+        let start = self.src[..offset as usize].rfind('\n').map_or(0, |i| i + 1);
+        let line = &self.src[start..];
+        let indent = line.len() - line.trim_start_matches([' ', '\t']).len();
+        match !first || start + indent == offset as usize {
+            true => Some(indent),
+            false => None,
+        }
+    }
+
+    /// Counts one more level of nesting, failing past the limit. Each call to
+    /// `parse_exp` is a level, and so is each unary minus. `parse_exp` puts
+    /// the depth back when it returns.
+    fn descend(&mut self) -> PResult<()> {
+        let max = self.limits.max_parse_depth;
+        if self.depth >= max {
+            return Err(ParseError {
+                msg: Message::new("E0202").arg("max", max),
+                pos: *self.token().pos(),
+            });
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Counts one more operator or accessor in a chain, such as `a + b + c`
+    /// or `r.a[0]`, failing past the limit. Chains are parsed in a loop, so
+    /// they do not nest, but they make a tree as deep as they are long.
+    fn lengthen(&self, links: &mut u32) -> PResult<()> {
+        let max = self.limits.max_chain_length;
+        if *links >= max {
+            return Err(ParseError {
+                msg: Message::new("E0208").arg("max", max),
+                pos: *self.token().pos(),
+            });
+        }
+        *links += 1;
+        Ok(())
+    }
+
+    /// Fails if a record already has as many fields as allowed, with the
+    /// next field's position.
+    fn check_field_count(&self, count: usize) -> PResult<()> {
+        let max = self.limits.max_record_fields;
+        match count < max {
+            true => Ok(()),
+            false => Err(ParseError {
+                msg: Message::new("E0203").arg("max", max),
+                pos: *self.token().pos(),
+            }),
+        }
+    }
+
+    fn token(&self) -> &Token {
+        self.tokens.peek()
+    }
+
+    /// Every error and warning about the input, lexing the rest of it for
+    /// its errors. A syntax error right after a lexical error is left out.
+    fn diagnostics(&mut self) -> Vec<Diagnostic> {
+        while self.kind() != &TokenKind::EOF {
+            self.bump();
+        }
+        let mut diagnostics = Vec::new();
+        let mut lex_errors = self.tokens.errors().iter().peekable();
+        let mut after_lex = false;
+        for err in &self.errors {
+            while let Some(lex) = lex_errors.next_if(|lex| lex.pos().lo() <= err.pos.lo()) {
+                diagnostics.push(Diagnostic::from(lex));
+                after_lex = true;
+            }
+            if !std::mem::take(&mut after_lex) {
+                diagnostics.push(Diagnostic::from(err));
+            }
+        }
+        diagnostics.extend(lex_errors.map(Diagnostic::from));
+        diagnostics.extend(self.insertions.iter().map(Diagnostic::from));
+        let reserved = self.tokens.reserved_words().iter();
+        let reserved = reserved.filter(|word| !self.imports.contains(&word.pos));
+        diagnostics.extend(reserved.map(Diagnostic::from));
+        diagnostics.sort_by_key(|d| d.pos.lo());
+        diagnostics
+    }
+
+    fn parse_program(&mut self) -> Option<Exp> {
+        let exp = match self.parse_exp() {
+            Ok(exp) => exp,
+            Err(err) => {
+                self.record(err);
+                return None;
+            }
+        };
+        if self.kind() != &TokenKind::EOF {
+            let err = self.unexpected(Expected::Phrase("end-of-input"));
+            self.record(err);
+        }
+        Some(exp)
+    }
+
+    /// Records a syntax error to recover from. An error at the same place as
+    /// the previous one is a consequence of it and is dropped.
+    fn record(&mut self, err: ParseError) {
+        if self
+            .errors
+            .last()
+            .is_none_or(|last| last.pos.lo() != err.pos.lo())
+        {
+            self.errors.push(err);
+        }
+    }
+
+    /// Skips tokens up to one of `sync` outside any brackets or `let`, or up
+    /// to a closing bracket or `end` that has no opening one among them.
+    fn synchronize(&mut self, sync: &[TokenKind]) {
+        let mut depth = 0usize;
+        loop {
+            match self.kind() {
+                TokenKind::EOF => return,
+                kind if depth == 0 && sync.contains(kind) => return,
+                TokenKind::LPAREN | TokenKind::LBRACK | TokenKind::LCURLY | TokenKind::LET => {
+                    depth += 1
+                }
+                TokenKind::RPAREN | TokenKind::RBRACK | TokenKind::RCURLY | TokenKind::END => {
+                    if depth == 0 {
+                        return;
+                    }
+                    depth -= 1;
+                }
+                _ => {}
+            }
+            self.bump();
+        }
+    }
+
+    /// Skips the rest of a construct that failed to parse, which began
+    /// with `open` brackets open: up to one of `sync`, and past the closing
+    /// brackets of those the construct opened, so that the construct around
+    /// it does not take them for its own.
+    fn recover(&mut self, open: usize, sync: &[TokenKind]) {
+        let checkpoint = self.checkpoint();
+        loop {
+            self.synchronize(sync);
+            let closes = matches!(
+                self.kind(),
+                TokenKind::RPAREN | TokenKind::RBRACK | TokenKind::RCURLY | TokenKind::END
+            );
+            if !closes || self.open <= open {
+                break;
+            }
+            self.bump();
+        }
+        self.skipped_since(checkpoint);
+    }
+
+    fn kind(&self) -> &TokenKind {
+        self.token().kind()
+    }
+
+    fn lo(&self) -> u32 {
+        self.token().pos().lo()
+    }
+
+    fn span_from(&self, lo: u32) -> Span {
+        Span::in_file(self.file, lo, self.prev_hi.max(lo))
+    }
+
+    fn bump(&mut self) -> Token {
+        if let Some(tree) = &mut self.tree {
+            tree.token(self.tokens.peek(), self.src);
+        }
+        let token = self.tokens.bump();
+        self.prev_hi = token.pos().hi();
+        match token.kind() {
+            TokenKind::LPAREN | TokenKind::LBRACK | TokenKind::LCURLY | TokenKind::LET => {
+                self.open += 1
+            }
+            TokenKind::RPAREN | TokenKind::RBRACK | TokenKind::RCURLY | TokenKind::END => {
+                self.open = self.open.saturating_sub(1)
+            }
+            _ => {}
+        }
+        token
+    }
+
+    fn eat(&mut self, kind: TokenKind) -> bool {
+        if self.kind() == &kind {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, kind: TokenKind, what: Expected) -> PResult<Token> {
+        if self.kind() == &kind {
+            Ok(self.bump())
+        } else {
+            Err(self.unexpected(what))
+        }
+    }
+
+    // Kept out of line, so that building the message does not grow the
+    // frames of the recursive parsing functions that call it.
+    #[cold]
+    #[inline(never)]
+    fn unexpected(&self, expected: Expected) -> ParseError {
+        let found = match self.kind() {
+            TokenKind::EOF => Message::new("end-of-input"),
+            _ => Message::Text(format!("`{}`", self.text(self.token())).into()),
+        };
+        ParseError {
+            msg: Message::new("E0204")
+                .nested("expected", expected.message())
+                .nested("found", found),
+            pos: *self.token().pos(),
+        }
+    }
+
+    fn text(&self, token: &Token) -> &'a str {
+        &self.src[token.pos().lo() as usize..token.pos().hi() as usize]
+    }
+
+    fn ident(&mut self, what: Expected) -> PResult<(Symbol, Span)> {
+        let token = self.expect(TokenKind::ID, what)?;
+        let name = token.symbol().expect("ID tokens carry a symbol");
+        Ok((name, *token.pos()))
+    }
+
+    fn type_id(&mut self, what: Expected) -> PResult<(Symbol, Span)> {
+        self.expecting(Completion::Type, || None);
+        self.ident(what)
+    }
+
+    fn parse_exp(&mut self) -> PResult<Exp> {
+        let depth = self.depth;
+        let exp = self.descend().and_then(|()| self.parse_assign());
+        self.depth = depth;
+        exp
+    }
+
+    fn parse_assign(&mut self) -> PResult<Exp> {
+        let lo = self.lo();
+        let checkpoint = self.checkpoint();
+        let exp = self.parse_binary(0)?;
+        if self.kind() != &TokenKind::ASSIGN {
+            return Ok(exp);
+        }
+        let var = match exp.kind {
+            ExpKind::Var(var) => var,
+            _ => {
+                return Err(ParseError {
+                    msg: Message::new("E0205"),
+                    pos: exp.pos,
+                })
+            }
+        };
+        let rhs = self.wrap(checkpoint, NodeKind::Assign, |p| {
+            p.bump();
+            p.expecting(Completion::AssignedValue, || Some(var_exp(var.clone())));
+            p.parse_exp()
+        })?;
+        Ok(Exp {
+            kind: ExpKind::Assign {
+                var,
+                exp: Box::new(rhs),
+            },
+            pos: self.span_from(lo),
+        })
+    }
+
+    /// Parses the binary operators of `BINARY_OPS[level..]`, by precedence
+    /// climbing: an operand, then for as long as one of those operators
+    /// follows, the operator and an operand of the ones that bind tighter.
+    /// Operands that are not operations take one frame here, rather than
+    /// one for each level, so nesting costs less stack.
+    fn parse_binary(&mut self, level: usize) -> PResult<Exp> {
+        let checkpoint = self.checkpoint();
+        let mut left = self.parse_unary()?;
+        // The levels of the operators taken here never rise, so each
+        // level's operators are consecutive, and its links one chain.
+        let mut links = [0; BINARY_OPS.len()];
+        while let Some((at, op)) = self.binary_op(level) {
+            self.lengthen(&mut links[at])?;
+            let right = self.wrap(checkpoint, NodeKind::Binary, |p| {
+                p.bump();
+                p.parse_binary(at + 1)
+            })?;
+            left = binop(left, op, right);
+            let prec = &BINARY_OPS[at];
+            if prec.assoc == Assoc::Non && prec.op(self.kind()).is_some() {
+                return Err(self.chained());
+            }
+        }
+        Ok(left)
+    }
+
+    /// The next token's operator and its level, if it is one of
+    /// `BINARY_OPS[level..]`.
+    fn binary_op(&self, level: usize) -> Option<(usize, Oper)> {
+        let kind = self.kind();
+        (level..BINARY_OPS.len()).find_map(|at| Some((at, BINARY_OPS[at].op(kind)?)))
+    }
+
+    /// The error for a non-associative operator right after another of its
+    /// level.
+    #[cold]
+    #[inline(never)]
+    fn chained(&self) -> ParseError {
+        ParseError {
+            msg: Message::new("E0206").arg("op", self.text(self.token())),
+            pos: *self.token().pos(),
+        }
+    }
+
+    /// Unary minus is sugar for `0 - e`, as in the book.
+    fn parse_unary(&mut self) -> PResult<Exp> {
+        if self.kind() != &TokenKind::MINUS {
+            return self.parse_primary();
+        }
+        self.descend()?;
+        let (minus, operand) = self.node(NodeKind::Neg, |p| {
+            let minus = p.bump();
+            p.parse_unary().map(|operand| (minus, operand))
+        })?;
+        self.depth -= 1;
+        let zero = Exp {
+            kind: ExpKind::Int(0),
+            pos: *minus.pos(),
+        };
+        Ok(binop(zero, Oper::Minus, operand))
+    }
+
+    fn parse_primary(&mut self) -> PResult<Exp> {
+        let lo = self.lo();
+        let kind = match self.kind() {
+            TokenKind::NIL => {
+                self.node(NodeKind::Literal, Self::bump);
+                ExpKind::Nil
+            }
+            TokenKind::INT => {
+                // The lexer reports literals that don't fit.
+                let token = self.node(NodeKind::Literal, Self::bump);
+                ExpKind::Int(token.int().unwrap_or_default())
+            }
+            TokenKind::STRING => {
+                let token = self.node(NodeKind::Literal, Self::bump);
+                ExpKind::String(token.string().expect("STRING tokens carry their contents"))
+            }
+            TokenKind::BREAK => {
+                self.node(NodeKind::Break, Self::bump);
+                ExpKind::Break
+            }
+            TokenKind::LPAREN => return self.node(NodeKind::Paren, Self::parse_paren),
+            TokenKind::IF => self.node(NodeKind::If, Self::parse_if)?,
+            TokenKind::WHILE => self.node(NodeKind::While, Self::parse_while)?,
+            TokenKind::FOR => self.node(NodeKind::For, Self::parse_for)?,
+            TokenKind::LET => self.node(NodeKind::Let, Self::parse_let)?,
+            TokenKind::ID => return self.parse_id_exp(),
+            TokenKind::FLOAT => {
+                return Err(ParseError {
+                    msg: Message::new("E0207"),
+                    pos: *self.token().pos(),
+                })
+            }
+            _ => {
+                self.expecting(Completion::Expression, || None);
+                return Err(self.unexpected(Expected::Phrase("expected-expression")));
+            }
+        };
+        Ok(Exp {
+            kind,
+            pos: self.span_from(lo),
+        })
+    }
+
+    /// `()`, `(e)` or `(e1; ...; en)`.
+    fn parse_paren(&mut self) -> PResult<Exp> {
+        let lo = self.lo();
+        self.expect(TokenKind::LPAREN, Expected::Token("("))?;
+        let mut exps = self.parse_exp_seq(TokenKind::RPAREN);
+        self.expect_close(TokenKind::RPAREN, Expected::Either(";", ")"))?;
+        if exps.len() == 1 {
+            return Ok(exps.pop().expect("length checked"));
+        }
+        Ok(self.seq(exps, lo))
+    }
+
+    /// Parses `e1; ...; en` (possibly empty) up to, but not including, `close`.
+    fn parse_exp_seq(&mut self, close: TokenKind) -> Vec<Exp> {
+        let mut exps = Vec::new();
+        if self.kind() == &close {
+            return exps;
+        }
+        exps.push(self.parse_seq_item());
+        while self.eat(TokenKind::SEMICOLON) {
+            exps.push(self.parse_seq_item());
+        }
+        exps
+    }
+
+    /// Expects the `close` token of a sequence. If something else comes
+    /// first, it is skipped after recording the error, provided `close`
+    /// follows it.
+    fn expect_close(&mut self, close: TokenKind, what: Expected) -> PResult<()> {
+        if self.eat(close.clone()) {
+            return Ok(());
+        }
+        let err = self.unexpected(what);
+        let checkpoint = self.checkpoint();
+        self.synchronize(&[]);
+        self.skipped_since(checkpoint);
+        if self.eat(close) {
+            self.record(err);
+            Ok(())
+        } else {
+            Err(err)
+        }
+    }
+
+    /// One expression of a sequence, or `ExpKind::Error` covering the
+    /// tokens skipped after a syntax error.
+    fn parse_seq_item(&mut self) -> Exp {
+        let (lo, open) = (self.lo(), self.open);
+        self.parse_exp().unwrap_or_else(|err| {
+            self.record(err);
+            self.recover(open, &[TokenKind::SEMICOLON, TokenKind::IN]);
+            Exp {
+                kind: ExpKind::Error,
+                pos: self.span_from(lo),
+            }
+        })
+    }
+
+    /// Builds the value of an expression sequence: `Unit` when empty, `Seq` otherwise.
+    fn seq(&self, exps: Vec<Exp>, lo: u32) -> Exp {
+        let kind = if exps.is_empty() {
+            ExpKind::Unit
+        } else {
+            ExpKind::Seq(exps)
+        };
+        Exp {
+            kind,
+            pos: self.span_from(lo),
+        }
+    }
+
+    fn parse_if(&mut self) -> PResult<ExpKind> {
+        self.expect(TokenKind::IF, Expected::Token("if"))?;
+        let test = self.parse_exp()?;
+        self.expect(TokenKind::THEN, Expected::Token("then"))?;
+        let then_ = self.parse_exp()?;
+        let else_ = if self.eat(TokenKind::ELSE) {
+            Some(Box::new(self.parse_exp()?))
+        } else {
+            None
+        };
+        Ok(ExpKind::If {
+            test: Box::new(test),
+            then_: Box::new(then_),
+            else_,
+        })
+    }
+
+    fn parse_while(&mut self) -> PResult<ExpKind> {
+        self.expect(TokenKind::WHILE, Expected::Token("while"))?;
+        let test = self.parse_exp()?;
+        self.expect(TokenKind::DO, Expected::Token("do"))?;
+        let body = self.parse_exp()?;
+        Ok(ExpKind::While {
+            test: Box::new(test),
+            body: Box::new(body),
+        })
+    }
+
+    fn parse_for(&mut self) -> PResult<ExpKind> {
+        self.expect(TokenKind::FOR, Expected::Token("for"))?;
+        let (var, _) = self.ident(Expected::Phrase("expected-loop-variable"))?;
+        self.expect(TokenKind::ASSIGN, Expected::Token(":="))?;
+        let lo = self.parse_exp()?;
+        self.expect(TokenKind::TO, Expected::Token("to"))?;
+        let hi = self.parse_exp()?;
+        self.expect(TokenKind::DO, Expected::Token("do"))?;
+        let body = self.parse_exp()?;
+        Ok(ExpKind::For {
+            var,
+            lo: Box::new(lo),
+            hi: Box::new(hi),
+            body: Box::new(body),
+        })
+    }
+
+    /// A `let`, assuming a missing `in` before an expression or `end`, and
+    /// a missing `end` before what cannot continue the body.
+    fn parse_let(&mut self) -> PResult<ExpKind> {
+        let opener = *self.expect(TokenKind::LET, Expected::Token("let"))?.pos();
+        let decs = self.parse_decs();
+        if !self.eat(TokenKind::IN) {
+            if !self.at_exp() && self.kind() != &TokenKind::END {
+                return Err(self.unexpected(Expected::Phrase("expected-declaration-or-in")));
+            }
+            self.assume(TokenKind::IN, opener);
+        }
+        let lo = self.lo();
+        let mut exps = self.parse_exp_seq(TokenKind::END);
+        self.lets
+            .push((opener, exps.iter().map(|e| e.pos).collect()));
+        let body = if exps.len() == 1 {
+            exps.pop().expect("length checked")
+        } else {
+            self.seq(exps, lo)
+        };
+        if self.kind() != &TokenKind::END && self.after_let() {
+            self.assume(TokenKind::END, opener);
+        } else {
+            self.expect_close(TokenKind::END, Expected::Either(";", "end"))?;
+        }
+        Ok(ExpKind::Let {
+            decs,
+            body: Box::new(body),
+        })
+    }
+
+    /// Expressions starting with an identifier: variables, calls and record
+    /// or array creation.
+    fn parse_id_exp(&mut self) -> PResult<Exp> {
+        let lo = self.lo();
+        let checkpoint = self.checkpoint();
+        let (name, name_pos) = self.ident(Expected::Phrase("expected-identifier"))?;
+        let kind = match self.kind() {
+            TokenKind::LPAREN => {
+                self.wrap(checkpoint, NodeKind::Call, |p| -> PResult<ExpKind> {
+                    p.bump();
+                    let mut args = Vec::new();
+                    if p.kind() != &TokenKind::RPAREN {
+                        args.push(p.parse_arg(name, &args, lo)?);
+                        while p.eat(TokenKind::COMMA) {
+                            args.push(p.parse_arg(name, &args, lo)?);
+                        }
+                    }
+                    p.expect(TokenKind::RPAREN, Expected::Either(",", ")"))?;
+                    Ok(ExpKind::Call { func: name, args })
+                })?
+            }
+            TokenKind::LCURLY => {
+                self.wrap(checkpoint, NodeKind::Record, |p| -> PResult<ExpKind> {
+                    p.bump();
+                    let mut fields = Vec::new();
+                    if p.kind() != &TokenKind::RCURLY {
+                        fields.push(p.parse_record_field()?);
+                        while p.eat(TokenKind::COMMA) {
+                            p.check_field_count(fields.len())?;
+                            fields.push(p.parse_record_field()?);
+                        }
+                    }
+                    p.expect(TokenKind::RCURLY, Expected::Either(",", "}"))?;
+                    Ok(ExpKind::Record { typ: name, fields })
+                })?
+            }
+            TokenKind::LBRACK => {
+                self.bump();
+                let index = self.parse_exp()?;
+                self.expect(TokenKind::RBRACK, Expected::Token("]"))?;
+                if self.kind() == &TokenKind::OF {
+                    let init = self.wrap(checkpoint, NodeKind::Array, |p| {
+                        p.bump();
+                        p.parse_exp()
+                    })?;
+                    ExpKind::Array {
+                        typ: name,
+                        size: Box::new(index),
+                        init: Box::new(init),
+                    }
+                } else {
+                    let base = Var {
+                        kind: VarKind::Simple(name),
+                        pos: name_pos,
+                    };
+                    // Only the missing `of` tells that the name was a variable.
+                    if let Some(tree) = &mut self.tree {
+                        tree.wrap_child(checkpoint, NodeKind::Name);
+                    }
+                    self.wrap(checkpoint, NodeKind::Index, |_| ());
+                    let var = Var {
+                        kind: VarKind::Subscript(Box::new(base), Box::new(index)),
+                        pos: self.span_from(lo),
+                    };
+                    ExpKind::Var(self.parse_var_suffix(var, checkpoint)?)
+                }
+            }
+            _ => {
+                let var = Var {
+                    kind: VarKind::Simple(name),
+                    pos: name_pos,
+                };
+                self.wrap(checkpoint, NodeKind::Name, |_| ());
+                ExpKind::Var(self.parse_var_suffix(var, checkpoint)?)
+            }
+        };
+        Ok(Exp {
+            kind,
+            pos: self.span_from(lo),
+        })
+    }
+
+    /// The next argument of a call to `func` starting at `lo`.
+    fn parse_arg(&mut self, func: Symbol, args: &[Exp], lo: u32) -> PResult<Exp> {
+        let pos = self.span_from(lo);
+        self.expecting(Completion::Argument(args.len()), || {
+            Some(Exp {
+                kind: ExpKind::Call {
+                    func,
+                    args: args.to_vec(),
+                },
+                pos,
+            })
+        });
+        self.parse_exp()
+    }
+
+    /// Parses any trailing `.field` and `[index]` accessors of an lvalue,
+    /// `var`, which starts at `checkpoint` in the tree.
+    fn parse_var_suffix(&mut self, mut var: Var, checkpoint: usize) -> PResult<Var> {
+        let lo = var.pos.lo();
+        let mut links = 0;
+        loop {
+            if matches!(self.kind(), TokenKind::DOT | TokenKind::LBRACK) {
+                self.lengthen(&mut links)?;
+            }
+            let kind = match self.kind() {
+                TokenKind::DOT => {
+                    self.wrap(checkpoint, NodeKind::FieldAccess, |p| -> PResult<VarKind> {
+                        p.bump();
+                        p.expecting(Completion::Field, || Some(var_exp(var.clone())));
+                        let (field, _) = p.ident(Expected::Phrase("expected-field-name"))?;
+                        Ok(VarKind::Field(Box::new(var), field))
+                    })?
+                }
+                TokenKind::LBRACK => {
+                    self.wrap(checkpoint, NodeKind::Index, |p| -> PResult<VarKind> {
+                        p.bump();
+                        let index = p.parse_exp()?;
+                        p.expect(TokenKind::RBRACK, Expected::Token("]"))?;
+                        Ok(VarKind::Subscript(Box::new(var), Box::new(index)))
+                    })?
+                }
+                _ => return Ok(var),
+            };
+            var = Var {
+                kind,
+                pos: self.span_from(lo),
+            };
+        }
+    }
+
+    fn parse_record_field(&mut self) -> PResult<RecordField> {
+        self.node(NodeKind::RecordField, |p| {
+            let lo = p.lo();
+            let (name, _) = p.ident(Expected::Phrase("expected-field-name"))?;
+            p.expect(TokenKind::EQ, Expected::Token("="))?;
+            let exp = p.parse_exp()?;
+            Ok(RecordField {
+                name,
+                exp,
+                pos: p.span_from(lo),
+            })
+        })
+    }
+
+    /// Parses declarations up to `in`, dropping any that fail to parse.
+    fn parse_decs(&mut self) -> Vec<Dec> {
+        let mut decs: Vec<Dec> = Vec::new();
+        loop {
+            let (import, open) = (self.at_import(), self.open);
+            let result = match self.kind() {
+                TokenKind::ID if import => self
+                    .node(NodeKind::Import, Self::parse_import)
+                    .map(|dec| decs.push(Dec::Import(dec))),
+                TokenKind::TYPE => self
+                    .node(NodeKind::TypeDec, Self::parse_type_dec)
+                    .map(|dec| match decs.last_mut() {
+                        Some(Dec::Type(group)) => group.push(dec),
+                        _ => decs.push(Dec::Type(vec![dec])),
+                    }),
+                TokenKind::FUNCTION => {
+                    self.node(NodeKind::FunDec, Self::parse_fun_dec).map(|dec| {
+                        match decs.last_mut() {
+                            Some(Dec::Function(group)) => group.push(dec),
+                            _ => decs.push(Dec::Function(vec![dec])),
+                        }
+                    })
+                }
+                TokenKind::VAR => self
+                    .node(NodeKind::VarDec, Self::parse_var_dec)
+                    .map(|dec| decs.push(Dec::Var(dec))),
+                _ => return decs,
+            };
+            if let Err(err) = result {
+                self.record(err);
+                self.recover(
+                    open,
+                    &[
+                        TokenKind::FUNCTION,
+                        TokenKind::VAR,
+                        TokenKind::TYPE,
+                        TokenKind::IN,
+                    ],
+                );
+            }
+        }
+    }
+
+    /// Whether the next tokens are `import` and a string. `import` is only
+    /// a keyword there, so that it can still name a variable elsewhere.
+    fn at_import(&mut self) -> bool {
+        let token = self.token();
+        token.kind() == &TokenKind::ID
+            && self.text(token) == "import"
+            && self.tokens.peek_nth(1).kind() == &TokenKind::STRING
+    }
+
+    fn parse_import(&mut self) -> PResult<Import> {
+        let keyword = *self.expect(TokenKind::ID, Expected::Token("import"))?.pos();
+        let path = self.expect(TokenKind::STRING, Expected::Phrase("expected-file-name"))?;
+        self.imports.push(keyword);
+        Ok(Import {
+            path: Symbol::intern(
+                &path
+                    .string()
+                    .expect("STRING tokens carry their contents")
+                    .to_str_lossy(),
+            ),
+            pos: keyword.to(*path.pos()),
+        })
+    }
+
+    fn parse_type_dec(&mut self) -> PResult<TypeDec> {
+        let lo = self.lo();
+        self.expect(TokenKind::TYPE, Expected::Token("type"))?;
+        let (name, _) = self.ident(Expected::Phrase("expected-type-name"))?;
+        self.expect(TokenKind::EQ, Expected::Token("="))?;
+        let ty = match self.kind() {
+            TokenKind::LCURLY => self.node(NodeKind::RecordTy, |p| -> PResult<Ty> {
+                p.bump();
+                let fields = p.parse_ty_fields(TokenKind::RCURLY)?;
+                p.expect(TokenKind::RCURLY, Expected::Either(",", "}"))?;
+                Ok(Ty::Record(fields))
+            })?,
+            TokenKind::ARRAY => self.node(NodeKind::ArrayTy, |p| -> PResult<Ty> {
+                p.bump();
+                p.expect(TokenKind::OF, Expected::Token("of"))?;
+                let (elem, pos) = p.type_id(Expected::Phrase("expected-element-type"))?;
+                Ok(Ty::Array(elem, pos))
+            })?,
+            _ => {
+                let (alias, pos) = self.node(NodeKind::NameTy, |p| {
+                    p.type_id(Expected::Phrase("expected-type"))
+                })?;
+                Ty::Name(alias, pos)
+            }
+        };
+        Ok(TypeDec {
+            name,
+            ty,
+            pos: self.span_from(lo),
+        })
+    }
+
+    /// Parses `id: type-id, ...` (possibly empty) up to `close`.
+    fn parse_ty_fields(&mut self, close: TokenKind) -> PResult<Vec<Field>> {
+        let mut fields = Vec::new();
+        if self.kind() == &close {
+            return Ok(fields);
+        }
+        loop {
+            self.check_field_count(fields.len())?;
+            let field = self.node(NodeKind::TyField, |p| -> PResult<Field> {
+                let lo = p.lo();
+                let (name, _) = p.ident(Expected::Phrase("expected-field-name"))?;
+                p.expect(TokenKind::COLON, Expected::Token(":"))?;
+                let (typ, _) = p.type_id(Expected::Phrase("expected-type"))?;
+                Ok(Field {
+                    name,
+                    typ,
+                    pos: p.span_from(lo),
+                })
+            })?;
+            fields.push(field);
+            if !self.eat(TokenKind::COMMA) {
+                return Ok(fields);
+            }
+        }
+    }
+
+    fn parse_fun_dec(&mut self) -> PResult<FunDec> {
+        let lo = self.lo();
+        self.expect(TokenKind::FUNCTION, Expected::Token("function"))?;
+        let (name, _) = self.ident(Expected::Phrase("expected-function-name"))?;
+        self.expect(TokenKind::LPAREN, Expected::Token("("))?;
+        let params = self.parse_ty_fields(TokenKind::RPAREN)?;
+        self.expect(TokenKind::RPAREN, Expected::Either(",", ")"))?;
+        let result = if self.eat(TokenKind::COLON) {
+            Some(self.type_id(Expected::Phrase("expected-result-type"))?)
+        } else {
+            None
+        };
+        self.expect(TokenKind::EQ, Expected::Token("="))?;
+        let body = self.parse_exp()?;
+        Ok(FunDec {
+            name,
+            params,
+            result,
+            body,
+            pos: self.span_from(lo),
+        })
+    }
+
+    fn parse_var_dec(&mut self) -> PResult<VarDec> {
+        let lo = self.lo();
+        self.expect(TokenKind::VAR, Expected::Token("var"))?;
+        let (name, _) = self.ident(Expected::Phrase("expected-variable-name"))?;
+        let typ = if self.eat(TokenKind::COLON) {
+            Some(self.type_id(Expected::Phrase("expected-type"))?)
+        } else {
+            None
+        };
+        self.expect(TokenKind::ASSIGN, Expected::Token(":="))?;
+        let init = self.parse_exp()?;
+        Ok(VarDec {
+            name,
+            typ,
+            init,
+            pos: self.span_from(lo),
+        })
+    }
+}
+
+fn var_exp(var: Var) -> Exp {
+    let pos = var.pos;
+    Exp {
+        kind: ExpKind::Var(var),
+        pos,
+    }
+}
+
+fn binop(left: Exp, op: Oper, right: Exp) -> Exp {
+    let pos = left.pos.to(right.pos);
+    Exp {
+        kind: ExpKind::Op {
+            left: Box::new(left),
+            op,
+            right: Box::new(right),
+        },
+        pos,
+    }
+}
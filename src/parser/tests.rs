@@ -0,0 +1,585 @@
+use crate::ast::ExpKind;
+use crate::limits::Limits;
+use crate::opt::Passes;
+use crate::parser::{
+    parse, parse_at_cursor, parse_library_in, parse_reporting, parse_reporting_in,
+    parse_reporting_with, Completion,
+};
+use crate::pretty::{assert_same_tree, sexp};
+use crate::span::{FileId, Span};
+
+fn parses_to(src: &str, expected: &str) {
+    let exp = parse(src).unwrap_or_else(|e| panic!("failed to parse {src:?}: {e:?}"));
+    assert_eq!(sexp(&exp), expected, "while parsing {src:?}");
+}
+
+#[test]
+fn unit_and_parenthesized_sequences() {
+    parses_to("()", "()");
+    parses_to("(())", "()");
+    parses_to("(1)", "1");
+    parses_to("((1))", "1");
+    parses_to("(1; 2)", "(seq 1 2)");
+    parses_to("(1; (); x)", "(seq 1 () x)");
+    parses_to("(a := 1; a)", "(seq (:= a 1) a)");
+}
+
+#[test]
+fn let_bodies() {
+    parses_to("let in end", "(let () ())");
+    parses_to("let var a := 1 in end", "(let ((var a 1)) ())");
+    parses_to("let in a end", "(let () a)");
+    parses_to("let in a; b end", "(let () (seq a b))");
+}
+
+#[test]
+fn sequence_spans_cover_parentheses() {
+    let exp = parse("  (1; 2)").unwrap();
+    assert_eq!(exp.pos, Span::new(2, 8));
+    let exp = parse("(())").unwrap();
+    assert_eq!(exp.pos, Span::new(1, 3));
+}
+
+#[test]
+fn if_without_else() {
+    let exp = parse("if a then b").unwrap();
+    assert!(matches!(exp.kind, ExpKind::If { else_: None, .. }));
+    parses_to("if a then b", "(if a b)");
+    parses_to("if a then if b then c else d", "(if a (if b c d))");
+    parses_to("if a then (if b then c) else d", "(if a (if b c) d)");
+}
+
+#[test]
+fn control_bodies_extend_right() {
+    parses_to("if a then b else c + 1", "(if a b (+ c 1))");
+    parses_to("while a do b := b + 1", "(while a (:= b (+ b 1)))");
+    parses_to(
+        "for i := 0 to n - 1 do f(i)",
+        "(for i 0 (- n 1) (call f i))",
+    );
+    parses_to("1 + if a then b else c", "(+ 1 (if a b c))");
+}
+
+#[test]
+fn operator_precedence() {
+    parses_to("1 + 2 * 3", "(+ 1 (* 2 3))");
+    parses_to("1 * 2 + 3", "(+ (* 1 2) 3)");
+    parses_to("1 - 2 - 3", "(- (- 1 2) 3)");
+    parses_to("8 / 4 / 2", "(/ (/ 8 4) 2)");
+    parses_to("-a * b", "(* (- 0 a) b)");
+    parses_to("- - a", "(- 0 (- 0 a))");
+    parses_to("a + 1 < b * 2", "(< (+ a 1) (* b 2))");
+    parses_to("a = b & c <> d", "(& (= a b) (<> c d))");
+    parses_to("a & b | c & d", "(| (& a b) (& c d))");
+    parses_to("a | b | c", "(| (| a b) c)");
+    parses_to("(1 + 2) * 3", "(* (+ 1 2) 3)");
+}
+
+#[test]
+fn comparisons_do_not_associate() {
+    let err = parse("a < b < c").unwrap_err();
+    assert_eq!(err.pos, Span::new(6, 7));
+}
+
+#[test]
+fn assignment() {
+    parses_to("a := b := 1", "(:= a (:= b 1))");
+    parses_to("a.b[i + 1] := 0", "(:= ([] (. a b) (+ i 1)) 0)");
+    let err = parse("1 + x := 3").unwrap_err();
+    assert_eq!(err.pos, Span::new(0, 5));
+}
+
+#[test]
+fn ids_calls_records_and_arrays() {
+    parses_to("f()", "(call f)");
+    parses_to("f(1, g(x))", "(call f 1 (call g x))");
+    parses_to("p{}", "(record p)");
+    parses_to("p{x = 1, y = nil}", "(record p (x 1) (y nil))");
+    parses_to("intArray [n] of 0", "(array intArray n 0)");
+    parses_to("a[n]", "([] a n)");
+    parses_to("a[i][j].k", "(. ([] ([] a i) j) k)");
+}
+
+#[test]
+fn declarations_group_adjacent_functions_and_types() {
+    let src = "let
+        type a = int
+        type l = {hd: int, tl: l}
+        var x: a := 1
+        type arr = array of int
+        function f(n: int): int = g(n)
+        function g(n: int) = ()
+    in x end";
+    parses_to(
+        src,
+        "(let ((type a int) (type l {hd:int tl:l}) (var x a 1) (type arr (array-of int)) \
+         (function f (n:int) int (call g n)) (function g (n:int) ())) x)",
+    );
+    let ExpKind::Let { decs, .. } = parse(src).unwrap().kind else {
+        panic!("expected let");
+    };
+    assert_eq!(decs.len(), 4);
+}
+
+#[test]
+fn comments_are_skipped() {
+    parses_to("/* a */ 1 /* b /* c */ */ + 2", "(+ 1 2)");
+}
+
+#[test]
+fn reports_unexpected_tokens() {
+    let err = parse("let var := 1 in end").unwrap_err();
+    assert_eq!(err.msg, "expected a variable name, found `:=`");
+    let err = parse("(1; 2").unwrap_err();
+    assert_eq!(err.msg, "expected `;` or `)`, found end of input");
+    let err = parse("1 2").unwrap_err();
+    assert_eq!(err.msg, "expected end of input, found `2`");
+}
+
+#[test]
+fn grammar_terminals_are_tokens() {
+    use crate::lexer::{StringReader, TokenKind};
+
+    let grammar = crate::parser::grammar::ebnf();
+    let terminals: Vec<&str> = grammar.split('"').skip(1).step_by(2).collect();
+    assert!(terminals.contains(&"<>") && terminals.contains(&"function"));
+    assert!(grammar.contains("| \"import\" STRING ;"));
+    for terminal in terminals {
+        let mut sr = StringReader::new(terminal);
+        let token = sr.next_token();
+        // `import` is a keyword only before a string; it lexes as a name.
+        match terminal {
+            "import" => assert_eq!(token.kind(), &TokenKind::ID),
+            _ => assert_eq!(token.kind().text(), Some(terminal)),
+        }
+        assert_eq!(sr.next_token().kind(), &TokenKind::EOF);
+    }
+}
+
+#[test]
+fn grammar_nonterminals_are_defined() {
+    let grammar = crate::parser::grammar::ebnf();
+    let defined: Vec<&str> = grammar
+        .lines()
+        .filter_map(|line| line.split_once(" = ").map(|(name, _)| name.trim()))
+        .collect();
+    let unquoted: String = grammar.split('"').step_by(2).collect::<Vec<_>>().join(" ");
+    for word in unquoted.split(|c: char| !(c.is_ascii_alphabetic() || c == '-')) {
+        if word.chars().any(|c| c.is_ascii_lowercase()) {
+            assert!(defined.contains(&word), "`{word}` is not defined");
+        }
+    }
+}
+
+#[test]
+fn reports_lexical_errors_first() {
+    let err = parse("let var s := \"abc in s end").unwrap_err();
+    assert_eq!(err.msg, "unterminated string literal");
+    let err = parse("1 + $").unwrap_err();
+    assert_eq!(err.msg, "unexpected character `$`");
+    assert_eq!(err.pos, Span::new(4, 5));
+    let err = parse("(1 2) /* oops").unwrap_err();
+    assert_eq!(err.msg, "expected `;` or `)`, found `2`");
+}
+
+fn errors(src: &str) -> Vec<String> {
+    parse_reporting(src)
+        .diagnostics
+        .into_iter()
+        .map(|d| d.msg.to_string())
+        .collect()
+}
+
+#[test]
+fn recovers_at_semicolons_and_declarations() {
+    let src = "let
+        var x :=
+        var y: int := 2
+        type t = array int
+        function f() = (1; ; 2 +)
+    in
+        x := (1 2);
+        y + 3
+    end";
+    assert_eq!(
+        errors(src),
+        [
+            "expected an expression, found `var`",
+            "expected `of`, found `int`",
+            "expected an expression, found `;`",
+            "expected an expression, found `)`",
+            "expected `;` or `)`, found `2`",
+        ]
+    );
+    let parsed = parse_reporting(src);
+    assert!(!parsed.complete);
+    assert_eq!(
+        sexp(&parsed.ast.unwrap()),
+        "(let ((var y int 2) (function f () (seq 1 <error> <error>))) \
+         (seq (:= x 1) (+ y 3)))"
+    );
+}
+
+#[test]
+fn skipping_stops_outside_brackets() {
+    // The `;` and `end` inside the skipped parentheses and `let` do not end
+    // the recovery.
+    assert_eq!(
+        errors("(1 + * (a; b) + let in 2 end; 3)"),
+        ["expected an expression, found `*`"]
+    );
+    let parsed = parse_reporting("(1 + * (a; b) + let in 2 end; 3)");
+    assert_eq!(sexp(&parsed.ast.unwrap()), "(seq <error> 3)");
+}
+
+#[test]
+fn syntax_errors_after_lexical_errors_are_dropped() {
+    assert_eq!(errors("1 + $"), ["unexpected character `$`"]);
+    assert_eq!(
+        errors("(1 +); \"\\q\""),
+        [
+            "expected an expression, found `)`",
+            "expected end of input, found `;`",
+            "invalid escape sequence",
+        ]
+    );
+    assert!(parse_reporting("").ast.is_none());
+}
+
+#[test]
+fn built_trees_match_parsed_ones() {
+    use crate::ast::build::*;
+    use crate::ast::Oper;
+    let built = let_(
+        [
+            types([type_dec("list", record_ty([("hd", "int"), ("tl", "list")]))]),
+            var_dec("x", Some("int"), int(1)),
+            functions([fun_dec(
+                "f",
+                [("a", "int")],
+                Some("int"),
+                op(var("a"), Oper::Plus, var("x")),
+            )]),
+        ],
+        [
+            assign(simple("x"), call("f", [int(2)])),
+            lvalue(field(simple("l"), "hd")),
+        ],
+    );
+    let src = "let type list = {hd: int, tl: list}
+                   var x: int := 1
+                   function f(a: int): int = a + x
+               in x := f(2); l.hd end";
+    assert_same_tree(&built, &parse(src).unwrap());
+}
+
+#[test]
+fn nesting_and_fields_are_limited() {
+    let limits = Limits {
+        max_parse_depth: 3,
+        max_chain_length: 4,
+        max_record_fields: 2,
+        ..Limits::default()
+    };
+    let errors = |src: &str| -> Vec<_> {
+        let parsed = parse_reporting_with(src, limits);
+        parsed
+            .diagnostics
+            .into_iter()
+            .map(|d| (d.msg.to_string(), d.pos))
+            .collect()
+    };
+    assert_eq!(errors("((1))"), []);
+    assert_eq!(errors("1 + 2 + 3"), []);
+    let too_deep = "expression nested too deeply; the limit is 3 levels".to_string();
+    assert_eq!(errors("(((1)))"), [(too_deep.clone(), Span::new(3, 4))]);
+    // Chains are not nesting, but each unary minus is a level.
+    assert_eq!(errors("1 + 2 + 3 + 4 * 5 * 6"), []);
+    assert_eq!(errors("a.b.c[0].d"), []);
+    assert_eq!(errors("- - - 1"), [(too_deep, Span::new(4, 5))]);
+    let too_long = "chain of operators or accessors too long; the limit is 4".to_string();
+    assert_eq!(
+        errors("1 + 2 + 3 + 4 + 5 + 6"),
+        [(too_long.clone(), Span::new(18, 19))]
+    );
+    assert_eq!(errors("a.b.c[0].d.e"), [(too_long, Span::new(10, 11))]);
+    assert_eq!(errors("r{a = 1, b = 2}"), []);
+    let too_many = "too many fields; the limit is 2".to_string();
+    assert_eq!(
+        errors("r{a = 1, b = 2, c = 3}"),
+        [(too_many.clone(), Span::new(16, 17))]
+    );
+    assert_eq!(
+        errors("let function f(a: int, b: int, c: int) = a in end"),
+        [(too_many, Span::new(31, 32))]
+    );
+}
+
+#[test]
+fn deep_nesting_is_an_error_not_an_overflow() {
+    let deep = |open: &str, close: &str| {
+        let n = 100_000;
+        format!("{}x{}", open.repeat(n), close.repeat(n))
+    };
+    let too_deep = "expression nested too deeply; the limit is 128 levels";
+    let too_long = "chain of operators or accessors too long; the limit is 10000";
+    for (src, msg) in [
+        (deep("(", ")"), too_deep),
+        (deep("-", ""), too_deep),
+        (deep("", "+x"), too_long),
+        (deep("", ".a"), too_long),
+    ] {
+        let parsed = parse_reporting(&src);
+        assert!(!parsed.complete);
+        assert_eq!(parsed.diagnostics[0].msg, msg);
+    }
+}
+
+#[test]
+fn programs_at_the_depth_limit_compile() {
+    use crate::codegen::emit;
+    use crate::semant::Semant;
+    let depth = Limits::default().max_parse_depth as usize;
+    // The `let` and the body take the outermost levels.
+    let nested = format!("{}1{}", "(".repeat(depth - 2), ")".repeat(depth - 2));
+    let chained = format!("1{}", " + 1".repeat(depth - 2));
+    for body in [nested, chained] {
+        let src = format!("let function f(): int = {body} in f() end");
+        let ast = parse(&src).unwrap_or_else(|e| panic!("{}", e.msg));
+        let mut semant = Semant::new();
+        semant.check(&ast);
+        assert!(semant.errors().is_empty());
+        assert!(!emit::program(semant.fragments(), Passes::NONE).is_empty());
+    }
+}
+
+#[test]
+fn long_flat_sequences_compile() {
+    use crate::codegen::emit;
+    use crate::semant::Semant;
+    // A sequence is not nested, so no limit applies to its length; every
+    // phase must handle one far longer than the depth limit.
+    let src = format!("({}print(\"a\"))", "print(\"a\"); ".repeat(20_000));
+    let ast = parse(&src).unwrap_or_else(|e| panic!("{}", e.msg));
+    let mut semant = Semant::new();
+    semant.check(&ast);
+    assert!(semant.errors().is_empty());
+    for fragment in semant.fragments() {
+        if let crate::translate::Fragment::Proc { body, .. } = fragment {
+            assert_eq!(crate::ir::check(body), Ok(()));
+            assert!(!body.to_string().is_empty());
+        }
+    }
+    assert!(!emit::program(semant.fragments(), Passes::NONE).is_empty());
+}
+
+#[test]
+fn long_chains_compile() {
+    use crate::codegen::emit;
+    use crate::limits::with_stack;
+    use crate::semant::Semant;
+    // Longer than the nesting limit, which chains do not count against.
+    let sum = format!("x{}", " + x".repeat(1000));
+    let fields = format!("r{}", ".next".repeat(300));
+    for body in [sum, fields] {
+        let src = format!(
+            "let type r = {{next: r}} var r := r {{next = nil}} var x := 1 in {body}; () end"
+        );
+        with_stack(|| {
+            let ast = parse(&src).unwrap_or_else(|e| panic!("{}", e.msg));
+            let mut semant = Semant::new();
+            semant.check(&ast);
+            assert!(semant.errors().is_empty());
+            assert!(!emit::program(semant.fragments(), Passes::NONE).is_empty());
+        });
+    }
+}
+
+#[test]
+fn assumes_missing_in_and_end() {
+    let recovered = |src: &str| {
+        let parsed = parse_reporting(src);
+        assert!(parsed.complete, "{src:?}");
+        let errors: Vec<_> = parsed
+            .diagnostics
+            .iter()
+            .map(|d| (d.msg.to_string(), d.pos))
+            .collect();
+        (sexp(&parsed.ast.unwrap()), format!("{errors:?}"))
+    };
+    let assumed =
+        |keyword: &str, at: u32| format!("[(\"missing `{keyword}`\", {:?})]", Span::new(at, at));
+    assert_eq!(
+        recovered("let var x := 1 x + 1 end"),
+        ("(let ((var x 1)) (+ x 1))".to_string(), assumed("in", 14))
+    );
+    assert_eq!(
+        recovered("let var x := 1 end"),
+        ("(let ((var x 1)) ())".to_string(), assumed("in", 14))
+    );
+    assert_eq!(
+        recovered("let in x"),
+        ("(let () x)".to_string(), assumed("end", 8))
+    );
+    assert_eq!(
+        recovered("(let in x)"),
+        ("(let () x)".to_string(), assumed("end", 9))
+    );
+    assert_eq!(
+        recovered("let var a := let in 1 in a end"),
+        (
+            "(let ((var a (let () 1))) a)".to_string(),
+            assumed("end", 21)
+        )
+    );
+    let err = parse("let in x").unwrap_err();
+    assert_eq!(
+        (err.msg.to_string(), err.pos),
+        ("missing `end`".to_string(), Span::new(8, 8))
+    );
+}
+
+#[test]
+fn indentation_places_a_missing_end() {
+    // Without the indentation, the inner `let` would take the outer one's
+    // `end` and `a` with it.
+    let src = "let
+  var a := 1
+in
+  let
+    var b := 2
+  in
+    b;
+  a
+end";
+    let parsed = parse_reporting(src);
+    assert!(parsed.complete);
+    let diags: Vec<_> = parsed
+        .diagnostics
+        .iter()
+        .map(|d| (d.msg.to_string(), d.pos))
+        .collect();
+    let after_b = src.find("b;").unwrap() as u32 + 1;
+    let missing = "missing `end`".to_string();
+    assert_eq!(diags, [(missing, Span::new(after_b, after_b))]);
+    let inner = src.find("  let").unwrap() as u32 + 2;
+    assert_eq!(
+        parsed.diagnostics[0].labels[0].pos,
+        Span::new(inner, inner + 3),
+        "the label points at the inner `let`"
+    );
+    assert_eq!(
+        sexp(&parsed.ast.unwrap()),
+        "(let ((var a 1)) (seq (let ((var b 2)) b) a))"
+    );
+}
+
+/// `parse_at_cursor` with the cursor at the `|` in `src`, as the
+/// completion, the partial node if any, and the prefix in backquotes.
+fn at_cursor(src: &str) -> String {
+    let offset = src.find('|').expect("a cursor") as u32;
+    let src = src.replacen('|', "", 1);
+    let found = parse_at_cursor(&src, offset);
+    assert_eq!(found.prefix.hi(), offset);
+    let prefix = &src[found.prefix.lo() as usize..offset as usize];
+    match found.partial {
+        Some(partial) => format!("{:?} {} `{prefix}`", found.completion, sexp(&partial)),
+        None => format!("{:?} `{prefix}`", found.completion),
+    }
+}
+
+#[test]
+fn completes_incomplete_expressions_at_the_cursor() {
+    let cases = [
+        ("let var r := p {a = 1} in r.| end", "Field r ``"),
+        // What comes after the cursor is ignored.
+        ("r.b[0].na| + 1)) end", "Field ([] (. r b) 0) `na`"),
+        ("f(|)", "Argument(0) (call f) ``"),
+        ("f(1, g(x|), 2)", "Argument(0) (call g) `x`"),
+        ("f(1, |", "Argument(1) (call f 1) ``"),
+        ("(x.a := |; y)", "AssignedValue (. x a) ``"),
+        // Only an operand of `+` goes here, not the whole value.
+        ("x := 1 + |", "Expression ``"),
+        ("if a < b then pr|", "Expression `pr`"),
+        ("let var x: in| := 1 in end", "Type `in`"),
+        ("let function f(a: int): str| = a in end", "Type `str`"),
+    ];
+    for (src, expected) in cases {
+        assert_eq!(at_cursor(src), expected, "in {src:?}");
+    }
+}
+
+#[test]
+fn nothing_is_completed_in_names_comments_and_strings() {
+    let cases = [
+        ("let var na| := 1 in end", "Nothing `na`"),
+        ("f(x) |", "Nothing ``"),
+        ("f(x, /* a.| */ y)", "Nothing ``"),
+        ("f(\"a.|\")", "Nothing ``"),
+        ("x := 12|", "Nothing ``"),
+    ];
+    for (src, expected) in cases {
+        assert_eq!(at_cursor(src), expected, "in {src:?}");
+    }
+}
+
+#[test]
+fn cursors_past_the_end_or_inside_a_character() {
+    let src = "let var é := 1 in f(";
+    let past = parse_at_cursor(src, src.len() as u32 + 5);
+    assert_eq!(past.completion, Completion::Nothing);
+    assert_eq!(past.partial, None);
+    // Inside the two bytes of `é`, the cursor is moved back before it.
+    let inside = src.find('é').unwrap() as u32 + 1;
+    assert_eq!(
+        parse_at_cursor(src, inside).prefix,
+        Span::new(inside - 1, inside - 1)
+    );
+    assert_eq!(
+        parse_at_cursor(src, src.len() as u32).completion,
+        Completion::Argument(0)
+    );
+}
+
+#[test]
+fn spans_are_in_the_file_parsed() {
+    let file = FileId::new(2);
+    let parsed = parse_reporting_in("let var x := 1 in x + $ ", file, Limits::default());
+    let ast = parsed.ast.expect("recovered");
+    assert_eq!(ast.pos, Span::in_file(file, 0, 23));
+    assert!(!parsed.diagnostics.is_empty());
+    assert!(parsed.diagnostics.iter().all(|d| d.pos.file == file));
+}
+
+#[test]
+fn imports_are_declarations() {
+    let src = "let import \"lib.tig\" var x := import in x end";
+    parses_to(src, "(let ((import \"lib.tig\") (var x import)) x)");
+    // `import` is only a keyword before a string, and is not warned about
+    // there; as a name it still is.
+    assert_eq!(
+        errors(src),
+        ["`import` is reserved as a keyword for future versions of Tiger"]
+    );
+
+    let file = FileId::new(1);
+    let library = parse_library_in(
+        "import \"a.tig\"\nvar y := 1 function f() = ()",
+        file,
+        Limits::default(),
+    );
+    assert_eq!(library.decs.len(), 3);
+    assert!(library.diagnostics.is_empty());
+    let library = parse_library_in("var y := 1 in y", file, Limits::default());
+    assert_eq!(library.decs.len(), 1);
+    let messages: Vec<String> = library
+        .diagnostics
+        .iter()
+        .map(|d| d.msg.to_string())
+        .collect();
+    assert_eq!(
+        messages,
+        ["expected a declaration or end of input, found `in`"]
+    );
+    assert_eq!(library.diagnostics[0].pos, Span::in_file(file, 11, 13));
+}
@@ -0,0 +1,81 @@
+//! Structural comparison of syntax trees.
+//!
+//! A failing golden test on a whole tree is hard to read as a text diff.
+//! `diff` instead walks both trees as `Node`s and stops at the first node
+//! whose label or number of children differs, reporting the path to it and
+//! both subtrees. Spans are shown but not compared, so trees from `ast::build`
+//! can be checked against parsed ones.
+
+use std::fmt;
+
+use super::{node, Node};
+use crate::ast::Exp;
+
+/// Where two trees first differ.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct TreeDiff {
+    /// Labels of the nodes from the root down to the parent of the
+    /// differing nodes.
+    pub(crate) path: Vec<String>,
+    pub(crate) expected: Node,
+    pub(crate) actual: Node,
+}
+
+/// The first difference between `expected` and `actual`, or `None` if they
+/// have the same shape and labels.
+pub(crate) fn diff(expected: &Exp, actual: &Exp) -> Option<TreeDiff> {
+    diff_nodes(&node(expected), &node(actual))
+}
+
+/// Panics with the first difference if `actual` is not `expected`.
+#[track_caller]
+pub(crate) fn assert_same_tree(expected: &Exp, actual: &Exp) {
+    if let Some(diff) = diff(expected, actual) {
+        panic!("{diff}");
+    }
+}
+
+/// `diff` for any trees of `Node`s, such as a subtree of a program.
+pub(crate) fn diff_nodes(expected: &Node, actual: &Node) -> Option<TreeDiff> {
+    let mut path = Vec::new();
+    let (expected, actual) = first_difference(expected, actual, &mut path)?;
+    Some(TreeDiff {
+        path,
+        expected: expected.clone(),
+        actual: actual.clone(),
+    })
+}
+
+fn first_difference<'a>(
+    expected: &'a Node,
+    actual: &'a Node,
+    path: &mut Vec<String>,
+) -> Option<(&'a Node, &'a Node)> {
+    if expected.label != actual.label || expected.children.len() != actual.children.len() {
+        return Some((expected, actual));
+    }
+    path.push(expected.label.clone());
+    for (e, a) in expected.children.iter().zip(&actual.children) {
+        if let Some(found) = first_difference(e, a, path) {
+            return Some(found);
+        }
+    }
+    path.pop();
+    None
+}
+
+impl fmt::Display for TreeDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.path.is_empty() {
+            true => writeln!(f, "the trees differ at the root")?,
+            false => writeln!(f, "the trees differ under {}", self.path.join(" > "))?,
+        }
+        for (which, node) in [("expected", &self.expected), ("actual", &self.actual)] {
+            writeln!(f, "{which}:")?;
+            let mut text = String::new();
+            node.write(&mut text, 1);
+            f.write_str(&text)?;
+        }
+        Ok(())
+    }
+}
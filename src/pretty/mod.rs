@@ -0,0 +1,323 @@
+#![allow(dead_code)]
+
+//! Readable renderings of tokens and syntax trees for `--emit` and for
+//! golden tests, where the `Debug` output of the raw structs is too noisy.
+//!
+//! `sexp` prints an expression on a single line without positions; `tree`
+//! prints one node per line, indented by depth, with the span of every node.
+//! `diff` finds where two trees first differ, for golden test failures.
+//! `read::sexp` parses what `sexp` prints back into a tree.
+
+#[cfg(test)]
+mod diff;
+pub(crate) mod read;
+#[cfg(test)]
+mod tests;
+
+#[cfg(test)]
+pub(crate) use diff::{assert_same_tree, diff};
+
+use std::fmt::Write;
+
+use crate::ast::{Dec, Exp, ExpKind, Field, Ty, Var, VarKind};
+use crate::lexer::{Token, TokenValue};
+use crate::span::Span;
+
+/// One token per line: kind, value if any, and span.
+pub(crate) fn tokens(tokens: &[Token]) -> String {
+    let mut out = String::new();
+    for token in tokens {
+        let _ = write!(out, "{:?}", token.kind());
+        let _ = match token.value() {
+            TokenValue::None => Ok(()),
+            TokenValue::Ident(name) => write!(out, " {name}"),
+            TokenValue::Int(n) => write!(out, " {n}"),
+            TokenValue::Float(x) => write!(out, " {x:?}"),
+            TokenValue::Str(s) => write!(out, " {s:?}"),
+        };
+        let _ = writeln!(out, " {}", span(*token.pos()));
+    }
+    out
+}
+
+/// The expression as a compact s-expression, e.g. `(+ 1 (* 2 3))`.
+pub(crate) fn sexp(exp: &Exp) -> String {
+    match &exp.kind {
+        ExpKind::Var(var) => sexp_var(var),
+        ExpKind::Nil => "nil".to_string(),
+        ExpKind::Unit => "()".to_string(),
+        ExpKind::Error => "<error>".to_string(),
+        ExpKind::Int(n) => n.to_string(),
+        ExpKind::String(s) => format!("{s:?}"),
+        ExpKind::Call { func, args } => list("call", func.as_str(), args.iter().map(sexp)),
+        ExpKind::Op { left, op, right } => {
+            format!("({} {} {})", op.text(), sexp(left), sexp(right))
+        }
+        ExpKind::Record { typ, fields } => list(
+            "record",
+            typ.as_str(),
+            fields
+                .iter()
+                .map(|f| format!("({} {})", f.name, sexp(&f.exp))),
+        ),
+        ExpKind::Seq(exps) => format!("(seq {})", join(exps.iter().map(sexp))),
+        ExpKind::Assign { var, exp } => format!("(:= {} {})", sexp_var(var), sexp(exp)),
+        ExpKind::If {
+            test,
+            then_,
+            else_: Some(else_),
+        } => format!("(if {} {} {})", sexp(test), sexp(then_), sexp(else_)),
+        ExpKind::If { test, then_, .. } => format!("(if {} {})", sexp(test), sexp(then_)),
+        ExpKind::While { test, body } => format!("(while {} {})", sexp(test), sexp(body)),
+        ExpKind::For { var, lo, hi, body } => {
+            format!("(for {var} {} {} {})", sexp(lo), sexp(hi), sexp(body))
+        }
+        ExpKind::Break => "break".to_string(),
+        ExpKind::Let { decs, body } => {
+            format!("(let ({}) {})", join(decs.iter().map(sexp_dec)), sexp(body))
+        }
+        ExpKind::Array { typ, size, init } => {
+            format!("(array {typ} {} {})", sexp(size), sexp(init))
+        }
+    }
+}
+
+fn sexp_var(var: &Var) -> String {
+    match &var.kind {
+        VarKind::Simple(name) => name.to_string(),
+        VarKind::Field(var, field) => format!("(. {} {field})", sexp_var(var)),
+        VarKind::Subscript(var, index) => format!("([] {} {})", sexp_var(var), sexp(index)),
+    }
+}
+
+fn sexp_dec(dec: &Dec) -> String {
+    match dec {
+        Dec::Var(v) => match &v.typ {
+            Some((typ, _)) => format!("(var {} {typ} {})", v.name, sexp(&v.init)),
+            None => format!("(var {} {})", v.name, sexp(&v.init)),
+        },
+        Dec::Type(group) => join(group.iter().map(|t| {
+            let ty = match &t.ty {
+                Ty::Name(name, _) => name.to_string(),
+                Ty::Array(elem, _) => format!("(array-of {elem})"),
+                Ty::Record(fields) => format!(
+                    "{{{}}}",
+                    join(fields.iter().map(|f| format!("{}:{}", f.name, f.typ)))
+                ),
+            };
+            format!("(type {} {ty})", t.name)
+        })),
+        Dec::Function(group) => join(group.iter().map(|f| {
+            let params = join(f.params.iter().map(|p| format!("{}:{}", p.name, p.typ)));
+            match &f.result {
+                Some((result, _)) => {
+                    format!(
+                        "(function {} ({params}) {result} {})",
+                        f.name,
+                        sexp(&f.body)
+                    )
+                }
+                None => format!("(function {} ({params}) {})", f.name, sexp(&f.body)),
+            }
+        })),
+        Dec::Import(import) => format!("(import {:?})", import.path.as_str()),
+    }
+}
+
+fn list(head: &str, name: &str, items: impl Iterator<Item = String>) -> String {
+    let mut out = format!("({head} {name}");
+    for item in items {
+        out.push(' ');
+        out.push_str(&item);
+    }
+    out.push(')');
+    out
+}
+
+fn join(items: impl Iterator<Item = String>) -> String {
+    items.collect::<Vec<_>>().join(" ")
+}
+
+/// The expression as an indented tree, one node per line with its span.
+pub(crate) fn tree(exp: &Exp) -> String {
+    let mut out = String::new();
+    node(exp).write(&mut out, 0);
+    out
+}
+
+/// A node of the tree `tree` prints: what it is, its span, and its
+/// children. Declaration groups have no span of their own.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Node {
+    pub(crate) label: String,
+    pub(crate) pos: Option<Span>,
+    pub(crate) children: Vec<Node>,
+}
+
+impl Node {
+    fn write(&self, out: &mut String, depth: usize) {
+        let _ = write!(out, "{:indent$}{}", "", self.label, indent = depth * 2);
+        if let Some(pos) = self.pos {
+            let _ = write!(out, " {}", span(pos));
+        }
+        out.push('\n');
+        for child in &self.children {
+            child.write(out, depth + 1);
+        }
+    }
+}
+
+/// The expression as a generic tree of labelled nodes.
+pub(crate) fn node(exp: &Exp) -> Node {
+    let mut builder = TreeBuilder {
+        stack: vec![Vec::new()],
+    };
+    builder.exp(exp);
+    builder
+        .stack
+        .pop()
+        .and_then(|mut root| root.pop())
+        .expect("one root node")
+}
+
+struct TreeBuilder {
+    /// The children gathered so far of each node being built.
+    stack: Vec<Vec<Node>>,
+}
+
+impl TreeBuilder {
+    /// Adds a node for `label` and its span, with the nodes `children` adds.
+    fn node(&mut self, label: &str, pos: Option<Span>, children: impl FnOnce(&mut Self)) {
+        self.stack.push(Vec::new());
+        children(self);
+        let children = self.stack.pop().expect("pushed above");
+        self.stack
+            .last_mut()
+            .expect("root is never popped")
+            .push(Node {
+                label: label.to_string(),
+                pos,
+                children,
+            });
+    }
+
+    fn leaf(&mut self, label: &str, pos: Span) {
+        self.node(label, Some(pos), |_| {});
+    }
+
+    fn exp(&mut self, exp: &Exp) {
+        let pos = Some(exp.pos);
+        match &exp.kind {
+            ExpKind::Var(var) => self.var(var),
+            ExpKind::Nil => self.leaf("Nil", exp.pos),
+            ExpKind::Unit => self.leaf("Unit", exp.pos),
+            ExpKind::Error => self.leaf("Error", exp.pos),
+            ExpKind::Int(n) => self.leaf(&format!("Int {n}"), exp.pos),
+            ExpKind::String(s) => self.leaf(&format!("String {s:?}"), exp.pos),
+            ExpKind::Call { func, args } => self.node(&format!("Call {func}"), pos, |p| {
+                args.iter().for_each(|arg| p.exp(arg))
+            }),
+            ExpKind::Op { left, op, right } => self.node(&format!("Op {}", op.text()), pos, |p| {
+                p.exp(left);
+                p.exp(right);
+            }),
+            ExpKind::Record { typ, fields } => self.node(&format!("Record {typ}"), pos, |p| {
+                for field in fields {
+                    p.node(&format!("Field {}", field.name), Some(field.pos), |p| {
+                        p.exp(&field.exp)
+                    });
+                }
+            }),
+            ExpKind::Seq(exps) => self.node("Seq", pos, |p| exps.iter().for_each(|e| p.exp(e))),
+            ExpKind::Assign { var, exp } => self.node("Assign", pos, |p| {
+                p.var(var);
+                p.exp(exp);
+            }),
+            ExpKind::If { test, then_, else_ } => self.node("If", pos, |p| {
+                p.exp(test);
+                p.exp(then_);
+                if let Some(else_) = else_ {
+                    p.exp(else_);
+                }
+            }),
+            ExpKind::While { test, body } => self.node("While", pos, |p| {
+                p.exp(test);
+                p.exp(body);
+            }),
+            ExpKind::For { var, lo, hi, body } => self.node(&format!("For {var}"), pos, |p| {
+                p.exp(lo);
+                p.exp(hi);
+                p.exp(body);
+            }),
+            ExpKind::Break => self.leaf("Break", exp.pos),
+            ExpKind::Let { decs, body } => self.node("Let", pos, |p| {
+                decs.iter().for_each(|dec| p.dec(dec));
+                p.exp(body);
+            }),
+            ExpKind::Array { typ, size, init } => self.node(&format!("Array {typ}"), pos, |p| {
+                p.exp(size);
+                p.exp(init);
+            }),
+        }
+    }
+
+    fn var(&mut self, var: &Var) {
+        let pos = Some(var.pos);
+        match &var.kind {
+            VarKind::Simple(name) => self.leaf(&format!("Simple {name}"), var.pos),
+            VarKind::Field(base, field) => {
+                self.node(&format!("FieldVar {field}"), pos, |p| p.var(base))
+            }
+            VarKind::Subscript(base, index) => self.node("Subscript", pos, |p| {
+                p.var(base);
+                p.exp(index);
+            }),
+        }
+    }
+
+    fn dec(&mut self, dec: &Dec) {
+        match dec {
+            Dec::Function(group) => self.node("FunctionDecs", None, |p| {
+                for f in group {
+                    let mut label = format!("Function {}({})", f.name, fields(&f.params));
+                    if let Some((result, _)) = f.result {
+                        let _ = write!(label, ": {result}");
+                    }
+                    p.node(&label, Some(f.pos), |p| p.exp(&f.body));
+                }
+            }),
+            Dec::Var(v) => {
+                let label = match v.typ {
+                    Some((typ, _)) => format!("VarDec {}: {typ}", v.name),
+                    None => format!("VarDec {}", v.name),
+                };
+                self.node(&label, Some(v.pos), |p| p.exp(&v.init));
+            }
+            Dec::Type(group) => self.node("TypeDecs", None, |p| {
+                for t in group {
+                    let ty = match &t.ty {
+                        Ty::Name(name, _) => name.to_string(),
+                        Ty::Record(fields_) => format!("{{{}}}", fields(fields_)),
+                        Ty::Array(elem, _) => format!("array of {elem}"),
+                    };
+                    p.leaf(&format!("TypeDec {} = {ty}", t.name), t.pos);
+                }
+            }),
+            Dec::Import(import) => {
+                self.leaf(&format!("Import {:?}", import.path.as_str()), import.pos)
+            }
+        }
+    }
+}
+
+fn fields(fields: &[Field]) -> String {
+    fields
+        .iter()
+        .map(|f| format!("{}: {}", f.name, f.typ))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn span(pos: Span) -> String {
+    format!("@{}..{}", pos.lo(), pos.hi())
+}
@@ -0,0 +1,464 @@
+//! Reads back the s-expressions `sexp` prints, for `tigerc --from ast`:
+//! the back end and type checker can then be driven without the lexer and
+//! parser. Each node's span is where it was written in the s-expression
+//! text, so diagnostics point into that file.
+
+use crate::ast::{
+    Dec, Exp, ExpKind, Field, FunDec, Import, Oper, RecordField, Symbol, Ty, TypeDec, Var, VarDec,
+    VarKind,
+};
+use crate::diagnostics::Diagnostic;
+use crate::span::Span;
+use crate::symbol::ByteStr;
+
+/// The expression written in `src` as `sexp` writes it.
+pub(crate) fn sexp(src: &str) -> Result<Exp, Diagnostic> {
+    let mut reader = Reader { src, at: 0 };
+    let sx = reader.sx()?;
+    reader.skip_whitespace();
+    if reader.at < src.len() {
+        return Err(error(reader.here(), "expected the end of the input"));
+    }
+    exp(&sx)
+}
+
+/// A parsed s-expression: `{...}` only appears in record types.
+enum Sx<'a> {
+    Atom(&'a str, Span),
+    Str(Vec<u8>, Span),
+    List(Vec<Sx<'a>>, Span),
+    Braces(Vec<Sx<'a>>, Span),
+}
+
+impl Sx<'_> {
+    fn pos(&self) -> Span {
+        match self {
+            Sx::Atom(_, pos) | Sx::Str(_, pos) | Sx::List(_, pos) | Sx::Braces(_, pos) => *pos,
+        }
+    }
+}
+
+fn error(pos: Span, msg: impl Into<String>) -> Diagnostic {
+    Diagnostic::error(pos, msg.into())
+}
+
+struct Reader<'a> {
+    src: &'a str,
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn skip_whitespace(&mut self) {
+        let rest = &self.src[self.at..];
+        self.at += rest.len() - rest.trim_start().len();
+    }
+
+    fn here(&self) -> Span {
+        Span::new(self.at as u32, self.at as u32)
+    }
+
+    fn span_from(&self, lo: usize) -> Span {
+        Span::new(lo as u32, self.at as u32)
+    }
+
+    fn sx(&mut self) -> Result<Sx<'a>, Diagnostic> {
+        self.skip_whitespace();
+        let lo = self.at;
+        match self.src[self.at..].chars().next() {
+            None => Err(error(self.here(), "unexpected end of input")),
+            Some(open @ ('(' | '{')) => {
+                let close = if open == '(' { ')' } else { '}' };
+                self.at += 1;
+                let mut items = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    match self.src[self.at..].chars().next() {
+                        Some(c) if c == close => break,
+                        None => {
+                            return Err(error(self.span_from(lo), format!("unclosed `{open}`")))
+                        }
+                        _ => items.push(self.sx()?),
+                    }
+                }
+                self.at += 1;
+                let pos = self.span_from(lo);
+                Ok(if open == '(' {
+                    Sx::List(items, pos)
+                } else {
+                    Sx::Braces(items, pos)
+                })
+            }
+            Some(c @ (')' | '}')) => Err(error(self.span_from(lo), format!("unexpected `{c}`"))),
+            Some('"') => self.string(),
+            Some(_) => {
+                let rest = &self.src[self.at..];
+                let len = rest
+                    .find(|c: char| c.is_whitespace() || "(){}\"".contains(c))
+                    .unwrap_or(rest.len());
+                self.at += len;
+                Ok(Sx::Atom(&rest[..len], self.span_from(lo)))
+            }
+        }
+    }
+
+    /// A string literal with the escapes of Rust's `{:?}`, plus `\xNN` for
+    /// a byte that is not UTF-8.
+    fn string(&mut self) -> Result<Sx<'a>, Diagnostic> {
+        let lo = self.at;
+        let mut out = Vec::new();
+        let mut chars = self.src[lo + 1..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.at = lo + 1 + i + 1;
+                    return Ok(Sx::Str(out, self.span_from(lo)));
+                }
+                '\\' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('0') => '\0',
+                        Some(c @ ('"' | '\\' | '\'')) => c,
+                        Some('x') => {
+                            let rest = chars.as_str();
+                            let byte = rest
+                                .get(..2)
+                                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                            let Some(byte) = byte else {
+                                let at = (lo + 1 + i) as u32;
+                                return Err(error(Span::new(at, at + 2), "invalid `\\x` escape"));
+                            };
+                            chars.nth(1);
+                            out.push(byte);
+                            continue;
+                        }
+                        Some('u') => {
+                            let rest = chars.as_str();
+                            let code = rest
+                                .strip_prefix('{')
+                                .and_then(|r| r.split_once('}'))
+                                .and_then(|(hex, _)| u32::from_str_radix(hex, 16).ok())
+                                .and_then(char::from_u32);
+                            let len = rest.find('}').map_or(0, |end| end + 1);
+                            chars.nth(len.saturating_sub(1));
+                            code.ok_or_else(|| {
+                                let at = (lo + 1 + i) as u32;
+                                error(Span::new(at, at + 2), "invalid `\\u{...}` escape")
+                            })?
+                        }
+                        _ => {
+                            let at = (lo + 1 + i) as u32;
+                            return Err(error(Span::new(at, at + 2), "invalid escape"));
+                        }
+                    };
+                    out.extend_from_slice(escaped.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                c => out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            }
+        }
+        Err(error(
+            Span::new(lo as u32, self.src.len() as u32),
+            "unterminated string",
+        ))
+    }
+}
+
+fn is_ident(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic())
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn name(sx: &Sx) -> Result<Symbol, Diagnostic> {
+    match sx {
+        Sx::Atom(s, _) if is_ident(s) => Ok(Symbol::intern(s)),
+        _ => Err(error(sx.pos(), "expected a name")),
+    }
+}
+
+/// The `name:type` of a parameter or record field.
+fn field(sx: &Sx) -> Result<Field, Diagnostic> {
+    match sx {
+        Sx::Atom(s, pos) => match s.split_once(':') {
+            Some((name, typ)) if is_ident(name) && is_ident(typ) => Ok(Field {
+                name: Symbol::intern(name),
+                typ: Symbol::intern(typ),
+                pos: *pos,
+            }),
+            _ => Err(error(*pos, "expected `name:type`")),
+        },
+        _ => Err(error(sx.pos(), "expected `name:type`")),
+    }
+}
+
+fn operator(s: &str) -> Option<Oper> {
+    Some(match s {
+        "+" => Oper::Plus,
+        "-" => Oper::Minus,
+        "*" => Oper::Times,
+        "/" => Oper::Divide,
+        "=" => Oper::Eq,
+        "<>" => Oper::Neq,
+        "<" => Oper::Lt,
+        "<=" => Oper::Le,
+        ">" => Oper::Gt,
+        ">=" => Oper::Ge,
+        "&" => Oper::And,
+        "|" => Oper::Or,
+        _ => return None,
+    })
+}
+
+/// Checks that the form `head` at `pos` has one of `counts` operands.
+fn arity<'s, 'a>(
+    head: &str,
+    pos: Span,
+    args: &'s [Sx<'a>],
+    counts: &[usize],
+) -> Result<&'s [Sx<'a>], Diagnostic> {
+    match counts.contains(&args.len()) {
+        true => Ok(args),
+        false => Err(error(
+            pos,
+            format!("`{head}` takes {} operands, not {}", counts[0], args.len()),
+        )),
+    }
+}
+
+fn boxed(sx: &Sx) -> Result<Box<Exp>, Diagnostic> {
+    exp(sx).map(Box::new)
+}
+
+fn exp(sx: &Sx) -> Result<Exp, Diagnostic> {
+    let pos = sx.pos();
+    let kind = match sx {
+        Sx::Atom("nil", _) => ExpKind::Nil,
+        Sx::Atom("break", _) => ExpKind::Break,
+        Sx::Atom(s, _) if s.starts_with(|c: char| c.is_ascii_digit()) => {
+            let n = s
+                .parse()
+                .map_err(|_| error(pos, format!("invalid integer `{s}`")))?;
+            ExpKind::Int(n)
+        }
+        Sx::Atom(s, _) if is_ident(s) => ExpKind::Var(var(sx)?),
+        Sx::Atom(s, _) => return Err(error(pos, format!("expected an expression, found `{s}`"))),
+        Sx::Str(s, _) => ExpKind::String(ByteStr::new(s)),
+        Sx::Braces(..) => return Err(error(pos, "expected an expression, found a record type")),
+        Sx::List(items, _) if items.is_empty() => ExpKind::Unit,
+        Sx::List(items, _) => {
+            let Sx::Atom(head, _) = &items[0] else {
+                return Err(error(items[0].pos(), "expected the name of a form"));
+            };
+            let args = &items[1..];
+            match *head {
+                "." | "[]" => ExpKind::Var(var(sx)?),
+                "call" => {
+                    let Some((func, args)) = args.split_first() else {
+                        return Err(error(pos, "`call` needs a function name"));
+                    };
+                    ExpKind::Call {
+                        func: name(func)?,
+                        args: args.iter().map(exp).collect::<Result<_, _>>()?,
+                    }
+                }
+                "record" => {
+                    let Some((typ, fields)) = args.split_first() else {
+                        return Err(error(pos, "`record` needs a type name"));
+                    };
+                    let fields = fields
+                        .iter()
+                        .map(|f| match f {
+                            Sx::List(pair, pos) if pair.len() == 2 => Ok(RecordField {
+                                name: name(&pair[0])?,
+                                exp: exp(&pair[1])?,
+                                pos: *pos,
+                            }),
+                            _ => Err(error(f.pos(), "expected `(field value)`")),
+                        })
+                        .collect::<Result<_, _>>()?;
+                    ExpKind::Record {
+                        typ: name(typ)?,
+                        fields,
+                    }
+                }
+                "seq" if args.len() < 2 => {
+                    return Err(error(pos, "`seq` takes at least 2 operands"));
+                }
+                "seq" => ExpKind::Seq(args.iter().map(exp).collect::<Result<_, _>>()?),
+                ":=" => {
+                    let [v, e] = arity(head, pos, args, &[2])? else {
+                        unreachable!()
+                    };
+                    ExpKind::Assign {
+                        var: var(v)?,
+                        exp: boxed(e)?,
+                    }
+                }
+                "if" => match arity(head, pos, args, &[2, 3])? {
+                    [test, then_] => ExpKind::If {
+                        test: boxed(test)?,
+                        then_: boxed(then_)?,
+                        else_: None,
+                    },
+                    [test, then_, else_] => ExpKind::If {
+                        test: boxed(test)?,
+                        then_: boxed(then_)?,
+                        else_: Some(boxed(else_)?),
+                    },
+                    _ => unreachable!(),
+                },
+                "while" => {
+                    let [test, body] = arity(head, pos, args, &[2])? else {
+                        unreachable!()
+                    };
+                    ExpKind::While {
+                        test: boxed(test)?,
+                        body: boxed(body)?,
+                    }
+                }
+                "for" => {
+                    let [v, lo, hi, body] = arity(head, pos, args, &[4])? else {
+                        unreachable!()
+                    };
+                    ExpKind::For {
+                        var: name(v)?,
+                        lo: boxed(lo)?,
+                        hi: boxed(hi)?,
+                        body: boxed(body)?,
+                    }
+                }
+                "let" => {
+                    let [decs, body] = arity(head, pos, args, &[2])? else {
+                        unreachable!()
+                    };
+                    let Sx::List(decs, _) = decs else {
+                        return Err(error(decs.pos(), "expected a list of declarations"));
+                    };
+                    ExpKind::Let {
+                        decs: declarations(decs)?,
+                        body: boxed(body)?,
+                    }
+                }
+                "array" => {
+                    let [typ, size, init] = arity(head, pos, args, &[3])? else {
+                        unreachable!()
+                    };
+                    ExpKind::Array {
+                        typ: name(typ)?,
+                        size: boxed(size)?,
+                        init: boxed(init)?,
+                    }
+                }
+                op => match operator(op) {
+                    Some(oper) => {
+                        let [left, right] = arity(head, pos, args, &[2])? else {
+                            unreachable!()
+                        };
+                        ExpKind::Op {
+                            left: boxed(left)?,
+                            op: oper,
+                            right: boxed(right)?,
+                        }
+                    }
+                    None => return Err(error(items[0].pos(), format!("unknown form `{op}`"))),
+                },
+            }
+        }
+    };
+    Ok(Exp { kind, pos })
+}
+
+fn var(sx: &Sx) -> Result<Var, Diagnostic> {
+    let pos = sx.pos();
+    let kind = match sx {
+        Sx::Atom(..) => VarKind::Simple(name(sx)?),
+        Sx::List(items, _) => match items.as_slice() {
+            [Sx::Atom(".", _), record, field] => {
+                VarKind::Field(Box::new(var(record)?), name(field)?)
+            }
+            [Sx::Atom("[]", _), array, index] => {
+                VarKind::Subscript(Box::new(var(array)?), boxed(index)?)
+            }
+            _ => return Err(error(pos, "expected a variable")),
+        },
+        _ => return Err(error(pos, "expected a variable")),
+    };
+    Ok(Var { kind, pos })
+}
+
+/// The declarations of a `let`; adjacent types and functions form groups,
+/// as they do in source.
+fn declarations(items: &[Sx]) -> Result<Vec<Dec>, Diagnostic> {
+    let mut decs: Vec<Dec> = Vec::new();
+    for item in items {
+        let pos = item.pos();
+        let Sx::List(parts, _) = item else {
+            return Err(error(pos, "expected a declaration"));
+        };
+        match parts.as_slice() {
+            [Sx::Atom("var", _), v, init] | [Sx::Atom("var", _), v, _, init] => {
+                let typ = match parts.len() {
+                    4 => Some((name(&parts[2])?, parts[2].pos())),
+                    _ => None,
+                };
+                decs.push(Dec::Var(VarDec {
+                    name: name(v)?,
+                    typ,
+                    init: exp(init)?,
+                    pos,
+                }));
+            }
+            [Sx::Atom("type", _), t, ty] => {
+                let ty = match ty {
+                    Sx::Atom(..) => Ty::Name(name(ty)?, ty.pos()),
+                    Sx::List(of, _) => match of.as_slice() {
+                        [Sx::Atom("array-of", _), elem] => Ty::Array(name(elem)?, elem.pos()),
+                        _ => return Err(error(ty.pos(), "expected `(array-of type)`")),
+                    },
+                    Sx::Braces(fields, _) => {
+                        Ty::Record(fields.iter().map(field).collect::<Result<_, _>>()?)
+                    }
+                    Sx::Str(..) => return Err(error(ty.pos(), "expected a type")),
+                };
+                let dec = TypeDec {
+                    name: name(t)?,
+                    ty,
+                    pos,
+                };
+                match decs.last_mut() {
+                    Some(Dec::Type(group)) => group.push(dec),
+                    _ => decs.push(Dec::Type(vec![dec])),
+                }
+            }
+            [Sx::Atom("function", _), f, Sx::List(params, _), body]
+            | [Sx::Atom("function", _), f, Sx::List(params, _), _, body] => {
+                let result = match parts.len() {
+                    5 => Some((name(&parts[3])?, parts[3].pos())),
+                    _ => None,
+                };
+                let dec = FunDec {
+                    name: name(f)?,
+                    params: params.iter().map(field).collect::<Result<_, _>>()?,
+                    result,
+                    body: exp(body)?,
+                    pos,
+                };
+                match decs.last_mut() {
+                    Some(Dec::Function(group)) => group.push(dec),
+                    _ => decs.push(Dec::Function(vec![dec])),
+                }
+            }
+            [Sx::Atom("import", _), Sx::Str(path, _)] => decs.push(Dec::Import(Import {
+                path: Symbol::intern(&String::from_utf8_lossy(path)),
+                pos,
+            })),
+            _ => {
+                return Err(error(
+                    pos,
+                    "expected a `var`, `type`, `function` or `import` declaration",
+                ))
+            }
+        }
+    }
+    Ok(decs)
+}
@@ -0,0 +1,145 @@
+use crate::lexer::stream::tokenize;
+use crate::parser::parse;
+use crate::pretty::{read, sexp, tokens, tree};
+use crate::semant::type_check;
+use crate::span::Span;
+
+#[test]
+fn token_listing() {
+    assert_eq!(
+        tokens(&tokenize("x := \"a\\\"\" + 12")),
+        "ID x @0..1\nASSIGN @2..4\nSTRING \"a\\\"\" @5..10\nPLUS @11..12\nINT 12 @13..15\nEOF @15..15\n"
+    );
+}
+
+#[test]
+fn tree_has_spans() {
+    let src = "let
+  type r = {a: int}
+  function f(x: r): int = x.a
+  var v := f(r {a = 1})
+in
+  if v > 0 then v := -v
+end";
+    let expected = "\
+Let @0..108
+  TypeDecs
+    TypeDec r = {a: int} @6..23
+  FunctionDecs
+    Function f(x: r): int @26..53
+      FieldVar a @50..53
+        Simple x @50..51
+  VarDec v @56..77
+    Call f @65..77
+      Record r @67..76
+        Field a @70..75
+          Int 1 @74..75
+  If @83..104
+    Op > @86..91
+      Simple v @86..87
+      Int 0 @90..91
+    Assign @97..104
+      Simple v @97..98
+      Op - @102..104
+        Int 0 @102..103
+        Simple v @103..104
+";
+    assert_eq!(tree(&parse(src).unwrap()), expected);
+}
+
+#[test]
+fn sexp_is_compact() {
+    let exp = parse("let var a: int := 1 in a[0] := b.c end").unwrap();
+    assert_eq!(sexp(&exp), "(let ((var a int 1)) (:= ([] a 0) (. b c)))");
+}
+
+#[test]
+fn sexps_read_back_into_the_same_tree() {
+    for src in [
+        "let
+            type list = {hd: int, tl: list}
+            type ints = array of int
+            type alias = list
+            var xs: list := list {hd = 1, tl = nil}
+            var a := ints [3] of 0
+            function len(l: list): int = if l = nil then 0 else 1 + len(l.tl)
+            function show(s: string) = print(s)
+        in
+            for i := 0 to 2 do (a[i] := len(xs); if i = 1 then break);
+            while a[0] <> 0 & 1 | 0 do a[0] := a[0] - 1;
+            show(\"tab\\t \\\"quoted\\\" \\001\");
+            (); let in end
+        end",
+        "-1 * (2 / 3) >= 4",
+        "concat(\"\\200é\\255\", \"\")",
+    ] {
+        let exp = parse(src).unwrap();
+        let text = sexp(&exp);
+        let back = read::sexp(&text).unwrap_or_else(|e| panic!("{text}: {:?}", e.msg));
+        assert_eq!(sexp(&back), text);
+    }
+}
+
+#[test]
+fn read_sexps_have_spans_into_their_text() {
+    let text = "(let ((var x string 1)) x)";
+    let exp = read::sexp(text).unwrap();
+    assert_eq!(exp.pos, Span::new(0, text.len() as u32));
+    let errors = type_check(&exp).unwrap_err();
+    assert_eq!(errors[0].pos, Span::new(20, 21));
+
+    let error = |text: &str| {
+        let err = read::sexp(text).expect_err("an error");
+        (err.msg.to_string(), err.pos.lo())
+    };
+    assert_eq!(
+        error("(if 1)"),
+        ("`if` takes 2 operands, not 1".to_string(), 0)
+    );
+    assert_eq!(
+        error("(+ 1 (frob 2))"),
+        ("unknown form `frob`".to_string(), 6)
+    );
+    assert_eq!(
+        error("(let ((var 1 2)) 3)"),
+        ("expected a name".to_string(), 11)
+    );
+    assert_eq!(error("(seq 1 2"), ("unclosed `(`".to_string(), 0));
+    assert_eq!(
+        error("1 2"),
+        ("expected the end of the input".to_string(), 2)
+    );
+}
+
+#[test]
+fn diff_points_at_the_first_differing_node() {
+    use crate::pretty::diff;
+
+    let expected = parse("let var x := 1 in f(x + 1, 2) end").unwrap();
+    let same = parse("let var x := 1 in f(x+1,2) end").unwrap();
+    assert_eq!(diff(&expected, &same), None);
+
+    let actual = parse("let var x := 1 in f(x * 1, 2) end").unwrap();
+    let found = diff(&expected, &actual).unwrap();
+    assert_eq!(found.path, ["Let", "Call f"]);
+    assert_eq!(
+        found.to_string(),
+        "\
+the trees differ under Let > Call f
+expected:
+  Op + @20..25
+    Simple x @20..21
+    Int 1 @24..25
+actual:
+  Op * @20..25
+    Simple x @20..21
+    Int 1 @24..25
+"
+    );
+
+    let fewer = parse("let var x := 1 in f(x + 1) end").unwrap();
+    let found = diff(&expected, &fewer).unwrap();
+    assert_eq!(found.path, ["Let"]);
+    assert_eq!(found.expected.children.len(), 2);
+    assert_eq!(found.actual.children.len(), 1);
+}
@@ -0,0 +1,221 @@
+#![allow(dead_code)]
+
+//! Register allocation by graph coloring, the `RegAlloc` and `Color`
+//! modules of chapter 11.
+//!
+//! Temporaries are simplified off the interference graph while some has
+//! fewer neighbors than there are registers. When none does, the one with
+//! the most neighbors is pushed anyway in the hope it still gets a color
+//! (optimistic coloring). Temporaries left without a color are spilled to
+//! the frame, and the rewritten program is allocated again. Moves whose
+//! ends get the same register can be deleted afterwards; there is no
+//! coalescing.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::codegen::Instr;
+use crate::flowgraph::instrs_to_graph;
+use crate::frame::{self, Access, Frame};
+use crate::liveness::{interference_graph, Interference};
+use crate::temp::Temp;
+
+pub(crate) struct Allocation {
+    pub(crate) instrs: Vec<Instr>,
+    /// The register of every temporary in `instrs`.
+    pub(crate) registers: HashMap<Temp, Temp>,
+    /// How many temporaries were spilled to the frame.
+    pub(crate) spills: usize,
+}
+
+impl Allocation {
+    /// Deletes the moves whose ends got the same register.
+    pub(crate) fn remove_redundant_moves(&mut self) {
+        let registers = &self.registers;
+        self.instrs.retain(|instr| match instr {
+            Instr::Move { dst, src, .. } => registers[dst] != registers[src],
+            _ => true,
+        });
+    }
+}
+
+/// Allocates `frame::ALLOCATABLE` to the temporaries of `instrs`, adding
+/// spill slots to `frame` as needed.
+pub(crate) fn alloc(instrs: Vec<Instr>, frame: &mut Frame) -> Allocation {
+    alloc_with(instrs, frame, &frame::ALLOCATABLE)
+}
+
+pub(crate) fn alloc_with(
+    mut instrs: Vec<Instr>,
+    frame: &mut Frame,
+    registers: &[Temp],
+) -> Allocation {
+    // Temporaries created by spilling live for a single instruction;
+    // spilling them again would not help.
+    let mut no_spill = HashSet::new();
+    let mut spilled = 0;
+    loop {
+        let graph = interference_graph(&instrs_to_graph(&instrs));
+        match color(&graph, registers, &no_spill) {
+            Ok(registers) => {
+                return Allocation {
+                    instrs,
+                    registers,
+                    spills: spilled,
+                }
+            }
+            Err(spills) => {
+                assert!(
+                    spills.iter().any(|t| !no_spill.contains(t)),
+                    "more temporaries are used by one instruction than there are registers"
+                );
+                spilled += spills.len();
+                instrs = rewrite(instrs, frame, &spills, &mut no_spill);
+            }
+        }
+    }
+}
+
+/// Colors `graph` with `registers`, or returns the temporaries that must be
+/// spilled.
+fn color(
+    graph: &Interference,
+    registers: &[Temp],
+    no_spill: &HashSet<Temp>,
+) -> Result<HashMap<Temp, Temp>, Vec<Temp>> {
+    let k = registers.len();
+    let mut remaining: BTreeSet<Temp> = graph
+        .adj
+        .keys()
+        .copied()
+        .filter(|t| !t.is_register())
+        .collect();
+    let mut degree: HashMap<Temp, usize> =
+        graph.adj.iter().map(|(&t, adj)| (t, adj.len())).collect();
+    let mut stack = Vec::with_capacity(remaining.len());
+    while !remaining.is_empty() {
+        let next = remaining
+            .iter()
+            .copied()
+            .find(|t| degree[t] < k)
+            .or_else(|| {
+                remaining
+                    .iter()
+                    .copied()
+                    .max_by_key(|t| (!no_spill.contains(t), degree[t]))
+            })
+            .expect("remaining is not empty");
+        remaining.remove(&next);
+        stack.push(next);
+        for n in &graph.adj[&next] {
+            if remaining.contains(n) {
+                *degree.get_mut(n).expect("every node has a degree") -= 1;
+            }
+        }
+    }
+
+    let mut colors: HashMap<Temp, Temp> = graph
+        .adj
+        .keys()
+        .filter(|t| t.is_register())
+        .map(|&t| (t, t))
+        .collect();
+    let mut spills = Vec::new();
+    while let Some(t) = stack.pop() {
+        let taken: HashSet<Temp> = graph.adj[&t]
+            .iter()
+            .filter_map(|n| colors.get(n).copied())
+            .collect();
+        match registers.iter().find(|r| !taken.contains(r)) {
+            Some(&r) => {
+                colors.insert(t, r);
+            }
+            None => spills.push(t),
+        }
+    }
+    if spills.is_empty() {
+        Ok(colors)
+    } else {
+        Err(spills)
+    }
+}
+
+/// Gives each of `spills` a frame slot, loading it into a fresh temporary
+/// before every use and storing it after every definition.
+fn rewrite(
+    instrs: Vec<Instr>,
+    frame: &mut Frame,
+    spills: &[Temp],
+    no_spill: &mut HashSet<Temp>,
+) -> Vec<Instr> {
+    let slots: HashMap<Temp, i64> = spills
+        .iter()
+        .map(|&t| match frame.alloc_local(true) {
+            Access::InFrame(offset) => (t, offset),
+            Access::InReg(_) => unreachable!("escaping locals live in the frame"),
+        })
+        .collect();
+    let mut out = Vec::with_capacity(instrs.len());
+    for instr in instrs {
+        let mut fresh: HashMap<Temp, Temp> = HashMap::new();
+        let mut rename = |t: Temp| match slots.get(&t) {
+            Some(_) => *fresh.entry(t).or_insert_with(|| {
+                let new = Temp::new();
+                no_spill.insert(new);
+                new
+            }),
+            None => t,
+        };
+        let (uses, defs): (Vec<Temp>, Vec<Temp>) = (
+            instr
+                .src()
+                .iter()
+                .copied()
+                .filter(|t| slots.contains_key(t))
+                .collect(),
+            instr
+                .dst()
+                .iter()
+                .copied()
+                .filter(|t| slots.contains_key(t))
+                .collect(),
+        );
+        let instr = match instr {
+            Instr::Oper {
+                assem,
+                dst,
+                src,
+                jump,
+            } => Instr::Oper {
+                assem,
+                dst: dst.into_iter().map(&mut rename).collect(),
+                src: src.into_iter().map(&mut rename).collect(),
+                jump,
+            },
+            Instr::Move { assem, dst, src } => Instr::Move {
+                assem,
+                dst: rename(dst),
+                src: rename(src),
+            },
+            label @ Instr::Label { .. } => label,
+        };
+        for t in dedup(uses) {
+            let assem = format!("movq {}(%rbp), `d0", slots[&t]);
+            out.push(Instr::oper(assem, vec![fresh[&t]], vec![]));
+        }
+        out.push(instr);
+        for t in dedup(defs) {
+            let assem = format!("movq `s0, {}(%rbp)", slots[&t]);
+            out.push(Instr::oper(assem, vec![], vec![fresh[&t]]));
+        }
+    }
+    out
+}
+
+fn dedup(mut temps: Vec<Temp>) -> Vec<Temp> {
+    temps.sort();
+    temps.dedup();
+    temps
+}
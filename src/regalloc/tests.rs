@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+
+use crate::canon;
+use crate::codegen::{codegen, Instr};
+use crate::flowgraph::instrs_to_graph;
+use crate::frame::{self, Frame, ALLOCATABLE, RAX, RBX, WORD_SIZE};
+use crate::liveness::interference_graph;
+use crate::parser::parse;
+use crate::regalloc::{alloc, alloc_with};
+use crate::semant::Semant;
+use crate::temp::{Label, Temp};
+use crate::translate::Fragment;
+
+/// Checks that every temporary got one of `registers` and that no two
+/// interfering temporaries share one.
+fn assert_valid(instrs: &[Instr], registers: &HashMap<Temp, Temp>, allowed: &[Temp]) {
+    let graph = interference_graph(&instrs_to_graph(instrs));
+    for (&t, adj) in &graph.adj {
+        let r = registers[&t];
+        assert!(t.is_register() || allowed.contains(&r), "{t} got {r}");
+        for &n in adj {
+            assert_ne!(r, registers[&n], "{t} and {n} interfere");
+        }
+    }
+}
+
+#[test]
+fn translated_functions_get_valid_registers() {
+    let src = "let
+        type intArray = array of int
+        var a := intArray [8] of 1
+        function f(x: int, y: int): int = if x < 2 then x + y else f(x - 1, y * 2) + f(x - 2, y)
+    in
+        for i := 0 to 7 do a[i] := f(i, a[i]) / (i + 1);
+        print(chr(a[3]))
+    end";
+    let exp = parse(src).unwrap();
+    let mut semant = Semant::new();
+    semant.check(&exp);
+    assert!(semant.errors().is_empty(), "{:?}", semant.errors());
+    for fragment in semant.fragments() {
+        let Fragment::Proc { body, frame } = fragment else {
+            continue;
+        };
+        let (blocks, done) = canon::basic_blocks(canon::linearize(body.clone()));
+        let instrs = frame::proc_entry_exit2(codegen(canon::trace_schedule(blocks, done)));
+        let mut frame = frame.clone();
+        let allocation = alloc(instrs, &mut frame);
+        assert_valid(&allocation.instrs, &allocation.registers, &ALLOCATABLE);
+    }
+}
+
+#[test]
+fn high_pressure_spills_to_the_frame() {
+    // Three values live at once, but only two registers.
+    let temps: Vec<Temp> = (0..3).map(|_| Temp::new()).collect();
+    let mut instrs: Vec<Instr> = temps
+        .iter()
+        .map(|&t| Instr::oper("movq $1, `d0", vec![t], vec![]))
+        .collect();
+    instrs.extend(
+        temps
+            .iter()
+            .map(|&t| Instr::oper("pushq `s0", vec![], vec![t])),
+    );
+    instrs.push(Instr::jump("", vec![], vec![]));
+    let mut frame = Frame::new(Label::named("f"), &[]);
+    let registers = [RAX, RBX];
+    let allocation = alloc_with(instrs, &mut frame, &registers);
+    assert!(frame.size() > 0);
+    assert_eq!(frame.size(), allocation.spills as i64 * WORD_SIZE);
+    assert_valid(&allocation.instrs, &allocation.registers, &registers);
+    let text: Vec<String> = allocation
+        .instrs
+        .iter()
+        .map(|i| {
+            i.format(&|t| {
+                frame::register_name(allocation.registers[&t])
+                    .unwrap()
+                    .to_string()
+            })
+        })
+        .collect();
+    assert!(text.iter().any(|line| line.contains("(%rbp)")), "{text:?}");
+}
+
+#[test]
+fn moves_within_one_register_are_removed() {
+    let (a, b) = (Temp::new(), Temp::new());
+    let instrs = vec![
+        Instr::oper("movq $1, `d0", vec![a], vec![]),
+        Instr::mov(b, a),
+        Instr::mov(RAX, b),
+        Instr::jump("", vec![RAX], vec![]),
+    ];
+    let mut frame = Frame::new(Label::named("f"), &[]);
+    let mut allocation = alloc(instrs, &mut frame);
+    assert_eq!(allocation.registers[&a], RAX);
+    allocation.remove_redundant_moves();
+    assert_eq!(allocation.instrs.len(), 2);
+}
@@ -0,0 +1,56 @@
+//! Evaluation of integer expressions whose value is known when the program
+//! is compiled, for warnings about array sizes, subscripts and conditions
+//! that are wrong on every run. Arithmetic wraps, as it does when the
+//! program runs; division by a constant zero is left to the run.
+
+use crate::ast::{Exp, ExpKind, Oper};
+use crate::span::Span;
+
+/// The value of a constant expression and the literals it was folded from.
+#[derive(Debug, PartialEq)]
+pub(super) struct Const {
+    pub(super) value: i64,
+    pub(super) parts: Vec<Span>,
+}
+
+/// The value of `exp` if it only combines integer literals.
+pub(super) fn eval(exp: &Exp) -> Option<Const> {
+    let mut parts = Vec::new();
+    let value = fold(exp, &mut parts)?;
+    Some(Const { value, parts })
+}
+
+fn fold(exp: &Exp, parts: &mut Vec<Span>) -> Option<i64> {
+    match &exp.kind {
+        ExpKind::Int(n) => {
+            parts.push(exp.pos);
+            Some(*n)
+        }
+        // A sequence has the value of its last expression.
+        ExpKind::Seq(exps) => fold(exps.last()?, parts),
+        ExpKind::Op { left, op, right } => {
+            // `-e` is `0 - e`, whose zero was not written as a literal.
+            let l = match (&left.kind, op) {
+                (ExpKind::Int(0), Oper::Minus) => 0,
+                _ => fold(left, parts)?,
+            };
+            let r = fold(right, parts)?;
+            Some(match op {
+                Oper::Plus => l.wrapping_add(r),
+                Oper::Minus => l.wrapping_sub(r),
+                Oper::Times => l.wrapping_mul(r),
+                Oper::Divide if r == 0 => return None,
+                Oper::Divide => l.wrapping_div(r),
+                Oper::Eq => (l == r) as i64,
+                Oper::Neq => (l != r) as i64,
+                Oper::Lt => (l < r) as i64,
+                Oper::Le => (l <= r) as i64,
+                Oper::Gt => (l > r) as i64,
+                Oper::Ge => (l >= r) as i64,
+                Oper::And => (l != 0 && r != 0) as i64,
+                Oper::Or => (l != 0 || r != 0) as i64,
+            })
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use crate::span::Span;
+use crate::symbol::Symbol;
+use crate::temp::Label;
+use crate::translate::{Access, Level};
+use crate::types::Ty;
+
+/// A symbol table with nested scopes, as in the book's `Symbol.table` with
+/// `beginScope`/`endScope`. Entering a name shadows outer bindings until the
+/// scope it was entered in ends.
+pub(crate) struct ScopedTable<T> {
+    bindings: HashMap<Symbol, Vec<T>>,
+    scopes: Vec<Vec<Symbol>>,
+}
+
+impl<T> ScopedTable<T> {
+    pub(crate) fn new() -> ScopedTable<T> {
+        ScopedTable {
+            bindings: HashMap::new(),
+            scopes: vec![Vec::new()],
+        }
+    }
+
+    pub(crate) fn begin_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    pub(crate) fn end_scope(&mut self) {
+        let scope = self.scopes.pop().expect("end_scope without begin_scope");
+        for name in scope {
+            let stack = self
+                .bindings
+                .get_mut(&name)
+                .expect("entered names are bound");
+            stack.pop();
+        }
+    }
+
+    pub(crate) fn enter(&mut self, name: Symbol, value: T) {
+        self.bindings.entry(name).or_default().push(value);
+        self.scopes
+            .last_mut()
+            .expect("there is always a scope")
+            .push(name);
+    }
+
+    pub(crate) fn look(&self, name: Symbol) -> Option<&T> {
+        self.bindings.get(&name).and_then(|stack| stack.last())
+    }
+
+    pub(crate) fn look_mut(&mut self, name: Symbol) -> Option<&mut T> {
+        self.bindings
+            .get_mut(&name)
+            .and_then(|stack| stack.last_mut())
+    }
+}
+
+pub(crate) type TypeEnv = ScopedTable<Ty>;
+pub(crate) type ValueEnv = ScopedTable<EnvEntry>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum EnvEntry {
+    Var {
+        ty: Ty,
+        access: Access,
+        /// For-loop counters may not be assigned to.
+        read_only: bool,
+        /// The constant size of the array the variable was declared with,
+        /// until it is assigned another. Only kept for variables no nested
+        /// function uses, so that no call can assign it unseen.
+        size: Option<i64>,
+        /// The declaration: of the variable, parameter or loop.
+        dec: Span,
+    },
+    Fun {
+        formals: Vec<Ty>,
+        result: Ty,
+        /// The level the function's body runs at; `None` for the runtime.
+        level: Option<Level>,
+        label: Label,
+        /// The declaration; `None` for the runtime.
+        dec: Option<Span>,
+    },
+}
+
+/// The predefined types and standard library functions.
+pub(crate) fn base_envs() -> (TypeEnv, ValueEnv) {
+    let mut tenv = TypeEnv::new();
+    tenv.enter(Symbol::intern("int"), Ty::INT);
+    tenv.enter(Symbol::intern("string"), Ty::STRING);
+
+    let mut venv = ValueEnv::new();
+    let builtins: [(&str, &[Ty], Ty); 20] = [
+        ("print", &[Ty::STRING], Ty::UNIT),
+        ("flush", &[], Ty::UNIT),
+        ("getchar", &[], Ty::STRING),
+        ("ord", &[Ty::STRING], Ty::INT),
+        ("chr", &[Ty::INT], Ty::STRING),
+        ("itoa", &[Ty::INT], Ty::STRING),
+        ("atoi", &[Ty::STRING, Ty::INT], Ty::INT),
+        ("size", &[Ty::STRING], Ty::INT),
+        ("substring", &[Ty::STRING, Ty::INT, Ty::INT], Ty::STRING),
+        ("concat", &[Ty::STRING, Ty::STRING], Ty::STRING),
+        ("not", &[Ty::INT], Ty::INT),
+        ("exit", &[Ty::INT], Ty::UNIT),
+        ("argc", &[], Ty::INT),
+        ("argv", &[Ty::INT], Ty::STRING),
+        ("getenv", &[Ty::STRING], Ty::STRING),
+        ("random", &[Ty::INT], Ty::INT),
+        ("seed", &[Ty::INT], Ty::UNIT),
+        ("clock", &[], Ty::INT),
+        ("heap_used", &[], Ty::INT),
+        ("gc_collect", &[], Ty::UNIT),
+    ];
+    for (name, formals, result) in builtins {
+        venv.enter(
+            Symbol::intern(name),
+            EnvEntry::Fun {
+                formals: formals.to_vec(),
+                result,
+                level: None,
+                label: Label::named(&format!("tig_{name}")),
+                dec: None,
+            },
+        );
+    }
+    (tenv, venv)
+}
@@ -0,0 +1,993 @@
+#![allow(dead_code)]
+
+//! Type checking of the AST, following chapter 5 of the book, and
+//! translation to intermediate code as in chapter 7: every `trans_*` method
+//! returns the translated expression together with its type.
+//!
+//! There is no typed HIR between the two. Names and types are resolved in
+//! the same pass that translates, so canon, opt and the backends see only
+//! IR trees, and what later walks the AST (the lints, the language server,
+//! `--explain`) reads the facts recorded here by span instead of resolving
+//! names again: `bindings`, the type `derivations` and each variable's
+//! `var_type`. The tree interpreter is the one exception, since it gives
+//! the front end's semantics without translating at all.
+
+mod consteval;
+mod env;
+#[cfg(test)]
+mod tests;
+pub(crate) mod type_graph;
+
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{Dec, Exp, ExpKind, FunDec, Oper, Ty as AstTy, TypeDec, Var, VarDec, VarKind};
+use crate::cancel::CancellationToken;
+use crate::diagnostics::{self, Diagnostic, Message};
+use crate::span::{Span, Spanned};
+use crate::symbol::Symbol;
+use crate::temp::Label;
+use crate::translate::{self, escape, Fragment, Level};
+use crate::types::{Ty, TyKind, TypeTable};
+pub(crate) use env::{EnvEntry, ScopedTable, TypeEnv, ValueEnv};
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct TypeError {
+    pub(crate) msg: Message,
+    pub(crate) pos: Span,
+    /// Related spans, such as the annotation a value failed to match.
+    pub(crate) labels: Vec<diagnostics::Label>,
+}
+
+/// A use of a name declared in the program and the declaration it refers
+/// to, for go-to-definition and the lints.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Binding {
+    pub(crate) name: Symbol,
+    pub(crate) pos: Span,
+    pub(crate) dec: Span,
+}
+
+/// How a name declared in the program got its type, as shown by
+/// `tigerc --explain`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Derivation {
+    pub(crate) name: Symbol,
+    pub(crate) pos: Span,
+    pub(crate) ty: String,
+    /// The typing rule that applied.
+    pub(crate) rule: &'static str,
+}
+
+/// Type checks a whole program, returning its type or every error found.
+pub(crate) fn type_check(exp: &Exp) -> Result<Ty, Vec<TypeError>> {
+    let mut semant = Semant::new();
+    let ty = semant.check(exp);
+    if semant.errors.is_empty() {
+        Ok(ty)
+    } else {
+        Err(semant.errors)
+    }
+}
+
+/// A translated expression and its type.
+struct ExpTy {
+    exp: translate::Exp,
+    ty: Ty,
+}
+
+impl ExpTy {
+    fn new(exp: translate::Exp, ty: Ty) -> ExpTy {
+        ExpTy { exp, ty }
+    }
+
+    fn unit(exp: translate::Exp) -> ExpTy {
+        ExpTy::new(exp, Ty::UNIT)
+    }
+
+    fn error() -> ExpTy {
+        ExpTy::new(translate::error(), Ty::ERROR)
+    }
+}
+
+pub(crate) struct Semant {
+    pub(crate) types: TypeTable,
+    tenv: TypeEnv,
+    venv: ValueEnv,
+    /// The function being translated.
+    level: Level,
+    /// Where `break` jumps to in the innermost loop of the current function.
+    break_label: Option<Label>,
+    /// Declarations of variables used from nested functions.
+    escapes: HashSet<Span>,
+    /// The variables of the enclosing `let`s not declared yet, the next one
+    /// last, to tell uses before a declaration from undefined names.
+    undeclared: Vec<Spanned<Symbol>>,
+    fragments: Vec<Fragment>,
+    errors: Vec<TypeError>,
+    /// Code that type checks but does the same wrong thing on every run,
+    /// found by evaluating constant expressions.
+    warnings: Vec<Diagnostic>,
+    derivations: Vec<Derivation>,
+    bindings: Vec<Binding>,
+    /// The type of every variable, field and subscript checked, by its span.
+    var_types: HashMap<Span, Ty>,
+    /// The declaration of every type declared in the program, by the type
+    /// its name was bound to.
+    type_decs: HashMap<Ty, Span>,
+    /// The span of the declaration of every translated function, and of the
+    /// whole program for `tigermain`.
+    proc_spans: HashMap<Label, Span>,
+    /// Stops the check early, leaving its results incomplete.
+    cancel: CancellationToken,
+}
+
+impl Semant {
+    pub(crate) fn new() -> Semant {
+        let (tenv, venv) = env::base_envs();
+        Semant {
+            types: TypeTable::new(),
+            tenv,
+            venv,
+            level: Level::outermost(),
+            break_label: None,
+            escapes: HashSet::new(),
+            undeclared: Vec::new(),
+            fragments: Vec::new(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            derivations: Vec::new(),
+            bindings: Vec::new(),
+            var_types: HashMap::new(),
+            type_decs: HashMap::new(),
+            proc_spans: HashMap::new(),
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    /// Gives up checking once `cancel` is cancelled: every expression left
+    /// is taken to be an error, without reporting one.
+    pub(crate) fn with_cancellation(mut self, cancel: CancellationToken) -> Semant {
+        self.cancel = cancel;
+        self
+    }
+
+    pub(crate) fn errors(&self) -> &[TypeError] {
+        &self.errors
+    }
+
+    /// Warnings about constant array sizes, subscripts, conditions and loop
+    /// bounds, in the order they were found. `lints` reports them with the
+    /// other warnings.
+    pub(crate) fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
+    /// The type of every variable, parameter, loop index and function, in
+    /// the order they were declared.
+    pub(crate) fn derivations(&self) -> &[Derivation] {
+        &self.derivations
+    }
+
+    /// Every use of a declared variable, function or type, in the order
+    /// they were checked.
+    pub(crate) fn bindings(&self) -> &[Binding] {
+        &self.bindings
+    }
+
+    /// The type of the variable, field or subscript at `pos`, if one was
+    /// checked there.
+    pub(crate) fn var_type(&self, pos: Span) -> Option<Ty> {
+        self.var_types.get(&pos).copied()
+    }
+
+    fn bind(&mut self, name: Symbol, pos: Span, dec: Span) {
+        self.bindings.push(Binding { name, pos, dec });
+    }
+
+    /// Where the function compiled to `label` was declared.
+    pub(crate) fn proc_span(&self, label: Label) -> Option<Span> {
+        self.proc_spans.get(&label).copied()
+    }
+
+    /// The translated program: `tigermain` and every function and string
+    /// literal, in the order they were finished.
+    pub(crate) fn fragments(&self) -> &[Fragment] {
+        &self.fragments
+    }
+
+    /// Type checks `exp` as the main program and returns its type; errors
+    /// are accumulated. The translation is added to the fragments.
+    pub(crate) fn check(&mut self, exp: &Exp) -> Ty {
+        self.escapes = escape::find_escapes(exp);
+        let main = Level::new(&self.level, Label::named("tigermain"), &[]);
+        self.proc_spans.insert(main.name(), exp.pos);
+        let outer = std::mem::replace(&mut self.level, main);
+        let ExpTy { exp: body, ty } = self.trans_exp(exp);
+        let returns_value = self.types.actual(ty) != Ty::UNIT;
+        translate::proc_entry_exit(&self.level, body, returns_value, &mut self.fragments);
+        self.level = outer;
+        ty
+    }
+
+    fn error(&mut self, pos: Span, msg: Message) -> Ty {
+        self.errors.push(TypeError {
+            msg,
+            pos,
+            labels: Vec::new(),
+        });
+        Ty::ERROR
+    }
+
+    /// Points the last reported error at another span of the program.
+    fn label(&mut self, pos: Span, msg: Message) {
+        let err = self.errors.last_mut().expect("an error was reported");
+        err.labels.push(diagnostics::Label { pos, msg });
+    }
+
+    /// Warns with `msg`, built from the value of `exp`, if `exp` is constant
+    /// and `wrong` holds for its value. The literals the value was folded
+    /// from are labelled, unless `exp` is a literal itself.
+    fn check_constant(
+        &mut self,
+        exp: &Exp,
+        wrong: impl Fn(i64) -> bool,
+        msg: impl Fn(i64) -> Message,
+    ) {
+        let Some(constant) = consteval::eval(exp) else {
+            return;
+        };
+        if !wrong(constant.value) {
+            return;
+        }
+        let mut warning = Diagnostic::warning(exp.pos, msg(constant.value));
+        if constant.parts != [exp.pos] {
+            for part in constant.parts {
+                warning = warning.with_label(part, Message::new("folded-from"));
+            }
+        }
+        self.warnings.push(warning);
+    }
+
+    fn derive(&mut self, name: Symbol, pos: Span, ty: Ty, rule: &'static str) {
+        let ty = self.name(ty);
+        self.derivations.push(Derivation {
+            name,
+            pos,
+            ty,
+            rule,
+        });
+    }
+
+    fn name(&self, ty: Ty) -> String {
+        self.types.name(ty)
+    }
+
+    /// Reports an error unless `actual` can be used where `expected` is
+    /// needed, in `what`, a phrase of the catalog. Returns whether it can.
+    fn expect_ty(&mut self, actual: Ty, expected: Ty, pos: Span, what: Message) -> bool {
+        let compatible = self.types.compatible(actual, expected);
+        if !compatible {
+            let msg = Message::new("E0301")
+                .nested("what", what)
+                .arg("expected", self.name(expected))
+                .arg("found", self.name(actual));
+            self.error(pos, msg);
+        }
+        compatible
+    }
+
+    fn trans_exp(&mut self, exp: &Exp) -> ExpTy {
+        if self.cancel.is_cancelled() {
+            return ExpTy::error();
+        }
+        match &exp.kind {
+            ExpKind::Var(var) => self.trans_var(var),
+            ExpKind::Nil => ExpTy::new(translate::nil(), Ty::NIL),
+            ExpKind::Unit => ExpTy::unit(translate::unit()),
+            // Already reported as a syntax error.
+            ExpKind::Error => ExpTy::error(),
+            ExpKind::Int(n) => ExpTy::new(translate::int(*n), Ty::INT),
+            ExpKind::String(s) => ExpTy::new(
+                translate::string(s.clone(), &mut self.fragments),
+                Ty::STRING,
+            ),
+            ExpKind::Call { func, args } => self.trans_call(*func, args, exp.pos),
+            ExpKind::Op { left, op, right } => self.trans_op(left, *op, right),
+            ExpKind::Record { typ, fields } => {
+                let Some(record_ty) = self.look_type(*typ, exp.pos) else {
+                    for field in fields {
+                        self.trans_exp(&field.exp);
+                    }
+                    return ExpTy::error();
+                };
+                let TyKind::Record { fields: formal, .. } = self.types.actual_kind(record_ty)
+                else {
+                    for field in fields {
+                        self.trans_exp(&field.exp);
+                    }
+                    self.error(exp.pos, Message::new("E0302").arg("name", typ));
+                    return ExpTy::error();
+                };
+                let formal = formal.clone();
+                let names: Vec<_> = fields
+                    .iter()
+                    .map(|field| Spanned::new(field.name, field.pos))
+                    .collect();
+                let repeated = self.check_repeated(&names, "E0329", "first-given");
+                if formal.len() != fields.len() && !repeated {
+                    let msg = Message::new("E0303")
+                        .arg("name", typ)
+                        .nested("fields", count("one-field", "fields", formal.len()))
+                        .nested("given", count("given-one", "given", fields.len()));
+                    self.error(exp.pos, msg);
+                }
+                let mut values = Vec::new();
+                for (i, field) in fields.iter().enumerate() {
+                    let ExpTy { exp: value, ty } = self.trans_exp(&field.exp);
+                    values.push(value);
+                    match formal.get(i) {
+                        Some(&(name, expected)) if name == field.name => {
+                            let what = Message::new("field").arg("name", name);
+                            self.expect_ty(ty, expected, field.exp.pos, what);
+                        }
+                        // A repeated field was reported above.
+                        Some(_) if repeated => {}
+                        Some(&(name, _)) => {
+                            let msg = Message::new("E0304")
+                                .arg("expected", name)
+                                .arg("found", field.name);
+                            self.error(field.pos, msg);
+                        }
+                        None => {}
+                    }
+                }
+                ExpTy::new(translate::record(values), record_ty)
+            }
+            ExpKind::Seq(exps) => {
+                let mut ty = Ty::UNIT;
+                let mut translated = Vec::new();
+                for exp in exps {
+                    let result = self.trans_exp(exp);
+                    translated.push(result.exp);
+                    ty = result.ty;
+                }
+                ExpTy::new(translate::seq(translated), ty)
+            }
+            ExpKind::Assign { var, exp: value } => {
+                if let VarKind::Simple(name) = var.kind {
+                    if let Some(EnvEntry::Var {
+                        read_only: true, ..
+                    }) = self.venv.look(name)
+                    {
+                        self.error(var.pos, Message::new("E0305").arg("name", name));
+                    }
+                    if let Some(EnvEntry::Var { size, .. }) = self.venv.look_mut(name) {
+                        *size = None;
+                    }
+                }
+                let var = self.trans_var(var);
+                let value_ = self.trans_exp(value);
+                self.expect_ty(
+                    value_.ty,
+                    var.ty,
+                    value.pos,
+                    Message::new("mismatched-assignment"),
+                );
+                ExpTy::unit(translate::assign(var.exp, value_.exp))
+            }
+            ExpKind::If { test, then_, else_ } => {
+                let test_ = self.trans_exp(test);
+                self.expect_ty(test_.ty, Ty::INT, test.pos, Message::new("if-condition"));
+                self.check_constant(test, |n| n == 0, |_| Message::new("W0303"));
+                let then_ty = self.trans_exp(then_);
+                match else_ {
+                    Some(else_) => {
+                        let else_ty = self.trans_exp(else_);
+                        let ty = if self.types.compatible(else_ty.ty, then_ty.ty) {
+                            // `if c then nil else r` has the record's type.
+                            if self.types.actual(then_ty.ty) == Ty::NIL {
+                                else_ty.ty
+                            } else {
+                                then_ty.ty
+                            }
+                        } else {
+                            let msg = Message::new("E0306")
+                                .arg("then", self.name(then_ty.ty))
+                                .arg("else", self.name(else_ty.ty));
+                            self.error(exp.pos, msg)
+                        };
+                        let exp = translate::if_(test_.exp, then_ty.exp, Some(else_ty.exp));
+                        ExpTy::new(exp, ty)
+                    }
+                    None => {
+                        self.expect_ty(
+                            then_ty.ty,
+                            Ty::UNIT,
+                            then_.pos,
+                            Message::new("if-without-else"),
+                        );
+                        ExpTy::unit(translate::if_(test_.exp, then_ty.exp, None))
+                    }
+                }
+            }
+            ExpKind::While { test, body } => {
+                let test_ = self.trans_exp(test);
+                self.expect_ty(test_.ty, Ty::INT, test.pos, Message::new("while-condition"));
+                self.check_constant(test, |n| n == 0, |_| Message::new("W0303"));
+                let done = Label::new();
+                let outer = self.break_label.replace(done);
+                let body_ = self.trans_exp(body);
+                self.break_label = outer;
+                self.expect_ty(body_.ty, Ty::UNIT, body.pos, Message::new("while-body"));
+                ExpTy::unit(translate::while_(test_.exp, body_.exp, done))
+            }
+            ExpKind::For { var, lo, hi, body } => {
+                let lo_ = self.trans_exp(lo);
+                self.expect_ty(lo_.ty, Ty::INT, lo.pos, Message::new("for-lower-bound"));
+                let hi_ = self.trans_exp(hi);
+                self.expect_ty(hi_.ty, Ty::INT, hi.pos, Message::new("for-upper-bound"));
+                if let (Some(l), Some(h)) = (consteval::eval(lo), consteval::eval(hi)) {
+                    if l.value > h.value {
+                        let msg = Message::new("W0304").arg("lo", l.value).arg("hi", h.value);
+                        let warning = Diagnostic::warning(exp.pos, msg)
+                            .with_label(lo.pos, Message::new("for-lower-bound"))
+                            .with_label(hi.pos, Message::new("for-upper-bound"));
+                        self.warnings.push(warning);
+                    }
+                }
+                let access = self.level.alloc_local(self.escapes.contains(&exp.pos));
+                let counter = translate::simple_var(&access, &self.level);
+                self.venv.begin_scope();
+                self.derive(*var, exp.pos, Ty::INT, "loop indices are always `int`");
+                self.venv.enter(
+                    *var,
+                    EnvEntry::Var {
+                        ty: Ty::INT,
+                        access,
+                        read_only: true,
+                        size: None,
+                        dec: exp.pos,
+                    },
+                );
+                let done = Label::new();
+                let outer = self.break_label.replace(done);
+                let body_ = self.trans_exp(body);
+                self.break_label = outer;
+                self.venv.end_scope();
+                self.expect_ty(body_.ty, Ty::UNIT, body.pos, Message::new("for-body"));
+                ExpTy::unit(translate::for_(counter, lo_.exp, hi_.exp, body_.exp, done))
+            }
+            ExpKind::Break => match self.break_label {
+                Some(done) => ExpTy::unit(translate::break_(done)),
+                None => {
+                    self.error(exp.pos, Message::new("E0307"));
+                    ExpTy::unit(translate::error())
+                }
+            },
+            ExpKind::Let { decs, body } => {
+                self.tenv.begin_scope();
+                self.venv.begin_scope();
+                let vars = decs.iter().rev().filter_map(|dec| match dec {
+                    Dec::Var(var) => Some(Spanned::new(var.name, var.pos)),
+                    _ => None,
+                });
+                self.undeclared.extend(vars);
+                let inits = decs.iter().filter_map(|dec| self.trans_dec(dec)).collect();
+                let body = self.trans_exp(body);
+                self.venv.end_scope();
+                self.tenv.end_scope();
+                ExpTy::new(translate::let_(inits, body.exp), body.ty)
+            }
+            ExpKind::Array { typ, size, init } => {
+                let size_ = self.trans_exp(size);
+                self.expect_ty(size_.ty, Ty::INT, size.pos, Message::new("array-size"));
+                self.check_constant(size, |n| n < 0, |n| Message::new("W0301").arg("size", n));
+                let init_ = self.trans_exp(init);
+                let Some(array_ty) = self.look_type(*typ, exp.pos) else {
+                    return ExpTy::error();
+                };
+                let TyKind::Array { elem, .. } = *self.types.actual_kind(array_ty) else {
+                    self.error(exp.pos, Message::new("E0308").arg("name", typ));
+                    return ExpTy::error();
+                };
+                self.expect_ty(init_.ty, elem, init.pos, Message::new("array-initializer"));
+                ExpTy::new(translate::array(size_.exp, init_.exp), array_ty)
+            }
+        }
+    }
+
+    fn trans_call(&mut self, func: Symbol, args: &[Exp], pos: Span) -> ExpTy {
+        let (arg_exps, arg_tys): (Vec<_>, Vec<_>) = args
+            .iter()
+            .map(|arg| {
+                let ExpTy { exp, ty } = self.trans_exp(arg);
+                (exp, ty)
+            })
+            .unzip();
+        let (formals, result, level, label, dec) = match self.venv.look(func) {
+            Some(EnvEntry::Fun {
+                formals,
+                result,
+                level,
+                label,
+                dec,
+            }) => (formals.clone(), *result, level.clone(), *label, *dec),
+            Some(EnvEntry::Var { .. }) => {
+                self.error(pos, Message::new("E0309").arg("name", func));
+                return ExpTy::error();
+            }
+            None => {
+                self.error(pos, Message::new("E0310").arg("name", func));
+                return ExpTy::error();
+            }
+        };
+        if let Some(dec) = dec {
+            self.bind(func, pos, dec);
+        }
+        if formals.len() != args.len() {
+            let msg = match formals.len() {
+                1 => Message::new("E0311"),
+                n => Message::new("E0312").arg("expected", n),
+            };
+            let msg = msg
+                .arg("name", func)
+                .nested("given", count("given-one", "given", args.len()));
+            self.error(pos, msg);
+        }
+        for ((arg, &ty), &formal) in args.iter().zip(&arg_tys).zip(&formals) {
+            self.expect_ty(ty, formal, arg.pos, Message::new("mismatched-argument"));
+        }
+        let exp = translate::call(level.as_ref(), label, &self.level, arg_exps);
+        ExpTy::new(exp, result)
+    }
+
+    fn trans_op(&mut self, left: &Exp, op: Oper, right: &Exp) -> ExpTy {
+        let left_ = self.trans_exp(left);
+        let right_ = self.trans_exp(right);
+        let (left_ty, right_ty) = (left_.ty, right_.ty);
+        let mut strings = false;
+        match op {
+            Oper::Plus | Oper::Minus | Oper::Times | Oper::Divide | Oper::And | Oper::Or => {
+                self.expect_ty(
+                    left_ty,
+                    Ty::INT,
+                    left.pos,
+                    Message::new("arithmetic-operand"),
+                );
+                self.expect_ty(
+                    right_ty,
+                    Ty::INT,
+                    right.pos,
+                    Message::new("arithmetic-operand"),
+                );
+            }
+            Oper::Lt | Oper::Le | Oper::Gt | Oper::Ge => {
+                let ty = self.types.actual(left_ty);
+                if ty != Ty::INT && ty != Ty::STRING && ty != Ty::ERROR {
+                    let msg = Message::new("E0313").arg("ty", self.name(left_ty));
+                    self.error(left.pos, msg);
+                } else {
+                    self.expect_ty(
+                        right_ty,
+                        left_ty,
+                        right.pos,
+                        Message::new("mismatched-comparison"),
+                    );
+                    strings = ty == Ty::STRING;
+                }
+            }
+            Oper::Eq | Oper::Neq => {
+                let (l, r) = (self.types.actual(left_ty), self.types.actual(right_ty));
+                if l == Ty::UNIT || r == Ty::UNIT {
+                    self.error(left.pos.to(right.pos), Message::new("E0314"));
+                } else if l == Ty::NIL && r == Ty::NIL {
+                    self.error(left.pos.to(right.pos), Message::new("E0315"));
+                } else {
+                    self.expect_ty(
+                        right_ty,
+                        left_ty,
+                        right.pos,
+                        Message::new("mismatched-comparison"),
+                    );
+                    strings = l == Ty::STRING;
+                }
+            }
+        }
+        let exp = if strings {
+            translate::string_op(op, left_.exp, right_.exp)
+        } else {
+            translate::op(op, left_.exp, right_.exp)
+        };
+        ExpTy::new(exp, Ty::INT)
+    }
+
+    fn trans_var(&mut self, var: &Var) -> ExpTy {
+        let exp = match &var.kind {
+            VarKind::Simple(name) => match self.venv.look(*name) {
+                Some(EnvEntry::Var {
+                    ty, access, dec, ..
+                }) => {
+                    let exp = ExpTy::new(translate::simple_var(access, &self.level), *ty);
+                    let dec = *dec;
+                    self.bind(*name, var.pos, dec);
+                    exp
+                }
+                Some(EnvEntry::Fun { .. }) => {
+                    self.error(var.pos, Message::new("E0316").arg("name", name));
+                    ExpTy::error()
+                }
+                None => {
+                    let later = self.undeclared.iter().rev().find(|v| v.node == *name);
+                    match later.map(|v| v.span) {
+                        Some(dec) => {
+                            self.error(var.pos, Message::new("E0330").arg("name", name));
+                            self.label(dec, Message::new("declared-after-use"));
+                        }
+                        None => {
+                            self.error(var.pos, Message::new("E0317").arg("name", name));
+                        }
+                    }
+                    ExpTy::error()
+                }
+            },
+            VarKind::Field(record, field) => {
+                let ExpTy { exp, ty } = self.trans_var(record);
+                match self.types.actual_kind(ty) {
+                    TyKind::Record { fields, .. } => {
+                        match fields.iter().position(|(name, _)| name == field) {
+                            Some(i) => {
+                                let field_ty = fields[i].1;
+                                ExpTy::new(
+                                    translate::field_var(exp, i, *field, &mut self.fragments),
+                                    field_ty,
+                                )
+                            }
+                            None => {
+                                let msg = Message::new("E0318")
+                                    .arg("ty", self.name(ty))
+                                    .arg("field", field);
+                                self.error(var.pos, msg);
+                                ExpTy::error()
+                            }
+                        }
+                    }
+                    TyKind::Error => ExpTy::error(),
+                    _ => {
+                        let msg = Message::new("E0319").arg("ty", self.name(ty));
+                        self.error(record.pos, msg);
+                        ExpTy::error()
+                    }
+                }
+            }
+            VarKind::Subscript(array, index) => {
+                let array_ = self.trans_var(array);
+                let index_ = self.trans_exp(index);
+                self.expect_ty(index_.ty, Ty::INT, index.pos, Message::new("array-index"));
+                self.check_constant(index, |n| n < 0, |n| Message::new("W0302").arg("index", n));
+                if let VarKind::Simple(name) = array.kind {
+                    if let Some(&EnvEntry::Var {
+                        size: Some(size), ..
+                    }) = self.venv.look(name)
+                    {
+                        let msg = |n| Message::new("W0305").arg("index", n).arg("size", size);
+                        self.check_constant(index, |n| n >= size, msg);
+                    }
+                }
+                match *self.types.actual_kind(array_.ty) {
+                    TyKind::Array { elem, .. } => {
+                        ExpTy::new(translate::subscript_var(array_.exp, index_.exp), elem)
+                    }
+                    TyKind::Error => ExpTy::error(),
+                    _ => {
+                        let msg = Message::new("E0320").arg("ty", self.name(array_.ty));
+                        self.error(array.pos, msg);
+                        ExpTy::error()
+                    }
+                }
+            }
+        };
+        self.var_types.insert(var.pos, exp.ty);
+        exp
+    }
+
+    fn look_type(&mut self, name: Symbol, pos: Span) -> Option<Ty> {
+        let ty = self.tenv.look(name).copied();
+        match ty {
+            Some(ty) => {
+                if let Some(&dec) = self.type_decs.get(&ty) {
+                    self.bind(name, pos, dec);
+                }
+            }
+            None => {
+                self.error(pos, Message::new("E0321").arg("name", name));
+            }
+        }
+        ty
+    }
+
+    /// Translates a declaration, returning the initialization it performs,
+    /// if any.
+    fn trans_dec(&mut self, dec: &Dec) -> Option<translate::Exp> {
+        match dec {
+            Dec::Var(var) => {
+                let init = self.trans_var_dec(var);
+                self.undeclared.pop();
+                Some(init)
+            }
+            Dec::Type(group) => {
+                self.trans_type_decs(group);
+                None
+            }
+            Dec::Function(group) => {
+                self.trans_fun_decs(group);
+                None
+            }
+            Dec::Import(import) => {
+                self.error(import.pos, Message::new("E0326").arg("path", import.path));
+                None
+            }
+        }
+    }
+
+    fn trans_var_dec(&mut self, dec: &VarDec) -> translate::Exp {
+        let init = self.trans_exp(&dec.init);
+        let ty = match &dec.typ {
+            Some((typ, pos)) => match self.look_type(*typ, *pos) {
+                Some(declared) => {
+                    if !self.expect_ty(
+                        init.ty,
+                        declared,
+                        dec.init.pos,
+                        Message::new("mismatched-initializer"),
+                    ) {
+                        self.label(*pos, Message::new("because-annotation"));
+                    }
+                    declared
+                }
+                None => Ty::ERROR,
+            },
+            None if self.types.actual(init.ty) == Ty::NIL => {
+                self.error(dec.init.pos, Message::new("E0322").arg("name", dec.name))
+            }
+            None => init.ty,
+        };
+        let rule = match dec.typ {
+            Some(_) => "declared; the initializer must match",
+            None => "inferred from the initializer",
+        };
+        self.derive(dec.name, dec.pos, ty, rule);
+        let escapes = self.escapes.contains(&dec.pos);
+        let size = match &dec.init.kind {
+            ExpKind::Array { size, .. } if !escapes => {
+                consteval::eval(size).map(|c| c.value).filter(|&n| n >= 0)
+            }
+            _ => None,
+        };
+        let access = self.level.alloc_local(escapes);
+        let var = translate::simple_var(&access, &self.level);
+        self.venv.enter(
+            dec.name,
+            EnvEntry::Var {
+                ty,
+                access,
+                read_only: false,
+                size,
+                dec: dec.pos,
+            },
+        );
+        translate::assign(var, init.exp)
+    }
+
+    /// Declares a group of possibly mutually recursive types: names first,
+    /// then their definitions.
+    fn trans_type_decs(&mut self, group: &[TypeDec]) {
+        let names: Vec<_> = group
+            .iter()
+            .map(|dec| Spanned::new(dec.name, dec.pos))
+            .collect();
+        self.check_duplicates(&names, "E0324");
+        let headers: Vec<Ty> = group
+            .iter()
+            .map(|dec| {
+                let ty = self.types.add(TyKind::Name(dec.name, None));
+                self.tenv.enter(dec.name, ty);
+                self.type_decs.insert(ty, dec.pos);
+                ty
+            })
+            .collect();
+        for (dec, &header) in group.iter().zip(&headers) {
+            let ty = match &dec.ty {
+                AstTy::Name(name, pos) => self.look_type(*name, *pos).unwrap_or(Ty::ERROR),
+                AstTy::Array(elem, pos) => {
+                    let elem = self.look_type(*elem, *pos).unwrap_or(Ty::ERROR);
+                    self.types.add(TyKind::Array {
+                        name: dec.name,
+                        elem,
+                    })
+                }
+                AstTy::Record(fields) => {
+                    let names: Vec<_> = fields
+                        .iter()
+                        .map(|field| Spanned::new(field.name, field.pos))
+                        .collect();
+                    self.check_duplicates(&names, "E0327");
+                    let fields = fields
+                        .iter()
+                        .map(|field| {
+                            let ty = self.look_type(field.typ, field.pos).unwrap_or(Ty::ERROR);
+                            (field.name, ty)
+                        })
+                        .collect();
+                    self.types.add(TyKind::Record {
+                        name: dec.name,
+                        fields,
+                    })
+                }
+            };
+            *self.types.kind_mut(header) = TyKind::Name(dec.name, Some(ty));
+        }
+        // A group of plain aliases can loop back on itself without ever
+        // reaching a record or array. Breaking the cycle at its first
+        // declaration reports it once, labelling the other links.
+        for (dec, &header) in group.iter().zip(&headers) {
+            if self.types.actual(header) != Ty::ERROR {
+                continue;
+            }
+            let Some(cycle) = self.alias_cycle(header, group.len()) else {
+                continue;
+            };
+            self.error(dec.pos, Message::new("E0323").arg("name", dec.name));
+            for (&link, &next) in cycle.iter().zip(cycle.iter().cycle().skip(1)).skip(1) {
+                let Some(i) = headers.iter().position(|&h| h == link) else {
+                    continue;
+                };
+                let next = self.name(next);
+                let msg = Message::new("alias-in-cycle")
+                    .arg("name", group[i].name)
+                    .arg("next", next);
+                self.label(group[i].pos, msg);
+            }
+            *self.types.kind_mut(header) = TyKind::Name(dec.name, Some(Ty::ERROR));
+        }
+    }
+
+    /// The aliases followed from `start` until they lead back to it, starting
+    /// with `start`, or `None` if they do not. Only names of the current group
+    /// can be unresolved, so a cycle is at most `group_len` links long.
+    fn alias_cycle(&self, start: Ty, group_len: usize) -> Option<Vec<Ty>> {
+        let mut cycle = vec![start];
+        for _ in 0..group_len {
+            match self.types.kind(*cycle.last().unwrap()) {
+                TyKind::Name(_, Some(next)) if *next == start => return Some(cycle),
+                TyKind::Name(_, Some(next)) => cycle.push(*next),
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    /// Reports every name declared twice in one group of mutually recursive
+    /// declarations, one record type or one parameter list, pointing back at
+    /// its first declaration. Returns whether there were any.
+    fn check_duplicates(&mut self, names: &[Spanned<Symbol>], key: &'static str) -> bool {
+        self.check_repeated(names, key, "first-declared")
+    }
+
+    /// Reports every name that comes twice in `names` with the message `key`,
+    /// labelling its first occurrence with `first`.
+    fn check_repeated(
+        &mut self,
+        names: &[Spanned<Symbol>],
+        key: &'static str,
+        first: &'static str,
+    ) -> bool {
+        let mut found = false;
+        for (i, name) in names.iter().enumerate() {
+            if let Some(earlier) = names[..i].iter().find(|n| n.node == name.node) {
+                self.error(name.span, Message::new(key).arg("name", name.node));
+                self.label(earlier.span, Message::new(first));
+                found = true;
+            }
+        }
+        found
+    }
+
+    /// Declares a group of possibly mutually recursive functions: headers
+    /// first, then bodies.
+    fn trans_fun_decs(&mut self, group: &[FunDec]) {
+        let names: Vec<_> = group
+            .iter()
+            .map(|dec| Spanned::new(dec.name, dec.pos))
+            .collect();
+        self.check_duplicates(&names, "E0325");
+        let mut signatures = Vec::new();
+        for dec in group {
+            let params: Vec<_> = dec
+                .params
+                .iter()
+                .map(|param| Spanned::new(param.name, param.pos))
+                .collect();
+            self.check_duplicates(&params, "E0328");
+            let formals: Vec<Ty> = dec
+                .params
+                .iter()
+                .map(|param| self.look_type(param.typ, param.pos).unwrap_or(Ty::ERROR))
+                .collect();
+            let result = match &dec.result {
+                Some((typ, pos)) => self.look_type(*typ, *pos).unwrap_or(Ty::ERROR),
+                None => Ty::UNIT,
+            };
+            let label = Label::with_prefix(dec.name.as_str());
+            self.proc_spans.insert(label, dec.pos);
+            let escapes: Vec<bool> = dec
+                .params
+                .iter()
+                .map(|param| self.escapes.contains(&param.pos))
+                .collect();
+            let level = Level::new(&self.level, label, &escapes);
+            let params: Vec<String> = formals.iter().map(|&ty| self.name(ty)).collect();
+            self.derivations.push(Derivation {
+                name: dec.name,
+                pos: dec.pos,
+                ty: format!("({}) -> {}", params.join(", "), self.name(result)),
+                rule: "declared parameter and result types",
+            });
+            self.venv.enter(
+                dec.name,
+                EnvEntry::Fun {
+                    formals: formals.clone(),
+                    result,
+                    level: Some(level.clone()),
+                    label,
+                    dec: Some(dec.pos),
+                },
+            );
+            signatures.push((formals, result, level));
+        }
+        for (dec, (formals, result, level)) in group.iter().zip(signatures) {
+            self.venv.begin_scope();
+            for ((param, ty), access) in dec.params.iter().zip(formals).zip(level.formals()) {
+                self.derive(param.name, param.pos, ty, "declared parameter type");
+                self.venv.enter(
+                    param.name,
+                    EnvEntry::Var {
+                        ty,
+                        access,
+                        read_only: false,
+                        size: None,
+                        dec: param.pos,
+                    },
+                );
+            }
+            let outer_level = std::mem::replace(&mut self.level, level);
+            // `break` cannot jump out of a function body.
+            let outer_break = self.break_label.take();
+            let body = self.trans_exp(&dec.body);
+            self.break_label = outer_break;
+            let level = std::mem::replace(&mut self.level, outer_level);
+            self.venv.end_scope();
+            if let Some((_, pos)) = dec.result {
+                if !self.expect_ty(body.ty, result, dec.body.pos, Message::new("function-body")) {
+                    self.label(pos, Message::new("because-result"));
+                }
+            } else {
+                self.expect_ty(
+                    body.ty,
+                    Ty::UNIT,
+                    dec.body.pos,
+                    Message::new("procedure-body"),
+                );
+            }
+            translate::proc_entry_exit(&level, body.exp, dec.result.is_some(), &mut self.fragments);
+        }
+    }
+}
+
+/// How many of something there are, in the catalog phrase `one` when there
+/// is one and in `many`, which shows `{n}`, otherwise.
+fn count(one: &'static str, many: &'static str, n: usize) -> Message {
+    match n {
+        1 => Message::new(one),
+        n => Message::new(many).arg("n", n),
+    }
+}
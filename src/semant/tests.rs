@@ -0,0 +1,544 @@
+use crate::cancel::CancellationToken;
+use crate::parser::parse;
+use crate::semant::Semant;
+use crate::span::Span;
+
+/// Type checks `src`, returning the program's type name or the error messages.
+fn check(src: &str) -> Result<String, Vec<String>> {
+    let exp = parse(src).unwrap_or_else(|e| panic!("failed to parse {src:?}: {e:?}"));
+    let mut semant = Semant::new();
+    let ty = semant.check(&exp);
+    if semant.errors().is_empty() {
+        Ok(semant.types.name(ty))
+    } else {
+        Err(semant.errors().iter().map(|e| e.msg.to_string()).collect())
+    }
+}
+
+fn ok(src: &str, ty: &str) {
+    assert_eq!(check(src), Ok(ty.to_string()), "while checking {src:?}");
+}
+
+fn err(src: &str, msg: &str) {
+    match check(src) {
+        Ok(ty) => panic!("{src:?} type checked as `{ty}`, expected error {msg:?}"),
+        Err(errors) => assert!(
+            errors.iter().any(|e| e == msg),
+            "{src:?} produced {errors:?}, expected {msg:?}"
+        ),
+    }
+}
+
+#[test]
+fn literals_and_arithmetic() {
+    ok("1 + 2 * 3", "int");
+    ok("\"abc\"", "string");
+    ok("()", "()");
+    ok("nil", "nil");
+    ok("(1; \"a\")", "string");
+    err(
+        "1 + \"a\"",
+        "arithmetic operand: expected `int`, found `string`",
+    );
+}
+
+#[test]
+fn comparisons() {
+    ok("\"a\" < \"b\"", "int");
+    ok("let type r = {} var x: r := nil in x = nil end", "int");
+    err(
+        "1 = \"a\"",
+        "mismatched comparison: expected `int`, found `string`",
+    );
+    err("nil = nil", "cannot compare `nil` with `nil`");
+    err("() = ()", "cannot compare values of type `()`");
+    err(
+        "let type r = {} var x: r := nil in x < x end",
+        "cannot order values of type `r`",
+    );
+}
+
+#[test]
+fn appel_test_programs() {
+    // test1: arrays of ints.
+    ok(
+        "let type arrtype = array of int var arr1: arrtype := arrtype [10] of 0 in arr1 end",
+        "arrtype",
+    );
+    // test3: record fields.
+    ok(
+        "let type rectype = {name: string, age: int}
+             var rec1: rectype := rectype {name = \"Nobody\", age = 1000}
+         in rec1.name := \"Somebody\"; rec1 end",
+        "rectype",
+    );
+    // test4: recursive function.
+    ok(
+        "let function nfactor(n: int): int =
+                 if n = 0 then 1 else n * nfactor(n - 1)
+         in nfactor(10) end",
+        "int",
+    );
+    // test5: recursive types.
+    ok(
+        "let type intlist = {hd: int, tl: intlist}
+             type tree = {key: int, children: treelist}
+             type treelist = {hd: tree, tl: treelist}
+             var lis: intlist := intlist {hd = 0, tl = nil}
+         in lis end",
+        "intlist",
+    );
+    // test6: mutually recursive procedures.
+    ok(
+        "let function do_nothing1(a: int, b: string) = do_nothing2(a + 1)
+             function do_nothing2(d: int) = do_nothing1(d, \"str\")
+         in do_nothing1(0, \"str2\") end",
+        "()",
+    );
+}
+
+#[test]
+fn type_equivalence_is_by_declaration() {
+    // test21-style: structurally equal records are different types.
+    err(
+        "let type a = {x: int} type b = {x: int} var v: a := b {x = 1} in v end",
+        "mismatched initializer: expected `a`, found `b`",
+    );
+    err(
+        "let type a = array of int type b = array of int var v: a := b [1] of 0 in v end",
+        "mismatched initializer: expected `a`, found `b`",
+    );
+    // Aliases name the same type.
+    ok(
+        "let type a = {x: int} type b = a var v: a := b {x = 1} in v end",
+        "a",
+    );
+}
+
+#[test]
+fn nil_needs_a_known_record_type() {
+    err(
+        "let var x := nil in x end",
+        "cannot infer the type of `x` from `nil`; add a record type annotation",
+    );
+    err(
+        "let var x: int := nil in x end",
+        "mismatched initializer: expected `int`, found `nil`",
+    );
+    ok(
+        "let type r = {a: int} var x := if 1 then nil else r {a = 1} in x end",
+        "r",
+    );
+    ok(
+        "let type r = {a: int} function f(x: r) = () in f(nil) end",
+        "()",
+    );
+}
+
+#[test]
+fn calls_check_arity_and_arguments() {
+    ok("size(\"abc\")", "int");
+    ok("substring(\"abc\", 0, 1)", "string");
+    ok("atoi(itoa(12), 0)", "int");
+    ok("concat(argv(argc() - 1), getenv(\"HOME\"))", "string");
+    ok("(seed(7); random(6))", "int");
+    ok("(gc_collect(); heap_used())", "int");
+    err("size()", "`size` takes 1 argument, but 0 were given");
+    err("atoi(\"1\")", "`atoi` takes 2 arguments, but 1 was given");
+    err(
+        "substring(\"a\", 1)",
+        "`substring` takes 3 arguments, but 2 were given",
+    );
+    err(
+        "ord(1)",
+        "mismatched argument type: expected `string`, found `int`",
+    );
+    err("f(1)", "undefined function `f`");
+    err(
+        "let var f := 1 in f() end",
+        "`f` is a variable, not a function",
+    );
+    err("print", "`print` is a function, not a variable");
+}
+
+#[test]
+fn break_only_inside_loops() {
+    ok("while 1 do break", "()");
+    ok("for i := 0 to 10 do (if i = 5 then break)", "()");
+    err("break", "`break` outside of a loop");
+    err(
+        "while 1 do let function f() = break in f() end",
+        "`break` outside of a loop",
+    );
+}
+
+#[test]
+fn loops_and_conditionals() {
+    err(
+        "while \"a\" do ()",
+        "`while` condition: expected `int`, found `string`",
+    );
+    err("while 1 do 5", "`while` body: expected `()`, found `int`");
+    err(
+        "for i := 0 to 10 do i := 1",
+        "cannot assign to loop variable `i`",
+    );
+    err(
+        "if 1 then 5",
+        "`if` without `else`: expected `()`, found `int`",
+    );
+    err(
+        "if 1 then 5 else \"a\"",
+        "`if` branches have different types: `int` and `string`",
+    );
+    ok("for i := 0 to 10 do print(chr(i))", "()");
+}
+
+#[test]
+fn records_and_arrays() {
+    err(
+        "let type r = {a: int, b: string} in r {a = 1} end",
+        "record `r` has 2 fields, but 1 was given",
+    );
+    err(
+        "let type r = {a: int} in r {a = 1, b = 2} end",
+        "record `r` has 1 field, but 2 were given",
+    );
+    err(
+        "let type r = {a: int, b: string} in r {b = \"\", a = 1} end",
+        "expected field `a`, found `b`",
+    );
+    err(
+        "let type r = {a: int} var x := r {a = 1} in x.b end",
+        "type `r` has no field `b`",
+    );
+    err("let var x := 1 in x.b end", "type `int` is not a record");
+    err("let var x := 1 in x[0] end", "type `int` is not an array");
+    err(
+        "let type r = {} in r [1] of 0 end",
+        "`r` is not an array type",
+    );
+    err(
+        "let type a = array of int in a {} end",
+        "`a` is not a record type",
+    );
+    err(
+        "let type a = array of int in a [1] of \"\" end",
+        "array initializer: expected `int`, found `string`",
+    );
+}
+
+#[test]
+fn functions() {
+    err(
+        "let function f(): int = \"a\" in f() end",
+        "function body: expected `int`, found `string`",
+    );
+    err(
+        "let function f() = 1 in f() end",
+        "procedure body: expected `()`, found `int`",
+    );
+    err("let function f(a: t) = () in end", "undefined type `t`");
+}
+
+#[test]
+fn scoping() {
+    ok(
+        "let var a := 1 in let var a := \"s\" in a end end",
+        "string",
+    );
+    ok(
+        "let var a := 1 in (let var a := \"s\" in end; a) end",
+        "int",
+    );
+    err("let in x end", "undefined variable `x`");
+    err(
+        "let type a = b type b = a in end",
+        "type `a` is defined in terms of itself",
+    );
+}
+
+#[test]
+fn declaration_groups() {
+    ok(
+        "let type tree = {kids: forest} type forest = array of tree in nil end",
+        "nil",
+    );
+    ok(
+        "let function even(n: int): int = if n = 0 then 1 else odd(n - 1)
+             function odd(n: int): int = if n = 0 then 0 else even(n - 1)
+         in even(4) end",
+        "int",
+    );
+    // A variable between two declarations splits them into separate groups.
+    err(
+        "let type a = b var x := 1 type b = int in end",
+        "undefined type `b`",
+    );
+    err(
+        "let function f() = g() var x := 1 function g() = () in end",
+        "undefined function `g`",
+    );
+    err(
+        "let type a = int type a = string in end",
+        "type `a` is declared twice in one group of type declarations",
+    );
+    err(
+        "let function f() = () function f() = () in end",
+        "function `f` is declared twice in one group of function declarations",
+    );
+    // The same name in separate groups shadows instead.
+    ok(
+        "let type a = int var x: a := 1 type a = string var y: a := \"s\" in y end",
+        "a",
+    );
+}
+
+/// The position of the one error in `src`, and the text and message of its labels.
+fn labels(src: &str) -> (Span, Vec<(&str, String)>) {
+    let mut semant = Semant::new();
+    semant.check(&parse(src).unwrap());
+    let errors = semant.errors();
+    assert_eq!(errors.len(), 1, "{errors:?}");
+    let labels = errors[0].labels.iter();
+    let labels = labels.map(|l| {
+        (
+            &src[l.pos.lo() as usize..l.pos.hi() as usize],
+            l.msg.to_string(),
+        )
+    });
+    (errors[0].pos, labels.collect::<Vec<_>>())
+}
+
+#[test]
+fn declaration_errors_point_at_both_declarations() {
+    let (pos, found) = labels("let type a = b type b = c type c = a in end");
+    assert_eq!(pos, Span::new(4, 14));
+    assert_eq!(
+        found,
+        [
+            ("type b = c", "`b` is an alias of `c`".to_string()),
+            ("type c = a", "`c` is an alias of `a`".to_string()),
+        ]
+    );
+    let (pos, found) = labels("let function f() = () function f() = () in end");
+    assert_eq!(pos, Span::new(22, 39));
+    assert_eq!(
+        found,
+        [("function f() = ()", "first declared here".to_string())]
+    );
+}
+
+/// The warnings for `src`, as the text each points at, its message and the
+/// text of its labels.
+fn warnings(src: &str) -> Vec<(&str, String, Vec<&str>)> {
+    let mut semant = Semant::new();
+    semant.check(&parse(src).unwrap());
+    assert_eq!(semant.errors(), []);
+    let text = |pos: Span| &src[pos.lo() as usize..pos.hi() as usize];
+    let warnings = semant.warnings().iter().map(|w| {
+        let labels = w.labels.iter().map(|l| text(l.pos)).collect();
+        (text(w.pos), w.msg.to_string(), labels)
+    });
+    warnings.collect()
+}
+
+#[test]
+fn constants_that_are_wrong_on_every_run() {
+    let src = "let type a = array of int var x := a [2 - 5] of 0 in x[-1] end";
+    assert_eq!(
+        warnings(src),
+        [
+            (
+                "2 - 5",
+                "array size is always negative: -3".to_string(),
+                vec!["2", "5"]
+            ),
+            (
+                "-1",
+                "array index is always negative: -1".to_string(),
+                vec!["1"]
+            ),
+        ]
+    );
+    assert_eq!(
+        warnings("(if 0 then print(\"a\"); while 1 < 0 do ())"),
+        [
+            ("0", "condition is always false".to_string(), vec![]),
+            (
+                "1 < 0",
+                "condition is always false".to_string(),
+                vec!["1", "0"]
+            ),
+        ]
+    );
+    assert_eq!(
+        warnings("for i := 5 to 2 + 2 do ()"),
+        [(
+            "for i := 5 to 2 + 2 do ()",
+            "`for` loop never runs: 5 is greater than 4".to_string(),
+            vec!["5", "2 + 2"]
+        )]
+    );
+    assert_eq!(warnings("for i := 4 to 4 do ()"), []);
+    // Sizes, subscripts, conditions and bounds that depend on variables, or
+    // only fail at run time, are left to the run.
+    let src = "let type a = array of int var n := 3 var x := a [n - 5] of 0 in
+                 x[1 / 0]; while 1 do break; if n then (); for i := n to 1 do () end";
+    assert_eq!(warnings(src), []);
+}
+
+#[test]
+fn constant_subscripts_past_a_constant_size() {
+    let src = "let type a = array of int var x := a [3] of 0 in x[2]; x[1 + 2] end";
+    assert_eq!(
+        warnings(src),
+        [(
+            "1 + 2",
+            "array index is always out of bounds: 3 is not less than the size 3".to_string(),
+            vec!["1", "2"]
+        )]
+    );
+    // The size is forgotten once the variable is assigned, and not known for
+    // a variable a nested function could assign.
+    let src = "let type a = array of int var x := a [3] of 0 in x := a [9] of 0; x[5] end";
+    assert_eq!(warnings(src), []);
+    let src = "let type a = array of int var x := a [3] of 0
+                   function grow() = x := a [9] of 0
+               in grow(); x[5] end";
+    assert_eq!(warnings(src), []);
+}
+
+#[test]
+fn record_types_declare_each_field_once() {
+    let (pos, found) = labels("let type r = {a: int, b: int, a: string} in end");
+    assert_eq!(pos, Span::new(30, 39));
+    assert_eq!(found, [("a: int", "first declared here".to_string())]);
+    err(
+        "let type r = {a: int, a: string} in end",
+        "field `a` is declared twice in one record type",
+    );
+}
+
+#[test]
+fn parameter_lists_declare_each_name_once() {
+    let (pos, found) = labels("let function f(x: int, x: int) = () in f(1, 2) end");
+    assert_eq!(pos, Span::new(23, 29));
+    assert_eq!(found, [("x: int", "first declared here".to_string())]);
+    err(
+        "let function f(x: int, y: int, x: string) = () in end",
+        "parameter `x` is declared twice in one parameter list",
+    );
+}
+
+#[test]
+fn record_expressions_give_each_field_once() {
+    let src = "let type r = {a: int, b: int} in r {a = 1, a = 2} end";
+    let (pos, found) = labels(src);
+    assert_eq!(&src[pos.lo() as usize..pos.hi() as usize], "a = 2");
+    assert_eq!(found, [("a = 1", "first given here".to_string())]);
+    err(
+        "let type r = {a: int} in r {a = 1, a = 2} end",
+        "field `a` is given twice in one record expression",
+    );
+}
+
+#[test]
+fn variables_used_before_their_declaration() {
+    let src = "let var a := b var b := 1 in a end";
+    let (pos, found) = labels(src);
+    assert_eq!(&src[pos.lo() as usize..pos.hi() as usize], "b");
+    assert_eq!(
+        found,
+        [("var b := 1", "declared here, after the use".to_string())]
+    );
+    err(
+        "let var a := b var b := 1 in a end",
+        "variable `b` is used before it is declared",
+    );
+    err(
+        "let var a := a + 1 in a end",
+        "variable `a` is used before it is declared",
+    );
+    err(
+        "let function f(): int = b var b := 1 in f() end",
+        "variable `b` is used before it is declared",
+    );
+    // An outer variable of the same name is used instead, and a name
+    // declared in no enclosing `let` is undefined.
+    ok(
+        "let var a := 1 in let var b := a var a := \"s\" in a end end",
+        "string",
+    );
+    err("let var a := c in a end", "undefined variable `c`");
+    err(
+        "let var a := let var b := 1 in b end in b end",
+        "undefined variable `b`",
+    );
+}
+
+#[test]
+fn errors_do_not_cascade() {
+    assert_eq!(
+        check("let var x := y in x + 1; x.f; size(x) end"),
+        Err(vec!["undefined variable `y`".to_string()])
+    );
+}
+
+#[test]
+fn type_graph_highlights_alias_cycles() {
+    let exp = parse(
+        "let type a = b type b = a
+             type list = {hd: int, tl: list}
+             type arr = array of undefined
+         in end",
+    )
+    .unwrap();
+    let dot = super::type_graph::dot(&exp);
+    for line in [
+        "  t2 [label=\"a\", shape=ellipse, color=red];",
+        "  t2 -> t3 [style=dashed, color=red];",
+        "  t4 [label=\"list\", shape=box];",
+        "  t4 -> t0 [label=\"hd\"];",
+        "  t4 -> t4 [label=\"tl\"];",
+        "  t6 [label=\"undefined\", shape=ellipse, style=dashed];",
+        "  t5 -> t6 [label=\"[]\"];",
+    ] {
+        assert!(dot.lines().any(|l| l == line), "{line:?} not in\n{dot}");
+    }
+}
+
+#[test]
+fn built_programs_type_check() {
+    use crate::ast::build::*;
+    use crate::ast::Oper;
+    let program = let_(
+        [
+            types([type_dec("arr", array_ty("int"))]),
+            var_dec("a", None, array("arr", int(3), int(0))),
+        ],
+        [for_(
+            "i",
+            int(0),
+            int(2),
+            assign(
+                subscript(simple("a"), var("i")),
+                op(var("i"), Oper::Times, int(2)),
+            ),
+        )],
+    );
+    let mut semant = Semant::new();
+    let ty = semant.check(&program);
+    assert!(semant.errors().is_empty(), "{:?}", semant.errors());
+    assert_eq!(semant.types.name(ty), "()");
+}
+
+#[test]
+fn a_cancelled_check_gives_up_without_errors() {
+    let program = parse("let var x: int := \"s\" in x + nil end").unwrap();
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+    let mut semant = Semant::new().with_cancellation(cancel);
+    semant.check(&program);
+    assert!(semant.errors().is_empty(), "{:?}", semant.errors());
+}
@@ -0,0 +1,170 @@
+//! Graphviz rendering of the type declarations in a program, for
+//! `--emit type-graph`.
+//!
+//! Works on the AST alone so that it can be drawn for programs that fail to
+//! type check. Names are resolved with the same scoping as `trans_type_decs`:
+//! every declaration gets its own node, and names that resolve to nothing are
+//! drawn dashed. Alias chains that loop back on themselves, which the type
+//! checker rejects, are drawn in red.
+
+use std::fmt::Write;
+
+use super::env::ScopedTable;
+use crate::ast::visit::{walk_dec, walk_exp, Visitor};
+use crate::ast::{Dec, Exp, ExpKind, Ty};
+use crate::graph;
+use crate::symbol::Symbol;
+
+enum Shape {
+    Builtin,
+    Alias,
+    Record,
+    Array,
+    Undefined,
+}
+
+struct Node {
+    name: Symbol,
+    shape: Shape,
+    /// Target of an alias declaration.
+    alias: Option<usize>,
+}
+
+enum Edge {
+    Alias,
+    Field(Symbol),
+    Element,
+}
+
+struct TypeGraph {
+    nodes: Vec<Node>,
+    edges: Vec<(usize, usize, Edge)>,
+    tenv: ScopedTable<usize>,
+}
+
+/// The declared types of `exp` as a DOT digraph.
+pub(crate) fn dot(exp: &Exp) -> String {
+    let mut graph = TypeGraph {
+        nodes: Vec::new(),
+        edges: Vec::new(),
+        tenv: ScopedTable::new(),
+    };
+    for name in ["int", "string"] {
+        let id = graph.node(Symbol::intern(name), Shape::Builtin);
+        graph.tenv.enter(Symbol::intern(name), id);
+    }
+    graph.visit_exp(exp);
+    graph.render()
+}
+
+impl TypeGraph {
+    fn node(&mut self, name: Symbol, shape: Shape) -> usize {
+        self.nodes.push(Node {
+            name,
+            shape,
+            alias: None,
+        });
+        self.nodes.len() - 1
+    }
+
+    fn look(&mut self, name: Symbol) -> usize {
+        match self.tenv.look(name) {
+            Some(&id) => id,
+            None => {
+                let id = self.node(name, Shape::Undefined);
+                self.tenv.enter(name, id);
+                id
+            }
+        }
+    }
+
+    /// Marks every node on a cycle of aliases.
+    fn alias_cycles(&self) -> Vec<bool> {
+        let aliases: Vec<Vec<usize>> = self
+            .nodes
+            .iter()
+            .map(|n| n.alias.into_iter().collect())
+            .collect();
+        graph::on_cycle(&aliases)
+    }
+
+    fn render(&self) -> String {
+        let in_cycle = self.alias_cycles();
+        let mut out = String::from("digraph types {\n");
+        for (id, node) in self.nodes.iter().enumerate() {
+            let attrs = match node.shape {
+                Shape::Builtin => "shape=plaintext",
+                Shape::Alias => "shape=ellipse",
+                Shape::Record => "shape=box",
+                Shape::Array => "shape=box, peripheries=2",
+                Shape::Undefined => "shape=ellipse, style=dashed",
+            };
+            let color = if in_cycle[id] { ", color=red" } else { "" };
+            let _ = writeln!(out, "  t{id} [label=\"{}\", {attrs}{color}];", node.name);
+        }
+        for (from, to, edge) in &self.edges {
+            let attrs = match edge {
+                Edge::Alias if in_cycle[*from] => "style=dashed, color=red".to_string(),
+                Edge::Alias => "style=dashed".to_string(),
+                Edge::Field(name) => format!("label=\"{name}\""),
+                Edge::Element => "label=\"[]\"".to_string(),
+            };
+            let _ = writeln!(out, "  t{from} -> t{to} [{attrs}];");
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+impl Visitor for TypeGraph {
+    fn visit_exp(&mut self, exp: &Exp) {
+        if let ExpKind::Let { .. } = exp.kind {
+            self.tenv.begin_scope();
+            walk_exp(self, exp);
+            self.tenv.end_scope();
+        } else {
+            walk_exp(self, exp);
+        }
+    }
+
+    fn visit_dec(&mut self, dec: &Dec) {
+        match dec {
+            Dec::Type(group) => {
+                // Headers first, so the group may refer to itself.
+                let ids: Vec<usize> = group
+                    .iter()
+                    .map(|t| {
+                        let shape = match t.ty {
+                            Ty::Name(..) => Shape::Alias,
+                            Ty::Record(_) => Shape::Record,
+                            Ty::Array(..) => Shape::Array,
+                        };
+                        let id = self.node(t.name, shape);
+                        self.tenv.enter(t.name, id);
+                        id
+                    })
+                    .collect();
+                for (t, from) in group.iter().zip(ids) {
+                    match &t.ty {
+                        Ty::Name(name, _) => {
+                            let to = self.look(*name);
+                            self.nodes[from].alias = Some(to);
+                            self.edges.push((from, to, Edge::Alias));
+                        }
+                        Ty::Record(fields) => {
+                            for field in fields {
+                                let to = self.look(field.typ);
+                                self.edges.push((from, to, Edge::Field(field.name)));
+                            }
+                        }
+                        Ty::Array(elem, _) => {
+                            let to = self.look(*elem);
+                            self.edges.push((from, to, Edge::Element));
+                        }
+                    }
+                }
+            }
+            _ => walk_dec(self, dec),
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! `SharedStr`, an immutable string that is cheap to clone, for text that
+//! several phases hold on to: file names, string literals and the values
+//! filled into diagnostics. It either borrows a string that lives for the
+//! rest of the process, such as an interned `Symbol`'s, or counts
+//! references to one on the heap, so a clone never copies the text.
+
+#[cfg(test)]
+mod tests;
+
+use std::borrow::Borrow;
+use std::ffi::OsStr;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::symbol::Symbol;
+use crate::temp::Label;
+
+#[derive(Clone)]
+pub(crate) struct SharedStr(Repr);
+
+#[derive(Clone)]
+enum Repr {
+    Static(&'static str),
+    Heap(Arc<str>),
+}
+
+impl SharedStr {
+    /// `s`, without copying it.
+    pub(crate) const fn from_static(s: &'static str) -> SharedStr {
+        SharedStr(Repr::Static(s))
+    }
+
+    pub(crate) fn as_str(&self) -> &str {
+        match &self.0 {
+            Repr::Static(s) => s,
+            Repr::Heap(s) => s,
+        }
+    }
+}
+
+impl Default for SharedStr {
+    fn default() -> SharedStr {
+        SharedStr::from_static("")
+    }
+}
+
+impl Deref for SharedStr {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for SharedStr {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<OsStr> for SharedStr {
+    fn as_ref(&self) -> &OsStr {
+        self.as_str().as_ref()
+    }
+}
+
+impl AsRef<Path> for SharedStr {
+    fn as_ref(&self) -> &Path {
+        self.as_str().as_ref()
+    }
+}
+
+impl Borrow<str> for SharedStr {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for SharedStr {
+    fn from(s: &str) -> SharedStr {
+        SharedStr(Repr::Heap(Arc::from(s)))
+    }
+}
+
+impl From<String> for SharedStr {
+    fn from(s: String) -> SharedStr {
+        SharedStr(Repr::Heap(Arc::from(s)))
+    }
+}
+
+impl From<&String> for SharedStr {
+    fn from(s: &String) -> SharedStr {
+        SharedStr::from(s.as_str())
+    }
+}
+
+impl From<&SharedStr> for SharedStr {
+    fn from(s: &SharedStr) -> SharedStr {
+        s.clone()
+    }
+}
+
+impl From<Symbol> for SharedStr {
+    /// The symbol's text, which is interned for good and so not copied.
+    fn from(sym: Symbol) -> SharedStr {
+        SharedStr::from_static(sym.as_str())
+    }
+}
+
+impl From<&Symbol> for SharedStr {
+    fn from(sym: &Symbol) -> SharedStr {
+        SharedStr::from(*sym)
+    }
+}
+
+impl From<Label> for SharedStr {
+    fn from(label: Label) -> SharedStr {
+        SharedStr::from_static(label.name())
+    }
+}
+
+/// Numbers and characters, as diagnostics show them.
+macro_rules! from_display {
+    ($($ty:ty),*) => {
+        $(impl From<$ty> for SharedStr {
+            fn from(value: $ty) -> SharedStr {
+                SharedStr::from(value.to_string())
+            }
+        })*
+    };
+}
+
+from_display!(i64, u64, i32, u32, usize, u8, char);
+
+impl PartialEq for SharedStr {
+    fn eq(&self, other: &SharedStr) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for SharedStr {}
+
+impl PartialEq<str> for SharedStr {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for SharedStr {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialOrd for SharedStr {
+    fn partial_cmp(&self, other: &SharedStr) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SharedStr {
+    fn cmp(&self, other: &SharedStr) -> std::cmp::Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl Hash for SharedStr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl fmt::Debug for SharedStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for SharedStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
@@ -0,0 +1,22 @@
+use crate::alloc_counter::measure;
+use crate::shared_str::SharedStr;
+use crate::symbol::Symbol;
+
+#[test]
+fn clones_share_the_text() {
+    let s = SharedStr::from(String::from("a.tig"));
+    let (copy, stats) = measure(|| s.clone());
+    assert_eq!(stats.allocations, 0);
+    assert_eq!(copy, s);
+    assert!(std::ptr::eq(copy.as_ptr(), s.as_ptr()));
+}
+
+#[test]
+fn symbols_are_not_copied() {
+    let sym = Symbol::intern("hello");
+    let (s, stats) = measure(|| SharedStr::from(sym));
+    assert_eq!(stats.allocations, 0);
+    assert!(std::ptr::eq(s.as_ptr(), sym.as_str().as_ptr()));
+    assert_eq!(s, "hello");
+    assert_eq!(format!("{s} {s:?}"), "hello \"hello\"");
+}
@@ -0,0 +1,306 @@
+#![allow(dead_code)]
+
+//! Maps byte offsets in a source file to human readable positions.
+//!
+//! Files are read as bytes: a UTF-8 byte order mark is dropped and invalid
+//! UTF-8 is replaced with U+FFFD, recording where each bad sequence was so
+//! it can be reported instead of failing to read the file.
+//!
+//! Lines end at `\n`, `\r\n` or a lone `\r`. How columns are counted is a
+//! `ColumnPolicy` of the file, so every consumer agrees: the command line
+//! counts characters, while editors speaking LSP count UTF-16 code units.
+//!
+//! Generated text (emitted phases, assembly) is built with `\n` and given
+//! the line endings chosen by a `NewlinePolicy` on output.
+
+#[cfg(test)]
+mod tests;
+
+use std::borrow::Cow;
+
+use crate::diagnostics::Message;
+use crate::shared_str::SharedStr;
+use crate::span::{FileId, Span};
+
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// An invalid UTF-8 sequence found by `SourceFile::from_bytes`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct DecodeError {
+    /// The replacement character in the decoded text.
+    pub(crate) pos: Span,
+    /// Offset of the offending byte in the file as read.
+    pub(crate) byte: usize,
+}
+
+impl DecodeError {
+    pub(crate) fn message(&self) -> Message {
+        Message::new("E0001").arg("byte", self.byte)
+    }
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+/// What a column counts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ColumnUnit {
+    Byte,
+    Char,
+    Utf16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct ColumnPolicy {
+    pub(crate) unit: ColumnUnit,
+    /// Tabs advance to the next multiple of this many columns; 1 counts a
+    /// tab like any other character.
+    pub(crate) tab_width: u32,
+}
+
+impl ColumnPolicy {
+    /// Columns as printed in command-line diagnostics.
+    pub(crate) const CLI: ColumnPolicy = ColumnPolicy {
+        unit: ColumnUnit::Char,
+        tab_width: 1,
+    };
+    /// Columns as the Language Server Protocol's default encoding expects.
+    pub(crate) const LSP: ColumnPolicy = ColumnPolicy {
+        unit: ColumnUnit::Utf16,
+        tab_width: 1,
+    };
+
+    pub(crate) fn with_tab_width(self, tab_width: u32) -> ColumnPolicy {
+        ColumnPolicy {
+            tab_width: tab_width.max(1),
+            ..self
+        }
+    }
+
+    /// Width of `text`, which starts at the beginning of a line.
+    fn width(self, text: &str) -> usize {
+        let tab = self.tab_width as usize;
+        text.chars().fold(0, |col, c| match c {
+            '\t' if tab > 1 => (col / tab + 1) * tab,
+            c => {
+                col + match self.unit {
+                    ColumnUnit::Byte => c.len_utf8(),
+                    ColumnUnit::Char => 1,
+                    ColumnUnit::Utf16 => c.len_utf16(),
+                }
+            }
+        })
+    }
+}
+
+/// Line endings for text the toolchain writes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum NewlinePolicy {
+    /// Whatever the input file uses.
+    #[default]
+    Preserve,
+    Lf,
+    Crlf,
+}
+
+impl NewlinePolicy {
+    pub(crate) fn parse(s: &str) -> Option<NewlinePolicy> {
+        match s {
+            "preserve" => Some(NewlinePolicy::Preserve),
+            "lf" => Some(NewlinePolicy::Lf),
+            "crlf" => Some(NewlinePolicy::Crlf),
+            _ => None,
+        }
+    }
+
+    /// Gives `text` the line endings of this policy; `Preserve` follows
+    /// `file`, or uses `\n` when there is no input file.
+    pub(crate) fn apply<'a>(self, text: &'a str, file: Option<&SourceFile>) -> Cow<'a, str> {
+        let crlf = match self {
+            NewlinePolicy::Preserve => file.is_some_and(SourceFile::uses_crlf),
+            NewlinePolicy::Lf => false,
+            NewlinePolicy::Crlf => true,
+        };
+        let text = if text.contains("\r\n") {
+            Cow::Owned(text.replace("\r\n", "\n"))
+        } else {
+            Cow::Borrowed(text)
+        };
+        if crlf {
+            Cow::Owned(text.replace('\n', "\r\n"))
+        } else {
+            text
+        }
+    }
+}
+
+/// Every source file read in one run, each named by the `FileId` of the
+/// spans into it.
+#[derive(Default)]
+pub(crate) struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub(crate) fn new() -> SourceMap {
+        SourceMap::default()
+    }
+
+    /// Adds `file`, which the first time is `FileId::MAIN`.
+    pub(crate) fn add(&mut self, file: SourceFile) -> FileId {
+        self.files.push(file);
+        FileId::new(self.files.len() - 1)
+    }
+
+    pub(crate) fn get(&self, id: FileId) -> &SourceFile {
+        &self.files[id.index()]
+    }
+
+    /// The file the run started from.
+    pub(crate) fn main(&self) -> &SourceFile {
+        self.get(FileId::MAIN)
+    }
+
+    /// The text `span` covers, in whichever file it is in.
+    pub(crate) fn span_to_snippet(&self, span: Span) -> &str {
+        self.get(span.file).span_to_snippet(span)
+    }
+}
+
+impl From<SourceFile> for SourceMap {
+    /// A map of only `file`, which is `FileId::MAIN`.
+    fn from(file: SourceFile) -> SourceMap {
+        SourceMap { files: vec![file] }
+    }
+}
+
+pub(crate) struct SourceFile {
+    name: SharedStr,
+    src: String,
+    /// Byte offset of the first character of every line.
+    line_starts: Vec<u32>,
+    columns: ColumnPolicy,
+}
+
+impl SourceFile {
+    pub(crate) fn new(name: impl Into<SharedStr>, src: impl Into<String>) -> SourceFile {
+        let src = src.into();
+        let bytes = src.as_bytes();
+        let line_starts = std::iter::once(0)
+            .chain(bytes.iter().enumerate().filter_map(|(i, &b)| {
+                let ends_line = b == b'\n' || (b == b'\r' && bytes.get(i + 1) != Some(&b'\n'));
+                ends_line.then_some(i as u32 + 1)
+            }))
+            .collect();
+        SourceFile {
+            name: name.into(),
+            src,
+            line_starts,
+            columns: ColumnPolicy::CLI,
+        }
+    }
+
+    /// Counts columns according to `columns` instead of `ColumnPolicy::CLI`.
+    pub(crate) fn with_columns(mut self, columns: ColumnPolicy) -> SourceFile {
+        self.columns = columns;
+        self
+    }
+
+    /// Decodes `bytes` as UTF-8, skipping a leading byte order mark and
+    /// replacing invalid sequences. Offsets in the returned file refer to
+    /// the decoded text.
+    pub(crate) fn from_bytes(
+        name: impl Into<SharedStr>,
+        bytes: &[u8],
+    ) -> (SourceFile, Vec<DecodeError>) {
+        let body = bytes.strip_prefix(BOM).unwrap_or(bytes);
+        let mut byte = bytes.len() - body.len();
+        let mut src = String::with_capacity(body.len());
+        let mut errors = Vec::new();
+        for chunk in body.utf8_chunks() {
+            src.push_str(chunk.valid());
+            byte += chunk.valid().len();
+            if !chunk.invalid().is_empty() {
+                let lo = src.len() as u32;
+                src.push(char::REPLACEMENT_CHARACTER);
+                errors.push(DecodeError {
+                    pos: Span::new(lo, src.len() as u32),
+                    byte,
+                });
+                byte += chunk.invalid().len();
+            }
+        }
+        (SourceFile::new(name, src), errors)
+    }
+
+    pub(crate) fn name(&self) -> &SharedStr {
+        &self.name
+    }
+
+    pub(crate) fn src(&self) -> &str {
+        &self.src
+    }
+
+    /// Whether the first line ends with `\r\n`.
+    pub(crate) fn uses_crlf(&self) -> bool {
+        self.src
+            .find('\n')
+            .is_some_and(|i| self.src[..i].ends_with('\r'))
+    }
+
+    pub(crate) fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// 1-based line and column of byte offset `pos`, counting columns
+    /// according to the file's `ColumnPolicy`.
+    pub(crate) fn lookup_line_col(&self, pos: u32) -> (usize, usize) {
+        let line = self.line_index(pos);
+        let start = self.line_starts[line] as usize;
+        let col = self.columns.width(&self.src[start..pos as usize]);
+        (line + 1, col + 1)
+    }
+
+    /// Byte offset of the 1-based `line` and `col`, counted as in
+    /// `lookup_line_col`. Positions past the end of a line or of the file
+    /// are clamped to it.
+    pub(crate) fn lookup_offset(&self, line: usize, col: usize) -> u32 {
+        let Some(&start) = self.line_starts.get(line.max(1) - 1) else {
+            return self.src.len() as u32;
+        };
+        let text = self.line_text(line.max(1));
+        let offset = text
+            .char_indices()
+            .map(|(i, _)| i)
+            .find(|&i| self.columns.width(&text[..i]) + 1 >= col)
+            .unwrap_or(text.len());
+        start + offset as u32
+    }
+
+    pub(crate) fn span_to_snippet(&self, span: Span) -> &str {
+        &self.src[span.lo() as usize..span.hi() as usize]
+    }
+
+    /// Byte offset where the 1-based `line` starts.
+    pub(crate) fn line_start(&self, line: usize) -> u32 {
+        self.line_starts[line - 1]
+    }
+
+    /// Text of the 1-based `line`, without its line terminator.
+    pub(crate) fn line_text(&self, line: usize) -> &str {
+        let start = self.line_starts[line - 1] as usize;
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(self.src.len(), |&next| next as usize);
+        self.src[start..end].trim_end_matches(['\n', '\r'])
+    }
+
+    fn line_index(&self, pos: u32) -> usize {
+        debug_assert!(pos as usize <= self.src.len());
+        self.line_starts.partition_point(|&start| start <= pos) - 1
+    }
+}
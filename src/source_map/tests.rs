@@ -0,0 +1,95 @@
+use crate::source_map::{ColumnPolicy, ColumnUnit, NewlinePolicy, SourceFile};
+use crate::span::Span;
+
+#[test]
+fn line_col_lookup() {
+    let file = SourceFile::new("t.tig", "let\n  var x := 1\nin x end");
+    assert_eq!(file.lookup_line_col(0), (1, 1));
+    assert_eq!(file.lookup_line_col(3), (1, 4));
+    assert_eq!(file.lookup_line_col(4), (2, 1));
+    assert_eq!(file.lookup_line_col(10), (2, 7));
+    assert_eq!(file.lookup_line_col(25), (3, 9));
+    assert_eq!(file.line_count(), 3);
+}
+
+#[test]
+fn columns_count_characters() {
+    let file = SourceFile::new("t.tig", "\"héllo\" x");
+    assert_eq!(file.lookup_line_col(9), (1, 9));
+}
+
+#[test]
+fn snippets_and_lines() {
+    let file = SourceFile::new("t.tig", "a := 1\r\nb := a + 2\n");
+    assert_eq!(file.span_to_snippet(Span::new(8, 9)), "b");
+    assert_eq!(file.line_text(1), "a := 1");
+    assert_eq!(file.line_text(2), "b := a + 2");
+    assert_eq!(file.line_text(3), "");
+}
+
+#[test]
+fn bytes_are_decoded_without_bom() {
+    let (file, errors) = SourceFile::from_bytes("t.tig", b"\xEF\xBB\xBFlet in end");
+    assert_eq!(file.src(), "let in end");
+    assert!(errors.is_empty());
+}
+
+#[test]
+fn invalid_utf8_is_replaced_and_reported() {
+    let (file, errors) = SourceFile::from_bytes("t.tig", b"\xEF\xBB\xBF\"a\xFF\"\n\xC3x");
+    assert_eq!(file.src(), "\"a\u{FFFD}\"\n\u{FFFD}x");
+    let reported: Vec<(String, (usize, usize))> = errors
+        .iter()
+        .map(|e| (e.to_string(), file.lookup_line_col(e.pos.lo())))
+        .collect();
+    assert_eq!(
+        reported,
+        [
+            ("invalid UTF-8 at byte 5".to_string(), (1, 3)),
+            ("invalid UTF-8 at byte 8".to_string(), (2, 1)),
+        ]
+    );
+}
+
+#[test]
+fn crlf_and_lone_cr_end_lines() {
+    let file = SourceFile::new("t.tig", "a\r\nb\rc\n");
+    assert_eq!(file.line_count(), 4);
+    assert_eq!(file.lookup_line_col(3), (2, 1));
+    assert_eq!(file.lookup_line_col(5), (3, 1));
+    assert_eq!(file.line_text(1), "a");
+    assert_eq!(file.line_text(2), "b");
+}
+
+#[test]
+fn column_policies() {
+    let src = "\t\"é😀\" x";
+    let x = src.find('x').unwrap() as u32;
+    let col = |policy| {
+        SourceFile::new("t.tig", src)
+            .with_columns(policy)
+            .lookup_line_col(x)
+            .1
+    };
+    assert_eq!(col(ColumnPolicy::CLI), 7);
+    assert_eq!(col(ColumnPolicy::LSP), 8);
+    assert_eq!(col(ColumnPolicy::CLI.with_tab_width(4)), 10);
+    let bytes = ColumnPolicy {
+        unit: ColumnUnit::Byte,
+        tab_width: 1,
+    };
+    assert_eq!(col(bytes), 11);
+}
+
+#[test]
+fn newline_policies() {
+    let unix = SourceFile::new("u.tig", "a\nb\r\n");
+    let windows = SourceFile::new("w.tig", "a\r\nb\n");
+    let text = "x\ny\r\n";
+    let apply = |policy: NewlinePolicy, file| policy.apply(text, file).into_owned();
+    assert_eq!(apply(NewlinePolicy::Preserve, Some(&unix)), "x\ny\n");
+    assert_eq!(apply(NewlinePolicy::Preserve, Some(&windows)), "x\r\ny\r\n");
+    assert_eq!(apply(NewlinePolicy::Preserve, None), "x\ny\n");
+    assert_eq!(apply(NewlinePolicy::Lf, Some(&windows)), "x\ny\n");
+    assert_eq!(apply(NewlinePolicy::Crlf, Some(&unix)), "x\r\ny\r\n");
+}
@@ -0,0 +1,139 @@
+#![allow(dead_code)]
+
+//! Spans: where in which source file something came from.
+//!
+//! A `Span` is a range of byte offsets into one file, named by the `FileId`
+//! a `SourceMap` gave it. Tokens, syntax trees and diagnostics all carry
+//! spans, so positions from different files can be told apart once more
+//! than one file is read. A program read on its own is `FileId::MAIN`.
+
+#[cfg(test)]
+mod tests;
+
+/// A source file among those read in one run, as handed out by
+/// `SourceMap::add`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub(crate) struct FileId(u32);
+
+impl FileId {
+    /// The file of a program read on its own, and the first one added to a
+    /// `SourceMap`.
+    pub(crate) const MAIN: FileId = FileId(0);
+
+    pub(crate) fn new(index: usize) -> FileId {
+        FileId(index as u32)
+    }
+
+    pub(crate) fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// The bytes `lo..hi` of `file`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub(crate) struct Span {
+    pub(crate) file: FileId,
+    lo: u32,
+    hi: u32,
+}
+
+impl Span {
+    /// The bytes `lo..hi` of `FileId::MAIN`.
+    pub(crate) fn new(lo: u32, hi: u32) -> Span {
+        Span::in_file(FileId::MAIN, lo, hi)
+    }
+
+    pub(crate) fn in_file(file: FileId, lo: u32, hi: u32) -> Span {
+        Span { file, lo, hi }
+    }
+
+    /// Span covering both `self` and `other`, which must be in the same
+    /// file.
+    pub(crate) fn to(&self, other: Span) -> Span {
+        debug_assert_eq!(self.file, other.file, "merging spans of different files");
+        Span {
+            file: self.file,
+            lo: self.lo.min(other.lo),
+            hi: self.hi.max(other.hi),
+        }
+    }
+
+    /// The empty span where `self` starts.
+    pub(crate) fn shrink_to_lo(&self) -> Span {
+        Span {
+            hi: self.lo,
+            ..*self
+        }
+    }
+
+    /// The empty span where `self` ends.
+    pub(crate) fn shrink_to_hi(&self) -> Span {
+        Span {
+            lo: self.hi,
+            ..*self
+        }
+    }
+
+    /// `self` with both offsets moved by `f`, as when text before it is
+    /// edited.
+    pub(crate) fn map(&self, f: impl Fn(u32) -> u32) -> Span {
+        Span {
+            file: self.file,
+            lo: f(self.lo),
+            hi: f(self.hi),
+        }
+    }
+
+    pub(crate) fn lo(&self) -> u32 {
+        self.lo
+    }
+
+    pub(crate) fn hi(&self) -> u32 {
+        self.hi
+    }
+
+    pub(crate) fn len(&self) -> u32 {
+        self.hi.saturating_sub(self.lo)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether `other` lies within `self`.
+    pub(crate) fn contains(&self, other: Span) -> bool {
+        self.file == other.file && self.lo <= other.lo && other.hi <= self.hi
+    }
+}
+
+/// The span covering every span of `spans`, or `None` if there are none.
+pub(crate) fn merge(spans: impl IntoIterator<Item = Span>) -> Option<Span> {
+    spans.into_iter().reduce(|a, b| a.to(b))
+}
+
+/// A value with the span it was read from.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub(crate) struct Spanned<T> {
+    pub(crate) node: T,
+    pub(crate) span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub(crate) fn new(node: T, span: Span) -> Spanned<T> {
+        Spanned { node, span }
+    }
+
+    pub(crate) fn map<U>(self, f: impl FnOnce(T) -> U) -> Spanned<U> {
+        Spanned {
+            node: f(self.node),
+            span: self.span,
+        }
+    }
+
+    pub(crate) fn as_ref(&self) -> Spanned<&T> {
+        Spanned {
+            node: &self.node,
+            span: self.span,
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use crate::span::{merge, FileId, Span, Spanned};
+
+#[test]
+fn merging_spans() {
+    let a = Span::new(4, 7);
+    let b = Span::new(10, 12);
+    assert_eq!(a.to(b), Span::new(4, 12));
+    assert_eq!(b.to(a), Span::new(4, 12));
+    assert_eq!(merge([b, Span::new(0, 1), a]), Some(Span::new(0, 12)));
+    assert_eq!(merge([]), None);
+    assert_eq!(a.shrink_to_lo(), Span::new(4, 4));
+    assert_eq!(a.shrink_to_hi(), Span::new(7, 7));
+    assert!(Span::new(0, 12).contains(a));
+    assert!(!a.contains(b));
+}
+
+#[test]
+fn spans_keep_their_file() {
+    let other = FileId::new(1);
+    let a = Span::in_file(other, 2, 5);
+    assert_ne!(a, Span::new(2, 5));
+    assert_eq!(a.to(Span::in_file(other, 8, 9)).file, other);
+    assert_eq!(a.map(|p| p + 10), Span::in_file(other, 12, 15));
+    assert!(!Span::new(0, 10).contains(a));
+
+    let name = Spanned::new("x", a);
+    assert_eq!(name.map(str::len), Spanned::new(1, a));
+}
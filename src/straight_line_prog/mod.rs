@@ -37,7 +37,7 @@ impl AExpList<'_> {
         &'a self,
         context: Option<Box<Context<'b>>>,
         collector: &mut Vec<u32>,
-    ) -> Option<Box<Context>>
+    ) -> Option<Box<Context<'a>>>
     where
         'b: 'a,
     {
@@ -74,7 +74,7 @@ pub(crate) struct Context<'a> {
 impl Context<'_> {
     pub(crate) fn find(&self, id: &str) -> Option<u32> {
         if self.value.0 == id {
-            return Some(self.value.1);
+            Some(self.value.1)
         } else if let Some(ctx) = self.next.as_ref() {
             ctx.find(id)
         } else {
@@ -87,7 +87,7 @@ pub(crate) trait Interp {
     fn interp<'a, 'b>(
         &'a self,
         context: Option<Box<Context<'b>>>,
-    ) -> (Option<u32>, Option<Box<Context>>)
+    ) -> (Option<u32>, Option<Box<Context<'a>>>)
     where
         'b: 'a;
 }
@@ -96,13 +96,13 @@ impl Interp for AExp<'_> {
     fn interp<'a, 'b>(
         &'a self,
         context: Option<Box<Context<'b>>>,
-    ) -> (Option<u32>, Option<Box<Context>>)
+    ) -> (Option<u32>, Option<Box<Context<'a>>>)
     where
         'b: 'a,
     {
         match self {
             AExp::Id(id) => {
-                let v = context.as_ref().map_or(None, |v| v.find(id));
+                let v = context.as_ref().and_then(|v| v.find(id));
                 (v, context)
             }
             AExp::Num(n) => (Some(*n), context),
@@ -125,7 +125,7 @@ impl Interp for AStm<'_> {
     fn interp<'a, 'b>(
         &'a self,
         context: Option<Box<Context<'b>>>,
-    ) -> (Option<u32>, Option<Box<Context>>)
+    ) -> (Option<u32>, Option<Box<Context<'a>>>)
     where
         'b: 'a,
     {
@@ -159,3 +159,37 @@ impl Interp for AStm<'_> {
         }
     }
 }
+
+/// Interprets the sample program from chapter 1 and dumps the final context.
+pub(crate) fn demo() {
+    let prog = AStm::Compound(
+        &AStm::Compound(
+            &AStm::Assign("a", &AExp::Op(&AExp::Num(5), ABinop::Plus, &AExp::Num(3))),
+            &AStm::Compound(
+                &AStm::Assign(
+                    "b",
+                    &AExp::Eseq(
+                        &AStm::Print(&AExpList::Pair(
+                            &AExp::Id("a"),
+                            &AExpList::Last(&AExp::Op(
+                                &AExp::Id("a"),
+                                ABinop::Minus,
+                                &AExp::Num(1),
+                            )),
+                        )),
+                        &AExp::Op(&AExp::Num(10), ABinop::Times, &AExp::Id("a")),
+                    ),
+                ),
+                &AStm::Print(&AExpList::Last(&AExp::Id("b"))),
+            ),
+        ),
+        &AStm::Assign("a", &AExp::Num(50)),
+    );
+    let (_, context) = prog.interp(None);
+    println!("response: {context:?}",);
+    if let Some(context) = context {
+        println!("a: {:?}", context.find("a"));
+        println!("b: {:?}", context.find("b"));
+        println!("c: {:?}", context.find("c"));
+    }
+}
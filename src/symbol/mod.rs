@@ -0,0 +1,128 @@
+//! Interned strings. A `Symbol` is a small copyable handle; equal strings
+//! always intern to the same symbol, so comparing names is an integer compare.
+//! Symbols live for the rest of the process, so only names are interned.
+//!
+//! A `ByteStr` holds the contents of a string literal, which are bytes
+//! rather than text. Literals are many and rarely repeat, and an editor
+//! session lexes new ones on every keystroke, so they are not interned:
+//! a `ByteStr` counts references to its bytes, like a `SharedStr`, and they
+//! are freed with the last tree that holds them.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex, OnceLock};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) struct Symbol(u32);
+
+/// The contents of a string literal, whose `\ddd` escapes can make bytes
+/// that are not UTF-8. Clones share the bytes.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) struct ByteStr(Arc<[u8]>);
+
+struct Interner<T: ?Sized + 'static> {
+    ids: HashMap<&'static T, u32>,
+    strings: Vec<&'static T>,
+}
+
+impl<T: ?Sized + Eq + Hash> Default for Interner<T> {
+    fn default() -> Interner<T> {
+        Interner {
+            ids: HashMap::new(),
+            strings: Vec::new(),
+        }
+    }
+}
+
+impl<T: ?Sized + Eq + Hash> Interner<T> {
+    /// The id of `s`, leaking a copy of it the first time.
+    fn intern(&mut self, s: &T, leak: impl FnOnce(&T) -> &'static T) -> u32 {
+        if let Some(&id) = self.ids.get(s) {
+            return id;
+        }
+        // Interned strings live for the rest of the process, which lets
+        // `as_str` hand out plain `&'static str`s.
+        let s = leak(s);
+        let id = self.strings.len() as u32;
+        self.strings.push(s);
+        self.ids.insert(s, id);
+        id
+    }
+}
+
+fn interner() -> &'static Mutex<Interner<str>> {
+    static INTERNER: OnceLock<Mutex<Interner<str>>> = OnceLock::new();
+    INTERNER.get_or_init(Default::default)
+}
+
+impl Symbol {
+    pub(crate) fn intern(s: &str) -> Symbol {
+        let mut interner = interner().lock().expect("interner lock poisoned");
+        Symbol(interner.intern(s, |s| Box::leak(s.into())))
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        interner().lock().expect("interner lock poisoned").strings[self.0 as usize]
+    }
+}
+
+impl ByteStr {
+    pub(crate) fn new(bytes: &[u8]) -> ByteStr {
+        ByteStr(Arc::from(bytes))
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// The bytes as text, with those that are not UTF-8 replaced.
+    pub(crate) fn to_str_lossy(&self) -> std::borrow::Cow<'_, str> {
+        String::from_utf8_lossy(self.as_bytes())
+    }
+}
+
+impl From<&str> for ByteStr {
+    fn from(s: &str) -> ByteStr {
+        ByteStr::new(s.as_bytes())
+    }
+}
+
+impl From<Symbol> for ByteStr {
+    fn from(sym: Symbol) -> ByteStr {
+        ByteStr::from(sym.as_str())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Quoted like a `str`, with the bytes that are not UTF-8 as `\xNN`.
+impl fmt::Debug for ByteStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"")?;
+        for chunk in self.as_bytes().utf8_chunks() {
+            let valid = format!("{:?}", chunk.valid());
+            f.write_str(&valid[1..valid.len() - 1])?;
+            for b in chunk.invalid() {
+                write!(f, "\\x{b:02x}")?;
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+impl fmt::Display for ByteStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_str_lossy())
+    }
+}
@@ -0,0 +1,108 @@
+#![allow(dead_code)]
+
+//! Temporaries and labels, the `Temp` module of chapter 6: abstract names
+//! for values held in registers and for static memory addresses.
+//!
+//! Counters are per thread, so every test numbers its temporaries from the
+//! same starting point.
+
+use std::cell::Cell;
+use std::fmt;
+
+use crate::symbol::Symbol;
+
+/// A value that will live in a register. Numbers below `Temp::FIRST` are
+/// the machine registers described in `frame`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) struct Temp(u32);
+
+thread_local! {
+    static NEXT_TEMP: Cell<u32> = const { Cell::new(Temp::FIRST) };
+    static NEXT_LABEL: Cell<u32> = const { Cell::new(0) };
+}
+
+impl Temp {
+    const FIRST: u32 = 100;
+
+    /// A fresh temporary, distinct from every other one on this thread.
+    pub(crate) fn new() -> Temp {
+        NEXT_TEMP.with(|next| {
+            let n = next.get();
+            next.set(n + 1);
+            Temp(n)
+        })
+    }
+
+    /// The temporary standing for machine register `n`.
+    pub(crate) const fn register(n: u32) -> Temp {
+        assert!(n < Temp::FIRST);
+        Temp(n)
+    }
+
+    pub(crate) fn is_register(self) -> bool {
+        self.0 < Temp::FIRST
+    }
+
+    pub(crate) fn index(self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Debug for Temp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "t{}", self.0)
+    }
+}
+
+impl fmt::Display for Temp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "t{}", self.0)
+    }
+}
+
+/// A machine-language location whose address is yet to be decided.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) struct Label(Symbol);
+
+impl Label {
+    /// A fresh label `L<n>`.
+    pub(crate) fn new() -> Label {
+        Label(Symbol::intern(&format!("L{}", next_label())))
+    }
+
+    /// A fresh label `<prefix>.<n>`, e.g. for a function named `prefix`.
+    /// Tiger identifiers can't contain dots, so these never clash with
+    /// `named` runtime functions or `L<n>` labels.
+    pub(crate) fn with_prefix(prefix: &str) -> Label {
+        Label(Symbol::intern(&format!("{prefix}.{}", next_label())))
+    }
+
+    /// The label with the given assembly-language name, e.g. a function's.
+    pub(crate) fn named(name: &str) -> Label {
+        Label(Symbol::intern(name))
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        self.0.as_str()
+    }
+}
+
+fn next_label() -> u32 {
+    NEXT_LABEL.with(|next| {
+        let n = next.get();
+        next.set(n + 1);
+        n
+    })
+}
+
+impl fmt::Debug for Label {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
@@ -0,0 +1,240 @@
+//! Randomized testing of the lexer and parser: generators for token
+//! streams and for arbitrary input, and the properties they are checked
+//! against. Hand-written cases only cover what their author thought of;
+//! these also catch, say, two tokens with their kinds swapped.
+//!
+//! The tests run a few hundred cases from fixed seeds, so failures are
+//! reproducible. For a longer run, try
+//! `TIGER_FUZZ_ITERATIONS=1000000 cargo test fuzz -- --ignored`, with
+//! `TIGER_FUZZ_SEED` to start somewhere else.
+
+#[cfg(test)]
+mod tests;
+
+use crate::cst;
+use crate::lexer::{LexerConfig, StringReader, TokenKind};
+use crate::parser;
+
+/// A small xorshift generator; the quality is plenty for test inputs.
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Rng {
+        // Zero is a fixed point of xorshift.
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A number in `0..n`.
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    pub(crate) fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len())]
+    }
+}
+
+/// Kinds with a fixed spelling, as `TokenKind::text` gives them.
+const FIXED: &[TokenKind] = &[
+    TokenKind::COMMA,
+    TokenKind::COLON,
+    TokenKind::SEMICOLON,
+    TokenKind::LPAREN,
+    TokenKind::RPAREN,
+    TokenKind::LBRACK,
+    TokenKind::RBRACK,
+    TokenKind::LCURLY,
+    TokenKind::RCURLY,
+    TokenKind::DOT,
+    TokenKind::ASSIGN,
+    TokenKind::PLUS,
+    TokenKind::MINUS,
+    TokenKind::TIMES,
+    TokenKind::DIVIDE,
+    TokenKind::PERCENT,
+    TokenKind::EQ,
+    TokenKind::NEQ,
+    TokenKind::LT,
+    TokenKind::LE,
+    TokenKind::GT,
+    TokenKind::GE,
+    TokenKind::AND,
+    TokenKind::OR,
+    TokenKind::ARRAY,
+    TokenKind::IF,
+    TokenKind::THEN,
+    TokenKind::ELSE,
+    TokenKind::WHILE,
+    TokenKind::FOR,
+    TokenKind::TO,
+    TokenKind::DO,
+    TokenKind::LET,
+    TokenKind::IN,
+    TokenKind::END,
+    TokenKind::OF,
+    TokenKind::BREAK,
+    TokenKind::FUNCTION,
+    TokenKind::VAR,
+    TokenKind::TYPE,
+    TokenKind::NIL,
+];
+
+/// Generates `n` tokens with the text each should be lexed from, not
+/// counting the final `EOF`.
+pub(crate) fn tokens(rng: &mut Rng, n: usize) -> Vec<(TokenKind, String)> {
+    (0..n).map(|_| token(rng)).collect()
+}
+
+fn token(rng: &mut Rng) -> (TokenKind, String) {
+    const LETTERS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    const REST: &[u8] = b"abcxyzXYZ0189_";
+    const ESCAPES: &[&str] = &["\\n", "\\t", "\\\"", "\\\\", "\\065", "\\^A", "\\ \n \\"];
+    match rng.below(10) {
+        0..=4 => {
+            let kind = FIXED[rng.below(FIXED.len())].clone();
+            let text = kind.text().expect("fixed tokens have a spelling");
+            (kind, text.to_string())
+        }
+        5 | 6 => {
+            let mut id = String::from(rng.pick(LETTERS) as char);
+            for _ in 0..rng.below(8) {
+                id.push(rng.pick(REST) as char);
+            }
+            // Keywords are covered by `FIXED`.
+            match FIXED.iter().any(|k| k.text() == Some(id.as_str())) {
+                true => (TokenKind::ID, format!("{id}_")),
+                false => (TokenKind::ID, id),
+            }
+        }
+        7 => (TokenKind::INT, (rng.next_u64() % 100_000).to_string()),
+        8 => {
+            let mut s = String::from("\"");
+            for _ in 0..rng.below(6) {
+                match rng.below(3) {
+                    0 => s.push_str(rng.pick(ESCAPES)),
+                    _ => s.push(rng.pick(REST) as char),
+                }
+            }
+            s.push('"');
+            (TokenKind::STRING, s)
+        }
+        _ => {
+            let body = match rng.below(3) {
+                0 => " a /* nested */ b ",
+                1 => "*",
+                _ => " note ",
+            };
+            (TokenKind::COMMENT, format!("/*{body}*/"))
+        }
+    }
+}
+
+/// `tokens` as source, each followed by some whitespace so that no two run
+/// together.
+pub(crate) fn to_source(rng: &mut Rng, tokens: &[(TokenKind, String)]) -> String {
+    const SPACE: &[&str] = &[" ", "  ", "\n", "\t", "\r\n", " \n  "];
+    let mut src = String::new();
+    for (_, text) in tokens {
+        src.push_str(text);
+        src.push_str(rng.pick(SPACE));
+    }
+    src
+}
+
+/// Arbitrary text of up to `max_len` characters, mostly the ones Tiger
+/// gives a meaning to, so that comments, strings and operators show up
+/// half-finished and run into each other.
+pub(crate) fn input(rng: &mut Rng, max_len: usize) -> String {
+    const CHARS: &[char] = &[
+        '/', '*', '"', '\\', '^', '{', '}', '(', ')', '[', ']', ':', '=', '<', '>', '-', '+', '.',
+        ',', ';', '&', '|', '%', ' ', '\n', '\r', '\t', 'a', 'z', '_', '0', '7', '9', 'e', 'E',
+        '\0', '$', 'é', '😀', '\u{feff}',
+    ];
+    const WORDS: &[&str] = &[
+        "let", "in", "end", "if", "then", "/*", "*/", "\\0", "1e+", "1.",
+    ];
+    let len = rng.below(max_len + 1);
+    let mut s = String::new();
+    while s.chars().count() < len {
+        match rng.below(8) {
+            0 => s.push_str(rng.pick(WORDS)),
+            _ => s.push(rng.pick(CHARS)),
+        }
+    }
+    s
+}
+
+/// Checks that lexing `src` losslessly terminates with tokens that tile it
+/// exactly: each non-empty, each starting where the last ended, and the
+/// final `EOF` empty at the end of the input. A panic in the lexer is left
+/// to fail the test.
+pub(crate) fn check_lexer(src: &str) -> Result<(), String> {
+    let mut reader = StringReader::new(src).with_config(LexerConfig::LOSSLESS);
+    let mut end = 0;
+    // Every token but `EOF` covers at least one byte.
+    for _ in 0..=src.len() {
+        let token = reader.next_token();
+        let pos = *token.pos();
+        if pos.lo() != end {
+            return Err(format!(
+                "{:?} at {pos:?} does not start at {end}",
+                token.kind()
+            ));
+        }
+        if token.kind() == &TokenKind::EOF {
+            return match pos.hi() as usize == src.len() {
+                true => Ok(()),
+                false => Err(format!("`EOF` at {pos:?} before the end of the input")),
+            };
+        }
+        if pos.hi() <= pos.lo() {
+            return Err(format!("{:?} at {pos:?} is empty", token.kind()));
+        }
+        end = pos.hi();
+    }
+    Err("the lexer did not reach `EOF`".to_string())
+}
+
+/// Checks that parsing `src` produces a result, with every diagnostic
+/// inside the input.
+pub(crate) fn check_parser(src: &str) -> Result<(), String> {
+    let parsed = parser::parse_reporting(src);
+    match parsed
+        .diagnostics
+        .iter()
+        .find(|d| d.pos.lo() > d.pos.hi() || d.pos.hi() as usize > src.len())
+    {
+        Some(d) => Err(format!("{:?} is outside the input: {}", d.pos, d.msg)),
+        None => Ok(()),
+    }
+}
+
+/// Checks that the concrete syntax tree of `src` holds exactly its text,
+/// and that a program `parser` accepts has no `Error` node in it.
+pub(crate) fn check_cst(src: &str) -> Result<(), String> {
+    let root = cst::parse(src).root;
+    let text = root.text();
+    if text != src {
+        return Err(format!("the tree holds {text:?}"));
+    }
+    if parser::parse(src).is_err() {
+        return Ok(());
+    }
+    match root
+        .descendants()
+        .find(|node| node.kind() == cst::NodeKind::Error)
+    {
+        Some(error) => Err(format!(
+            "a valid program has an error node at {:?}",
+            error.span()
+        )),
+        None => Ok(()),
+    }
+}
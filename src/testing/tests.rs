@@ -0,0 +1,88 @@
+use super::{check_cst, check_lexer, check_parser, input, to_source, tokens, Rng};
+use crate::lexer::{StringReader, TokenKind};
+
+/// Reports the input a property failed on, escaped so it can be pasted
+/// into a test.
+fn check(src: &str, result: Result<(), String>) {
+    if let Err(e) = result {
+        panic!("{e}\ninput: {src:?}");
+    }
+}
+
+#[test]
+fn generated_tokens_lex_back() {
+    for seed in 0..300 {
+        let mut rng = Rng::new(seed);
+        let n = rng.below(40);
+        let expected = tokens(&mut rng, n);
+        let src = to_source(&mut rng, &expected);
+        let mut reader = StringReader::new(&src);
+        let lexed: Vec<_> = reader
+            .by_ref()
+            .map(|t| {
+                (
+                    t.kind().clone(),
+                    src[t.pos().lo() as usize..t.pos().hi() as usize].to_string(),
+                )
+            })
+            .collect();
+        let mut expected = expected;
+        expected.push((TokenKind::EOF, String::new()));
+        assert_eq!(lexed, expected, "input: {src:?}");
+        assert_eq!(reader.errors(), [], "input: {src:?}");
+    }
+}
+
+#[test]
+fn arbitrary_input_lexes_into_tiles() {
+    for seed in 0..500 {
+        let src = input(&mut Rng::new(seed), 60);
+        check(&src, check_lexer(&src));
+    }
+}
+
+#[test]
+fn arbitrary_input_builds_lossless_trees() {
+    for seed in 0..300 {
+        let src = input(&mut Rng::new(seed), 60);
+        check(&src, check_cst(&src));
+    }
+}
+
+#[test]
+fn arbitrary_input_parses() {
+    for seed in 0..300 {
+        let src = input(&mut Rng::new(seed), 60);
+        check(&src, check_parser(&src));
+    }
+}
+
+#[test]
+fn generated_token_streams_parse() {
+    for seed in 0..300 {
+        let mut rng = Rng::new(seed);
+        let n = rng.below(40);
+        let generated = tokens(&mut rng, n);
+        let src = to_source(&mut rng, &generated);
+        check(&src, check_parser(&src).and_then(|()| check_cst(&src)));
+    }
+}
+
+#[test]
+#[ignore = "runs for as long as TIGER_FUZZ_ITERATIONS asks"]
+fn fuzz() {
+    let var = |name: &str, default: u64| {
+        std::env::var(name)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default)
+    };
+    let seed = var("TIGER_FUZZ_SEED", 1_000);
+    for seed in seed..seed + var("TIGER_FUZZ_ITERATIONS", 100_000) {
+        let src = input(&mut Rng::new(seed), 200);
+        let checked = check_lexer(&src)
+            .and_then(|()| check_parser(&src))
+            .and_then(|()| check_cst(&src));
+        check(&src, checked);
+    }
+}
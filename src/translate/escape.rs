@@ -0,0 +1,88 @@
+//! Escape analysis, the `FindEscape` module of chapter 6.
+//!
+//! A variable escapes if it is used inside a function nested deeper than the
+//! one declaring it; such variables must live in the frame so the nested
+//! function can reach them through static links. Everything else can be
+//! kept in a temporary.
+//!
+//! The book records escapes in mutable fields of the AST. Here they are
+//! returned as the set of declarations that escape, identified by the span
+//! of the `VarDec`, the parameter's `Field` or the `for` expression.
+
+use std::collections::HashSet;
+
+use crate::ast::visit::{walk_exp, walk_var, Visitor};
+use crate::ast::{Dec, Exp, ExpKind, Var, VarKind};
+use crate::semant::ScopedTable;
+use crate::span::Span;
+
+pub(crate) fn find_escapes(exp: &Exp) -> HashSet<Span> {
+    let mut finder = FindEscape {
+        env: ScopedTable::new(),
+        depth: 0,
+        escapes: HashSet::new(),
+    };
+    finder.visit_exp(exp);
+    finder.escapes
+}
+
+struct FindEscape {
+    /// The function depth and declaration of every variable in scope.
+    env: ScopedTable<(usize, Span)>,
+    depth: usize,
+    escapes: HashSet<Span>,
+}
+
+impl Visitor for FindEscape {
+    fn visit_exp(&mut self, exp: &Exp) {
+        match &exp.kind {
+            ExpKind::For { var, lo, hi, body } => {
+                self.visit_exp(lo);
+                self.visit_exp(hi);
+                self.env.begin_scope();
+                self.env.enter(*var, (self.depth, exp.pos));
+                self.visit_exp(body);
+                self.env.end_scope();
+            }
+            ExpKind::Let { .. } => {
+                self.env.begin_scope();
+                walk_exp(self, exp);
+                self.env.end_scope();
+            }
+            _ => walk_exp(self, exp),
+        }
+    }
+
+    fn visit_var(&mut self, var: &Var) {
+        if let VarKind::Simple(name) = var.kind {
+            if let Some(&(depth, dec)) = self.env.look(name) {
+                if depth < self.depth {
+                    self.escapes.insert(dec);
+                }
+            }
+        }
+        walk_var(self, var);
+    }
+
+    fn visit_dec(&mut self, dec: &Dec) {
+        match dec {
+            Dec::Var(v) => {
+                self.visit_exp(&v.init);
+                self.env.enter(v.name, (self.depth, v.pos));
+            }
+            Dec::Type(_) | Dec::Import(_) => {}
+            Dec::Function(group) => {
+                for f in group {
+                    self.depth += 1;
+                    self.env.begin_scope();
+                    for param in &f.params {
+                        self.env.enter(param.name, (self.depth, param.pos));
+                    }
+                    self.visit_exp(&f.body);
+                    self.env.end_scope();
+                    self.depth -= 1;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,529 @@
+#![allow(dead_code)]
+
+//! Translation to intermediate code, the `Translate` module of chapter 7.
+//!
+//! The type checker calls these functions as it goes, so each one receives
+//! the already translated subexpressions. Nesting levels and static links
+//! are handled here; the machine-specific layout is left to `frame`.
+
+pub(crate) mod escape;
+#[cfg(test)]
+mod tests;
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::ast::Oper;
+use crate::frame::{self, Frame, FP, RV, WORD_SIZE};
+use crate::ir::{self, BinOp, RelOp, Stm};
+use crate::symbol::{ByteStr, Symbol};
+use crate::temp::{Label, Temp};
+
+/// A function nesting level: its frame and the level it is declared in.
+#[derive(Clone)]
+pub(crate) struct Level(Rc<LevelData>);
+
+struct LevelData {
+    frame: RefCell<Frame>,
+    /// `None` for the outermost level, which holds only the runtime.
+    parent: Option<Level>,
+}
+
+impl Level {
+    pub(crate) fn outermost() -> Level {
+        Level(Rc::new(LevelData {
+            frame: RefCell::new(Frame::new(Label::named("outermost"), &[])),
+            parent: None,
+        }))
+    }
+
+    /// A level for a function declared in `parent`. The static link is
+    /// added as an escaping first formal.
+    pub(crate) fn new(parent: &Level, name: Label, escapes: &[bool]) -> Level {
+        let escapes: Vec<bool> = std::iter::once(true)
+            .chain(escapes.iter().copied())
+            .collect();
+        Level(Rc::new(LevelData {
+            frame: RefCell::new(Frame::new(name, &escapes)),
+            parent: Some(parent.clone()),
+        }))
+    }
+
+    pub(crate) fn name(&self) -> Label {
+        self.0.frame.borrow().name()
+    }
+
+    /// The formals as seen by the function, without the static link.
+    pub(crate) fn formals(&self) -> Vec<Access> {
+        self.0.frame.borrow().formals()[1..]
+            .iter()
+            .map(|&access| Access {
+                level: self.clone(),
+                access,
+            })
+            .collect()
+    }
+
+    pub(crate) fn alloc_local(&self, escape: bool) -> Access {
+        Access {
+            level: self.clone(),
+            access: self.0.frame.borrow_mut().alloc_local(escape),
+        }
+    }
+
+    /// The address of the frame of `target`, an enclosing level, as seen
+    /// from code running at this level.
+    fn frame_of(&self, target: &Level) -> ir::Exp {
+        let mut fp = ir::Exp::Temp(FP);
+        let mut level = self;
+        while level != target {
+            let static_link = level.0.frame.borrow().formals()[0];
+            fp = frame::exp(static_link, fp);
+            level = level
+                .0
+                .parent
+                .as_ref()
+                .expect("variables and functions are in scope of their level");
+        }
+        fp
+    }
+}
+
+impl PartialEq for Level {
+    fn eq(&self, other: &Level) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Level({})", self.name())
+    }
+}
+
+/// A variable's location, together with the level declaring it.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Access {
+    level: Level,
+    access: frame::Access,
+}
+
+/// A translated expression, in whichever form is most convenient for what
+/// produced it.
+pub(crate) enum Exp {
+    /// An expression with a value.
+    Ex(ir::Exp),
+    /// A statement, with no value.
+    Nx(Stm),
+    /// A condition: given the true and false labels, a statement jumping
+    /// to one of them.
+    Cx(Box<dyn FnOnce(Label, Label) -> Stm>),
+}
+
+impl Exp {
+    pub(crate) fn un_ex(self) -> ir::Exp {
+        match self {
+            Exp::Ex(exp) => exp,
+            Exp::Nx(stm) => ir::Exp::eseq(stm, ir::Exp::Const(0)),
+            Exp::Cx(gen_stm) => {
+                let r = Temp::new();
+                let (t, f) = (Label::new(), Label::new());
+                ir::Exp::eseq(
+                    ir::seq([
+                        Stm::mov(ir::Exp::Temp(r), ir::Exp::Const(1)),
+                        gen_stm(t, f),
+                        Stm::Label(f),
+                        Stm::mov(ir::Exp::Temp(r), ir::Exp::Const(0)),
+                        Stm::Label(t),
+                    ]),
+                    ir::Exp::Temp(r),
+                )
+            }
+        }
+    }
+
+    pub(crate) fn un_nx(self) -> Stm {
+        match self {
+            Exp::Ex(exp) => Stm::exp(exp),
+            Exp::Nx(stm) => stm,
+            Exp::Cx(gen_stm) => {
+                let join = Label::new();
+                ir::seq([gen_stm(join, join), Stm::Label(join)])
+            }
+        }
+    }
+
+    pub(crate) fn un_cx(self, t: Label, f: Label) -> Stm {
+        match self {
+            Exp::Ex(ir::Exp::Const(0)) => Stm::jump(f),
+            Exp::Ex(ir::Exp::Const(_)) => Stm::jump(t),
+            Exp::Ex(exp) => Stm::cjump(RelOp::Ne, exp, ir::Exp::Const(0), t, f),
+            // Only reachable after a type error.
+            Exp::Nx(stm) => ir::seq([stm, Stm::jump(f)]),
+            Exp::Cx(gen_stm) => gen_stm(t, f),
+        }
+    }
+}
+
+/// A piece of the program that the back end emits on its own.
+#[derive(Debug)]
+pub(crate) enum Fragment {
+    Proc { body: Stm, frame: Frame },
+    String(Label, ByteStr),
+}
+
+impl fmt::Display for Fragment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fragment::Proc { body, frame } => {
+                writeln!(f, "PROC {} (frame size {})", frame.name(), frame.size())?;
+                writeln!(f, "{body}")
+            }
+            Fragment::String(label, s) => writeln!(f, "STRING {label} {s:?}"),
+        }
+    }
+}
+
+/// Stands in for expressions that failed to type check.
+pub(crate) fn error() -> Exp {
+    Exp::Ex(ir::Exp::Const(0))
+}
+
+/// The value of expressions that produce none.
+pub(crate) fn unit() -> Exp {
+    Exp::Ex(ir::Exp::Const(0))
+}
+
+pub(crate) fn nil() -> Exp {
+    Exp::Ex(ir::Exp::Const(0))
+}
+
+pub(crate) fn int(n: i64) -> Exp {
+    Exp::Ex(ir::Exp::Const(n))
+}
+
+/// A string literal, stored as a fragment: a length word, then the bytes.
+pub(crate) fn string(s: ByteStr, fragments: &mut Vec<Fragment>) -> Exp {
+    let label = Label::new();
+    fragments.push(Fragment::String(label, s));
+    Exp::Ex(ir::Exp::Name(label))
+}
+
+/// A variable declared at `access`, used from code at `level`.
+pub(crate) fn simple_var(access: &Access, level: &Level) -> Exp {
+    Exp::Ex(frame::exp(access.access, level.frame_of(&access.level)))
+}
+
+/// Field number `index` of a record, named `name`. A `nil` record calls
+/// the runtime's error handler with the name.
+pub(crate) fn field_var(
+    record: Exp,
+    index: usize,
+    name: Symbol,
+    fragments: &mut Vec<Fragment>,
+) -> Exp {
+    let base = Temp::new();
+    let (ok, fail) = (Label::new(), Label::new());
+    let name = string(ByteStr::from(name), fragments).un_ex();
+    Exp::Ex(ir::Exp::eseq(
+        ir::seq([
+            Stm::mov(ir::Exp::Temp(base), record.un_ex()),
+            Stm::cjump(RelOp::Ne, ir::Exp::Temp(base), ir::Exp::Const(0), ok, fail),
+            Stm::Label(fail),
+            Stm::exp(frame::external_call("tig_nilError", vec![name])),
+            Stm::Label(ok),
+        ]),
+        ir::Exp::offset(ir::Exp::Temp(base), index as i64 * WORD_SIZE),
+    ))
+}
+
+/// An array element. Arrays store their length in the word before the
+/// first element; out-of-bounds indices call the runtime's error handler.
+pub(crate) fn subscript_var(array: Exp, index: Exp) -> Exp {
+    let (base, i) = (Temp::new(), Temp::new());
+    let (ok, fail) = (Label::new(), Label::new());
+    let length = ir::Exp::offset(ir::Exp::Temp(base), -WORD_SIZE);
+    Exp::Ex(ir::Exp::eseq(
+        ir::seq([
+            Stm::mov(ir::Exp::Temp(base), array.un_ex()),
+            Stm::mov(ir::Exp::Temp(i), index.un_ex()),
+            // Unsigned, so that negative indices are out of bounds too.
+            Stm::cjump(RelOp::ULt, ir::Exp::Temp(i), length, ok, fail),
+            Stm::Label(fail),
+            Stm::exp(frame::external_call(
+                "tig_indexError",
+                vec![ir::Exp::Temp(i)],
+            )),
+            Stm::Label(ok),
+        ]),
+        ir::Exp::mem(frame::word_index(ir::Exp::Temp(base), ir::Exp::Temp(i))),
+    ))
+}
+
+/// A binary operator on integers. `&` and `|` short-circuit.
+pub(crate) fn op(op: Oper, left: Exp, right: Exp) -> Exp {
+    let relop = match op {
+        Oper::Plus | Oper::Minus | Oper::Times | Oper::Divide => {
+            let op = match op {
+                Oper::Plus => BinOp::Plus,
+                Oper::Minus => BinOp::Minus,
+                Oper::Times => BinOp::Mul,
+                _ => return Exp::Ex(divide(left.un_ex(), right.un_ex())),
+            };
+            return Exp::Ex(ir::Exp::binop(op, left.un_ex(), right.un_ex()));
+        }
+        Oper::And => {
+            return Exp::Cx(Box::new(move |t, f| {
+                let z = Label::new();
+                ir::seq([left.un_cx(z, f), Stm::Label(z), right.un_cx(t, f)])
+            }))
+        }
+        Oper::Or => {
+            return Exp::Cx(Box::new(move |t, f| {
+                let z = Label::new();
+                ir::seq([left.un_cx(t, z), Stm::Label(z), right.un_cx(t, f)])
+            }))
+        }
+        Oper::Eq => RelOp::Eq,
+        Oper::Neq => RelOp::Ne,
+        Oper::Lt => RelOp::Lt,
+        Oper::Le => RelOp::Le,
+        Oper::Gt => RelOp::Gt,
+        Oper::Ge => RelOp::Ge,
+    };
+    Exp::Cx(Box::new(move |t, f| {
+        Stm::cjump(relop, left.un_ex(), right.un_ex(), t, f)
+    }))
+}
+
+/// `left / right`. A divisor that is not a constant is checked first:
+/// zero calls the runtime's error handler instead of trapping, and -1
+/// negates, wrapping as the interpreters do rather than trapping on the
+/// smallest integer.
+fn divide(left: ir::Exp, right: ir::Exp) -> ir::Exp {
+    let negate = |x| ir::Exp::binop(BinOp::Minus, ir::Exp::Const(0), x);
+    match right {
+        ir::Exp::Const(-1) => return negate(left),
+        ir::Exp::Const(n) if n != 0 => return ir::Exp::binop(BinOp::Div, left, right),
+        _ => {}
+    }
+    let (l, r, q) = (Temp::new(), Temp::new(), Temp::new());
+    let (ok, fail) = (Label::new(), Label::new());
+    let (div, neg, join) = (Label::new(), Label::new(), Label::new());
+    ir::Exp::eseq(
+        ir::seq([
+            Stm::mov(ir::Exp::Temp(l), left),
+            Stm::mov(ir::Exp::Temp(r), right),
+            Stm::cjump(RelOp::Ne, ir::Exp::Temp(r), ir::Exp::Const(0), ok, fail),
+            Stm::Label(fail),
+            Stm::exp(frame::external_call("tig_divError", vec![])),
+            Stm::Label(ok),
+            Stm::cjump(RelOp::Ne, ir::Exp::Temp(r), ir::Exp::Const(-1), div, neg),
+            Stm::Label(div),
+            Stm::mov(
+                ir::Exp::Temp(q),
+                ir::Exp::binop(BinOp::Div, ir::Exp::Temp(l), ir::Exp::Temp(r)),
+            ),
+            Stm::jump(join),
+            Stm::Label(neg),
+            Stm::mov(ir::Exp::Temp(q), negate(ir::Exp::Temp(l))),
+            Stm::Label(join),
+        ]),
+        ir::Exp::Temp(q),
+    )
+}
+
+/// A comparison of two strings by contents, through the runtime.
+pub(crate) fn string_op(op: Oper, left: Exp, right: Exp) -> Exp {
+    let (func, relop) = match op {
+        Oper::Eq => ("tig_stringEqual", RelOp::Ne),
+        Oper::Neq => ("tig_stringEqual", RelOp::Eq),
+        Oper::Lt => ("tig_stringCompare", RelOp::Lt),
+        Oper::Le => ("tig_stringCompare", RelOp::Le),
+        Oper::Gt => ("tig_stringCompare", RelOp::Gt),
+        Oper::Ge => ("tig_stringCompare", RelOp::Ge),
+        _ => unreachable!("`{}` is not a string comparison", op.text()),
+    };
+    let result = frame::external_call(func, vec![left.un_ex(), right.un_ex()]);
+    Exp::Cx(Box::new(move |t, f| {
+        Stm::cjump(relop, result, ir::Exp::Const(0), t, f)
+    }))
+}
+
+/// A call from code at `caller`. Tiger functions get the static link of
+/// the level they were declared in; runtime functions, with no level, don't.
+pub(crate) fn call(callee: Option<&Level>, label: Label, caller: &Level, args: Vec<Exp>) -> Exp {
+    let args = args.into_iter().map(Exp::un_ex);
+    let args = match callee.and_then(|level| level.0.parent.as_ref()) {
+        Some(parent) => std::iter::once(caller.frame_of(parent))
+            .chain(args)
+            .collect(),
+        None => args.collect(),
+    };
+    Exp::Ex(ir::Exp::call(ir::Exp::Name(label), args))
+}
+
+/// A record creation: allocates the record, then initializes the fields in
+/// order.
+pub(crate) fn record(fields: Vec<Exp>) -> Exp {
+    let r = Temp::new();
+    let alloc = Stm::mov(
+        ir::Exp::Temp(r),
+        frame::external_call(
+            "tig_allocRecord",
+            vec![ir::Exp::Const(fields.len() as i64 * WORD_SIZE)],
+        ),
+    );
+    let inits = fields.into_iter().enumerate().map(|(i, field)| {
+        Stm::mov(
+            ir::Exp::offset(ir::Exp::Temp(r), i as i64 * WORD_SIZE),
+            field.un_ex(),
+        )
+    });
+    Exp::Ex(ir::Exp::eseq(
+        ir::seq(std::iter::once(alloc).chain(inits)),
+        ir::Exp::Temp(r),
+    ))
+}
+
+pub(crate) fn array(size: Exp, init: Exp) -> Exp {
+    Exp::Ex(frame::external_call(
+        "tig_initArray",
+        vec![size.un_ex(), init.un_ex()],
+    ))
+}
+
+/// A sequence; its value is that of the last expression.
+pub(crate) fn seq(mut exps: Vec<Exp>) -> Exp {
+    let Some(last) = exps.pop() else {
+        return unit();
+    };
+    if exps.is_empty() {
+        return last;
+    }
+    let stms = ir::seq(exps.into_iter().map(Exp::un_nx));
+    match last {
+        Exp::Nx(stm) => Exp::Nx(ir::seq([stms, stm])),
+        last => Exp::Ex(ir::Exp::eseq(stms, last.un_ex())),
+    }
+}
+
+pub(crate) fn assign(var: Exp, value: Exp) -> Exp {
+    Exp::Nx(Stm::mov(var.un_ex(), value.un_ex()))
+}
+
+/// `if test then then_ [else else_]`. Without `else` there is no value.
+pub(crate) fn if_(test: Exp, then_: Exp, else_: Option<Exp>) -> Exp {
+    let (t, f, join) = (Label::new(), Label::new(), Label::new());
+    let Some(else_) = else_ else {
+        return Exp::Nx(ir::seq([
+            test.un_cx(t, join),
+            Stm::Label(t),
+            then_.un_nx(),
+            Stm::Label(join),
+        ]));
+    };
+    match (then_, else_) {
+        (Exp::Nx(then_), Exp::Nx(else_)) => Exp::Nx(ir::seq([
+            test.un_cx(t, f),
+            Stm::Label(t),
+            then_,
+            Stm::jump(join),
+            Stm::Label(f),
+            else_,
+            Stm::Label(join),
+        ])),
+        (then_, else_) => {
+            let r = Temp::new();
+            Exp::Ex(ir::Exp::eseq(
+                ir::seq([
+                    test.un_cx(t, f),
+                    Stm::Label(t),
+                    Stm::mov(ir::Exp::Temp(r), then_.un_ex()),
+                    Stm::jump(join),
+                    Stm::Label(f),
+                    Stm::mov(ir::Exp::Temp(r), else_.un_ex()),
+                    Stm::Label(join),
+                ]),
+                ir::Exp::Temp(r),
+            ))
+        }
+    }
+}
+
+/// A `while` loop; `break` in the body jumps to `done`.
+pub(crate) fn while_(test: Exp, body: Exp, done: Label) -> Exp {
+    let (start, body_label) = (Label::new(), Label::new());
+    Exp::Nx(ir::seq([
+        Stm::Label(start),
+        test.un_cx(body_label, done),
+        Stm::Label(body_label),
+        body.un_nx(),
+        Stm::jump(start),
+        Stm::Label(done),
+    ]))
+}
+
+/// A `for` loop over the variable `var`; `break` in the body jumps to
+/// `done`. The loop exits before incrementing past `hi`, so it works for
+/// `hi` equal to the largest integer.
+pub(crate) fn for_(var: Exp, lo: Exp, hi: Exp, body: Exp, done: Label) -> Exp {
+    let i = var.un_ex();
+    let limit = ir::Exp::Temp(Temp::new());
+    let (body_label, inc) = (Label::new(), Label::new());
+    Exp::Nx(ir::seq([
+        Stm::mov(i.clone(), lo.un_ex()),
+        Stm::mov(limit.clone(), hi.un_ex()),
+        Stm::cjump(RelOp::Le, i.clone(), limit.clone(), body_label, done),
+        Stm::Label(body_label),
+        body.un_nx(),
+        Stm::cjump(RelOp::Lt, i.clone(), limit, inc, done),
+        Stm::Label(inc),
+        Stm::mov(i.clone(), ir::Exp::binop(BinOp::Plus, i, ir::Exp::Const(1))),
+        Stm::jump(body_label),
+        Stm::Label(done),
+    ]))
+}
+
+pub(crate) fn break_(done: Label) -> Exp {
+    Exp::Nx(Stm::jump(done))
+}
+
+/// A `let`: the variable initializations, then the body.
+pub(crate) fn let_(inits: Vec<Exp>, body: Exp) -> Exp {
+    if inits.is_empty() {
+        return body;
+    }
+    let inits = ir::seq(inits.into_iter().map(Exp::un_nx));
+    match body {
+        Exp::Nx(stm) => Exp::Nx(ir::seq([inits, stm])),
+        body => Exp::Ex(ir::Exp::eseq(inits, body.un_ex())),
+    }
+}
+
+/// Finishes the function at `level`, adding it to `fragments`. Functions
+/// returning a value leave it in the return-value register.
+pub(crate) fn proc_entry_exit(
+    level: &Level,
+    body: Exp,
+    returns_value: bool,
+    fragments: &mut Vec<Fragment>,
+) {
+    let body = if returns_value {
+        Stm::mov(ir::Exp::Temp(RV), body.un_ex())
+    } else {
+        body.un_nx()
+    };
+    debug_assert_eq!(
+        ir::check(&body),
+        Ok(()),
+        "ill-formed body of {}",
+        level.name()
+    );
+    let frame = level.0.frame.borrow().clone();
+    fragments.push(Fragment::Proc {
+        body: frame::proc_entry_exit1(&frame, body),
+        frame,
+    });
+}
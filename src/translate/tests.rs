@@ -0,0 +1,184 @@
+use crate::frame::{FP, RV};
+use crate::ir::{self, BinOp, RelOp, Stm};
+use crate::parser::parse;
+use crate::semant::Semant;
+use crate::temp::Label;
+use crate::translate::escape::find_escapes;
+use crate::translate::{self, Fragment, Level};
+
+fn fp_offset(base: ir::Exp, offset: i64) -> ir::Exp {
+    ir::Exp::mem(ir::Exp::binop(BinOp::Plus, base, ir::Exp::Const(offset)))
+}
+
+/// Translates `src`, which must type check, and prints every fragment.
+fn translate(src: &str) -> (Vec<String>, String) {
+    let exp = parse(src).unwrap_or_else(|e| panic!("failed to parse {src:?}: {e:?}"));
+    let mut semant = Semant::new();
+    semant.check(&exp);
+    assert!(semant.errors().is_empty(), "{:?}", semant.errors());
+    let names = semant
+        .fragments()
+        .iter()
+        .map(|fragment| match fragment {
+            Fragment::Proc { frame, .. } => frame.name().to_string(),
+            Fragment::String(_, s) => format!("{s:?}"),
+        })
+        .collect();
+    let text = semant.fragments().iter().map(|f| f.to_string()).collect();
+    (names, text)
+}
+
+#[test]
+fn variables_used_in_nested_functions_escape() {
+    let src = "let
+        var a := 1
+        var b := 2
+        function f(x: int, y: int): int =
+            let function g(): int = a + x in for i := 0 to y do (); g() end
+    in b end";
+    let exp = parse(src).unwrap();
+    let escapes = find_escapes(&exp);
+    let escaped: Vec<&str> = {
+        let mut names: Vec<&str> = escapes
+            .iter()
+            .map(|pos| &src[pos.lo() as usize..pos.hi() as usize])
+            .collect();
+        names.sort();
+        names
+    };
+    assert_eq!(escaped, ["var a := 1", "x: int"]);
+}
+
+#[test]
+fn static_links_reach_enclosing_frames() {
+    let main = Level::new(&Level::outermost(), Label::named("main"), &[]);
+    let var = main.alloc_local(true);
+    let f = Level::new(&main, Label::named("f"), &[false]);
+    let g = Level::new(&f, Label::named("g"), &[]);
+
+    let fp = ir::Exp::Temp(FP);
+    let at_main = translate::simple_var(&var, &main).un_ex();
+    assert_eq!(at_main, fp_offset(fp.clone(), -16));
+    let at_g = translate::simple_var(&var, &g).un_ex();
+    assert_eq!(
+        at_g,
+        fp_offset(fp_offset(fp_offset(fp.clone(), -8), -8), -16)
+    );
+
+    // Callees get the frame of the level they are declared in: `main`'s for
+    // `f`, and `f`'s for `g` calling itself.
+    let call = |callee: &Level| {
+        let exp = translate::call(Some(callee), callee.name(), &g, vec![]).un_ex();
+        let ir::Exp::Call(_, args) = exp else {
+            panic!("expected a call");
+        };
+        args
+    };
+    assert_eq!(call(&f), [fp_offset(fp_offset(fp.clone(), -8), -8)]);
+    assert_eq!(call(&g), [fp_offset(fp, -8)]);
+    let external = translate::call(None, Label::named("tig_print"), &g, vec![]).un_ex();
+    assert_eq!(
+        external,
+        ir::Exp::call(ir::Exp::Name(Label::named("tig_print")), vec![])
+    );
+}
+
+#[test]
+fn conditions_convert_between_forms() {
+    let (t, f) = (Label::named("t"), Label::named("f"));
+    assert_eq!(translate::int(0).un_cx(t, f), Stm::jump(f));
+    assert_eq!(translate::int(7).un_cx(t, f), Stm::jump(t));
+    let less = translate::op(crate::ast::Oper::Lt, translate::int(1), translate::int(2));
+    assert_eq!(
+        less.un_cx(t, f),
+        Stm::cjump(RelOp::Lt, ir::Exp::Const(1), ir::Exp::Const(2), t, f)
+    );
+    let less = translate::op(crate::ast::Oper::Lt, translate::int(1), translate::int(2));
+    assert!(matches!(less.un_ex(), ir::Exp::ESeq(_, r) if matches!(*r, ir::Exp::Temp(_))));
+}
+
+#[test]
+fn programs_become_fragments() {
+    let (names, text) = translate(
+        "let function f(s: string): int = if s = \"a\" then 1 else 0
+         in f(\"b\") end",
+    );
+    assert_eq!(names.len(), 4);
+    assert!(names[0].starts_with("\"a\""));
+    assert!(names[1].starts_with("f."));
+    assert_eq!(names[2..], ["\"b\"", "tigermain"]);
+    assert!(text.contains("NAME tig_stringEqual"));
+    assert!(text.contains(&format!(
+        "TEMP {}",
+        crate::frame::register_name(RV).unwrap()
+    )));
+}
+
+#[test]
+fn subscripts_are_bounds_checked() {
+    let (_, text) = translate("let type a = array of int var x := a [3] of 0 in x[1] end");
+    assert!(text.contains("NAME tig_initArray"));
+    assert!(text.contains("CJUMP(ULt"));
+    assert!(text.contains("NAME tig_indexError"));
+}
+
+#[test]
+fn nil_records_and_zero_divisors_are_checked() {
+    let (names, text) = translate("let type r = {f: int} var x := r {f = 1} in x.f / x.f end");
+    assert!(text.contains("NAME tig_nilError"));
+    assert!(text.contains("NAME tig_divError"));
+    // The name of the field, for the error message.
+    assert!(names.iter().any(|name| name.starts_with("\"f\"")));
+    // A constant divisor other than zero needs no check.
+    let (_, text) = translate("let var x := 7 in x / 2 end");
+    assert!(!text.contains("tig_divError"));
+    // Any other divisor is also checked for -1, which negates rather than
+    // trapping on the smallest integer.
+    let (_, text) = translate("let var x := 7 var y := 1 in x / y end");
+    assert!(text.contains("CONST -1"));
+}
+
+#[test]
+fn ill_formed_trees_are_rejected() {
+    let (l, m) = (Label::new(), Label::new());
+    let jump_elsewhere = Stm::Jump(Box::new(ir::Exp::Name(l)), vec![m]);
+    assert!(ir::check(&jump_elsewhere)
+        .unwrap_err()
+        .contains("not among its targets"));
+    let undefined = ir::seq([Stm::Label(m), Stm::jump(l)]);
+    assert_eq!(
+        ir::check(&undefined),
+        Err(format!("jump to {l}, which is never defined"))
+    );
+    let twice = ir::seq([Stm::Label(l), Stm::Label(l)]);
+    assert_eq!(
+        ir::check(&twice),
+        Err(format!("label {l} is defined twice"))
+    );
+    let into_const = Stm::Move(Box::new(ir::Exp::Const(1)), Box::new(ir::Exp::Const(2)));
+    assert!(ir::check(&into_const).unwrap_err().starts_with("MOVE into"));
+}
+
+#[test]
+#[should_panic(expected = "MOVE into a non-location")]
+fn move_into_a_constant_panics() {
+    Stm::mov(ir::Exp::Const(1), ir::Exp::Const(2));
+}
+
+#[test]
+fn long_sequences_are_shallow_trees() {
+    fn depth(stm: &Stm) -> usize {
+        match stm {
+            Stm::Seq(a, b) => 1 + depth(a).max(depth(b)),
+            _ => 0,
+        }
+    }
+    let labels: Vec<Label> = (0..1000).map(|_| Label::new()).collect();
+    let stm = ir::seq(labels.iter().map(|&l| Stm::Label(l)));
+    assert_eq!(depth(&stm), 10);
+    let order: Vec<Stm> = crate::canon::linearize(stm);
+    assert_eq!(
+        order,
+        labels.into_iter().map(Stm::Label).collect::<Vec<_>>()
+    );
+}
@@ -0,0 +1,117 @@
+#![allow(dead_code)]
+
+//! Tiger types, interned in a `TypeTable`. Every record and array type
+//! declaration creates a fresh entry, which gives the language its
+//! by-declaration type equivalence: two types are equal iff their `Ty` handles
+//! are, after following `Name` aliases.
+
+use crate::symbol::Symbol;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) struct Ty(u32);
+
+impl Ty {
+    pub(crate) const INT: Ty = Ty(0);
+    pub(crate) const STRING: Ty = Ty(1);
+    pub(crate) const NIL: Ty = Ty(2);
+    pub(crate) const UNIT: Ty = Ty(3);
+    /// Type of erroneous expressions; compatible with everything so that one
+    /// mistake doesn't cascade into many errors.
+    pub(crate) const ERROR: Ty = Ty(4);
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum TyKind {
+    Int,
+    String,
+    Nil,
+    Unit,
+    Error,
+    Record {
+        name: Symbol,
+        fields: Vec<(Symbol, Ty)>,
+    },
+    Array {
+        name: Symbol,
+        elem: Ty,
+    },
+    /// A declared type name. `None` until its declaration group is resolved.
+    Name(Symbol, Option<Ty>),
+}
+
+pub(crate) struct TypeTable {
+    kinds: Vec<TyKind>,
+}
+
+impl TypeTable {
+    pub(crate) fn new() -> TypeTable {
+        TypeTable {
+            kinds: vec![
+                TyKind::Int,
+                TyKind::String,
+                TyKind::Nil,
+                TyKind::Unit,
+                TyKind::Error,
+            ],
+        }
+    }
+
+    pub(crate) fn add(&mut self, kind: TyKind) -> Ty {
+        self.kinds.push(kind);
+        Ty(self.kinds.len() as u32 - 1)
+    }
+
+    pub(crate) fn kind(&self, ty: Ty) -> &TyKind {
+        &self.kinds[ty.0 as usize]
+    }
+
+    pub(crate) fn kind_mut(&mut self, ty: Ty) -> &mut TyKind {
+        &mut self.kinds[ty.0 as usize]
+    }
+
+    /// Follows `Name` aliases to the underlying type. Unresolved or cyclic
+    /// names resolve to `Ty::ERROR`.
+    pub(crate) fn actual(&self, mut ty: Ty) -> Ty {
+        for _ in 0..self.kinds.len() {
+            match self.kind(ty) {
+                TyKind::Name(_, Some(target)) => ty = *target,
+                TyKind::Name(_, None) => return Ty::ERROR,
+                _ => return ty,
+            }
+        }
+        Ty::ERROR
+    }
+
+    pub(crate) fn actual_kind(&self, ty: Ty) -> &TyKind {
+        self.kind(self.actual(ty))
+    }
+
+    pub(crate) fn is_record(&self, ty: Ty) -> bool {
+        matches!(self.actual_kind(ty), TyKind::Record { .. })
+    }
+
+    /// Whether a value of type `actual` can be used where `expected` is
+    /// required.
+    pub(crate) fn compatible(&self, actual: Ty, expected: Ty) -> bool {
+        let (a, e) = (self.actual(actual), self.actual(expected));
+        a == e
+            || a == Ty::ERROR
+            || e == Ty::ERROR
+            || (a == Ty::NIL && self.is_record(e))
+            || (e == Ty::NIL && self.is_record(a))
+    }
+
+    /// Name of `ty` as written in source, for diagnostics.
+    pub(crate) fn name(&self, ty: Ty) -> String {
+        match self.kind(ty) {
+            TyKind::Int => "int".to_string(),
+            TyKind::String => "string".to_string(),
+            TyKind::Nil => "nil".to_string(),
+            TyKind::Unit => "()".to_string(),
+            TyKind::Error => "{error}".to_string(),
+            TyKind::Record { name, .. } | TyKind::Array { name, .. } | TyKind::Name(name, _) => {
+                name.to_string()
+            }
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Compatibility with another Tiger implementation. With `TIGER_REFERENCE`
+//! naming its command, every program in `tests/ui` and `xtask/corpus` is
+//! given to both `tigerc` and the reference, and the programs they
+//! disagree on are written to `target/compat/report.txt`:
+//!
+//! - whether the program is accepted, by `<command> check <file.tig>`
+//!   exiting with 0;
+//! - for a program both accept, what `<command> run <file.tig>` prints on
+//!   stdout and the exit code it ends with, given no input.
+//!
+//! `tigerc` itself has that interface, and a wrapper script gives it to a
+//! compiler that has another. Disagreements are reported rather than
+//! failed: the report is for reading, since each implementation has
+//! extensions, such as `random`, that the other rejects. Without
+//! `TIGER_REFERENCE` the test checks only that `tigerc` agrees with itself.
+//!
+//! Run with `TIGER_REFERENCE=<command> cargo test --test compat -- --nocapture`
+//! to see the summary.
+
+use std::fmt::Write;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// How long a run may take before it counts as not ending.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+const TIGERC: &str = env!("CARGO_BIN_EXE_tigerc");
+
+#[test]
+fn compat() {
+    let Some(reference) = std::env::var_os("TIGER_REFERENCE") else {
+        return;
+    };
+    let reference = PathBuf::from(reference);
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let report = report(&reference, &corpus(root));
+    let path = root.join("target/compat/report.txt");
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, &report).unwrap();
+    println!("{}; see {}", report.lines().next().unwrap(), path.display());
+}
+
+#[test]
+fn tigerc_is_compatible_with_itself() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let programs = ["tests/ui/queens.tig", "tests/ui/test10.tig"].map(|p| root.join(p));
+    assert_eq!(
+        report(Path::new(TIGERC), &programs),
+        format!("{TIGERC}: 2 programs, all agree with tigerc\n")
+    );
+    // `false` rejects every program, as `tigerc` does only the ill-typed.
+    let rejecting = report(Path::new("false"), &programs);
+    let queens = programs[0].display();
+    assert!(
+        rejecting.starts_with(&format!(
+            "false: 2 programs, 1 differs from tigerc\n{queens}\n  tigerc prints \""
+        )),
+        "{rejecting}"
+    );
+    assert!(rejecting.ends_with(" and exits with 0\n  the reference rejects it\n"));
+}
+
+/// The `.tig` programs the test suite and the benchmarks share.
+fn corpus(root: &Path) -> Vec<PathBuf> {
+    let mut programs: Vec<PathBuf> = ["tests/ui", "xtask/corpus"]
+        .iter()
+        .flat_map(|dir| std::fs::read_dir(root.join(dir)).unwrap())
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "tig"))
+        .collect();
+    programs.sort();
+    programs
+}
+
+/// How an implementation handled a program.
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Rejected,
+    Ran { stdout: String, code: Option<i32> },
+    TimedOut,
+}
+
+impl Outcome {
+    fn describe(&self) -> String {
+        match self {
+            Outcome::Rejected => "rejects it".to_string(),
+            Outcome::Ran { stdout, code } => {
+                let ending = match code {
+                    Some(code) => format!("exits with {code}"),
+                    None => "is killed".to_string(),
+                };
+                format!("prints {stdout:?} and {ending}")
+            }
+            Outcome::TimedOut => format!("runs for more than {}s", TIMEOUT.as_secs()),
+        }
+    }
+}
+
+/// A summary line, then each program `reference` handles differently from
+/// `tigerc`.
+fn report(reference: &Path, programs: &[PathBuf]) -> String {
+    let mut divergences = String::new();
+    let mut agree = 0;
+    for program in programs {
+        let ours = outcome(Path::new(TIGERC), program);
+        let theirs = outcome(reference, program);
+        if ours == theirs {
+            agree += 1;
+            continue;
+        }
+        writeln!(
+            divergences,
+            "{}\n  tigerc {}\n  the reference {}",
+            program.display(),
+            ours.describe(),
+            theirs.describe()
+        )
+        .unwrap();
+    }
+    let summary = match programs.len() - agree {
+        0 => "all agree with tigerc".to_string(),
+        1 => "1 differs from tigerc".to_string(),
+        n => format!("{n} differ from tigerc"),
+    };
+    format!(
+        "{}: {} programs, {summary}\n{divergences}",
+        reference.display(),
+        programs.len()
+    )
+}
+
+fn outcome(command: &Path, program: &Path) -> Outcome {
+    match execute(command, "check", program) {
+        None => return Outcome::TimedOut,
+        Some((_, Some(0))) => {}
+        Some(_) => return Outcome::Rejected,
+    }
+    match execute(command, "run", program) {
+        None => Outcome::TimedOut,
+        Some((stdout, code)) => Outcome::Ran { stdout, code },
+    }
+}
+
+/// The stdout and exit code of `command <subcommand> <program>`, or `None`
+/// if it did not end within `TIMEOUT`.
+fn execute(command: &Path, subcommand: &str, program: &Path) -> Option<(String, Option<i32>)> {
+    let mut child = Command::new(command)
+        .args([subcommand.as_ref(), program.as_os_str()])
+        .env("NO_COLOR", "1")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap_or_else(|e| panic!("cannot run `{}`: {e}", command.display()));
+    // Read as the program writes, so that it never blocks on a full pipe.
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = std::thread::spawn(move || {
+        let mut out = Vec::new();
+        let _ = stdout.read_to_end(&mut out);
+        out
+    });
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        if start.elapsed() > TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    let stdout = reader.join().unwrap();
+    Some((String::from_utf8_lossy(&stdout).into_owned(), status.code()))
+}
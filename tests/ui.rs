@@ -0,0 +1,141 @@
+//! Golden-file tests: every `.tig` file in `tests/ui` is compiled by
+//! `tigerc` through the phases its first line names, as in
+//! `/* phases: tokens ast */`, and what each phase prints is compared with
+//! the checked-in `<name>.<phase>.expected`. The phase `check` runs
+//! `tigerc` without `--emit`, so its output is the rendered diagnostics; a
+//! fixture without a `phases` line is checked only that way.
+//!
+//! Run with `TIGER_BLESS=1` to write the output as the expected files
+//! instead, then review the changes with `git diff`.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Where the fixtures are, relative to the package.
+const UI: &str = "tests/ui";
+
+#[test]
+fn ui() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(UI);
+    let bless = std::env::var_os("TIGER_BLESS").is_some();
+    let mut fixtures: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .expect("tests/ui exists")
+        .map(|entry| entry.expect("tests/ui can be listed").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "tig"))
+        .collect();
+    fixtures.sort();
+    assert!(!fixtures.is_empty(), "no fixtures in {UI}");
+
+    let mut failures = String::new();
+    for fixture in &fixtures {
+        let name = fixture.file_stem().unwrap().to_str().unwrap();
+        let phases = phases(fixture);
+        for phase in &phases {
+            let expected_path = dir.join(format!("{name}.{phase}.expected"));
+            let actual = run(&dir, name, phase);
+            if bless {
+                std::fs::write(&expected_path, &actual).unwrap();
+                continue;
+            }
+            match std::fs::read_to_string(&expected_path) {
+                Ok(expected) if expected == actual => {}
+                Ok(expected) => failures.push_str(&mismatch(&expected_path, &expected, &actual)),
+                Err(_) => writeln!(failures, "{}: missing\n", expected_path.display()).unwrap(),
+            }
+        }
+        // Expected files for phases the fixture no longer names.
+        for stale in expected_files(&dir, name) {
+            let phase = stale.to_str().unwrap()[name.len() + 1..].trim_end_matches(".expected");
+            if !phases.iter().any(|p| p == phase) {
+                let path = dir.join(&stale);
+                if bless {
+                    std::fs::remove_file(&path).unwrap();
+                } else {
+                    writeln!(
+                        failures,
+                        "{}: no phase `{phase}` in the fixture\n",
+                        path.display()
+                    )
+                    .unwrap();
+                }
+            }
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{failures}run with TIGER_BLESS=1 to update the expected files"
+    );
+}
+
+/// The phases on the fixture's first line, or `check`.
+fn phases(fixture: &Path) -> Vec<String> {
+    let src = std::fs::read_to_string(fixture).unwrap();
+    let first = src.lines().next().unwrap_or_default().trim();
+    match first
+        .strip_prefix("/* phases:")
+        .and_then(|rest| rest.strip_suffix("*/"))
+    {
+        Some(phases) => phases.split_whitespace().map(String::from).collect(),
+        None => vec!["check".to_string()],
+    }
+}
+
+/// The files `<name>.<phase>.expected` in `dir`.
+fn expected_files(dir: &Path, name: &str) -> Vec<PathBuf> {
+    let prefix = format!("{name}.");
+    std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| PathBuf::from(entry.unwrap().file_name()))
+        .filter(|file| {
+            let file = file.to_str().unwrap_or_default();
+            file.starts_with(&prefix) && file.ends_with(".expected")
+        })
+        .collect()
+}
+
+/// What `tigerc` prints for `phase` of fixture `name`: its output, then its
+/// diagnostics and exit code if there are any. It runs in `dir`, so that
+/// diagnostics name the fixture by its file name alone.
+fn run(dir: &Path, name: &str, phase: &str) -> String {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_tigerc"));
+    command
+        .arg(format!("{name}.tig"))
+        .current_dir(dir)
+        .env_remove("TIGER_LANG")
+        .env("NO_COLOR", "1");
+    if phase != "check" {
+        command.args(["--emit", phase]);
+    }
+    let output = command.output().expect("tigerc runs");
+    let mut out = String::from_utf8_lossy(&output.stdout).into_owned();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.is_empty() {
+        writeln!(out, "--- stderr\n{}", stderr.trim_end()).unwrap();
+    }
+    if let Some(code) = output.status.code().filter(|&code| code != 0) {
+        writeln!(out, "--- exit code {code}").unwrap();
+    }
+    out
+}
+
+/// The first line where `actual` differs from `expected`.
+fn mismatch(path: &Path, expected: &str, actual: &str) -> String {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    let mut line = 1;
+    loop {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(e), Some(a)) if e == a => line += 1,
+            (None, None) => return format!("{}: differs in its last newline\n\n", path.display()),
+            (e, a) => {
+                return format!(
+                    "{}:{line}: differs\n  expected: {}\n  actual:   {}\n\n",
+                    path.display(),
+                    e.unwrap_or("<end of file>"),
+                    a.unwrap_or("<end of file>"),
+                )
+            }
+        }
+    }
+}
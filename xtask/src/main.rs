@@ -0,0 +1,260 @@
+//! Development tasks for the workspace, run with `cargo xtask <task>`.
+//!
+//! `perfcheck` guards the compiler's speed. It builds `tigerc` in release
+//! mode, times each phase over the programs in `xtask/corpus` with
+//! `tigerc time`, and compares the timings with a baseline recorded on the
+//! same machine, in `target/perfcheck/baseline.json`. A phase that got
+//! slower by more than the threshold fails the check. Timings depend on the
+//! machine, so the baseline is never committed: record it on the commit a
+//! change starts from, then check the change against it.
+//!
+//! `regress` turns a miscompiled program into a regression test; see
+//! `regress.rs`.
+
+mod regress;
+#[cfg(test)]
+mod tests;
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+
+const USAGE: &str = "\
+usage: cargo xtask perfcheck [--record] [--threshold <percent>] [--repeat <n>]
+       cargo xtask regress <program.tig> [--input <file>] [-- <tigerc build flag>...]
+
+Builds tigerc in release mode, times each phase of compiling the programs in
+xtask/corpus, and compares the timings with target/perfcheck/baseline.json.
+Fails if a phase got slower by more than the threshold, 25% by default, and
+by more than 50 microseconds, below which timings are noise. Records the
+baseline instead when there is none or with --record. --repeat is passed on
+to `tigerc time`, which keeps the fastest of that many runs (10 by default).
+
+regress builds a minimized program with the given tigerc build flags and
+runs it, with the input file on stdin. If it prints or ends otherwise than
+under `tigerc run`, it packages the program, flags, input and both outcomes
+in tests/regressions/NNNN and adds a test of it to tests/regressions.rs.";
+
+/// Slowdowns smaller than this many microseconds are never regressions.
+const NOISE_FLOOR: u64 = 50;
+
+#[derive(Debug, PartialEq)]
+struct PerfOptions {
+    record: bool,
+    threshold: f64,
+    repeat: u32,
+}
+
+impl PerfOptions {
+    fn parse(args: &[String]) -> Result<PerfOptions, String> {
+        let mut opts = PerfOptions {
+            record: false,
+            threshold: 25.0,
+            repeat: 10,
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) => (flag, Some(value)),
+                None => (arg.as_str(), None),
+            };
+            let mut value = || {
+                inline
+                    .or_else(|| args.next().map(String::as_str))
+                    .ok_or(format!("`{flag}` expects a number"))
+            };
+            match flag {
+                "--record" if inline.is_none() => opts.record = true,
+                "--threshold" => {
+                    let pct = value()?;
+                    opts.threshold = match pct.parse() {
+                        Ok(pct) if pct >= 0.0 => pct,
+                        _ => return Err(format!("invalid threshold `{pct}`")),
+                    };
+                }
+                "--repeat" => {
+                    let n = value()?;
+                    opts.repeat = match n.parse() {
+                        Ok(n) if n > 0 => n,
+                        _ => return Err(format!("invalid repeat count `{n}`")),
+                    };
+                }
+                _ if arg.starts_with('-') => return Err(format!("unknown option `{arg}`")),
+                _ => return Err(format!("unexpected argument `{arg}`")),
+            }
+        }
+        Ok(opts)
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("perfcheck") => perfcheck(&args[1..]),
+        Some("regress") => regress::regress(&args[1..]),
+        Some("-h" | "--help") | None => {
+            println!("{USAGE}");
+            Ok(true)
+        }
+        Some(task) => Err(format!("unknown task `{task}`\n\n{USAGE}")),
+    };
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("xtask: error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Returns whether no phase regressed.
+fn perfcheck(args: &[String]) -> Result<bool, String> {
+    let opts = PerfOptions::parse(args)?;
+    let root = workspace_root();
+    let timings = time_corpus(&root, opts.repeat)?;
+    let current = phases(&timings)?;
+
+    let baseline_path = root.join("target/perfcheck/baseline.json");
+    if opts.record || !baseline_path.exists() {
+        let dir = baseline_path.parent().expect("the path has a directory");
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("could not create `{}`: {e}", dir.display()))?;
+        std::fs::write(&baseline_path, format!("{timings}\n"))
+            .map_err(|e| format!("could not write `{}`: {e}", baseline_path.display()))?;
+        print!("{}", table(&current, &current, opts.threshold));
+        println!("recorded the baseline in {}", baseline_path.display());
+        return Ok(true);
+    }
+    let baseline = std::fs::read_to_string(&baseline_path)
+        .map_err(|e| format!("could not read `{}`: {e}", baseline_path.display()))?;
+    let baseline = phases(&baseline)?;
+    print!("{}", table(&baseline, &current, opts.threshold));
+    let slower = regressions(&baseline, &current, opts.threshold)?;
+    if slower.is_empty() {
+        println!("no phase is more than {}% slower", opts.threshold);
+        return Ok(true);
+    }
+    println!(
+        "{} slower than the baseline by more than {}%",
+        slower.join(", "),
+        opts.threshold
+    );
+    Ok(false)
+}
+
+fn workspace_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("xtask is in the workspace")
+        .to_path_buf()
+}
+
+/// Builds a release `tigerc` and prints the output of `tigerc time` over
+/// the corpus.
+fn time_corpus(root: &Path, repeat: u32) -> Result<String, String> {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let status = Command::new(&cargo)
+        .args(["build", "--quiet", "--release", "--bin", "tigerc"])
+        .current_dir(root)
+        .status()
+        .map_err(|e| format!("could not run `{cargo}`: {e}"))?;
+    if !status.success() {
+        return Err("could not build `tigerc`".to_string());
+    }
+
+    let corpus = root.join("xtask/corpus");
+    let mut files: Vec<PathBuf> = std::fs::read_dir(&corpus)
+        .map_err(|e| format!("could not read `{}`: {e}", corpus.display()))?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "tig"))
+        .collect();
+    files.sort();
+
+    let output = Command::new(root.join("target/release/tigerc"))
+        .arg("time")
+        .args(&files)
+        .arg(format!("--repeat={repeat}"))
+        .output()
+        .map_err(|e| format!("could not run `tigerc`: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "`tigerc time` failed:\n{}",
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        ));
+    }
+    String::from_utf8(output.stdout)
+        .map(|s| s.trim().to_string())
+        .map_err(|_| "`tigerc time` printed invalid UTF-8".to_string())
+}
+
+/// The phases and their times in microseconds from the JSON `tigerc time`
+/// prints, in its order.
+fn phases(json: &str) -> Result<Vec<(String, u64)>, String> {
+    let invalid = || "the timings are not the JSON `tigerc time` prints".to_string();
+    let start = json.find("\"phases\":{").ok_or_else(invalid)? + "\"phases\":{".len();
+    let len = json[start..].find('}').ok_or_else(invalid)?;
+    json[start..start + len]
+        .split(',')
+        .map(|member| {
+            let (name, time) = member.split_once(':').ok_or_else(invalid)?;
+            let name = name
+                .trim()
+                .strip_prefix('"')
+                .and_then(|n| n.strip_suffix('"'));
+            match (name, time.trim().parse()) {
+                (Some(name), Ok(time)) => Ok((name.to_string(), time)),
+                _ => Err(invalid()),
+            }
+        })
+        .collect()
+}
+
+/// The phases of `current` more than `threshold` percent, and more than
+/// `NOISE_FLOOR`, slower than in `baseline`.
+fn regressions(
+    baseline: &[(String, u64)],
+    current: &[(String, u64)],
+    threshold: f64,
+) -> Result<Vec<String>, String> {
+    let mut slower = Vec::new();
+    for (phase, now) in current {
+        let Some(&(_, before)) = baseline.iter().find(|(p, _)| p == phase) else {
+            return Err(format!(
+                "the baseline has no phase `{phase}`; record it again with `--record`"
+            ));
+        };
+        let limit = before as f64 * (1.0 + threshold / 100.0);
+        if *now as f64 > limit && now - before > NOISE_FLOOR {
+            slower.push(phase.clone());
+        }
+    }
+    Ok(slower)
+}
+
+/// Each phase's baseline and current time, the change, and a mark on those
+/// that regressed.
+fn table(baseline: &[(String, u64)], current: &[(String, u64)], threshold: f64) -> String {
+    let mut out = format!(
+        "{:<10}{:>12}{:>12}{:>10}\n",
+        "phase", "baseline", "now", "change"
+    );
+    let slower = regressions(baseline, current, threshold).unwrap_or_default();
+    for (phase, now) in current {
+        let before = baseline.iter().find(|(p, _)| p == phase).map(|&(_, t)| t);
+        let change = match before {
+            Some(0) | None => "-".to_string(),
+            Some(before) => format!("{:+.1}%", (*now as f64 / before as f64 - 1.0) * 100.0),
+        };
+        let before = before.map_or("-".to_string(), |t| format!("{t}µs"));
+        let mark = if slower.contains(phase) {
+            "  slower"
+        } else {
+            ""
+        };
+        out.push_str(&format!(
+            "{phase:<10}{before:>12}{:>12}{change:>10}{mark}\n",
+            format!("{now}µs")
+        ));
+    }
+    out
+}
@@ -0,0 +1,272 @@
+//! `regress` packages a miscompilation, once the fuzzer or `tigerc
+//! opt-diff` has found one and the program has been minimized, as a test
+//! that keeps it fixed. The program is built with `tigerc build` and the
+//! given flags, and run; it is miscompiled if it prints something else, or
+//! ends another way, than under `tigerc run`. The case goes in
+//! `tests/regressions/NNNN/`:
+//!
+//! - `program.tig`, the program;
+//! - `flags`, the `tigerc build` flags that miscompiled it;
+//! - `input`, what the program reads, if anything;
+//! - `expected`, what `tigerc run` prints and the exit code it ends with;
+//! - `actual`, the same for the miscompiled executable.
+//!
+//! and a test is added to `tests/regressions.rs` that builds the program
+//! with the same flags and checks that it prints what is expected. It
+//! fails until the miscompilation is fixed.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct RegressOptions {
+    pub(crate) program: PathBuf,
+    pub(crate) input: Option<PathBuf>,
+    /// The flags `tigerc build` is given, after `--`.
+    pub(crate) flags: Vec<String>,
+}
+
+impl RegressOptions {
+    pub(crate) fn parse(args: &[String]) -> Result<RegressOptions, String> {
+        let (mut program, mut input) = (None, None);
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--input" => {
+                    let file = args.next().ok_or("`--input` expects a file name")?;
+                    input = Some(PathBuf::from(file));
+                }
+                "--" => break,
+                _ if arg.starts_with('-') => return Err(format!("unknown option `{arg}`")),
+                _ if program.is_none() => program = Some(PathBuf::from(arg)),
+                _ => return Err(format!("unexpected argument `{arg}`")),
+            }
+        }
+        let flags: Vec<String> = args.cloned().collect();
+        if let Some(flag) = flags
+            .iter()
+            .find(|flag| ["-o", "--runtime"].contains(&flag.as_str()))
+        {
+            return Err(format!("`{flag}` is set by the regression test itself"));
+        }
+        Ok(RegressOptions {
+            program: program.ok_or("`regress` expects a program")?,
+            input,
+            flags,
+        })
+    }
+}
+
+/// What the generated tests share, which starts `tests/regressions.rs`.
+const HARNESS: &str = r#"//! Miscompilations that were found and fixed. `cargo xtask regress`
+//! packaged each in `tests/regressions/NNNN/` and generated its test below,
+//! which builds the program with the flags that miscompiled it and checks
+//! that it prints, and ends with, what `tigerc run` did: the `expected`
+//! file. `actual` is what the miscompiled program did.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+fn check(case: &str) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/regressions")
+        .join(case);
+    let flags = std::fs::read_to_string(dir.join("flags")).unwrap();
+    let exe = std::env::temp_dir().join(format!("tiger-regression-{case}-{}", std::process::id()));
+    let built = Command::new(env!("CARGO_BIN_EXE_tigerc"))
+        .arg("build")
+        .arg(dir.join("program.tig"))
+        .arg("-o")
+        .arg(&exe)
+        .arg("--runtime")
+        .arg(runtime_library())
+        .args(flags.split_whitespace())
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&built.stderr);
+    assert!(
+        built.status.success(),
+        "tests/regressions/{case} does not build:\n{stderr}"
+    );
+    let input = std::fs::read(dir.join("input")).unwrap_or_default();
+    let mut child = Command::new(&exe)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    // A program that exits before reading all of its input closes the pipe.
+    let _ = child.stdin.take().unwrap().write_all(&input);
+    let out = child.wait_with_output().unwrap();
+    std::fs::remove_file(&exe).unwrap();
+    let expected = std::fs::read_to_string(dir.join("expected")).unwrap();
+    assert_eq!(
+        outcome(&out.stdout, out.status.code()),
+        expected,
+        "tests/regressions/{case} is miscompiled again"
+    );
+}
+
+/// What a program printed, and how it ended unless it exited with 0.
+fn outcome(stdout: &[u8], code: Option<i32>) -> String {
+    let mut out = String::from_utf8_lossy(stdout).into_owned();
+    match code {
+        Some(0) => {}
+        Some(code) => out.push_str(&format!("--- exit code {code}\n")),
+        None => out.push_str("--- killed\n"),
+    }
+    out
+}
+
+/// The newest runtime library Cargo built for the tests.
+fn runtime_library() -> PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    let deps = exe.parent().unwrap();
+    let libs = std::fs::read_dir(deps).unwrap().filter_map(|entry| {
+        let path = entry.ok()?.path();
+        let name = path.file_name()?.to_str()?;
+        if !name.starts_with("libtiger_runtime") || !name.ends_with(".a") {
+            return None;
+        }
+        Some((path.metadata().ok()?.modified().ok()?, path))
+    });
+    libs.max()
+        .map(|(_, path)| path)
+        .expect("the runtime library is built with the workspace's tests")
+}
+"#;
+
+/// Returns whether the miscompilation was packaged.
+pub(crate) fn regress(args: &[String]) -> Result<bool, String> {
+    let opts = RegressOptions::parse(args)?;
+    let root = crate::workspace_root();
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    // `tigerc`, and the runtime library next to it.
+    let status = Command::new(&cargo)
+        .args(["build", "--quiet"])
+        .current_dir(&root)
+        .status()
+        .map_err(|e| format!("could not run `{cargo}`: {e}"))?;
+    if !status.success() {
+        return Err("could not build `tigerc`".to_string());
+    }
+    let tigerc = root.join("target/debug/tigerc");
+    let input = match &opts.input {
+        Some(path) => {
+            std::fs::read(path).map_err(|e| format!("could not read `{}`: {e}", path.display()))?
+        }
+        None => Vec::new(),
+    };
+
+    let mut interpret = Command::new(&tigerc);
+    interpret.arg("run").arg(&opts.program);
+    let expected = execute(&mut interpret, &input)?;
+    let exe = std::env::temp_dir().join(format!("tiger-regress-{}", std::process::id()));
+    let built = Command::new(&tigerc)
+        .arg("build")
+        .arg(&opts.program)
+        .arg("-o")
+        .arg(&exe)
+        .args(&opts.flags)
+        .output()
+        .map_err(|e| format!("could not run `tigerc`: {e}"))?;
+    if !built.status.success() {
+        return Err(format!(
+            "`tigerc build` failed:\n{}",
+            String::from_utf8_lossy(&built.stderr).trim_end()
+        ));
+    }
+    let actual = execute(&mut Command::new(&exe), &input);
+    let _ = std::fs::remove_file(&exe);
+    let actual = actual?;
+    if actual == expected {
+        println!(
+            "built with `{}`, the program does what `tigerc run` does; \
+             there is no miscompilation to package",
+            opts.flags.join(" ")
+        );
+        return Ok(false);
+    }
+
+    let regressions = root.join("tests/regressions");
+    let case = format!("{:04}", next_case(&regressions)?);
+    let dir = regressions.join(&case);
+    let write = |file: &Path, contents: &[u8]| {
+        std::fs::write(file, contents)
+            .map_err(|e| format!("could not write `{}`: {e}", file.display()))
+    };
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("could not create `{}`: {e}", dir.display()))?;
+    std::fs::copy(&opts.program, dir.join("program.tig"))
+        .map_err(|e| format!("could not copy `{}`: {e}", opts.program.display()))?;
+    write(
+        &dir.join("flags"),
+        format!("{}\n", opts.flags.join(" ")).as_bytes(),
+    )?;
+    if !input.is_empty() {
+        write(&dir.join("input"), &input)?;
+    }
+    write(&dir.join("expected"), expected.as_bytes())?;
+    write(&dir.join("actual"), actual.as_bytes())?;
+
+    let tests = root.join("tests/regressions.rs");
+    let mut source = std::fs::read_to_string(&tests).unwrap_or_else(|_| HARNESS.to_string());
+    source.push_str(&test(&case));
+    write(&tests, source.as_bytes())?;
+    println!("packaged the miscompilation as tests/regressions/{case}");
+    Ok(true)
+}
+
+/// The number after the highest of the cases in `dir`, or 1.
+pub(crate) fn next_case(dir: &Path) -> Result<u32, String> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Ok(1);
+    };
+    let mut last = 0;
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("could not list `{}`: {e}", dir.display()))?;
+        if let Some(n) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        {
+            last = last.max(n);
+        }
+    }
+    Ok(last + 1)
+}
+
+/// The generated test of `case`.
+pub(crate) fn test(case: &str) -> String {
+    format!("\n#[test]\nfn regression_{case}() {{\n    check(\"{case}\");\n}}\n")
+}
+
+/// What `command` prints, given `input`, and how it ends, as the generated
+/// tests compare it.
+fn execute(command: &mut Command, input: &[u8]) -> Result<String, String> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("could not run {command:?}: {e}"))?;
+    // A program that exits before reading all of its input closes the pipe.
+    let _ = child.stdin.take().expect("stdin is piped").write_all(input);
+    let out = child
+        .wait_with_output()
+        .map_err(|e| format!("could not run {command:?}: {e}"))?;
+    Ok(outcome(&out.stdout, out.status.code()))
+}
+
+/// What a program printed, and how it ended unless it exited with 0.
+pub(crate) fn outcome(stdout: &[u8], code: Option<i32>) -> String {
+    let mut out = String::from_utf8_lossy(stdout).into_owned();
+    match code {
+        Some(0) => {}
+        Some(code) => out.push_str(&format!("--- exit code {code}\n")),
+        None => out.push_str("--- killed\n"),
+    }
+    out
+}
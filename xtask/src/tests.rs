@@ -0,0 +1,106 @@
+use super::*;
+use crate::regress::{next_case, outcome, test, RegressOptions};
+
+fn args(s: &str) -> Vec<String> {
+    s.split_whitespace().map(String::from).collect()
+}
+
+fn timings(times: &[(&str, u64)]) -> Vec<(String, u64)> {
+    times.iter().map(|&(p, t)| (p.to_string(), t)).collect()
+}
+
+#[test]
+fn perfcheck_options() {
+    let opts = PerfOptions::parse(&args("--record --threshold=10 --repeat 3")).unwrap();
+    assert_eq!(
+        opts,
+        PerfOptions {
+            record: true,
+            threshold: 10.0,
+            repeat: 3
+        }
+    );
+    assert_eq!(PerfOptions::parse(&[]).unwrap().threshold, 25.0);
+    let err = PerfOptions::parse(&args("--threshold -5")).err();
+    assert_eq!(err.as_deref(), Some("invalid threshold `-5`"));
+    let err = PerfOptions::parse(&args("--repeat")).err();
+    assert_eq!(err.as_deref(), Some("`--repeat` expects a number"));
+    let err = PerfOptions::parse(&args("corpus")).err();
+    assert_eq!(err.as_deref(), Some("unexpected argument `corpus`"));
+}
+
+#[test]
+fn phases_are_read_from_tigerc_time() {
+    let json = r#"{"files":3,"repeat":10,"phases":{"lex":120,"parse":340}}"#;
+    assert_eq!(phases(json), Ok(timings(&[("lex", 120), ("parse", 340)])));
+    assert_eq!(
+        phases(r#"{"phases":{"lex":fast}}"#).err().as_deref(),
+        Some("the timings are not the JSON `tigerc time` prints")
+    );
+}
+
+#[test]
+fn regressions_beyond_the_threshold_and_the_noise_fail() {
+    let baseline = timings(&[("lex", 100), ("parse", 1000), ("check", 2000)]);
+    // `lex` is 40% slower but only by 40µs; `parse` by less than 25%.
+    let current = timings(&[("lex", 140), ("parse", 1200), ("check", 3000)]);
+    assert_eq!(
+        regressions(&baseline, &current, 25.0),
+        Ok(vec!["check".to_string()])
+    );
+    assert_eq!(regressions(&baseline, &current, 60.0), Ok(vec![]));
+    let expected = "\
+phase         baseline         now    change
+lex              100µs       140µs    +40.0%
+parse           1000µs      1200µs    +20.0%
+check           2000µs      3000µs    +50.0%  slower
+";
+    assert_eq!(table(&baseline, &current, 25.0), expected);
+    let new = timings(&[("emit", 10)]);
+    assert_eq!(
+        regressions(&baseline, &new, 25.0).err().as_deref(),
+        Some("the baseline has no phase `emit`; record it again with `--record`")
+    );
+}
+
+#[test]
+fn regress_options() {
+    let opts = RegressOptions::parse(&args("bug.tig --input in.txt -- --opt-level 2")).unwrap();
+    assert_eq!(
+        opts,
+        RegressOptions {
+            program: PathBuf::from("bug.tig"),
+            input: Some(PathBuf::from("in.txt")),
+            flags: args("--opt-level 2"),
+        }
+    );
+    let err = RegressOptions::parse(&args("bug.tig -- -o bug")).err();
+    assert_eq!(
+        err.as_deref(),
+        Some("`-o` is set by the regression test itself")
+    );
+    let err = RegressOptions::parse(&args("--input in.txt")).err();
+    assert_eq!(err.as_deref(), Some("`regress` expects a program"));
+}
+
+#[test]
+fn regressions_are_numbered_after_the_last() {
+    let dir = std::env::temp_dir().join(format!("xtask-regress-{}", std::process::id()));
+    assert_eq!(next_case(&dir), Ok(1));
+    for case in ["0001", "0007", "notes"] {
+        std::fs::create_dir_all(dir.join(case)).unwrap();
+    }
+    assert_eq!(next_case(&dir), Ok(8));
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(
+        test("0008"),
+        "\n#[test]\nfn regression_0008() {\n    check(\"0008\");\n}\n"
+    );
+}
+
+#[test]
+fn outcomes_end_with_the_exit_code_unless_it_is_0() {
+    assert_eq!(outcome(b"7\n", Some(0)), "7\n");
+    assert_eq!(outcome(b"", Some(1)), "--- exit code 1\n");
+    assert_eq!(outcome(b"x", None), "x--- killed\n");
+}